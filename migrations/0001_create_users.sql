@@ -0,0 +1,9 @@
+-- Users signed in through the OAuth providers, keyed by their provider id
+CREATE TABLE IF NOT EXISTS users (
+    id TEXT PRIMARY KEY,
+    provider TEXT NOT NULL,
+    provider_user_id TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    last_seen BIGINT NOT NULL,
+    UNIQUE (provider, provider_user_id)
+);
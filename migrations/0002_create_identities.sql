@@ -0,0 +1,27 @@
+-- Users may sign in through several providers: move the provider ids of
+-- the users to an identities table, each identity belonging to one user
+ALTER TABLE users RENAME TO users_v1;
+
+CREATE TABLE users (
+    id TEXT PRIMARY KEY,
+    created_at BIGINT NOT NULL,
+    last_seen BIGINT NOT NULL
+);
+
+INSERT INTO users (id, created_at, last_seen)
+SELECT id, created_at, last_seen FROM users_v1;
+
+CREATE TABLE identities (
+    provider TEXT NOT NULL,
+    provider_user_id TEXT NOT NULL,
+    user_id TEXT NOT NULL REFERENCES users (id),
+    linked_at BIGINT NOT NULL,
+    PRIMARY KEY (provider, provider_user_id)
+);
+
+CREATE INDEX identities_user_id ON identities (user_id);
+
+INSERT INTO identities (provider, provider_user_id, user_id, linked_at)
+SELECT provider, provider_user_id, id, created_at FROM users_v1;
+
+DROP TABLE users_v1;
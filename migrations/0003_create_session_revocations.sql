@@ -0,0 +1,9 @@
+-- Sessions revoked by an administrator, by session id or by user id for
+-- every session of the user, kept until those sessions would have expired
+CREATE TABLE session_revocations (
+    kind TEXT NOT NULL,
+    id TEXT NOT NULL,
+    revoked_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL,
+    PRIMARY KEY (kind, id)
+);
@@ -0,0 +1,13 @@
+-- Signed-in sessions, indexed by user so that they can be listed and
+-- revoked, kept until they expire
+CREATE TABLE sessions (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL REFERENCES users (id),
+    provider TEXT NOT NULL,
+    provider_user_id TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    last_seen BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL
+);
+
+CREATE INDEX sessions_user_id ON sessions (user_id);
@@ -0,0 +1,14 @@
+-- Tokens issued by the providers at sign-in, encrypted by the server and
+-- kept so that they can be refreshed before they expire
+CREATE TABLE provider_tokens (
+    user_id TEXT NOT NULL REFERENCES users (id),
+    provider TEXT NOT NULL,
+    access_token TEXT NOT NULL,
+    refresh_token TEXT,
+    expires_at BIGINT,
+    updated_at BIGINT NOT NULL,
+    needs_reauth_at BIGINT,
+    PRIMARY KEY (user_id, provider)
+);
+
+CREATE INDEX provider_tokens_expires_at ON provider_tokens (expires_at);
@@ -0,0 +1,6 @@
+-- Emails of the users, encrypted by the server, and their blind index, an
+-- HMAC of the normalized email that supports lookups by equality
+ALTER TABLE users ADD COLUMN email TEXT;
+ALTER TABLE users ADD COLUMN email_index TEXT;
+
+CREATE INDEX users_email_index ON users (email_index);
@@ -0,0 +1,1705 @@
+//! Application setup
+//!
+//! Builds the OAuth providers, the shared HTTP client and the application
+//! state from the configuration. The binary wires them into a [`Server`],
+//! applications embedding the OAuth routes call them before merging
+//! [`router`] into their own router.
+//!
+//! [`Server`]: crate::server::server::Server
+//! [`router`]: crate::server::server::router
+
+#[cfg(feature = "database")]
+use crate::users::SqlUserRepository;
+use crate::{
+    auth::TokenIssuer,
+    clock::Clock,
+    crypto::SecretCipher,
+    http_client::{RetryPolicy, SharedClient},
+    primitives::{ProviderContext, ProviderEndpoints},
+    providers::ProviderRegistry,
+    server::{
+        admin::ConfigResponse,
+        audit::AuditSink,
+        cors::cors_layer,
+        login_audit::LoginAudit,
+        metrics::FlowMetrics,
+        provider_health::ProviderHealth,
+        rate_limit::RateLimiter,
+        redirect::Redirects,
+        server::{AppState, CALLBACK_PATH},
+        stateless::{ReplayGuard, MAX_PROVIDER_NAME_LEN},
+        stats::LoginStats,
+        throttle::FailureThrottle,
+    },
+    settings::{ConfigError, FlowStateMode, HttpClientSettings, OAuthSettings, Settings},
+    traits::OAuthProvider,
+    types::{BareOAuthClient, HttpClient, OAuthClient},
+    users::{InMemoryUserRepository, UserRepository},
+};
+use eyre::{bail, Result};
+use oauth2::{AuthUrl, ClientId, DeviceAuthorizationUrl, RedirectUrl, RevocationUrl, TokenUrl};
+use reqwest::Url;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    sync::Arc,
+    time::Duration,
+};
+use tracing::warn;
+
+/// Authorization URL parameters set by the OAuth client or the server itself
+const RESERVED_AUTH_PARAMS: &[&str] = &[
+    "response_type",
+    "client_id",
+    "redirect_uri",
+    "scope",
+    "state",
+    "code_challenge",
+    "code_challenge_method",
+    "nonce",
+];
+
+/// Builds the store of the signed-in users
+///
+/// # Arguments
+///
+/// * `settings` - The loaded configuration
+///
+/// # Returns
+///
+/// Returns the database store when `[database]` is configured, the
+/// in-memory store otherwise, or an error if the database settings are
+/// invalid or the `database` feature is disabled
+pub fn build_user_repository(settings: &Settings) -> Result<Arc<dyn UserRepository>> {
+    match &settings.database {
+        #[cfg(feature = "database")]
+        Some(database) => Ok(Arc::new(SqlUserRepository::from_settings(database)?)),
+        #[cfg(not(feature = "database"))]
+        Some(_) => bail!("[database] requires building with the `database` feature"),
+        None => Ok(Arc::new(InMemoryUserRepository::default())),
+    }
+}
+
+/// Builds the state shared by the request handlers
+///
+/// Sets up the secret cipher, the per-provider options, the metrics and
+/// statistics, the audit webhook, whose delivery worker is spawned on the
+/// current Tokio runtime, the login audit log, the user store and the
+/// refresh of the provider tokens kept there, and the JWT issuer and
+/// frontend redirects.
+///
+/// # Arguments
+///
+/// * `settings` - The loaded configuration
+/// * `http_client` - The HTTP client shared by every provider
+/// * `oauth_providers` - The providers built by [`build_oauth_providers`]
+///
+/// # Returns
+///
+/// Returns the application state, or an error if a section of the
+/// configuration is invalid
+pub fn build_app_state(
+    settings: &Settings,
+    http_client: HttpClient,
+    oauth_providers: HashMap<String, Arc<dyn OAuthProvider>>,
+) -> Result<Arc<AppState>> {
+    if settings.flow_state == FlowStateMode::Stateless && settings.encryption.is_none() {
+        bail!("flow_state = \"stateless\" requires an [encryption] section");
+    }
+    if settings.flow_state == FlowStateMode::Stateless {
+        if let Some(name) = settings
+            .oauth
+            .keys()
+            .find(|name| name.len() > MAX_PROVIDER_NAME_LEN)
+        {
+            bail!(
+                "Provider name {} is longer than the {} characters allowed with flow_state = \"stateless\"",
+                name,
+                MAX_PROVIDER_NAME_LEN
+            );
+        }
+    }
+
+    let secret_cipher = match &settings.encryption {
+        Some(encryption) => SecretCipher::from_settings(encryption)?,
+        None => {
+            warn!("No encryption keys configured, session secrets will be stored in plaintext");
+            SecretCipher::disabled()
+        }
+    };
+
+    let throttle_webhook = match &settings.csrf_throttle.webhook_url {
+        Some(url) => Some(Url::parse(url.expose())?),
+        None => None,
+    };
+
+    // PKCE is only skipped through the per-provider escape hatch, or for
+    // providers that reject it
+    let pkce_exempt_providers: HashSet<String> = settings
+        .oauth
+        .iter()
+        .filter(|(name, provider)| {
+            !provider.pkce
+                || oauth_providers
+                    .get(name.as_str())
+                    .is_some_and(|provider| !provider.supports_pkce())
+        })
+        .map(|(name, _)| name.clone())
+        .collect();
+    for provider_name in &pkce_exempt_providers {
+        warn!(
+            "PKCE is disabled for provider {}, its authorization codes are not bound to the flow",
+            provider_name
+        );
+    }
+
+    let unverified_email_providers: HashSet<String> = settings
+        .oauth
+        .iter()
+        .filter(|(_, provider)| !provider.require_verified_email)
+        .map(|(name, _)| name.clone())
+        .collect();
+    let strict_scope_providers: HashSet<String> = settings
+        .oauth
+        .iter()
+        .filter(|(_, provider)| provider.require_all_scopes)
+        .map(|(name, _)| name.clone())
+        .collect();
+    let extra_auth_params: HashMap<String, BTreeMap<String, String>> = settings
+        .oauth
+        .iter()
+        .filter_map(|(name, provider)| Some((name.clone(), provider.extra_auth_params.clone()?)))
+        .collect();
+
+    // Form posted callbacks are cross-site, which drops SameSite=Lax cookies
+    if settings.flow_state == FlowStateMode::Session {
+        for (name, provider) in &oauth_providers {
+            if provider.uses_form_post() {
+                warn!("Provider {} posts its callback cross-site, set flow_state = \"stateless\" so the flow survives without the session cookie", name);
+            }
+        }
+    }
+
+    let metrics = FlowMetrics::new(oauth_providers.keys().cloned());
+    let stats = LoginStats::new(settings.stats.clone(), oauth_providers.keys().cloned());
+    if let Some(unknown) = settings
+        .provider_health
+        .required_providers
+        .iter()
+        .flatten()
+        .find(|name| !oauth_providers.contains_key(name.as_str()))
+    {
+        bail!(
+            "provider_health.required_providers lists {}, which is not a configured provider",
+            unknown
+        );
+    }
+    let provider_health = ProviderHealth::new(
+        settings.provider_health.clone(),
+        oauth_providers.keys().cloned(),
+    );
+
+    let audit = match &settings.audit_webhook {
+        Some(audit_webhook) => {
+            if audit_webhook.secret.expose().is_empty() {
+                bail!("audit_webhook.secret is required");
+            }
+            let (sink, worker) = AuditSink::new(
+                audit_webhook.clone(),
+                Url::parse(audit_webhook.url.expose())?,
+            );
+            tokio::spawn(worker.run());
+            sink
+        }
+        None => AuditSink::disabled(),
+    };
+    let login_audit = match &settings.audit {
+        Some(audit) => LoginAudit::from_settings(audit)?,
+        None => LoginAudit::disabled(),
+    };
+    let users = build_user_repository(settings)?;
+    if let Some(token_refresh) = &settings.token_refresh {
+        if token_refresh.interval_seconds == 0 || token_refresh.max_concurrency_per_provider == 0 {
+            bail!("token_refresh.interval_seconds and token_refresh.max_concurrency_per_provider must be positive");
+        }
+        if !secret_cipher.is_enabled() {
+            warn!("No encryption keys configured, provider tokens will be stored in plaintext");
+        }
+    }
+
+    let token_issuer = match &settings.jwt {
+        Some(jwt) => Some(TokenIssuer::from_settings(jwt)?),
+        None => None,
+    };
+    let token_redirect_url = match settings
+        .jwt
+        .as_ref()
+        .and_then(|jwt| jwt.redirect_url.as_ref())
+    {
+        Some(url) => Some(Url::parse(url)?),
+        None => None,
+    };
+
+    let redirects = Redirects::from_settings(settings)?;
+
+    Ok(Arc::new(AppState {
+        oauth_providers,
+        secret_cipher,
+        flow_state: settings.flow_state,
+        flow_ttl_seconds: settings.flow_ttl_seconds,
+        replay_guard: ReplayGuard::default(),
+        csrf_throttle: FailureThrottle::new(settings.csrf_throttle.clone(), throttle_webhook),
+        rate_limiter: RateLimiter::new(settings.rate_limit.clone()),
+        flow_binding: settings.flow_binding,
+        trusted_proxies: settings.trusted_proxies.clone(),
+        pkce_exempt_providers,
+        unverified_email_providers,
+        strict_scope_providers,
+        extra_auth_params,
+        http_client,
+        clock: Clock::system(settings.leeway_seconds),
+        metrics,
+        stats,
+        admin_api_key: settings
+            .admin
+            .api_key
+            .as_ref()
+            .map(|key| key.expose().to_string()),
+        metrics_token: settings
+            .metrics
+            .bearer_token
+            .as_ref()
+            .map(|token| token.expose().to_string()),
+        audit,
+        login_audit,
+        users,
+        session_ttl_seconds: settings.session.ttl_seconds,
+        token_refresh: settings.token_refresh.clone(),
+        effective_config: ConfigResponse::new(settings),
+        provider_health,
+        token_issuer,
+        token_redirect_url,
+        redirects,
+        route_prefix: settings.route_prefix()?.to_string(),
+    }))
+}
+
+/// Builds OAuth provider instances from configuration
+///
+/// This function creates OAuth client instances for each provider configured
+/// in the settings. It validates URLs, creates OAuth clients, and registers
+/// them with the provider factory. Each section uses the implementation
+/// registered under its `type`, or under its name when no type is set.
+/// Endpoints that are not configured are derived or discovered by the
+/// implementation, which may fetch a discovery document. Omitted redirect
+/// URIs default to the callback route under `base_url`.
+///
+/// # Arguments
+///
+/// * `http_client` - The HTTP client shared by every provider
+/// * `registry` - Factories of the providers the sections can select
+/// * `oauth` - HashMap containing OAuth provider configurations
+/// * `base_url` - Public URL the server is reached at, if configured
+/// * `route_prefix` - Path the routes are served under, see [`Settings::route_prefix`]
+/// * `strict` - Whether a provider without a registered factory is an error
+///   instead of being skipped with a warning
+///
+/// # Returns
+///
+/// Returns `Result<HashMap<String, Arc<dyn OAuthProvider>>, ConfigError>`
+/// containing the initialized OAuth providers mapped by provider name, or
+/// the first invalid provider field
+pub async fn build_oauth_providers(
+    http_client: &HttpClient,
+    registry: &ProviderRegistry,
+    oauth: &HashMap<String, OAuthSettings>,
+    base_url: Option<&Url>,
+    route_prefix: &str,
+    strict: bool,
+) -> Result<HashMap<String, Arc<dyn OAuthProvider>>, ConfigError> {
+    let mut oauth_providers = HashMap::new();
+
+    for (provider_name, provider_config) in oauth.iter() {
+        let provider = || provider_name.clone();
+
+        // Get the OAuth provider factory
+        let provider_type = provider_config
+            .provider_type
+            .as_deref()
+            .unwrap_or(provider_name);
+        let Some(factory) = registry.get(provider_type) else {
+            if strict {
+                return Err(ConfigError::UnknownProvider {
+                    provider: provider(),
+                });
+            }
+            warn!(
+                "OAuth provider {} not configured in the factory",
+                provider_name
+            );
+            continue;
+        };
+
+        // Omitted endpoints fall back to the ones the provider derives
+        let endpoints = factory
+            .discover_endpoints(provider_config)
+            .await
+            .map_err(|e| ConfigError::DiscoveryFailed {
+                provider: provider(),
+                reason: e.to_string(),
+            })?;
+        let endpoint =
+            |configured: &str, default: fn(&ProviderEndpoints) -> &String| match &endpoints {
+                Some(endpoints) if configured.is_empty() => default(endpoints).clone(),
+                _ => configured.to_string(),
+            };
+
+        // Validate the required urls
+        let auth_url = AuthUrl::new(endpoint(&provider_config.auth_url, |e| &e.auth_url)).map_err(
+            |error| ConfigError::InvalidAuthUrl {
+                provider: provider(),
+                error,
+            },
+        )?;
+
+        let token_url = TokenUrl::new(endpoint(&provider_config.token_url, |e| &e.token_url))
+            .map_err(|error| ConfigError::InvalidTokenUrl {
+                provider: provider(),
+                error,
+            })?;
+
+        let redirect_url = resolve_redirect_uri(
+            provider_name,
+            &provider_config.redirect_uri,
+            base_url,
+            route_prefix,
+        )?;
+
+        let user_info_url = Url::parse(&endpoint(&provider_config.user_info_url, |e| {
+            &e.user_info_url
+        }))
+        .map_err(|error| ConfigError::InvalidUserInfoUrl {
+            provider: provider(),
+            error,
+        })?;
+
+        let revocation_url = provider_config
+            .revocation_url
+            .as_ref()
+            .map(|url| RevocationUrl::new(url.clone()))
+            .transpose()
+            .map_err(|error| ConfigError::InvalidRevocationUrl {
+                provider: provider(),
+                error,
+            })?;
+        // RFC 7009 requires HTTPS, the OAuth client refuses other endpoints
+        if revocation_url
+            .as_ref()
+            .is_some_and(|url| url.url().scheme() != "https")
+        {
+            return Err(ConfigError::InvalidProviderSettings {
+                provider: provider(),
+                reason: "revocation_url must be an https URL".to_string(),
+            });
+        }
+
+        let device_auth_url = provider_config
+            .device_auth_url
+            .as_ref()
+            .map(|url| DeviceAuthorizationUrl::new(url.clone()))
+            .transpose()
+            .map_err(|error| ConfigError::InvalidDeviceAuthUrl {
+                provider: provider(),
+                error,
+            })?;
+
+        // The OAuth client sets these itself, a second value would be ambiguous
+        if let Some(name) = provider_config
+            .extra_auth_params
+            .iter()
+            .flat_map(|params| params.keys())
+            .find(|name| RESERVED_AUTH_PARAMS.contains(&name.as_str()))
+        {
+            return Err(ConfigError::InvalidProviderSettings {
+                provider: provider(),
+                reason: format!("extra_auth_params cannot set {}", name),
+            });
+        }
+
+        // Create the OAuth client
+        let client = BareOAuthClient::new(ClientId::new(provider_config.client_id.clone()))
+            .set_auth_uri(auth_url)
+            .set_token_uri(token_url)
+            .set_redirect_uri(redirect_url)
+            .set_revocation_url_option(revocation_url)
+            .set_device_authorization_url_option(device_auth_url)
+            .set_auth_type(
+                provider_config
+                    .auth_type
+                    .unwrap_or_else(|| factory.default_auth_type())
+                    .into(),
+            );
+        let client = OAuthClient::new(client, Some(provider_config.client_secret.clone()));
+
+        // A discovered key set verifies the ID tokens unless one is configured
+        let discovered_config;
+        let provider_config = match endpoints.as_ref().and_then(|e| e.jwks_url.as_ref()) {
+            Some(jwks_url) if provider_config.jwks_url.is_none() => {
+                discovered_config = OAuthSettings {
+                    jwks_url: Some(jwks_url.clone()),
+                    ..provider_config.clone()
+                };
+                &discovered_config
+            }
+            _ => provider_config,
+        };
+
+        // Create the OAuth provider instance
+        let oauth_provider = factory
+            .create(ProviderContext {
+                name: provider_name,
+                http_client,
+                oauth_client: client,
+                user_info_url,
+                settings: provider_config,
+            })
+            .map_err(|e| ConfigError::InvalidProviderSettings {
+                provider: provider(),
+                reason: e.to_string(),
+            })?;
+        if provider_config.device_auth_url.is_some() && !oauth_provider.supports_device_flow() {
+            return Err(ConfigError::InvalidProviderSettings {
+                provider: provider(),
+                reason: "the provider does not support the device flow".to_string(),
+            });
+        }
+        oauth_providers.insert(provider_name.clone(), oauth_provider);
+    }
+
+    Ok(oauth_providers)
+}
+
+/// Outcome of validating a configuration without starting the server
+///
+/// # Fields
+///
+/// * `providers` - Providers that were built successfully, by name
+/// * `errors` - Every problem found, in the order the checks ran
+#[derive(Debug, Default)]
+pub struct ConfigReport {
+    /// Providers that were built successfully, by name
+    pub providers: Vec<String>,
+    /// Every problem found, in the order the checks ran
+    pub errors: Vec<String>,
+}
+
+impl ConfigReport {
+    /// Returns whether the configuration is valid
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl fmt::Display for ConfigReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for provider in &self.providers {
+            writeln!(f, "ok     oauth.{}", provider)?;
+        }
+        for error in &self.errors {
+            writeln!(f, "error  {}", error)?;
+        }
+        match self.errors.len() {
+            0 => write!(
+                f,
+                "Configuration is valid, {} provider(s) configured",
+                self.providers.len()
+            ),
+            errors => write!(f, "Configuration is invalid, {} error(s) found", errors),
+        }
+    }
+}
+
+/// Validates a configuration without starting the server
+///
+/// Builds every provider as on startup, except that a provider without a
+/// registered implementation is an error, and collects every problem
+/// instead of stopping at the first one. Redirect URIs must also be under
+/// `base_url` when one is set. Providers whose endpoints are discovered
+/// fetch their discovery document.
+///
+/// # Arguments
+///
+/// * `settings` - The loaded configuration
+/// * `registry` - Factories of the providers the sections can select
+///
+/// # Returns
+///
+/// Returns the report listing the valid providers and every error
+pub async fn check_config(settings: &Settings, registry: &ProviderRegistry) -> ConfigReport {
+    let mut report = ConfigReport::default();
+
+    let base_url = match settings.base_url.as_deref().map(Url::parse).transpose() {
+        Ok(base_url) => base_url,
+        Err(e) => {
+            report.errors.push(format!("Invalid base_url: {}", e));
+            None
+        }
+    };
+    let route_prefix = match settings.route_prefix() {
+        Ok(route_prefix) => route_prefix,
+        Err(e) => {
+            report.errors.push(e.to_string());
+            ""
+        }
+    };
+
+    if settings.oauth.is_empty() {
+        report
+            .errors
+            .push("No OAuth provider is configured".to_string());
+    }
+    match build_http_client(&settings.http_client) {
+        Ok(http_client) => {
+            let mut names: Vec<&String> = settings.oauth.keys().collect();
+            names.sort();
+            for name in names {
+                let provider_config = &settings.oauth[name];
+                let oauth = HashMap::from([(name.clone(), provider_config.clone())]);
+                let result = build_oauth_providers(
+                    &http_client,
+                    registry,
+                    &oauth,
+                    base_url.as_ref(),
+                    route_prefix,
+                    true,
+                )
+                .await
+                .and_then(|_| {
+                    check_redirect_uri(name, &provider_config.redirect_uri, base_url.as_ref())
+                });
+                match result {
+                    Ok(()) => report.providers.push(name.clone()),
+                    Err(e) => report.errors.push(e.to_string()),
+                }
+            }
+        }
+        Err(e) => report
+            .errors
+            .push(format!("Invalid http_client settings: {}", e)),
+    }
+
+    if let Some(unknown) = settings
+        .provider_health
+        .required_providers
+        .iter()
+        .flatten()
+        .find(|name| !settings.oauth.contains_key(name.as_str()))
+    {
+        report.errors.push(format!(
+            "provider_health.required_providers lists {}, which is not a configured provider",
+            unknown
+        ));
+    }
+
+    let urls = [
+        (
+            "csrf_throttle.webhook_url",
+            settings.csrf_throttle.webhook_url.as_ref(),
+        ),
+        (
+            "audit_webhook.url",
+            settings.audit_webhook.as_ref().map(|webhook| &webhook.url),
+        ),
+    ];
+    for (name, url) in urls {
+        if let Some(Err(e)) = url.map(|url| Url::parse(url.expose())) {
+            report.errors.push(format!("Invalid {}: {}", name, e));
+        }
+    }
+    if let Some(Err(e)) = settings
+        .jwt
+        .as_ref()
+        .and_then(|jwt| jwt.redirect_url.as_deref())
+        .map(Url::parse)
+    {
+        report
+            .errors
+            .push(format!("Invalid jwt.redirect_url: {}", e));
+    }
+
+    let checks = [
+        Redirects::from_settings(settings).map(|_| ()),
+        cors_layer(settings.cors.as_ref()).map(|_| ()),
+        settings.session.cookie.validate(),
+    ];
+    report.errors.extend(
+        checks
+            .into_iter()
+            .filter_map(Result::err)
+            .map(|e| e.to_string()),
+    );
+
+    report
+}
+
+/// Checks that a configured redirect URI is under the base URL
+///
+/// # Arguments
+///
+/// * `provider_name` - Name of the provider section
+/// * `configured` - The configured redirect URI, empty when omitted
+/// * `base_url` - Public URL the server is reached at, if configured
+///
+/// # Returns
+///
+/// Returns an error if the redirect URI has another origin than `base_url`
+/// or is outside its path
+fn check_redirect_uri(
+    provider_name: &str,
+    configured: &str,
+    base_url: Option<&Url>,
+) -> Result<(), ConfigError> {
+    let (Some(base_url), Ok(redirect_url)) = (base_url, Url::parse(configured)) else {
+        return Ok(());
+    };
+
+    let base_path = base_url.path().trim_end_matches('/');
+    let under_base_path = redirect_url
+        .path()
+        .strip_prefix(base_path)
+        .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'));
+    if redirect_url.origin() != base_url.origin() || !under_base_path {
+        return Err(ConfigError::InvalidProviderSettings {
+            provider: provider_name.to_string(),
+            reason: format!(
+                "redirect_uri {} is not under base_url {}",
+                redirect_url, base_url
+            ),
+        });
+    }
+
+    Ok(())
+}
+
+/// Builds the HTTP client shared by every provider
+///
+/// The client does not follow redirects, so a token or user info endpoint
+/// cannot bounce requests carrying credentials to another host.
+///
+/// # Arguments
+///
+/// * `settings` - Timeouts, User-Agent and retry policy from the configuration
+///
+/// # Returns
+///
+/// Returns the shared client, or an error if the User-Agent is not a valid
+/// header value
+pub fn build_http_client(settings: &HttpClientSettings) -> reqwest::Result<HttpClient> {
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .connect_timeout(Duration::from_millis(settings.connect_timeout_ms))
+        .timeout(Duration::from_millis(settings.timeout_ms))
+        .user_agent(settings.user_agent.as_str())
+        .build()?;
+    let retry = RetryPolicy {
+        max_retries: settings.max_retries,
+        initial_backoff: Duration::from_millis(settings.retry_backoff_ms),
+    };
+
+    Ok(Arc::new(SharedClient::new(client, retry)))
+}
+
+/// Resolves the redirect URI of a provider
+///
+/// An omitted redirect URI defaults to the callback route under `base_url`
+/// and the route prefix. A configured one that does not point at the callback route is kept, as a
+/// proxy may rewrite it, but logged loudly since the provider would
+/// otherwise send every user to a route the server does not serve.
+///
+/// # Arguments
+///
+/// * `provider_name` - Name of the provider section
+/// * `configured` - The configured redirect URI, empty when omitted
+/// * `base_url` - Public URL the server is reached at, if configured
+/// * `route_prefix` - Path the routes are served under
+///
+/// # Returns
+///
+/// Returns the redirect URI, or an error if it is invalid or omitted
+/// without a `base_url`
+fn resolve_redirect_uri(
+    provider_name: &str,
+    configured: &str,
+    base_url: Option<&Url>,
+    route_prefix: &str,
+) -> Result<RedirectUrl, ConfigError> {
+    let redirect_uri = match base_url {
+        _ if !configured.is_empty() => configured.to_string(),
+        Some(base_url) => callback_url(base_url, route_prefix).to_string(),
+        None => {
+            return Err(ConfigError::MissingRedirectUri {
+                provider: provider_name.to_string(),
+            })
+        }
+    };
+
+    let redirect_url =
+        RedirectUrl::new(redirect_uri).map_err(|error| ConfigError::InvalidRedirectUri {
+            provider: provider_name.to_string(),
+            error,
+        })?;
+
+    if let Some(expected_path) = callback_path_mismatch(redirect_url.url(), base_url, route_prefix)
+    {
+        warn!(
+            "oauth.{}.redirect_uri {} does not point at the callback route {}, the provider will redirect users to a route this server does not serve",
+            provider_name,
+            redirect_url.as_str(),
+            expected_path
+        );
+    }
+
+    Ok(redirect_url)
+}
+
+/// Checks that a redirect URI points at the callback route
+///
+/// # Arguments
+///
+/// * `redirect_url` - The resolved redirect URI
+/// * `base_url` - Public URL the server is reached at, whose path prefixes the route
+/// * `route_prefix` - Path the routes are served under
+///
+/// # Returns
+///
+/// Returns the expected path when the redirect URI's path differs from it
+fn callback_path_mismatch(
+    redirect_url: &Url,
+    base_url: Option<&Url>,
+    route_prefix: &str,
+) -> Option<String> {
+    let expected_path = base_url.map_or(format!("{}{}", route_prefix, CALLBACK_PATH), |base_url| {
+        callback_url(base_url, route_prefix).path().to_string()
+    });
+    (redirect_url.path() != expected_path).then_some(expected_path)
+}
+
+/// Returns the URL of the callback route under a base URL
+///
+/// # Arguments
+///
+/// * `base_url` - Public URL the server is reached at, possibly with a path prefix
+/// * `route_prefix` - Path the routes are served under
+///
+/// # Returns
+///
+/// Returns `{base_url}{route_prefix}/callback` without the base URL's query
+/// or fragment
+fn callback_url(base_url: &Url, route_prefix: &str) -> Url {
+    let mut url = base_url.clone();
+    url.set_path(&format!(
+        "{}{}{}",
+        base_url.path().trim_end_matches('/'),
+        route_prefix,
+        CALLBACK_PATH
+    ));
+    url.set_query(None);
+    url.set_fragment(None);
+    url
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::TokenAuthType;
+
+    /// Returns valid settings for a provider
+    fn provider_settings() -> OAuthSettings {
+        OAuthSettings {
+            client_id: "client".to_string(),
+            client_secret: "secret".into(),
+            auth_url: "https://idp.example.com/authorize".to_string(),
+            token_url: "https://idp.example.com/token".to_string(),
+            redirect_uri: "https://app.example.com/callback".to_string(),
+            user_info_url: "https://idp.example.com/userinfo".to_string(),
+            ..OAuthSettings::default()
+        }
+    }
+
+    /// Tests that valid settings build every registered provider
+    #[tokio::test]
+    async fn test_build_oauth_providers() {
+        let oauth = HashMap::from([("google".to_string(), provider_settings())]);
+        let providers = build_oauth_providers(
+            &HttpClient::default(),
+            &ProviderRegistry::with_builtins(),
+            &oauth,
+            None,
+            "",
+            true,
+        )
+        .await
+        .unwrap();
+        assert!(providers.contains_key("google"));
+    }
+
+    /// Tests that every provider sends its requests through the one shared client
+    #[tokio::test]
+    async fn test_providers_share_http_client() {
+        let oauth = HashMap::from([
+            ("google".to_string(), provider_settings()),
+            ("github".to_string(), provider_settings()),
+            ("discord".to_string(), provider_settings()),
+        ]);
+        let http_client = build_http_client(&HttpClientSettings::default()).unwrap();
+
+        let providers = build_oauth_providers(
+            &http_client,
+            &ProviderRegistry::with_builtins(),
+            &oauth,
+            None,
+            "",
+            true,
+        )
+        .await
+        .unwrap();
+        assert_eq!(providers.len(), 3);
+        for (name, provider) in &providers {
+            assert!(
+                Arc::ptr_eq(provider.http_client(), &http_client),
+                "{}",
+                name
+            );
+        }
+    }
+
+    /// Tests that each malformed URL is reported with its provider and field
+    #[tokio::test]
+    async fn test_invalid_urls() {
+        let error = url::ParseError::RelativeUrlWithoutBase;
+        let provider = || "google".to_string();
+
+        for (field, expected) in [
+            (
+                "auth_url",
+                ConfigError::InvalidAuthUrl {
+                    provider: provider(),
+                    error,
+                },
+            ),
+            (
+                "token_url",
+                ConfigError::InvalidTokenUrl {
+                    provider: provider(),
+                    error,
+                },
+            ),
+            (
+                "redirect_uri",
+                ConfigError::InvalidRedirectUri {
+                    provider: provider(),
+                    error,
+                },
+            ),
+            (
+                "user_info_url",
+                ConfigError::InvalidUserInfoUrl {
+                    provider: provider(),
+                    error,
+                },
+            ),
+            (
+                "revocation_url",
+                ConfigError::InvalidRevocationUrl {
+                    provider: provider(),
+                    error,
+                },
+            ),
+            (
+                "device_auth_url",
+                ConfigError::InvalidDeviceAuthUrl {
+                    provider: provider(),
+                    error,
+                },
+            ),
+        ] {
+            let mut settings = provider_settings();
+            let broken = format!("google_{}", field);
+            match field {
+                "auth_url" => settings.auth_url = broken,
+                "token_url" => settings.token_url = broken,
+                "redirect_uri" => settings.redirect_uri = broken,
+                "revocation_url" => settings.revocation_url = Some(broken),
+                "device_auth_url" => settings.device_auth_url = Some(broken),
+                _ => settings.user_info_url = broken,
+            }
+
+            let oauth = HashMap::from([(provider(), settings)]);
+            assert_eq!(
+                build_oauth_providers(
+                    &HttpClient::default(),
+                    &ProviderRegistry::with_builtins(),
+                    &oauth,
+                    None,
+                    "",
+                    false
+                )
+                .await
+                .err(),
+                Some(expected)
+            );
+        }
+    }
+
+    /// Tests that omitted endpoints are derived from the base URL, explicit ones win
+    #[tokio::test]
+    async fn test_endpoints_derived_from_base_url() {
+        let settings = OAuthSettings {
+            client_id: "client".to_string(),
+            redirect_uri: "https://app.example.com/callback".to_string(),
+            token_url: "https://proxy.example.com/oauth/token".to_string(),
+            base_url: Some("https://git.example.com".to_string()),
+            ..OAuthSettings::default()
+        };
+        let oauth = HashMap::from([("gitlab".to_string(), settings)]);
+        let providers = build_oauth_providers(
+            &HttpClient::default(),
+            &ProviderRegistry::with_builtins(),
+            &oauth,
+            None,
+            "",
+            true,
+        )
+        .await
+        .unwrap();
+
+        let client = providers["gitlab"].get_oauth_client();
+        assert_eq!(
+            client.auth_uri().as_str(),
+            "https://git.example.com/oauth/authorize"
+        );
+        assert_eq!(
+            client.token_uri().as_str(),
+            "https://proxy.example.com/oauth/token"
+        );
+
+        // Providers without default endpoints still require them
+        let oauth = HashMap::from([(
+            "google".to_string(),
+            OAuthSettings {
+                auth_url: String::new(),
+                ..provider_settings()
+            },
+        )]);
+        assert!(matches!(
+            build_oauth_providers(
+                &HttpClient::default(),
+                &ProviderRegistry::with_builtins(),
+                &oauth,
+                None,
+                "",
+                true
+            )
+            .await,
+            Err(ConfigError::InvalidAuthUrl { .. })
+        ));
+    }
+
+    /// Tests that extra authorization parameters cannot replace the ones the OAuth client sets
+    #[tokio::test]
+    async fn test_extra_auth_params_reserved() {
+        let oauth = HashMap::from([(
+            "google".to_string(),
+            OAuthSettings {
+                extra_auth_params: Some(BTreeMap::from([(
+                    "redirect_uri".to_string(),
+                    "https://evil.example.com".to_string(),
+                )])),
+                ..provider_settings()
+            },
+        )]);
+        assert!(matches!(
+            build_oauth_providers(&HttpClient::default(), &ProviderRegistry::with_builtins(), &oauth, None, "", true).await,
+            Err(ConfigError::InvalidProviderSettings { provider, .. }) if provider == "google"
+        ));
+    }
+
+    /// Tests that the client credentials reach the token endpoint in the
+    /// header or the body depending on the provider's `auth_type`
+    #[tokio::test]
+    async fn test_token_endpoint_auth_type() {
+        use axum::{http::HeaderMap, routing::post, Form, Json, Router};
+
+        /// Authorization header and form fields of the last token request
+        type Recorded = std::sync::Mutex<(Option<String>, HashMap<String, String>)>;
+        let recorded = Arc::new(Recorded::default());
+        let recorder = Arc::clone(&recorded);
+        let router = Router::new().route(
+            "/token",
+            post(
+                move |headers: HeaderMap, Form(form): Form<HashMap<String, String>>| async move {
+                    let authorization = headers
+                        .get("authorization")
+                        .map(|value| value.to_str().unwrap().to_string());
+                    *recorder.lock().unwrap() = (authorization, form);
+                    Json(serde_json::json!({ "access_token": "token", "token_type": "bearer" }))
+                },
+            ),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let token_url = format!("http://{}/token", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        // LinkedIn defaults to the request body, the setting overrides it
+        let cases = [
+            ("google", None, TokenAuthType::Basic),
+            ("linkedin", None, TokenAuthType::RequestBody),
+            ("yahoo", None, TokenAuthType::Basic),
+            (
+                "google",
+                Some(TokenAuthType::RequestBody),
+                TokenAuthType::RequestBody,
+            ),
+            ("linkedin", Some(TokenAuthType::Basic), TokenAuthType::Basic),
+            (
+                "yahoo",
+                Some(TokenAuthType::RequestBody),
+                TokenAuthType::RequestBody,
+            ),
+        ];
+        for (provider, auth_type, expected) in cases {
+            let oauth = HashMap::from([(
+                provider.to_string(),
+                OAuthSettings {
+                    token_url: token_url.clone(),
+                    auth_type,
+                    ..provider_settings()
+                },
+            )]);
+            let providers = build_oauth_providers(
+                &HttpClient::default(),
+                &ProviderRegistry::with_builtins(),
+                &oauth,
+                None,
+                "",
+                true,
+            )
+            .await
+            .unwrap();
+            providers[provider]
+                .exchange_code("code", None, 0)
+                .await
+                .unwrap();
+
+            let (authorization, form) = recorded.lock().unwrap().clone();
+            match expected {
+                TokenAuthType::Basic => {
+                    // base64("client:secret")
+                    assert_eq!(authorization.as_deref(), Some("Basic Y2xpZW50OnNlY3JldA=="));
+                    assert!(!form.contains_key("client_secret"), "{}", provider);
+                }
+                TokenAuthType::RequestBody => {
+                    assert_eq!(authorization, None, "{}", provider);
+                    assert_eq!(form["client_id"], "client");
+                    assert_eq!(form["client_secret"], "secret");
+                }
+            }
+        }
+    }
+
+    /// Tests that unknown token endpoint authentication methods are refused
+    #[test]
+    fn test_auth_type_is_validated() {
+        let parse = |auth_type: &str| {
+            serde_json::from_value::<OAuthSettings>(serde_json::json!({
+                "client_id": "client",
+                "auth_type": auth_type,
+            }))
+        };
+        assert_eq!(
+            parse("request_body").unwrap().auth_type,
+            Some(TokenAuthType::RequestBody)
+        );
+        assert!(parse("client_secret_jwt").is_err());
+    }
+
+    /// Tests that revocation endpoints are optional and must use HTTPS
+    #[tokio::test]
+    async fn test_revocation_url() {
+        let oauth = HashMap::from([(
+            "google".to_string(),
+            OAuthSettings {
+                revocation_url: Some("https://idp.example.com/revoke".to_string()),
+                ..provider_settings()
+            },
+        )]);
+        let providers = build_oauth_providers(
+            &HttpClient::default(),
+            &ProviderRegistry::with_builtins(),
+            &oauth,
+            None,
+            "",
+            true,
+        )
+        .await
+        .unwrap();
+        let revocation_url = providers["google"].get_oauth_client().revocation_url();
+        assert_eq!(
+            revocation_url.unwrap().as_str(),
+            "https://idp.example.com/revoke"
+        );
+
+        let oauth = HashMap::from([(
+            "google".to_string(),
+            OAuthSettings {
+                revocation_url: Some("http://idp.example.com/revoke".to_string()),
+                ..provider_settings()
+            },
+        )]);
+        assert!(matches!(
+            build_oauth_providers(&HttpClient::default(), &ProviderRegistry::with_builtins(), &oauth, None, "", true).await,
+            Err(ConfigError::InvalidProviderSettings { provider, .. }) if provider == "google"
+        ));
+    }
+
+    /// Tests that device authorization endpoints are only accepted for
+    /// providers supporting the device flow
+    #[tokio::test]
+    async fn test_device_auth_url() {
+        let settings = OAuthSettings {
+            device_auth_url: Some("https://idp.example.com/device/code".to_string()),
+            ..provider_settings()
+        };
+        let oauth = HashMap::from([("google".to_string(), settings.clone())]);
+        let providers = build_oauth_providers(
+            &HttpClient::default(),
+            &ProviderRegistry::with_builtins(),
+            &oauth,
+            None,
+            "",
+            true,
+        )
+        .await
+        .unwrap();
+        let device_auth_url = providers["google"]
+            .get_oauth_client()
+            .device_authorization_url();
+        assert_eq!(
+            device_auth_url.unwrap().as_str(),
+            "https://idp.example.com/device/code"
+        );
+
+        let oauth = HashMap::from([("discord".to_string(), settings)]);
+        assert!(matches!(
+            build_oauth_providers(&HttpClient::default(), &ProviderRegistry::with_builtins(), &oauth, None, "", true).await,
+            Err(ConfigError::InvalidProviderSettings { provider, .. }) if provider == "discord"
+        ));
+    }
+
+    /// Tests that omitted redirect URIs default to the callback under
+    /// `base_url` and the route prefix
+    #[tokio::test]
+    async fn test_redirect_uri_defaults_to_base_url() {
+        let oauth = HashMap::from([(
+            "google".to_string(),
+            OAuthSettings {
+                redirect_uri: String::new(),
+                ..provider_settings()
+            },
+        )]);
+
+        for (base_url, route_prefix, expected) in [
+            (
+                "https://app.example.com",
+                "",
+                "https://app.example.com/callback",
+            ),
+            (
+                "https://app.example.com/",
+                "",
+                "https://app.example.com/callback",
+            ),
+            (
+                "https://example.com/auth/?x=1",
+                "",
+                "https://example.com/auth/callback",
+            ),
+            (
+                "https://app.example.com",
+                "/auth",
+                "https://app.example.com/auth/callback",
+            ),
+            (
+                "https://example.com/app/",
+                "/auth",
+                "https://example.com/app/auth/callback",
+            ),
+        ] {
+            let base_url = Url::parse(base_url).unwrap();
+            let providers = build_oauth_providers(
+                &HttpClient::default(),
+                &ProviderRegistry::with_builtins(),
+                &oauth,
+                Some(&base_url),
+                route_prefix,
+                true,
+            )
+            .await
+            .unwrap();
+            let redirect_uri = providers["google"].get_oauth_client().redirect_uri();
+            assert_eq!(redirect_uri.unwrap().as_str(), expected);
+        }
+
+        assert_eq!(
+            build_oauth_providers(
+                &HttpClient::default(),
+                &ProviderRegistry::with_builtins(),
+                &oauth,
+                None,
+                "",
+                true
+            )
+            .await
+            .err(),
+            Some(ConfigError::MissingRedirectUri {
+                provider: "google".to_string(),
+            })
+        );
+
+        // A configured redirect URI takes precedence over the base URL
+        let base_url = Url::parse("https://other.example.com").unwrap();
+        let oauth = HashMap::from([("google".to_string(), provider_settings())]);
+        let providers = build_oauth_providers(
+            &HttpClient::default(),
+            &ProviderRegistry::with_builtins(),
+            &oauth,
+            Some(&base_url),
+            "",
+            true,
+        )
+        .await
+        .unwrap();
+        let redirect_uri = providers["google"].get_oauth_client().redirect_uri();
+        assert_eq!(
+            redirect_uri.unwrap().as_str(),
+            "https://app.example.com/callback"
+        );
+    }
+
+    /// Tests that redirect URIs missing the callback route are flagged
+    #[test]
+    fn test_callback_path_mismatch() {
+        let url = |url: &str| Url::parse(url).unwrap();
+        let base_url = url("https://example.com/auth");
+
+        assert_eq!(
+            callback_path_mismatch(&url("https://app.example.com/callback"), None, ""),
+            None
+        );
+        assert_eq!(
+            callback_path_mismatch(&url("https://app.example.com/oauth/callback"), None, ""),
+            Some("/callback".to_string())
+        );
+        assert_eq!(
+            callback_path_mismatch(
+                &url("https://example.com/auth/callback"),
+                Some(&base_url),
+                ""
+            ),
+            None
+        );
+        assert_eq!(
+            callback_path_mismatch(&url("https://example.com/callback"), Some(&base_url), ""),
+            Some("/auth/callback".to_string())
+        );
+
+        // The route prefix follows the base URL's path
+        assert_eq!(
+            callback_path_mismatch(&url("https://app.example.com/auth/callback"), None, "/auth"),
+            None
+        );
+        assert_eq!(
+            callback_path_mismatch(&url("https://app.example.com/callback"), None, "/auth"),
+            Some("/auth/callback".to_string())
+        );
+        assert_eq!(
+            callback_path_mismatch(
+                &url("https://example.com/auth/oauth/callback"),
+                Some(&base_url),
+                "/oauth"
+            ),
+            None
+        );
+
+        // Mismatched redirect URIs are still accepted
+        assert!(
+            resolve_redirect_uri("google", "https://app.example.com/oauth2/google", None, "")
+                .is_ok()
+        );
+    }
+
+    /// Tests that unknown providers are only an error in strict mode
+    #[tokio::test]
+    async fn test_unknown_provider() {
+        let oauth = HashMap::from([("bitbucket".to_string(), provider_settings())]);
+        assert!(build_oauth_providers(
+            &HttpClient::default(),
+            &ProviderRegistry::with_builtins(),
+            &oauth,
+            None,
+            "",
+            false
+        )
+        .await
+        .unwrap()
+        .is_empty());
+        assert_eq!(
+            build_oauth_providers(
+                &HttpClient::default(),
+                &ProviderRegistry::with_builtins(),
+                &oauth,
+                None,
+                "",
+                true
+            )
+            .await
+            .err(),
+            Some(ConfigError::UnknownProvider {
+                provider: "bitbucket".to_string(),
+            })
+        );
+    }
+
+    /// Tests that `type` selects the implementation and that failed discovery is an error
+    #[tokio::test]
+    async fn test_provider_type() {
+        let corp = OAuthSettings {
+            provider_type: Some("google".to_string()),
+            ..provider_settings()
+        };
+        let oauth = HashMap::from([("corp".to_string(), corp)]);
+        let providers = build_oauth_providers(
+            &HttpClient::default(),
+            &ProviderRegistry::with_builtins(),
+            &oauth,
+            None,
+            "",
+            true,
+        )
+        .await
+        .unwrap();
+        assert!(providers.contains_key("corp"));
+
+        let mycompany = OAuthSettings {
+            provider_type: Some("oidc".to_string()),
+            issuer: Some("http://127.0.0.1:9/unreachable".to_string()),
+            ..provider_settings()
+        };
+        let oauth = HashMap::from([("mycompany".to_string(), mycompany)]);
+        assert!(matches!(
+            build_oauth_providers(&HttpClient::default(), &ProviderRegistry::with_builtins(), &oauth, None, "", false).await,
+            Err(ConfigError::DiscoveryFailed { provider, .. }) if provider == "mycompany"
+        ));
+    }
+
+    /// Tests that sections sharing a `type` are built as separate providers
+    #[tokio::test]
+    async fn test_provider_instances() {
+        let instance = |client_id: &str| OAuthSettings {
+            provider_type: Some("google".to_string()),
+            client_id: client_id.to_string(),
+            ..provider_settings()
+        };
+        let oauth = HashMap::from([
+            ("google_staging".to_string(), instance("staging-client")),
+            ("google_internal".to_string(), instance("internal-client")),
+        ]);
+        let providers = build_oauth_providers(
+            &HttpClient::default(),
+            &ProviderRegistry::with_builtins(),
+            &oauth,
+            None,
+            "",
+            true,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(providers.len(), 2);
+        for (name, client_id) in [
+            ("google_staging", "staging-client"),
+            ("google_internal", "internal-client"),
+        ] {
+            let client = providers[name].get_oauth_client();
+            assert_eq!(client.client_id().as_str(), client_id);
+        }
+    }
+
+    /// Tests that `type = "generic"` builds a provider under any name once it has a user id path
+    #[tokio::test]
+    async fn test_generic_provider() {
+        let internal = OAuthSettings {
+            provider_type: Some("generic".to_string()),
+            user_id_path: Some("data.user.id".to_string()),
+            ..provider_settings()
+        };
+        let oauth = HashMap::from([("internal".to_string(), internal)]);
+        let providers = build_oauth_providers(
+            &HttpClient::default(),
+            &ProviderRegistry::with_builtins(),
+            &oauth,
+            None,
+            "",
+            true,
+        )
+        .await
+        .unwrap();
+        assert!(providers.contains_key("internal"));
+
+        let internal = OAuthSettings {
+            provider_type: Some("generic".to_string()),
+            ..provider_settings()
+        };
+        let oauth = HashMap::from([("internal".to_string(), internal)]);
+        assert!(matches!(
+            build_oauth_providers(&HttpClient::default(), &ProviderRegistry::with_builtins(), &oauth, None, "", false).await,
+            Err(ConfigError::InvalidProviderSettings { provider, .. }) if provider == "internal"
+        ));
+    }
+
+    /// Provider registered by an application embedding the server
+    struct CustomProvider {
+        /// HTTP client for API requests
+        client: HttpClient,
+        /// Configured OAuth client
+        oauth_client: crate::types::OAuthClient,
+    }
+
+    #[async_trait::async_trait]
+    impl OAuthProvider for CustomProvider {
+        fn get_oauth_client(&self) -> &crate::types::OAuthClient {
+            &self.oauth_client
+        }
+
+        fn http_client(&self) -> &HttpClient {
+            &self.client
+        }
+
+        fn get_scopes(&self) -> Vec<String> {
+            vec!["custom".to_string()]
+        }
+
+        async fn get_user_info(&self, _access_token: &str) -> Result<crate::primitives::UserInfo> {
+            bail!("No user info")
+        }
+    }
+
+    /// Factory of `CustomProvider`
+    struct CustomProviderFactory;
+
+    impl crate::traits::OAuthProviderFactory for CustomProviderFactory {
+        fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+            Ok(Arc::new(CustomProvider {
+                client: Arc::clone(ctx.http_client),
+                oauth_client: ctx.oauth_client,
+            }))
+        }
+    }
+
+    /// Factory requiring the `issuer` of its section
+    struct IssuerRequiredFactory;
+
+    impl crate::traits::OAuthProviderFactory for IssuerRequiredFactory {
+        fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+            if ctx.settings.issuer.is_none() {
+                bail!("{} requires an issuer", ctx.name);
+            }
+            CustomProviderFactory.create(ctx)
+        }
+    }
+
+    /// Tests that a factory rejecting its context aborts startup naming the provider
+    #[tokio::test]
+    async fn test_factory_rejects_context() {
+        let mut registry = ProviderRegistry::with_builtins();
+        registry.register("tenant", Arc::new(IssuerRequiredFactory));
+        let oauth = HashMap::from([("tenant".to_string(), provider_settings())]);
+        let error =
+            build_oauth_providers(&HttpClient::default(), &registry, &oauth, None, "", true)
+                .await
+                .err()
+                .unwrap();
+        assert!(matches!(
+            &error,
+            ConfigError::InvalidProviderSettings { provider, .. } if provider == "tenant"
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Invalid oauth.tenant: tenant requires an issuer"
+        );
+
+        let oauth = HashMap::from([(
+            "tenant".to_string(),
+            OAuthSettings {
+                issuer: Some("https://tenant.example.com".to_string()),
+                ..provider_settings()
+            },
+        )]);
+        assert!(
+            build_oauth_providers(&HttpClient::default(), &registry, &oauth, None, "", true)
+                .await
+                .is_ok()
+        );
+
+        // The Apple signing key is loaded at startup
+        let apple = OAuthSettings {
+            team_id: Some("TEAM123456".to_string()),
+            key_id: Some("KEY1234567".to_string()),
+            private_key_path: Some("/nonexistent/AuthKey.p8".to_string()),
+            ..provider_settings()
+        };
+        let oauth = HashMap::from([("apple".to_string(), apple)]);
+        let error = build_oauth_providers(
+            &HttpClient::default(),
+            &ProviderRegistry::with_builtins(),
+            &oauth,
+            None,
+            "",
+            true,
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("Invalid oauth.apple: Failed to read /nonexistent/AuthKey.p8"));
+    }
+
+    /// Tests that a provider registered at runtime is built into the application state
+    #[tokio::test]
+    async fn test_custom_provider() {
+        let oauth = HashMap::from([
+            ("custom".to_string(), provider_settings()),
+            ("google".to_string(), provider_settings()),
+        ]);
+
+        // Unknown until registered
+        assert!(matches!(
+            build_oauth_providers(&HttpClient::default(), &ProviderRegistry::with_builtins(), &oauth, None, "", true).await,
+            Err(ConfigError::UnknownProvider { provider }) if provider == "custom"
+        ));
+
+        let mut registry = ProviderRegistry::with_builtins();
+        registry.register("custom", Arc::new(CustomProviderFactory));
+        let http_client = HttpClient::default();
+        let providers = build_oauth_providers(&http_client, &registry, &oauth, None, "", true)
+            .await
+            .unwrap();
+
+        let settings: Settings =
+            serde_json::from_value(serde_json::json!({ "port": 3000, "oauth": {} })).unwrap();
+        let state = build_app_state(&settings, http_client, providers).unwrap();
+        assert_eq!(state.oauth_providers["custom"].get_scopes(), ["custom"]);
+        assert!(state.oauth_providers.contains_key("google"));
+    }
+
+    /// Tests that a valid configuration passes the check and that every
+    /// problem of an invalid one is reported
+    #[tokio::test]
+    async fn test_check_config() {
+        let registry = ProviderRegistry::with_builtins();
+        let mut settings: Settings = serde_json::from_value(serde_json::json!({
+            "port": 3000,
+            "oauth": {},
+            "base_url": "https://app.example.com",
+        }))
+        .unwrap();
+        settings
+            .oauth
+            .insert("google".to_string(), provider_settings());
+        settings.oauth.insert(
+            "github".to_string(),
+            OAuthSettings {
+                redirect_uri: String::new(),
+                ..provider_settings()
+            },
+        );
+
+        let report = check_config(&settings, &registry).await;
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.providers, ["github", "google"]);
+        assert!(report.to_string().ends_with("2 provider(s) configured"));
+
+        settings.oauth.insert(
+            "google".to_string(),
+            OAuthSettings {
+                redirect_uri: "https://evil.example.com/callback".to_string(),
+                ..provider_settings()
+            },
+        );
+        settings.oauth.insert(
+            "github".to_string(),
+            OAuthSettings {
+                token_url: "/token".to_string(),
+                ..provider_settings()
+            },
+        );
+        settings
+            .oauth
+            .insert("bitbucket".to_string(), provider_settings());
+        settings.success_redirect_url = Some("not a url".to_string());
+
+        let report = check_config(&settings, &registry).await;
+        assert!(!report.is_ok());
+        assert!(report.providers.is_empty());
+        assert_eq!(
+            report.errors,
+            [
+                "Unknown OAuth provider oauth.bitbucket",
+                "Invalid oauth.github.token_url: relative URL without a base",
+                "Invalid oauth.google: redirect_uri https://evil.example.com/callback is not under base_url https://app.example.com/",
+                "Invalid success_redirect_url: relative URL without a base",
+            ]
+        );
+        assert!(report.to_string().ends_with("4 error(s) found"));
+
+        settings.oauth.clear();
+        settings.base_url = Some("app.example.com".to_string());
+        settings.success_redirect_url = None;
+        let report = check_config(&settings, &registry).await;
+        assert_eq!(
+            report.errors,
+            [
+                "Invalid base_url: relative URL without a base",
+                "No OAuth provider is configured",
+            ]
+        );
+    }
+
+    /// Tests that redirect URIs must have the origin and path prefix of the base URL
+    #[test]
+    fn test_check_redirect_uri() {
+        let base_url = Url::parse("https://example.com/auth/").unwrap();
+        let cases = [
+            ("https://example.com/auth/callback", None, true),
+            ("https://example.com/auth/callback", Some(&base_url), true),
+            ("https://example.com/authz/callback", Some(&base_url), false),
+            ("https://example.com/callback", Some(&base_url), false),
+            ("http://example.com/auth/callback", Some(&base_url), false),
+            (
+                "https://example.com:8443/auth/callback",
+                Some(&base_url),
+                false,
+            ),
+        ];
+
+        for (redirect_uri, base_url, valid) in cases {
+            assert_eq!(
+                check_redirect_uri("google", redirect_uri, base_url).is_ok(),
+                valid,
+                "{}",
+                redirect_uri
+            );
+        }
+    }
+}
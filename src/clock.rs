@@ -0,0 +1,149 @@
+use std::{
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Source of the current unix time
+///
+/// Abstracted so tests can control time instead of sleeping.
+pub trait TimeSource: Send + Sync {
+    /// Returns the current unix timestamp in seconds
+    fn now(&self) -> u64;
+}
+
+/// Time source backed by the system clock
+pub struct SystemTimeSource;
+
+impl TimeSource for SystemTimeSource {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or_default()
+    }
+}
+
+/// Clock used for every time-based validation
+///
+/// Hosts drift by a few seconds, so strict comparisons against timestamps
+/// minted elsewhere (or by another instance) produce spurious rejections.
+/// All expiry and issued-at checks go through this clock, which tolerates
+/// a configurable leeway in both directions.
+///
+/// # Fields
+///
+/// * `source` - Source of the current time
+/// * `leeway_seconds` - Tolerated clock skew
+#[derive(Clone)]
+pub struct Clock {
+    /// Source of the current time
+    source: Arc<dyn TimeSource>,
+    /// Tolerated clock skew in seconds
+    leeway_seconds: u64,
+}
+
+impl Clock {
+    /// Creates a clock reading the given time source
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - Source of the current time
+    /// * `leeway_seconds` - Tolerated clock skew
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `Clock` instance
+    pub fn new(source: Arc<dyn TimeSource>, leeway_seconds: u64) -> Self {
+        Self {
+            source,
+            leeway_seconds,
+        }
+    }
+
+    /// Creates a clock reading the system time
+    ///
+    /// # Arguments
+    ///
+    /// * `leeway_seconds` - Tolerated clock skew
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `Clock` instance
+    pub fn system(leeway_seconds: u64) -> Self {
+        Self::new(Arc::new(SystemTimeSource), leeway_seconds)
+    }
+
+    /// Returns the current unix timestamp in seconds
+    pub fn now(&self) -> u64 {
+        self.source.now()
+    }
+
+    /// Returns whether a timestamp has passed, allowing for the leeway
+    ///
+    /// # Arguments
+    ///
+    /// * `expires_at` - Unix timestamp after which the value is no longer valid
+    pub fn has_expired(&self, expires_at: u64) -> bool {
+        self.now() > expires_at.saturating_add(self.leeway_seconds)
+    }
+
+    /// Returns whether a timestamp lies in the future, allowing for the leeway
+    ///
+    /// # Arguments
+    ///
+    /// * `issued_at` - Unix timestamp at which the value was issued
+    pub fn is_in_future(&self, issued_at: u64) -> bool {
+        issued_at > self.now().saturating_add(self.leeway_seconds)
+    }
+}
+
+/// Time source returning a settable timestamp
+#[cfg(test)]
+#[derive(Default)]
+pub struct FixedTimeSource(std::sync::atomic::AtomicU64);
+
+#[cfg(test)]
+impl FixedTimeSource {
+    /// Sets the current unix timestamp
+    pub fn set(&self, now: u64) {
+        self.0.store(now, std::sync::atomic::Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+impl TimeSource for FixedTimeSource {
+    fn now(&self) -> u64 {
+        self.0.load(std::sync::atomic::Ordering::SeqCst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that expiry tolerates the leeway and nothing beyond it
+    #[test]
+    fn test_expiry_leeway() {
+        let time = Arc::new(FixedTimeSource::default());
+        let clock = Clock::new(time.clone(), 30);
+
+        time.set(1_000);
+        assert!(!clock.has_expired(1_000));
+        time.set(1_030);
+        assert!(!clock.has_expired(1_000));
+        time.set(1_031);
+        assert!(clock.has_expired(1_000));
+    }
+
+    /// Tests that issue times slightly in the future are tolerated
+    #[test]
+    fn test_issued_at_leeway() {
+        let time = Arc::new(FixedTimeSource::default());
+        let clock = Clock::new(time.clone(), 30);
+
+        time.set(1_000);
+        assert!(!clock.is_in_future(1_000));
+        assert!(!clock.is_in_future(1_030));
+        assert!(clock.is_in_future(1_031));
+    }
+}
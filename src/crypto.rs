@@ -0,0 +1,390 @@
+use crate::{server::audit::hex, settings::EncryptionSettings};
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use eyre::{bail, eyre, Result};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Prefix marking a value produced by [`SecretCipher::encrypt`]
+const ENCRYPTED_PREFIX: &str = "enc:v1:";
+
+/// Length in bytes of an AES-GCM nonce
+const NONCE_LEN: usize = 12;
+
+/// Length in bytes of an AES-256 key
+const KEY_LEN: usize = 32;
+
+/// Label the blind index keys are derived under, keeping them apart from the encryption keys
+const BLIND_INDEX_LABEL: &[u8] = b"oauth-server blind index v1";
+
+/// Application-level encryption for secrets persisted outside the process
+///
+/// This struct encrypts bearer-equivalent secrets (PKCE verifiers, tokens)
+/// with AES-256-GCM before they are written to the session store, and
+/// decrypts them transparently on read. It holds a key ring: the first key
+/// is used for encryption and every key is tried for decryption, which
+/// allows keys to be rotated without invalidating in-flight records.
+///
+/// Values that must stay searchable, such as emails, are stored encrypted
+/// next to a blind index: an HMAC-SHA256 under a key derived from each
+/// encryption key, which supports equality lookups without revealing the
+/// value.
+///
+/// # Fields
+///
+/// * `keys` - AES-256-GCM ciphers, the first one is used for encryption
+/// * `index_keys` - Blind index keys derived from the encryption keys, in the same order
+/// * `allow_plaintext` - Whether unencrypted records are accepted on read
+pub struct SecretCipher {
+    /// AES-256-GCM ciphers, the first one is used for encryption
+    keys: Vec<Aes256Gcm>,
+    /// Blind index keys derived from the encryption keys
+    index_keys: Vec<[u8; KEY_LEN]>,
+    /// Whether unencrypted records are accepted on read
+    allow_plaintext: bool,
+}
+
+impl SecretCipher {
+    /// Creates a new cipher from raw 32-byte keys
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - Raw AES-256 keys, the first one is used for encryption
+    /// * `allow_plaintext` - Whether unencrypted records are accepted on read
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `SecretCipher`, or an error if no key is provided
+    pub fn new(keys: &[[u8; KEY_LEN]], allow_plaintext: bool) -> Result<Self> {
+        if keys.is_empty() {
+            bail!("At least one encryption key is required");
+        }
+
+        Ok(Self {
+            keys: keys.iter().map(|key| Aes256Gcm::new(key.into())).collect(),
+            index_keys: keys
+                .iter()
+                .map(|key| hmac_sha256(key, BLIND_INDEX_LABEL))
+                .collect(),
+            allow_plaintext,
+        })
+    }
+
+    /// Creates a cipher that stores values unencrypted
+    ///
+    /// Used when no `[encryption]` section is configured. Values are passed
+    /// through as-is, and encrypted records cannot be read back.
+    ///
+    /// # Returns
+    ///
+    /// Returns a pass-through `SecretCipher`
+    pub fn disabled() -> Self {
+        Self {
+            keys: Vec::new(),
+            index_keys: Vec::new(),
+            allow_plaintext: true,
+        }
+    }
+
+    /// Creates a new cipher from the encryption settings
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Encryption settings with base64-encoded keys
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `SecretCipher`, or an error if a key is not valid
+    /// base64 or is not exactly 32 bytes long
+    pub fn from_settings(settings: &EncryptionSettings) -> Result<Self> {
+        let keys = settings
+            .keys
+            .iter()
+            .enumerate()
+            .map(|(index, key)| {
+                let bytes = STANDARD
+                    .decode(key.expose().trim())
+                    .map_err(|e| eyre!("Encryption key {} is not valid base64: {}", index, e))?;
+                <[u8; KEY_LEN]>::try_from(bytes.as_slice()).map_err(|_| {
+                    eyre!(
+                        "Encryption key {} must be {} bytes, got {}",
+                        index,
+                        KEY_LEN,
+                        bytes.len()
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Self::new(&keys, settings.allow_plaintext)
+    }
+
+    /// Returns whether encryption keys are configured
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    /// Returns whether unencrypted records are accepted on read
+    pub fn allows_plaintext(&self) -> bool {
+        self.allow_plaintext
+    }
+
+    /// Encrypts raw bytes with the active key
+    ///
+    /// A fresh random nonce is generated for every call, so sealing the
+    /// same bytes twice yields different outputs.
+    ///
+    /// # Arguments
+    ///
+    /// * `plaintext` - The bytes to encrypt
+    ///
+    /// # Returns
+    ///
+    /// Returns `nonce || ciphertext`, or an error if encryption is disabled
+    pub fn seal(&self, plaintext: &[u8]) -> Result<Vec<u8>> {
+        let cipher = self
+            .keys
+            .first()
+            .ok_or_else(|| eyre!("No encryption key configured"))?;
+
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher
+            .encrypt(&nonce, plaintext)
+            .map_err(|_| eyre!("Failed to encrypt secret"))?;
+
+        let mut payload = nonce.to_vec();
+        payload.extend_from_slice(&ciphertext);
+        Ok(payload)
+    }
+
+    /// Decrypts bytes produced by [`SecretCipher::seal`]
+    ///
+    /// Every key in the ring is tried in order.
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The `nonce || ciphertext` bytes to decrypt
+    ///
+    /// # Returns
+    ///
+    /// Returns the decrypted bytes, or an error if the payload is malformed
+    /// or was not encrypted under any configured key
+    pub fn open(&self, payload: &[u8]) -> Result<Vec<u8>> {
+        if payload.len() < NONCE_LEN {
+            bail!("Encrypted secret is too short");
+        }
+        let (nonce, ciphertext) = payload.split_at(NONCE_LEN);
+        let nonce = Nonce::from_slice(nonce);
+
+        self.keys
+            .iter()
+            .find_map(|cipher| cipher.decrypt(nonce, ciphertext).ok())
+            .ok_or_else(|| eyre!("Failed to decrypt secret with any configured key"))
+    }
+
+    /// Encrypts a secret value with the active key
+    ///
+    /// # Arguments
+    ///
+    /// * `plaintext` - The secret value to encrypt
+    ///
+    /// # Returns
+    ///
+    /// Returns the encrypted value as `enc:v1:<base64(nonce || ciphertext)>`,
+    /// or the plaintext unchanged when encryption is disabled
+    pub fn encrypt(&self, plaintext: &str) -> Result<String> {
+        if !self.is_enabled() {
+            return Ok(plaintext.to_string());
+        }
+
+        let payload = self.seal(plaintext.as_bytes())?;
+        Ok(format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(payload)))
+    }
+
+    /// Decrypts a value previously produced by [`SecretCipher::encrypt`]
+    ///
+    /// Values without the encryption prefix are returned as-is only when
+    /// plaintext records are allowed.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The stored value to decrypt
+    ///
+    /// # Returns
+    ///
+    /// Returns the decrypted secret, or an error if the value is malformed,
+    /// was encrypted under an unknown key, or is plaintext while plaintext
+    /// records are not allowed
+    pub fn decrypt(&self, value: &str) -> Result<String> {
+        let Some(encoded) = value.strip_prefix(ENCRYPTED_PREFIX) else {
+            if self.allow_plaintext {
+                return Ok(value.to_string());
+            }
+            bail!("Refusing to read unencrypted secret");
+        };
+
+        let payload = STANDARD
+            .decode(encoded)
+            .map_err(|e| eyre!("Encrypted secret is not valid base64: {}", e))?;
+        let plaintext = self.open(&payload)?;
+
+        String::from_utf8(plaintext).map_err(|_| eyre!("Decrypted secret is not valid UTF-8"))
+    }
+
+    /// Computes the blind index of a value with the active key
+    ///
+    /// The index is deterministic, so equal values have equal indexes and
+    /// can be looked up by it. Callers normalize the value first.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to index
+    ///
+    /// # Returns
+    ///
+    /// Returns the HMAC-SHA256 of the value in hex, or an error if
+    /// encryption is disabled
+    pub fn blind_index(&self, value: &str) -> Result<String> {
+        let key = self
+            .index_keys
+            .first()
+            .ok_or_else(|| eyre!("No encryption key configured"))?;
+        Ok(index_with(key, value))
+    }
+
+    /// Computes the blind indexes of a value under every key in the ring
+    ///
+    /// Records indexed before a key rotation are found through the index of
+    /// the key they were written with, until they are re-indexed.
+    ///
+    /// # Arguments
+    ///
+    /// * `value` - The value to index
+    ///
+    /// # Returns
+    ///
+    /// Returns one index per key, the active key's first, none when
+    /// encryption is disabled
+    pub fn blind_indexes(&self, value: &str) -> Vec<String> {
+        self.index_keys
+            .iter()
+            .map(|key| index_with(key, value))
+            .collect()
+    }
+}
+
+/// Computes the HMAC-SHA256 of a value in hex
+fn index_with(key: &[u8; KEY_LEN], value: &str) -> String {
+    hex(&hmac_sha256(key, value.as_bytes()))
+}
+
+/// Computes the HMAC-SHA256 of a message
+fn hmac_sha256(key: &[u8], message: &[u8]) -> [u8; KEY_LEN] {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(message);
+    mac.finalize().into_bytes().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redact::SecretString;
+
+    /// Tests that an encrypted value decrypts back to the original
+    #[test]
+    fn test_round_trip() {
+        let cipher = SecretCipher::new(&[[7u8; KEY_LEN]], false).unwrap();
+        let encrypted = cipher.encrypt("pkce-verifier").unwrap();
+
+        assert!(encrypted.starts_with(ENCRYPTED_PREFIX));
+        assert!(!encrypted.contains("pkce-verifier"));
+        assert_eq!(cipher.decrypt(&encrypted).unwrap(), "pkce-verifier");
+    }
+
+    /// Tests that a value encrypted under one key is rejected by another
+    #[test]
+    fn test_wrong_key_is_rejected() {
+        let cipher = SecretCipher::new(&[[1u8; KEY_LEN]], false).unwrap();
+        let other = SecretCipher::new(&[[2u8; KEY_LEN]], false).unwrap();
+
+        let encrypted = cipher.encrypt("secret").unwrap();
+        assert!(other.decrypt(&encrypted).is_err());
+    }
+
+    /// Tests that values encrypted under a rotated-out key still decrypt
+    #[test]
+    fn test_key_rotation() {
+        let old = SecretCipher::new(&[[1u8; KEY_LEN]], false).unwrap();
+        let rotated = SecretCipher::new(&[[2u8; KEY_LEN], [1u8; KEY_LEN]], false).unwrap();
+
+        let encrypted = old.encrypt("secret").unwrap();
+        assert_eq!(rotated.decrypt(&encrypted).unwrap(), "secret");
+    }
+
+    /// Tests that plaintext records are only readable during migration
+    #[test]
+    fn test_plaintext_migration_flag() {
+        let strict = SecretCipher::new(&[[1u8; KEY_LEN]], false).unwrap();
+        let migrating = SecretCipher::new(&[[1u8; KEY_LEN]], true).unwrap();
+
+        assert!(strict.decrypt("legacy-verifier").is_err());
+        assert_eq!(
+            migrating.decrypt("legacy-verifier").unwrap(),
+            "legacy-verifier"
+        );
+    }
+
+    /// Tests that tampered ciphertexts fail authentication
+    #[test]
+    fn test_tampered_ciphertext_is_rejected() {
+        let cipher = SecretCipher::new(&[[1u8; KEY_LEN]], false).unwrap();
+        let encrypted = cipher.encrypt("secret").unwrap();
+
+        let mut payload = STANDARD
+            .decode(encrypted.strip_prefix(ENCRYPTED_PREFIX).unwrap())
+            .unwrap();
+        *payload.last_mut().unwrap() ^= 0x01;
+        let tampered = format!("{}{}", ENCRYPTED_PREFIX, STANDARD.encode(payload));
+
+        assert!(cipher.decrypt(&tampered).is_err());
+    }
+
+    /// Tests that keys of the wrong length fail settings validation
+    #[test]
+    fn test_from_settings_rejects_short_key() {
+        let settings = EncryptionSettings {
+            keys: vec![SecretString::from(STANDARD.encode([0u8; 16]))],
+            allow_plaintext: false,
+        };
+        assert!(SecretCipher::from_settings(&settings).is_err());
+    }
+
+    /// Tests that blind indexes are deterministic per key, differ between
+    /// keys, and follow the key ring order
+    #[test]
+    fn test_blind_index() {
+        let cipher = SecretCipher::new(&[[1u8; KEY_LEN]], false).unwrap();
+        let rotated = SecretCipher::new(&[[2u8; KEY_LEN], [1u8; KEY_LEN]], false).unwrap();
+
+        let index = cipher.blind_index("user@example.com").unwrap();
+        assert_eq!(index, cipher.blind_index("user@example.com").unwrap());
+        assert_ne!(index, cipher.blind_index("other@example.com").unwrap());
+        assert!(!index.contains("user"));
+
+        let indexes = rotated.blind_indexes("user@example.com");
+        assert_eq!(indexes.len(), 2);
+        assert_ne!(indexes[0], index);
+        assert_eq!(indexes[0], rotated.blind_index("user@example.com").unwrap());
+        assert_eq!(indexes[1], index);
+
+        assert!(SecretCipher::disabled()
+            .blind_index("user@example.com")
+            .is_err());
+        assert!(SecretCipher::disabled()
+            .blind_indexes("user@example.com")
+            .is_empty());
+    }
+}
@@ -0,0 +1,292 @@
+//! Outgoing HTTP client
+//!
+//! Every request to the identity providers goes through one shared client,
+//! which retries requests that failed before reaching the provider or that
+//! the provider answered with a server error. Requests carry the trace
+//! context of the current span, and the status of the responses is recorded
+//! on it.
+
+use crate::telemetry::{record_status, trace_context_headers};
+use oauth2::{AsyncHttpClient, HttpClientError, HttpRequest, HttpResponse};
+use reqwest::{header::HeaderMap, RequestBuilder, Response};
+use std::{future::Future, ops::Deref, pin::Pin, time::Duration};
+use tracing::warn;
+
+/// Retry policy of the outgoing requests
+///
+/// Only connection failures and 5xx responses are retried. Timeouts are
+/// not, a provider that is too slow once is unlikely to answer faster on
+/// the next attempt, and 4xx responses would fail the same way again.
+///
+/// # Fields
+///
+/// * `max_retries` - Retries after the first attempt, none when zero
+/// * `initial_backoff` - Delay before the first retry, doubled for each next one
+#[derive(Debug, Clone, Default)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt
+    pub max_retries: u32,
+    /// Delay before the first retry
+    pub initial_backoff: Duration,
+}
+
+impl RetryPolicy {
+    /// Returns the delays before each retry
+    ///
+    /// # Returns
+    ///
+    /// Returns `max_retries` exponentially growing delays
+    fn delays(&self) -> impl Iterator<Item = Duration> + '_ {
+        (0..self.max_retries).map(|retry| self.initial_backoff.saturating_mul(1 << retry.min(16)))
+    }
+}
+
+/// HTTP client shared by the providers and the token exchange
+///
+/// It dereferences to the underlying `reqwest::Client`, requests sent
+/// through `send` are retried according to the retry policy.
+///
+/// # Fields
+///
+/// * `client` - The underlying client, with its timeouts and User-Agent
+/// * `retry` - Retry policy of the requests sent through `send`
+#[derive(Debug, Default)]
+pub struct SharedClient {
+    /// The underlying client
+    client: reqwest::Client,
+    /// Retry policy of the requests sent through `send`
+    retry: RetryPolicy,
+}
+
+impl SharedClient {
+    /// Creates a shared client
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The configured client
+    /// * `retry` - Retry policy of the requests
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `SharedClient` instance
+    pub fn new(client: reqwest::Client, retry: RetryPolicy) -> Self {
+        Self { client, retry }
+    }
+
+    /// Sends a request, retrying connection failures and server errors
+    ///
+    /// Requests with a streaming body cannot be replayed and are sent once.
+    ///
+    /// # Arguments
+    ///
+    /// * `request` - The request to send
+    ///
+    /// # Returns
+    ///
+    /// Returns the response of the last attempt, whatever its status, or
+    /// the error of the last attempt
+    pub async fn send(&self, request: RequestBuilder) -> reqwest::Result<Response> {
+        let request = request.headers(trace_context_headers());
+        for delay in self.retry.delays() {
+            let Some(attempt) = request.try_clone() else {
+                break;
+            };
+            let result = attempt.send().await;
+            let retryable = match &result {
+                Ok(response) => response.status().is_server_error(),
+                Err(e) => e.is_connect(),
+            };
+            if !retryable {
+                return result.inspect(|response| record_status(response.status()));
+            }
+            warn!("Provider request failed, retrying in {:?}", delay);
+            tokio::time::sleep(delay).await;
+        }
+
+        request
+            .send()
+            .await
+            .inspect(|response| record_status(response.status()))
+    }
+
+    /// Returns a client sending the OAuth client's token requests
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - Extra headers of the provider's token endpoint
+    ///
+    /// # Returns
+    ///
+    /// Returns a `TokenHttpClient` borrowing this client
+    pub fn token_client(&self, headers: HeaderMap) -> TokenHttpClient<'_> {
+        TokenHttpClient {
+            http_client: self,
+            headers,
+        }
+    }
+}
+
+impl Deref for SharedClient {
+    type Target = reqwest::Client;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
+
+/// Shared HTTP client sending the OAuth client's token requests
+///
+/// # Fields
+///
+/// * `http_client` - The shared HTTP client
+/// * `headers` - Extra headers of the provider's token endpoint
+pub struct TokenHttpClient<'a> {
+    /// The shared HTTP client
+    http_client: &'a SharedClient,
+    /// Extra headers of the provider's token endpoint
+    headers: HeaderMap,
+}
+
+impl<'c> AsyncHttpClient<'c> for TokenHttpClient<'_> {
+    type Error = HttpClientError<reqwest::Error>;
+    type Future = Pin<Box<dyn Future<Output = Result<HttpResponse, Self::Error>> + Send + 'c>>;
+
+    /// Sends a request built by the OAuth client with the extra headers,
+    /// retrying connection failures and server errors
+    fn call(&'c self, mut request: HttpRequest) -> Self::Future {
+        request.headers_mut().extend(self.headers.clone());
+        request.headers_mut().extend(trace_context_headers());
+
+        Box::pin(async move {
+            let client = &self.http_client.client;
+            for delay in self.http_client.retry.delays() {
+                let result = client.call(clone_request(&request)).await;
+                let retryable = match &result {
+                    Ok(response) => response.status().is_server_error(),
+                    Err(HttpClientError::Reqwest(e)) => e.is_connect(),
+                    Err(_) => false,
+                };
+                if !retryable {
+                    return result.inspect(|response| record_status(response.status()));
+                }
+                warn!("Token request failed, retrying in {:?}", delay);
+                tokio::time::sleep(delay).await;
+            }
+
+            client
+                .call(request)
+                .await
+                .inspect(|response| record_status(response.status()))
+        })
+    }
+}
+
+/// Copies a token request so it can be sent again
+///
+/// # Arguments
+///
+/// * `request` - The request built by the OAuth client
+///
+/// # Returns
+///
+/// Returns a request with the same method, URI, version, headers and body
+fn clone_request(request: &HttpRequest) -> HttpRequest {
+    let mut clone = HttpRequest::new(request.body().clone());
+    *clone.method_mut() = request.method().clone();
+    *clone.uri_mut() = request.uri().clone();
+    *clone.version_mut() = request.version();
+    *clone.headers_mut() = request.headers().clone();
+    clone
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{http::StatusCode, routing::get, Router};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    /// Serves the given statuses in order, then 200, and returns the URL and hit counter
+    async fn flaky_server(statuses: &'static [StatusCode]) -> (String, Arc<AtomicUsize>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&hits);
+        let router = Router::new().route(
+            "/userinfo",
+            get(move || async move {
+                let hit = counter.fetch_add(1, Ordering::SeqCst);
+                statuses.get(hit).copied().unwrap_or(StatusCode::OK)
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        (format!("http://{}/userinfo", address), hits)
+    }
+
+    fn client(max_retries: u32) -> SharedClient {
+        SharedClient::new(
+            reqwest::Client::new(),
+            RetryPolicy {
+                max_retries,
+                initial_backoff: Duration::from_millis(1),
+            },
+        )
+    }
+
+    /// Tests that server errors are retried until the provider recovers
+    #[tokio::test]
+    async fn test_retries_server_errors() {
+        let (url, hits) = flaky_server(&[
+            StatusCode::SERVICE_UNAVAILABLE,
+            StatusCode::SERVICE_UNAVAILABLE,
+        ])
+        .await;
+        let client = client(2);
+
+        let response = client.send(client.get(&url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    /// Tests that the last server error is returned once the retries are exhausted
+    #[tokio::test]
+    async fn test_retries_are_bounded() {
+        let (url, hits) = flaky_server(&[StatusCode::SERVICE_UNAVAILABLE; 3]).await;
+        let client = client(1);
+
+        let response = client.send(client.get(&url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    /// Tests that client errors are never retried
+    #[tokio::test]
+    async fn test_client_errors_are_not_retried() {
+        let (url, hits) = flaky_server(&[StatusCode::UNAUTHORIZED]).await;
+        let client = client(2);
+
+        let response = client.send(client.get(&url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    /// Tests that token requests answered with a server error are retried
+    #[tokio::test]
+    async fn test_retries_token_requests() {
+        let (url, hits) = flaky_server(&[StatusCode::SERVICE_UNAVAILABLE]).await;
+        let client = client(2);
+        let mut request = HttpRequest::new(Vec::new());
+        *request.uri_mut() = url.parse().unwrap();
+
+        let response = client
+            .token_client(HeaderMap::new())
+            .call(request)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+}
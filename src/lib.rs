@@ -0,0 +1,94 @@
+//! OAuth 2.0 Server
+//!
+//! Signs users in with the configured OAuth 2.0 and OpenID Connect
+//! providers. The `oauth_server` binary serves the routes on their own,
+//! this library lets an existing axum application serve them itself.
+//!
+//! # Embedding the OAuth routes
+//!
+//! Build the providers and the application state from the configuration,
+//! then merge [`router`] into the application's router. The routes keep
+//! the flow state in the session, so the application adds the session
+//! layer, along with any middleware of its own:
+//!
+//! ```no_run
+//! use axum::{routing::get, Router};
+//! use oauth_server::{
+//!     app::{build_app_state, build_http_client, build_oauth_providers},
+//!     providers::ProviderRegistry,
+//!     router,
+//!     settings::Settings,
+//! };
+//! use std::net::SocketAddr;
+//! use tower_sessions::SessionManagerLayer;
+//! use tower_sessions_moka_store::MokaStore;
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let settings = Settings::from_toml("Settings.toml")?;
+//! let http_client = build_http_client(&settings.http_client)?;
+//! let registry = ProviderRegistry::with_builtins();
+//! let providers = build_oauth_providers(
+//!     &http_client,
+//!     &registry,
+//!     &settings.oauth,
+//!     None,
+//!     settings.route_prefix()?,
+//!     settings.strict_providers,
+//! )
+//! .await?;
+//! let state = build_app_state(&settings, http_client, providers)?;
+//! state.users.prepare().await?;
+//!
+//! let app = Router::new()
+//!     .route("/", get(|| async { "My application" }))
+//!     .nest("/auth", router(state))
+//!     .layer(SessionManagerLayer::new(MokaStore::new(Some(10_000))));
+//!
+//! let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
+//! axum::serve(
+//!     listener,
+//!     app.into_make_service_with_connect_info::<SocketAddr>(),
+//! )
+//! .await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Providers redirect back to the callback route, so with the routes nested
+//! under `/auth` the providers' `redirect_uri` must point at `/auth/callback`.
+//! Setting `route_prefix = "/auth"` makes omitted redirect URIs default to it
+//! and the `authorize_url` listed by `/providers` point at the nested route.
+//!
+//! # Custom providers
+//!
+//! Implement [`OAuthProvider`] and its [`OAuthProviderFactory`], then
+//! register the factory before building the providers. Sections select it
+//! by their name or their `type`:
+//!
+//! ```ignore
+//! let mut registry = ProviderRegistry::with_builtins();
+//! registry.register("internal", Arc::new(InternalProviderFactory));
+//! ```
+
+pub mod app;
+pub mod auth;
+pub mod clock;
+pub mod crypto;
+pub mod http_client;
+pub mod oidc;
+pub mod primitives;
+pub mod providers;
+pub mod redact;
+pub mod server;
+pub mod settings;
+pub mod telemetry;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod testing;
+pub mod traits;
+pub mod types;
+pub mod users;
+
+pub use app::{build_app_state, build_oauth_providers};
+pub use providers::ProviderRegistry;
+pub use server::server::{router, AppState, Server};
+pub use traits::{OAuthProvider, OAuthProviderFactory};
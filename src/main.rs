@@ -1,36 +1,78 @@
 //! OAuth 2.0 Server
 //!
 //! This module provides the main entry point for the OAuth 2.0 server application.
-//! It initializes the server with configured OAuth providers and starts the HTTP server.
-
-use crate::{
-    providers::OAUTH_PROVIDER_REGISTRY,
-    server::server::{AppState, Server},
-    settings::OAuthSettings,
-    traits::OAuthProvider,
+//! It builds the server from the library with the configured OAuth providers
+//! and starts the HTTP server.
+
+use clap::Parser;
+#[cfg(unix)]
+use oauth_server::server::server::AppState;
+use oauth_server::{
+    app::{
+        build_app_state, build_http_client, build_oauth_providers, build_user_repository,
+        check_config,
+    },
+    crypto::SecretCipher,
+    providers::ProviderRegistry,
+    redact,
+    server::{
+        cors::cors_layer,
+        reporting::{self, ErrorReporter},
+        server::Server,
+        session::{resolve_cookie_key, SessionBackend},
+    },
+    settings::Settings,
+    telemetry,
 };
-use eyre::Result;
-use oauth2::{basic::BasicClient, AuthUrl, ClientId, ClientSecret, RedirectUrl, TokenUrl};
 use reqwest::Url;
-use std::{collections::HashMap, sync::Arc};
-use tracing::{info, warn};
+use std::sync::Arc;
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info};
 
-mod primitives;
-mod providers;
-mod server;
-mod settings;
-mod traits;
-mod types;
+/// Command line arguments
+///
+/// # Fields
+///
+/// * `config` - Path of the configuration file
+/// * `check_config` - Validates the configuration and exits instead of starting the server
+/// * `migrate` - Applies the database migrations and exits instead of starting the server
+/// * `reindex_emails` - Re-encrypts and re-indexes the stored emails with the active key and exits
+#[derive(Debug, Parser)]
+#[command(about = "OAuth 2.0 server")]
+struct Args {
+    /// Path of the configuration file
+    #[arg(long, value_name = "PATH", default_value = "Settings.toml")]
+    config: String,
+    /// Validate the configuration, at PATH or `--config`, and exit with 0 when it is valid
+    #[arg(long, value_name = "PATH", num_args = 0..=1)]
+    check_config: Option<Option<String>>,
+    /// Apply the pending migrations of the `[database]` and exit
+    #[arg(long)]
+    migrate: bool,
+    /// Re-encrypt and re-index the stored emails with the first `encryption.keys` key and exit
+    #[arg(long)]
+    reindex_emails: bool,
+}
 
 /// Main application entry point
 ///
 /// Initializes the OAuth 2.0 server with the following steps:
-/// 1. Sets up tracing for logging
-/// 2. Loads configuration from Settings.toml
+/// 1. Loads configuration from `--config` (Settings.toml by default) and the
+///    environment
+/// 2. Sets up tracing for logging, exporting the spans when `[telemetry]` is
+///    configured, and the redaction of the configured secrets from the logs
 /// 3. Builds OAuth providers from configuration
-/// 4. Creates application state with providers
+/// 4. Creates application state with providers and the secret cipher
 /// 5. Starts the HTTP server
 ///
+/// With `--check-config`, the configuration is only validated and the
+/// process exits with 0 when it is valid, 1 otherwise. With `--migrate`,
+/// the pending database migrations are applied and the process exits. With
+/// `--reindex-emails`, the stored emails are moved to the active encryption
+/// key and the process exits. On SIGHUP, the `[jwt]` keys are reloaded from
+/// the configuration file.
+///
 /// # Returns
 ///
 /// Returns `Result<(), Box<dyn std::error::Error>>` indicating success or failure
@@ -42,79 +84,160 @@ mod types;
 /// - Server startup errors
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize tracing
-    tracing_subscriber::fmt::init();
+    let args = Args::parse();
+    if let Some(path) = args.check_config {
+        let _telemetry = telemetry::init(None)?;
+        let path = path.unwrap_or(args.config);
+        let report = match Settings::from_toml(&path) {
+            Ok(settings) => check_config(&settings, &ProviderRegistry::with_builtins()).await,
+            Err(e) => {
+                println!("Failed to load {}: {}", path, e);
+                std::process::exit(1);
+            }
+        };
+        println!("{}", report);
+        std::process::exit(if report.is_ok() { 0 } else { 1 });
+    }
 
-    let settings = settings::Settings::from_toml("Settings.toml");
+    let settings = Settings::from_toml(&args.config)?;
 
-    let oauth_providers = build_oauth_providers(&settings.oauth).unwrap();
+    // Initialize tracing, exporting the spans when configured
+    let _telemetry = telemetry::init(settings.telemetry.as_ref())?;
+    redact::configure(&settings.redaction, &settings.secret_values());
 
-    let app_state = Arc::new(AppState { oauth_providers });
+    if args.migrate {
+        if settings.database.is_none() {
+            error!("--migrate requires a [database] section");
+            std::process::exit(1);
+        }
+        build_user_repository(&settings)?.migrate().await?;
+        info!("The user database is up to date");
+        return Ok(());
+    }
 
-    info!("Starting server on port {}", settings.port);
+    if args.reindex_emails {
+        let (Some(_), Some(encryption)) = (&settings.database, &settings.encryption) else {
+            error!("--reindex-emails requires [database] and [encryption] sections");
+            std::process::exit(1);
+        };
+        let cipher = SecretCipher::from_settings(encryption)?;
+        let reindexed = build_user_repository(&settings)?
+            .reindex_emails(&cipher)
+            .await?;
+        info!(
+            "Re-indexed {} emails with the active encryption key",
+            reindexed
+        );
+        return Ok(());
+    }
 
-    let app = Server::new(settings.port, app_state);
+    let base_url = match &settings.base_url {
+        Some(url) => Some(Url::parse(url).map_err(|e| format!("Invalid base_url: {}", e))?),
+        None => None,
+    };
+
+    let http_client = build_http_client(&settings.http_client)?;
+
+    let registry = ProviderRegistry::with_builtins();
+    let oauth_providers = match build_oauth_providers(
+        &http_client,
+        &registry,
+        &settings.oauth,
+        base_url.as_ref(),
+        settings.route_prefix()?,
+        settings.strict_providers,
+    )
+    .await
+    {
+        Ok(oauth_providers) => oauth_providers,
+        Err(e) => {
+            error!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
 
-    app.run().await;
+    settings.session.cookie.validate()?;
+
+    let app_state = build_app_state(&settings, http_client, oauth_providers)?;
+    #[cfg(unix)]
+    tokio::spawn(reload_jwt_keys_on_hangup(
+        args.config.clone(),
+        Arc::clone(&app_state),
+    ));
+
+    info!("Starting server on port {}", settings.port);
+
+    let cookie_key = resolve_cookie_key(&settings.session)?;
+    let session_store = SessionBackend::from_settings(&settings.session).await?;
+
+    // Unexpected failures are reported to Sentry when built with the `sentry` feature
+    #[cfg(feature = "sentry")]
+    let _sentry = sentry::init(sentry::ClientOptions {
+        release: sentry::release_name!(),
+        ..Default::default()
+    });
+    #[cfg(feature = "sentry")]
+    let error_reporter: Arc<dyn ErrorReporter> = Arc::new(reporting::SentryReporter);
+    #[cfg(not(feature = "sentry"))]
+    let error_reporter: Arc<dyn ErrorReporter> = Arc::new(reporting::NoopReporter);
+
+    let cors = cors_layer(settings.cors.as_ref())?;
+
+    let app = Server::new(
+        settings.port,
+        app_state,
+        cookie_key,
+        settings.session.cookie.clone(),
+    )
+    .with_session_store(session_store, settings.session.ttl_seconds)
+    .with_error_reporter(error_reporter)
+    .with_cors(cors)
+    .with_shutdown_timeout(settings.shutdown_timeout_seconds)
+    .with_home_page(settings.serve_home_page)
+    .with_unprefixed_health(settings.unprefixed_health);
+
+    app.run().await?;
 
     Ok(())
 }
 
-/// Builds OAuth provider instances from configuration
+/// Reloads the `[jwt]` key ring from the configuration file on every SIGHUP
 ///
-/// This function creates OAuth client instances for each provider configured
-/// in the settings. It validates URLs, creates OAuth clients, and registers
-/// them with the provider factory.
+/// Only the keys, the active key, the issuer and the token lifetime are
+/// replaced. A configuration that fails to load or to build the ring is
+/// logged and the current keys are kept.
 ///
 /// # Arguments
 ///
-/// * `oauth` - HashMap containing OAuth provider configurations
-///
-/// # Returns
-///
-/// Returns `Result<HashMap<String, Arc<dyn OAuthProvider>>>` containing
-/// the initialized OAuth providers mapped by provider name
-fn build_oauth_providers(
-    oauth: &HashMap<String, OAuthSettings>,
-) -> Result<HashMap<String, Arc<dyn OAuthProvider>>> {
-    let mut oauth_providers = HashMap::new();
-
-    for (provider_name, provider_config) in oauth.iter() {
-        // Validate the required urls
-        let auth_url = AuthUrl::new(provider_config.auth_url.to_string())
-            .expect(&format!("Invalid auth_url for provider {}", provider_name));
-
-        let token_url = TokenUrl::new(provider_config.token_url.to_string())
-            .expect(&format!("Invalid token_url for provider {}", provider_name));
-
-        let redirect_url = RedirectUrl::new(provider_config.redirect_uri.to_string()).expect(
-            &format!("Invalid redirect_uri for provider {}", provider_name),
-        );
-
-        let user_info_url = Url::parse(&provider_config.user_info_url).expect(&format!(
-            "Invalid user_info_url for provider {}",
-            provider_name
-        ));
-
-        // Create the OAuth client
-        let client = BasicClient::new(ClientId::new(provider_config.client_id.clone()))
-            .set_client_secret(ClientSecret::new(provider_config.client_secret.clone()))
-            .set_auth_uri(auth_url)
-            .set_token_uri(token_url)
-            .set_redirect_uri(redirect_url);
-
-        // Get the OAuth provider factory
-        if let Some(factory) = OAUTH_PROVIDER_REGISTRY.get(provider_name.as_str()) {
-            // Create the OAuth provider instance
-            let provider = factory.create(client, user_info_url);
-            oauth_providers.insert(provider_name.clone(), provider);
-        } else {
-            warn!(
-                "OAuth provider {} not configured in the factory",
-                provider_name
+/// * `config` - Path of the configuration file
+/// * `app_state` - Shared application state holding the token issuer
+#[cfg(unix)]
+async fn reload_jwt_keys_on_hangup(config: String, app_state: Arc<AppState>) {
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(e) => {
+            error!(
+                "Failed to listen for SIGHUP, JWT keys cannot be reloaded: {}",
+                e
             );
+            return;
+        }
+    };
+    while hangup.recv().await.is_some() {
+        let reloaded = Settings::from_toml(&config).and_then(|settings| {
+            let (Some(token_issuer), Some(jwt)) = (&app_state.token_issuer, &settings.jwt) else {
+                eyre::bail!("Turning JWT issuance on or off requires a restart");
+            };
+            token_issuer.reload(jwt)?;
+            redact::configure(&settings.redaction, &settings.secret_values());
+            Ok(())
+        });
+        match reloaded {
+            Ok(()) => info!("Reloaded the JWT keys from {}", config),
+            Err(e) => error!(
+                "Failed to reload the JWT keys, keeping the current ones: {}",
+                e
+            ),
         }
     }
-
-    Ok(oauth_providers)
 }
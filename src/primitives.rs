@@ -1,4 +1,17 @@
+use crate::{
+    crypto::SecretCipher,
+    redact::Redact,
+    settings::OAuthSettings,
+    types::{HttpClient, OAuthClient, OAuthTokenResponse},
+};
+use oauth2::{DeviceAuthorizationResponse, EmptyExtraDeviceAuthorizationFields, TokenResponse};
+use reqwest::Url;
 use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt,
+};
+use uuid::Uuid;
 
 /// OAuth session state for tracking OAuth flow
 ///
@@ -9,16 +22,49 @@ use serde::{Deserialize, Serialize};
 /// # Fields
 ///
 /// * `provider` - The name of the OAuth provider (e.g., "google", "github")
-/// * `pkce_verifier` - The PKCE code verifier used for enhanced security
+/// * `pkce_verifier` - The PKCE code verifier used for enhanced security, `None` without PKCE
 /// * `csrf_token` - The CSRF token for protecting against CSRF attacks
-#[derive(Debug, Serialize, Deserialize)]
+/// * `client_fingerprint` - Hash of the client attributes the flow is bound to
+/// * `created_at` - Unix timestamp at which the flow was started
+/// * `return_to` - Validated frontend URL receiving the user after sign-in
+/// * `nonce` - OpenID Connect nonce the ID token must carry, if one was sent
+/// * `mode` - Whether the flow signs the user in or links a provider
+/// * `flow_id` - Id of the flow, prefixing the `state` parameter
+/// * `redirect_uri` - Redirect URI the provider was asked to send the user back to
+/// * `provider_params` - Parameters the provider was configured with for the flow
+#[derive(Serialize, Deserialize)]
 pub struct OAuthSessionState {
     /// OAuth provider name (google, github, etc.)
     pub provider: String,
-    /// PKCE code verifier for security
-    pub pkce_verifier: String,
+    /// PKCE code verifier for security, `None` when PKCE is disabled
+    #[serde(default)]
+    pub pkce_verifier: Option<String>,
     /// CSRF state token for security
     pub csrf_token: String,
+    /// Hash of the client IP and/or user agent when flow binding is enabled
+    #[serde(default)]
+    pub client_fingerprint: Option<String>,
+    /// Unix timestamp at which the flow was started, flows stored without one are expired
+    #[serde(default)]
+    pub created_at: u64,
+    /// Validated frontend URL receiving the user after sign-in, if requested
+    #[serde(default)]
+    pub return_to: Option<String>,
+    /// OpenID Connect nonce sent on the authorization URL, if any
+    #[serde(default)]
+    pub nonce: Option<String>,
+    /// Whether the flow signs the user in or links a provider
+    #[serde(default)]
+    pub mode: FlowMode,
+    /// Id of the flow, nil for flows stored without one
+    #[serde(default)]
+    pub flow_id: Uuid,
+    /// Redirect URI sent on the authorization URL, if recorded
+    #[serde(default)]
+    pub redirect_uri: Option<String>,
+    /// Parameters the provider was configured with, e.g. the Shopify shop
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub provider_params: BTreeMap<String, String>,
 }
 
 impl OAuthSessionState {
@@ -30,18 +76,197 @@ impl OAuthSessionState {
     /// # Arguments
     ///
     /// * `provider` - The name of the OAuth provider
-    /// * `pkce_verifier` - The PKCE code verifier string
+    /// * `pkce_verifier` - The PKCE code verifier string, `None` without PKCE
     /// * `csrf_token` - The CSRF token string
+    /// * `client_fingerprint` - Hash of the bound client attributes, if any
+    /// * `created_at` - Unix timestamp at which the flow was started
+    /// * `return_to` - Validated frontend URL receiving the user after sign-in
     ///
     /// # Returns
     ///
     /// Returns a new `OAuthSessionState` instance
-    pub fn new(provider: String, pkce_verifier: String, csrf_token: String) -> Self {
+    pub fn new(
+        provider: String,
+        pkce_verifier: Option<String>,
+        csrf_token: String,
+        client_fingerprint: Option<String>,
+        created_at: u64,
+        return_to: Option<String>,
+    ) -> Self {
         Self {
             provider,
             pkce_verifier,
             csrf_token,
+            client_fingerprint,
+            created_at,
+            return_to,
+            nonce: None,
+            mode: FlowMode::Login,
+            flow_id: Uuid::nil(),
+            redirect_uri: None,
+            provider_params: BTreeMap::new(),
+        }
+    }
+
+    /// Sets the OpenID Connect nonce the ID token must carry
+    ///
+    /// # Arguments
+    ///
+    /// * `nonce` - The nonce sent on the authorization URL, if any
+    ///
+    /// # Returns
+    ///
+    /// Returns the session state with the nonce
+    pub fn with_nonce(mut self, nonce: Option<String>) -> Self {
+        self.nonce = nonce;
+        self
+    }
+
+    /// Sets whether the flow signs the user in or links a provider
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - The purpose of the flow
+    ///
+    /// # Returns
+    ///
+    /// Returns the session state with the mode
+    pub fn with_mode(mut self, mode: FlowMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Sets the id of the flow and the redirect URI it was started with
+    ///
+    /// # Arguments
+    ///
+    /// * `flow_id` - The id prefixing the `state` parameter
+    /// * `redirect_uri` - The redirect URI sent on the authorization URL, if any
+    ///
+    /// # Returns
+    ///
+    /// Returns the session state with the flow id and redirect URI
+    pub fn with_flow(mut self, flow_id: Uuid, redirect_uri: Option<String>) -> Self {
+        self.flow_id = flow_id;
+        self.redirect_uri = redirect_uri;
+        self
+    }
+
+    /// Sets the parameters the provider was configured with for the flow
+    ///
+    /// # Arguments
+    ///
+    /// * `provider_params` - The provider's flow parameters by name
+    ///
+    /// # Returns
+    ///
+    /// Returns the session state with the provider parameters
+    pub fn with_provider_params(mut self, provider_params: BTreeMap<String, String>) -> Self {
+        self.provider_params = provider_params;
+        self
+    }
+
+    /// Seals the state before it is written to the session store
+    ///
+    /// With encryption keys configured, the whole state is serialized and
+    /// encrypted under the active key, so the session store never sees the
+    /// PKCE verifier, the CSRF token or the rest of the flow. Without keys
+    /// the state is stored as-is.
+    ///
+    /// # Arguments
+    ///
+    /// * `cipher` - The cipher sealing the state
+    ///
+    /// # Returns
+    ///
+    /// Returns the record to store, or an error if the state cannot be
+    /// serialized or encrypted
+    pub fn seal(&self, cipher: &SecretCipher) -> eyre::Result<serde_json::Value> {
+        if !cipher.is_enabled() {
+            return Ok(serde_json::to_value(self)?);
         }
+        let sealed = cipher.encrypt(&serde_json::to_string(self)?)?;
+        Ok(serde_json::Value::String(sealed))
+    }
+
+    /// Opens a record written by [`OAuthSessionState::seal`]
+    ///
+    /// Records stored as-is are only accepted while plaintext records are
+    /// allowed, and their PKCE verifier, encrypted on its own before whole
+    /// states were sealed, is decrypted.
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - The record read from the session store
+    /// * `cipher` - The cipher the record was sealed with
+    ///
+    /// # Returns
+    ///
+    /// Returns the state, or an error if the record was tampered with,
+    /// sealed under an unknown key, or is plaintext while plaintext records
+    /// are not allowed
+    pub fn open(record: serde_json::Value, cipher: &SecretCipher) -> eyre::Result<Self> {
+        if let serde_json::Value::String(sealed) = record {
+            return Ok(serde_json::from_str(&cipher.decrypt(&sealed)?)?);
+        }
+        if !cipher.allows_plaintext() {
+            eyre::bail!("Refusing to read unsealed OAuth session state");
+        }
+
+        let mut state: Self = serde_json::from_value(record)?;
+        state.pkce_verifier = state
+            .pkce_verifier
+            .map(|pkce_verifier| cipher.decrypt(&pkce_verifier))
+            .transpose()?;
+        Ok(state)
+    }
+}
+
+impl fmt::Debug for OAuthSessionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OAuthSessionState")
+            .field("provider", &self.provider)
+            .field("pkce_verifier", &Redact(&self.pkce_verifier))
+            .field("csrf_token", &Redact(&self.csrf_token))
+            .field("client_fingerprint", &self.client_fingerprint)
+            .field("created_at", &self.created_at)
+            .field("return_to", &self.return_to)
+            .field("nonce", &Redact(&self.nonce))
+            .field("mode", &self.mode)
+            .field("flow_id", &self.flow_id)
+            .field("redirect_uri", &self.redirect_uri)
+            .field("provider_params", &self.provider_params)
+            .finish()
+    }
+}
+
+/// Purpose of an OAuth flow
+///
+/// Flow states that do not record a mode sign the user in.
+///
+/// * `Login` - Signs the user in
+/// * `Link` - Links the provider account to the signed-in user
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlowMode {
+    /// Signs the user in
+    #[default]
+    Login,
+    /// Links the provider account to the signed-in user
+    Link {
+        /// Internal id of the signed-in user
+        user_id: Uuid,
+    },
+}
+
+impl FlowMode {
+    /// Returns whether the flow signs the user in
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` for `FlowMode::Login`
+    pub fn is_login(&self) -> bool {
+        *self == FlowMode::Login
     }
 }
 
@@ -50,15 +275,482 @@ impl OAuthSessionState {
 /// This structure contains the basic user information that is returned
 /// from OAuth providers after successful authentication. The structure
 /// is designed to be generic enough to work with multiple providers.
+/// Profile fields a provider does not return are `None` and omitted when
+/// serialized.
 ///
 /// # Fields
 ///
 /// * `id` - The user's unique identifier (usually email or user ID)
 /// * `provider` - The name of the OAuth provider that provided this information
-#[derive(Debug, Serialize, Deserialize)]
+/// * `email` - The user's email address, if shared
+/// * `name` - The user's display name, if any
+/// * `username` - The user's handle, which unlike `id` may change, if any
+/// * `avatar_url` - URL of the user's avatar, if any
+/// * `email_verified` - Whether the provider verified the email, if it says
+/// * `tenant` - Organization the user belongs to, for providers hosting several
+/// * `raw` - The untouched user info response of the provider
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct UserInfo {
     /// User's unique identifier (email, user ID, etc.)
     pub id: String,
     /// OAuth provider name
     pub provider: String,
+    /// User's email address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// User's display name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// User's handle
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// URL of the user's avatar
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub avatar_url: Option<String>,
+    /// Whether the provider verified the email address
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email_verified: Option<bool>,
+    /// Organization the user belongs to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// Untouched user info response of the provider
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw: Option<serde_json::Value>,
+}
+
+/// User signed in through the session
+///
+/// Stored in the session once the callback completes, so later requests
+/// carrying the session cookie are authenticated.
+///
+/// # Fields
+///
+/// * `user` - The user information returned by the provider
+/// * `provider` - The configured name of the provider the user signed in with
+/// * `logged_in_at` - Unix timestamp at which the user signed in
+/// * `internal_user_id` - Stable id assigned to the user by this server
+/// * `session_id` - Id assigned to the session at sign-in, under which it can be revoked
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CurrentUser {
+    /// User information returned by the provider
+    pub user: UserInfo,
+    /// Configured provider name
+    pub provider: String,
+    /// Unix timestamp of the sign-in
+    pub logged_in_at: u64,
+    /// Stable id assigned to the user, unset for sessions signed in before ids were assigned
+    #[serde(default)]
+    pub internal_user_id: Option<Uuid>,
+    /// Id assigned to the session at sign-in, unset for sessions signed in before ids were assigned
+    #[serde(default)]
+    pub session_id: Option<Uuid>,
+}
+
+/// Endpoints of a provider derived from its base URL
+///
+/// Providers that can be self-hosted derive their endpoints from a single
+/// `base_url` setting, so they do not have to be configured one by one.
+///
+/// # Fields
+///
+/// * `auth_url` - OAuth authorization endpoint URL
+/// * `token_url` - OAuth token exchange endpoint URL
+/// * `user_info_url` - User info endpoint URL
+/// * `jwks_url` - Key set the ID tokens are signed with, if published
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProviderEndpoints {
+    /// OAuth authorization endpoint URL
+    pub auth_url: String,
+    /// OAuth token exchange endpoint URL
+    pub token_url: String,
+    /// User info endpoint URL
+    pub user_info_url: String,
+    /// Key set the ID tokens are signed with
+    pub jwks_url: Option<String>,
+}
+
+/// Branding of a provider's sign-in button on the home page
+///
+/// # Fields
+///
+/// * `label` - Name of the provider shown on the button
+/// * `color` - CSS background color of the button
+/// * `icon` - SVG `path` elements of the provider's logo, drawn in a 24x24 view box
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProviderDisplay {
+    /// Name of the provider shown on the button
+    pub label: &'static str,
+    /// CSS background color of the button
+    pub color: &'static str,
+    /// SVG `path` elements of the provider's logo
+    pub icon: &'static str,
+}
+
+/// Everything a provider factory builds a provider from
+///
+/// The OAuth client and user info URL are resolved from the settings,
+/// discovery or the factory's default endpoints beforehand, the settings
+/// give access to every other option of the provider's section.
+///
+/// # Fields
+///
+/// * `name` - Name of the provider's section in the configuration
+/// * `http_client` - The HTTP client shared by every provider
+/// * `oauth_client` - The configured OAuth client for the provider
+/// * `user_info_url` - The URL of the provider's user info endpoint
+/// * `settings` - The provider's settings
+pub struct ProviderContext<'a> {
+    /// Name of the provider's section in the configuration
+    pub name: &'a str,
+    /// The HTTP client shared by every provider
+    pub http_client: &'a HttpClient,
+    /// The configured OAuth client for the provider
+    pub oauth_client: OAuthClient,
+    /// The URL of the provider's user info endpoint
+    pub user_info_url: Url,
+    /// The provider's settings
+    pub settings: &'a OAuthSettings,
+}
+
+/// Tokens issued by an OAuth provider
+///
+/// Providers that do not issue a refresh token or an expiry leave the
+/// corresponding field `None`, which serializes as `null`.
+///
+/// # Fields
+///
+/// * `access_token` - The access token
+/// * `refresh_token` - The refresh token, if the provider issued one
+/// * `expires_at` - Unix timestamp at which the access token expires, if known
+/// * `scopes` - Scopes granted by the provider
+/// * `id_token` - The OpenID Connect ID token, if the provider issued one
+/// * `user_data` - What the provider's token response says about the user, if anything
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TokenDetails {
+    /// The access token
+    pub access_token: String,
+    /// The refresh token, if the provider issued one
+    pub refresh_token: Option<String>,
+    /// Unix timestamp at which the access token expires, if known
+    pub expires_at: Option<u64>,
+    /// Scopes granted by the provider
+    pub scopes: Vec<String>,
+    /// The OpenID Connect ID token, if the provider issued one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+    /// What the provider's token response says about the user, such as the
+    /// user itself or where to fetch them, read by `get_user_info_from_tokens`
+    /// and never serialized
+    #[serde(skip)]
+    pub user_data: Option<serde_json::Value>,
+}
+
+impl TokenDetails {
+    /// Extracts the token details from a token response
+    ///
+    /// Providers omit the granted scopes when they match the requested
+    /// ones, in which case the requested scopes are reported. The ID token
+    /// is kept when the provider issued one.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The token response of the provider
+    /// * `requested_scopes` - The scopes requested from the provider
+    /// * `now` - The current unix timestamp
+    ///
+    /// # Returns
+    ///
+    /// Returns the token details
+    pub fn from_response(
+        token: &OAuthTokenResponse,
+        requested_scopes: Vec<String>,
+        now: u64,
+    ) -> Self {
+        Self {
+            access_token: token.access_token().secret().to_string(),
+            refresh_token: token
+                .refresh_token()
+                .map(|refresh_token| refresh_token.secret().to_string()),
+            expires_at: token
+                .expires_in()
+                .map(|expires_in| now.saturating_add(expires_in.as_secs())),
+            scopes: token
+                .scopes()
+                .map(|scopes| scopes.iter().map(|scope| scope.to_string()).collect())
+                .unwrap_or(requested_scopes),
+            id_token: token.extra_fields().id_token.clone(),
+            user_data: None,
+        }
+    }
+
+    /// Returns the requested scopes the provider did not grant
+    ///
+    /// Granted scopes separated by commas, as GitHub reports them, are
+    /// split like space-separated ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `requested_scopes` - The scopes requested from the provider
+    ///
+    /// # Returns
+    ///
+    /// Returns the missing scopes in the order they were requested, empty
+    /// when every scope was granted
+    pub fn missing_scopes(&self, requested_scopes: &[String]) -> Vec<String> {
+        let granted: HashSet<&str> = self
+            .scopes
+            .iter()
+            .flat_map(|scopes| scopes.split([',', ' ']))
+            .filter(|scope| !scope.is_empty())
+            .collect();
+        requested_scopes
+            .iter()
+            .filter(|scope| !granted.contains(scope.as_str()))
+            .cloned()
+            .collect()
+    }
+}
+
+impl fmt::Debug for TokenDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TokenDetails")
+            .field("access_token", &Redact(&self.access_token))
+            .field("refresh_token", &self.refresh_token.as_ref().map(Redact))
+            .field("expires_at", &self.expires_at)
+            .field("scopes", &self.scopes)
+            .field("id_token", &self.id_token.as_ref().map(Redact))
+            .field("user_data", &self.user_data.as_ref().map(Redact))
+            .finish()
+    }
+}
+
+/// Codes issued by a provider's device authorization endpoint (RFC 8628)
+///
+/// The user enters `user_code` at `verification_uri` on another device,
+/// while the client polls with `device_code` every `interval` seconds.
+///
+/// # Fields
+///
+/// * `device_code` - Code the client polls the token endpoint with
+/// * `user_code` - Code the user enters at the verification URI
+/// * `verification_uri` - Page where the user enters the code
+/// * `verification_uri_complete` - Verification URI carrying the user code, if provided
+/// * `expires_in` - Seconds until both codes expire
+/// * `interval` - Seconds the client waits between polls
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DeviceAuthorization {
+    /// Code the client polls the token endpoint with
+    pub device_code: String,
+    /// Code the user enters at the verification URI
+    pub user_code: String,
+    /// Page where the user enters the code
+    pub verification_uri: String,
+    /// Verification URI carrying the user code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub verification_uri_complete: Option<String>,
+    /// Seconds until both codes expire
+    pub expires_in: u64,
+    /// Seconds the client waits between polls
+    pub interval: u64,
+}
+
+impl DeviceAuthorization {
+    /// Extracts the codes from a device authorization response
+    ///
+    /// # Arguments
+    ///
+    /// * `response` - The device authorization response of the provider
+    ///
+    /// # Returns
+    ///
+    /// Returns the device authorization
+    pub fn from_response(
+        response: &DeviceAuthorizationResponse<EmptyExtraDeviceAuthorizationFields>,
+    ) -> Self {
+        Self {
+            device_code: response.device_code().secret().to_string(),
+            user_code: response.user_code().secret().to_string(),
+            verification_uri: response.verification_uri().to_string(),
+            verification_uri_complete: response
+                .verification_uri_complete()
+                .map(|uri| uri.secret().to_string()),
+            expires_in: response.expires_in().as_secs(),
+            interval: response.interval().as_secs(),
+        }
+    }
+}
+
+impl fmt::Debug for DeviceAuthorization {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DeviceAuthorization")
+            .field("device_code", &Redact(&self.device_code))
+            .field("user_code", &self.user_code)
+            .field("verification_uri", &self.verification_uri)
+            .field("verification_uri_complete", &self.verification_uri_complete)
+            .field("expires_in", &self.expires_in)
+            .field("interval", &self.interval)
+            .finish()
+    }
+}
+
+/// Outcome of polling the token endpoint with a device code
+///
+/// * `Pending` - The user has not approved the device yet, poll again after `interval` seconds
+/// * `SlowDown` - The client polls too often, poll again after the longer `interval`
+/// * `Complete` - The user approved the device and the tokens were issued
+#[derive(Debug)]
+pub enum DevicePoll {
+    /// The user has not approved the device yet
+    Pending {
+        /// Seconds to wait before the next poll
+        interval: u64,
+    },
+    /// The client polls too often
+    SlowDown {
+        /// Seconds to wait before the next poll
+        interval: u64,
+    },
+    /// The user approved the device
+    Complete(TokenDetails),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a session state carrying a verifier and CSRF token
+    fn session_state() -> OAuthSessionState {
+        OAuthSessionState::new(
+            "google".to_string(),
+            Some("pkce-verifier".to_string()),
+            "csrf-token".to_string(),
+            None,
+            1_700_000_000,
+            None,
+        )
+    }
+
+    /// Tests that a sealed session state opens back to the original, and
+    /// that the record reveals none of it
+    #[test]
+    fn test_session_state_round_trip() {
+        let cipher = SecretCipher::new(&[[1; 32]], false).unwrap();
+        let record = session_state().seal(&cipher).unwrap();
+
+        let sealed = record.as_str().unwrap();
+        assert!(!sealed.contains("pkce-verifier"));
+        assert!(!sealed.contains("csrf-token"));
+        assert!(!sealed.contains("google"));
+
+        let opened = OAuthSessionState::open(record, &cipher).unwrap();
+        assert_eq!(opened.provider, "google");
+        assert_eq!(opened.pkce_verifier.as_deref(), Some("pkce-verifier"));
+        assert_eq!(opened.csrf_token, "csrf-token");
+
+        // Without keys, the state is stored and read as-is
+        let disabled = SecretCipher::disabled();
+        let record = session_state().seal(&disabled).unwrap();
+        assert_eq!(record["pkce_verifier"], "pkce-verifier");
+        let opened = OAuthSessionState::open(record, &disabled).unwrap();
+        assert_eq!(opened.pkce_verifier.as_deref(), Some("pkce-verifier"));
+    }
+
+    /// Tests that tampered, foreign and unsealed session states are refused
+    #[test]
+    fn test_session_state_tampering() {
+        let cipher = SecretCipher::new(&[[1; 32]], false).unwrap();
+        let record = session_state().seal(&cipher).unwrap();
+
+        // Flip a character in the middle of the ciphertext
+        let mut tampered = record.as_str().unwrap().to_string();
+        let middle = tampered.len() / 2;
+        let flipped = if &tampered[middle..=middle] == "A" {
+            "B"
+        } else {
+            "A"
+        };
+        tampered.replace_range(middle..=middle, flipped);
+        assert!(OAuthSessionState::open(tampered.into(), &cipher).is_err());
+
+        let other = SecretCipher::new(&[[2; 32]], false).unwrap();
+        assert!(OAuthSessionState::open(record.clone(), &other).is_err());
+
+        // Rotated keys still open states sealed under the previous key
+        let rotated = SecretCipher::new(&[[2; 32], [1; 32]], false).unwrap();
+        assert!(OAuthSessionState::open(record, &rotated).is_ok());
+
+        let unsealed = serde_json::to_value(session_state()).unwrap();
+        assert!(OAuthSessionState::open(unsealed.clone(), &cipher).is_err());
+        let migrating = SecretCipher::new(&[[1; 32]], true).unwrap();
+        assert!(OAuthSessionState::open(unsealed, &migrating).is_ok());
+    }
+
+    /// Tests that token details are extracted from a full token response
+    #[test]
+    fn test_token_details_from_response() {
+        let token: OAuthTokenResponse = serde_json::from_value(serde_json::json!({
+            "access_token": "access",
+            "token_type": "bearer",
+            "refresh_token": "refresh",
+            "expires_in": 3600,
+            "scope": "email profile",
+            "id_token": "header.claims.signature",
+        }))
+        .unwrap();
+
+        let tokens = TokenDetails::from_response(&token, vec!["email".to_string()], 1_000);
+        assert_eq!(tokens.access_token, "access");
+        assert_eq!(tokens.refresh_token.as_deref(), Some("refresh"));
+        assert_eq!(tokens.expires_at, Some(4_600));
+        assert_eq!(tokens.scopes, ["email", "profile"]);
+        assert_eq!(tokens.id_token.as_deref(), Some("header.claims.signature"));
+        assert!(!format!("{:?}", tokens).contains("\"refresh\""));
+    }
+
+    /// Tests that scopes missing from the granted ones are reported
+    #[test]
+    fn test_missing_scopes() {
+        let tokens = |scope: &str| {
+            let token: OAuthTokenResponse = serde_json::from_value(serde_json::json!({
+                "access_token": "access",
+                "token_type": "bearer",
+                "scope": scope,
+            }))
+            .unwrap();
+            TokenDetails::from_response(&token, Vec::new(), 0)
+        };
+        let requested = ["read:user".to_string(), "user:email".to_string()];
+
+        assert!(tokens("read:user user:email")
+            .missing_scopes(&requested)
+            .is_empty());
+        assert!(tokens("user:email,read:user")
+            .missing_scopes(&requested)
+            .is_empty());
+        assert_eq!(
+            tokens("read:user").missing_scopes(&requested),
+            ["user:email"]
+        );
+    }
+
+    /// Tests that missing refresh tokens and expiries serialize as null
+    #[test]
+    fn test_token_details_without_refresh_token() {
+        let token: OAuthTokenResponse = serde_json::from_value(serde_json::json!({
+            "access_token": "access",
+            "token_type": "bearer",
+        }))
+        .unwrap();
+
+        let tokens = TokenDetails::from_response(&token, vec!["email".to_string()], 1_000);
+        assert_eq!(
+            serde_json::to_value(&tokens).unwrap(),
+            serde_json::json!({
+                "access_token": "access",
+                "refresh_token": null,
+                "expires_at": null,
+                "scopes": ["email"],
+            })
+        );
+    }
 }
@@ -0,0 +1,359 @@
+use crate::{
+    primitives::{ProviderContext, UserInfo},
+    providers::{flag, merge_scopes, non_empty, parse_response},
+    settings::TokenAuthType,
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
+};
+use axum::async_trait;
+use eyre::{bail, Result};
+use reqwest::Url;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Atlassian `GET /me` response
+///
+/// # Fields
+///
+/// * `account_id` - Id of the Atlassian account, shared by every Atlassian product
+/// * `email` - The account's email, hidden by some accounts' profile visibility
+/// * `email_verified` - Whether Atlassian verified the email
+/// * `name` - The account's public name
+/// * `nickname` - The account's nickname
+/// * `picture` - URL of the account's avatar
+#[derive(Debug, Deserialize)]
+struct AtlassianUserResponse {
+    /// Id of the Atlassian account
+    account_id: String,
+    /// The account's email
+    #[serde(default, deserialize_with = "non_empty")]
+    email: Option<String>,
+    /// Whether Atlassian verified the email
+    #[serde(default, deserialize_with = "flag")]
+    email_verified: Option<bool>,
+    /// The account's public name
+    #[serde(default, deserialize_with = "non_empty")]
+    name: Option<String>,
+    /// The account's nickname
+    #[serde(default, deserialize_with = "non_empty")]
+    nickname: Option<String>,
+    /// URL of the account's avatar
+    #[serde(default, deserialize_with = "non_empty")]
+    picture: Option<String>,
+}
+
+impl From<AtlassianUserResponse> for UserInfo {
+    /// Maps an Atlassian account to the user information
+    fn from(user: AtlassianUserResponse) -> Self {
+        UserInfo {
+            id: user.account_id,
+            provider: "atlassian".to_string(),
+            email: user.email,
+            name: user.name,
+            username: user.nickname,
+            avatar_url: user.picture,
+            email_verified: user.email_verified,
+            tenant: None,
+            raw: None,
+        }
+    }
+}
+
+/// Atlassian OAuth provider implementation
+///
+/// This struct implements the OAuth provider interface for Atlassian
+/// cloud products such as Jira and Confluence. It handles OAuth 2.0
+/// authentication flow and user information retrieval from the Atlassian
+/// platform API.
+///
+/// # Fields
+///
+/// * `client` - HTTP client for making API requests
+/// * `oauth_client` - Configured OAuth 2.0 client
+/// * `user_info_url` - Atlassian's user info endpoint URL
+/// * `scopes` - OAuth scopes requested on authorization
+pub struct AtlassianProvider {
+    /// HTTP client for API requests
+    client: HttpClient,
+    /// Configured OAuth client
+    oauth_client: OAuthClient,
+    /// Atlassian user info endpoint URL
+    user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
+}
+
+impl AtlassianProvider {
+    /// Creates a new Atlassian OAuth provider instance
+    ///
+    /// This constructor creates a new Atlassian provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_client` - The HTTP client shared by every provider
+    /// * `oauth_client` - The configured OAuth client for Atlassian
+    /// * `user_info_url` - The URL for Atlassian's user info endpoint
+    /// * `scopes` - Scopes configured in addition to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `AtlassianProvider` instance
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        scopes: Option<Vec<String>>,
+    ) -> Self {
+        Self {
+            client: http_client,
+            oauth_client,
+            user_info_url,
+            scopes: merge_scopes(&["read:me", "offline_access"], scopes),
+        }
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for AtlassianProvider {
+    /// Returns a reference to the OAuth client for Atlassian
+    ///
+    /// # Returns
+    ///
+    /// Returns a reference to the configured `OAuthClient` instance
+    fn get_oauth_client(&self) -> &OAuthClient {
+        &self.oauth_client
+    }
+
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Returns the OAuth scopes required for Atlassian
+    ///
+    /// Atlassian requires the "read:me" scope to access the signed-in
+    /// account, and only issues a refresh token with "offline_access".
+    ///
+    /// # Returns
+    ///
+    /// Returns the required scopes followed by the configured ones
+    fn get_scopes(&self) -> Vec<String> {
+        self.scopes.clone()
+    }
+
+    /// Returns the extra authorization parameters for Atlassian
+    ///
+    /// Atlassian requires the API the token is issued for as `audience`,
+    /// and `prompt=consent` for its consent screen.
+    ///
+    /// # Returns
+    ///
+    /// Returns the `audience=api.atlassian.com` and `prompt=consent` parameters
+    fn get_extra_auth_params(&self) -> Vec<(String, String)> {
+        vec![
+            ("audience".to_string(), "api.atlassian.com".to_string()),
+            ("prompt".to_string(), "consent".to_string()),
+        ]
+    }
+
+    /// Fetches user information from Atlassian's `/me` endpoint
+    ///
+    /// # Arguments
+    ///
+    /// * `access_token` - The OAuth access token obtained from Atlassian
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the account's identifier
+    /// or an error if the request fails
+    async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
+        let request = self
+            .client
+            .get(self.user_info_url.as_str())
+            .header("Authorization", format!("Bearer {}", access_token));
+
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|e| request_error("Failed to get user info from Atlassian", e))?;
+
+        if !response.status().is_success() {
+            bail!(
+                "Failed to get user info from Atlassian: {}",
+                response.status()
+            );
+        }
+
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from Atlassian", e))?;
+        parse_user_info(&user_data)
+    }
+}
+
+/// Extracts the user information from an Atlassian `/me` response
+///
+/// Accounts hiding their email are identified by their account id alone.
+///
+/// # Arguments
+///
+/// * `user_data` - The JSON body returned by the Atlassian API
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` containing the user's id, or an error if
+/// the id is missing or a field has an unexpected type
+fn parse_user_info(user_data: &serde_json::Value) -> Result<UserInfo> {
+    let user: AtlassianUserResponse = parse_response("Atlassian user info", user_data)?;
+    Ok(UserInfo {
+        raw: Some(user_data.clone()),
+        ..user.into()
+    })
+}
+
+/// Factory for creating Atlassian OAuth provider instances
+///
+/// This struct implements the factory pattern for creating Atlassian
+/// OAuth provider instances. It allows the application to create
+/// provider instances dynamically based on configuration.
+pub struct AtlassianProviderFactory;
+
+impl OAuthProviderFactory for AtlassianProviderFactory {
+    /// Creates a new Atlassian OAuth provider instance
+    ///
+    /// This method creates a new Atlassian provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, whose `scopes` are
+    ///   added to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created Atlassian provider
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        Ok(Arc::new(AtlassianProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.scopes.clone(),
+        )))
+    }
+
+    /// Returns how Atlassian expects the client credentials
+    ///
+    /// Atlassian expects the client credentials in the request body.
+    ///
+    /// # Returns
+    ///
+    /// Returns `TokenAuthType::RequestBody`
+    fn default_auth_type(&self) -> TokenAuthType {
+        TokenAuthType::RequestBody
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockApi;
+    use axum::http::StatusCode;
+
+    /// Tests that the account is extracted from a recorded `/me` response
+    #[test]
+    fn test_parse_user_response() {
+        let user_data = serde_json::json!({
+            "account_type": "atlassian",
+            "account_id": "112233aa-bb11-cc22-33dd-444444abcd",
+            "email": "mia@example.com",
+            "email_verified": true,
+            "name": "Mia Krystof",
+            "picture": "https://avatar-management--avatars.us-west-2.prod.public.atl-paas.net/112233aa-bb11-cc22-33dd-444444abcd/1234abcd-9876-54aa-33aa-1234dfsade9487ds",
+            "account_status": "active",
+            "nickname": "mkrystof",
+            "zoneinfo": "Australia/Sydney",
+            "locale": "en-US",
+            "extended_profile": {
+                "job_title": "Designer",
+                "organization": "mia@example.com",
+                "department": "Design team",
+                "location": "Sydney"
+            }
+        });
+
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "112233aa-bb11-cc22-33dd-444444abcd");
+        assert_eq!(user_info.provider, "atlassian");
+        assert_eq!(user_info.email.as_deref(), Some("mia@example.com"));
+        assert_eq!(user_info.email_verified, Some(true));
+        assert_eq!(user_info.name.as_deref(), Some("Mia Krystof"));
+        assert_eq!(user_info.username.as_deref(), Some("mkrystof"));
+        assert_eq!(user_info.raw, Some(user_data));
+
+        let error = parse_user_info(&serde_json::json!({ "account_id": 42 }))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.starts_with("Invalid Atlassian user info response at account_id:"),
+            "{}",
+            error
+        );
+    }
+
+    /// Tests that accounts hiding their email sign in with their account id
+    #[tokio::test]
+    async fn test_hidden_email() {
+        let api = MockApi::builder()
+            .get("/me", |_| {
+                (
+                    StatusCode::OK,
+                    serde_json::json!({
+                        "account_type": "atlassian",
+                        "account_id": "557058:f58131cb-b67d-43c7-b30d-6b58d40bd077",
+                        "name": "Hidden Email",
+                        "account_status": "active",
+                        "nickname": "hidden"
+                    }),
+                )
+            })
+            .start()
+            .await
+            .unwrap();
+
+        let provider = api
+            .provider(
+                "atlassian",
+                api.settings("/authorize", "/oauth/token", "/me"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(provider.get_scopes(), vec!["read:me", "offline_access"]);
+        assert_eq!(
+            provider.get_extra_auth_params(),
+            vec![
+                ("audience".to_string(), "api.atlassian.com".to_string()),
+                ("prompt".to_string(), "consent".to_string()),
+            ]
+        );
+
+        let user_info = provider.get_user_info("atlassian-token").await.unwrap();
+        assert_eq!(user_info.id, "557058:f58131cb-b67d-43c7-b30d-6b58d40bd077");
+        assert_eq!(user_info.email, None);
+        assert_eq!(user_info.email_verified, None);
+        assert_eq!(user_info.name.as_deref(), Some("Hidden Email"));
+    }
+}
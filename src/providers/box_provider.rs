@@ -0,0 +1,290 @@
+use crate::{
+    primitives::{ProviderContext, UserInfo},
+    providers::{merge_scopes, non_empty, parse_response},
+    settings::TokenAuthType,
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
+};
+use axum::async_trait;
+use eyre::{bail, Result};
+use reqwest::Url;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Box `GET /2.0/users/me` response
+///
+/// # Fields
+///
+/// * `id` - Numeric id of the user, as a string
+/// * `login` - The user's login, their primary email
+/// * `name` - The user's name
+/// * `avatar_url` - URL of the user's avatar
+#[derive(Debug, Deserialize)]
+struct BoxUserResponse {
+    /// Numeric id of the user
+    id: String,
+    /// The user's login
+    #[serde(default, deserialize_with = "non_empty")]
+    login: Option<String>,
+    /// The user's name
+    #[serde(default, deserialize_with = "non_empty")]
+    name: Option<String>,
+    /// URL of the user's avatar
+    #[serde(default, deserialize_with = "non_empty")]
+    avatar_url: Option<String>,
+}
+
+impl From<BoxUserResponse> for UserInfo {
+    /// Maps a Box user to the user information
+    ///
+    /// Box logins are the user's primary email.
+    fn from(user: BoxUserResponse) -> Self {
+        UserInfo {
+            id: user.id,
+            provider: "box".to_string(),
+            email: user.login,
+            name: user.name,
+            username: None,
+            avatar_url: user.avatar_url,
+            email_verified: None,
+            tenant: None,
+            raw: None,
+        }
+    }
+}
+
+/// Box OAuth provider implementation
+///
+/// This struct implements the OAuth provider interface for Box. It
+/// handles OAuth 2.0 authentication flow and user information retrieval
+/// from the Box API.
+///
+/// # Fields
+///
+/// * `client` - HTTP client for making API requests
+/// * `oauth_client` - Configured OAuth 2.0 client
+/// * `user_info_url` - Box's user info endpoint URL
+/// * `scopes` - OAuth scopes requested on authorization
+pub struct BoxProvider {
+    /// HTTP client for API requests
+    client: HttpClient,
+    /// Configured OAuth client
+    oauth_client: OAuthClient,
+    /// Box user info endpoint URL
+    user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
+}
+
+impl BoxProvider {
+    /// Creates a new Box OAuth provider instance
+    ///
+    /// This constructor creates a new Box provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_client` - The HTTP client shared by every provider
+    /// * `oauth_client` - The configured OAuth client for Box
+    /// * `user_info_url` - The URL for Box's user info endpoint
+    /// * `scopes` - Scopes configured for the authorization request
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `BoxProvider` instance
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        scopes: Option<Vec<String>>,
+    ) -> Self {
+        Self {
+            client: http_client,
+            oauth_client,
+            user_info_url,
+            scopes: merge_scopes(&[], scopes),
+        }
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for BoxProvider {
+    /// Returns a reference to the OAuth client for Box
+    ///
+    /// # Returns
+    ///
+    /// Returns a reference to the configured `OAuthClient` instance
+    fn get_oauth_client(&self) -> &OAuthClient {
+        &self.oauth_client
+    }
+
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Returns the OAuth scopes requested from Box
+    ///
+    /// Box grants access to the signed-in user without any scope, and
+    /// otherwise uses the scopes configured for the app.
+    ///
+    /// # Returns
+    ///
+    /// Returns the configured scopes, none by default
+    fn get_scopes(&self) -> Vec<String> {
+        self.scopes.clone()
+    }
+
+    /// Fetches user information from Box's `/2.0/users/me` endpoint
+    ///
+    /// # Arguments
+    ///
+    /// * `access_token` - The OAuth access token obtained from Box
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the user's identifier
+    /// or an error if the request fails
+    async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
+        let request = self
+            .client
+            .get(self.user_info_url.as_str())
+            .header("Authorization", format!("Bearer {}", access_token));
+
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|e| request_error("Failed to get user info from Box", e))?;
+
+        if !response.status().is_success() {
+            bail!("Failed to get user info from Box: {}", response.status());
+        }
+
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from Box", e))?;
+        parse_user_info(&user_data)
+    }
+}
+
+/// Extracts the user information from a Box `/2.0/users/me` response
+///
+/// # Arguments
+///
+/// * `user_data` - The JSON body returned by the Box API
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` containing the user's id, or an error if
+/// the id is missing or a field has an unexpected type
+fn parse_user_info(user_data: &serde_json::Value) -> Result<UserInfo> {
+    let user: BoxUserResponse = parse_response("Box user info", user_data)?;
+    Ok(UserInfo {
+        raw: Some(user_data.clone()),
+        ..user.into()
+    })
+}
+
+/// Factory for creating Box OAuth provider instances
+///
+/// This struct implements the factory pattern for creating Box
+/// OAuth provider instances. It allows the application to create
+/// provider instances dynamically based on configuration.
+pub struct BoxProviderFactory;
+
+impl OAuthProviderFactory for BoxProviderFactory {
+    /// Creates a new Box OAuth provider instance
+    ///
+    /// This method creates a new Box provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, whose `scopes` are
+    ///   requested
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created Box provider
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        Ok(Arc::new(BoxProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.scopes.clone(),
+        )))
+    }
+
+    /// Returns how Box expects the client credentials
+    ///
+    /// Box expects the client credentials in the request body.
+    ///
+    /// # Returns
+    ///
+    /// Returns `TokenAuthType::RequestBody`
+    fn default_auth_type(&self) -> TokenAuthType {
+        TokenAuthType::RequestBody
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that the user is extracted from a recorded `/2.0/users/me` response
+    #[test]
+    fn test_parse_user_response() {
+        let user_data = serde_json::json!({
+            "type": "user",
+            "id": "11446498",
+            "name": "Aaron Levie",
+            "login": "ceo@example.com",
+            "created_at": "2012-12-12T10:53:43-08:00",
+            "modified_at": "2012-12-12T10:53:43-08:00",
+            "language": "en",
+            "timezone": "Africa/Bujumbura",
+            "space_amount": 11345156112i64,
+            "space_used": 1237009912,
+            "max_upload_size": 2147483648i64,
+            "status": "active",
+            "job_title": "CEO",
+            "phone": "6509241374",
+            "address": "900 Jefferson Ave, Redwood City, CA 94063",
+            "avatar_url": "https://www.box.com/api/avatar/large/181216415",
+            "notification_email": { "email": "notifications@example.com", "is_confirmed": true }
+        });
+
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "11446498");
+        assert_eq!(user_info.provider, "box");
+        assert_eq!(user_info.email.as_deref(), Some("ceo@example.com"));
+        assert_eq!(user_info.name.as_deref(), Some("Aaron Levie"));
+        assert_eq!(
+            user_info.avatar_url.as_deref(),
+            Some("https://www.box.com/api/avatar/large/181216415")
+        );
+        assert_eq!(user_info.raw, Some(user_data));
+
+        let error = parse_user_info(&serde_json::json!({ "id": 11446498 }))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.starts_with("Invalid Box user info response at id:"),
+            "{}",
+            error
+        );
+    }
+}
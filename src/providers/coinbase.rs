@@ -0,0 +1,399 @@
+use crate::{
+    primitives::{ProviderContext, UserInfo},
+    providers::{merge_scopes, non_empty, parse_response},
+    settings::TokenAuthType,
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
+};
+use axum::async_trait;
+use eyre::{bail, Result};
+use reqwest::Url;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Version of the Coinbase API the user info request is made against
+const COINBASE_VERSION: &str = "2024-06-01";
+
+/// Coinbase `GET /v2/user` response
+///
+/// # Fields
+///
+/// * `data` - The signed-in user
+#[derive(Debug, Deserialize)]
+struct CoinbaseUserResponse {
+    /// The signed-in user
+    data: CoinbaseUser,
+}
+
+/// User returned by the Coinbase API
+///
+/// # Fields
+///
+/// * `id` - UUID of the user
+/// * `name` - The user's name
+/// * `username` - The user's public username, if they chose one
+/// * `avatar_url` - URL of the user's avatar
+/// * `email` - The user's email, with the `wallet:user:email` scope
+#[derive(Debug, Deserialize)]
+struct CoinbaseUser {
+    /// UUID of the user
+    id: String,
+    /// The user's name
+    #[serde(default, deserialize_with = "non_empty")]
+    name: Option<String>,
+    /// The user's public username
+    #[serde(default, deserialize_with = "non_empty")]
+    username: Option<String>,
+    /// URL of the user's avatar
+    #[serde(default, deserialize_with = "non_empty")]
+    avatar_url: Option<String>,
+    /// The user's email
+    #[serde(default, deserialize_with = "non_empty")]
+    email: Option<String>,
+}
+
+impl From<CoinbaseUser> for UserInfo {
+    /// Maps a Coinbase user to the user information
+    fn from(user: CoinbaseUser) -> Self {
+        UserInfo {
+            id: user.id,
+            provider: "coinbase".to_string(),
+            email: user.email,
+            name: user.name,
+            username: user.username,
+            avatar_url: user.avatar_url,
+            email_verified: None,
+            tenant: None,
+            raw: None,
+        }
+    }
+}
+
+/// Error body of the Coinbase API
+///
+/// # Fields
+///
+/// * `errors` - The errors of the request, most relevant first
+#[derive(Debug, Deserialize)]
+struct CoinbaseErrorResponse {
+    /// The errors of the request
+    errors: Vec<CoinbaseError>,
+}
+
+/// Error reported by the Coinbase API
+///
+/// # Fields
+///
+/// * `id` - Coinbase's error code, e.g. `invalid_token`
+/// * `message` - Description of the error
+#[derive(Debug, Deserialize)]
+struct CoinbaseError {
+    /// Coinbase's error code
+    id: String,
+    /// Description of the error
+    message: String,
+}
+
+/// Coinbase OAuth provider implementation
+///
+/// This struct implements the OAuth provider interface for Coinbase. It
+/// handles OAuth 2.0 authentication flow and user information retrieval
+/// from the Coinbase API, which requires a `CB-VERSION` header naming the
+/// API version.
+///
+/// # Fields
+///
+/// * `client` - HTTP client for making API requests
+/// * `oauth_client` - Configured OAuth 2.0 client
+/// * `user_info_url` - Coinbase's user info endpoint URL
+/// * `scopes` - OAuth scopes requested on authorization
+pub struct CoinbaseProvider {
+    /// HTTP client for API requests
+    client: HttpClient,
+    /// Configured OAuth client
+    oauth_client: OAuthClient,
+    /// Coinbase user info endpoint URL
+    user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
+}
+
+impl CoinbaseProvider {
+    /// Creates a new Coinbase OAuth provider instance
+    ///
+    /// This constructor creates a new Coinbase provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_client` - The HTTP client shared by every provider
+    /// * `oauth_client` - The configured OAuth client for Coinbase
+    /// * `user_info_url` - The URL for Coinbase's user info endpoint
+    /// * `scopes` - Scopes configured in addition to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `CoinbaseProvider` instance
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        scopes: Option<Vec<String>>,
+    ) -> Self {
+        Self {
+            client: http_client,
+            oauth_client,
+            user_info_url,
+            scopes: merge_scopes(&["wallet:user:read", "wallet:user:email"], scopes),
+        }
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for CoinbaseProvider {
+    /// Returns a reference to the OAuth client for Coinbase
+    ///
+    /// # Returns
+    ///
+    /// Returns a reference to the configured `OAuthClient` instance
+    fn get_oauth_client(&self) -> &OAuthClient {
+        &self.oauth_client
+    }
+
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Returns the OAuth scopes required for Coinbase
+    ///
+    /// Coinbase requires the "wallet:user:read" scope to access the
+    /// signed-in user, and the "wallet:user:email" scope to share their
+    /// email.
+    ///
+    /// # Returns
+    ///
+    /// Returns the required scopes followed by the configured ones
+    fn get_scopes(&self) -> Vec<String> {
+        self.scopes.clone()
+    }
+
+    /// Fetches user information from Coinbase's `/v2/user` endpoint
+    ///
+    /// # Arguments
+    ///
+    /// * `access_token` - The OAuth access token obtained from Coinbase
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the user's identifier, or an
+    /// error carrying Coinbase's first error message if the request fails
+    async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
+        let request = self
+            .client
+            .get(self.user_info_url.as_str())
+            .header("Authorization", format!("Bearer {}", access_token))
+            .header("CB-VERSION", COINBASE_VERSION);
+
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|e| request_error("Failed to get user info from Coinbase", e))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error = response
+                .json::<CoinbaseErrorResponse>()
+                .await
+                .ok()
+                .and_then(|body| body.errors.into_iter().next());
+            match error {
+                Some(error) => bail!(
+                    "Failed to get user info from Coinbase: {}: {} ({})",
+                    status,
+                    error.message,
+                    error.id
+                ),
+                None => bail!("Failed to get user info from Coinbase: {}", status),
+            }
+        }
+
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from Coinbase", e))?;
+        parse_user_info(&user_data)
+    }
+}
+
+/// Extracts the user information from a Coinbase `/v2/user` response
+///
+/// # Arguments
+///
+/// * `user_data` - The JSON body returned by the Coinbase API
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` containing the user's id, or an error if
+/// the id is missing or a field has an unexpected type
+fn parse_user_info(user_data: &serde_json::Value) -> Result<UserInfo> {
+    let user: CoinbaseUserResponse = parse_response("Coinbase user info", user_data)?;
+    Ok(UserInfo {
+        raw: Some(user_data.clone()),
+        ..user.data.into()
+    })
+}
+
+/// Factory for creating Coinbase OAuth provider instances
+///
+/// This struct implements the factory pattern for creating Coinbase
+/// OAuth provider instances. It allows the application to create
+/// provider instances dynamically based on configuration.
+pub struct CoinbaseProviderFactory;
+
+impl OAuthProviderFactory for CoinbaseProviderFactory {
+    /// Creates a new Coinbase OAuth provider instance
+    ///
+    /// This method creates a new Coinbase provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, whose `scopes` are
+    ///   added to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created Coinbase provider
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        Ok(Arc::new(CoinbaseProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.scopes.clone(),
+        )))
+    }
+
+    /// Returns how Coinbase expects the client credentials
+    ///
+    /// Coinbase expects the client credentials in the request body.
+    ///
+    /// # Returns
+    ///
+    /// Returns `TokenAuthType::RequestBody`
+    fn default_auth_type(&self) -> TokenAuthType {
+        TokenAuthType::RequestBody
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockApi;
+    use axum::http::StatusCode;
+
+    /// Tests that the user is extracted from a recorded `/v2/user` response
+    #[test]
+    fn test_parse_user_response() {
+        let user_data = serde_json::json!({
+            "data": {
+                "id": "9da7a204-544e-5fd1-9a12-61176c5d4cd8",
+                "name": "User One",
+                "username": "user1",
+                "profile_location": null,
+                "profile_bio": null,
+                "profile_url": "https://coinbase.com/user1",
+                "avatar_url": "https://images.coinbase.com/avatar?h=vR%2FY8igBoPwuwGren5JMwvDNGpURAY%2F0nRIOgH%2FY2Qh%2BQ6nomR3qusA%2Bh6o2%0Af9rH&s=128",
+                "resource": "user",
+                "resource_path": "/v2/user",
+                "email": "user1@example.com",
+                "time_zone": "Pacific Time (US & Canada)",
+                "native_currency": "USD",
+                "bitcoin_unit": "bits",
+                "country": { "code": "US", "name": "United States" },
+                "created_at": "2015-01-31T20:49:02Z"
+            }
+        });
+
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "9da7a204-544e-5fd1-9a12-61176c5d4cd8");
+        assert_eq!(user_info.provider, "coinbase");
+        assert_eq!(user_info.email.as_deref(), Some("user1@example.com"));
+        assert_eq!(user_info.name.as_deref(), Some("User One"));
+        assert_eq!(user_info.username.as_deref(), Some("user1"));
+        assert_eq!(user_info.email_verified, None);
+        assert_eq!(user_info.raw, Some(user_data));
+
+        let error = parse_user_info(&serde_json::json!({ "data": { "id": 42 } }))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.starts_with("Invalid Coinbase user info response at data.id:"),
+            "{}",
+            error
+        );
+    }
+
+    /// Tests that the user info request names the API version, and that the
+    /// first of Coinbase's errors is kept when the request fails
+    #[tokio::test]
+    async fn test_user_info_request() {
+        let api = MockApi::builder()
+            .get("/v2/user", |request| {
+                if request.header("cb-version") != Some(COINBASE_VERSION) {
+                    return (StatusCode::BAD_REQUEST, serde_json::Value::Null);
+                }
+                if request.header("authorization") != Some("Bearer coinbase-token") {
+                    return (
+                        StatusCode::UNAUTHORIZED,
+                        serde_json::json!({
+                            "errors": [
+                                { "id": "invalid_token", "message": "The access token is invalid" },
+                                { "id": "authentication_error", "message": "Unauthorized" }
+                            ]
+                        }),
+                    );
+                }
+                (
+                    StatusCode::OK,
+                    serde_json::json!({ "data": { "id": "9da7a204-544e-5fd1-9a12-61176c5d4cd8" } }),
+                )
+            })
+            .start()
+            .await
+            .unwrap();
+
+        let provider = api
+            .provider(
+                "coinbase",
+                api.settings("/oauth2/auth", "/oauth2/token", "/v2/user"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            provider.get_scopes(),
+            vec!["wallet:user:read", "wallet:user:email"]
+        );
+
+        let user_info = provider.get_user_info("coinbase-token").await.unwrap();
+        assert_eq!(user_info.id, "9da7a204-544e-5fd1-9a12-61176c5d4cd8");
+
+        let error = provider.get_user_info("revoked").await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Failed to get user info from Coinbase: 401 Unauthorized: The access token is invalid (invalid_token)"
+        );
+    }
+}
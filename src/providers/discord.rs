@@ -1,13 +1,78 @@
 use crate::{
-    primitives::UserInfo,
-    traits::{OAuthProvider, OAuthProviderFactory},
-    types::OAuthClient,
+    primitives::{ProviderContext, ProviderDisplay, UserInfo},
+    providers::{flag, merge_scopes, non_empty, parse_response},
+    settings::UserIdField,
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
 };
 use axum::async_trait;
 use eyre::{bail, Result};
-use reqwest::{Client, Url};
+use reqwest::Url;
+use serde::Deserialize;
 use std::sync::Arc;
 
+/// Base URL of Discord's user avatar CDN
+const AVATAR_CDN_URL: &str = "https://cdn.discordapp.com/avatars";
+
+/// Discord `/users/@me` response
+///
+/// # Fields
+///
+/// * `id` - Snowflake id of the user
+/// * `username` - The user's unique username
+/// * `global_name` - The user's display name, if set
+/// * `avatar` - Hash of the user's avatar, if set
+/// * `email` - The user's email, with the `email` scope
+/// * `verified` - Whether the email is verified, with the `email` scope
+#[derive(Debug, Deserialize)]
+struct DiscordUserResponse {
+    /// Snowflake id of the user
+    #[serde(default, deserialize_with = "non_empty")]
+    id: Option<String>,
+    /// The user's unique username
+    #[serde(default, deserialize_with = "non_empty")]
+    username: Option<String>,
+    /// The user's display name
+    #[serde(default, deserialize_with = "non_empty")]
+    global_name: Option<String>,
+    /// Hash of the user's avatar
+    #[serde(default, deserialize_with = "non_empty")]
+    avatar: Option<String>,
+    /// The user's email
+    #[serde(default, deserialize_with = "non_empty")]
+    email: Option<String>,
+    /// Whether the email is verified
+    #[serde(default, deserialize_with = "flag")]
+    verified: Option<bool>,
+}
+
+impl From<DiscordUserResponse> for UserInfo {
+    /// Maps a Discord user to the user information
+    ///
+    /// The display name prefers the user's global name over the username,
+    /// and the avatar URL is built from the user id and avatar hash, animated
+    /// avatars (`a_` hashes) are served as GIFs. The user is identified by
+    /// their snowflake id, empty when missing.
+    fn from(user: DiscordUserResponse) -> Self {
+        let avatar_url = user.id.as_ref().zip(user.avatar).map(|(id, hash)| {
+            let extension = if hash.starts_with("a_") { "gif" } else { "png" };
+            format!("{}/{}/{}.{}", AVATAR_CDN_URL, id, hash, extension)
+        });
+
+        UserInfo {
+            id: user.id.unwrap_or_default(),
+            provider: "discord".to_string(),
+            email: user.email,
+            name: user.global_name.or_else(|| user.username.clone()),
+            username: user.username,
+            avatar_url,
+            email_verified: user.verified,
+            tenant: None,
+            raw: None,
+        }
+    }
+}
+
 /// Discord OAuth provider implementation
 ///
 /// This struct implements the OAuth provider interface for Discord.
@@ -19,13 +84,19 @@ use std::sync::Arc;
 /// * `client` - HTTP client for making API requests
 /// * `oauth_client` - Configured OAuth 2.0 client
 /// * `user_info_url` - Discord's user info endpoint URL
+/// * `scopes` - OAuth scopes requested on authorization
+/// * `id_field` - Field of the user info response identifying the user
 pub struct DiscordProvider {
     /// HTTP client for API requests
-    client: Client,
+    client: HttpClient,
     /// Configured OAuth client
     oauth_client: OAuthClient,
     /// Discord user info endpoint URL
     user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
+    /// Field identifying the user
+    id_field: UserIdField,
 }
 
 impl DiscordProvider {
@@ -36,17 +107,28 @@ impl DiscordProvider {
     ///
     /// # Arguments
     ///
+    /// * `http_client` - The HTTP client shared by every provider
     /// * `oauth_client` - The configured OAuth client for Discord
     /// * `user_info_url` - The URL for Discord's user info endpoint
+    /// * `scopes` - Scopes configured in addition to the required ones
+    /// * `id_field` - Field of the user info response identifying the user
     ///
     /// # Returns
     ///
     /// Returns a new `DiscordProvider` instance
-    pub fn new(oauth_client: OAuthClient, user_info_url: Url) -> Self {
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        scopes: Option<Vec<String>>,
+        id_field: UserIdField,
+    ) -> Self {
         Self {
-            client: Client::new(),
+            client: http_client,
             oauth_client,
             user_info_url,
+            scopes: merge_scopes(&["identify"], scopes),
+            id_field,
         }
     }
 }
@@ -62,6 +144,15 @@ impl OAuthProvider for DiscordProvider {
         &self.oauth_client
     }
 
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
     /// Returns the OAuth scopes required for Discord
     ///
     /// Discord requires the "identify" scope to access the user's
@@ -69,9 +160,22 @@ impl OAuthProvider for DiscordProvider {
     ///
     /// # Returns
     ///
-    /// Returns a vector containing the "identify" scope
+    /// Returns the required scopes followed by the configured ones
     fn get_scopes(&self) -> Vec<String> {
-        vec!["identify".to_string()]
+        self.scopes.clone()
+    }
+
+    /// Returns the Discord brand color and logo
+    ///
+    /// # Returns
+    ///
+    /// Returns the branding of the Discord sign-in button
+    fn display_metadata(&self) -> Option<ProviderDisplay> {
+        Some(ProviderDisplay {
+            label: "Discord",
+            color: "#5865f2",
+            icon: r#"<path fill="currentColor" d="M20.317 4.3698a19.7913 19.7913 0 00-4.8851-1.5152.0741.0741 0 00-.0785.0371c-.211.3753-.4447.8648-.6083 1.2495-1.8447-.2762-3.68-.2762-5.4868 0-.1636-.3933-.4058-.8742-.6177-1.2495a.077.077 0 00-.0785-.037 19.7363 19.7363 0 00-4.8852 1.515.0699.0699 0 00-.0321.0277C.5334 9.0458-.319 13.5799.0992 18.0578a.0824.0824 0 00.0312.0561c2.0528 1.5076 4.0413 2.4228 5.9929 3.0294a.0777.0777 0 00.0842-.0276c.4616-.6304.8731-1.2952 1.226-1.9942a.076.076 0 00-.0416-.1057c-.6528-.2476-1.2743-.5495-1.8722-.8923a.077.077 0 01-.0076-.1277c.1258-.0943.2517-.1923.3718-.2914a.0743.0743 0 01.0776-.0105c3.9278 1.7933 8.18 1.7933 12.0614 0a.0739.0739 0 01.0785.0095c.1202.099.246.1981.3728.2924a.077.077 0 01-.0066.1276 12.2986 12.2986 0 01-1.873.8914.0766.0766 0 00-.0407.1067c.3604.698.7719 1.3628 1.225 1.9932a.076.076 0 00.0842.0286c1.961-.6067 3.9495-1.5219 6.0023-3.0294a.077.077 0 00.0313-.0552c.5004-5.177-.8382-9.6739-3.5485-13.6604a.061.061 0 00-.0312-.0286zM8.02 15.3312c-1.1825 0-2.1569-1.0857-2.1569-2.419 0-1.3332.9555-2.4189 2.157-2.4189 1.2108 0 2.1757 1.0952 2.1568 2.419-.019 1.3332-.9555 2.4189-2.1569 2.4189zm7.9748 0c-1.1825 0-2.1569-1.0857-2.1569-2.419 0-1.3332.9554-2.4189 2.1569-2.4189 1.2108 0 2.1757 1.0952 2.1568 2.419 0 1.3332-.9555 2.4189-2.1568 2.4189Z"/>"#,
+        })
     }
 
     /// Fetches user information from Discord's user info endpoint
@@ -86,18 +190,19 @@ impl OAuthProvider for DiscordProvider {
     ///
     /// # Returns
     ///
-    /// Returns `Result<UserInfo>` containing the user's username
-    /// or an error if the request fails
+    /// Returns `Result<UserInfo>` containing the user's identifier and
+    /// profile, or an error if the request fails
     async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
         let request = self
             .client
             .get(self.user_info_url.as_str())
             .header("Authorization", format!("Bearer {}", access_token));
 
-        let response = request
-            .send()
+        let response = self
+            .client
+            .send(request)
             .await
-            .map_err(|e| eyre::eyre!("Failed to get user info from Discord: {}", e))?;
+            .map_err(|e| request_error("Failed to get user info from Discord", e))?;
 
         if !response.status().is_success() {
             bail!(
@@ -106,19 +211,45 @@ impl OAuthProvider for DiscordProvider {
             );
         }
 
-        let user_data: serde_json::Value = response.json().await?;
-        let username = user_data["username"]
-            .as_str()
-            .ok_or_else(|| eyre::eyre!("No username field in Discord user info response"))?
-            .to_string();
-
-        Ok(UserInfo {
-            id: username,
-            provider: "discord".to_string(),
-        })
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from Discord", e))?;
+        parse_user_info(&user_data, self.id_field)
     }
 }
 
+/// Extracts the user information from a Discord `/users/@me` response
+///
+/// # Arguments
+///
+/// * `user_data` - The JSON body returned by the user info endpoint
+/// * `id_field` - Whether the user is identified by their snowflake id or username
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` identified by the selected field, or an error
+/// if that field is missing or a field has an unexpected type
+fn parse_user_info(user_data: &serde_json::Value, id_field: UserIdField) -> Result<UserInfo> {
+    let user: DiscordUserResponse = parse_response("Discord user info", user_data)?;
+    let id = match id_field {
+        UserIdField::Id => user
+            .id
+            .clone()
+            .ok_or_else(|| eyre::eyre!("No id field in Discord user info response"))?,
+        UserIdField::Username => user
+            .username
+            .clone()
+            .ok_or_else(|| eyre::eyre!("No username field in Discord user info response"))?,
+    };
+
+    Ok(UserInfo {
+        id,
+        raw: Some(user_data.clone()),
+        ..user.into()
+    })
+}
+
 /// Factory for creating Discord OAuth provider instances
 ///
 /// This struct implements the factory pattern for creating Discord
@@ -134,13 +265,124 @@ impl OAuthProviderFactory for DiscordProviderFactory {
     ///
     /// # Arguments
     ///
-    /// * `oauth_client` - The configured OAuth client for Discord
-    /// * `user_info_url` - The URL for Discord's user info endpoint
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, whose `scopes` are
+    ///   added to the required ones and whose `id_field` selects the user's identifier
     ///
     /// # Returns
     ///
     /// Returns an `Arc<dyn OAuthProvider>` containing the created Discord provider
-    fn create(&self, oauth_client: OAuthClient, user_info_url: Url) -> Arc<dyn OAuthProvider> {
-        Arc::new(DiscordProvider::new(oauth_client, user_info_url))
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        Ok(Arc::new(DiscordProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.scopes.clone(),
+            settings.id_field,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that the profile is extracted from a recorded user response
+    #[test]
+    fn test_parse_user_response() {
+        let user_data = serde_json::json!({
+            "id": "80351110224678912",
+            "username": "nelly",
+            "discriminator": "0",
+            "global_name": "Nelly",
+            "avatar": "8342729096ea3675442027381ff50dfe",
+            "verified": true,
+            "email": "nelly@discord.com",
+            "flags": 64,
+            "premium_type": 1,
+            "public_flags": 64
+        });
+
+        let user_info = parse_user_info(&user_data, UserIdField::Id).unwrap();
+        assert_eq!(user_info.id, "80351110224678912");
+        assert_eq!(user_info.provider, "discord");
+        assert_eq!(user_info.username.as_deref(), Some("nelly"));
+        assert_eq!(user_info.email.as_deref(), Some("nelly@discord.com"));
+        assert_eq!(user_info.email_verified, Some(true));
+        assert_eq!(user_info.name.as_deref(), Some("Nelly"));
+        assert_eq!(
+            user_info.avatar_url.as_deref(),
+            Some("https://cdn.discordapp.com/avatars/80351110224678912/8342729096ea3675442027381ff50dfe.png")
+        );
+        assert_eq!(user_info.raw, Some(user_data));
+    }
+
+    /// Tests that users can still be identified by their username
+    #[test]
+    fn test_username_id_field() {
+        let user_data = serde_json::json!({ "id": "80351110224678912", "username": "nelly" });
+
+        let user_info = parse_user_info(&user_data, UserIdField::Username).unwrap();
+        assert_eq!(user_info.id, "nelly");
+        assert_eq!(user_info.username.as_deref(), Some("nelly"));
+
+        let user_data = serde_json::json!({ "id": "80351110224678912" });
+        assert!(parse_user_info(&user_data, UserIdField::Username).is_err());
+        assert!(parse_user_info(&user_data, UserIdField::Id).is_ok());
+    }
+
+    /// Tests animated avatars and users without a global name or avatar
+    #[test]
+    fn test_avatar_variants() {
+        let user_data = serde_json::json!({
+            "id": "80351110224678912",
+            "username": "nelly",
+            "global_name": null,
+            "avatar": "a_1269e74af4df7417b13759eae50c83dc"
+        });
+
+        let user_info = parse_user_info(&user_data, UserIdField::Id).unwrap();
+        assert_eq!(user_info.name.as_deref(), Some("nelly"));
+        assert_eq!(
+            user_info.avatar_url.as_deref(),
+            Some("https://cdn.discordapp.com/avatars/80351110224678912/a_1269e74af4df7417b13759eae50c83dc.gif")
+        );
+
+        let user_data = serde_json::json!({
+            "id": "80351110224678912",
+            "username": "nelly",
+            "avatar": null
+        });
+        assert_eq!(
+            parse_user_info(&user_data, UserIdField::Id)
+                .unwrap()
+                .avatar_url,
+            None
+        );
+    }
+
+    /// Tests that fields the server does not read are ignored, and that a
+    /// field with an unexpected type is reported with its path
+    #[test]
+    fn test_response_fields() {
+        let user_data = serde_json::json!({ "id": "80351110224678912", "username": "nelly", "banner": null, "accent_color": 16711680, "flags": 64, "premium_type": 1 });
+        let user_info = parse_user_info(&user_data, UserIdField::Id).unwrap();
+        assert_eq!(user_info.id, "80351110224678912");
+
+        let user_data = serde_json::json!({ "id": "80351110224678912", "username": "nelly", "verified": "yes" });
+        let error = parse_user_info(&user_data, UserIdField::Id)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.starts_with("Invalid Discord user info response at verified:"),
+            "{}",
+            error
+        );
     }
 }
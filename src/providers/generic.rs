@@ -0,0 +1,353 @@
+use crate::{
+    primitives::{ProviderContext, UserInfo},
+    providers::merge_scopes,
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
+};
+use axum::async_trait;
+use eyre::{bail, eyre, Result};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION},
+    Url,
+};
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Generic OAuth provider implementation
+///
+/// This struct implements the OAuth provider interface for providers
+/// without a dedicated implementation, such as internal services. It is
+/// selected with `type = "generic"` and configured entirely from the
+/// settings: the scopes to request, extra headers for the user info
+/// request, and where the user id sits in the user info response.
+///
+/// # Fields
+///
+/// * `client` - HTTP client for making API requests
+/// * `oauth_client` - Configured OAuth 2.0 client
+/// * `user_info_url` - The provider's user info endpoint URL
+/// * `scopes` - OAuth scopes requested on authorization
+/// * `user_id_path` - JSON pointer of the user id in the user info response
+/// * `user_info_headers` - Extra headers sent with the user info request
+pub struct GenericProvider {
+    /// HTTP client for API requests
+    client: HttpClient,
+    /// Configured OAuth client
+    oauth_client: OAuthClient,
+    /// User info endpoint URL
+    user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
+    /// JSON pointer of the user id in the user info response
+    user_id_path: String,
+    /// Extra headers sent with the user info request
+    user_info_headers: HeaderMap,
+}
+
+impl GenericProvider {
+    /// Creates a new generic OAuth provider instance
+    ///
+    /// # Arguments
+    ///
+    /// * `http_client` - The HTTP client shared by every provider
+    /// * `oauth_client` - The configured OAuth client
+    /// * `user_info_url` - The URL of the provider's user info endpoint
+    /// * `scopes` - Scopes to request, none are required
+    /// * `user_id_path` - JSON pointer of the user id in the user info response
+    /// * `user_info_headers` - Extra headers sent with the user info request
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `GenericProvider` instance
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        scopes: Option<Vec<String>>,
+        user_id_path: String,
+        user_info_headers: HeaderMap,
+    ) -> Self {
+        Self {
+            client: http_client,
+            oauth_client,
+            user_info_url,
+            scopes: merge_scopes(&[], scopes),
+            user_id_path,
+            user_info_headers,
+        }
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for GenericProvider {
+    /// Returns a reference to the configured OAuth client
+    ///
+    /// # Returns
+    ///
+    /// Returns a reference to the configured `OAuthClient` instance
+    fn get_oauth_client(&self) -> &OAuthClient {
+        &self.oauth_client
+    }
+
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Returns the configured OAuth scopes
+    ///
+    /// # Returns
+    ///
+    /// Returns the scopes from the settings, the provider requires none
+    fn get_scopes(&self) -> Vec<String> {
+        self.scopes.clone()
+    }
+
+    /// Opts in to the device authorization grant
+    ///
+    /// Whether the server implements RFC 8628 is only known once a
+    /// `device_auth_url` is configured.
+    ///
+    /// # Returns
+    ///
+    /// Always returns `true`
+    fn supports_device_flow(&self) -> bool {
+        true
+    }
+
+    /// Fetches user information from the configured user info endpoint
+    ///
+    /// The configured extra headers are sent alongside the access token.
+    ///
+    /// # Arguments
+    ///
+    /// * `access_token` - The OAuth access token obtained from the provider
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the user's identifier
+    /// or an error if the request fails or the id is missing
+    async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
+        let request = self
+            .client
+            .get(self.user_info_url.as_str())
+            .headers(self.user_info_headers.clone())
+            .header("Authorization", format!("Bearer {}", access_token));
+
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|e| request_error("Failed to get user info", e))?;
+
+        if !response.status().is_success() {
+            bail!("Failed to get user info: {}", response.status());
+        }
+
+        let user_data: Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info", e))?;
+        parse_user_info(&user_data, &self.user_id_path)
+    }
+}
+
+/// Extracts the user information from a user info response
+///
+/// String ids are used as is, numeric ids are converted to their decimal
+/// representation. The response shape is unknown, so the profile is only
+/// available through the raw response.
+///
+/// # Arguments
+///
+/// * `user_data` - The JSON body returned by the user info endpoint
+/// * `user_id_path` - JSON pointer of the user id
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` containing the user's identifier
+/// or an error if the path is missing or not a string or number
+fn parse_user_info(user_data: &Value, user_id_path: &str) -> Result<UserInfo> {
+    let id = match user_data.pointer(user_id_path) {
+        Some(Value::String(id)) if !id.is_empty() => id.clone(),
+        Some(Value::Number(id)) => id.to_string(),
+        Some(_) => bail!(
+            "{} in user info response is not a string or number",
+            user_id_path
+        ),
+        None => bail!("No {} field in user info response", user_id_path),
+    };
+
+    Ok(UserInfo {
+        id,
+        provider: "generic".to_string(),
+        raw: Some(user_data.clone()),
+        ..UserInfo::default()
+    })
+}
+
+/// Converts a configured user id path to a JSON pointer
+///
+/// Paths starting with `/` are JSON pointers and kept as is, other paths
+/// are dotted (`data.user.id`), with numeric segments indexing arrays.
+///
+/// # Arguments
+///
+/// * `path` - The configured path
+///
+/// # Returns
+///
+/// Returns the equivalent JSON pointer
+fn to_json_pointer(path: &str) -> String {
+    if path.starts_with('/') {
+        return path.to_string();
+    }
+
+    path.split('.')
+        .map(|segment| format!("/{}", segment.replace('~', "~0").replace('/', "~1")))
+        .collect()
+}
+
+/// Parses the configured extra user info headers
+///
+/// # Arguments
+///
+/// * `headers` - Header names and values from the settings
+///
+/// # Returns
+///
+/// Returns the headers, or an error if one is invalid or overrides the
+/// `Authorization` header carrying the access token
+fn parse_headers<'a>(
+    headers: impl IntoIterator<Item = (&'a String, &'a String)>,
+) -> Result<HeaderMap> {
+    let mut header_map = HeaderMap::new();
+    for (name, value) in headers {
+        let name = HeaderName::try_from(name.as_str())
+            .map_err(|_| eyre!("invalid user_info_headers name {:?}", name))?;
+        if name == AUTHORIZATION {
+            bail!("user_info_headers cannot set the Authorization header");
+        }
+        let value = HeaderValue::try_from(value.as_str())
+            .map_err(|_| eyre!("invalid user_info_headers value for {}", name))?;
+        header_map.insert(name, value);
+    }
+
+    Ok(header_map)
+}
+
+/// Factory for creating generic OAuth provider instances
+///
+/// This struct implements the factory pattern for creating generic OAuth
+/// provider instances. It allows the application to create provider
+/// instances dynamically based on configuration.
+pub struct GenericProviderFactory;
+
+impl OAuthProviderFactory for GenericProviderFactory {
+    /// Creates a new generic OAuth provider instance
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, with the
+    ///   `user_id_path`, and optionally `scopes` and `user_info_headers`
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created generic
+    /// provider, or an error if `user_id_path` is missing or a header is invalid
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        let Some(user_id_path) = settings.user_id_path.as_deref().filter(|p| !p.is_empty()) else {
+            bail!("user_id_path is required for generic providers");
+        };
+        let headers = parse_headers(settings.user_info_headers.iter().flatten())?;
+
+        Ok(Arc::new(GenericProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.scopes.clone(),
+            to_json_pointer(user_id_path),
+            headers,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    /// Tests that ids are extracted from nested objects and arrays
+    #[test]
+    fn test_nested_path() {
+        let user_data = serde_json::json!({
+            "data": {
+                "user": { "id": "u-42", "name": "Jane Doe" },
+                "accounts": [{ "id": "a-1" }]
+            }
+        });
+
+        let user_info = parse_user_info(&user_data, &to_json_pointer("data.user.id")).unwrap();
+        assert_eq!(user_info.id, "u-42");
+        assert_eq!(user_info.provider, "generic");
+
+        let user_info =
+            parse_user_info(&user_data, &to_json_pointer("data.accounts.0.id")).unwrap();
+        assert_eq!(user_info.id, "a-1");
+
+        let user_info = parse_user_info(&user_data, &to_json_pointer("/data/user/id")).unwrap();
+        assert_eq!(user_info.id, "u-42");
+    }
+
+    /// Tests that numeric ids are converted to strings
+    #[test]
+    fn test_numeric_id() {
+        let user_data = serde_json::json!({ "user": { "id": 1234567890123_u64 } });
+
+        let user_info = parse_user_info(&user_data, &to_json_pointer("user.id")).unwrap();
+        assert_eq!(user_info.id, "1234567890123");
+    }
+
+    /// Tests that missing, null and non-scalar ids are rejected
+    #[test]
+    fn test_missing_path() {
+        let user_data = serde_json::json!({
+            "user": { "id": null, "profile": { "name": "Jane Doe" } }
+        });
+
+        for path in [
+            "user.login",
+            "account.id",
+            "user.id",
+            "user.profile",
+            "user.profile.name.first",
+        ] {
+            assert!(
+                parse_user_info(&user_data, &to_json_pointer(path)).is_err(),
+                "{}",
+                path
+            );
+        }
+    }
+
+    /// Tests that invalid headers and Authorization overrides are rejected
+    #[test]
+    fn test_user_info_headers() {
+        let headers = |name: &str| Some(BTreeMap::from([(name.to_string(), "value".to_string())]));
+
+        assert!(parse_headers(headers("X-Api-Version").iter().flatten()).is_ok());
+        assert!(parse_headers(headers("Authorization").iter().flatten()).is_err());
+        assert!(parse_headers(headers("not a header").iter().flatten()).is_err());
+    }
+}
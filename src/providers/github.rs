@@ -1,13 +1,78 @@
 use crate::{
-    primitives::UserInfo,
-    traits::{OAuthProvider, OAuthProviderFactory},
-    types::OAuthClient,
+    primitives::{ProviderContext, ProviderDisplay, TokenDetails, UserInfo},
+    providers::{merge_scopes, non_empty, parse_response},
+    traits::{request_error, OAuthProvider, OAuthProviderFactory, RefreshUnsupported},
+    types::{HttpClient, OAuthClient},
 };
 use axum::async_trait;
-use eyre::{bail, Result};
-use reqwest::{Client, Url};
+use eyre::{bail, eyre, Result};
+use reqwest::{StatusCode, Url};
+use serde::Deserialize;
 use std::sync::Arc;
 
+/// GitHub `/user` response
+///
+/// # Fields
+///
+/// * `id` - Numeric id of the user
+/// * `login` - The user's handle
+/// * `email` - The user's public email, if they made one public
+/// * `avatar_url` - URL of the user's avatar
+#[derive(Debug, Deserialize)]
+struct GithubUserResponse {
+    /// Numeric id of the user
+    id: u64,
+    /// The user's handle
+    #[serde(default, deserialize_with = "non_empty")]
+    login: Option<String>,
+    /// The user's public email
+    #[serde(default, deserialize_with = "non_empty")]
+    email: Option<String>,
+    /// URL of the user's avatar
+    #[serde(default, deserialize_with = "non_empty")]
+    avatar_url: Option<String>,
+}
+
+impl From<GithubUserResponse> for UserInfo {
+    /// Maps a GitHub user to the user information
+    ///
+    /// The login is used as the display name, and the email is only set when
+    /// the user made one public.
+    fn from(user: GithubUserResponse) -> Self {
+        UserInfo {
+            id: user.id.to_string(),
+            provider: "github".to_string(),
+            email: user.email,
+            name: user.login.clone(),
+            username: user.login,
+            avatar_url: user.avatar_url,
+            email_verified: None,
+            tenant: None,
+            raw: None,
+        }
+    }
+}
+
+/// Address listed by GitHub's `/user/emails` endpoint
+///
+/// # Fields
+///
+/// * `email` - The email address
+/// * `primary` - Whether it is the user's primary address
+/// * `verified` - Whether GitHub verified it
+#[derive(Debug, Deserialize)]
+struct GithubEmailResponse {
+    /// The email address
+    #[serde(default, deserialize_with = "non_empty")]
+    email: Option<String>,
+    /// Whether it is the user's primary address
+    #[serde(default)]
+    primary: bool,
+    /// Whether GitHub verified it
+    #[serde(default)]
+    verified: bool,
+}
+
 /// GitHub OAuth provider implementation
 ///
 /// This struct implements the OAuth provider interface for GitHub.
@@ -19,13 +84,19 @@ use std::sync::Arc;
 /// * `client` - HTTP client for making API requests
 /// * `oauth_client` - Configured OAuth 2.0 client
 /// * `user_info_url` - GitHub's user info endpoint URL
+/// * `emails_url` - GitHub's endpoint listing the user's email addresses
+/// * `scopes` - OAuth scopes requested on authorization
 pub struct GithubProvider {
     /// HTTP client for API requests
-    client: Client,
+    client: HttpClient,
     /// Configured OAuth client
     oauth_client: OAuthClient,
     /// GitHub user info endpoint URL
     user_info_url: Url,
+    /// GitHub user emails endpoint URL
+    emails_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
 }
 
 impl GithubProvider {
@@ -36,18 +107,76 @@ impl GithubProvider {
     ///
     /// # Arguments
     ///
+    /// * `http_client` - The HTTP client shared by every provider
     /// * `oauth_client` - The configured OAuth client for GitHub
     /// * `user_info_url` - The URL for GitHub's user info endpoint
+    /// * `emails_url` - The URL for GitHub's user emails endpoint
+    /// * `scopes` - Scopes configured in addition to the required ones
     ///
     /// # Returns
     ///
     /// Returns a new `GithubProvider` instance
-    pub fn new(oauth_client: OAuthClient, user_info_url: Url) -> Self {
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        emails_url: Url,
+        scopes: Option<Vec<String>>,
+    ) -> Self {
         Self {
-            client: Client::new(),
+            client: http_client,
             oauth_client,
             user_info_url,
+            emails_url,
+            scopes: merge_scopes(&["user:email"], scopes),
+        }
+    }
+
+    /// Fetches the user's verified primary email from the emails endpoint
+    ///
+    /// Tokens that may not list the emails, such as fine-grained tokens
+    /// without the email permission, are answered with a 404 or 403 and
+    /// leave the email unset rather than failing the sign-in.
+    ///
+    /// # Arguments
+    ///
+    /// * `access_token` - The OAuth access token obtained from GitHub
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<Option<String>>` containing the verified primary
+    /// email if there is one, or an error if the request fails
+    async fn fetch_primary_email(&self, access_token: &str) -> Result<Option<String>> {
+        let request = self
+            .client
+            .get(self.emails_url.as_str())
+            .header("Authorization", format!("Bearer {}", access_token));
+
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|e| request_error("Failed to get emails from Github", e))?;
+
+        match response.status() {
+            StatusCode::NOT_FOUND | StatusCode::FORBIDDEN => {
+                tracing::debug!(
+                    "Github emails endpoint answered {}, leaving the email unset",
+                    response.status()
+                );
+                return Ok(None);
+            }
+            status if !status.is_success() => {
+                bail!("Failed to get emails from Github: {}", status)
+            }
+            _ => {}
         }
+
+        let emails: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get emails from Github", e))?;
+        Ok(primary_email(parse_response("GitHub emails", &emails)?))
     }
 }
 
@@ -62,6 +191,15 @@ impl OAuthProvider for GithubProvider {
         &self.oauth_client
     }
 
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
     /// Returns the OAuth scopes required for GitHub
     ///
     /// GitHub requires the "user:email" scope to access the user's
@@ -69,16 +207,53 @@ impl OAuthProvider for GithubProvider {
     ///
     /// # Returns
     ///
-    /// Returns a vector containing the "user:email" scope
+    /// Returns the required scopes followed by the configured ones
     fn get_scopes(&self) -> Vec<String> {
-        vec!["user:email".to_string()]
+        self.scopes.clone()
+    }
+
+    /// Returns the GitHub brand color and logo
+    ///
+    /// # Returns
+    ///
+    /// Returns the branding of the GitHub sign-in button
+    fn display_metadata(&self) -> Option<ProviderDisplay> {
+        Some(ProviderDisplay {
+            label: "GitHub",
+            color: "#24292e",
+            icon: r#"<path fill="currentColor" d="M12 0c-6.626 0-12 5.373-12 12 0 5.302 3.438 9.8 8.207 11.387.599.111.793-.261.793-.577v-2.234c-3.338.726-4.033-1.416-4.033-1.416-.546-1.387-1.333-1.756-1.333-1.756-1.089-.745.083-.729.083-.729 1.205.084 1.839 1.237 1.839 1.237 1.07 1.834 2.807 1.304 3.492.997.107-.775.418-1.305.762-1.604-2.665-.305-5.467-1.334-5.467-5.931 0-1.311.469-2.381 1.236-3.221-.124-.303-.535-1.524.117-3.176 0 0 1.008-.322 3.301 1.23.957-.266 1.983-.399 3.003-.404 1.02.005 2.047.138 3.006.404 2.291-1.552 3.297-1.23 3.297-1.23.653 1.653.242 2.874.118 3.176.77.84 1.235 1.911 1.235 3.221 0 4.609-2.807 5.624-5.479 5.921.43.372.823 1.102.823 2.222v3.293c0 .319.192.694.801.576 4.765-1.589 8.199-6.086 8.199-11.386 0-6.627-5.373-12-12-12z"/>"#,
+        })
+    }
+
+    /// Opts in to the device authorization grant
+    ///
+    /// GitHub issues device codes to OAuth apps with the device flow enabled.
+    ///
+    /// # Returns
+    ///
+    /// Always returns `true`
+    fn supports_device_flow(&self) -> bool {
+        true
+    }
+
+    /// Refuses to refresh tokens
+    ///
+    /// Access tokens of classic GitHub OAuth apps do not expire and no
+    /// refresh token is issued.
+    ///
+    /// # Returns
+    ///
+    /// Always returns a `RefreshUnsupported` error
+    async fn refresh_token(&self, _refresh_token: &str, _now: u64) -> Result<TokenDetails> {
+        Err(RefreshUnsupported.into())
     }
 
     /// Fetches user information from GitHub's user info endpoint
     ///
     /// This method makes an authenticated request to GitHub's user info
     /// endpoint to retrieve the user's profile information including
-    /// their user ID.
+    /// their user ID. Users who keep their email private have no email
+    /// in their profile, it is then taken from the emails endpoint.
     ///
     /// # Arguments
     ///
@@ -86,39 +261,89 @@ impl OAuthProvider for GithubProvider {
     ///
     /// # Returns
     ///
-    /// Returns `Result<UserInfo>` containing the user's ID
+    /// Returns `Result<UserInfo>` containing the user's ID and profile
     /// or an error if the request fails
     async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
         let request = self
             .client
             .get(self.user_info_url.as_str())
-            .header("Authorization", format!("Bearer {}", access_token))
-            .header("User-Agent", "Garden-Authenticator");
+            .header("Authorization", format!("Bearer {}", access_token));
 
-        let response = request
-            .send()
+        let response = self
+            .client
+            .send(request)
             .await
-            .map_err(|e| eyre::eyre!("Failed to get user info from Github: {}", e))?;
+            .map_err(|e| request_error("Failed to get user info from Github", e))?;
 
         if !response.status().is_success() {
             bail!("Failed to get user info from Github: {}", response.status());
         }
 
-        let user_data: serde_json::Value = response.json().await?;
-
-        // GitHub returns user ID as a number, so we need to convert it to string
-        let id = user_data["id"]
-            .as_u64()
-            .ok_or_else(|| eyre::eyre!("No valid user ID in GitHub response"))?
-            .to_string();
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from Github", e))?;
+        let mut user_info = parse_user_info(&user_data)?;
+        if user_info.email.is_none() {
+            user_info.email = self.fetch_primary_email(access_token).await?;
+            user_info.email_verified = user_info.email.as_ref().map(|_| true);
+        }
 
-        Ok(UserInfo {
-            id,
-            provider: "github".to_string(),
-        })
+        Ok(user_info)
     }
 }
 
+/// Picks the verified primary address from a GitHub `/user/emails` response
+///
+/// # Arguments
+///
+/// * `emails` - The addresses listed by the emails endpoint
+///
+/// # Returns
+///
+/// Returns the primary email if it is verified, `None` otherwise
+fn primary_email(emails: Vec<GithubEmailResponse>) -> Option<String> {
+    emails
+        .into_iter()
+        .find(|email| email.primary && email.verified)
+        .and_then(|email| email.email)
+}
+
+/// Derives the emails endpoint from the user info endpoint
+///
+/// # Arguments
+///
+/// * `user_info_url` - The URL of GitHub's user info endpoint, `/user`
+///
+/// # Returns
+///
+/// Returns the URL of the emails endpoint, `/user/emails`
+fn default_emails_url(user_info_url: &Url) -> Url {
+    let mut emails_url = user_info_url.clone();
+    let path = format!("{}/emails", user_info_url.path().trim_end_matches('/'));
+    emails_url.set_path(&path);
+    emails_url.set_query(None);
+    emails_url
+}
+
+/// Extracts the user information from a GitHub `/user` response
+///
+/// # Arguments
+///
+/// * `user_data` - The JSON body returned by the user endpoint
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` containing the user's ID and profile
+/// or an error if the ID is missing or a field has an unexpected type
+fn parse_user_info(user_data: &serde_json::Value) -> Result<UserInfo> {
+    let user: GithubUserResponse = parse_response("GitHub user info", user_data)?;
+    Ok(UserInfo {
+        raw: Some(user_data.clone()),
+        ..user.into()
+    })
+}
+
 /// Factory for creating GitHub OAuth provider instances
 ///
 /// This struct implements the factory pattern for creating GitHub
@@ -134,13 +359,179 @@ impl OAuthProviderFactory for GithubProviderFactory {
     ///
     /// # Arguments
     ///
-    /// * `oauth_client` - The configured OAuth client for GitHub
-    /// * `user_info_url` - The URL for GitHub's user info endpoint
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, whose `scopes` are
+    ///   added to the required ones, and optionally the `emails_url`
     ///
     /// # Returns
     ///
-    /// Returns an `Arc<dyn OAuthProvider>` containing the created GitHub provider
-    fn create(&self, oauth_client: OAuthClient, user_info_url: Url) -> Arc<dyn OAuthProvider> {
-        Arc::new(GithubProvider::new(oauth_client, user_info_url))
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created GitHub provider,
+    /// or an error if `emails_url` is invalid
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        let emails_url = match &settings.emails_url {
+            Some(emails_url) => {
+                Url::parse(emails_url).map_err(|e| eyre!("invalid emails_url: {}", e))?
+            }
+            None => default_emails_url(&user_info_url),
+        };
+
+        Ok(Arc::new(GithubProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            emails_url,
+            settings.scopes.clone(),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{settings::OAuthSettings, testing::MockApi};
+    use axum::http::StatusCode;
+
+    /// Tests that the profile is extracted from a recorded user response
+    #[test]
+    fn test_parse_user_response() {
+        let user_data = serde_json::json!({
+            "login": "octocat",
+            "id": 583231,
+            "node_id": "MDQ6VXNlcjU4MzIzMQ==",
+            "avatar_url": "https://avatars.githubusercontent.com/u/583231?v=4",
+            "type": "User",
+            "site_admin": false,
+            "name": "The Octocat",
+            "company": "@github",
+            "email": "octocat@github.com",
+            "public_repos": 8
+        });
+
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "583231");
+        assert_eq!(user_info.provider, "github");
+        assert_eq!(user_info.email.as_deref(), Some("octocat@github.com"));
+        assert_eq!(user_info.name.as_deref(), Some("octocat"));
+        assert_eq!(
+            user_info.avatar_url.as_deref(),
+            Some("https://avatars.githubusercontent.com/u/583231?v=4")
+        );
+        assert_eq!(user_info.raw, Some(user_data));
+    }
+
+    /// Tests that a private email is left unset
+    #[test]
+    fn test_private_email() {
+        let user_data = serde_json::json!({ "login": "octocat", "id": 583231, "email": null });
+
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.email, None);
+    }
+
+    /// Tests that only a verified primary address is picked from a recorded emails response
+    #[test]
+    fn test_primary_email() {
+        let emails = serde_json::json!([
+            { "email": "octocat@users.noreply.github.com", "primary": false, "verified": true, "visibility": null },
+            { "email": "octocat@github.com", "primary": true, "verified": true, "visibility": "private" }
+        ]);
+        let primary = |emails: serde_json::Value| {
+            primary_email(parse_response("GitHub emails", &emails).unwrap())
+        };
+        assert_eq!(primary(emails).as_deref(), Some("octocat@github.com"));
+
+        let emails = serde_json::json!([
+            { "email": "octocat@github.com", "primary": true, "verified": false, "visibility": "private" }
+        ]);
+        assert_eq!(primary(emails), None);
+        assert_eq!(primary(serde_json::json!([])), None);
+    }
+
+    /// Tests that the emails endpoint is derived from the user info endpoint
+    #[test]
+    fn test_default_emails_url() {
+        for (user_info_url, emails_url) in [
+            (
+                "https://api.github.com/user",
+                "https://api.github.com/user/emails",
+            ),
+            (
+                "https://github.example.com/api/v3/user/",
+                "https://github.example.com/api/v3/user/emails",
+            ),
+        ] {
+            let user_info_url = Url::parse(user_info_url).unwrap();
+            assert_eq!(default_emails_url(&user_info_url).as_str(), emails_url);
+        }
+    }
+
+    /// Tests that private emails are fetched from the emails endpoint, and
+    /// that tokens unable to list them still sign the user in
+    #[tokio::test]
+    async fn test_private_email_fallback() {
+        let api = MockApi::builder()
+            .get("/user", |_| {
+                (
+                    StatusCode::OK,
+                    serde_json::json!({ "login": "octocat", "id": 583231, "email": null }),
+                )
+            })
+            .get("/user/emails", |_| {
+                (
+                    StatusCode::OK,
+                    serde_json::json!([
+                        { "email": "octocat@github.com", "primary": true, "verified": true }
+                    ]),
+                )
+            })
+            .get("/fine-grained/emails", |_| {
+                (StatusCode::NOT_FOUND, serde_json::Value::Null)
+            })
+            .start()
+            .await
+            .unwrap();
+        let settings = api.settings(
+            "/login/oauth/authorize",
+            "/login/oauth/access_token",
+            "/user",
+        );
+
+        let provider = api.provider("github", settings.clone()).await.unwrap();
+        let user_info = provider.get_user_info("token").await.unwrap();
+        assert_eq!(user_info.id, "583231");
+        assert_eq!(user_info.email.as_deref(), Some("octocat@github.com"));
+        assert_eq!(user_info.email_verified, Some(true));
+
+        let settings = OAuthSettings {
+            emails_url: Some(api.endpoint("/fine-grained/emails")),
+            ..settings
+        };
+        let provider = api.provider("github", settings).await.unwrap();
+        let user_info = provider.get_user_info("token").await.unwrap();
+        assert_eq!(user_info.id, "583231");
+        assert_eq!(user_info.email, None);
+    }
+
+    /// Tests that fields the server does not read are ignored, and that a
+    /// field with an unexpected type is reported with its path
+    #[test]
+    fn test_response_fields() {
+        let user_data = serde_json::json!({ "id": 1, "login": "octocat", "node_id": "MDQ6VXNlcjE=", "site_admin": false, "plan": { "name": "free" } });
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "1");
+
+        let user_data = serde_json::json!({ "id": "1", "login": "octocat" });
+        let error = parse_user_info(&user_data).unwrap_err().to_string();
+        assert!(
+            error.starts_with("Invalid GitHub user info response at id:"),
+            "{}",
+            error
+        );
     }
 }
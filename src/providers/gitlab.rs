@@ -0,0 +1,350 @@
+use crate::{
+    primitives::{ProviderContext, ProviderEndpoints, UserInfo},
+    providers::{merge_scopes, non_empty, parse_response},
+    settings::OAuthSettings,
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
+};
+use axum::async_trait;
+use eyre::{bail, Result};
+use reqwest::Url;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Base URL of the public GitLab instance
+const GITLAB_COM: &str = "https://gitlab.com";
+
+/// GitLab `/api/v4/user` response
+///
+/// # Fields
+///
+/// * `id` - Numeric id of the user
+/// * `username` - The user's handle
+/// * `name` - The user's full name
+/// * `email` - The user's email, with the `read_user` scope
+/// * `avatar_url` - URL of the user's avatar
+#[derive(Debug, Deserialize)]
+struct GitlabUserResponse {
+    /// Numeric id of the user
+    #[serde(default)]
+    id: Option<u64>,
+    /// The user's handle
+    #[serde(default, deserialize_with = "non_empty")]
+    username: Option<String>,
+    /// The user's full name
+    #[serde(default, deserialize_with = "non_empty")]
+    name: Option<String>,
+    /// The user's email
+    #[serde(default, deserialize_with = "non_empty")]
+    email: Option<String>,
+    /// URL of the user's avatar
+    #[serde(default, deserialize_with = "non_empty")]
+    avatar_url: Option<String>,
+}
+
+impl From<GitlabUserResponse> for UserInfo {
+    /// Maps a GitLab user to the user information
+    ///
+    /// The numeric `id` is used as the user's identifier, since it is stable
+    /// across renames, falling back to the `username`, empty when both are
+    /// missing.
+    fn from(user: GitlabUserResponse) -> Self {
+        UserInfo {
+            id: user
+                .id
+                .map(|id| id.to_string())
+                .or_else(|| user.username.clone())
+                .unwrap_or_default(),
+            provider: "gitlab".to_string(),
+            email: user.email,
+            name: user.name,
+            username: user.username,
+            avatar_url: user.avatar_url,
+            email_verified: None,
+            tenant: None,
+            raw: None,
+        }
+    }
+}
+
+/// GitLab OAuth provider implementation
+///
+/// This struct implements the OAuth provider interface for GitLab.com and
+/// self-hosted GitLab instances. It handles OAuth 2.0 authentication flow
+/// and user information retrieval from GitLab's REST API.
+///
+/// # Fields
+///
+/// * `client` - HTTP client for making API requests
+/// * `oauth_client` - Configured OAuth 2.0 client
+/// * `user_info_url` - GitLab's user info endpoint URL
+/// * `scopes` - OAuth scopes requested on authorization
+pub struct GitlabProvider {
+    /// HTTP client for API requests
+    client: HttpClient,
+    /// Configured OAuth client
+    oauth_client: OAuthClient,
+    /// GitLab user info endpoint URL
+    user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
+}
+
+impl GitlabProvider {
+    /// Creates a new GitLab OAuth provider instance
+    ///
+    /// This constructor creates a new GitLab provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_client` - The HTTP client shared by every provider
+    /// * `oauth_client` - The configured OAuth client for GitLab
+    /// * `user_info_url` - The URL for GitLab's user info endpoint
+    /// * `scopes` - Scopes configured in addition to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `GitlabProvider` instance
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        scopes: Option<Vec<String>>,
+    ) -> Self {
+        Self {
+            client: http_client,
+            oauth_client,
+            user_info_url,
+            scopes: merge_scopes(&["read_user"], scopes),
+        }
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for GitlabProvider {
+    /// Returns a reference to the OAuth client for GitLab
+    ///
+    /// # Returns
+    ///
+    /// Returns a reference to the configured `OAuthClient` instance
+    fn get_oauth_client(&self) -> &OAuthClient {
+        &self.oauth_client
+    }
+
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Returns the OAuth scopes required for GitLab
+    ///
+    /// GitLab requires the "read_user" scope to access the signed-in
+    /// user's profile through `/api/v4/user`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the required scopes followed by the configured ones
+    fn get_scopes(&self) -> Vec<String> {
+        self.scopes.clone()
+    }
+
+    /// Fetches user information from GitLab's `/api/v4/user` endpoint
+    ///
+    /// This method makes an authenticated request to GitLab to
+    /// retrieve the signed-in user's profile.
+    ///
+    /// # Arguments
+    ///
+    /// * `access_token` - The OAuth access token obtained from GitLab
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the user's identifier
+    /// or an error if the request fails
+    async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
+        let request = self
+            .client
+            .get(self.user_info_url.as_str())
+            .header("Authorization", format!("Bearer {}", access_token));
+
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|e| request_error("Failed to get user info from GitLab", e))?;
+
+        if !response.status().is_success() {
+            bail!("Failed to get user info from GitLab: {}", response.status());
+        }
+
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from GitLab", e))?;
+        parse_user_info(&user_data)
+    }
+}
+
+/// Extracts the user information from a GitLab `/api/v4/user` response
+///
+/// # Arguments
+///
+/// * `user_data` - The JSON body returned by the user endpoint
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` containing the user's identifier, or an
+/// error if both the id and username are missing or a field has an
+/// unexpected type
+fn parse_user_info(user_data: &serde_json::Value) -> Result<UserInfo> {
+    let user: GitlabUserResponse = parse_response("GitLab user info", user_data)?;
+    let user_info = UserInfo::from(user);
+    if user_info.id.is_empty() {
+        bail!("No id or username field in GitLab user info response");
+    }
+
+    Ok(UserInfo {
+        raw: Some(user_data.clone()),
+        ..user_info
+    })
+}
+
+/// Factory for creating GitLab OAuth provider instances
+///
+/// This struct implements the factory pattern for creating GitLab
+/// OAuth provider instances. It allows the application to create
+/// provider instances dynamically based on configuration.
+pub struct GitlabProviderFactory;
+
+impl OAuthProviderFactory for GitlabProviderFactory {
+    /// Creates a new GitLab OAuth provider instance
+    ///
+    /// This method creates a new GitLab provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, whose `scopes` are
+    ///   added to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created GitLab provider
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        Ok(Arc::new(GitlabProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.scopes.clone(),
+        )))
+    }
+
+    /// Derives the GitLab endpoints from the instance's base URL
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The provider's settings, whose `base_url` points to a
+    ///   self-hosted instance, GitLab.com without one
+    ///
+    /// # Returns
+    ///
+    /// Returns the authorization, token and user endpoints of the instance
+    fn default_endpoints(&self, settings: &OAuthSettings) -> Option<ProviderEndpoints> {
+        let base_url = settings
+            .base_url
+            .as_deref()
+            .unwrap_or(GITLAB_COM)
+            .trim_end_matches('/');
+
+        Some(ProviderEndpoints {
+            auth_url: format!("{}/oauth/authorize", base_url),
+            token_url: format!("{}/oauth/token", base_url),
+            user_info_url: format!("{}/api/v4/user", base_url),
+            jwks_url: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that GitLab.com endpoints are used without a base URL
+    #[test]
+    fn test_gitlab_com_endpoints() {
+        assert_eq!(
+            GitlabProviderFactory.default_endpoints(&OAuthSettings::default()),
+            Some(ProviderEndpoints {
+                auth_url: "https://gitlab.com/oauth/authorize".to_string(),
+                token_url: "https://gitlab.com/oauth/token".to_string(),
+                user_info_url: "https://gitlab.com/api/v4/user".to_string(),
+                jwks_url: None,
+            })
+        );
+    }
+
+    /// Tests that the endpoints of a self-hosted instance are derived from its base URL
+    #[test]
+    fn test_self_hosted_endpoints() {
+        assert_eq!(
+            GitlabProviderFactory.default_endpoints(&OAuthSettings {
+                base_url: Some("https://git.example.com/gitlab/".to_string()),
+                ..OAuthSettings::default()
+            }),
+            Some(ProviderEndpoints {
+                auth_url: "https://git.example.com/gitlab/oauth/authorize".to_string(),
+                token_url: "https://git.example.com/gitlab/oauth/token".to_string(),
+                user_info_url: "https://git.example.com/gitlab/api/v4/user".to_string(),
+                jwks_url: None,
+            })
+        );
+    }
+
+    /// Tests that the user id is extracted from a recorded user response
+    #[test]
+    fn test_parse_user_response() {
+        let user_data = serde_json::json!({
+            "id": 1,
+            "username": "john_smith",
+            "name": "John Smith",
+            "state": "active",
+            "locked": false,
+            "avatar_url": "http://localhost:3000/uploads/user/avatar/1/cd8.jpeg",
+            "web_url": "http://localhost:3000/john_smith",
+            "email": "john@example.com"
+        });
+
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "1");
+        assert_eq!(user_info.provider, "gitlab");
+    }
+
+    /// Tests that fields the server does not read are ignored, and that a
+    /// field with an unexpected type is reported with its path
+    #[test]
+    fn test_response_fields() {
+        let user_data = serde_json::json!({ "id": 1, "username": "john_smith", "state": "active", "identities": [{ "provider": "github" }] });
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "1");
+
+        let user_data = serde_json::json!({ "id": 1, "username": "john_smith", "avatar_url": 42 });
+        let error = parse_user_info(&user_data).unwrap_err().to_string();
+        assert!(
+            error.starts_with("Invalid GitLab user info response at avatar_url:"),
+            "{}",
+            error
+        );
+    }
+}
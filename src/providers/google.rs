@@ -1,13 +1,62 @@
 use crate::{
-    primitives::UserInfo,
-    traits::{OAuthProvider, OAuthProviderFactory},
-    types::OAuthClient,
+    oidc::IdTokenVerifier,
+    primitives::{ProviderContext, ProviderDisplay, UserInfo},
+    providers::{flag, merge_scopes, non_empty, parse_response},
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
 };
 use axum::async_trait;
 use eyre::{bail, Result};
-use reqwest::{Client, Url};
+use reqwest::Url;
+use serde::Deserialize;
 use std::sync::Arc;
 
+/// Issuer of Google ID tokens
+const GOOGLE_ISSUER: &str = "https://accounts.google.com";
+
+/// Google user info response, or the claims of a Google ID token
+///
+/// # Fields
+///
+/// * `email` - The user's email address, identifying the user
+/// * `email_verified` - Whether Google verified the email
+/// * `name` - The user's full name
+/// * `picture` - URL of the user's profile picture
+#[derive(Debug, Deserialize)]
+struct GoogleUserInfoResponse {
+    /// The user's email address
+    email: String,
+    /// Whether Google verified the email
+    #[serde(default, deserialize_with = "flag")]
+    email_verified: Option<bool>,
+    /// The user's full name
+    #[serde(default, deserialize_with = "non_empty")]
+    name: Option<String>,
+    /// URL of the user's profile picture
+    #[serde(default, deserialize_with = "non_empty")]
+    picture: Option<String>,
+}
+
+impl From<GoogleUserInfoResponse> for UserInfo {
+    /// Maps a Google user to the user information
+    ///
+    /// Google identifies the user by their email, so an email without
+    /// `email_verified = true` is reported as unverified.
+    fn from(user: GoogleUserInfoResponse) -> Self {
+        UserInfo {
+            id: user.email.clone(),
+            provider: "google".to_string(),
+            email: Some(user.email),
+            name: user.name,
+            username: None,
+            avatar_url: user.picture,
+            email_verified: Some(user.email_verified == Some(true)),
+            tenant: None,
+            raw: None,
+        }
+    }
+}
+
 /// Google OAuth provider implementation
 ///
 /// This struct implements the OAuth provider interface for Google.
@@ -19,13 +68,19 @@ use std::sync::Arc;
 /// * `client` - HTTP client for making API requests
 /// * `oauth_client` - Configured OAuth 2.0 client
 /// * `user_info_url` - Google's user info endpoint URL
+/// * `scopes` - OAuth scopes requested on authorization
+/// * `id_token_verifier` - Verifier of the ID tokens, when a `jwks_url` is configured
 pub struct GoogleProvider {
     /// HTTP client for API requests
-    client: Client,
+    client: HttpClient,
     /// Configured OAuth client
     oauth_client: OAuthClient,
     /// Google user info endpoint URL
     user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
+    /// Verifier of the ID tokens
+    id_token_verifier: Option<IdTokenVerifier>,
 }
 
 impl GoogleProvider {
@@ -36,17 +91,33 @@ impl GoogleProvider {
     ///
     /// # Arguments
     ///
+    /// * `http_client` - The HTTP client shared by every provider
     /// * `oauth_client` - The configured OAuth client for Google
     /// * `user_info_url` - The URL for Google's user info endpoint
+    /// * `scopes` - Scopes configured in addition to the required ones
+    /// * `id_token_verifier` - Verifier of the ID tokens, `None` to use the user info endpoint
     ///
     /// # Returns
     ///
     /// Returns a new `GoogleProvider` instance
-    pub fn new(oauth_client: OAuthClient, user_info_url: Url) -> Self {
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        scopes: Option<Vec<String>>,
+        id_token_verifier: Option<IdTokenVerifier>,
+    ) -> Self {
+        // Google only issues an ID token for the openid scope
+        let required: &[&str] = match id_token_verifier {
+            Some(_) => &["openid", "email"],
+            None => &["email"],
+        };
         Self {
-            client: Client::new(),
+            client: http_client,
             oauth_client,
             user_info_url,
+            scopes: merge_scopes(required, scopes),
+            id_token_verifier,
         }
     }
 }
@@ -62,16 +133,84 @@ impl OAuthProvider for GoogleProvider {
         &self.oauth_client
     }
 
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
     /// Returns the OAuth scopes required for Google
     ///
     /// Google requires the "email" scope to access the user's email address
-    /// from their profile information.
+    /// from their profile information, and "openid" when ID tokens are verified.
     ///
     /// # Returns
     ///
-    /// Returns a vector containing the "email" scope
+    /// Returns the required scopes followed by the configured ones
     fn get_scopes(&self) -> Vec<String> {
-        vec!["email".to_string()]
+        self.scopes.clone()
+    }
+
+    /// Returns the Google brand color and logo
+    ///
+    /// # Returns
+    ///
+    /// Returns the branding of the Google sign-in button
+    fn display_metadata(&self) -> Option<ProviderDisplay> {
+        Some(ProviderDisplay {
+            label: "Google",
+            color: "#4285f4",
+            icon: r#"<path fill="currentColor" d="M22.56 12.25c0-.78-.07-1.53-.2-2.25H12v4.26h5.92c-.26 1.37-1.04 2.53-2.21 3.31v2.77h3.57c2.08-1.92 3.28-4.74 3.28-8.09z"/><path fill="currentColor" d="M12 23c2.97 0 5.46-.98 7.28-2.66l-3.57-2.77c-.98.66-2.23 1.06-3.71 1.06-2.86 0-5.29-1.93-6.16-4.53H2.18v2.84C3.99 20.53 7.7 23 12 23z"/><path fill="currentColor" d="M5.84 14.09c-.22-.66-.35-1.36-.35-2.09s.13-1.43.35-2.09V7.07H2.18C1.43 8.55 1 10.22 1 12s.43 3.45 1.18 4.93l2.85-2.22.81-.62z"/><path fill="currentColor" d="M12 5.38c1.62 0 3.06.56 4.21 1.64l3.15-3.15C17.45 2.09 14.97 1 12 1 7.7 1 3.99 3.47 2.18 7.07l3.66 2.84c.87-2.6 3.3-4.53 6.16-4.53z"/>"#,
+        })
+    }
+
+    /// Opts in to the device authorization grant
+    ///
+    /// Google issues device codes to clients of the "TV and Limited Input" type.
+    ///
+    /// # Returns
+    ///
+    /// Always returns `true`
+    fn supports_device_flow(&self) -> bool {
+        true
+    }
+
+    /// Returns the extra authorization parameters for Google
+    ///
+    /// Google only issues a refresh token for `access_type=offline`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the `access_type=offline` parameter
+    fn get_extra_auth_params(&self) -> Vec<(String, String)> {
+        vec![("access_type".to_string(), "offline".to_string())]
+    }
+
+    /// Returns the verifier of the ID tokens
+    ///
+    /// # Returns
+    ///
+    /// Returns the verifier when a `jwks_url` is configured
+    fn id_token_verifier(&self) -> Option<&IdTokenVerifier> {
+        self.id_token_verifier.as_ref()
+    }
+
+    /// Reads the user information from the verified ID token
+    ///
+    /// Google ID tokens carry the same claims as the user info response.
+    ///
+    /// # Arguments
+    ///
+    /// * `claims` - The claims of the verified ID token
+    ///
+    /// # Returns
+    ///
+    /// Returns the user information identified by the user's email address
+    fn user_info_from_id_token(&self, claims: &serde_json::Value) -> Option<Result<UserInfo>> {
+        Some(parse_user_info(claims))
     }
 
     /// Fetches user information from Google's user info endpoint
@@ -86,38 +225,50 @@ impl OAuthProvider for GoogleProvider {
     ///
     /// # Returns
     ///
-    /// Returns `Result<UserInfo>` containing the user's email address
-    /// or an error if the request fails
+    /// Returns `Result<UserInfo>` containing the user's email address and
+    /// profile, or an error if the request fails
     async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
         let request = self
             .client
             .get(self.user_info_url.as_str())
             .header("Authorization", format!("Bearer {}", access_token));
 
-        let response = request
-            .send()
+        let response = self
+            .client
+            .send(request)
             .await
-            .map_err(|e| eyre::eyre!("Failed to get user info from Google: {}", e))?;
+            .map_err(|e| request_error("Failed to get user info from Google", e))?;
 
         if !response.status().is_success() {
             bail!("Failed to get user info from Google: {}", response.status());
         }
 
-        let user_data: serde_json::Value = response.json().await?;
-
-        let email = user_data
-            .get("email")
-            .and_then(|v| v.as_str())
-            .ok_or_else(|| eyre::eyre!("No email field in Google user info response"))?
-            .to_string();
-
-        Ok(UserInfo {
-            id: email,
-            provider: "google".to_string(),
-        })
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from Google", e))?;
+        parse_user_info(&user_data)
     }
 }
 
+/// Extracts the user information from a Google user info response
+///
+/// # Arguments
+///
+/// * `user_data` - The JSON body returned by the user info endpoint
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` identified by the user's email address,
+/// or an error if the email is missing or a field has an unexpected type
+fn parse_user_info(user_data: &serde_json::Value) -> Result<UserInfo> {
+    let user: GoogleUserInfoResponse = parse_response("Google user info", user_data)?;
+    Ok(UserInfo {
+        raw: Some(user_data.clone()),
+        ..user.into()
+    })
+}
+
 /// Factory for creating Google OAuth provider instances
 ///
 /// This struct implements the factory pattern for creating Google
@@ -133,13 +284,96 @@ impl OAuthProviderFactory for GoogleProviderFactory {
     ///
     /// # Arguments
     ///
-    /// * `oauth_client` - The configured OAuth client for Google
-    /// * `user_info_url` - The URL for Google's user info endpoint
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, whose `scopes` are
+    ///   added to the required ones and whose `jwks_url` enables ID token verification
     ///
     /// # Returns
     ///
-    /// Returns an `Arc<dyn OAuthProvider>` containing the created Google provider
-    fn create(&self, oauth_client: OAuthClient, user_info_url: Url) -> Arc<dyn OAuthProvider> {
-        Arc::new(GoogleProvider::new(oauth_client, user_info_url))
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created Google provider,
+    /// or an error if the `jwks_url` is invalid
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        Ok(Arc::new(GoogleProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.scopes.clone(),
+            IdTokenVerifier::from_settings(settings, Some(GOOGLE_ISSUER))?,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that the profile is extracted from a recorded user info response
+    #[test]
+    fn test_parse_user_info_response() {
+        let user_data = serde_json::json!({
+            "sub": "110169484474386276334",
+            "name": "Jane Doe",
+            "given_name": "Jane",
+            "family_name": "Doe",
+            "picture": "https://lh3.googleusercontent.com/a/ACg8ocJ-example=s96-c",
+            "email": "jane.doe@gmail.com",
+            "email_verified": true
+        });
+
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "jane.doe@gmail.com");
+        assert_eq!(user_info.provider, "google");
+        assert_eq!(user_info.email.as_deref(), Some("jane.doe@gmail.com"));
+        assert_eq!(user_info.name.as_deref(), Some("Jane Doe"));
+        assert_eq!(
+            user_info.avatar_url.as_deref(),
+            Some("https://lh3.googleusercontent.com/a/ACg8ocJ-example=s96-c")
+        );
+        assert_eq!(user_info.email_verified, Some(true));
+        assert_eq!(user_info.raw, Some(user_data));
+    }
+
+    /// Tests that unverified and unflagged emails are reported as unverified
+    #[test]
+    fn test_unverified_email() {
+        let user_data = serde_json::json!({
+            "sub": "110169484474386276334",
+            "email": "jane.doe@example.com",
+            "email_verified": false
+        });
+        assert_eq!(
+            parse_user_info(&user_data).unwrap().email_verified,
+            Some(false)
+        );
+
+        let user_data = serde_json::json!({ "email": "jane.doe@example.com" });
+        assert_eq!(
+            parse_user_info(&user_data).unwrap().email_verified,
+            Some(false)
+        );
+    }
+
+    /// Tests that fields the server does not read are ignored, and that a
+    /// field with an unexpected type is reported with its path
+    #[test]
+    fn test_response_fields() {
+        let user_data = serde_json::json!({ "email": "user@example.com", "email_verified": true, "sub": "110169484474386276334", "locale": "en", "hd": "example.com" });
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "user@example.com");
+
+        let user_data =
+            serde_json::json!({ "email": "user@example.com", "email_verified": "maybe" });
+        let error = parse_user_info(&user_data).unwrap_err().to_string();
+        assert!(
+            error.starts_with("Invalid Google user info response at email_verified:"),
+            "{}",
+            error
+        );
     }
 }
@@ -0,0 +1,401 @@
+use crate::{
+    primitives::{ProviderContext, ProviderEndpoints, UserInfo},
+    providers::{merge_scopes, oidc_user_info, parse_response, OidcUserInfoResponse},
+    settings::OAuthSettings,
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
+};
+use axum::async_trait;
+use eyre::{bail, Result};
+use reqwest::Url;
+use std::sync::Arc;
+
+/// Keycloak OAuth provider implementation
+///
+/// This struct implements the OAuth provider interface for a Keycloak
+/// realm. It handles OAuth 2.0 authentication flow and user information
+/// retrieval from the realm's OpenID Connect userinfo endpoint.
+///
+/// # Fields
+///
+/// * `client` - HTTP client for making API requests
+/// * `oauth_client` - Configured OAuth 2.0 client
+/// * `user_info_url` - Keycloak's user info endpoint URL
+/// * `scopes` - OAuth scopes requested on authorization
+/// * `end_session_url` - The realm's logout endpoint URL, if known
+pub struct KeycloakProvider {
+    /// HTTP client for API requests
+    client: HttpClient,
+    /// Configured OAuth client
+    oauth_client: OAuthClient,
+    /// Keycloak user info endpoint URL
+    user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
+    /// The realm's logout endpoint URL
+    end_session_url: Option<Url>,
+}
+
+impl KeycloakProvider {
+    /// Creates a new Keycloak OAuth provider instance
+    ///
+    /// This constructor creates a new Keycloak provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_client` - The HTTP client shared by every provider
+    /// * `oauth_client` - The configured OAuth client for Keycloak
+    /// * `user_info_url` - The URL for Keycloak's user info endpoint
+    /// * `scopes` - Scopes configured in addition to the required ones
+    /// * `end_session_url` - The realm's logout endpoint URL, if known
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `KeycloakProvider` instance
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        scopes: Option<Vec<String>>,
+        end_session_url: Option<Url>,
+    ) -> Self {
+        Self {
+            client: http_client,
+            oauth_client,
+            user_info_url,
+            scopes: merge_scopes(&["openid", "profile", "email"], scopes),
+            end_session_url,
+        }
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for KeycloakProvider {
+    /// Returns a reference to the OAuth client for Keycloak
+    ///
+    /// # Returns
+    ///
+    /// Returns a reference to the configured `OAuthClient` instance
+    fn get_oauth_client(&self) -> &OAuthClient {
+        &self.oauth_client
+    }
+
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Returns the OAuth scopes required for Keycloak
+    ///
+    /// Keycloak requires the "openid", "profile" and "email" scopes to access
+    /// the user's identity through its userinfo endpoint.
+    ///
+    /// # Returns
+    ///
+    /// Returns the required scopes followed by the configured ones
+    fn get_scopes(&self) -> Vec<String> {
+        self.scopes.clone()
+    }
+
+    /// Returns the realm's logout endpoint
+    ///
+    /// # Returns
+    ///
+    /// Returns the `/logout` endpoint derived from `base_url` and `realm`,
+    /// `None` when the endpoints were configured explicitly
+    fn get_end_session_url(&self) -> Option<&Url> {
+        self.end_session_url.as_ref()
+    }
+
+    /// Fetches user information from Keycloak's OpenID Connect userinfo endpoint
+    ///
+    /// This method makes an authenticated request to Keycloak to
+    /// retrieve the signed-in user's profile.
+    ///
+    /// # Arguments
+    ///
+    /// * `access_token` - The OAuth access token obtained from Keycloak
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the user's identifier
+    /// or an error if the request fails
+    async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
+        let request = self
+            .client
+            .get(self.user_info_url.as_str())
+            .header("Authorization", format!("Bearer {}", access_token));
+
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|e| request_error("Failed to get user info from Keycloak", e))?;
+
+        if !response.status().is_success() {
+            bail!(
+                "Failed to get user info from Keycloak: {}",
+                response.status()
+            );
+        }
+
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from Keycloak", e))?;
+        parse_user_info(&user_data)
+    }
+}
+
+/// Extracts the user information from an Keycloak userinfo response
+///
+/// The `sub` claim is used as the user's identifier, falling back to the
+/// `preferred_username` and then the `email` claim when it is missing.
+///
+/// # Arguments
+///
+/// * `user_data` - The JSON body returned by the userinfo endpoint
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` containing the user's identifier, or an
+/// error if every claim is missing or a claim has an unexpected type
+fn parse_user_info(user_data: &serde_json::Value) -> Result<UserInfo> {
+    let claims: OidcUserInfoResponse = parse_response("Keycloak user info", user_data)?;
+    let id = claims
+        .sub
+        .clone()
+        .or_else(|| claims.preferred_username.clone())
+        .or_else(|| claims.email.clone())
+        .ok_or_else(|| {
+            eyre::eyre!("No sub, preferred_username or email field in Keycloak user info response")
+        })?;
+
+    Ok(oidc_user_info(id, "keycloak", claims, user_data))
+}
+
+/// Factory for creating Keycloak OAuth provider instances
+///
+/// This struct implements the factory pattern for creating Keycloak
+/// OAuth provider instances. It allows the application to create
+/// provider instances dynamically based on configuration.
+pub struct KeycloakProviderFactory;
+
+/// Returns the base of a realm's OpenID Connect endpoints
+///
+/// # Arguments
+///
+/// * `settings` - The provider's settings, with the `base_url` and `realm`
+///
+/// # Returns
+///
+/// Returns `{base_url}/realms/{realm}/protocol/openid-connect`, or `None`
+/// unless both settings are present
+fn realm_url(settings: &OAuthSettings) -> Option<String> {
+    let base_url = settings.base_url.as_deref()?.trim_end_matches('/');
+    let realm = settings.realm.as_deref()?;
+    Some(format!(
+        "{}/realms/{}/protocol/openid-connect",
+        base_url, realm
+    ))
+}
+
+impl OAuthProviderFactory for KeycloakProviderFactory {
+    /// Creates a new Keycloak OAuth provider instance
+    ///
+    /// This method creates a new Keycloak provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, whose `scopes` are
+    ///   added to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created Keycloak provider,
+    /// or an error if the derived logout URL is invalid
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        let end_session_url = realm_url(settings)
+            .map(|realm_url| Url::parse(&format!("{}/logout", realm_url)))
+            .transpose()?;
+
+        Ok(Arc::new(KeycloakProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.scopes.clone(),
+            end_session_url,
+        )))
+    }
+
+    /// Derives the Keycloak endpoints from the server's base URL and realm
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The provider's settings, with the `base_url` and `realm`
+    ///
+    /// # Returns
+    ///
+    /// Returns the realm's authorization, token and userinfo endpoints, or
+    /// `None` unless both settings are present
+    fn default_endpoints(&self, settings: &OAuthSettings) -> Option<ProviderEndpoints> {
+        let realm_url = realm_url(settings)?;
+
+        Some(ProviderEndpoints {
+            auth_url: format!("{}/auth", realm_url),
+            token_url: format!("{}/token", realm_url),
+            user_info_url: format!("{}/userinfo", realm_url),
+            jwks_url: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::BareOAuthClient;
+    use oauth2::{AuthUrl, ClientId, TokenUrl};
+
+    fn settings(base_url: Option<&str>, realm: Option<&str>) -> OAuthSettings {
+        OAuthSettings {
+            base_url: base_url.map(str::to_string),
+            realm: realm.map(str::to_string),
+            ..OAuthSettings::default()
+        }
+    }
+
+    /// Tests that the realm's endpoints are derived from the base URL and realm
+    #[test]
+    fn test_endpoints_derived_from_realm() {
+        let expected = Some(ProviderEndpoints {
+            auth_url: "https://sso.example.com/realms/staff/protocol/openid-connect/auth"
+                .to_string(),
+            token_url: "https://sso.example.com/realms/staff/protocol/openid-connect/token"
+                .to_string(),
+            user_info_url: "https://sso.example.com/realms/staff/protocol/openid-connect/userinfo"
+                .to_string(),
+            jwks_url: None,
+        });
+        for base_url in ["https://sso.example.com", "https://sso.example.com/"] {
+            assert_eq!(
+                KeycloakProviderFactory.default_endpoints(&settings(Some(base_url), Some("staff"))),
+                expected
+            );
+        }
+
+        // Keycloak before 17 serves everything under /auth
+        assert_eq!(
+            KeycloakProviderFactory
+                .default_endpoints(&settings(
+                    Some("https://sso.example.com/auth"),
+                    Some("staff")
+                ))
+                .unwrap()
+                .token_url,
+            "https://sso.example.com/auth/realms/staff/protocol/openid-connect/token"
+        );
+    }
+
+    /// Tests that nothing is derived unless both the base URL and realm are set
+    #[test]
+    fn test_endpoints_require_base_url_and_realm() {
+        for (base_url, realm) in [
+            (Some("https://sso.example.com"), None),
+            (None, Some("staff")),
+            (None, None),
+        ] {
+            assert_eq!(
+                KeycloakProviderFactory.default_endpoints(&settings(base_url, realm)),
+                None
+            );
+        }
+    }
+
+    /// Tests that the logout endpoint is only exposed when derived from the realm
+    #[test]
+    fn test_end_session_url() {
+        let client = BareOAuthClient::new(ClientId::new("client".to_string()))
+            .set_auth_uri(AuthUrl::new("https://sso.example.com/auth".to_string()).unwrap())
+            .set_token_uri(TokenUrl::new("https://sso.example.com/token".to_string()).unwrap())
+            .set_revocation_url_option(None)
+            .set_device_authorization_url_option(None);
+        let user_info_url = Url::parse("https://sso.example.com/userinfo").unwrap();
+
+        let provider = KeycloakProviderFactory
+            .create(ProviderContext {
+                name: "keycloak",
+                http_client: &HttpClient::default(),
+                oauth_client: client.clone().into(),
+                user_info_url: user_info_url.clone(),
+                settings: &settings(Some("https://sso.example.com"), Some("staff")),
+            })
+            .unwrap();
+        assert_eq!(
+            provider.get_end_session_url().map(Url::as_str),
+            Some("https://sso.example.com/realms/staff/protocol/openid-connect/logout")
+        );
+
+        let provider = KeycloakProviderFactory
+            .create(ProviderContext {
+                name: "keycloak",
+                http_client: &HttpClient::default(),
+                oauth_client: client.into(),
+                user_info_url,
+                settings: &OAuthSettings::default(),
+            })
+            .unwrap();
+        assert_eq!(provider.get_end_session_url(), None);
+    }
+
+    /// Tests that the user id is extracted from a recorded userinfo response
+    #[test]
+    fn test_parse_userinfo_response() {
+        let user_data = serde_json::json!({
+            "sub": "f1c2b6a0-3c1d-4f9e-9b7e-2d5c8a1e4f60",
+            "email_verified": true,
+            "name": "Jane Doe",
+            "preferred_username": "jdoe",
+            "given_name": "Jane",
+            "family_name": "Doe",
+            "email": "jdoe@example.com"
+        });
+
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "f1c2b6a0-3c1d-4f9e-9b7e-2d5c8a1e4f60");
+        assert_eq!(user_info.provider, "keycloak");
+
+        let user_info =
+            parse_user_info(&serde_json::json!({ "preferred_username": "jdoe" })).unwrap();
+        assert_eq!(user_info.id, "jdoe");
+    }
+
+    /// Tests that fields the server does not read are ignored, and that a
+    /// field with an unexpected type is reported with its path
+    #[test]
+    fn test_response_fields() {
+        let user_data = serde_json::json!({ "sub": "248289761001", "preferred_username": "jdoe", "realm_access": { "roles": ["admin"] }, "groups": ["/staff"] });
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "248289761001");
+
+        let user_data = serde_json::json!({ "sub": "248289761001", "email": ["jdoe@example.com"] });
+        let error = parse_user_info(&user_data).unwrap_err().to_string();
+        assert!(
+            error.starts_with("Invalid Keycloak user info response at email:"),
+            "{}",
+            error
+        );
+    }
+}
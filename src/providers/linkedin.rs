@@ -0,0 +1,273 @@
+use crate::{
+    primitives::{ProviderContext, UserInfo},
+    providers::{merge_scopes, oidc_user_info, parse_response, OidcUserInfoResponse},
+    settings::TokenAuthType,
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
+};
+use axum::async_trait;
+use eyre::{bail, Result};
+use reqwest::Url;
+use std::sync::Arc;
+
+/// LinkedIn OAuth provider implementation
+///
+/// This struct implements the OAuth provider interface for LinkedIn's
+/// OpenID Connect product. It handles OAuth 2.0 authentication flow and
+/// user information retrieval from LinkedIn's userinfo endpoint. LinkedIn
+/// rejects PKCE on its token endpoint, so its flows run without it.
+///
+/// # Fields
+///
+/// * `client` - HTTP client for making API requests
+/// * `oauth_client` - Configured OAuth 2.0 client
+/// * `user_info_url` - LinkedIn's user info endpoint URL
+/// * `scopes` - OAuth scopes requested on authorization
+pub struct LinkedinProvider {
+    /// HTTP client for API requests
+    client: HttpClient,
+    /// Configured OAuth client
+    oauth_client: OAuthClient,
+    /// LinkedIn user info endpoint URL
+    user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
+}
+
+impl LinkedinProvider {
+    /// Creates a new LinkedIn OAuth provider instance
+    ///
+    /// This constructor creates a new LinkedIn provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_client` - The HTTP client shared by every provider
+    /// * `oauth_client` - The configured OAuth client for LinkedIn
+    /// * `user_info_url` - The URL for LinkedIn's user info endpoint
+    /// * `scopes` - Scopes configured in addition to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `LinkedinProvider` instance
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        scopes: Option<Vec<String>>,
+    ) -> Self {
+        Self {
+            client: http_client,
+            oauth_client,
+            user_info_url,
+            scopes: merge_scopes(&["openid", "profile", "email"], scopes),
+        }
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for LinkedinProvider {
+    /// Returns a reference to the OAuth client for LinkedIn
+    ///
+    /// # Returns
+    ///
+    /// Returns a reference to the configured `OAuthClient` instance
+    fn get_oauth_client(&self) -> &OAuthClient {
+        &self.oauth_client
+    }
+
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Returns the OAuth scopes required for LinkedIn
+    ///
+    /// LinkedIn requires the "openid", "profile" and "email" scopes to access
+    /// the user's identity through its userinfo endpoint.
+    ///
+    /// # Returns
+    ///
+    /// Returns the required scopes followed by the configured ones
+    fn get_scopes(&self) -> Vec<String> {
+        self.scopes.clone()
+    }
+
+    /// Opts out of PKCE
+    ///
+    /// LinkedIn's token endpoint rejects requests carrying a `code_verifier`.
+    ///
+    /// # Returns
+    ///
+    /// Always returns `false`
+    fn supports_pkce(&self) -> bool {
+        false
+    }
+
+    /// Fetches user information from LinkedIn's OpenID Connect userinfo endpoint
+    ///
+    /// This method makes an authenticated request to LinkedIn to
+    /// retrieve the signed-in user's profile.
+    ///
+    /// # Arguments
+    ///
+    /// * `access_token` - The OAuth access token obtained from LinkedIn
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the user's identifier
+    /// or an error if the request fails
+    async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
+        let request = self
+            .client
+            .get(self.user_info_url.as_str())
+            .header("Authorization", format!("Bearer {}", access_token));
+
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|e| request_error("Failed to get user info from LinkedIn", e))?;
+
+        if !response.status().is_success() {
+            bail!(
+                "Failed to get user info from LinkedIn: {}",
+                response.status()
+            );
+        }
+
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from LinkedIn", e))?;
+        parse_user_info(&user_data)
+    }
+}
+
+/// Extracts the user information from a LinkedIn userinfo response
+///
+/// The `sub` claim is used as the user's identifier, falling back to the
+/// `email` claim when it is missing.
+///
+/// # Arguments
+///
+/// * `user_data` - The JSON body returned by the userinfo endpoint
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` containing the user's identifier, or an
+/// error if both claims are missing or a claim has an unexpected type
+fn parse_user_info(user_data: &serde_json::Value) -> Result<UserInfo> {
+    let claims: OidcUserInfoResponse = parse_response("LinkedIn user info", user_data)?;
+    let id = claims
+        .sub
+        .clone()
+        .or_else(|| claims.email.clone())
+        .ok_or_else(|| eyre::eyre!("No sub or email field in LinkedIn user info response"))?;
+
+    Ok(oidc_user_info(id, "linkedin", claims, user_data))
+}
+
+/// Factory for creating LinkedIn OAuth provider instances
+///
+/// This struct implements the factory pattern for creating LinkedIn
+/// OAuth provider instances. It allows the application to create
+/// provider instances dynamically based on configuration.
+pub struct LinkedinProviderFactory;
+
+impl OAuthProviderFactory for LinkedinProviderFactory {
+    /// Creates a new LinkedIn OAuth provider instance
+    ///
+    /// This method creates a new LinkedIn provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, whose `scopes` are
+    ///   added to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created LinkedIn provider
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        Ok(Arc::new(LinkedinProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.scopes.clone(),
+        )))
+    }
+
+    /// Returns how LinkedIn expects the client credentials
+    ///
+    /// LinkedIn expects the client credentials in the request body.
+    ///
+    /// # Returns
+    ///
+    /// Returns `TokenAuthType::RequestBody`
+    fn default_auth_type(&self) -> TokenAuthType {
+        TokenAuthType::RequestBody
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that the user id is extracted from a recorded userinfo response
+    #[test]
+    fn test_parse_userinfo_response() {
+        let user_data = serde_json::json!({
+            "sub": "782bbtaQ",
+            "name": "John Doe",
+            "given_name": "John",
+            "family_name": "Doe",
+            "picture": "https://media.licdn-ei.com/dms/image/C5F03AQHqK8v7tB1HCQ/profile-displayphoto-shrink_100_100/0/",
+            "locale": "en-US",
+            "email": "doe@email.com",
+            "email_verified": true
+        });
+
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "782bbtaQ");
+        assert_eq!(user_info.provider, "linkedin");
+    }
+
+    /// Tests that the email is used when the sub claim is missing
+    #[test]
+    fn test_parse_falls_back_to_email() {
+        let user_info = parse_user_info(&serde_json::json!({ "email": "doe@email.com" })).unwrap();
+        assert_eq!(user_info.id, "doe@email.com");
+
+        assert!(parse_user_info(&serde_json::json!({ "name": "John Doe" })).is_err());
+    }
+
+    /// Tests that fields the server does not read are ignored, and that a
+    /// field with an unexpected type is reported with its path
+    #[test]
+    fn test_response_fields() {
+        let user_data = serde_json::json!({ "sub": "782bbtaQ", "name": "John Doe", "locale": { "country": "US", "language": "en" } });
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "782bbtaQ");
+
+        let user_data =
+            serde_json::json!({ "sub": "782bbtaQ", "name": { "localized": "John Doe" } });
+        let error = parse_user_info(&user_data).unwrap_err().to_string();
+        assert!(
+            error.starts_with("Invalid LinkedIn user info response at name:"),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use crate::{
+    primitives::{ProviderContext, UserInfo},
+    providers::{merge_scopes, non_empty, parse_response},
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
+};
+use axum::async_trait;
+use eyre::{bail, Result};
+use reqwest::Url;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Microsoft Graph `/me` response
+///
+/// # Fields
+///
+/// * `id` - Object id of the user
+/// * `mail` - The user's email, unset for accounts without a mailbox
+/// * `display_name` - The user's display name
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MicrosoftUserResponse {
+    /// Object id of the user
+    id: String,
+    /// The user's email
+    #[serde(default, deserialize_with = "non_empty")]
+    mail: Option<String>,
+    /// The user's display name
+    #[serde(default, deserialize_with = "non_empty")]
+    display_name: Option<String>,
+}
+
+impl From<MicrosoftUserResponse> for UserInfo {
+    /// Maps a Microsoft Graph user to the user information
+    ///
+    /// The object `id` is used as the user's identifier, since it is stable
+    /// across renames, unlike `userPrincipalName` and `mail`.
+    fn from(user: MicrosoftUserResponse) -> Self {
+        UserInfo {
+            id: user.id,
+            provider: "microsoft".to_string(),
+            email: user.mail,
+            name: user.display_name,
+            username: None,
+            avatar_url: None,
+            email_verified: None,
+            tenant: None,
+            raw: None,
+        }
+    }
+}
+
+/// Microsoft OAuth provider implementation
+///
+/// This struct implements the OAuth provider interface for Microsoft
+/// (Entra ID). It handles OAuth 2.0 authentication flow and user
+/// information retrieval from Microsoft Graph. The authorization and token
+/// URLs are tenant-specific and taken from the configuration as-is.
+///
+/// # Fields
+///
+/// * `client` - HTTP client for making API requests
+/// * `oauth_client` - Configured OAuth 2.0 client
+/// * `user_info_url` - Microsoft Graph's user info endpoint URL
+/// * `scopes` - OAuth scopes requested on authorization
+pub struct MicrosoftProvider {
+    /// HTTP client for API requests
+    client: HttpClient,
+    /// Configured OAuth client
+    oauth_client: OAuthClient,
+    /// Microsoft user info endpoint URL
+    user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
+}
+
+impl MicrosoftProvider {
+    /// Creates a new Microsoft OAuth provider instance
+    ///
+    /// This constructor creates a new Microsoft provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_client` - The HTTP client shared by every provider
+    /// * `oauth_client` - The configured OAuth client for Microsoft
+    /// * `user_info_url` - The URL for Microsoft Graph's user info endpoint
+    /// * `scopes` - Scopes configured in addition to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `MicrosoftProvider` instance
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        scopes: Option<Vec<String>>,
+    ) -> Self {
+        Self {
+            client: http_client,
+            oauth_client,
+            user_info_url,
+            scopes: merge_scopes(&["openid", "email", "User.Read"], scopes),
+        }
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for MicrosoftProvider {
+    /// Returns a reference to the OAuth client for Microsoft
+    ///
+    /// # Returns
+    ///
+    /// Returns a reference to the configured `OAuthClient` instance
+    fn get_oauth_client(&self) -> &OAuthClient {
+        &self.oauth_client
+    }
+
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Returns the OAuth scopes required for Microsoft
+    ///
+    /// Microsoft requires the "openid", "email", "User.Read" scope to access the user's
+    /// basic profile information including their username.
+    ///
+    /// # Returns
+    ///
+    /// Returns the required scopes followed by the configured ones
+    fn get_scopes(&self) -> Vec<String> {
+        self.scopes.clone()
+    }
+
+    /// Opts in to the device authorization grant
+    ///
+    /// The Microsoft identity platform issues device codes to public clients.
+    ///
+    /// # Returns
+    ///
+    /// Always returns `true`
+    fn supports_device_flow(&self) -> bool {
+        true
+    }
+
+    /// Fetches user information from Microsoft Graph's `/me` endpoint
+    ///
+    /// This method makes an authenticated request to Microsoft Graph to
+    /// retrieve the signed-in user's profile.
+    ///
+    /// # Arguments
+    ///
+    /// * `access_token` - The OAuth access token obtained from Microsoft
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the user's object id
+    /// or an error if the request fails
+    async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
+        let request = self
+            .client
+            .get(self.user_info_url.as_str())
+            .header("Authorization", format!("Bearer {}", access_token));
+
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|e| request_error("Failed to get user info from Microsoft", e))?;
+
+        if !response.status().is_success() {
+            bail!(
+                "Failed to get user info from Microsoft: {}",
+                response.status()
+            );
+        }
+
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from Microsoft", e))?;
+        parse_user_info(&user_data)
+    }
+}
+
+/// Extracts the user information from a Microsoft Graph `/me` response
+///
+/// # Arguments
+///
+/// * `user_data` - The JSON body returned by Microsoft Graph
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` containing the user's object id, or an error
+/// if the field is missing or a field has an unexpected type
+fn parse_user_info(user_data: &serde_json::Value) -> Result<UserInfo> {
+    let user: MicrosoftUserResponse = parse_response("Microsoft user info", user_data)?;
+    Ok(UserInfo {
+        raw: Some(user_data.clone()),
+        ..user.into()
+    })
+}
+
+/// Factory for creating Microsoft OAuth provider instances
+///
+/// This struct implements the factory pattern for creating Microsoft
+/// OAuth provider instances. It allows the application to create
+/// provider instances dynamically based on configuration.
+pub struct MicrosoftProviderFactory;
+
+impl OAuthProviderFactory for MicrosoftProviderFactory {
+    /// Creates a new Microsoft OAuth provider instance
+    ///
+    /// This method creates a new Microsoft provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, whose `scopes` are
+    ///   added to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created Microsoft provider
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        Ok(Arc::new(MicrosoftProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.scopes.clone(),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that the user id is extracted from a captured Graph response
+    #[test]
+    fn test_parse_graph_response() {
+        let user_data = serde_json::json!({
+            "@odata.context": "https://graph.microsoft.com/v1.0/$metadata#users/$entity",
+            "businessPhones": [],
+            "displayName": "Adele Vance",
+            "givenName": "Adele",
+            "jobTitle": null,
+            "mail": "AdeleV@contoso.onmicrosoft.com",
+            "mobilePhone": null,
+            "officeLocation": null,
+            "preferredLanguage": "en-US",
+            "surname": "Vance",
+            "userPrincipalName": "AdeleV@contoso.onmicrosoft.com",
+            "id": "87d349ed-44d7-43e1-9a83-5f2406dee5bd"
+        });
+
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "87d349ed-44d7-43e1-9a83-5f2406dee5bd");
+        assert_eq!(user_info.provider, "microsoft");
+
+        assert!(parse_user_info(&serde_json::json!({ "displayName": "Adele" })).is_err());
+    }
+
+    /// Tests that fields the server does not read are ignored, and that a
+    /// field with an unexpected type is reported with its path
+    #[test]
+    fn test_response_fields() {
+        let user_data = serde_json::json!({ "id": "87d349ed-44d7-43e1-9a83-5f2406dee5bd", "displayName": "Adele Vance", "businessPhones": ["+1 425 555 0109"], "jobTitle": null });
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "87d349ed-44d7-43e1-9a83-5f2406dee5bd");
+
+        let user_data = serde_json::json!({ "id": 42, "displayName": "Adele Vance" });
+        let error = parse_user_info(&user_data).unwrap_err().to_string();
+        assert!(
+            error.starts_with("Invalid Microsoft user info response at id:"),
+            "{}",
+            error
+        );
+    }
+}
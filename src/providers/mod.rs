@@ -1,56 +1,427 @@
 use crate::{
+    http_client::SharedClient,
+    primitives::UserInfo,
     providers::{
-        discord::DiscordProviderFactory, github::GithubProviderFactory,
-        google::GoogleProviderFactory, spotify::SpotifyProviderFactory,
-        twitter::TwitterProviderFactory,
+        apple::AppleProviderFactory, atlassian::AtlassianProviderFactory,
+        box_provider::BoxProviderFactory, coinbase::CoinbaseProviderFactory,
+        discord::DiscordProviderFactory, generic::GenericProviderFactory,
+        github::GithubProviderFactory, gitlab::GitlabProviderFactory,
+        google::GoogleProviderFactory, keycloak::KeycloakProviderFactory,
+        linkedin::LinkedinProviderFactory, microsoft::MicrosoftProviderFactory,
+        notion::NotionProviderFactory, oidc::OidcProviderFactory, okta::OktaProviderFactory,
+        paypal::PaypalProviderFactory, reddit::RedditProviderFactory,
+        salesforce::SalesforceProviderFactory, shopify::ShopifyProviderFactory,
+        slack::SlackProviderFactory, spotify::SpotifyProviderFactory,
+        twitch::TwitchProviderFactory, twitter::TwitterProviderFactory,
+        yahoo::YahooProviderFactory, zoom::ZoomProviderFactory,
     },
-    traits::OAuthProviderFactory,
+    traits::{request_error, OAuthProviderFactory},
+    types::OAuthClient,
 };
-use once_cell::sync::Lazy;
+use eyre::{eyre, Result};
+use oauth2::AuthType;
+use reqwest::{header::HeaderMap, Response};
+use serde::{
+    de::{DeserializeOwned, Error as _, Unexpected},
+    Deserialize, Deserializer,
+};
+use serde_json::Value;
 use std::{collections::HashMap, sync::Arc};
+use url::form_urlencoded::byte_serialize;
 
+mod apple;
+mod atlassian;
+mod box_provider;
+mod coinbase;
 mod discord;
+mod generic;
 mod github;
+mod gitlab;
 mod google;
+mod keycloak;
+mod linkedin;
+mod microsoft;
+mod notion;
+mod oidc;
+mod okta;
+mod paypal;
+mod reddit;
+mod salesforce;
+mod shopify;
+mod slack;
 mod spotify;
+mod twitch;
 mod twitter;
+mod yahoo;
+mod zoom;
 
-/// Global registry of OAuth provider factories
+/// Registry of OAuth provider factories
 ///
-/// This static registry contains all available OAuth provider factories
-/// mapped by their provider names. It allows the application to dynamically
-/// create provider instances based on configuration.
+/// Maps provider names to the factories creating their instances, so the
+/// application can create providers dynamically based on configuration. A
+/// section uses the provider registered under its `type`, or under its name
+/// when no type is set.
 ///
-/// # Supported Providers
+/// Applications embedding the server register their own factories next to
+/// the built-in ones, or replace a built-in provider by registering another
+/// factory under its name.
 ///
-/// - `"google"` - Google OAuth provider
-/// - `"github"` - GitHub OAuth provider
-/// - `"twitter"` - Twitter OAuth provider
-/// - `"discord"` - Discord OAuth provider
-/// - `"spotify"` - Spotify OAuth provider
+/// # Fields
 ///
-/// # Usage
+/// * `factories` - Provider factories keyed by provider name
+#[derive(Default)]
+pub struct ProviderRegistry {
+    /// Provider factories keyed by provider name
+    factories: HashMap<String, Arc<dyn OAuthProviderFactory>>,
+}
+
+impl ProviderRegistry {
+    /// Creates a registry holding every built-in provider
+    ///
+    /// # Built-in Providers
+    ///
+    /// - `"google"` - Google OAuth provider
+    /// - `"github"` - GitHub OAuth provider
+    /// - `"twitter"` - Twitter OAuth provider
+    /// - `"discord"` - Discord OAuth provider
+    /// - `"spotify"` - Spotify OAuth provider
+    /// - `"microsoft"` - Microsoft (Entra ID) OAuth provider
+    /// - `"linkedin"` - LinkedIn OAuth provider
+    /// - `"apple"` - Sign in with Apple provider
+    /// - `"gitlab"` - GitLab OAuth provider, GitLab.com or self-hosted
+    /// - `"reddit"` - Reddit OAuth provider
+    /// - `"twitch"` - Twitch OAuth provider
+    /// - `"notion"` - Notion OAuth provider
+    /// - `"paypal"` - Log in with PayPal provider, live or sandbox
+    /// - `"yahoo"` - Yahoo OAuth provider
+    /// - `"salesforce"` - Salesforce OAuth provider, production or sandbox
+    /// - `"zoom"` - Zoom OAuth provider
+    /// - `"shopify"` - Shopify OAuth provider, for the shop named on `/authorize`
+    /// - `"coinbase"` - Coinbase OAuth provider
+    /// - `"atlassian"` - Atlassian OAuth provider, for Jira and Confluence cloud
+    /// - `"box"` - Box OAuth provider
+    /// - `"slack"` - Sign in with Slack provider
+    /// - `"okta"` - Okta OAuth provider
+    /// - `"keycloak"` - Keycloak OAuth provider
+    /// - `"oidc"` - Any OpenID Connect provider, configured through discovery
+    /// - `"generic"` - Any OAuth provider, configured entirely from the settings
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `ProviderRegistry` instance
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::default();
+
+        // Register Google OAuth provider
+        registry.register("google", Arc::new(GoogleProviderFactory));
+
+        // Register Github OAuth provider
+        registry.register("github", Arc::new(GithubProviderFactory));
+
+        // Register Twitter OAuth provider
+        registry.register("twitter", Arc::new(TwitterProviderFactory));
+
+        // Register Discord OAuth provider
+        registry.register("discord", Arc::new(DiscordProviderFactory));
+
+        // Register Spotify OAuth provider
+        registry.register("spotify", Arc::new(SpotifyProviderFactory));
+
+        // Register Microsoft OAuth provider
+        registry.register("microsoft", Arc::new(MicrosoftProviderFactory));
+
+        // Register LinkedIn OAuth provider
+        registry.register("linkedin", Arc::new(LinkedinProviderFactory));
+
+        // Register Apple OAuth provider
+        registry.register("apple", Arc::new(AppleProviderFactory));
+
+        // Register GitLab OAuth provider
+        registry.register("gitlab", Arc::new(GitlabProviderFactory));
+
+        // Register Reddit OAuth provider
+        registry.register("reddit", Arc::new(RedditProviderFactory));
+
+        // Register Twitch OAuth provider
+        registry.register("twitch", Arc::new(TwitchProviderFactory));
+
+        // Register Notion OAuth provider
+        registry.register("notion", Arc::new(NotionProviderFactory));
+
+        // Register PayPal OAuth provider
+        registry.register("paypal", Arc::new(PaypalProviderFactory));
+
+        // Register Yahoo OAuth provider
+        registry.register("yahoo", Arc::new(YahooProviderFactory));
+
+        // Register Salesforce OAuth provider
+        registry.register("salesforce", Arc::new(SalesforceProviderFactory));
+
+        // Register Zoom OAuth provider
+        registry.register("zoom", Arc::new(ZoomProviderFactory));
+
+        // Register Shopify OAuth provider
+        registry.register("shopify", Arc::new(ShopifyProviderFactory));
+
+        // Register Coinbase OAuth provider
+        registry.register("coinbase", Arc::new(CoinbaseProviderFactory));
+
+        // Register Atlassian OAuth provider
+        registry.register("atlassian", Arc::new(AtlassianProviderFactory));
+
+        // Register Box OAuth provider
+        registry.register("box", Arc::new(BoxProviderFactory));
+
+        // Register Slack OAuth provider
+        registry.register("slack", Arc::new(SlackProviderFactory));
+
+        // Register Okta OAuth provider
+        registry.register("okta", Arc::new(OktaProviderFactory));
+
+        // Register Keycloak OAuth provider
+        registry.register("keycloak", Arc::new(KeycloakProviderFactory));
+
+        // Register the generic OpenID Connect provider
+        registry.register("oidc", Arc::new(OidcProviderFactory));
+
+        // Register the generic OAuth provider
+        registry.register("generic", Arc::new(GenericProviderFactory));
+
+        registry
+    }
+
+    /// Registers a provider factory
+    ///
+    /// A factory already registered under the name is replaced.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name configuration sections select the provider by
+    /// * `factory` - Factory creating the provider's instances
+    pub fn register(&mut self, name: &str, factory: Arc<dyn OAuthProviderFactory>) {
+        self.factories.insert(name.to_string(), factory);
+    }
+
+    /// Returns the factory registered under a name
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The provider name
+    ///
+    /// # Returns
+    ///
+    /// Returns the factory, or `None` if no provider is registered under the name
+    pub fn get(&self, name: &str) -> Option<&Arc<dyn OAuthProviderFactory>> {
+        self.factories.get(name)
+    }
+}
+
+/// Merges the scopes a provider requires with the configured ones
+///
+/// The required scopes come first and are always requested, since the
+/// provider needs them to fetch the user's information. Configured scopes
+/// that are already required are not repeated.
+///
+/// # Arguments
+///
+/// * `required` - Scopes the provider requires
+/// * `configured` - Scopes from the provider's `scopes` setting, if any
+///
+/// # Returns
+///
+/// Returns the scopes to request on authorization
+fn merge_scopes(required: &[&str], configured: Option<Vec<String>>) -> Vec<String> {
+    let mut scopes: Vec<String> = required.iter().map(|scope| scope.to_string()).collect();
+    for scope in configured.unwrap_or_default() {
+        if !scopes.contains(&scope) {
+            scopes.push(scope);
+        }
+    }
+    scopes
+}
+
+/// Encoding of a token request's body
+pub(crate) enum TokenRequestBody {
+    /// An `application/x-www-form-urlencoded` form, as RFC 6749 specifies
+    Form,
+    /// A JSON object of string fields
+    Json,
+}
+
+/// Sends a grant to the provider's token endpoint
+///
+/// The client authenticates the way its `auth_type` says: through HTTP
+/// Basic auth, or with its id and secret in the body. A client without a
+/// secret only sends its id in the body.
+///
+/// # Arguments
+///
+/// * `http_client` - The shared HTTP client
+/// * `oauth_client` - The provider's OAuth client, with its token endpoint and credentials
+/// * `headers` - Extra headers of the provider's token endpoint
+/// * `params` - The grant parameters
+/// * `body` - How the parameters are encoded
+/// * `context` - What the request is for, used in errors
+///
+/// # Returns
+///
+/// Returns the response whatever its status, a `ProviderTimeout` if the
+/// token endpoint did not answer in time, or an error carrying the context
+/// if it could not be reached
+pub(crate) async fn send_token_request(
+    http_client: &SharedClient,
+    oauth_client: &OAuthClient,
+    headers: HeaderMap,
+    params: &[(&str, &str)],
+    body: TokenRequestBody,
+    context: &str,
+) -> Result<Response> {
+    let client_id = oauth_client.client_id().as_str();
+    let mut request = http_client
+        .post(oauth_client.token_uri().as_str())
+        .headers(headers);
+    let mut form = Vec::with_capacity(params.len() + 2);
+    match (oauth_client.auth_type(), oauth_client.client_secret()) {
+        (AuthType::BasicAuth, Some(client_secret)) => {
+            // RFC 6749 section 2.3.1 form-encodes both before joining them
+            let encode = |value: &str| byte_serialize(value.as_bytes()).collect::<String>();
+            request = request.basic_auth(encode(client_id), Some(encode(client_secret.expose())));
+        }
+        (_, client_secret) => {
+            form.push(("client_id", client_id));
+            if let Some(client_secret) = client_secret {
+                form.push(("client_secret", client_secret.expose()));
+            }
+        }
+    }
+    form.extend_from_slice(params);
+    let request = match body {
+        TokenRequestBody::Form => request.form(&form),
+        TokenRequestBody::Json => request.json(&form.into_iter().collect::<HashMap<_, _>>()),
+    };
+
+    http_client
+        .send(request)
+        .await
+        .map_err(|e| request_error(context, e))
+}
+
+/// Deserializes a provider response into its typed form
+///
+/// # Arguments
+///
+/// * `response` - Name of the response in errors, e.g. `Discord user info`
+/// * `data` - The JSON body returned by the provider
 ///
-/// The registry is used in the main application to create provider instances
-/// based on the configuration in Settings.toml.
-pub static OAUTH_PROVIDER_REGISTRY: Lazy<
-    HashMap<&'static str, Arc<dyn OAuthProviderFactory + Send + Sync>>,
-> = Lazy::new(|| {
-    let mut m: HashMap<&'static str, Arc<dyn OAuthProviderFactory + Send + Sync>> = HashMap::new();
-    // Register Google OAuth provider
-    m.insert("google", Arc::new(GoogleProviderFactory));
+/// # Returns
+///
+/// Returns the typed response, or an error naming the response and the
+/// path of the offending field
+fn parse_response<T: DeserializeOwned>(response: &str, data: &Value) -> Result<T> {
+    serde_path_to_error::deserialize(data).map_err(|e| {
+        eyre!(
+            "Invalid {} response at {}: {}",
+            response,
+            e.path(),
+            e.inner()
+        )
+    })
+}
 
-    // Register Github OAuth provider
-    m.insert("github", Arc::new(GithubProviderFactory));
+/// Deserializes an optional string, treating an empty one as missing
+fn non_empty<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    Ok(Option::<String>::deserialize(deserializer)?.filter(|value| !value.is_empty()))
+}
 
-    // Register Twitter OAuth provider
-    m.insert("twitter", Arc::new(TwitterProviderFactory));
+/// Deserializes an optional boolean flag
+///
+/// Some providers, Apple among them, send flags as `"true"` or `"false"`
+/// strings, which are accepted as well.
+fn flag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<bool>, D::Error> {
+    /// A flag as a boolean or a string
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Flag {
+        /// A JSON boolean
+        Bool(bool),
+        /// A boolean in a string
+        String(String),
+    }
 
-    // Register Discord OAuth provider
-    m.insert("discord", Arc::new(DiscordProviderFactory));
+    match Option::<Flag>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(Flag::Bool(flag)) => Ok(Some(flag)),
+        Some(Flag::String(flag)) => flag
+            .parse()
+            .map(Some)
+            .map_err(|_| D::Error::invalid_value(Unexpected::Str(&flag), &"a boolean")),
+    }
+}
 
-    // Register Spotify OAuth provider
-    m.insert("spotify", Arc::new(SpotifyProviderFactory));
+/// Standard claims of an OpenID Connect userinfo response
+///
+/// Other claims are only kept in the raw response.
+///
+/// # Fields
+///
+/// * `sub` - Subject identifier of the user
+/// * `preferred_username` - The user's preferred username
+/// * `email` - The user's email address
+/// * `email_verified` - Whether the provider verified the email
+/// * `name` - The user's full name
+/// * `picture` - URL of the user's profile picture
+#[derive(Debug, Deserialize)]
+struct OidcUserInfoResponse {
+    /// Subject identifier of the user
+    #[serde(default, deserialize_with = "non_empty")]
+    sub: Option<String>,
+    /// The user's preferred username
+    #[serde(default, deserialize_with = "non_empty")]
+    preferred_username: Option<String>,
+    /// The user's email address
+    #[serde(default, deserialize_with = "non_empty")]
+    email: Option<String>,
+    /// Whether the provider verified the email
+    #[serde(default, deserialize_with = "flag")]
+    email_verified: Option<bool>,
+    /// The user's full name
+    #[serde(default, deserialize_with = "non_empty")]
+    name: Option<String>,
+    /// URL of the user's profile picture
+    #[serde(default, deserialize_with = "non_empty")]
+    picture: Option<String>,
+}
 
-    m
-});
+/// Builds the user information of an OpenID Connect userinfo response
+///
+/// The profile is read from the standard `email`, `name`, `picture` and
+/// `email_verified` claims.
+///
+/// # Arguments
+///
+/// * `id` - The user's identifier
+/// * `provider` - The provider name
+/// * `claims` - The standard claims of the response
+/// * `user_data` - The JSON body returned by the userinfo endpoint
+///
+/// # Returns
+///
+/// Returns the user information with the profile claims the response has
+fn oidc_user_info(
+    id: String,
+    provider: &str,
+    claims: OidcUserInfoResponse,
+    user_data: &Value,
+) -> UserInfo {
+    UserInfo {
+        id,
+        provider: provider.to_string(),
+        email: claims.email,
+        name: claims.name,
+        username: None,
+        avatar_url: claims.picture,
+        email_verified: claims.email_verified,
+        tenant: None,
+        raw: Some(user_data.clone()),
+    }
+}
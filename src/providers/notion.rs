@@ -0,0 +1,659 @@
+use crate::{
+    primitives::{ProviderContext, TokenDetails, UserInfo},
+    providers::{merge_scopes, non_empty, parse_response, send_token_request, TokenRequestBody},
+    settings::TokenAuthType,
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
+};
+use axum::async_trait;
+use eyre::{bail, eyre, Result};
+use oauth2::PkceCodeVerifier;
+use reqwest::Url;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Version of the Notion API the user info request is made against
+const NOTION_VERSION: &str = "2022-06-28";
+
+/// Token response of Notion's token endpoint
+///
+/// Notion describes the workspace and the user who installed the
+/// integration next to the tokens.
+///
+/// # Fields
+///
+/// * `access_token` - The access token
+/// * `expires_in` - Lifetime of the access token in seconds
+/// * `refresh_token` - The refresh token
+/// * `owner` - Owner of the integration, the user who authorized it for public integrations
+#[derive(Deserialize)]
+struct NotionTokenResponse {
+    /// The access token
+    access_token: String,
+    /// Lifetime of the access token in seconds
+    expires_in: Option<u64>,
+    /// The refresh token
+    refresh_token: Option<String>,
+    /// Owner of the integration
+    #[serde(default)]
+    owner: Option<NotionOwner>,
+}
+
+/// Owner of a Notion integration
+///
+/// # Fields
+///
+/// * `user` - The owning user, unset when a workspace owns the integration
+#[derive(Debug, Deserialize)]
+struct NotionOwner {
+    /// The owning user
+    #[serde(default)]
+    user: Option<serde_json::Value>,
+}
+
+/// Notion user object
+///
+/// # Fields
+///
+/// * `id` - UUID of the user
+/// * `name` - The user's name
+/// * `avatar_url` - URL of the user's avatar
+/// * `person` - Details of a person, with the user's email
+/// * `bot` - Details of a bot, with the user owning it
+#[derive(Debug, Deserialize)]
+struct NotionUser {
+    /// UUID of the user
+    id: String,
+    /// The user's name
+    #[serde(default, deserialize_with = "non_empty")]
+    name: Option<String>,
+    /// URL of the user's avatar
+    #[serde(default, deserialize_with = "non_empty")]
+    avatar_url: Option<String>,
+    /// Details of a person
+    #[serde(default)]
+    person: Option<NotionPerson>,
+    /// Details of a bot
+    #[serde(default)]
+    bot: Option<NotionBot>,
+}
+
+/// Details of a Notion user who is a person
+///
+/// # Fields
+///
+/// * `email` - The person's email, with the user information capability
+#[derive(Debug, Deserialize)]
+struct NotionPerson {
+    /// The person's email
+    #[serde(default, deserialize_with = "non_empty")]
+    email: Option<String>,
+}
+
+/// Details of a Notion user who is a bot
+///
+/// # Fields
+///
+/// * `owner` - Owner of the bot
+#[derive(Debug, Deserialize)]
+struct NotionBot {
+    /// Owner of the bot
+    #[serde(default)]
+    owner: Option<NotionBotOwner>,
+}
+
+/// Owner of a Notion bot
+///
+/// # Fields
+///
+/// * `user` - The owning user, unset when a workspace owns the bot
+#[derive(Debug, Deserialize)]
+struct NotionBotOwner {
+    /// The owning user
+    #[serde(default)]
+    user: Option<Box<NotionUser>>,
+}
+
+impl From<NotionUser> for UserInfo {
+    /// Maps a Notion person to the user information
+    fn from(user: NotionUser) -> Self {
+        UserInfo {
+            id: user.id,
+            provider: "notion".to_string(),
+            email: user.person.and_then(|person| person.email),
+            name: user.name,
+            username: None,
+            avatar_url: user.avatar_url,
+            email_verified: None,
+            tenant: None,
+            raw: None,
+        }
+    }
+}
+
+/// Notion OAuth provider implementation
+///
+/// This struct implements the OAuth provider interface for Notion.
+/// Notion's token endpoint takes the client credentials through HTTP
+/// Basic auth and a JSON body, and returns the user who authorized the
+/// integration with the tokens, so the user info endpoint is only called
+/// when the token response does not name a user.
+///
+/// # Fields
+///
+/// * `client` - HTTP client for making API requests
+/// * `oauth_client` - Configured OAuth 2.0 client
+/// * `user_info_url` - Notion's user info endpoint URL
+/// * `scopes` - OAuth scopes requested on authorization
+pub struct NotionProvider {
+    /// HTTP client for API requests
+    client: HttpClient,
+    /// Configured OAuth client
+    oauth_client: OAuthClient,
+    /// Notion user info endpoint URL
+    user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
+}
+
+impl NotionProvider {
+    /// Creates a new Notion OAuth provider instance
+    ///
+    /// This constructor creates a new Notion provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_client` - The HTTP client shared by every provider
+    /// * `oauth_client` - The configured OAuth client for Notion
+    /// * `user_info_url` - The URL for Notion's user info endpoint
+    /// * `scopes` - Scopes configured for the authorization request
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `NotionProvider` instance
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        scopes: Option<Vec<String>>,
+    ) -> Self {
+        Self {
+            client: http_client,
+            oauth_client,
+            user_info_url,
+            scopes: merge_scopes(&[], scopes),
+        }
+    }
+
+    /// Sends a request to Notion's token endpoint
+    ///
+    /// The grant parameters are sent as a JSON body, with the client
+    /// credentials where the configured `auth_type` puts them, the
+    /// `Authorization` header by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The grant parameters
+    /// * `now` - The current unix timestamp
+    ///
+    /// # Returns
+    ///
+    /// Returns the issued token details, carrying the user who authorized
+    /// the integration when Notion names one
+    async fn request_tokens(&self, params: &[(&str, &str)], now: u64) -> Result<TokenDetails> {
+        let response = send_token_request(
+            &self.client,
+            &self.oauth_client,
+            self.token_request_headers(),
+            params,
+            TokenRequestBody::Json,
+            "Notion token request failed",
+        )
+        .await?;
+
+        // The error body is not logged, it may echo the code back
+        if !response.status().is_success() {
+            bail!("Notion token request failed: {}", response.status());
+        }
+
+        let token: NotionTokenResponse = response
+            .json()
+            .await
+            .map_err(|e| request_error("Notion token request failed", e))?;
+        Ok(TokenDetails {
+            access_token: token.access_token,
+            refresh_token: token.refresh_token,
+            expires_at: token
+                .expires_in
+                .map(|expires_in| now.saturating_add(expires_in)),
+            scopes: self.scopes.clone(),
+            id_token: None,
+            user_data: token.owner.and_then(|owner| owner.user),
+        })
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for NotionProvider {
+    /// Returns a reference to the OAuth client for Notion
+    ///
+    /// # Returns
+    ///
+    /// Returns a reference to the configured `OAuthClient` instance
+    fn get_oauth_client(&self) -> &OAuthClient {
+        &self.oauth_client
+    }
+
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Returns the OAuth scopes requested from Notion
+    ///
+    /// Notion grants the capabilities configured on the integration rather
+    /// than scopes, so none is required.
+    ///
+    /// # Returns
+    ///
+    /// Returns the configured scopes
+    fn get_scopes(&self) -> Vec<String> {
+        self.scopes.clone()
+    }
+
+    /// Returns the extra authorization parameters for Notion
+    ///
+    /// Notion requires `owner=user` for public integrations.
+    ///
+    /// # Returns
+    ///
+    /// Returns the `owner=user` parameter
+    fn get_extra_auth_params(&self) -> Vec<(String, String)> {
+        vec![("owner".to_string(), "user".to_string())]
+    }
+
+    /// Fetches user information from Notion's `/v1/users/me` endpoint
+    ///
+    /// The endpoint returns the integration's bot user, whose owner is the
+    /// user who authorized it. Notion rejects requests without a
+    /// `Notion-Version` header.
+    ///
+    /// # Arguments
+    ///
+    /// * `access_token` - The OAuth access token obtained from Notion
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the user's UUID
+    /// or an error if the request fails or the response is invalid
+    async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
+        let request = self
+            .client
+            .get(self.user_info_url.as_str())
+            .header("Authorization", format!("Bearer {}", access_token))
+            .header("Notion-Version", NOTION_VERSION);
+
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|e| request_error("Failed to get user info from Notion", e))?;
+
+        if !response.status().is_success() {
+            bail!("Failed to get user info from Notion: {}", response.status());
+        }
+
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from Notion", e))?;
+        parse_user_info(&user_data)
+    }
+
+    /// Reads the user information from the token response
+    ///
+    /// Falls back to the user info endpoint when the token response does
+    /// not name the user, such as for workspace-owned integrations.
+    ///
+    /// # Arguments
+    ///
+    /// * `tokens` - The tokens returned by the code exchange
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the user's UUID
+    /// or an error if the user cannot be read
+    async fn get_user_info_from_tokens(&self, tokens: &TokenDetails) -> Result<UserInfo> {
+        match &tokens.user_data {
+            Some(user_data) => parse_user_info(user_data),
+            None => self.get_user_info(&tokens.access_token).await,
+        }
+    }
+
+    /// Exchanges an authorization code for tokens
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The authorization code returned by Notion
+    /// * `pkce_verifier` - The PKCE verifier of the flow, `None` without PKCE
+    /// * `now` - The current unix timestamp
+    ///
+    /// # Returns
+    ///
+    /// Returns the issued token details
+    async fn exchange_code(
+        &self,
+        code: &str,
+        pkce_verifier: Option<PkceCodeVerifier>,
+        now: u64,
+    ) -> Result<TokenDetails> {
+        let redirect_uri = self
+            .oauth_client
+            .redirect_uri()
+            .map(|redirect_uri| redirect_uri.to_string())
+            .unwrap_or_default();
+        let mut params = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri.as_str()),
+        ];
+        if let Some(pkce_verifier) = &pkce_verifier {
+            params.push(("code_verifier", pkce_verifier.secret().as_str()));
+        }
+
+        self.request_tokens(&params, now).await
+    }
+
+    /// Exchanges a refresh token for a new access token
+    ///
+    /// # Arguments
+    ///
+    /// * `refresh_token` - The refresh token issued by Notion
+    /// * `now` - The current unix timestamp
+    ///
+    /// # Returns
+    ///
+    /// Returns the new token details
+    async fn refresh_token(&self, refresh_token: &str, now: u64) -> Result<TokenDetails> {
+        self.request_tokens(
+            &[
+                ("grant_type", "refresh_token"),
+                ("refresh_token", refresh_token),
+            ],
+            now,
+        )
+        .await
+    }
+}
+
+/// Extracts the user information from a Notion user object
+///
+/// A bot user, as returned by `/v1/users/me`, is replaced by the user
+/// owning it.
+///
+/// # Arguments
+///
+/// * `user_data` - The user object, from the token response or `/v1/users/me`
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` containing the user's UUID, or an error if
+/// the id is missing, a field has an unexpected type, or a workspace owns
+/// the bot
+fn parse_user_info(user_data: &serde_json::Value) -> Result<UserInfo> {
+    let mut user: NotionUser = parse_response("Notion user info", user_data)?;
+    if let Some(bot) = user.bot.take() {
+        user = *bot
+            .owner
+            .and_then(|owner| owner.user)
+            .ok_or_else(|| eyre!("The Notion integration is not owned by a user"))?;
+    }
+
+    Ok(UserInfo {
+        raw: Some(user_data.clone()),
+        ..user.into()
+    })
+}
+
+/// Factory for creating Notion OAuth provider instances
+///
+/// This struct implements the factory pattern for creating Notion
+/// OAuth provider instances. It allows the application to create
+/// provider instances dynamically based on configuration.
+pub struct NotionProviderFactory;
+
+impl OAuthProviderFactory for NotionProviderFactory {
+    /// Creates a new Notion OAuth provider instance
+    ///
+    /// This method creates a new Notion provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, with the client
+    ///   credentials and `scopes`
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created Notion provider
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        Ok(Arc::new(NotionProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.scopes.clone(),
+        )))
+    }
+
+    /// Returns how Notion expects the client credentials
+    ///
+    /// Notion only accepts the client credentials through HTTP Basic auth.
+    ///
+    /// # Returns
+    ///
+    /// Returns `TokenAuthType::Basic`
+    fn default_auth_type(&self) -> TokenAuthType {
+        TokenAuthType::Basic
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        settings::{OAuthSettings, TokenAuthType},
+        testing::MockApi,
+    };
+    use axum::http::StatusCode;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    /// Recorded `/v1/users/me` response of a public integration
+    fn bot_user() -> serde_json::Value {
+        serde_json::json!({
+            "object": "user",
+            "id": "92a680bb-6970-4726-952b-4f4c03bff617",
+            "name": "Sign in",
+            "avatar_url": null,
+            "type": "bot",
+            "bot": {
+                "owner": {
+                    "type": "user",
+                    "user": {
+                        "object": "user",
+                        "id": "6794760a-1f15-45cd-9c65-0dfe42f5135a",
+                        "name": "Aman Gupta",
+                        "avatar_url": "https://s3-us-west-2.amazonaws.com/public.notion-static.com/avatar.png",
+                        "type": "person",
+                        "person": { "email": "aman@example.com" }
+                    }
+                },
+                "workspace_name": "Aman's Notion"
+            },
+            "request_id": "9f4e5b1a-5a0c-4b8e-a3a0-7a57c8cfb8f0"
+        })
+    }
+
+    /// Starts a fake Notion API
+    ///
+    /// # Arguments
+    ///
+    /// * `token_response` - Body of the token response
+    ///
+    /// # Returns
+    ///
+    /// Returns the running API
+    async fn notion_api(token_response: serde_json::Value) -> MockApi {
+        MockApi::builder()
+            .post("/v1/oauth/token", move |_| {
+                (StatusCode::OK, token_response.clone())
+            })
+            .get("/v1/users/me", |_| (StatusCode::OK, bot_user()))
+            .start()
+            .await
+            .unwrap()
+    }
+
+    /// Creates a Notion provider against the fake API
+    async fn provider(api: &MockApi, auth_type: TokenAuthType) -> Arc<dyn OAuthProvider> {
+        let settings = OAuthSettings {
+            auth_type: Some(auth_type),
+            ..api.settings("/v1/oauth/authorize", "/v1/oauth/token", "/v1/users/me")
+        };
+        api.provider("notion", settings).await.unwrap()
+    }
+
+    /// Tests that the user named in the token response is used without
+    /// calling the user info endpoint
+    #[tokio::test]
+    async fn test_user_from_token_response() {
+        let owner = serde_json::json!({
+            "object": "user",
+            "id": "6794760a-1f15-45cd-9c65-0dfe42f5135a",
+            "name": "Aman Gupta",
+            "avatar_url": null,
+            "type": "person",
+            "person": {}
+        });
+        let api = notion_api(serde_json::json!({
+            "access_token": "secret_notion_access_token",
+            "token_type": "bearer",
+            "bot_id": "92a680bb-6970-4726-952b-4f4c03bff617",
+            "workspace_name": "Aman's Notion",
+            "workspace_icon": "https://www.notion.so/image/icon.png",
+            "workspace_id": "2f0e6f2b-2c6b-4c2b-9e1a-5a8f4f0c6d8e",
+            "owner": { "type": "user", "user": owner },
+            "duplicated_template_id": null,
+            "request_id": "8f7e5d2c-1b3a-4c5d-9e8f-0a1b2c3d4e5f"
+        }))
+        .await;
+        let provider = provider(&api, TokenAuthType::Basic).await;
+
+        let tokens = provider.exchange_code("code", None, 1_000).await.unwrap();
+        assert_eq!(tokens.access_token, "secret_notion_access_token");
+        assert_eq!(tokens.expires_at, None);
+
+        let request = &api.requests("/v1/oauth/token")[0];
+        assert_eq!(
+            request.header("authorization"),
+            Some(format!("Basic {}", STANDARD.encode("mock-client:mock-secret")).as_str())
+        );
+        let body = request.json();
+        assert_eq!(body["grant_type"], "authorization_code");
+        assert_eq!(body["code"], "code");
+        assert_eq!(body["redirect_uri"], api.endpoint("/callback"));
+
+        let user_info = provider.get_user_info_from_tokens(&tokens).await.unwrap();
+        assert_eq!(user_info.id, "6794760a-1f15-45cd-9c65-0dfe42f5135a");
+        assert_eq!(user_info.provider, "notion");
+        assert_eq!(user_info.name.as_deref(), Some("Aman Gupta"));
+        assert_eq!(user_info.email, None);
+        assert_eq!(user_info.raw, Some(owner));
+        assert!(api.requests("/v1/users/me").is_empty());
+    }
+
+    /// Tests that the bot owner is read from `/v1/users/me` when the token
+    /// response does not name a user
+    #[tokio::test]
+    async fn test_user_from_users_me() {
+        let api = notion_api(serde_json::json!({
+            "access_token": "secret_notion_access_token",
+            "token_type": "bearer",
+            "bot_id": "92a680bb-6970-4726-952b-4f4c03bff617",
+            "owner": { "type": "workspace", "workspace": true }
+        }))
+        .await;
+        let provider = provider(&api, TokenAuthType::Basic).await;
+
+        let tokens = provider.exchange_code("code", None, 1_000).await.unwrap();
+        let user_info = provider.get_user_info_from_tokens(&tokens).await.unwrap();
+        assert_eq!(user_info.id, "6794760a-1f15-45cd-9c65-0dfe42f5135a");
+        assert_eq!(user_info.name.as_deref(), Some("Aman Gupta"));
+        assert_eq!(user_info.email.as_deref(), Some("aman@example.com"));
+        assert_eq!(
+            user_info.avatar_url.as_deref(),
+            Some("https://s3-us-west-2.amazonaws.com/public.notion-static.com/avatar.png")
+        );
+        assert_eq!(user_info.raw, Some(bot_user()));
+
+        let request = &api.requests("/v1/users/me")[0];
+        assert_eq!(request.header("notion-version"), Some(NOTION_VERSION));
+        assert_eq!(
+            request.header("authorization"),
+            Some("Bearer secret_notion_access_token")
+        );
+    }
+
+    /// Tests that the client credentials are sent in the JSON body when the
+    /// `auth_type` setting asks for it
+    #[tokio::test]
+    async fn test_request_body_auth_type() {
+        let api = notion_api(serde_json::json!({
+            "access_token": "secret_notion_access_token",
+            "token_type": "bearer",
+            "bot_id": "92a680bb-6970-4726-952b-4f4c03bff617",
+        }))
+        .await;
+        let provider = provider(&api, TokenAuthType::RequestBody).await;
+
+        provider.exchange_code("code", None, 1_000).await.unwrap();
+
+        let request = &api.requests("/v1/oauth/token")[0];
+        assert_eq!(request.header("authorization"), None);
+        let body = request.json();
+        assert_eq!(body["client_id"], "mock-client");
+        assert_eq!(body["client_secret"], "mock-secret");
+        assert_eq!(body["grant_type"], "authorization_code");
+    }
+
+    /// Tests that bots owned by a workspace and wrongly typed fields are rejected
+    #[test]
+    fn test_parse_invalid_user() {
+        let user_data = serde_json::json!({
+            "object": "user",
+            "id": "92a680bb-6970-4726-952b-4f4c03bff617",
+            "type": "bot",
+            "bot": { "owner": { "type": "workspace", "workspace": true } }
+        });
+        assert!(parse_user_info(&user_data).is_err());
+
+        let user_data = serde_json::json!({ "object": "user", "id": 42 });
+        let error = parse_user_info(&user_data).unwrap_err().to_string();
+        assert!(
+            error.starts_with("Invalid Notion user info response at id:"),
+            "{}",
+            error
+        );
+    }
+}
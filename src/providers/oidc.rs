@@ -0,0 +1,430 @@
+use crate::{
+    oidc::IdTokenVerifier,
+    primitives::{ProviderContext, ProviderEndpoints, UserInfo},
+    providers::{merge_scopes, oidc_user_info, parse_response, OidcUserInfoResponse},
+    settings::OAuthSettings,
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
+};
+use axum::async_trait;
+use eyre::{bail, eyre, Result};
+use reqwest::Url;
+use serde::Deserialize;
+use std::{sync::Arc, time::Duration};
+
+/// Path of the discovery document under the issuer
+const DISCOVERY_PATH: &str = "/.well-known/openid-configuration";
+
+/// Time allowed for fetching the discovery document at startup
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Fields of an OpenID Provider discovery document used by the server
+///
+/// # Fields
+///
+/// * `issuer` - The issuer, which must match the configured one
+/// * `authorization_endpoint` - OAuth authorization endpoint URL
+/// * `token_endpoint` - OAuth token exchange endpoint URL
+/// * `userinfo_endpoint` - User info endpoint URL, optional in the specification
+/// * `jwks_uri` - Key set the ID tokens are signed with
+#[derive(Deserialize)]
+struct DiscoveryDocument {
+    /// The issuer
+    issuer: String,
+    /// OAuth authorization endpoint URL
+    authorization_endpoint: String,
+    /// OAuth token exchange endpoint URL
+    token_endpoint: String,
+    /// User info endpoint URL
+    userinfo_endpoint: Option<String>,
+    /// Key set the ID tokens are signed with
+    jwks_uri: Option<String>,
+}
+
+/// OpenID Connect OAuth provider implementation
+///
+/// This struct implements the OAuth provider interface for any OpenID
+/// Connect provider publishing a discovery document. It is selected with
+/// `type = "oidc"`, so several instances can be configured under their own
+/// names. It handles OAuth 2.0 authentication flow and user information
+/// retrieval from the discovered userinfo endpoint.
+///
+/// # Fields
+///
+/// * `client` - HTTP client for making API requests
+/// * `oauth_client` - Configured OAuth 2.0 client
+/// * `user_info_url` - OpenID Connect's user info endpoint URL
+/// * `scopes` - OAuth scopes requested on authorization
+/// * `id_token_verifier` - Verifier of the ID tokens, when a key set is known
+pub struct OidcProvider {
+    /// HTTP client for API requests
+    client: HttpClient,
+    /// Configured OAuth client
+    oauth_client: OAuthClient,
+    /// OpenID Connect user info endpoint URL
+    user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
+    /// Verifier of the ID tokens
+    id_token_verifier: Option<IdTokenVerifier>,
+}
+
+impl OidcProvider {
+    /// Creates a new OpenID Connect OAuth provider instance
+    ///
+    /// This constructor creates a new OpenID Connect provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_client` - The HTTP client shared by every provider
+    /// * `oauth_client` - The configured OAuth client for OpenID Connect
+    /// * `user_info_url` - The URL for OpenID Connect's user info endpoint
+    /// * `scopes` - Scopes configured in addition to the required ones
+    /// * `id_token_verifier` - Verifier of the ID tokens, `None` to skip the check
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `OidcProvider` instance
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        scopes: Option<Vec<String>>,
+        id_token_verifier: Option<IdTokenVerifier>,
+    ) -> Self {
+        Self {
+            client: http_client,
+            oauth_client,
+            user_info_url,
+            scopes: merge_scopes(&["openid", "profile", "email"], scopes),
+            id_token_verifier,
+        }
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for OidcProvider {
+    /// Returns a reference to the OAuth client for OpenID Connect
+    ///
+    /// # Returns
+    ///
+    /// Returns a reference to the configured `OAuthClient` instance
+    fn get_oauth_client(&self) -> &OAuthClient {
+        &self.oauth_client
+    }
+
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Returns the OAuth scopes required for OpenID Connect
+    ///
+    /// OpenID Connect providers require the "openid" scope, and the "profile"
+    /// and "email" scopes to return the matching userinfo claims.
+    ///
+    /// # Returns
+    ///
+    /// Returns the required scopes followed by the configured ones
+    fn get_scopes(&self) -> Vec<String> {
+        self.scopes.clone()
+    }
+
+    /// Opts in to the device authorization grant
+    ///
+    /// Whether the server implements RFC 8628 is only known once a
+    /// `device_auth_url` is configured.
+    ///
+    /// # Returns
+    ///
+    /// Always returns `true`
+    fn supports_device_flow(&self) -> bool {
+        true
+    }
+
+    /// Returns the verifier of the ID tokens
+    ///
+    /// # Returns
+    ///
+    /// Returns the verifier when a `jwks_url` is configured or discovered
+    fn id_token_verifier(&self) -> Option<&IdTokenVerifier> {
+        self.id_token_verifier.as_ref()
+    }
+
+    /// Fetches user information from OpenID Connect's OpenID Connect userinfo endpoint
+    ///
+    /// This method makes an authenticated request to OpenID Connect to
+    /// retrieve the signed-in user's profile.
+    ///
+    /// # Arguments
+    ///
+    /// * `access_token` - The OAuth access token obtained from OpenID Connect
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the user's identifier
+    /// or an error if the request fails
+    async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
+        let request = self
+            .client
+            .get(self.user_info_url.as_str())
+            .header("Authorization", format!("Bearer {}", access_token));
+
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|e| request_error("Failed to get user info from OpenID Connect", e))?;
+
+        if !response.status().is_success() {
+            bail!(
+                "Failed to get user info from OpenID Connect: {}",
+                response.status()
+            );
+        }
+
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from OpenID Connect", e))?;
+        parse_user_info(&user_data)
+    }
+}
+
+/// Extracts the user information from a userinfo response
+///
+/// The `sub` claim is used as the user's identifier, falling back to the
+/// `email` claim when it is missing.
+///
+/// # Arguments
+///
+/// * `user_data` - The JSON body returned by the userinfo endpoint
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` containing the user's identifier, or an
+/// error if both claims are missing or a claim has an unexpected type
+fn parse_user_info(user_data: &serde_json::Value) -> Result<UserInfo> {
+    let claims: OidcUserInfoResponse = parse_response("OpenID Connect user info", user_data)?;
+    let id = claims
+        .sub
+        .clone()
+        .or_else(|| claims.email.clone())
+        .ok_or_else(|| eyre::eyre!("No sub or email field in OpenID Connect user info response"))?;
+
+    Ok(oidc_user_info(id, "oidc", claims, user_data))
+}
+
+/// Factory for creating OpenID Connect OAuth provider instances
+///
+/// This struct implements the factory pattern for creating OpenID Connect
+/// OAuth provider instances. It allows the application to create
+/// provider instances dynamically based on configuration.
+pub struct OidcProviderFactory;
+
+#[async_trait]
+impl OAuthProviderFactory for OidcProviderFactory {
+    /// Creates a new OpenID Connect OAuth provider instance
+    ///
+    /// This method creates a new OpenID Connect provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, whose `scopes` are
+    ///   added to the required ones and whose `jwks_url` enables ID token verification
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created OpenID Connect provider,
+    /// or an error if the `jwks_url` is invalid
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        Ok(Arc::new(OidcProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.scopes.clone(),
+            IdTokenVerifier::from_settings(settings, None)?,
+        )))
+    }
+
+    /// Fetches the endpoints from the issuer's discovery document
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The provider's settings, with the `issuer`
+    ///
+    /// # Returns
+    ///
+    /// Returns the discovered authorization, token, userinfo and key set endpoints,
+    /// or an error if the issuer is missing or the document cannot be
+    /// fetched, parsed or does not belong to the issuer
+    async fn discover_endpoints(
+        &self,
+        settings: &OAuthSettings,
+    ) -> Result<Option<ProviderEndpoints>> {
+        let Some(issuer) = settings.issuer.as_deref() else {
+            bail!("issuer is required for OpenID Connect providers");
+        };
+        let issuer = issuer.trim_end_matches('/');
+
+        let client = reqwest::Client::builder()
+            .timeout(DISCOVERY_TIMEOUT)
+            .build()?;
+        let response = client
+            .get(format!("{}{}", issuer, DISCOVERY_PATH))
+            .send()
+            .await
+            .map_err(|e| eyre!("Failed to fetch discovery document: {}", e))?;
+        if !response.status().is_success() {
+            bail!("Failed to fetch discovery document: {}", response.status());
+        }
+        let document: DiscoveryDocument = response
+            .json()
+            .await
+            .map_err(|e| eyre!("Invalid discovery document: {}", e))?;
+
+        // OpenID Connect Discovery 1.0, section 4.3
+        if document.issuer.trim_end_matches('/') != issuer {
+            bail!(
+                "Discovery document issuer {} does not match {}",
+                document.issuer,
+                issuer
+            );
+        }
+
+        Ok(Some(ProviderEndpoints {
+            auth_url: document.authorization_endpoint,
+            token_url: document.token_endpoint,
+            // Leaving it empty falls back to a configured user_info_url
+            user_info_url: document.userinfo_endpoint.unwrap_or_default(),
+            jwks_url: document.jwks_uri,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{routing::get, Json, Router};
+
+    /// Serves a discovery document and returns the issuer it is served for
+    ///
+    /// The document claims `document_issuer` as issuer, or the served
+    /// issuer itself when it is `None`.
+    async fn serve_discovery(document_issuer: Option<&str>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let issuer = format!("http://{}/realms/corp", listener.local_addr().unwrap());
+        let document = serde_json::json!({
+            "issuer": document_issuer.unwrap_or(&issuer),
+            "authorization_endpoint": format!("{}/authorize", issuer),
+            "token_endpoint": format!("{}/token", issuer),
+            "userinfo_endpoint": format!("{}/userinfo", issuer),
+            "jwks_uri": format!("{}/certs", issuer),
+            "response_types_supported": ["code"],
+            "subject_types_supported": ["public"],
+            "id_token_signing_alg_values_supported": ["RS256"]
+        });
+        let router = Router::new().route(
+            "/realms/corp/.well-known/openid-configuration",
+            get(move || async move { Json(document) }),
+        );
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        issuer
+    }
+
+    fn settings(issuer: Option<&str>) -> OAuthSettings {
+        OAuthSettings {
+            issuer: issuer.map(str::to_string),
+            ..OAuthSettings::default()
+        }
+    }
+
+    /// Tests that the endpoints are read from the issuer's discovery document
+    #[tokio::test]
+    async fn test_discover_endpoints() {
+        let issuer = serve_discovery(None).await;
+
+        let endpoints = OidcProviderFactory
+            .discover_endpoints(&settings(Some(&format!("{}/", issuer))))
+            .await
+            .unwrap();
+        assert_eq!(
+            endpoints,
+            Some(ProviderEndpoints {
+                auth_url: format!("{}/authorize", issuer),
+                token_url: format!("{}/token", issuer),
+                user_info_url: format!("{}/userinfo", issuer),
+                jwks_url: Some(format!("{}/certs", issuer)),
+            })
+        );
+    }
+
+    /// Tests that discovery fails without an issuer, for a missing document or a foreign issuer
+    #[tokio::test]
+    async fn test_discovery_failures() {
+        assert!(OidcProviderFactory
+            .discover_endpoints(&settings(None))
+            .await
+            .is_err());
+
+        let issuer = serve_discovery(None).await;
+        let missing = format!("{}/other", issuer);
+        assert!(OidcProviderFactory
+            .discover_endpoints(&settings(Some(&missing)))
+            .await
+            .is_err());
+
+        let issuer = serve_discovery(Some("https://attacker.example.com")).await;
+        let error = OidcProviderFactory
+            .discover_endpoints(&settings(Some(&issuer)))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("does not match"), "{}", error);
+    }
+
+    /// Tests that the user id is extracted from a userinfo response
+    #[test]
+    fn test_parse_userinfo_response() {
+        let user_data = serde_json::json!({
+            "sub": "248289761001",
+            "name": "Jane Doe",
+            "preferred_username": "j.doe",
+            "email": "janedoe@example.com"
+        });
+
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "248289761001");
+        assert_eq!(user_info.provider, "oidc");
+    }
+
+    /// Tests that fields the server does not read are ignored, and that a
+    /// field with an unexpected type is reported with its path
+    #[test]
+    fn test_response_fields() {
+        let user_data = serde_json::json!({ "sub": "248289761001", "email": "jane@example.com", "address": { "country": "US" }, "updated_at": 1311280970 });
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "248289761001");
+
+        let user_data = serde_json::json!({ "sub": "248289761001", "picture": false });
+        let error = parse_user_info(&user_data).unwrap_err().to_string();
+        assert!(
+            error.starts_with("Invalid OpenID Connect user info response at picture:"),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,303 @@
+use crate::{
+    primitives::{ProviderContext, ProviderEndpoints, UserInfo},
+    providers::{merge_scopes, oidc_user_info, parse_response, OidcUserInfoResponse},
+    settings::OAuthSettings,
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
+};
+use axum::async_trait;
+use eyre::{bail, Result};
+use reqwest::Url;
+use std::sync::Arc;
+
+/// Okta OAuth provider implementation
+///
+/// This struct implements the OAuth provider interface for Okta. It
+/// handles OAuth 2.0 authentication flow and user information retrieval
+/// from the userinfo endpoint of the tenant's authorization server.
+///
+/// # Fields
+///
+/// * `client` - HTTP client for making API requests
+/// * `oauth_client` - Configured OAuth 2.0 client
+/// * `user_info_url` - Okta's user info endpoint URL
+/// * `scopes` - OAuth scopes requested on authorization
+pub struct OktaProvider {
+    /// HTTP client for API requests
+    client: HttpClient,
+    /// Configured OAuth client
+    oauth_client: OAuthClient,
+    /// Okta user info endpoint URL
+    user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
+}
+
+impl OktaProvider {
+    /// Creates a new Okta OAuth provider instance
+    ///
+    /// This constructor creates a new Okta provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_client` - The HTTP client shared by every provider
+    /// * `oauth_client` - The configured OAuth client for Okta
+    /// * `user_info_url` - The URL for Okta's user info endpoint
+    /// * `scopes` - Scopes configured in addition to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `OktaProvider` instance
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        scopes: Option<Vec<String>>,
+    ) -> Self {
+        Self {
+            client: http_client,
+            oauth_client,
+            user_info_url,
+            scopes: merge_scopes(&["openid", "profile", "email"], scopes),
+        }
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for OktaProvider {
+    /// Returns a reference to the OAuth client for Okta
+    ///
+    /// # Returns
+    ///
+    /// Returns a reference to the configured `OAuthClient` instance
+    fn get_oauth_client(&self) -> &OAuthClient {
+        &self.oauth_client
+    }
+
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Returns the OAuth scopes required for Okta
+    ///
+    /// Okta requires the "openid", "profile" and "email" scopes to access
+    /// the user's identity through its userinfo endpoint.
+    ///
+    /// # Returns
+    ///
+    /// Returns the required scopes followed by the configured ones
+    fn get_scopes(&self) -> Vec<String> {
+        self.scopes.clone()
+    }
+
+    /// Fetches user information from Okta's OpenID Connect userinfo endpoint
+    ///
+    /// This method makes an authenticated request to Okta to
+    /// retrieve the signed-in user's profile.
+    ///
+    /// # Arguments
+    ///
+    /// * `access_token` - The OAuth access token obtained from Okta
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the user's identifier
+    /// or an error if the request fails
+    async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
+        let request = self
+            .client
+            .get(self.user_info_url.as_str())
+            .header("Authorization", format!("Bearer {}", access_token));
+
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|e| request_error("Failed to get user info from Okta", e))?;
+
+        if !response.status().is_success() {
+            bail!("Failed to get user info from Okta: {}", response.status());
+        }
+
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from Okta", e))?;
+        parse_user_info(&user_data)
+    }
+}
+
+/// Extracts the user information from an Okta userinfo response
+///
+/// The `sub` claim is used as the user's identifier, falling back to the
+/// `email` claim when it is missing.
+///
+/// # Arguments
+///
+/// * `user_data` - The JSON body returned by the userinfo endpoint
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` containing the user's identifier, or an
+/// error if both claims are missing or a claim has an unexpected type
+fn parse_user_info(user_data: &serde_json::Value) -> Result<UserInfo> {
+    let claims: OidcUserInfoResponse = parse_response("Okta user info", user_data)?;
+    let id = claims
+        .sub
+        .clone()
+        .or_else(|| claims.email.clone())
+        .ok_or_else(|| eyre::eyre!("No sub or email field in Okta user info response"))?;
+
+    Ok(oidc_user_info(id, "okta", claims, user_data))
+}
+
+/// Factory for creating Okta OAuth provider instances
+///
+/// This struct implements the factory pattern for creating Okta
+/// OAuth provider instances. It allows the application to create
+/// provider instances dynamically based on configuration.
+pub struct OktaProviderFactory;
+
+impl OAuthProviderFactory for OktaProviderFactory {
+    /// Creates a new Okta OAuth provider instance
+    ///
+    /// This method creates a new Okta provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, whose `scopes` are
+    ///   added to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created Okta provider
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        Ok(Arc::new(OktaProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.scopes.clone(),
+        )))
+    }
+
+    /// Derives the Okta endpoints from the authorization server's issuer
+    ///
+    /// Custom authorization servers, such as `/oauth2/default`, serve their
+    /// endpoints under the issuer, the org authorization server under
+    /// `/oauth2` of the bare org URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The provider's settings, with the `issuer`
+    ///
+    /// # Returns
+    ///
+    /// Returns the authorization, token and userinfo endpoints, or `None`
+    /// without an issuer
+    fn default_endpoints(&self, settings: &OAuthSettings) -> Option<ProviderEndpoints> {
+        let issuer = settings.issuer.as_deref()?.trim_end_matches('/');
+        let org_server = Url::parse(issuer).is_ok_and(|url| url.path() == "/");
+        let base_url = if org_server {
+            format!("{}/oauth2", issuer)
+        } else {
+            issuer.to_string()
+        };
+
+        Some(ProviderEndpoints {
+            auth_url: format!("{}/v1/authorize", base_url),
+            token_url: format!("{}/v1/token", base_url),
+            user_info_url: format!("{}/v1/userinfo", base_url),
+            jwks_url: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn endpoints(issuer: Option<&str>) -> Option<ProviderEndpoints> {
+        OktaProviderFactory.default_endpoints(&OAuthSettings {
+            issuer: issuer.map(str::to_string),
+            ..OAuthSettings::default()
+        })
+    }
+
+    /// Tests that the endpoints are derived from custom and org authorization servers
+    #[test]
+    fn test_endpoints_derived_from_issuer() {
+        assert_eq!(
+            endpoints(Some("https://example.okta.com/oauth2/default")),
+            Some(ProviderEndpoints {
+                auth_url: "https://example.okta.com/oauth2/default/v1/authorize".to_string(),
+                token_url: "https://example.okta.com/oauth2/default/v1/token".to_string(),
+                user_info_url: "https://example.okta.com/oauth2/default/v1/userinfo".to_string(),
+                jwks_url: None,
+            })
+        );
+        assert_eq!(
+            endpoints(Some("https://example.okta.com/")),
+            Some(ProviderEndpoints {
+                auth_url: "https://example.okta.com/oauth2/v1/authorize".to_string(),
+                token_url: "https://example.okta.com/oauth2/v1/token".to_string(),
+                user_info_url: "https://example.okta.com/oauth2/v1/userinfo".to_string(),
+                jwks_url: None,
+            })
+        );
+        assert_eq!(endpoints(None), None);
+    }
+
+    /// Tests that the user id is extracted from a recorded userinfo response
+    #[test]
+    fn test_parse_userinfo_response() {
+        let user_data = serde_json::json!({
+            "sub": "00uid4BxXw6I6TV4m0g3",
+            "name": "John Doe",
+            "nickname": "Jimmy",
+            "given_name": "John",
+            "family_name": "Doe",
+            "preferred_username": "john.doe@example.com",
+            "email": "john.doe@example.com",
+            "email_verified": true,
+            "locale": "en-US",
+            "zoneinfo": "America/Los_Angeles",
+            "updated_at": 1311280970
+        });
+
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "00uid4BxXw6I6TV4m0g3");
+        assert_eq!(user_info.provider, "okta");
+    }
+
+    /// Tests that fields the server does not read are ignored, and that a
+    /// field with an unexpected type is reported with its path
+    #[test]
+    fn test_response_fields() {
+        let user_data = serde_json::json!({ "sub": "00uid4BxXw6I6TV4m0g3", "email": "john.doe@example.com", "zoneinfo": "America/Los_Angeles", "groups": ["Everyone"] });
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "00uid4BxXw6I6TV4m0g3");
+
+        let user_data =
+            serde_json::json!({ "sub": "00uid4BxXw6I6TV4m0g3", "preferred_username": 7 });
+        let error = parse_user_info(&user_data).unwrap_err().to_string();
+        assert!(
+            error.starts_with("Invalid Okta user info response at preferred_username:"),
+            "{}",
+            error
+        );
+    }
+}
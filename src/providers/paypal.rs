@@ -0,0 +1,384 @@
+use crate::{
+    primitives::{ProviderContext, UserInfo},
+    providers::{flag, merge_scopes, non_empty, parse_response},
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
+};
+use axum::async_trait;
+use eyre::{bail, Result};
+use reqwest::Url;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// PayPal `/v1/identity/openidconnect/userinfo` response
+///
+/// # Fields
+///
+/// * `user_id` - PayPal's identifier of the user, a URL
+/// * `payer_id` - The user's payer id, with the `https://uri.paypal.com/services/paypalattributes` scope
+/// * `name` - The user's full name
+/// * `email` - The user's email, with `schema=openid`
+/// * `email_verified` - Whether PayPal verified the email
+/// * `emails` - The user's email addresses, without `schema=openid`
+#[derive(Debug, Deserialize)]
+struct PaypalUserResponse {
+    /// PayPal's identifier of the user
+    #[serde(default, deserialize_with = "non_empty")]
+    user_id: Option<String>,
+    /// The user's payer id
+    #[serde(default, deserialize_with = "non_empty")]
+    payer_id: Option<String>,
+    /// The user's full name
+    #[serde(default, deserialize_with = "non_empty")]
+    name: Option<String>,
+    /// The user's email
+    #[serde(default, deserialize_with = "non_empty")]
+    email: Option<String>,
+    /// Whether PayPal verified the email
+    #[serde(default, deserialize_with = "flag")]
+    email_verified: Option<bool>,
+    /// The user's email addresses
+    #[serde(default)]
+    emails: Vec<PaypalEmail>,
+}
+
+/// Email address of a PayPal user
+///
+/// # Fields
+///
+/// * `value` - The email address
+/// * `primary` - Whether it is the user's primary address
+/// * `confirmed` - Whether the user confirmed the address
+#[derive(Debug, Deserialize)]
+struct PaypalEmail {
+    /// The email address
+    value: String,
+    /// Whether it is the user's primary address
+    #[serde(default)]
+    primary: bool,
+    /// Whether the user confirmed the address
+    #[serde(default)]
+    confirmed: bool,
+}
+
+impl From<PaypalUserResponse> for UserInfo {
+    /// Maps a PayPal user to the user information
+    ///
+    /// The `email` claim is used when set, the primary address of `emails`
+    /// otherwise. The user is identified by their `user_id`, empty when
+    /// missing.
+    fn from(user: PaypalUserResponse) -> Self {
+        let (email, email_verified) = match user.email {
+            Some(email) => (Some(email), user.email_verified),
+            None => match user.emails.into_iter().find(|email| email.primary) {
+                Some(email) => (Some(email.value), Some(email.confirmed)),
+                None => (None, None),
+            },
+        };
+        UserInfo {
+            id: user.user_id.unwrap_or_default(),
+            provider: "paypal".to_string(),
+            email,
+            name: user.name,
+            username: None,
+            avatar_url: None,
+            email_verified,
+            tenant: None,
+            raw: None,
+        }
+    }
+}
+
+/// PayPal OAuth provider implementation
+///
+/// This struct implements the OAuth provider interface for Log in with
+/// PayPal. It handles OAuth 2.0 authentication flow and user information
+/// retrieval from PayPal's identity API. The live and sandbox environments
+/// only differ by their endpoints.
+///
+/// # Fields
+///
+/// * `client` - HTTP client for making API requests
+/// * `oauth_client` - Configured OAuth 2.0 client
+/// * `user_info_url` - PayPal's user info endpoint URL, query string included
+/// * `scopes` - OAuth scopes requested on authorization
+pub struct PaypalProvider {
+    /// HTTP client for API requests
+    client: HttpClient,
+    /// Configured OAuth client
+    oauth_client: OAuthClient,
+    /// PayPal user info endpoint URL
+    user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
+}
+
+impl PaypalProvider {
+    /// Creates a new PayPal OAuth provider instance
+    ///
+    /// This constructor creates a new PayPal provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_client` - The HTTP client shared by every provider
+    /// * `oauth_client` - The configured OAuth client for PayPal
+    /// * `user_info_url` - The URL for PayPal's user info endpoint
+    /// * `scopes` - Scopes configured in addition to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `PaypalProvider` instance
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        scopes: Option<Vec<String>>,
+    ) -> Self {
+        Self {
+            client: http_client,
+            oauth_client,
+            user_info_url,
+            scopes: merge_scopes(&["openid", "email"], scopes),
+        }
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for PaypalProvider {
+    /// Returns a reference to the OAuth client for PayPal
+    ///
+    /// # Returns
+    ///
+    /// Returns a reference to the configured `OAuthClient` instance
+    fn get_oauth_client(&self) -> &OAuthClient {
+        &self.oauth_client
+    }
+
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Returns the OAuth scopes required for PayPal
+    ///
+    /// PayPal requires the "openid" and "email" scopes to share the user's
+    /// identity and email.
+    ///
+    /// # Returns
+    ///
+    /// Returns the required scopes followed by the configured ones
+    fn get_scopes(&self) -> Vec<String> {
+        self.scopes.clone()
+    }
+
+    /// Fetches user information from PayPal's identity API
+    ///
+    /// The configured URL is requested as is, so its `schema=openid` query
+    /// parameter selects the OpenID Connect claims.
+    ///
+    /// # Arguments
+    ///
+    /// * `access_token` - The OAuth access token obtained from PayPal
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the user's identifier
+    /// or an error if the request fails or the response is invalid
+    async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
+        let request = self
+            .client
+            .get(self.user_info_url.as_str())
+            .header("Authorization", format!("Bearer {}", access_token));
+
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|e| request_error("Failed to get user info from PayPal", e))?;
+
+        if !response.status().is_success() {
+            bail!("Failed to get user info from PayPal: {}", response.status());
+        }
+
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from PayPal", e))?;
+        parse_user_info(&user_data)
+    }
+}
+
+/// Extracts the user information from a PayPal user info response
+///
+/// The `user_id` is used as the user's identifier, falling back to the
+/// `payer_id` when it is missing.
+///
+/// # Arguments
+///
+/// * `user_data` - The JSON body returned by the user info endpoint
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` containing the user's identifier, or an
+/// error if both fields are missing or a field has an unexpected type
+fn parse_user_info(user_data: &serde_json::Value) -> Result<UserInfo> {
+    let user: PaypalUserResponse = parse_response("PayPal user info", user_data)?;
+    let id = user
+        .user_id
+        .clone()
+        .or_else(|| user.payer_id.clone())
+        .ok_or_else(|| eyre::eyre!("No user_id or payer_id field in PayPal user info response"))?;
+
+    Ok(UserInfo {
+        id,
+        raw: Some(user_data.clone()),
+        ..user.into()
+    })
+}
+
+/// Factory for creating PayPal OAuth provider instances
+///
+/// This struct implements the factory pattern for creating PayPal
+/// OAuth provider instances. It allows the application to create
+/// provider instances dynamically based on configuration.
+pub struct PaypalProviderFactory;
+
+impl OAuthProviderFactory for PaypalProviderFactory {
+    /// Creates a new PayPal OAuth provider instance
+    ///
+    /// This method creates a new PayPal provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, whose `scopes` are
+    ///   added to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created PayPal provider
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        Ok(Arc::new(PaypalProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.scopes.clone(),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{settings::OAuthSettings, testing::MockApi};
+    use axum::http::StatusCode;
+
+    /// Tests that the user id and email are extracted from a recorded
+    /// `schema=openid` response
+    #[test]
+    fn test_parse_openid_response() {
+        let user_data = serde_json::json!({
+            "user_id": "https://www.paypal.com/webapps/auth/identity/user/mWq6_1sU85v5EG9yHdPxJRrhGHrnMJ-1PQKtX6pcsmA",
+            "sub": "https://www.paypal.com/webapps/auth/identity/user/mWq6_1sU85v5EG9yHdPxJRrhGHrnMJ-1PQKtX6pcsmA",
+            "name": "Identity Test",
+            "payer_id": "WDJJHEBZ4X2LY",
+            "address": { "country": "US" },
+            "verified_account": "true",
+            "email": "user1@example.com",
+            "email_verified": "true"
+        });
+
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(
+            user_info.id,
+            "https://www.paypal.com/webapps/auth/identity/user/mWq6_1sU85v5EG9yHdPxJRrhGHrnMJ-1PQKtX6pcsmA"
+        );
+        assert_eq!(user_info.provider, "paypal");
+        assert_eq!(user_info.email.as_deref(), Some("user1@example.com"));
+        assert_eq!(user_info.email_verified, Some(true));
+        assert_eq!(user_info.name.as_deref(), Some("Identity Test"));
+        assert_eq!(user_info.raw, Some(user_data));
+    }
+
+    /// Tests that the payer id and the primary of `emails` are used when
+    /// the response has no `user_id` or `email`
+    #[test]
+    fn test_parse_payer_id_and_emails() {
+        let user_data = serde_json::json!({
+            "payer_id": "WDJJHEBZ4X2LY",
+            "emails": [
+                { "value": "old@example.com", "primary": false, "confirmed": true },
+                { "value": "user1@example.com", "primary": true, "confirmed": false }
+            ]
+        });
+
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "WDJJHEBZ4X2LY");
+        assert_eq!(user_info.email.as_deref(), Some("user1@example.com"));
+        assert_eq!(user_info.email_verified, Some(false));
+
+        assert!(parse_user_info(&serde_json::json!({ "name": "Identity Test" })).is_err());
+        let error = parse_user_info(&serde_json::json!({ "payer_id": 42 }))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.starts_with("Invalid PayPal user info response at payer_id:"),
+            "{}",
+            error
+        );
+    }
+
+    /// Tests that a sandbox configuration builds, and that the user info
+    /// request keeps the query string of the configured URL
+    #[tokio::test]
+    async fn test_sandbox_configuration() {
+        let api = MockApi::builder()
+            .get("/v1/identity/openidconnect/userinfo", |_| {
+                (
+                    StatusCode::OK,
+                    serde_json::json!({
+                        "user_id": "https://www.paypal.com/webapps/auth/identity/user/sandbox",
+                        "email": "buyer@example.com"
+                    }),
+                )
+            })
+            .start()
+            .await
+            .unwrap();
+
+        let settings = OAuthSettings {
+            client_id: "sandbox-client".to_string(),
+            client_secret: "sandbox-secret".to_string().into(),
+            auth_url: "https://www.sandbox.paypal.com/signin/authorize".to_string(),
+            token_url: "https://api-m.sandbox.paypal.com/v1/oauth2/token".to_string(),
+            user_info_url: api.endpoint("/v1/identity/openidconnect/userinfo?schema=openid"),
+            ..OAuthSettings::default()
+        };
+        let provider = api.provider("paypal", settings).await.unwrap();
+        assert_eq!(
+            provider.get_oauth_client().auth_uri().as_str(),
+            "https://www.sandbox.paypal.com/signin/authorize"
+        );
+        assert_eq!(provider.get_scopes(), vec!["openid", "email"]);
+
+        let user_info = provider.get_user_info("access-token").await.unwrap();
+        assert_eq!(user_info.email.as_deref(), Some("buyer@example.com"));
+        let requests = api.requests("/v1/identity/openidconnect/userinfo");
+        assert_eq!(
+            requests[0].query.get("schema").map(String::as_str),
+            Some("openid")
+        );
+    }
+}
@@ -0,0 +1,365 @@
+use crate::{
+    primitives::{ProviderContext, UserInfo},
+    providers::{merge_scopes, non_empty, parse_response},
+    settings::TokenAuthType,
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
+};
+use axum::async_trait;
+use eyre::{bail, Result};
+use reqwest::{
+    header::{HeaderMap, HeaderValue, USER_AGENT},
+    Url,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Reddit `/api/v1/me` response
+///
+/// # Fields
+///
+/// * `name` - The user's name, unique and used as their identifier
+/// * `icon_img` - URL of the user's avatar
+#[derive(Debug, Deserialize)]
+struct RedditUserResponse {
+    /// The user's name
+    name: String,
+    /// URL of the user's avatar
+    #[serde(default, deserialize_with = "non_empty")]
+    icon_img: Option<String>,
+}
+
+impl From<RedditUserResponse> for UserInfo {
+    /// Maps a Reddit account to the user information
+    ///
+    /// Reddit does not share email addresses, and accounts have no display
+    /// name besides their unique name.
+    fn from(user: RedditUserResponse) -> Self {
+        UserInfo {
+            id: user.name.clone(),
+            provider: "reddit".to_string(),
+            email: None,
+            name: Some(user.name.clone()),
+            username: Some(user.name),
+            // Reddit HTML-escapes the query string of its icon URLs
+            avatar_url: user.icon_img.map(|url| url.replace("&amp;", "&")),
+            email_verified: None,
+            tenant: None,
+            raw: None,
+        }
+    }
+}
+
+///
+/// This struct implements the OAuth provider interface for Reddit.
+/// It handles OAuth 2.0 authentication flow and user information
+/// retrieval from Reddit's API. Reddit rejects requests without a
+/// descriptive `User-Agent`, so every request, token exchange included,
+/// carries one naming the app.
+///
+/// # Fields
+///
+/// * `client` - HTTP client for making API requests
+/// * `oauth_client` - Configured OAuth 2.0 client
+/// * `user_info_url` - Reddit's user info endpoint URL
+/// * `scopes` - OAuth scopes requested on authorization
+/// * `user_agent` - `User-Agent` sent with every request
+pub struct RedditProvider {
+    /// HTTP client for API requests
+    client: HttpClient,
+    /// Configured OAuth client
+    oauth_client: OAuthClient,
+    /// Reddit user info endpoint URL
+    user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
+    /// `User-Agent` sent with every request
+    user_agent: HeaderValue,
+}
+
+impl RedditProvider {
+    /// Creates a new Reddit OAuth provider instance
+    ///
+    /// This constructor creates a new Reddit provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_client` - The HTTP client shared by every provider
+    /// * `oauth_client` - The configured OAuth client for Reddit
+    /// * `user_info_url` - The URL for Reddit's user info endpoint
+    /// * `scopes` - Scopes configured in addition to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `RedditProvider` instance
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        scopes: Option<Vec<String>>,
+    ) -> Self {
+        // Reddit asks for <platform>:<app ID>:<version>
+        let user_agent = format!(
+            "web:{}:v{} (by oauth_server)",
+            oauth_client.client_id().as_str(),
+            env!("CARGO_PKG_VERSION")
+        );
+        let user_agent = HeaderValue::try_from(user_agent)
+            .unwrap_or_else(|_| HeaderValue::from_static("web:oauth_server (by oauth_server)"));
+
+        Self {
+            client: http_client,
+            oauth_client,
+            user_info_url,
+            scopes: merge_scopes(&["identity"], scopes),
+            user_agent,
+        }
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for RedditProvider {
+    /// Returns a reference to the OAuth client for Reddit
+    ///
+    /// # Returns
+    ///
+    /// Returns a reference to the configured `OAuthClient` instance
+    fn get_oauth_client(&self) -> &OAuthClient {
+        &self.oauth_client
+    }
+
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Returns the OAuth scopes required for Reddit
+    ///
+    /// Reddit requires the "identity" scope to access the signed-in
+    /// user's account through `/api/v1/me`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the required scopes followed by the configured ones
+    fn get_scopes(&self) -> Vec<String> {
+        self.scopes.clone()
+    }
+
+    /// Returns the extra authorization parameters for Reddit
+    ///
+    /// Reddit only issues a refresh token for `duration=permanent`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the `duration=permanent` parameter
+    fn get_extra_auth_params(&self) -> Vec<(String, String)> {
+        vec![("duration".to_string(), "permanent".to_string())]
+    }
+
+    /// Returns the extra headers sent to Reddit's token endpoint
+    ///
+    /// # Returns
+    ///
+    /// Returns the provider's `User-Agent`, which replaces the shared one
+    fn token_request_headers(&self) -> HeaderMap {
+        HeaderMap::from_iter([(USER_AGENT, self.user_agent.clone())])
+    }
+
+    /// Fetches user information from Reddit's `/api/v1/me` endpoint
+    ///
+    /// This method makes an authenticated request to Reddit to
+    /// retrieve the signed-in user's account.
+    ///
+    /// # Arguments
+    ///
+    /// * `access_token` - The OAuth access token obtained from Reddit
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the user's name
+    /// or an error if the request fails or the response is invalid
+    async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
+        let request = self
+            .client
+            .get(self.user_info_url.as_str())
+            .header(USER_AGENT, self.user_agent.clone())
+            .header("Authorization", format!("Bearer {}", access_token));
+
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|e| request_error("Failed to get user info from Reddit", e))?;
+
+        if !response.status().is_success() {
+            bail!("Failed to get user info from Reddit: {}", response.status());
+        }
+
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from Reddit", e))?;
+        parse_user_info(&user_data)
+    }
+}
+
+/// Extracts the user information from a Reddit `/api/v1/me` response
+///
+/// # Arguments
+///
+/// * `user_data` - The JSON body returned by the user info endpoint
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` containing the user's name, or an error if
+/// the name is missing or a field has an unexpected type
+fn parse_user_info(user_data: &serde_json::Value) -> Result<UserInfo> {
+    let user: RedditUserResponse = parse_response("Reddit user info", user_data)?;
+    Ok(UserInfo {
+        raw: Some(user_data.clone()),
+        ..user.into()
+    })
+}
+
+/// Factory for creating Reddit OAuth provider instances
+///
+/// This struct implements the factory pattern for creating Reddit
+/// OAuth provider instances. It allows the application to create
+/// provider instances dynamically based on configuration.
+pub struct RedditProviderFactory;
+
+impl OAuthProviderFactory for RedditProviderFactory {
+    /// Creates a new Reddit OAuth provider instance
+    ///
+    /// This method creates a new Reddit provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, whose `scopes` are
+    ///   added to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created Reddit provider
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        Ok(Arc::new(RedditProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.scopes.clone(),
+        )))
+    }
+
+    /// Returns how Reddit expects the client credentials
+    ///
+    /// Reddit only accepts the client credentials through HTTP Basic auth.
+    ///
+    /// # Returns
+    ///
+    /// Returns `TokenAuthType::Basic`
+    fn default_auth_type(&self) -> TokenAuthType {
+        TokenAuthType::Basic
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockApi;
+    use axum::http::StatusCode;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    /// Tests that the token exchange carries the Basic credentials and the User-Agent
+    #[tokio::test]
+    async fn test_exchange_sends_basic_auth_and_user_agent() {
+        let api = MockApi::builder()
+            .post("/api/v1/access_token", |_| {
+                (
+                    StatusCode::OK,
+                    serde_json::json!({
+                        "access_token": "reddit-access-token",
+                        "token_type": "bearer",
+                        "expires_in": 86400,
+                        "refresh_token": "reddit-refresh-token",
+                        "scope": "identity",
+                    }),
+                )
+            })
+            .start()
+            .await
+            .unwrap();
+
+        let provider = api
+            .provider(
+                "reddit",
+                api.settings("/api/v1/authorize", "/api/v1/access_token", "/api/v1/me"),
+            )
+            .await
+            .unwrap();
+
+        let tokens = provider.exchange_code("code", None, 1_000).await.unwrap();
+        assert_eq!(tokens.access_token, "reddit-access-token");
+
+        let request = &api.requests("/api/v1/access_token")[0];
+        assert_eq!(
+            request.header("authorization"),
+            Some(format!("Basic {}", STANDARD.encode("mock-client:mock-secret")).as_str())
+        );
+        assert_eq!(
+            request.header("user-agent"),
+            Some(
+                format!(
+                    "web:mock-client:v{} (by oauth_server)",
+                    env!("CARGO_PKG_VERSION")
+                )
+                .as_str()
+            )
+        );
+    }
+
+    /// Tests that the profile is extracted from a recorded user response,
+    /// and that a field with an unexpected type is reported with its path
+    #[test]
+    fn test_parse_user_response() {
+        let user_data = serde_json::json!({
+            "name": "reddit_bot",
+            "icon_img": "https://styles.redditmedia.com/t5_6/styles/profileIcon_snoo.png?width=256&amp;s=d3b4",
+            "total_karma": 1,
+            "subreddit": { "display_name": "u_reddit_bot" }
+        });
+
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "reddit_bot");
+        assert_eq!(user_info.provider, "reddit");
+        assert_eq!(user_info.username.as_deref(), Some("reddit_bot"));
+        assert_eq!(
+            user_info.avatar_url.as_deref(),
+            Some(
+                "https://styles.redditmedia.com/t5_6/styles/profileIcon_snoo.png?width=256&s=d3b4"
+            )
+        );
+        assert_eq!(user_info.raw, Some(user_data));
+
+        let user_data = serde_json::json!({ "name": 42 });
+        let error = parse_user_info(&user_data).unwrap_err().to_string();
+        assert!(
+            error.starts_with("Invalid Reddit user info response at name:"),
+            "{}",
+            error
+        );
+        assert!(parse_user_info(&serde_json::json!({})).is_err());
+    }
+}
@@ -0,0 +1,760 @@
+use crate::{
+    primitives::{ProviderContext, ProviderEndpoints, TokenDetails, UserInfo},
+    providers::{
+        flag, merge_scopes, non_empty, parse_response, send_token_request, TokenRequestBody,
+    },
+    settings::{OAuthSettings, TokenAuthType},
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
+};
+use axum::async_trait;
+use eyre::{bail, Result};
+use oauth2::PkceCodeVerifier;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Login host of production orgs, used when no `base_url` is configured
+const SALESFORCE_LOGIN: &str = "https://login.salesforce.com";
+
+/// Token response of Salesforce's token endpoint
+///
+/// Salesforce does not return an expiry, and points to the user's identity
+/// next to the tokens.
+///
+/// # Fields
+///
+/// * `access_token` - The access token
+/// * `refresh_token` - The refresh token, with the `refresh_token` scope
+/// * `scope` - Scopes granted by Salesforce
+/// * `id_token` - The OpenID Connect ID token, with the `openid` scope
+/// * `identity` - Where the user's identity can be fetched
+#[derive(Deserialize)]
+struct SalesforceTokenResponse {
+    /// The access token
+    access_token: String,
+    /// The refresh token
+    refresh_token: Option<String>,
+    /// Scopes granted by Salesforce
+    scope: Option<String>,
+    /// The OpenID Connect ID token
+    id_token: Option<String>,
+    /// Where the user's identity can be fetched
+    #[serde(flatten)]
+    identity: SalesforceIdentity,
+}
+
+/// Location of a Salesforce user's identity
+///
+/// # Fields
+///
+/// * `id` - Identity URL of the user, `{login host}/id/{org id}/{user id}`
+/// * `instance_url` - URL of the user's org, serving the userinfo endpoint
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SalesforceIdentity {
+    /// Identity URL of the user
+    #[serde(default)]
+    id: Option<String>,
+    /// URL of the user's org
+    #[serde(default)]
+    instance_url: Option<String>,
+}
+
+/// Salesforce identity URL or userinfo response
+///
+/// The identity URL names the user with `display_name`, `username` and
+/// `photos`, the userinfo endpoint with the OpenID Connect claims.
+///
+/// # Fields
+///
+/// * `user_id` - Id of the user within the org
+/// * `organization_id` - Id of the user's org
+/// * `email` - The user's email
+/// * `email_verified` - Whether Salesforce verified the email
+/// * `display_name` - The user's name, from the identity URL
+/// * `name` - The user's name, from the userinfo endpoint
+/// * `username` - The user's login, from the identity URL
+/// * `preferred_username` - The user's login, from the userinfo endpoint
+/// * `photos` - The user's photos, from the identity URL
+/// * `picture` - URL of the user's photo, from the userinfo endpoint
+#[derive(Debug, Deserialize)]
+struct SalesforceUserResponse {
+    /// Id of the user within the org
+    user_id: String,
+    /// Id of the user's org
+    #[serde(default, deserialize_with = "non_empty")]
+    organization_id: Option<String>,
+    /// The user's email
+    #[serde(default, deserialize_with = "non_empty")]
+    email: Option<String>,
+    /// Whether Salesforce verified the email
+    #[serde(default, deserialize_with = "flag")]
+    email_verified: Option<bool>,
+    /// The user's name, from the identity URL
+    #[serde(default, deserialize_with = "non_empty")]
+    display_name: Option<String>,
+    /// The user's name, from the userinfo endpoint
+    #[serde(default, deserialize_with = "non_empty")]
+    name: Option<String>,
+    /// The user's login, from the identity URL
+    #[serde(default, deserialize_with = "non_empty")]
+    username: Option<String>,
+    /// The user's login, from the userinfo endpoint
+    #[serde(default, deserialize_with = "non_empty")]
+    preferred_username: Option<String>,
+    /// The user's photos, from the identity URL
+    #[serde(default)]
+    photos: Option<SalesforcePhotos>,
+    /// URL of the user's photo, from the userinfo endpoint
+    #[serde(default, deserialize_with = "non_empty")]
+    picture: Option<String>,
+}
+
+/// Photos of a Salesforce user
+///
+/// # Fields
+///
+/// * `picture` - URL of the full-size photo
+#[derive(Debug, Deserialize)]
+struct SalesforcePhotos {
+    /// URL of the full-size photo
+    #[serde(default, deserialize_with = "non_empty")]
+    picture: Option<String>,
+}
+
+impl From<SalesforceUserResponse> for UserInfo {
+    /// Maps a Salesforce user to the user information
+    ///
+    /// The user's org is reported as their tenant, since user ids are only
+    /// meaningful within it.
+    fn from(user: SalesforceUserResponse) -> Self {
+        UserInfo {
+            id: user.user_id,
+            provider: "salesforce".to_string(),
+            email: user.email,
+            name: user.display_name.or(user.name),
+            username: user.username.or(user.preferred_username),
+            avatar_url: user
+                .photos
+                .and_then(|photos| photos.picture)
+                .or(user.picture),
+            email_verified: user.email_verified,
+            tenant: user.organization_id,
+            raw: None,
+        }
+    }
+}
+
+/// Salesforce OAuth provider implementation
+///
+/// This struct implements the OAuth provider interface for Salesforce.
+/// Salesforce's token response points to the user's identity URL and to
+/// the org's instance, so the user is fetched in a second step from the
+/// identity URL, or from the instance's userinfo endpoint when Salesforce
+/// did not return one.
+///
+/// # Fields
+///
+/// * `client` - HTTP client for making API requests
+/// * `oauth_client` - Configured OAuth 2.0 client
+/// * `user_info_url` - Userinfo endpoint of the login host, used without identity URL or instance
+/// * `scopes` - OAuth scopes requested on authorization
+pub struct SalesforceProvider {
+    /// HTTP client for API requests
+    client: HttpClient,
+    /// Configured OAuth client
+    oauth_client: OAuthClient,
+    /// Salesforce user info endpoint URL
+    user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
+}
+
+impl SalesforceProvider {
+    /// Creates a new Salesforce OAuth provider instance
+    ///
+    /// This constructor creates a new Salesforce provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_client` - The HTTP client shared by every provider
+    /// * `oauth_client` - The configured OAuth client for Salesforce
+    /// * `user_info_url` - The URL for the login host's userinfo endpoint
+    /// * `scopes` - Scopes configured in addition to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `SalesforceProvider` instance
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        scopes: Option<Vec<String>>,
+    ) -> Self {
+        Self {
+            client: http_client,
+            oauth_client,
+            user_info_url,
+            scopes: merge_scopes(&["id", "email"], scopes),
+        }
+    }
+
+    /// Sends a request to Salesforce's token endpoint
+    ///
+    /// The client credentials are sent where the configured `auth_type`
+    /// puts them, the request body by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The grant parameters
+    ///
+    /// # Returns
+    ///
+    /// Returns the issued token details, carrying the user's identity URL
+    /// and instance
+    async fn request_tokens(&self, params: &[(&str, &str)]) -> Result<TokenDetails> {
+        let response = send_token_request(
+            &self.client,
+            &self.oauth_client,
+            self.token_request_headers(),
+            params,
+            TokenRequestBody::Form,
+            "Salesforce token request failed",
+        )
+        .await?;
+
+        // The error body is not logged, it may echo the code back
+        if !response.status().is_success() {
+            bail!("Salesforce token request failed: {}", response.status());
+        }
+
+        let token: SalesforceTokenResponse = response
+            .json()
+            .await
+            .map_err(|e| request_error("Salesforce token request failed", e))?;
+        Ok(TokenDetails {
+            access_token: token.access_token,
+            refresh_token: token.refresh_token,
+            expires_at: None,
+            scopes: token
+                .scope
+                .map(|scope| scope.split_whitespace().map(str::to_string).collect())
+                .unwrap_or_else(|| self.scopes.clone()),
+            id_token: token.id_token,
+            user_data: Some(serde_json::to_value(token.identity)?),
+        })
+    }
+
+    /// Fetches a user from a Salesforce identity or userinfo URL
+    ///
+    /// # Arguments
+    ///
+    /// * `url` - The identity URL or userinfo endpoint
+    /// * `access_token` - The OAuth access token obtained from Salesforce
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the user's and org's ids
+    /// or an error if the request fails or the response is invalid
+    async fn fetch_user(&self, url: &str, access_token: &str) -> Result<UserInfo> {
+        let request = self
+            .client
+            .get(url)
+            .header("Authorization", format!("Bearer {}", access_token));
+
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|e| request_error("Failed to get user info from Salesforce", e))?;
+
+        if !response.status().is_success() {
+            bail!(
+                "Failed to get user info from Salesforce: {}",
+                response.status()
+            );
+        }
+
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from Salesforce", e))?;
+        parse_user_info(&user_data)
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for SalesforceProvider {
+    /// Returns a reference to the OAuth client for Salesforce
+    ///
+    /// # Returns
+    ///
+    /// Returns a reference to the configured `OAuthClient` instance
+    fn get_oauth_client(&self) -> &OAuthClient {
+        &self.oauth_client
+    }
+
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Returns the OAuth scopes required for Salesforce
+    ///
+    /// Salesforce requires the "id" and "email" scopes to share the user's
+    /// identity and email.
+    ///
+    /// # Returns
+    ///
+    /// Returns the required scopes followed by the configured ones
+    fn get_scopes(&self) -> Vec<String> {
+        self.scopes.clone()
+    }
+
+    /// Fetches user information from the login host's userinfo endpoint
+    ///
+    /// # Arguments
+    ///
+    /// * `access_token` - The OAuth access token obtained from Salesforce
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the user's and org's ids
+    /// or an error if the request fails
+    async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
+        self.fetch_user(self.user_info_url.as_str(), access_token)
+            .await
+    }
+
+    /// Fetches the user the token response points to
+    ///
+    /// The identity URL is preferred, then the userinfo endpoint of the
+    /// org's instance, then the configured userinfo endpoint.
+    ///
+    /// # Arguments
+    ///
+    /// * `tokens` - The tokens returned by the code exchange
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the user's and org's ids
+    /// or an error if the request fails
+    async fn get_user_info_from_tokens(&self, tokens: &TokenDetails) -> Result<UserInfo> {
+        let identity: SalesforceIdentity = match &tokens.user_data {
+            Some(user_data) => parse_response("Salesforce token", user_data)?,
+            None => SalesforceIdentity::default(),
+        };
+        match (identity.id, identity.instance_url) {
+            (Some(id), _) => self.fetch_user(&id, &tokens.access_token).await,
+            (None, Some(instance_url)) => {
+                let url = format!(
+                    "{}/services/oauth2/userinfo",
+                    instance_url.trim_end_matches('/')
+                );
+                self.fetch_user(&url, &tokens.access_token).await
+            }
+            (None, None) => self.get_user_info(&tokens.access_token).await,
+        }
+    }
+
+    /// Exchanges an authorization code for tokens
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The authorization code returned by Salesforce
+    /// * `pkce_verifier` - The PKCE verifier of the flow, `None` without PKCE
+    /// * `now` - The current unix timestamp, unused as Salesforce returns no expiry
+    ///
+    /// # Returns
+    ///
+    /// Returns the issued token details
+    async fn exchange_code(
+        &self,
+        code: &str,
+        pkce_verifier: Option<PkceCodeVerifier>,
+        _now: u64,
+    ) -> Result<TokenDetails> {
+        let redirect_uri = self
+            .oauth_client
+            .redirect_uri()
+            .map(|redirect_uri| redirect_uri.to_string())
+            .unwrap_or_default();
+        let mut params = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri.as_str()),
+        ];
+        if let Some(pkce_verifier) = &pkce_verifier {
+            params.push(("code_verifier", pkce_verifier.secret().as_str()));
+        }
+
+        self.request_tokens(&params).await
+    }
+
+    /// Exchanges a refresh token for a new access token
+    ///
+    /// # Arguments
+    ///
+    /// * `refresh_token` - The refresh token issued by Salesforce
+    /// * `now` - The current unix timestamp, unused as Salesforce returns no expiry
+    ///
+    /// # Returns
+    ///
+    /// Returns the new token details
+    async fn refresh_token(&self, refresh_token: &str, _now: u64) -> Result<TokenDetails> {
+        self.request_tokens(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+        ])
+        .await
+    }
+}
+
+/// Extracts the user information from a Salesforce identity or userinfo response
+///
+/// # Arguments
+///
+/// * `user_data` - The JSON body returned by the identity URL or userinfo endpoint
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` containing the user's and org's ids, or an
+/// error if the user id is missing or a field has an unexpected type
+fn parse_user_info(user_data: &serde_json::Value) -> Result<UserInfo> {
+    let user: SalesforceUserResponse = parse_response("Salesforce user info", user_data)?;
+    Ok(UserInfo {
+        raw: Some(user_data.clone()),
+        ..user.into()
+    })
+}
+
+/// Factory for creating Salesforce OAuth provider instances
+///
+/// This struct implements the factory pattern for creating Salesforce
+/// OAuth provider instances. It allows the application to create
+/// provider instances dynamically based on configuration.
+pub struct SalesforceProviderFactory;
+
+impl OAuthProviderFactory for SalesforceProviderFactory {
+    /// Creates a new Salesforce OAuth provider instance
+    ///
+    /// This method creates a new Salesforce provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, with the client
+    ///   credentials and extra `scopes`
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created Salesforce provider
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        Ok(Arc::new(SalesforceProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.scopes.clone(),
+        )))
+    }
+
+    /// Derives the Salesforce endpoints from the login host
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The provider's settings, whose `base_url` points to
+    ///   `https://test.salesforce.com` for sandboxes or to a My Domain,
+    ///   `https://login.salesforce.com` without one
+    ///
+    /// # Returns
+    ///
+    /// Returns the authorization, token and userinfo endpoints of the login host
+    fn default_endpoints(&self, settings: &OAuthSettings) -> Option<ProviderEndpoints> {
+        let base_url = settings
+            .base_url
+            .as_deref()
+            .unwrap_or(SALESFORCE_LOGIN)
+            .trim_end_matches('/');
+
+        Some(ProviderEndpoints {
+            auth_url: format!("{}/services/oauth2/authorize", base_url),
+            token_url: format!("{}/services/oauth2/token", base_url),
+            user_info_url: format!("{}/services/oauth2/userinfo", base_url),
+            jwks_url: None,
+        })
+    }
+
+    /// Returns how Salesforce expects the client credentials
+    ///
+    /// Salesforce expects the client credentials in the request body.
+    ///
+    /// # Returns
+    ///
+    /// Returns `TokenAuthType::RequestBody`
+    fn default_auth_type(&self) -> TokenAuthType {
+        TokenAuthType::RequestBody
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{settings::TokenAuthType, testing::MockApi};
+    use axum::http::StatusCode;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    /// Org id of the fake org
+    const ORG_ID: &str = "00Dxx0000001gPLEAY";
+
+    /// User id of the fake user
+    const USER_ID: &str = "005xx000001SwiUAAS";
+
+    /// Starts a fake Salesforce org
+    ///
+    /// The token response is built from the org's URL by `token_response`.
+    /// The token endpoint accepts the client credentials in the body or in
+    /// a Basic `Authorization` header, never both.
+    ///
+    /// # Returns
+    ///
+    /// Returns the running org
+    async fn salesforce_org(token_response: fn(&str) -> serde_json::Value) -> MockApi {
+        MockApi::builder()
+            .post("/services/oauth2/token", move |request| {
+                let form = request.form();
+                match request.header("authorization") {
+                    Some(authorization) => {
+                        assert_eq!(
+                            authorization,
+                            format!("Basic {}", STANDARD.encode("mock-client:mock-secret"))
+                        );
+                        assert!(!form.contains_key("client_secret"));
+                    }
+                    None => {
+                        assert_eq!(form["client_id"], "mock-client");
+                        assert_eq!(form["client_secret"], "mock-secret");
+                    }
+                }
+                let org = format!("http://{}", request.header("host").unwrap());
+                (StatusCode::OK, token_response(&org))
+            })
+            .get("/id/:org_id/:user_id", |request| {
+                let (org_id, user_id) = request.path["/id/".len()..].split_once('/').unwrap();
+                (
+                    StatusCode::OK,
+                    serde_json::json!({
+                        "id": format!("https://login.salesforce.com/id/{}/{}", org_id, user_id),
+                        "asserted_user": true,
+                        "user_id": user_id,
+                        "organization_id": org_id,
+                        "username": "admin@acme.example.com",
+                        "nick_name": "admin",
+                        "display_name": "Ada Admin",
+                        "email": "ada@acme.example.com",
+                        "email_verified": true,
+                        "first_name": "Ada",
+                        "last_name": "Admin",
+                        "timezone": "America/Los_Angeles",
+                        "photos": {
+                            "picture": "https://acme.file.force.com/profilephoto/005/F",
+                            "thumbnail": "https://acme.file.force.com/profilephoto/005/T"
+                        },
+                        "urls": { "rest": "https://acme.my.salesforce.com/services/data/v{version}/" },
+                        "active": true,
+                        "user_type": "STANDARD",
+                        "language": "en_US",
+                        "locale": "en_US",
+                        "utcOffset": -28800000,
+                        "last_modified_date": "2024-01-15T18:35:16Z"
+                    }),
+                )
+            })
+            .get("/services/oauth2/userinfo", |_| {
+                (
+                    StatusCode::OK,
+                    serde_json::json!({
+                        "sub": format!("https://login.salesforce.com/id/{}/{}", ORG_ID, USER_ID),
+                        "user_id": USER_ID,
+                        "organization_id": ORG_ID,
+                        "preferred_username": "admin@acme.example.com",
+                        "name": "Ada Admin",
+                        "email": "ada@acme.example.com",
+                        "email_verified": true,
+                        "picture": "https://acme.file.force.com/profilephoto/005/F",
+                        "user_type": "STANDARD"
+                    }),
+                )
+            })
+            .start()
+            .await
+            .unwrap()
+    }
+
+    /// Creates a Salesforce provider against the fake org
+    ///
+    /// The user info endpoint refuses connections, the user is read from
+    /// the URLs of the token response.
+    async fn provider(org: &MockApi, auth_type: Option<TokenAuthType>) -> Arc<dyn OAuthProvider> {
+        let settings = OAuthSettings {
+            user_info_url: "http://127.0.0.1:9/services/oauth2/userinfo".to_string(),
+            auth_type,
+            ..org.settings(
+                "/services/oauth2/authorize",
+                "/services/oauth2/token",
+                "/services/oauth2/userinfo",
+            )
+        };
+        org.provider("salesforce", settings).await.unwrap()
+    }
+
+    /// Tests that production and sandbox endpoints are derived from the login host
+    #[test]
+    fn test_login_host_endpoints() {
+        let endpoints = |base_url: Option<&str>| {
+            SalesforceProviderFactory
+                .default_endpoints(&OAuthSettings {
+                    base_url: base_url.map(str::to_string),
+                    ..OAuthSettings::default()
+                })
+                .unwrap()
+        };
+        assert_eq!(
+            endpoints(None),
+            ProviderEndpoints {
+                auth_url: "https://login.salesforce.com/services/oauth2/authorize".to_string(),
+                token_url: "https://login.salesforce.com/services/oauth2/token".to_string(),
+                user_info_url: "https://login.salesforce.com/services/oauth2/userinfo".to_string(),
+                jwks_url: None,
+            }
+        );
+        assert_eq!(
+            endpoints(Some("https://test.salesforce.com/")).token_url,
+            "https://test.salesforce.com/services/oauth2/token"
+        );
+    }
+
+    /// Tests that the user is fetched from the identity URL of the token response
+    #[tokio::test]
+    async fn test_user_from_identity_url() {
+        let org = salesforce_org(|org| {
+            serde_json::json!({
+                "access_token": "00Dxx0000001gPL!AR8AQJXg5oj8jXSgxJfA0lBog",
+                "refresh_token": "5Aep861TSESvWeug_xvFHRBTTbf_YrTWgEyjBJrsBRxwNQ",
+                "signature": "CMJ4l+CCaPQiKjoOEwEig9H4wqhpuLSk4J2urAe+fVg=",
+                "scope": "refresh_token id email",
+                "instance_url": org,
+                "id": format!("{}/id/{}/{}", org, ORG_ID, USER_ID),
+                "token_type": "Bearer",
+                "issued_at": "1706193402034"
+            })
+        })
+        .await;
+        let provider = provider(&org, None).await;
+
+        let tokens = provider.exchange_code("code", None, 1_000).await.unwrap();
+        assert_eq!(tokens.scopes, vec!["refresh_token", "id", "email"]);
+        assert_eq!(tokens.expires_at, None);
+
+        let user_info = provider.get_user_info_from_tokens(&tokens).await.unwrap();
+        assert_eq!(user_info.id, USER_ID);
+        assert_eq!(user_info.provider, "salesforce");
+        assert_eq!(user_info.tenant.as_deref(), Some(ORG_ID));
+        assert_eq!(user_info.email.as_deref(), Some("ada@acme.example.com"));
+        assert_eq!(user_info.email_verified, Some(true));
+        assert_eq!(user_info.name.as_deref(), Some("Ada Admin"));
+        assert_eq!(
+            user_info.username.as_deref(),
+            Some("admin@acme.example.com")
+        );
+        assert_eq!(
+            user_info.avatar_url.as_deref(),
+            Some("https://acme.file.force.com/profilephoto/005/F")
+        );
+        let requests = org.requests(&format!("/id/{}/{}", ORG_ID, USER_ID));
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0].header("authorization"),
+            Some("Bearer 00Dxx0000001gPL!AR8AQJXg5oj8jXSgxJfA0lBog")
+        );
+        assert!(org.requests("/services/oauth2/userinfo").is_empty());
+    }
+
+    /// Tests that the instance's userinfo endpoint is used without identity URL
+    #[tokio::test]
+    async fn test_user_from_instance_userinfo() {
+        let org = salesforce_org(|org| {
+            serde_json::json!({
+                "access_token": "00Dxx0000001gPL!AR8AQJXg5oj8jXSgxJfA0lBog",
+                "instance_url": format!("{}/", org),
+                "token_type": "Bearer"
+            })
+        })
+        .await;
+        let provider = provider(&org, None).await;
+
+        let tokens = provider.exchange_code("code", None, 1_000).await.unwrap();
+        assert_eq!(tokens.scopes, vec!["id", "email"]);
+
+        let user_info = provider.get_user_info_from_tokens(&tokens).await.unwrap();
+        assert_eq!(user_info.id, USER_ID);
+        assert_eq!(user_info.tenant.as_deref(), Some(ORG_ID));
+        assert_eq!(user_info.name.as_deref(), Some("Ada Admin"));
+        assert_eq!(
+            user_info.username.as_deref(),
+            Some("admin@acme.example.com")
+        );
+        let requests = org.requests("/services/oauth2/userinfo");
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0].header("authorization"),
+            Some("Bearer 00Dxx0000001gPL!AR8AQJXg5oj8jXSgxJfA0lBog")
+        );
+    }
+
+    /// Tests that the client credentials are sent through HTTP Basic auth
+    /// when the `auth_type` setting asks for it
+    #[tokio::test]
+    async fn test_basic_auth_type() {
+        let org = salesforce_org(|org| {
+            serde_json::json!({
+                "access_token": "00Dxx0000001gPL!AR8AQJXg5oj8jXSgxJfA0lBog",
+                "instance_url": org,
+                "token_type": "Bearer"
+            })
+        })
+        .await;
+        let provider = provider(&org, Some(TokenAuthType::Basic)).await;
+
+        let tokens = provider.exchange_code("code", None, 1_000).await.unwrap();
+        assert_eq!(
+            tokens.access_token,
+            "00Dxx0000001gPL!AR8AQJXg5oj8jXSgxJfA0lBog"
+        );
+    }
+
+    /// Tests that responses without a user id are rejected
+    #[test]
+    fn test_parse_invalid_response() {
+        assert!(parse_user_info(&serde_json::json!({ "organization_id": ORG_ID })).is_err());
+        let error = parse_user_info(&serde_json::json!({ "user_id": USER_ID, "photos": "none" }))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.starts_with("Invalid Salesforce user info response at photos:"),
+            "{}",
+            error
+        );
+    }
+}
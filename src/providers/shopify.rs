@@ -0,0 +1,780 @@
+use crate::{
+    primitives::{ProviderContext, ProviderEndpoints, TokenDetails, UserInfo},
+    providers::{
+        flag, merge_scopes, non_empty, parse_response, send_token_request, TokenRequestBody,
+    },
+    settings::{OAuthSettings, TokenAuthType},
+    traits::{request_error, OAuthProvider, OAuthProviderFactory, RefreshUnsupported},
+    types::{HttpClient, OAuthClient},
+};
+use axum::async_trait;
+use eyre::{bail, eyre, Result};
+use oauth2::{AuthUrl, PkceCodeVerifier, TokenUrl};
+use reqwest::Url;
+use serde::Deserialize;
+use std::{collections::BTreeMap, sync::Arc};
+
+/// Placeholder of the shop name in the configured endpoints
+const SHOP_PLACEHOLDER: &str = "{shop}";
+
+/// Domain every shop is served under
+const SHOPIFY_DOMAIN: &str = ".myshopify.com";
+
+/// Token response of a shop's token endpoint
+///
+/// Online access tokens, requested with `grant_options[]=per-user`, come
+/// with the user who approved the installation.
+///
+/// # Fields
+///
+/// * `access_token` - The access token
+/// * `expires_in` - Lifetime of an online access token in seconds
+/// * `scope` - Comma separated scopes granted to the access token
+/// * `associated_user` - The user who approved the installation, online tokens only
+#[derive(Deserialize)]
+struct ShopifyTokenResponse {
+    /// The access token
+    access_token: String,
+    /// Lifetime of an online access token in seconds
+    expires_in: Option<u64>,
+    /// Comma separated scopes granted to the access token
+    scope: Option<String>,
+    /// The user who approved the installation
+    #[serde(default)]
+    associated_user: Option<serde_json::Value>,
+}
+
+/// User associated with an online Shopify access token
+///
+/// # Fields
+///
+/// * `id` - Id of the user within the shop
+/// * `first_name` - The user's first name
+/// * `last_name` - The user's last name
+/// * `email` - The user's email
+/// * `email_verified` - Whether Shopify verified the email
+#[derive(Debug, Deserialize)]
+struct ShopifyUser {
+    /// Id of the user within the shop
+    id: u64,
+    /// The user's first name
+    #[serde(default, deserialize_with = "non_empty")]
+    first_name: Option<String>,
+    /// The user's last name
+    #[serde(default, deserialize_with = "non_empty")]
+    last_name: Option<String>,
+    /// The user's email
+    #[serde(default, deserialize_with = "non_empty")]
+    email: Option<String>,
+    /// Whether Shopify verified the email
+    #[serde(default, deserialize_with = "flag")]
+    email_verified: Option<bool>,
+}
+
+/// Normalizes the name of a shop
+///
+/// # Arguments
+///
+/// * `shop` - The shop's name, or its `myshopify.com` domain
+///
+/// # Returns
+///
+/// Returns the lowercase name of the shop, or `None` when it is not a
+/// valid `myshopify.com` subdomain
+fn shop_name(shop: &str) -> Option<String> {
+    let shop = shop.to_ascii_lowercase();
+    let name = shop.strip_suffix(SHOPIFY_DOMAIN).unwrap_or(&shop);
+    let valid = !name.is_empty()
+        && name.len() <= 63
+        && !name.starts_with('-')
+        && !name.ends_with('-')
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-');
+    valid.then(|| name.to_string())
+}
+
+/// Substitutes the shop into a configured endpoint
+///
+/// The placeholder is percent-encoded once it is part of a parsed URL's
+/// path, so both forms are replaced.
+///
+/// # Arguments
+///
+/// * `endpoint` - The endpoint containing the `{shop}` placeholder
+/// * `shop` - The validated name of the shop
+///
+/// # Returns
+///
+/// Returns the shop's endpoint
+fn shop_endpoint(endpoint: &str, shop: &str) -> String {
+    endpoint
+        .replace(SHOP_PLACEHOLDER, shop)
+        .replace("%7Bshop%7D", shop)
+}
+
+/// Shopify OAuth provider implementation
+///
+/// Every shop has its own OAuth endpoints, so the provider built from the
+/// configuration only holds endpoint templates. Each flow names its shop
+/// with the `shop` parameter of `/authorize`, and runs against a copy of
+/// the provider configured for that shop. Users are identified by the
+/// shop's domain and the user who approved the installation.
+///
+/// # Fields
+///
+/// * `client` - HTTP client for making API requests
+/// * `oauth_client` - Configured OAuth 2.0 client, with templated endpoints until a shop is set
+/// * `user_info_url` - The shop's access scopes endpoint, templated until a shop is set
+/// * `scopes` - OAuth scopes requested on authorization
+/// * `shops` - Names of the shops allowed to sign in, any shop when `None`
+/// * `shop` - Name of the flow's shop, `None` for the configured provider
+pub struct ShopifyProvider {
+    /// HTTP client for API requests
+    client: HttpClient,
+    /// Configured OAuth client
+    oauth_client: OAuthClient,
+    /// Shopify access scopes endpoint URL
+    user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
+    /// Names of the shops allowed to sign in
+    shops: Option<Vec<String>>,
+    /// Name of the flow's shop
+    shop: Option<String>,
+}
+
+impl ShopifyProvider {
+    /// Creates a new Shopify OAuth provider instance
+    ///
+    /// This constructor creates a new Shopify provider with the given
+    /// OAuth client, user info URL and allowed shops.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_client` - The HTTP client shared by every provider
+    /// * `oauth_client` - The configured OAuth client, with `{shop}` in its endpoints
+    /// * `user_info_url` - The URL of the access scopes endpoint, with `{shop}` in it
+    /// * `scopes` - Scopes configured for the authorization request
+    /// * `shops` - Shops allowed to sign in, any shop when `None`
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `ShopifyProvider` instance, or an error if an allowed
+    /// shop is not a valid shop name
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        scopes: Option<Vec<String>>,
+        shops: Option<Vec<String>>,
+    ) -> Result<Self> {
+        let shops = shops
+            .map(|shops| {
+                shops
+                    .iter()
+                    .map(|shop| shop_name(shop).ok_or_else(|| eyre!("invalid shop {}", shop)))
+                    .collect::<Result<Vec<_>>>()
+            })
+            .transpose()?;
+
+        Ok(Self {
+            client: http_client,
+            oauth_client,
+            user_info_url,
+            scopes: merge_scopes(&[], scopes),
+            shops,
+            shop: None,
+        })
+    }
+
+    /// Returns the domain of the flow's shop
+    ///
+    /// # Returns
+    ///
+    /// Returns the `myshopify.com` domain of the shop, or an error for the
+    /// configured provider, which is not bound to a shop
+    fn shop_domain(&self) -> Result<String> {
+        match &self.shop {
+            Some(shop) => Ok(format!("{}{}", shop, SHOPIFY_DOMAIN)),
+            None => bail!("Shopify requests need the shop of the flow"),
+        }
+    }
+
+    /// Sends a request to the shop's token endpoint
+    ///
+    /// The client credentials are sent where the configured `auth_type`
+    /// puts them, the request body by default.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The grant parameters
+    /// * `now` - The current unix timestamp
+    ///
+    /// # Returns
+    ///
+    /// Returns the issued token details, carrying the associated user of
+    /// online access tokens
+    async fn request_tokens(&self, params: &[(&str, &str)], now: u64) -> Result<TokenDetails> {
+        self.shop_domain()?;
+
+        let response = send_token_request(
+            &self.client,
+            &self.oauth_client,
+            self.token_request_headers(),
+            params,
+            TokenRequestBody::Form,
+            "Shopify token request failed",
+        )
+        .await?;
+
+        // The error body is not logged, it may echo the code back
+        if !response.status().is_success() {
+            bail!("Shopify token request failed: {}", response.status());
+        }
+
+        let token: ShopifyTokenResponse = response
+            .json()
+            .await
+            .map_err(|e| request_error("Shopify token request failed", e))?;
+        Ok(TokenDetails {
+            access_token: token.access_token,
+            refresh_token: None,
+            expires_at: token
+                .expires_in
+                .map(|expires_in| now.saturating_add(expires_in)),
+            scopes: token
+                .scope
+                .map(|scope| scope.split(',').map(str::to_string).collect())
+                .unwrap_or_else(|| self.scopes.clone()),
+            id_token: None,
+            user_data: token.associated_user,
+        })
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for ShopifyProvider {
+    /// Returns a reference to the OAuth client for Shopify
+    ///
+    /// # Returns
+    ///
+    /// Returns a reference to the configured `OAuthClient` instance
+    fn get_oauth_client(&self) -> &OAuthClient {
+        &self.oauth_client
+    }
+
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Returns the OAuth scopes requested from Shopify
+    ///
+    /// Shopify requires no scope to identify the user, only the configured
+    /// scopes are requested.
+    ///
+    /// # Returns
+    ///
+    /// Returns the configured scopes
+    fn get_scopes(&self) -> Vec<String> {
+        self.scopes.clone()
+    }
+
+    /// Returns the separator of Shopify's scopes
+    ///
+    /// # Returns
+    ///
+    /// Returns `','`, Shopify expects comma separated scopes
+    fn scope_separator(&self) -> char {
+        ','
+    }
+
+    /// Requests an online access token
+    ///
+    /// Only online access tokens name the user who approved the
+    /// installation.
+    ///
+    /// # Returns
+    ///
+    /// Returns `grant_options[]=per-user`
+    fn get_extra_auth_params(&self) -> Vec<(String, String)> {
+        vec![("grant_options[]".to_string(), "per-user".to_string())]
+    }
+
+    /// Opts out of PKCE
+    ///
+    /// Shopify's token endpoint does not support PKCE.
+    ///
+    /// # Returns
+    ///
+    /// Always returns `false`
+    fn supports_pkce(&self) -> bool {
+        false
+    }
+
+    /// Takes the shop of the flow from `/authorize`
+    ///
+    /// # Returns
+    ///
+    /// Returns `shop`
+    fn flow_params(&self) -> &'static [&'static str] {
+        &["shop"]
+    }
+
+    /// Returns the provider configured for the flow's shop
+    ///
+    /// The shop is accepted as a name or a `myshopify.com` domain, and must
+    /// be one of the allowed `shops` when they are configured.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The flow's parameters, with the `shop`
+    ///
+    /// # Returns
+    ///
+    /// Returns a provider whose endpoints are the shop's, or an error if
+    /// the shop is missing, invalid or not allowed
+    fn with_flow_params(
+        &self,
+        params: &BTreeMap<String, String>,
+    ) -> Result<Option<Arc<dyn OAuthProvider>>> {
+        let Some(shop) = params.get("shop") else {
+            bail!("Shopify sign-ins need a shop parameter");
+        };
+        let Some(shop) = shop_name(shop) else {
+            bail!("Invalid shop name");
+        };
+        if self
+            .shops
+            .as_ref()
+            .is_some_and(|shops| !shops.contains(&shop))
+        {
+            bail!("Shop {} is not allowed to sign in", shop);
+        }
+
+        let auth_url = shop_endpoint(self.oauth_client.auth_uri().as_str(), &shop);
+        let token_url = shop_endpoint(self.oauth_client.token_uri().as_str(), &shop);
+        let user_info_url = shop_endpoint(self.user_info_url.as_str(), &shop);
+        let auth_url = AuthUrl::new(auth_url)?;
+        let token_url = TokenUrl::new(token_url)?;
+        let oauth_client = self
+            .oauth_client
+            .clone()
+            .map(|client| client.set_auth_uri(auth_url).set_token_uri(token_url));
+
+        Ok(Some(Arc::new(ShopifyProvider {
+            client: Arc::clone(&self.client),
+            oauth_client,
+            user_info_url: Url::parse(&user_info_url)?,
+            scopes: self.scopes.clone(),
+            shops: self.shops.clone(),
+            shop: Some(shop),
+        })))
+    }
+
+    /// Identifies the shop from its access scopes endpoint
+    ///
+    /// Offline access tokens name no user, the shop the token was issued
+    /// for is then the identity. The request also checks that the token
+    /// belongs to the shop. Online access tokens are identified by their
+    /// associated user in `get_user_info_from_tokens` instead.
+    ///
+    /// # Arguments
+    ///
+    /// * `access_token` - The access token issued by the shop
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` identifying the shop, or an error if the
+    /// request fails
+    async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
+        let shop_domain = self.shop_domain()?;
+
+        let request = self
+            .client
+            .get(self.user_info_url.as_str())
+            .header("X-Shopify-Access-Token", access_token);
+
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|e| request_error("Failed to get user info from Shopify", e))?;
+
+        if !response.status().is_success() {
+            bail!(
+                "Failed to get user info from Shopify: {}",
+                response.status()
+            );
+        }
+
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from Shopify", e))?;
+        Ok(UserInfo {
+            id: shop_domain.clone(),
+            provider: "shopify".to_string(),
+            email: None,
+            name: None,
+            username: None,
+            avatar_url: None,
+            email_verified: None,
+            tenant: Some(shop_domain),
+            raw: Some(user_data),
+        })
+    }
+
+    /// Identifies the user associated with the access token
+    ///
+    /// # Arguments
+    ///
+    /// * `tokens` - The tokens returned by the code exchange
+    ///
+    /// # Returns
+    ///
+    /// Returns the associated user within the shop, the shop itself for
+    /// offline access tokens, or an error for an online access token that
+    /// names no user
+    async fn get_user_info_from_tokens(&self, tokens: &TokenDetails) -> Result<UserInfo> {
+        match &tokens.user_data {
+            Some(user_data) => parse_user_info(&self.shop_domain()?, user_data),
+            // Only offline access tokens never expire, every staff member
+            // signing in with an online one would share the shop's identity
+            None if tokens.expires_at.is_some() => {
+                bail!("Shopify online access token names no associated user")
+            }
+            None => self.get_user_info(&tokens.access_token).await,
+        }
+    }
+
+    /// Exchanges an authorization code for tokens
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The authorization code returned by Shopify
+    /// * `_pkce_verifier` - Unused, Shopify does not support PKCE
+    /// * `now` - The current unix timestamp
+    ///
+    /// # Returns
+    ///
+    /// Returns the issued token details
+    async fn exchange_code(
+        &self,
+        code: &str,
+        _pkce_verifier: Option<PkceCodeVerifier>,
+        now: u64,
+    ) -> Result<TokenDetails> {
+        self.request_tokens(&[("code", code)], now).await
+    }
+
+    /// Refuses to refresh tokens
+    ///
+    /// Shopify issues no refresh tokens, online access tokens are renewed
+    /// by signing in again.
+    ///
+    /// # Returns
+    ///
+    /// Always returns a `RefreshUnsupported` error
+    async fn refresh_token(&self, _refresh_token: &str, _now: u64) -> Result<TokenDetails> {
+        Err(RefreshUnsupported.into())
+    }
+}
+
+/// Extracts the user information from the associated user of a token
+///
+/// The user's id is only unique within its shop, so users are identified
+/// by `{shop domain}/{user id}`.
+///
+/// # Arguments
+///
+/// * `shop_domain` - The `myshopify.com` domain of the shop
+/// * `user_data` - The `associated_user` of the token response
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` containing the user's id, or an error if
+/// the id is missing or a field has an unexpected type
+fn parse_user_info(shop_domain: &str, user_data: &serde_json::Value) -> Result<UserInfo> {
+    let user: ShopifyUser = parse_response("Shopify associated user", user_data)?;
+    let name = match (user.first_name, user.last_name) {
+        (Some(first_name), Some(last_name)) => Some(format!("{} {}", first_name, last_name)),
+        (first_name, last_name) => first_name.or(last_name),
+    };
+
+    Ok(UserInfo {
+        id: format!("{}/{}", shop_domain, user.id),
+        provider: "shopify".to_string(),
+        email: user.email,
+        name,
+        username: None,
+        avatar_url: None,
+        email_verified: user.email_verified,
+        tenant: Some(shop_domain.to_string()),
+        raw: Some(user_data.clone()),
+    })
+}
+
+/// Factory for creating Shopify OAuth provider instances
+///
+/// This struct implements the factory pattern for creating Shopify
+/// OAuth provider instances. It allows the application to create
+/// provider instances dynamically based on configuration.
+pub struct ShopifyProviderFactory;
+
+impl OAuthProviderFactory for ShopifyProviderFactory {
+    /// Creates a new Shopify OAuth provider instance
+    ///
+    /// This method creates a new Shopify provider with the given
+    /// OAuth client, user info URL and allowed shops.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, whose
+    ///   `scopes` are requested and whose `shops` may sign in
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created Shopify
+    /// provider, or an error if an allowed shop is invalid
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        Ok(Arc::new(ShopifyProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.scopes.clone(),
+            settings.shops.clone(),
+        )?))
+    }
+
+    /// Returns the endpoint templates of every shop
+    ///
+    /// # Arguments
+    ///
+    /// * `_settings` - The provider's settings
+    ///
+    /// # Returns
+    ///
+    /// Returns the authorization, token and access scopes endpoints under
+    /// `https://{shop}.myshopify.com`
+    fn default_endpoints(&self, _settings: &OAuthSettings) -> Option<ProviderEndpoints> {
+        let base_url = format!("https://{}{}", SHOP_PLACEHOLDER, SHOPIFY_DOMAIN);
+
+        Some(ProviderEndpoints {
+            auth_url: format!("{}/admin/oauth/authorize", base_url),
+            token_url: format!("{}/admin/oauth/access_token", base_url),
+            user_info_url: format!("{}/admin/oauth/access_scopes.json", base_url),
+            jwks_url: None,
+        })
+    }
+
+    /// Returns how Shopify expects the client credentials
+    ///
+    /// Shopify expects the client credentials in the request body.
+    ///
+    /// # Returns
+    ///
+    /// Returns `TokenAuthType::RequestBody`
+    fn default_auth_type(&self) -> TokenAuthType {
+        TokenAuthType::RequestBody
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockApi;
+    use axum::http::StatusCode;
+
+    /// Creates a Shopify provider whose endpoints are templated under the API
+    async fn provider(api: &MockApi, shops: Option<Vec<String>>) -> Arc<dyn OAuthProvider> {
+        let settings = OAuthSettings {
+            shops,
+            ..api.settings(
+                "/{shop}/admin/oauth/authorize",
+                "/{shop}/admin/oauth/access_token",
+                "/{shop}/admin/oauth/access_scopes.json",
+            )
+        };
+        api.provider("shopify", settings).await.unwrap()
+    }
+
+    /// Tests that shops are accepted by name or domain, and that anything
+    /// else is refused
+    #[test]
+    fn test_shop_name() {
+        assert_eq!(shop_name("my-store").as_deref(), Some("my-store"));
+        assert_eq!(
+            shop_name("My-Store.myshopify.com").as_deref(),
+            Some("my-store")
+        );
+        for shop in [
+            "",
+            ".myshopify.com",
+            "-store",
+            "my-store-",
+            "my-store-.myshopify.com",
+            "my-store.example.com",
+            "my_store",
+            "evil.com/x",
+            "my-store.myshopify.com.evil.com",
+        ] {
+            assert_eq!(shop_name(shop), None, "{}", shop);
+        }
+    }
+
+    /// Tests that the flow's shop is substituted into every endpoint, and
+    /// only allowed shops are accepted
+    #[tokio::test]
+    async fn test_with_flow_params() {
+        let api = MockApi::builder().start().await.unwrap();
+        let shop = |shop: &str| BTreeMap::from([("shop".to_string(), shop.to_string())]);
+
+        let provider = provider(&api, Some(vec!["My-Store.myshopify.com".to_string()])).await;
+        assert!(provider.with_flow_params(&BTreeMap::new()).is_err());
+        assert!(provider.with_flow_params(&shop("other-store")).is_err());
+
+        let configured = provider
+            .with_flow_params(&shop("my-store"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            configured.get_oauth_client().auth_uri().as_str(),
+            api.endpoint("/my-store/admin/oauth/authorize")
+        );
+        assert_eq!(
+            configured.get_oauth_client().token_uri().as_str(),
+            api.endpoint("/my-store/admin/oauth/access_token")
+        );
+
+        let provider = ShopifyProviderFactory;
+        let endpoints = provider
+            .default_endpoints(&OAuthSettings::default())
+            .unwrap();
+        assert_eq!(
+            shop_endpoint(&endpoints.auth_url, "my-store"),
+            "https://my-store.myshopify.com/admin/oauth/authorize"
+        );
+    }
+
+    /// Tests that users of online access tokens are identified within their shop
+    #[test]
+    fn test_parse_associated_user() {
+        let user_data = serde_json::json!({
+            "id": 902541635,
+            "first_name": "John",
+            "last_name": "Smith",
+            "email": "john@example.com",
+            "email_verified": true,
+            "account_owner": true,
+            "locale": "en",
+            "collaborator": false
+        });
+
+        let user_info = parse_user_info("my-store.myshopify.com", &user_data).unwrap();
+        assert_eq!(user_info.id, "my-store.myshopify.com/902541635");
+        assert_eq!(user_info.provider, "shopify");
+        assert_eq!(user_info.email.as_deref(), Some("john@example.com"));
+        assert_eq!(user_info.email_verified, Some(true));
+        assert_eq!(user_info.name.as_deref(), Some("John Smith"));
+        assert_eq!(user_info.tenant.as_deref(), Some("my-store.myshopify.com"));
+        assert_eq!(user_info.raw, Some(user_data));
+
+        let error = parse_user_info("my-store.myshopify.com", &serde_json::json!({ "id": "x" }))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.starts_with("Invalid Shopify associated user response at id:"),
+            "{}",
+            error
+        );
+    }
+
+    /// Tests that offline access tokens identify the shop through its access
+    /// scopes endpoint
+    #[tokio::test]
+    async fn test_offline_token_identifies_shop() {
+        let api = MockApi::builder()
+            .get("/my-store/admin/oauth/access_scopes.json", |_| {
+                (
+                    StatusCode::OK,
+                    serde_json::json!({ "access_scopes": [{ "handle": "read_orders" }] }),
+                )
+            })
+            .start()
+            .await
+            .unwrap();
+
+        let provider = provider(&api, None).await;
+        let tokens = TokenDetails {
+            access_token: "shpat_offline".to_string(),
+            refresh_token: None,
+            expires_at: None,
+            scopes: vec!["read_orders".to_string()],
+            id_token: None,
+            user_data: None,
+        };
+        assert!(provider.get_user_info_from_tokens(&tokens).await.is_err());
+
+        let provider = provider
+            .with_flow_params(&BTreeMap::from([(
+                "shop".to_string(),
+                "my-store".to_string(),
+            )]))
+            .unwrap()
+            .unwrap();
+        let user_info = provider.get_user_info_from_tokens(&tokens).await.unwrap();
+        assert_eq!(user_info.id, "my-store.myshopify.com");
+        assert_eq!(user_info.tenant.as_deref(), Some("my-store.myshopify.com"));
+        assert_eq!(user_info.email, None);
+        let requests = api.requests("/my-store/admin/oauth/access_scopes.json");
+        assert_eq!(
+            requests[0].header("x-shopify-access-token"),
+            Some("shpat_offline")
+        );
+    }
+
+    /// Tests that online access tokens without an associated user are not
+    /// identified as their shop
+    #[tokio::test]
+    async fn test_online_token_without_user() {
+        let api = MockApi::builder().start().await.unwrap();
+        let provider = provider(&api, None)
+            .await
+            .with_flow_params(&BTreeMap::from([(
+                "shop".to_string(),
+                "my-store".to_string(),
+            )]))
+            .unwrap()
+            .unwrap();
+        let tokens = TokenDetails {
+            access_token: "shpua_online".to_string(),
+            refresh_token: None,
+            expires_at: Some(86_400),
+            scopes: vec!["read_orders".to_string()],
+            id_token: None,
+            user_data: None,
+        };
+
+        let error = provider
+            .get_user_info_from_tokens(&tokens)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Shopify online access token names no associated user"
+        );
+    }
+}
@@ -0,0 +1,280 @@
+use crate::{
+    primitives::{ProviderContext, UserInfo},
+    providers::{merge_scopes, oidc_user_info, parse_response, OidcUserInfoResponse},
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
+};
+use axum::async_trait;
+use eyre::{bail, Result};
+use reqwest::Url;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Status of a Slack Web API response
+///
+/// # Fields
+///
+/// * `ok` - Whether the call succeeded
+/// * `error` - Error code of a failed call
+#[derive(Debug, Deserialize)]
+struct SlackResponseStatus {
+    /// Whether the call succeeded
+    #[serde(default)]
+    ok: bool,
+    /// Error code of a failed call
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Slack OAuth provider implementation
+///
+/// This struct implements the OAuth provider interface for Sign in with
+/// Slack, Slack's OpenID Connect product. It handles OAuth 2.0
+/// authentication flow and user information retrieval from Slack's
+/// `openid.connect.userInfo` method.
+///
+/// # Fields
+///
+/// * `client` - HTTP client for making API requests
+/// * `oauth_client` - Configured OAuth 2.0 client
+/// * `user_info_url` - Slack's user info endpoint URL
+/// * `scopes` - OAuth scopes requested on authorization
+pub struct SlackProvider {
+    /// HTTP client for API requests
+    client: HttpClient,
+    /// Configured OAuth client
+    oauth_client: OAuthClient,
+    /// Slack user info endpoint URL
+    user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
+}
+
+impl SlackProvider {
+    /// Creates a new Slack OAuth provider instance
+    ///
+    /// This constructor creates a new Slack provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_client` - The HTTP client shared by every provider
+    /// * `oauth_client` - The configured OAuth client for Slack
+    /// * `user_info_url` - The URL for Slack's user info endpoint
+    /// * `scopes` - Scopes configured in addition to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `SlackProvider` instance
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        scopes: Option<Vec<String>>,
+    ) -> Self {
+        Self {
+            client: http_client,
+            oauth_client,
+            user_info_url,
+            scopes: merge_scopes(&["openid", "profile", "email"], scopes),
+        }
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for SlackProvider {
+    /// Returns a reference to the OAuth client for Slack
+    ///
+    /// # Returns
+    ///
+    /// Returns a reference to the configured `OAuthClient` instance
+    fn get_oauth_client(&self) -> &OAuthClient {
+        &self.oauth_client
+    }
+
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Returns the OAuth scopes required for Slack
+    ///
+    /// Slack requires the "openid", "profile" and "email" scopes to access
+    /// the user's identity through `openid.connect.userInfo`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the required scopes followed by the configured ones
+    fn get_scopes(&self) -> Vec<String> {
+        self.scopes.clone()
+    }
+
+    /// Fetches user information from Slack's `openid.connect.userInfo` method
+    ///
+    /// This method makes an authenticated request to Slack to
+    /// retrieve the signed-in user's profile.
+    ///
+    /// # Arguments
+    ///
+    /// * `access_token` - The OAuth access token obtained from Slack
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the user's identifier
+    /// or an error if the request fails
+    async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
+        let request = self
+            .client
+            .get(self.user_info_url.as_str())
+            .header("Authorization", format!("Bearer {}", access_token));
+
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|e| request_error("Failed to get user info from Slack", e))?;
+
+        if !response.status().is_success() {
+            bail!("Failed to get user info from Slack: {}", response.status());
+        }
+
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from Slack", e))?;
+        parse_user_info(&user_data)
+    }
+}
+
+/// Extracts the user information from a Slack userinfo response
+///
+/// Slack answers failed API calls with a 200 and `"ok": false`, so the
+/// `ok` flag is checked before the claims are read. The `sub` claim, which
+/// encodes both the team and the user, is used as the user's identifier.
+///
+/// # Arguments
+///
+/// * `user_data` - The JSON body returned by the userinfo method
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` containing the user's identifier, or an
+/// error if Slack reported a failure, the claim is missing or a claim has
+/// an unexpected type
+fn parse_user_info(user_data: &serde_json::Value) -> Result<UserInfo> {
+    let status: SlackResponseStatus = parse_response("Slack user info", user_data)?;
+    if !status.ok {
+        bail!(
+            "Failed to get user info from Slack: {}",
+            status.error.as_deref().unwrap_or("unknown error")
+        );
+    }
+
+    let claims: OidcUserInfoResponse = parse_response("Slack user info", user_data)?;
+    let id = claims
+        .sub
+        .clone()
+        .ok_or_else(|| eyre::eyre!("No sub field in Slack user info response"))?;
+
+    Ok(oidc_user_info(id, "slack", claims, user_data))
+}
+
+/// Factory for creating Slack OAuth provider instances
+///
+/// This struct implements the factory pattern for creating Slack
+/// OAuth provider instances. It allows the application to create
+/// provider instances dynamically based on configuration.
+pub struct SlackProviderFactory;
+
+impl OAuthProviderFactory for SlackProviderFactory {
+    /// Creates a new Slack OAuth provider instance
+    ///
+    /// This method creates a new Slack provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, whose `scopes` are
+    ///   added to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created Slack provider
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        Ok(Arc::new(SlackProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.scopes.clone(),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that the user id is extracted from a recorded successful response
+    #[test]
+    fn test_parse_ok_response() {
+        let user_data = serde_json::json!({
+            "ok": true,
+            "sub": "U0R7JM",
+            "https://slack.com/user_id": "U0R7JM",
+            "https://slack.com/team_id": "T0R7GR",
+            "email": "krane@slack-corp.com",
+            "email_verified": true,
+            "date_email_verified": 1622128723,
+            "name": "krane",
+            "picture": "https://secure.gravatar.com/avatar/bc6e7ecad89a03dd5b5a1d4bad5e6d4c.jpg",
+            "given_name": "Bront",
+            "family_name": "Labradoodle",
+            "locale": "en-US",
+            "https://slack.com/team_name": "kraneflannel",
+            "https://slack.com/team_domain": "kraneflannel"
+        });
+
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "U0R7JM");
+        assert_eq!(user_info.provider, "slack");
+    }
+
+    /// Tests that a 200 response with `"ok": false` is a failure
+    #[test]
+    fn test_parse_not_ok_response() {
+        let error = parse_user_info(&serde_json::json!({
+            "ok": false,
+            "error": "invalid_auth"
+        }))
+        .unwrap_err();
+        assert!(error.to_string().contains("invalid_auth"));
+    }
+
+    /// Tests that fields the server does not read are ignored, and that a
+    /// field with an unexpected type is reported with its path
+    #[test]
+    fn test_response_fields() {
+        let user_data = serde_json::json!({ "ok": true, "sub": "U0R7JM", "https://slack.com/team_id": "T0R7GR", "https://slack.com/team_name": "Kraneflannel" });
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "U0R7JM");
+
+        let user_data = serde_json::json!({ "ok": true, "sub": "U0R7JM", "email_verified": 1 });
+        let error = parse_user_info(&user_data).unwrap_err().to_string();
+        assert!(
+            error.starts_with("Invalid Slack user info response at email_verified:"),
+            "{}",
+            error
+        );
+    }
+}
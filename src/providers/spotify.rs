@@ -1,13 +1,69 @@
 use crate::{
-    primitives::UserInfo,
-    traits::{OAuthProvider, OAuthProviderFactory},
-    types::OAuthClient,
+    primitives::{ProviderContext, ProviderDisplay, UserInfo},
+    providers::{merge_scopes, non_empty, parse_response},
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
 };
 use axum::async_trait;
 use eyre::Result;
-use reqwest::{Client, Url};
+use reqwest::Url;
+use serde::Deserialize;
 use std::sync::Arc;
 
+/// Spotify `/me` response
+///
+/// # Fields
+///
+/// * `id` - Spotify user id
+/// * `display_name` - The user's display name
+/// * `email` - The user's email, with the `user-read-email` scope
+/// * `images` - The user's profile images
+#[derive(Debug, Deserialize)]
+struct SpotifyUserResponse {
+    /// Spotify user id
+    id: String,
+    /// The user's display name
+    #[serde(default, deserialize_with = "non_empty")]
+    display_name: Option<String>,
+    /// The user's email
+    #[serde(default, deserialize_with = "non_empty")]
+    email: Option<String>,
+    /// The user's profile images
+    #[serde(default)]
+    images: Vec<SpotifyImage>,
+}
+
+/// Profile image of a Spotify user
+///
+/// # Fields
+///
+/// * `url` - URL of the image
+#[derive(Debug, Deserialize)]
+struct SpotifyImage {
+    /// URL of the image
+    #[serde(default, deserialize_with = "non_empty")]
+    url: Option<String>,
+}
+
+impl From<SpotifyUserResponse> for UserInfo {
+    /// Maps a Spotify user to the user information
+    ///
+    /// The first of the user's profile images is used as the avatar.
+    fn from(user: SpotifyUserResponse) -> Self {
+        UserInfo {
+            id: user.id,
+            provider: "spotify".to_string(),
+            email: user.email,
+            name: user.display_name,
+            username: None,
+            avatar_url: user.images.into_iter().next().and_then(|image| image.url),
+            email_verified: None,
+            tenant: None,
+            raw: None,
+        }
+    }
+}
+
 /// Spotify OAuth provider implementation
 ///
 /// This struct implements the OAuth provider interface for Spotify.
@@ -19,13 +75,16 @@ use std::sync::Arc;
 /// * `oauth_client` - Configured OAuth 2.0 client
 /// * `client` - HTTP client for making API requests
 /// * `user_info_url` - Spotify's user info endpoint URL
+/// * `scopes` - OAuth scopes requested on authorization
 pub struct SpotifyProvider {
     /// Configured OAuth client
     oauth_client: OAuthClient,
     /// HTTP client for API requests
-    client: Client,
+    client: HttpClient,
     /// Spotify user info endpoint URL
     user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
 }
 
 impl SpotifyProvider {
@@ -36,17 +95,25 @@ impl SpotifyProvider {
     ///
     /// # Arguments
     ///
+    /// * `http_client` - The HTTP client shared by every provider
     /// * `oauth_client` - The configured OAuth client for Spotify
     /// * `user_info_url` - The URL for Spotify's user info endpoint
+    /// * `scopes` - Scopes configured in addition to the required ones
     ///
     /// # Returns
     ///
     /// Returns a new `SpotifyProvider` instance
-    pub fn new(oauth_client: OAuthClient, user_info_url: Url) -> Self {
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        scopes: Option<Vec<String>>,
+    ) -> Self {
         Self {
             oauth_client,
-            client: Client::new(),
+            client: http_client,
             user_info_url,
+            scopes: merge_scopes(&["user-read-email"], scopes),
         }
     }
 }
@@ -62,6 +129,15 @@ impl OAuthProvider for SpotifyProvider {
         &self.oauth_client
     }
 
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
     /// Returns the OAuth scopes required for Spotify
     ///
     /// Spotify requires the "user-read-email" scope to access the user's
@@ -69,9 +145,22 @@ impl OAuthProvider for SpotifyProvider {
     ///
     /// # Returns
     ///
-    /// Returns a vector containing the "user-read-email" scope
+    /// Returns the required scopes followed by the configured ones
     fn get_scopes(&self) -> Vec<String> {
-        vec!["user-read-email".to_string()]
+        self.scopes.clone()
+    }
+
+    /// Returns the Spotify brand color and logo
+    ///
+    /// # Returns
+    ///
+    /// Returns the branding of the Spotify sign-in button
+    fn display_metadata(&self) -> Option<ProviderDisplay> {
+        Some(ProviderDisplay {
+            label: "Spotify",
+            color: "#1ed760",
+            icon: r#"<path fill="currentColor" d="M12 0C5.4 0 0 5.4 0 12s5.4 12 12 12 12-5.4 12-12S18.66 0 12 0zm5.521 17.34c-.24.359-.66.48-1.021.24-2.82-1.74-6.36-2.101-10.561-1.141-.418.122-.779-.179-.899-.539-.12-.421.18-.78.54-.9 4.56-1.021 8.52-.6 11.64 1.32.42.18.479.659.301 1.02zm1.44-3.3c-.301.42-.841.6-1.262.3-3.239-1.98-8.159-2.58-11.939-1.38-.479.12-1.02-.12-1.14-.6-.12-.48.12-1.021.6-1.141C9.6 9.9 15 10.561 18.72 12.84c.361.181.54.78.241 1.2zm.12-3.36C15.24 8.4 8.82 8.16 5.16 9.301c-.6.179-1.2-.181-1.38-.721-.18-.601.18-1.2.72-1.381 4.26-1.26 11.28-1.02 15.721 1.621.539.3.719 1.02.419 1.56-.299.421-1.02.599-1.559.3z"/>"#,
+        })
     }
 
     /// Fetches user information from Spotify's user info endpoint
@@ -86,7 +175,7 @@ impl OAuthProvider for SpotifyProvider {
     ///
     /// # Returns
     ///
-    /// Returns `Result<UserInfo>` containing the user's ID
+    /// Returns `Result<UserInfo>` containing the user's ID and profile
     /// or an error if the request fails
     async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
         let request = self
@@ -94,10 +183,11 @@ impl OAuthProvider for SpotifyProvider {
             .get(self.user_info_url.as_str())
             .header("Authorization", format!("Bearer {}", access_token));
 
-        let response = request
-            .send()
+        let response = self
+            .client
+            .send(request)
             .await
-            .map_err(|e| eyre::eyre!("Failed to get user info from Spotify: {}", e))?;
+            .map_err(|e| request_error("Failed to get user info from Spotify", e))?;
 
         if !response.status().is_success() {
             return Err(eyre::eyre!(
@@ -106,19 +196,32 @@ impl OAuthProvider for SpotifyProvider {
             ));
         }
 
-        let user_data: serde_json::Value = response.json().await?;
-        let id = user_data
-            .get("id")
-            .ok_or_else(|| eyre::eyre!("No id field in Spotify user info response"))?
-            .to_string();
-
-        Ok(UserInfo {
-            id,
-            provider: "spotify".to_string(),
-        })
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from Spotify", e))?;
+        parse_user_info(&user_data)
     }
 }
 
+/// Extracts the user information from a Spotify `/me` response
+///
+/// # Arguments
+///
+/// * `user_data` - The JSON body returned by the user info endpoint
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` containing the user's ID and profile
+/// or an error if the ID is missing or a field has an unexpected type
+fn parse_user_info(user_data: &serde_json::Value) -> Result<UserInfo> {
+    let user: SpotifyUserResponse = parse_response("Spotify user info", user_data)?;
+    Ok(UserInfo {
+        raw: Some(user_data.clone()),
+        ..user.into()
+    })
+}
+
 /// Factory for creating Spotify OAuth provider instances
 ///
 /// This struct implements the factory pattern for creating Spotify
@@ -134,13 +237,102 @@ impl OAuthProviderFactory for SpotifyProviderFactory {
     ///
     /// # Arguments
     ///
-    /// * `oauth_client` - The configured OAuth client for Spotify
-    /// * `user_info_url` - The URL for Spotify's user info endpoint
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, whose `scopes` are
+    ///   added to the required ones
     ///
     /// # Returns
     ///
     /// Returns an `Arc<dyn OAuthProvider>` containing the created Spotify provider
-    fn create(&self, oauth_client: OAuthClient, user_info_url: Url) -> Arc<dyn OAuthProvider> {
-        Arc::new(SpotifyProvider::new(oauth_client, user_info_url))
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        Ok(Arc::new(SpotifyProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.scopes.clone(),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that the profile is extracted from a recorded user response
+    #[test]
+    fn test_parse_user_response() {
+        let user_data = serde_json::json!({
+            "country": "SE",
+            "display_name": "Jane Doe",
+            "email": "jane.doe@example.com",
+            "external_urls": { "spotify": "https://open.spotify.com/user/smedjan" },
+            "followers": { "href": null, "total": 12 },
+            "href": "https://api.spotify.com/v1/users/smedjan",
+            "id": "smedjan",
+            "images": [
+                { "url": "https://i.scdn.co/image/ab6775700000ee85a1b2c3", "height": 300, "width": 300 },
+                { "url": "https://i.scdn.co/image/ab67757000003b82a1b2c3", "height": 64, "width": 64 }
+            ],
+            "product": "premium",
+            "type": "user",
+            "uri": "spotify:user:smedjan"
+        });
+
+        let user_info = parse_user_info(&user_data).unwrap();
+        // The id is the bare string, without the JSON quotes
+        assert_eq!(user_info.id, "smedjan");
+        assert_eq!(user_info.provider, "spotify");
+        assert_eq!(user_info.email.as_deref(), Some("jane.doe@example.com"));
+        assert_eq!(user_info.name.as_deref(), Some("Jane Doe"));
+        assert_eq!(
+            user_info.avatar_url.as_deref(),
+            Some("https://i.scdn.co/image/ab6775700000ee85a1b2c3")
+        );
+        assert_eq!(user_info.raw, Some(user_data));
+    }
+
+    /// Tests that missing and non-string ids are rejected
+    #[test]
+    fn test_invalid_id() {
+        for user_data in [
+            serde_json::json!({ "display_name": "Jane Doe" }),
+            serde_json::json!({ "id": null }),
+            serde_json::json!({ "id": 42 }),
+        ] {
+            assert!(parse_user_info(&user_data).is_err(), "{}", user_data);
+        }
+    }
+
+    /// Tests that users without profile images have no avatar
+    #[test]
+    fn test_no_images() {
+        let user_data = serde_json::json!({ "id": "smedjan", "display_name": null, "images": [] });
+
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.name, None);
+        assert_eq!(user_info.avatar_url, None);
+    }
+
+    /// Tests that fields the server does not read are ignored, and that a
+    /// field with an unexpected type is reported with its path
+    #[test]
+    fn test_response_fields() {
+        let user_data = serde_json::json!({ "id": "smedjan", "display_name": "JM Wizzler", "followers": { "total": 3829 }, "product": "premium", "images": [{ "url": "https://i.scdn.co/image/ab67", "height": 300, "width": 300 }] });
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "smedjan");
+
+        let user_data = serde_json::json!({ "id": "smedjan", "images": [{ "url": 300 }] });
+        let error = parse_user_info(&user_data).unwrap_err().to_string();
+        assert!(
+            error.starts_with("Invalid Spotify user info response at images[0].url:"),
+            "{}",
+            error
+        );
     }
 }
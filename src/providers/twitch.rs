@@ -0,0 +1,472 @@
+use crate::{
+    primitives::{ProviderContext, TokenDetails, UserInfo},
+    providers::{merge_scopes, non_empty, parse_response, send_token_request, TokenRequestBody},
+    settings::TokenAuthType,
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
+};
+use axum::async_trait;
+use eyre::{bail, eyre, Result};
+use oauth2::PkceCodeVerifier;
+use reqwest::Url;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Helix `GET /users` response
+///
+/// # Fields
+///
+/// * `data` - The requested users, the token's user when none is named
+#[derive(Debug, Deserialize)]
+struct TwitchUsersResponse {
+    /// The requested users
+    data: Vec<TwitchUser>,
+}
+
+/// User returned by Helix
+///
+/// # Fields
+///
+/// * `id` - Numeric id of the user, as a string
+/// * `login` - The user's login name
+/// * `display_name` - The user's display name
+/// * `email` - The user's email, with the `user:read:email` scope
+/// * `profile_image_url` - URL of the user's profile image
+#[derive(Debug, Deserialize)]
+struct TwitchUser {
+    /// Numeric id of the user
+    id: String,
+    /// The user's login name
+    #[serde(default, deserialize_with = "non_empty")]
+    login: Option<String>,
+    /// The user's display name
+    #[serde(default, deserialize_with = "non_empty")]
+    display_name: Option<String>,
+    /// The user's email
+    #[serde(default, deserialize_with = "non_empty")]
+    email: Option<String>,
+    /// URL of the user's profile image
+    #[serde(default, deserialize_with = "non_empty")]
+    profile_image_url: Option<String>,
+}
+
+impl From<TwitchUser> for UserInfo {
+    /// Maps a Twitch user to the user information
+    fn from(user: TwitchUser) -> Self {
+        UserInfo {
+            id: user.id,
+            provider: "twitch".to_string(),
+            email: user.email,
+            name: user.display_name,
+            username: user.login,
+            avatar_url: user.profile_image_url,
+            email_verified: None,
+            tenant: None,
+            raw: None,
+        }
+    }
+}
+
+/// Token response of Twitch's token endpoint
+///
+/// Twitch returns `scope` as a JSON array instead of the space-delimited
+/// string of RFC 6749, which the standard token response cannot parse.
+///
+/// # Fields
+///
+/// * `access_token` - The access token
+/// * `expires_in` - Lifetime of the access token in seconds
+/// * `refresh_token` - The refresh token
+/// * `scope` - Scopes granted by Twitch
+#[derive(Deserialize)]
+struct TwitchTokenResponse {
+    /// The access token
+    access_token: String,
+    /// Lifetime of the access token in seconds
+    expires_in: Option<u64>,
+    /// The refresh token
+    refresh_token: Option<String>,
+    /// Scopes granted by Twitch
+    scope: Option<Vec<String>>,
+}
+
+/// Twitch OAuth provider implementation
+///
+/// This struct implements the OAuth provider interface for Twitch.
+/// It handles OAuth 2.0 authentication flow and user information
+/// retrieval from the Helix API, which requires the app's `Client-Id`
+/// header alongside the access token.
+///
+/// # Fields
+///
+/// * `client` - HTTP client for making API requests
+/// * `oauth_client` - Configured OAuth 2.0 client
+/// * `user_info_url` - Twitch's user info endpoint URL
+/// * `scopes` - OAuth scopes requested on authorization
+/// * `client_id` - OAuth client ID, sent as `Client-Id` header
+pub struct TwitchProvider {
+    /// HTTP client for API requests
+    client: HttpClient,
+    /// Configured OAuth client
+    oauth_client: OAuthClient,
+    /// Twitch user info endpoint URL
+    user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
+    /// OAuth client ID
+    client_id: String,
+}
+
+impl TwitchProvider {
+    /// Creates a new Twitch OAuth provider instance
+    ///
+    /// This constructor creates a new Twitch provider with the given
+    /// OAuth client, user info URL and client credentials.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_client` - The HTTP client shared by every provider
+    /// * `oauth_client` - The configured OAuth client for Twitch
+    /// * `user_info_url` - The URL for Twitch's user info endpoint
+    /// * `client_id` - The OAuth client ID, sent as `Client-Id` header
+    /// * `scopes` - Scopes configured in addition to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `TwitchProvider` instance
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        client_id: String,
+        scopes: Option<Vec<String>>,
+    ) -> Self {
+        Self {
+            client: http_client,
+            oauth_client,
+            user_info_url,
+            scopes: merge_scopes(&["user:read:email"], scopes),
+            client_id,
+        }
+    }
+
+    /// Sends a request to Twitch's token endpoint
+    ///
+    /// The client credentials are sent where the configured `auth_type`
+    /// puts them, the request body by default as Twitch requires.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The grant parameters
+    /// * `now` - The current unix timestamp
+    ///
+    /// # Returns
+    ///
+    /// Returns the issued token details
+    async fn request_tokens(&self, params: &[(&str, &str)], now: u64) -> Result<TokenDetails> {
+        let response = send_token_request(
+            &self.client,
+            &self.oauth_client,
+            self.token_request_headers(),
+            params,
+            TokenRequestBody::Form,
+            "Twitch token request failed",
+        )
+        .await?;
+
+        // The error body is not logged, it may echo the code back
+        if !response.status().is_success() {
+            bail!("Twitch token request failed: {}", response.status());
+        }
+
+        let token: TwitchTokenResponse = response
+            .json()
+            .await
+            .map_err(|e| request_error("Twitch token request failed", e))?;
+        Ok(TokenDetails {
+            access_token: token.access_token,
+            refresh_token: token.refresh_token,
+            expires_at: token
+                .expires_in
+                .map(|expires_in| now.saturating_add(expires_in)),
+            scopes: token.scope.unwrap_or_else(|| self.scopes.clone()),
+            id_token: None,
+            user_data: None,
+        })
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for TwitchProvider {
+    /// Returns a reference to the OAuth client for Twitch
+    ///
+    /// # Returns
+    ///
+    /// Returns a reference to the configured `OAuthClient` instance
+    fn get_oauth_client(&self) -> &OAuthClient {
+        &self.oauth_client
+    }
+
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Returns the OAuth scopes required for Twitch
+    ///
+    /// Twitch requires the "user:read:email" scope to access the
+    /// signed-in user through Helix `GET /users`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the required scopes followed by the configured ones
+    fn get_scopes(&self) -> Vec<String> {
+        self.scopes.clone()
+    }
+
+    /// Fetches user information from Twitch's Helix `GET /users` endpoint
+    ///
+    /// This method makes an authenticated request to Twitch to
+    /// retrieve the signed-in user, with the `Client-Id` header Helix
+    /// requires.
+    ///
+    /// # Arguments
+    ///
+    /// * `access_token` - The OAuth access token obtained from Twitch
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the user's identifier
+    /// or an error if the request fails
+    async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
+        let request = self
+            .client
+            .get(self.user_info_url.as_str())
+            .header("Authorization", format!("Bearer {}", access_token))
+            .header("Client-Id", &self.client_id);
+
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|e| request_error("Failed to get user info from Twitch", e))?;
+
+        if !response.status().is_success() {
+            bail!("Failed to get user info from Twitch: {}", response.status());
+        }
+
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from Twitch", e))?;
+        parse_user_info(&user_data)
+    }
+
+    /// Exchanges an authorization code for tokens
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The authorization code returned by Twitch
+    /// * `pkce_verifier` - The PKCE verifier of the flow, `None` without PKCE
+    /// * `now` - The current unix timestamp
+    ///
+    /// # Returns
+    ///
+    /// Returns the issued token details
+    async fn exchange_code(
+        &self,
+        code: &str,
+        pkce_verifier: Option<PkceCodeVerifier>,
+        now: u64,
+    ) -> Result<TokenDetails> {
+        let redirect_uri = self
+            .oauth_client
+            .redirect_uri()
+            .map(|redirect_uri| redirect_uri.to_string())
+            .unwrap_or_default();
+        let mut params = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri.as_str()),
+        ];
+        if let Some(pkce_verifier) = &pkce_verifier {
+            params.push(("code_verifier", pkce_verifier.secret().as_str()));
+        }
+
+        self.request_tokens(&params, now).await
+    }
+
+    /// Exchanges a refresh token for a new access token
+    ///
+    /// # Arguments
+    ///
+    /// * `refresh_token` - The refresh token issued by Twitch
+    /// * `now` - The current unix timestamp
+    ///
+    /// # Returns
+    ///
+    /// Returns the new token details
+    async fn refresh_token(&self, refresh_token: &str, now: u64) -> Result<TokenDetails> {
+        self.request_tokens(
+            &[
+                ("grant_type", "refresh_token"),
+                ("refresh_token", refresh_token),
+            ],
+            now,
+        )
+        .await
+    }
+}
+
+/// Extracts the user information from a Helix `GET /users` response
+///
+/// Helix wraps the user in a `data` array, whose first entry is the user
+/// the access token belongs to.
+///
+/// # Arguments
+///
+/// * `user_data` - The JSON body returned by Helix
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` containing the user's identifier, or an error
+/// if the array or the id is missing or a field has an unexpected type
+fn parse_user_info(user_data: &serde_json::Value) -> Result<UserInfo> {
+    let users: TwitchUsersResponse = parse_response("Twitch user info", user_data)?;
+    let user = users
+        .data
+        .into_iter()
+        .next()
+        .ok_or_else(|| eyre!("No user in the data of the Twitch user info response"))?;
+
+    Ok(UserInfo {
+        raw: Some(user_data.clone()),
+        ..user.into()
+    })
+}
+
+/// Factory for creating Twitch OAuth provider instances
+///
+/// This struct implements the factory pattern for creating Twitch
+/// OAuth provider instances. It allows the application to create
+/// provider instances dynamically based on configuration.
+pub struct TwitchProviderFactory;
+
+impl OAuthProviderFactory for TwitchProviderFactory {
+    /// Creates a new Twitch OAuth provider instance
+    ///
+    /// This method creates a new Twitch provider with the given
+    /// OAuth client, user info URL and client credentials.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, with the client
+    ///   credentials and extra `scopes`
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created Twitch provider
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        Ok(Arc::new(TwitchProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.client_id.clone(),
+            settings.scopes.clone(),
+        )))
+    }
+
+    /// Returns how Twitch expects the client credentials
+    ///
+    /// Twitch expects the client credentials in the request body.
+    ///
+    /// # Returns
+    ///
+    /// Returns `TokenAuthType::RequestBody`
+    fn default_auth_type(&self) -> TokenAuthType {
+        TokenAuthType::RequestBody
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that the user id is extracted from a recorded Helix response
+    #[test]
+    fn test_parse_helix_response() {
+        let user_data = serde_json::json!({
+            "data": [{
+                "id": "141981764",
+                "login": "twitchdev",
+                "display_name": "TwitchDev",
+                "type": "",
+                "broadcaster_type": "partner",
+                "description": "Supporting third-party developers building Twitch integrations.",
+                "profile_image_url": "https://static-cdn.jtvnw.net/jtv_user_pictures/8a6381c7-d0c0-4576-b179-38bd5ce1d6af-profile_image-300x300.png",
+                "view_count": 5980557,
+                "email": "not-real@email.com",
+                "created_at": "2016-12-14T20:32:28Z"
+            }]
+        });
+
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "141981764");
+        assert_eq!(user_info.provider, "twitch");
+    }
+
+    /// Tests that responses without a user in `data` are rejected
+    #[test]
+    fn test_parse_missing_data() {
+        assert!(parse_user_info(&serde_json::json!({})).is_err());
+        assert!(parse_user_info(&serde_json::json!({ "data": [] })).is_err());
+        assert!(parse_user_info(&serde_json::json!({
+            "error": "Unauthorized",
+            "status": 401,
+            "message": "Invalid OAuth token"
+        }))
+        .is_err());
+    }
+
+    /// Tests that a token response with an array of scopes is accepted
+    #[test]
+    fn test_parse_token_response() {
+        let token: TwitchTokenResponse = serde_json::from_value(serde_json::json!({
+            "access_token": "rfx2uswqe8l4g1mkagrvg5tv0ks3",
+            "expires_in": 14124,
+            "refresh_token": "5b93chm6hdve3mycz05zfzatkfdenfspp1h1ar2xxdalen01",
+            "scope": ["user:read:email"],
+            "token_type": "bearer"
+        }))
+        .unwrap();
+        assert_eq!(token.scope, Some(vec!["user:read:email".to_string()]));
+    }
+
+    /// Tests that fields the server does not read are ignored, and that a
+    /// field with an unexpected type is reported with its path
+    #[test]
+    fn test_response_fields() {
+        let user_data = serde_json::json!({ "data": [{ "id": "141981764", "login": "twitchdev", "broadcaster_type": "partner", "view_count": 5980557 }], "pagination": {} });
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "141981764");
+
+        let user_data = serde_json::json!({ "data": [{ "id": 141981764, "login": "twitchdev" }] });
+        let error = parse_user_info(&user_data).unwrap_err().to_string();
+        assert!(
+            error.starts_with("Invalid Twitch user info response at data[0].id:"),
+            "{}",
+            error
+        );
+    }
+}
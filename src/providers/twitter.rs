@@ -1,13 +1,72 @@
 use crate::{
-    primitives::UserInfo,
-    traits::{OAuthProvider, OAuthProviderFactory},
-    types::OAuthClient,
+    primitives::{ProviderContext, ProviderDisplay, UserInfo},
+    providers::{merge_scopes, non_empty, parse_response},
+    settings::UserIdField,
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
 };
 use axum::async_trait;
 use eyre::{bail, Result};
-use reqwest::{Client, Url};
+use reqwest::Url;
+use serde::Deserialize;
 use std::sync::Arc;
 
+/// Twitter `/users/me` response
+///
+/// # Fields
+///
+/// * `data` - The signed-in user
+#[derive(Debug, Deserialize)]
+struct TwitterUserResponse {
+    /// The signed-in user
+    data: TwitterUser,
+}
+
+/// User returned by the Twitter API
+///
+/// # Fields
+///
+/// * `id` - Numeric id of the user, as a string
+/// * `username` - The user's handle
+/// * `name` - The user's display name
+/// * `profile_image_url` - URL of the user's profile image, with `user.fields=profile_image_url`
+#[derive(Debug, Deserialize)]
+struct TwitterUser {
+    /// Numeric id of the user
+    #[serde(default, deserialize_with = "non_empty")]
+    id: Option<String>,
+    /// The user's handle
+    #[serde(default, deserialize_with = "non_empty")]
+    username: Option<String>,
+    /// The user's display name
+    #[serde(default, deserialize_with = "non_empty")]
+    name: Option<String>,
+    /// URL of the user's profile image
+    #[serde(default, deserialize_with = "non_empty")]
+    profile_image_url: Option<String>,
+}
+
+impl From<TwitterUser> for UserInfo {
+    /// Maps a Twitter user to the user information
+    ///
+    /// Twitter does not share email addresses through this endpoint, so only
+    /// the name and profile image are set. The user is identified by their
+    /// numeric id, empty when missing.
+    fn from(user: TwitterUser) -> Self {
+        UserInfo {
+            id: user.id.unwrap_or_default(),
+            provider: "twitter".to_string(),
+            email: None,
+            name: user.name,
+            username: user.username,
+            avatar_url: user.profile_image_url,
+            email_verified: None,
+            tenant: None,
+            raw: None,
+        }
+    }
+}
+
 /// Twitter OAuth provider implementation
 ///
 /// This struct implements the OAuth provider interface for Twitter.
@@ -19,13 +78,19 @@ use std::sync::Arc;
 /// * `client` - HTTP client for making API requests
 /// * `oauth_client` - Configured OAuth 2.0 client
 /// * `user_info_url` - Twitter's user info endpoint URL
+/// * `scopes` - OAuth scopes requested on authorization
+/// * `id_field` - Field of the user info response identifying the user
 pub struct TwitterProvider {
     /// HTTP client for API requests
-    client: Client,
+    client: HttpClient,
     /// Configured OAuth client
     oauth_client: OAuthClient,
     /// Twitter user info endpoint URL
     user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
+    /// Field identifying the user
+    id_field: UserIdField,
 }
 
 impl TwitterProvider {
@@ -36,17 +101,28 @@ impl TwitterProvider {
     ///
     /// # Arguments
     ///
+    /// * `http_client` - The HTTP client shared by every provider
     /// * `oauth_client` - The configured OAuth client for Twitter
     /// * `user_info_url` - The URL for Twitter's user info endpoint
+    /// * `scopes` - Scopes configured in addition to the required ones
+    /// * `id_field` - Field of the user info response identifying the user
     ///
     /// # Returns
     ///
     /// Returns a new `TwitterProvider` instance
-    pub fn new(oauth_client: OAuthClient, user_info_url: Url) -> Self {
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        scopes: Option<Vec<String>>,
+        id_field: UserIdField,
+    ) -> Self {
         Self {
-            client: Client::new(),
+            client: http_client,
             oauth_client,
             user_info_url,
+            scopes: merge_scopes(&["users.read", "tweet.read"], scopes),
+            id_field,
         }
     }
 }
@@ -62,6 +138,15 @@ impl OAuthProvider for TwitterProvider {
         &self.oauth_client
     }
 
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
     /// Returns the OAuth scopes required for Twitter
     ///
     /// Twitter requires the "users.read" and "tweet.read" scopes to access
@@ -69,16 +154,29 @@ impl OAuthProvider for TwitterProvider {
     ///
     /// # Returns
     ///
-    /// Returns a vector containing the required Twitter scopes
+    /// Returns the required scopes followed by the configured ones
     fn get_scopes(&self) -> Vec<String> {
-        vec!["users.read".to_string(), "tweet.read".to_string()]
+        self.scopes.clone()
+    }
+
+    /// Returns the Twitter brand color and logo
+    ///
+    /// # Returns
+    ///
+    /// Returns the branding of the Twitter sign-in button
+    fn display_metadata(&self) -> Option<ProviderDisplay> {
+        Some(ProviderDisplay {
+            label: "Twitter",
+            color: "#1da1f2",
+            icon: r#"<path fill="currentColor" d="M24 4.557a9.83 9.83 0 0 1-2.828.775 4.932 4.932 0 0 0 2.168-2.728 9.864 9.864 0 0 1-3.127 1.195 4.916 4.916 0 0 0-8.394 4.49 13.925 13.925 0 0 1-10.025-5.028 4.902 4.902 0 0 0 1.523 6.574 4.906 4.906 0 0 1-2.23-1.227v.05c0 4.741 3.337 8.73 7.928 9.75a10.007 10.007 0 0 1-8.451 2.296 13.934 13.934 0 0 0 7.546 2.212c9.142 0 14.307-7.721 13.995-14.646A10.025 10.025 0 0 0 24 4.557z"/>"#,
+        })
     }
 
     /// Fetches user information from Twitter's user info endpoint
     ///
     /// This method makes an authenticated request to Twitter's user info
     /// endpoint to retrieve the user's profile information including
-    /// their username.
+    /// their username and profile image.
     ///
     /// # Arguments
     ///
@@ -86,18 +184,20 @@ impl OAuthProvider for TwitterProvider {
     ///
     /// # Returns
     ///
-    /// Returns `Result<UserInfo>` containing the user's username
-    /// or an error if the request fails
+    /// Returns `Result<UserInfo>` containing the user's identifier and
+    /// profile, or an error if the request fails
     async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
         let request = self
             .client
             .get(self.user_info_url.as_str())
+            .query(&[("user.fields", "profile_image_url")])
             .header("Authorization", format!("Bearer {}", access_token));
 
-        let response = request
-            .send()
+        let response = self
+            .client
+            .send(request)
             .await
-            .map_err(|e| eyre::eyre!("Failed to get user info from Twitter: {}", e))?;
+            .map_err(|e| request_error("Failed to get user info from Twitter", e))?;
 
         if !response.status().is_success() {
             bail!(
@@ -106,19 +206,45 @@ impl OAuthProvider for TwitterProvider {
             );
         }
 
-        let user_data: serde_json::Value = response.json().await?;
-        let username = user_data["data"]["username"]
-            .as_str()
-            .ok_or_else(|| eyre::eyre!("No username field in Twitter user info response"))?
-            .to_string();
-
-        Ok(UserInfo {
-            id: username,
-            provider: "twitter".to_string(),
-        })
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from Twitter", e))?;
+        parse_user_info(&user_data, self.id_field)
     }
 }
 
+/// Extracts the user information from a Twitter `/users/me` response
+///
+/// # Arguments
+///
+/// * `user_data` - The JSON body returned by the user info endpoint
+/// * `id_field` - Whether the user is identified by their numeric id or username
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` identified by the selected field, or an error
+/// if that field is missing or a field has an unexpected type
+fn parse_user_info(user_data: &serde_json::Value, id_field: UserIdField) -> Result<UserInfo> {
+    let user = parse_response::<TwitterUserResponse>("Twitter user info", user_data)?.data;
+    let id = match id_field {
+        UserIdField::Id => user
+            .id
+            .clone()
+            .ok_or_else(|| eyre::eyre!("No id field in Twitter user info response"))?,
+        UserIdField::Username => user
+            .username
+            .clone()
+            .ok_or_else(|| eyre::eyre!("No username field in Twitter user info response"))?,
+    };
+
+    Ok(UserInfo {
+        id,
+        raw: Some(user_data.clone()),
+        ..user.into()
+    })
+}
+
 /// Factory for creating Twitter OAuth provider instances
 ///
 /// This struct implements the factory pattern for creating Twitter
@@ -134,13 +260,92 @@ impl OAuthProviderFactory for TwitterProviderFactory {
     ///
     /// # Arguments
     ///
-    /// * `oauth_client` - The configured OAuth client for Twitter
-    /// * `user_info_url` - The URL for Twitter's user info endpoint
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, whose `scopes` are
+    ///   added to the required ones and whose `id_field` selects the user's identifier
     ///
     /// # Returns
     ///
     /// Returns an `Arc<dyn OAuthProvider>` containing the created Twitter provider
-    fn create(&self, oauth_client: OAuthClient, user_info_url: Url) -> Arc<dyn OAuthProvider> {
-        Arc::new(TwitterProvider::new(oauth_client, user_info_url))
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        Ok(Arc::new(TwitterProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.scopes.clone(),
+            settings.id_field,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that the profile is extracted from a recorded user response
+    #[test]
+    fn test_parse_user_response() {
+        let user_data = serde_json::json!({
+            "data": {
+                "id": "2244994945",
+                "name": "X Dev",
+                "username": "TwitterDev",
+                "profile_image_url": "https://pbs.twimg.com/profile_images/1445764922474827784/W2zEPN7U_normal.jpg"
+            }
+        });
+
+        let user_info = parse_user_info(&user_data, UserIdField::Id).unwrap();
+        assert_eq!(user_info.id, "2244994945");
+        assert_eq!(user_info.provider, "twitter");
+        assert_eq!(user_info.username.as_deref(), Some("TwitterDev"));
+        assert_eq!(user_info.email, None);
+        assert_eq!(user_info.name.as_deref(), Some("X Dev"));
+        assert_eq!(
+            user_info.avatar_url.as_deref(),
+            Some("https://pbs.twimg.com/profile_images/1445764922474827784/W2zEPN7U_normal.jpg")
+        );
+        assert_eq!(user_info.raw, Some(user_data));
+    }
+
+    /// Tests that users can still be identified by their username
+    #[test]
+    fn test_username_id_field() {
+        let user_data = serde_json::json!({
+            "data": { "id": "2244994945", "name": "X Dev", "username": "TwitterDev" }
+        });
+
+        let user_info = parse_user_info(&user_data, UserIdField::Username).unwrap();
+        assert_eq!(user_info.id, "TwitterDev");
+        assert_eq!(user_info.username.as_deref(), Some("TwitterDev"));
+
+        let user_data = serde_json::json!({ "data": { "username": "TwitterDev" } });
+        assert!(parse_user_info(&user_data, UserIdField::Id).is_err());
+        assert!(parse_user_info(&user_data, UserIdField::Username).is_ok());
+    }
+
+    /// Tests that fields the server does not read are ignored, and that a
+    /// field with an unexpected type is reported with its path
+    #[test]
+    fn test_response_fields() {
+        let user_data = serde_json::json!({ "data": { "id": "2244994945", "username": "TwitterDev", "verified": false, "public_metrics": { "followers_count": 513958 } }, "includes": {} });
+        let user_info = parse_user_info(&user_data, UserIdField::Id).unwrap();
+        assert_eq!(user_info.id, "2244994945");
+
+        let user_data =
+            serde_json::json!({ "data": { "id": 2244994945u64, "username": "TwitterDev" } });
+        let error = parse_user_info(&user_data, UserIdField::Id)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.starts_with("Invalid Twitter user info response at data.id:"),
+            "{}",
+            error
+        );
     }
 }
@@ -0,0 +1,263 @@
+use crate::{
+    primitives::{ProviderContext, UserInfo},
+    providers::{merge_scopes, oidc_user_info, parse_response, OidcUserInfoResponse},
+    settings::TokenAuthType,
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
+};
+use axum::async_trait;
+use eyre::{bail, Result};
+use reqwest::Url;
+use std::sync::Arc;
+
+/// Yahoo OAuth provider implementation
+///
+/// This struct implements the OAuth provider interface for Yahoo's
+/// OpenID Connect product. It handles OAuth 2.0 authentication flow and
+/// user information retrieval from Yahoo's userinfo endpoint.
+///
+/// # Fields
+///
+/// * `client` - HTTP client for making API requests
+/// * `oauth_client` - Configured OAuth 2.0 client
+/// * `user_info_url` - Yahoo's user info endpoint URL
+/// * `scopes` - OAuth scopes requested on authorization
+pub struct YahooProvider {
+    /// HTTP client for API requests
+    client: HttpClient,
+    /// Configured OAuth client
+    oauth_client: OAuthClient,
+    /// Yahoo user info endpoint URL
+    user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
+}
+
+impl YahooProvider {
+    /// Creates a new Yahoo OAuth provider instance
+    ///
+    /// This constructor creates a new Yahoo provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_client` - The HTTP client shared by every provider
+    /// * `oauth_client` - The configured OAuth client for Yahoo
+    /// * `user_info_url` - The URL for Yahoo's user info endpoint
+    /// * `scopes` - Scopes configured in addition to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `YahooProvider` instance
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        scopes: Option<Vec<String>>,
+    ) -> Self {
+        Self {
+            client: http_client,
+            oauth_client,
+            user_info_url,
+            scopes: merge_scopes(&["openid", "email", "profile"], scopes),
+        }
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for YahooProvider {
+    /// Returns a reference to the OAuth client for Yahoo
+    ///
+    /// # Returns
+    ///
+    /// Returns a reference to the configured `OAuthClient` instance
+    fn get_oauth_client(&self) -> &OAuthClient {
+        &self.oauth_client
+    }
+
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Returns the OAuth scopes required for Yahoo
+    ///
+    /// Yahoo requires the "openid", "email" and "profile" scopes to access
+    /// the user's identity through its userinfo endpoint.
+    ///
+    /// # Returns
+    ///
+    /// Returns the required scopes followed by the configured ones
+    fn get_scopes(&self) -> Vec<String> {
+        self.scopes.clone()
+    }
+
+    /// Fetches user information from Yahoo's OpenID Connect userinfo endpoint
+    ///
+    /// This method makes an authenticated request to Yahoo to
+    /// retrieve the signed-in user's profile.
+    ///
+    /// # Arguments
+    ///
+    /// * `access_token` - The OAuth access token obtained from Yahoo
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the user's identifier
+    /// or an error if the request fails
+    async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
+        let request = self
+            .client
+            .get(self.user_info_url.as_str())
+            .header("Authorization", format!("Bearer {}", access_token));
+
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|e| request_error("Failed to get user info from Yahoo", e))?;
+
+        if !response.status().is_success() {
+            bail!("Failed to get user info from Yahoo: {}", response.status());
+        }
+
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from Yahoo", e))?;
+        parse_user_info(&user_data)
+    }
+}
+
+/// Extracts the user information from a Yahoo userinfo response
+///
+/// The `sub` claim is used as the user's identifier, falling back to the
+/// `email` claim when it is missing.
+///
+/// # Arguments
+///
+/// * `user_data` - The JSON body returned by the userinfo endpoint
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` containing the user's identifier, or an
+/// error if both claims are missing or a claim has an unexpected type
+fn parse_user_info(user_data: &serde_json::Value) -> Result<UserInfo> {
+    let claims: OidcUserInfoResponse = parse_response("Yahoo user info", user_data)?;
+    let id = claims
+        .sub
+        .clone()
+        .or_else(|| claims.email.clone())
+        .ok_or_else(|| eyre::eyre!("No sub or email field in Yahoo user info response"))?;
+
+    Ok(oidc_user_info(id, "yahoo", claims, user_data))
+}
+
+/// Factory for creating Yahoo OAuth provider instances
+///
+/// This struct implements the factory pattern for creating Yahoo
+/// OAuth provider instances. It allows the application to create
+/// provider instances dynamically based on configuration.
+pub struct YahooProviderFactory;
+
+impl OAuthProviderFactory for YahooProviderFactory {
+    /// Creates a new Yahoo OAuth provider instance
+    ///
+    /// This method creates a new Yahoo provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, whose `scopes` are
+    ///   added to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created Yahoo provider
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        Ok(Arc::new(YahooProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.scopes.clone(),
+        )))
+    }
+
+    /// Returns how Yahoo expects the client credentials
+    ///
+    /// Yahoo only accepts the client credentials through HTTP Basic auth.
+    ///
+    /// # Returns
+    ///
+    /// Returns `TokenAuthType::Basic`
+    fn default_auth_type(&self) -> TokenAuthType {
+        TokenAuthType::Basic
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that the user id and email are extracted from a recorded userinfo response
+    #[test]
+    fn test_parse_userinfo_response() {
+        let user_data = serde_json::json!({
+            "sub": "FSLZIQD2RPAH2RKT52AD4LMB7E",
+            "name": "Jane Doe",
+            "given_name": "Jane",
+            "family_name": "Doe",
+            "locale": "en-US",
+            "email": "janedoe@yahoo.com",
+            "email_verified": true,
+            "birthdate": "1990",
+            "profile_images": {
+                "image32": "https://s.yimg.com/ag/images/default_user_profile_pic_32sq.jpg"
+            },
+            "picture": "https://s.yimg.com/ag/images/default_user_profile_pic_192sq.jpg",
+            "nickname": "jane"
+        });
+
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "FSLZIQD2RPAH2RKT52AD4LMB7E");
+        assert_eq!(user_info.provider, "yahoo");
+        assert_eq!(user_info.email.as_deref(), Some("janedoe@yahoo.com"));
+        assert_eq!(user_info.email_verified, Some(true));
+        assert_eq!(user_info.name.as_deref(), Some("Jane Doe"));
+        assert_eq!(
+            user_info.avatar_url.as_deref(),
+            Some("https://s.yimg.com/ag/images/default_user_profile_pic_192sq.jpg")
+        );
+        assert_eq!(user_info.raw, Some(user_data));
+    }
+
+    /// Tests that the email is used when the sub claim is missing, and
+    /// that a claim with an unexpected type is reported with its path
+    #[test]
+    fn test_parse_invalid_response() {
+        let user_info =
+            parse_user_info(&serde_json::json!({ "email": "janedoe@yahoo.com" })).unwrap();
+        assert_eq!(user_info.id, "janedoe@yahoo.com");
+
+        assert!(parse_user_info(&serde_json::json!({ "name": "Jane Doe" })).is_err());
+        let error = parse_user_info(&serde_json::json!({ "sub": ["FSLZIQD2RPAH2RKT52AD4LMB7E"] }))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.starts_with("Invalid Yahoo user info response at sub:"),
+            "{}",
+            error
+        );
+    }
+}
@@ -0,0 +1,611 @@
+use crate::{
+    primitives::{ProviderContext, TokenDetails, UserInfo},
+    providers::{merge_scopes, non_empty, parse_response, send_token_request, TokenRequestBody},
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
+};
+use axum::async_trait;
+use eyre::{eyre, Result};
+use oauth2::PkceCodeVerifier;
+use reqwest::Url;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// Token response of Zoom's token endpoint
+///
+/// # Fields
+///
+/// * `access_token` - The access token
+/// * `expires_in` - Lifetime of the access token in seconds
+/// * `refresh_token` - The refresh token
+/// * `scope` - Space separated scopes granted to the access token
+#[derive(Deserialize)]
+struct ZoomTokenResponse {
+    /// The access token
+    access_token: String,
+    /// Lifetime of the access token in seconds
+    expires_in: Option<u64>,
+    /// The refresh token
+    refresh_token: Option<String>,
+    /// Space separated scopes granted to the access token
+    scope: Option<String>,
+}
+
+/// Zoom `/v2/users/me` response
+///
+/// # Fields
+///
+/// * `id` - Id of the user
+/// * `email` - The user's email
+/// * `display_name` - The user's display name
+/// * `pic_url` - URL of the user's profile picture
+/// * `verified` - Whether the user verified their email, `1` when verified
+/// * `account_id` - Id of the Zoom account the user belongs to
+#[derive(Debug, Deserialize)]
+struct ZoomUserResponse {
+    /// Id of the user
+    id: String,
+    /// The user's email
+    #[serde(default, deserialize_with = "non_empty")]
+    email: Option<String>,
+    /// The user's display name
+    #[serde(default, deserialize_with = "non_empty")]
+    display_name: Option<String>,
+    /// URL of the user's profile picture
+    #[serde(default, deserialize_with = "non_empty")]
+    pic_url: Option<String>,
+    /// Whether the user verified their email
+    #[serde(default)]
+    verified: Option<u8>,
+    /// Id of the Zoom account the user belongs to
+    #[serde(default, deserialize_with = "non_empty")]
+    account_id: Option<String>,
+}
+
+impl From<ZoomUserResponse> for UserInfo {
+    /// Maps a Zoom user to the user information
+    ///
+    /// Zoom accounts hold several users, so the account is reported as the
+    /// user's tenant.
+    fn from(user: ZoomUserResponse) -> Self {
+        UserInfo {
+            id: user.id,
+            provider: "zoom".to_string(),
+            email: user.email,
+            name: user.display_name,
+            username: None,
+            avatar_url: user.pic_url,
+            email_verified: user.verified.map(|verified| verified == 1),
+            tenant: user.account_id,
+            raw: None,
+        }
+    }
+}
+
+/// Error body of the Zoom API
+///
+/// # Fields
+///
+/// * `code` - Zoom's error code, e.g. `124` for an invalid access token
+/// * `message` - Description of the error
+#[derive(Debug, Deserialize)]
+struct ZoomErrorResponse {
+    /// Zoom's error code
+    code: i64,
+    /// Description of the error
+    message: String,
+}
+
+/// Converts a failed Zoom response to an error
+///
+/// Zoom describes token and API failures with a `code` and a `message`,
+/// which are kept so the logs say why the request was refused.
+///
+/// # Arguments
+///
+/// * `context` - What the request was for
+/// * `response` - The unsuccessful response
+///
+/// # Returns
+///
+/// Returns an error carrying the context, the status and Zoom's message
+/// when the body describes the error
+async fn response_error(context: &str, response: reqwest::Response) -> eyre::Report {
+    let status = response.status();
+    match response.json::<ZoomErrorResponse>().await {
+        Ok(error) => eyre!(
+            "{}: {}: {} (code {})",
+            context,
+            status,
+            error.message,
+            error.code
+        ),
+        Err(_) => eyre!("{}: {}", context, status),
+    }
+}
+
+/// Zoom OAuth provider implementation
+///
+/// This struct implements the OAuth provider interface for Zoom. It
+/// handles OAuth 2.0 authentication flow and user information retrieval
+/// from the Zoom API, and sends its own token requests so Zoom's error
+/// messages are kept.
+///
+/// # Fields
+///
+/// * `client` - HTTP client for making API requests
+/// * `oauth_client` - Configured OAuth 2.0 client
+/// * `user_info_url` - Zoom's user info endpoint URL
+/// * `scopes` - OAuth scopes requested on authorization
+pub struct ZoomProvider {
+    /// HTTP client for API requests
+    client: HttpClient,
+    /// Configured OAuth client
+    oauth_client: OAuthClient,
+    /// Zoom user info endpoint URL
+    user_info_url: Url,
+    /// OAuth scopes requested on authorization
+    scopes: Vec<String>,
+}
+
+impl ZoomProvider {
+    /// Creates a new Zoom OAuth provider instance
+    ///
+    /// This constructor creates a new Zoom provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `http_client` - The HTTP client shared by every provider
+    /// * `oauth_client` - The configured OAuth client for Zoom
+    /// * `user_info_url` - The URL for Zoom's user info endpoint
+    /// * `scopes` - Scopes configured in addition to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `ZoomProvider` instance
+    pub fn new(
+        http_client: HttpClient,
+        oauth_client: OAuthClient,
+        user_info_url: Url,
+        scopes: Option<Vec<String>>,
+    ) -> Self {
+        Self {
+            client: http_client,
+            oauth_client,
+            user_info_url,
+            scopes: merge_scopes(&["user:read"], scopes),
+        }
+    }
+
+    /// Sends a request to Zoom's token endpoint
+    ///
+    /// The grant parameters are sent as a form, with the client
+    /// credentials where the configured `auth_type` puts them, the
+    /// `Authorization` header by default as Zoom documents.
+    ///
+    /// # Arguments
+    ///
+    /// * `params` - The grant parameters
+    /// * `now` - The current unix timestamp
+    ///
+    /// # Returns
+    ///
+    /// Returns the issued token details, or an error carrying Zoom's
+    /// message if the request was refused
+    async fn request_tokens(&self, params: &[(&str, &str)], now: u64) -> Result<TokenDetails> {
+        let response = send_token_request(
+            &self.client,
+            &self.oauth_client,
+            self.token_request_headers(),
+            params,
+            TokenRequestBody::Form,
+            "Zoom token request failed",
+        )
+        .await?;
+
+        // Only Zoom's message is logged, the rest of the body may echo the code back
+        if !response.status().is_success() {
+            return Err(response_error("Zoom token request failed", response).await);
+        }
+
+        let token: ZoomTokenResponse = response
+            .json()
+            .await
+            .map_err(|e| request_error("Zoom token request failed", e))?;
+        Ok(TokenDetails {
+            access_token: token.access_token,
+            refresh_token: token.refresh_token,
+            expires_at: token
+                .expires_in
+                .map(|expires_in| now.saturating_add(expires_in)),
+            scopes: token
+                .scope
+                .map(|scope| scope.split_whitespace().map(str::to_string).collect())
+                .unwrap_or_else(|| self.scopes.clone()),
+            id_token: None,
+            user_data: None,
+        })
+    }
+}
+
+#[async_trait]
+impl OAuthProvider for ZoomProvider {
+    /// Returns a reference to the OAuth client for Zoom
+    ///
+    /// # Returns
+    ///
+    /// Returns a reference to the configured `OAuthClient` instance
+    fn get_oauth_client(&self) -> &OAuthClient {
+        &self.oauth_client
+    }
+
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Returns the OAuth scopes required for Zoom
+    ///
+    /// Zoom requires the "user:read" scope to access the signed-in user
+    /// through `/v2/users/me`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the required scopes followed by the configured ones
+    fn get_scopes(&self) -> Vec<String> {
+        self.scopes.clone()
+    }
+
+    /// Fetches user information from Zoom's `/v2/users/me` endpoint
+    ///
+    /// # Arguments
+    ///
+    /// * `access_token` - The OAuth access token obtained from Zoom
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the user's identifier, or an
+    /// error carrying Zoom's message if the request fails
+    async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
+        let request = self
+            .client
+            .get(self.user_info_url.as_str())
+            .header("Authorization", format!("Bearer {}", access_token));
+
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|e| request_error("Failed to get user info from Zoom", e))?;
+
+        if !response.status().is_success() {
+            return Err(response_error("Failed to get user info from Zoom", response).await);
+        }
+
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from Zoom", e))?;
+        parse_user_info(&user_data)
+    }
+
+    /// Exchanges an authorization code for tokens
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The authorization code returned by Zoom
+    /// * `pkce_verifier` - The PKCE verifier of the flow, `None` without PKCE
+    /// * `now` - The current unix timestamp
+    ///
+    /// # Returns
+    ///
+    /// Returns the issued token details
+    async fn exchange_code(
+        &self,
+        code: &str,
+        pkce_verifier: Option<PkceCodeVerifier>,
+        now: u64,
+    ) -> Result<TokenDetails> {
+        let redirect_uri = self
+            .oauth_client
+            .redirect_uri()
+            .map(|redirect_uri| redirect_uri.to_string())
+            .unwrap_or_default();
+        let mut params = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri.as_str()),
+        ];
+        if let Some(pkce_verifier) = &pkce_verifier {
+            params.push(("code_verifier", pkce_verifier.secret().as_str()));
+        }
+
+        self.request_tokens(&params, now).await
+    }
+
+    /// Exchanges a refresh token for a new access token
+    ///
+    /// # Arguments
+    ///
+    /// * `refresh_token` - The refresh token issued by Zoom
+    /// * `now` - The current unix timestamp
+    ///
+    /// # Returns
+    ///
+    /// Returns the new token details
+    async fn refresh_token(&self, refresh_token: &str, now: u64) -> Result<TokenDetails> {
+        self.request_tokens(
+            &[
+                ("grant_type", "refresh_token"),
+                ("refresh_token", refresh_token),
+            ],
+            now,
+        )
+        .await
+    }
+}
+
+/// Extracts the user information from a Zoom `/v2/users/me` response
+///
+/// # Arguments
+///
+/// * `user_data` - The JSON body returned by the Zoom API
+///
+/// # Returns
+///
+/// Returns `Result<UserInfo>` containing the user's id, or an error if
+/// the id is missing or a field has an unexpected type
+fn parse_user_info(user_data: &serde_json::Value) -> Result<UserInfo> {
+    let user: ZoomUserResponse = parse_response("Zoom user info", user_data)?;
+    Ok(UserInfo {
+        raw: Some(user_data.clone()),
+        ..user.into()
+    })
+}
+
+/// Factory for creating Zoom OAuth provider instances
+///
+/// This struct implements the factory pattern for creating Zoom
+/// OAuth provider instances. It allows the application to create
+/// provider instances dynamically based on configuration.
+pub struct ZoomProviderFactory;
+
+impl OAuthProviderFactory for ZoomProviderFactory {
+    /// Creates a new Zoom OAuth provider instance
+    ///
+    /// This method creates a new Zoom provider with the given
+    /// OAuth client and user info URL.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The provider's OAuth client, endpoints and settings, whose `scopes` are
+    ///   added to the required ones
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created Zoom provider
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        let ProviderContext {
+            http_client,
+            oauth_client,
+            user_info_url,
+            settings,
+            ..
+        } = ctx;
+        Ok(Arc::new(ZoomProvider::new(
+            Arc::clone(http_client),
+            oauth_client,
+            user_info_url,
+            settings.scopes.clone(),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        app::build_http_client,
+        settings::{HttpClientSettings, OAuthSettings, TokenAuthType},
+        testing::MockApi,
+        traits::ProviderTimeout,
+    };
+    use axum::http::StatusCode;
+    use base64::{engine::general_purpose::STANDARD, Engine};
+
+    /// Tests that the user is extracted from a recorded `/v2/users/me` response
+    #[test]
+    fn test_parse_user_response() {
+        let user_data = serde_json::json!({
+            "id": "KDcuGIm1QgePTO8WbOqwIQ",
+            "first_name": "Jill",
+            "last_name": "Chill",
+            "display_name": "Jill Chill",
+            "email": "jchill@example.com",
+            "type": 1,
+            "role_name": "Owner",
+            "pmi": 3542471135i64,
+            "timezone": "America/Los_Angeles",
+            "verified": 1,
+            "dept": "",
+            "created_at": "2019-06-01T07:58:03Z",
+            "pic_url": "https://example.com/photo.jpg",
+            "language": "en-US",
+            "status": "active",
+            "role_id": "0",
+            "account_id": "q6gBJVO5TzexKYTb_I2rpg",
+            "account_number": 10009239
+        });
+
+        let user_info = parse_user_info(&user_data).unwrap();
+        assert_eq!(user_info.id, "KDcuGIm1QgePTO8WbOqwIQ");
+        assert_eq!(user_info.provider, "zoom");
+        assert_eq!(user_info.email.as_deref(), Some("jchill@example.com"));
+        assert_eq!(user_info.email_verified, Some(true));
+        assert_eq!(user_info.name.as_deref(), Some("Jill Chill"));
+        assert_eq!(
+            user_info.avatar_url.as_deref(),
+            Some("https://example.com/photo.jpg")
+        );
+        assert_eq!(user_info.tenant.as_deref(), Some("q6gBJVO5TzexKYTb_I2rpg"));
+        assert_eq!(user_info.raw, Some(user_data));
+
+        let error = parse_user_info(&serde_json::json!({ "id": 42 }))
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.starts_with("Invalid Zoom user info response at id:"),
+            "{}",
+            error
+        );
+    }
+
+    /// Starts a fake Zoom API
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - Status of the token and user info responses
+    /// * `token_response` - Body of the token response
+    /// * `user_response` - Body of the `/v2/users/me` response
+    ///
+    /// # Returns
+    ///
+    /// Returns the running API
+    async fn zoom_api(
+        status: StatusCode,
+        token_response: serde_json::Value,
+        user_response: serde_json::Value,
+    ) -> MockApi {
+        MockApi::builder()
+            .post("/oauth/token", move |_| (status, token_response.clone()))
+            .get("/v2/users/me", move |_| (status, user_response.clone()))
+            .start()
+            .await
+            .unwrap()
+    }
+
+    /// Creates a Zoom provider against the fake API
+    async fn provider(api: &MockApi, auth_type: TokenAuthType) -> Arc<dyn OAuthProvider> {
+        let settings = OAuthSettings {
+            auth_type: Some(auth_type),
+            ..api.settings("/oauth/authorize", "/oauth/token", "/v2/users/me")
+        };
+        api.provider("zoom", settings).await.unwrap()
+    }
+
+    /// Tests that the code is exchanged with the client credentials in a
+    /// Basic `Authorization` header
+    #[tokio::test]
+    async fn test_exchange_code() {
+        let api = zoom_api(
+            StatusCode::OK,
+            serde_json::json!({
+                "access_token": "zoom-access-token",
+                "token_type": "bearer",
+                "refresh_token": "zoom-refresh-token",
+                "expires_in": 3599,
+                "scope": "user:read user:read:email"
+            }),
+            serde_json::Value::Null,
+        )
+        .await;
+        let provider = provider(&api, TokenAuthType::Basic).await;
+        assert_eq!(provider.get_scopes(), vec!["user:read"]);
+
+        let tokens = provider.exchange_code("code", None, 1_000).await.unwrap();
+        assert_eq!(tokens.access_token, "zoom-access-token");
+        assert_eq!(tokens.refresh_token.as_deref(), Some("zoom-refresh-token"));
+        assert_eq!(tokens.expires_at, Some(4_599));
+        assert_eq!(tokens.scopes, vec!["user:read", "user:read:email"]);
+
+        let request = &api.requests("/oauth/token")[0];
+        assert_eq!(
+            request.header("authorization"),
+            Some(format!("Basic {}", STANDARD.encode("mock-client:mock-secret")).as_str())
+        );
+        let form = request.form();
+        assert_eq!(form["grant_type"], "authorization_code");
+        assert_eq!(form["code"], "code");
+        assert_eq!(form["redirect_uri"], api.endpoint("/callback"));
+        assert!(!form.contains_key("client_secret"));
+    }
+
+    /// Tests that the client credentials are sent in the form when the
+    /// `auth_type` setting asks for it
+    #[tokio::test]
+    async fn test_request_body_auth_type() {
+        let api = zoom_api(
+            StatusCode::OK,
+            serde_json::json!({ "access_token": "zoom-access-token", "token_type": "bearer" }),
+            serde_json::Value::Null,
+        )
+        .await;
+        let provider = provider(&api, TokenAuthType::RequestBody).await;
+
+        let tokens = provider.refresh_token("refresh", 1_000).await.unwrap();
+        assert_eq!(tokens.access_token, "zoom-access-token");
+
+        let request = &api.requests("/oauth/token")[0];
+        assert_eq!(request.header("authorization"), None);
+        let form = request.form();
+        assert_eq!(form["client_id"], "mock-client");
+        assert_eq!(form["client_secret"], "mock-secret");
+        assert_eq!(form["grant_type"], "refresh_token");
+        assert_eq!(form["refresh_token"], "refresh");
+    }
+
+    /// Tests that Zoom's error message is kept when a token or the access
+    /// token is refused
+    #[tokio::test]
+    async fn test_error_message() {
+        let error = serde_json::json!({ "code": 124, "message": "Invalid access token." });
+        let api = zoom_api(StatusCode::UNAUTHORIZED, error.clone(), error).await;
+        let provider = provider(&api, TokenAuthType::Basic).await;
+
+        let error = provider.refresh_token("expired", 1_000).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Zoom token request failed: 401 Unauthorized: Invalid access token. (code 124)"
+        );
+
+        let error = provider.get_user_info("expired").await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Failed to get user info from Zoom: 401 Unauthorized: Invalid access token. (code 124)"
+        );
+    }
+
+    /// Tests that a token response whose body stops arriving is reported as
+    /// a provider timeout
+    #[tokio::test]
+    async fn test_token_response_timeout() {
+        let api = MockApi::stalled().await.unwrap();
+        let http_client = build_http_client(&HttpClientSettings {
+            timeout_ms: 200,
+            ..HttpClientSettings::default()
+        })
+        .unwrap();
+        let provider = api
+            .provider_with_client(
+                &http_client,
+                "zoom",
+                api.settings("/oauth/authorize", "/oauth/token", "/v2/users/me"),
+            )
+            .await
+            .unwrap();
+
+        let error = provider
+            .exchange_code("code", None, 1_000)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<ProviderTimeout>().unwrap().context,
+            "Zoom token request failed"
+        );
+    }
+}
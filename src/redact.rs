@@ -0,0 +1,341 @@
+use crate::settings::RedactionSettings;
+use serde::{Deserialize, Serialize, Serializer};
+use std::{fmt, sync::RwLock};
+use uuid::Uuid;
+
+/// Placeholder written in place of a redacted value
+pub const REDACTED: &str = "[REDACTED]";
+
+/// Length from which a word mixing letters and digits is masked as a token
+pub const DEFAULT_MIN_TOKEN_LENGTH: usize = 32;
+
+/// Query parameters whose values are never written to the logs
+const SECRET_PARAMS: &[&str] = &[
+    "code",
+    "state",
+    "token",
+    "access_token",
+    "refresh_token",
+    "id_token",
+    "code_verifier",
+    "client_secret",
+];
+
+/// Wrapper hiding a secret value from `Debug` and `Display` output
+///
+/// Wrap authorization codes, tokens and PKCE verifiers in `Redact` whenever
+/// they are part of a value that may be logged, so that formatting it can
+/// never leak the secret.
+pub struct Redact<T>(pub T);
+
+impl<T> fmt::Debug for Redact<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<T> fmt::Display for Redact<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// Secret configuration value
+///
+/// Deserializes like a plain string but serializes, debugs and displays as
+/// `[REDACTED]`, so settings containing it can be dumped without leaking the
+/// value. Every secret-bearing setting must use this type; the value is only
+/// reachable through `expose`.
+#[derive(Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(transparent)]
+pub struct SecretString(String);
+
+impl SecretString {
+    /// Returns the secret value
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for SecretString {
+    fn from(value: String) -> Self {
+        Self(value)
+    }
+}
+
+impl From<&str> for SecretString {
+    fn from(value: &str) -> Self {
+        Self(value.to_string())
+    }
+}
+
+impl Serialize for SecretString {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(REDACTED)
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl fmt::Display for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+/// Redacts the values of secret query parameters in a piece of text
+///
+/// Works on bare URLs as well as on messages embedding them, such as
+/// `reqwest` errors, by redacting the value of every `code`, `state`,
+/// `access_token` (and similar) parameter that follows a `?`, `&` or `#`.
+///
+/// # Arguments
+///
+/// * `text` - The URL or message to scrub
+///
+/// # Returns
+///
+/// Returns the text with every secret parameter value replaced by `[REDACTED]`
+pub fn scrub_url(text: &str) -> String {
+    let mut scrubbed = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(position) = rest.find(['?', '&', '#']) {
+        let (before, after) = rest.split_at(position + 1);
+        scrubbed.push_str(before);
+        rest = after;
+
+        let Some(name) = SECRET_PARAMS.iter().find(|name| {
+            rest.strip_prefix(**name)
+                .is_some_and(|r| r.starts_with('='))
+        }) else {
+            continue;
+        };
+
+        let value_start = name.len() + 1;
+        let value_end = rest[value_start..]
+            .find(|c: char| {
+                matches!(c, '&' | '#' | '"' | '\'' | ')' | '>' | '<') || c.is_whitespace()
+            })
+            .map_or(rest.len(), |end| value_start + end);
+
+        scrubbed.push_str(&rest[..value_start]);
+        scrubbed.push_str(REDACTED);
+        rest = &rest[value_end..];
+    }
+
+    scrubbed.push_str(rest);
+    scrubbed
+}
+
+/// Redaction applied to every logged message on top of the caller's secrets
+///
+/// # Fields
+///
+/// * `secrets` - Configured secret values, longest first
+/// * `min_token_length` - Length from which token-looking words are masked, never when `None`
+struct Redactor {
+    /// Configured secret values, longest first
+    secrets: Vec<String>,
+    /// Length from which token-looking words are masked, never when `None`
+    min_token_length: Option<usize>,
+}
+
+/// Process-wide redaction, set up once from the settings on startup
+static REDACTOR: RwLock<Redactor> = RwLock::new(Redactor {
+    secrets: Vec::new(),
+    min_token_length: Some(DEFAULT_MIN_TOKEN_LENGTH),
+});
+
+/// Sets up the redaction of every message passed to `redact_secrets`
+///
+/// Messages are built in places that have no access to the settings, such
+/// as error constructors, so the configuration is process-wide.
+///
+/// # Arguments
+///
+/// * `settings` - The redaction settings
+/// * `secrets` - Secret values of the configuration, such as client secrets
+pub fn configure(settings: &RedactionSettings, secrets: &[&str]) {
+    let mut secrets: Vec<String> = secrets
+        .iter()
+        .copied()
+        .chain(settings.secrets.iter().map(SecretString::expose))
+        .filter(|secret| !secret.is_empty())
+        .map(str::to_string)
+        .collect();
+    // A secret containing another must be replaced first
+    secrets.sort_by_key(|secret| std::cmp::Reverse(secret.len()));
+    secrets.dedup();
+
+    *REDACTOR.write().unwrap_or_else(|e| e.into_inner()) = Redactor {
+        secrets,
+        min_token_length: settings.mask_tokens.then_some(settings.min_token_length),
+    };
+}
+
+/// Returns whether a word looks like a token, a code or a key
+///
+/// Long runs of base64url characters mixing letters and digits are taken
+/// for secrets. UUIDs, which are request and user ids, are not.
+fn is_token_like(word: &str, min_length: usize) -> bool {
+    word.len() >= min_length
+        && word.bytes().any(|b| b.is_ascii_digit())
+        && word.bytes().any(|b| b.is_ascii_alphabetic())
+        && Uuid::try_parse(word).is_err()
+}
+
+/// Masks every token-looking word of a message
+///
+/// # Arguments
+///
+/// * `message` - The message to mask
+/// * `min_length` - Length from which words are masked
+///
+/// # Returns
+///
+/// Returns the message with every token-looking word replaced by `[REDACTED]`
+fn mask_tokens(message: &str, min_length: usize) -> String {
+    let mut masked = String::with_capacity(message.len());
+    let mut rest = message;
+
+    while !rest.is_empty() {
+        let end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+            .unwrap_or(rest.len());
+        let (word, after) = rest.split_at(end);
+        if is_token_like(word, min_length) {
+            masked.push_str(REDACTED);
+        } else {
+            masked.push_str(word);
+        }
+
+        let Some(separator) = after.chars().next() else {
+            break;
+        };
+        masked.push(separator);
+        rest = &after[separator.len_utf8()..];
+    }
+
+    masked
+}
+
+/// Redacts secrets from a message before it is logged
+///
+/// Scrubs secret query parameters, redacts every literal occurrence of the
+/// given and the configured secrets, since provider error bodies sometimes
+/// echo the code or token they rejected, and masks token-looking words when
+/// enabled in the settings.
+///
+/// # Arguments
+///
+/// * `message` - The message to redact, usually a formatted error
+/// * `secrets` - Secret values known to the caller
+///
+/// # Returns
+///
+/// Returns the redacted message
+pub fn redact_secrets(message: &str, secrets: &[&str]) -> String {
+    let redactor = REDACTOR.read().unwrap_or_else(|e| e.into_inner());
+    let message = secrets
+        .iter()
+        .copied()
+        .chain(redactor.secrets.iter().map(String::as_str))
+        .filter(|secret| !secret.is_empty())
+        .fold(scrub_url(message), |message, secret| {
+            message.replace(secret, REDACTED)
+        });
+
+    match redactor.min_token_length {
+        Some(min_length) => mask_tokens(&message, min_length),
+        None => message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that the wrapper never formats its value
+    #[test]
+    fn test_redact_formatting() {
+        let secret = Redact("verifier".to_string());
+        assert_eq!(format!("{}", secret), REDACTED);
+        assert_eq!(format!("{:?}", secret), REDACTED);
+    }
+
+    /// Tests that secret strings round-trip through deserialization but never serialize
+    #[test]
+    fn test_secret_string() {
+        let secret: SecretString = serde_json::from_str("\"hunter2\"").unwrap();
+        assert_eq!(secret.expose(), "hunter2");
+        assert_eq!(serde_json::to_string(&secret).unwrap(), "\"[REDACTED]\"");
+        assert_eq!(format!("{:?}", secret), REDACTED);
+        assert_eq!(format!("{}", secret), REDACTED);
+    }
+
+    /// Tests that secret query parameters are scrubbed and others are kept
+    #[test]
+    fn test_scrub_url() {
+        let cases = [
+            (
+                "https://app.example.com/callback?code=abc&state=def",
+                "https://app.example.com/callback?code=[REDACTED]&state=[REDACTED]",
+            ),
+            (
+                "error sending request for url (https://idp.example.com/me?access_token=tok&x=1)",
+                "error sending request for url (https://idp.example.com/me?access_token=[REDACTED]&x=1)",
+            ),
+            (
+                "https://app.example.com/#access_token=tok&token_type=bearer",
+                "https://app.example.com/#access_token=[REDACTED]&token_type=bearer",
+            ),
+            (
+                "https://app.example.com/?provider=google&encode=1&codes=2",
+                "https://app.example.com/?provider=google&encode=1&codes=2",
+            ),
+            ("no url here", "no url here"),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(scrub_url(input), expected);
+        }
+    }
+
+    /// Tests that literal secret values are redacted from messages
+    #[test]
+    fn test_redact_secrets() {
+        let message = "invalid_grant: Code abc123 was already redeemed";
+        assert_eq!(
+            redact_secrets(message, &["abc123", ""]),
+            "invalid_grant: Code [REDACTED] was already redeemed"
+        );
+    }
+
+    /// Tests that token-looking words are masked and other words are kept
+    #[test]
+    fn test_mask_tokens() {
+        let token = "ya29a0AfB_byC3x9Qk2LmN7pR4sT6vW8yZ1aB3cD5eF";
+        let message = format!(
+            "request {} failed: invalid token {} for https://idp.example.com/oauth2/v1/token",
+            Uuid::from_u128(1),
+            token
+        );
+        assert_eq!(
+            mask_tokens(&message, 32),
+            format!(
+                "request {} failed: invalid token [REDACTED] for https://idp.example.com/oauth2/v1/token",
+                Uuid::from_u128(1)
+            )
+        );
+        assert_eq!(mask_tokens(token, token.len() + 1), token);
+        assert_eq!(
+            mask_tokens("abcdefghijklmnopqrstuvwxyzabcdefgh", 32),
+            "abcdefghijklmnopqrstuvwxyzabcdefgh"
+        );
+    }
+}
@@ -0,0 +1,838 @@
+use crate::{
+    auth::KeyRingError,
+    redact::redact_secrets,
+    server::{
+        audit::AuditEvent,
+        client::client_ip,
+        errors::{bad_request, internal_error, unauthorized, ApiError},
+        provider_health::ProviderHealthReport,
+        security::{request_id, security_event},
+        server::AppState,
+        stats::WindowStats,
+    },
+    settings::{ConfigSource, Settings},
+    users::{Identity, Revocation, StoredTokens, UserRecord, UserSession},
+};
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Path, Query, Request, State},
+    http::{header::AUTHORIZATION, HeaderMap, Response, StatusCode},
+    middleware::Next,
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, net::SocketAddr, sync::Arc};
+use uuid::Uuid;
+
+/// Middleware rejecting admin requests without the configured API key
+///
+/// The presented key is compared through its SHA-256 digest so that the
+/// comparison time does not depend on how much of the key matched.
+///
+/// # Arguments
+///
+/// * `state` - Shared application state holding the admin key
+/// * `request` - The incoming request
+/// * `next` - The rest of the middleware stack
+///
+/// # Returns
+///
+/// Returns the response of the admin route, or 401 when the key is missing or wrong
+pub async fn require_admin_key(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    let Some(api_key) = &state.admin_api_key else {
+        return unauthorized("admin_disabled", "Admin API is not enabled");
+    };
+
+    let presented = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .unwrap_or_default();
+
+    if Sha256::digest(presented.as_bytes()) != Sha256::digest(api_key.as_bytes()) {
+        security_event!(
+            "admin_auth_failed",
+            request_id: request_id(request.headers()),
+            client_ip: client_ip(
+                request
+                    .extensions()
+                    .get::<ConnectInfo<SocketAddr>>()
+                    .map(|ConnectInfo(addr)| addr.ip()),
+                request.headers(),
+                &state.trusted_proxies,
+            ),
+            detail: "Rejected admin request with a missing or invalid API key",
+        );
+        return unauthorized("invalid_api_key", "Invalid admin API key");
+    }
+
+    next.run(request).await
+}
+
+/// Response structure for the login statistics endpoint
+///
+/// # Fields
+///
+/// * `windows` - Statistics of every configured trailing window
+#[derive(Debug, Serialize)]
+pub struct StatsResponse {
+    /// Statistics of every configured trailing window
+    pub windows: Vec<WindowStats>,
+}
+
+/// Login statistics endpoint handler
+///
+/// # Arguments
+///
+/// * `state` - Shared application state holding the statistics
+///
+/// # Returns
+///
+/// Returns total logins, unique subjects and per-provider counts over the
+/// configured trailing windows as JSON
+pub async fn login_stats(State(state): State<Arc<AppState>>) -> Json<StatsResponse> {
+    Json(StatsResponse {
+        windows: state.stats.snapshot(state.clock.now()),
+    })
+}
+
+/// Response structure for the effective configuration endpoint
+///
+/// Built once at startup from the validated settings. Secrets are held in
+/// `SecretString` fields, which always serialize as `[REDACTED]`.
+///
+/// # Fields
+///
+/// * `settings` - The effective settings with every secret redacted
+/// * `sources` - Source that supplied each top-level section
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ConfigResponse {
+    /// The effective settings with every secret redacted
+    pub settings: serde_json::Value,
+    /// Source that supplied each top-level section
+    pub sources: BTreeMap<String, ConfigSource>,
+}
+
+impl ConfigResponse {
+    /// Captures the effective configuration
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The validated settings
+    ///
+    /// # Returns
+    ///
+    /// Returns the sanitized configuration report
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            settings: serde_json::to_value(settings).unwrap_or_default(),
+            sources: settings.sources(),
+        }
+    }
+}
+
+/// Effective configuration endpoint handler
+pub async fn effective_config(State(state): State<Arc<AppState>>) -> Json<ConfigResponse> {
+    Json(state.effective_config.clone())
+}
+
+/// Query parameters of the provider health endpoint
+///
+/// # Fields
+///
+/// * `probe` - Probe the providers' authorization endpoints before reporting
+#[derive(Debug, Deserialize)]
+pub struct ProviderHealthQuery {
+    /// Probe the providers' authorization endpoints before reporting
+    #[serde(default)]
+    probe: bool,
+}
+
+/// Provider health report endpoint handler
+pub async fn provider_health(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ProviderHealthQuery>,
+) -> Json<ProviderHealthReport> {
+    if query.probe {
+        state
+            .provider_health
+            .probe(
+                &state.http_client,
+                &state.oauth_providers,
+                state.clock.now(),
+            )
+            .await;
+    }
+    Json(state.provider_health.report(state.clock.now()))
+}
+
+/// Default number of sessions listed per page
+const DEFAULT_SESSIONS_PAGE: u64 = 50;
+
+/// Maximum number of sessions listed per page
+const MAX_SESSIONS_PAGE: u64 = 500;
+
+/// Query parameters of the session listing endpoint
+///
+/// # Fields
+///
+/// * `subject` - Internal id of the user whose sessions are listed, all users when unset
+/// * `offset` - Number of sessions skipped
+/// * `limit` - Maximum number of sessions listed, at most 500
+#[derive(Debug, Deserialize)]
+pub struct SessionsQuery {
+    /// Internal id of the user whose sessions are listed
+    pub subject: Option<Uuid>,
+    /// Number of sessions skipped
+    #[serde(default)]
+    pub offset: u64,
+    /// Maximum number of sessions listed
+    pub limit: Option<u64>,
+}
+
+/// Response structure for the session listing endpoint
+///
+/// # Fields
+///
+/// * `sessions` - The page of signed-in sessions, latest sign-in first
+/// * `next_offset` - Offset of the next page, `None` on the last page
+#[derive(Debug, Serialize)]
+pub struct SessionsResponse {
+    /// The page of signed-in sessions
+    pub sessions: Vec<UserSession>,
+    /// Offset of the next page
+    pub next_offset: Option<u64>,
+}
+
+/// Session listing endpoint handler
+///
+/// Sessions are read from the session index, to which the callback adds
+/// every sign-in. Ids listed are those assigned at sign-in, which revoke
+/// the session but are not the secret of its cookie.
+///
+/// # Arguments
+///
+/// * `state` - Shared application state holding the user store
+/// * `query` - The user whose sessions are listed and the page
+///
+/// # Returns
+///
+/// Returns the page of sessions that have not expired, or a 500 error when
+/// the store failed
+pub async fn list_sessions(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SessionsQuery>,
+) -> Response<Body> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_SESSIONS_PAGE)
+        .clamp(1, MAX_SESSIONS_PAGE);
+
+    // One more session than the page tells whether another page follows
+    let sessions = state
+        .users
+        .sessions(
+            query.subject,
+            state.clock.now(),
+            query.offset,
+            limit.saturating_add(1),
+        )
+        .await;
+    match sessions {
+        Ok(mut sessions) => {
+            let next_offset = (sessions.len() as u64 > limit).then(|| query.offset + limit);
+            sessions.truncate(limit as usize);
+            Json(SessionsResponse {
+                sessions,
+                next_offset,
+            })
+            .into_response()
+        }
+        Err(e) => {
+            tracing::warn!("Failed to list the sessions: {}", e);
+            internal_error("session_list_failed", "Failed to list the sessions")
+        }
+    }
+}
+
+/// Query parameters of the endpoint revoking the sessions of a user
+///
+/// # Fields
+///
+/// * `subject` - Internal id of the user to sign out of every session
+#[derive(Debug, Deserialize)]
+pub struct SubjectQuery {
+    /// Internal id of the user to sign out of every session
+    pub subject: Option<Uuid>,
+}
+
+/// Endpoint handler revoking a single session
+///
+/// A session the index does not list, such as one signed in before it was
+/// indexed, is revoked all the same.
+///
+/// # Arguments
+///
+/// * `state` - Shared application state holding the user store
+/// * `session_id` - Id of the session, as listed by `GET /admin/sessions`
+/// * `connect_info` - Peer address recorded in the security event
+/// * `headers` - Request headers recorded in the security event
+///
+/// # Returns
+///
+/// Returns 204 No Content once revoked, a 404 error when the index does not
+/// list the session, or a 500 error when the store failed
+pub async fn delete_session(
+    State(state): State<Arc<AppState>>,
+    Path(session_id): Path<Uuid>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    match revoke(
+        &state,
+        Revocation::Session(session_id),
+        connect_info,
+        &headers,
+    )
+    .await
+    {
+        Ok(0) => ApiError::new(StatusCode::NOT_FOUND, "unknown_session", "Unknown session")
+            .into_response(),
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(response) => response,
+    }
+}
+
+/// Endpoint handler revoking every session of a user
+///
+/// # Arguments
+///
+/// * `state` - Shared application state holding the user store
+/// * `query` - The user to sign out
+/// * `connect_info` - Peer address recorded in the security event
+/// * `headers` - Request headers recorded in the security event
+///
+/// # Returns
+///
+/// Returns 204 No Content once revoked, a 400 error without a subject, or a
+/// 500 error when the store failed
+pub async fn delete_sessions(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SubjectQuery>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let Some(subject) = query.subject else {
+        return bad_request("invalid_request", "The subject query parameter is required");
+    };
+    match revoke(&state, Revocation::User(subject), connect_info, &headers).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(response) => response,
+    }
+}
+
+/// Request body of the session revocation endpoint
+///
+/// Exactly one of the fields must be set.
+///
+/// # Fields
+///
+/// * `session_id` - Id of the session to sign out
+/// * `user_id` - Internal id of the user to sign out of every session
+#[derive(Debug, Deserialize)]
+pub struct RevokeRequest {
+    /// Id of the session to sign out
+    #[serde(default)]
+    pub session_id: Option<Uuid>,
+    /// Internal id of the user to sign out of every session
+    #[serde(default)]
+    pub user_id: Option<Uuid>,
+}
+
+/// Session revocation endpoint handler
+///
+/// # Arguments
+///
+/// * `state` - Shared application state holding the user store
+/// * `connect_info` - Peer address recorded in the security event
+/// * `headers` - Request headers recorded in the security event
+/// * `request` - The session or user to revoke
+///
+/// # Returns
+///
+/// Returns 204 No Content once revoked, a 400 error unless exactly one of
+/// the session and the user is given, or a 500 error when the store failed
+pub async fn revoke_sessions(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Json(request): Json<RevokeRequest>,
+) -> Response<Body> {
+    let revocation = match (request.session_id, request.user_id) {
+        (Some(session_id), None) => Revocation::Session(session_id),
+        (None, Some(user_id)) => Revocation::User(user_id),
+        _ => {
+            return bad_request(
+                "invalid_request",
+                "Either a session_id or a user_id is required",
+            )
+        }
+    };
+    match revoke(&state, revocation, connect_info, &headers).await {
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(response) => response,
+    }
+}
+
+/// Request body of the JWT key rotation endpoint
+///
+/// # Fields
+///
+/// * `key_id` - Id of the staged key of `jwt.keys` to sign with
+#[derive(Debug, Deserialize)]
+pub struct RotateKeyRequest {
+    /// Id of the staged key to sign with
+    pub key_id: String,
+}
+
+/// Response of the JWT key rotation endpoint
+///
+/// # Fields
+///
+/// * `active_key` - Id of the key now signing tokens
+/// * `previous_key` - Id of the key that signed until now, which keeps verifying
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RotateKeyResponse {
+    /// Id of the key now signing tokens
+    pub active_key: String,
+    /// Id of the key that signed until now
+    pub previous_key: Option<String>,
+}
+
+/// JWT key rotation endpoint handler
+///
+/// Promotes a staged key of the ring, which signs the tokens issued from
+/// then on. Every key of the ring keeps verifying the tokens it signed. The
+/// promotion only holds for this instance until the configuration is
+/// reloaded, so `jwt.active_key` should be updated alongside it. Every
+/// rotation is recorded as a `jwt_key_rotated` security event.
+///
+/// # Arguments
+///
+/// * `state` - Shared application state holding the token issuer
+/// * `connect_info` - Peer address recorded in the security event
+/// * `headers` - Request headers recorded in the security event
+/// * `request` - The key to promote
+///
+/// # Returns
+///
+/// Returns the active and previous key ids, a 404 error when JWT issuance
+/// is disabled or the key is unknown, or a 409 error when the key only
+/// verifies
+pub async fn rotate_jwt_key(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Json(request): Json<RotateKeyRequest>,
+) -> Response<Body> {
+    let Some(token_issuer) = &state.token_issuer else {
+        return ApiError::new(
+            StatusCode::NOT_FOUND,
+            "jwt_disabled",
+            "JWT issuance is not configured",
+        )
+        .into_response();
+    };
+    let previous_key = match token_issuer.promote(&request.key_id) {
+        Ok(previous_key) => previous_key,
+        Err(KeyRingError::UnknownKey) => {
+            return ApiError::new(StatusCode::NOT_FOUND, "unknown_key", "Unknown JWT key")
+                .into_response()
+        }
+        Err(KeyRingError::VerifyOnly) => {
+            return ApiError::new(
+                StatusCode::CONFLICT,
+                "key_cannot_sign",
+                &KeyRingError::VerifyOnly.to_string(),
+            )
+            .into_response()
+        }
+    };
+
+    let ip = client_ip(
+        connect_info.map(|ConnectInfo(addr)| addr.ip()),
+        &headers,
+        &state.trusted_proxies,
+    );
+    let detail = format!(
+        "An administrator made JWT key {} sign tokens, replacing {}",
+        request.key_id,
+        previous_key.as_deref().unwrap_or("an unnamed key")
+    );
+    security_event!(
+        "jwt_key_rotated",
+        request_id: request_id(&headers),
+        client_ip: ip,
+        detail: &detail,
+    );
+    state.audit.publish(AuditEvent::new(
+        "jwt_key_rotated",
+        state.clock.now(),
+        None,
+        ip.map(|ip| format!("ip:{}", ip)).as_deref(),
+        detail,
+    ));
+
+    Json(RotateKeyResponse {
+        active_key: request.key_id,
+        previous_key,
+    })
+    .into_response()
+}
+
+/// Largest number of sessions included in a user export
+const MAX_EXPORTED_SESSIONS: u64 = 10_000;
+
+/// Provider tokens stored for a user, as included in a user export
+///
+/// The tokens themselves are left out, an export must not hand out
+/// credentials to the provider accounts.
+///
+/// # Fields
+///
+/// * `provider` - Name of the provider that issued the tokens
+/// * `has_refresh_token` - Whether a refresh token is stored
+/// * `expires_at` - Unix timestamp at which the access token expires, if known
+/// * `updated_at` - Unix timestamp at which the tokens were stored
+/// * `needs_reauth_at` - Unix timestamp at which refreshing failed for good, if it did
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedTokens {
+    /// Name of the provider that issued the tokens
+    pub provider: String,
+    /// Whether a refresh token is stored
+    pub has_refresh_token: bool,
+    /// Unix timestamp at which the access token expires
+    pub expires_at: Option<u64>,
+    /// Unix timestamp at which the tokens were stored
+    pub updated_at: u64,
+    /// Unix timestamp at which refreshing failed for good
+    pub needs_reauth_at: Option<u64>,
+}
+
+/// Response structure for the user export endpoint
+///
+/// # Fields
+///
+/// * `user` - The user record, with their email decrypted
+/// * `identities` - Provider accounts linked to the user
+/// * `sessions` - Signed-in sessions of the user that have not expired
+/// * `tokens` - Provider tokens stored for the user, without the tokens themselves
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserExport {
+    /// The user record, with their email decrypted
+    pub user: UserRecord,
+    /// Provider accounts linked to the user
+    pub identities: Vec<Identity>,
+    /// Signed-in sessions of the user
+    pub sessions: Vec<UserSession>,
+    /// Provider tokens stored for the user
+    pub tokens: Vec<ExportedTokens>,
+}
+
+/// User export endpoint handler
+///
+/// Collects everything the user store keeps about a user. Every export is
+/// recorded as a `user_exported` security event.
+///
+/// # Arguments
+///
+/// * `state` - Shared application state holding the user store
+/// * `user_id` - Internal id of the user
+/// * `connect_info` - Peer address recorded in the security event
+/// * `headers` - Request headers recorded in the security event
+///
+/// # Returns
+///
+/// Returns the user as one JSON document, a 404 error for an unknown user,
+/// or a 500 error when the store failed
+pub async fn export_user(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let export = match collect_user(&state, user_id).await {
+        Ok(Some(export)) => export,
+        Ok(None) => {
+            return ApiError::new(StatusCode::NOT_FOUND, "unknown_user", "Unknown user")
+                .into_response()
+        }
+        Err(e) => {
+            tracing::warn!("Failed to export a user: {}", e);
+            return internal_error("user_export_failed", "Failed to export the user");
+        }
+    };
+
+    record_user_event(
+        &state,
+        "user_exported",
+        user_id,
+        connect_info,
+        &headers,
+        "An administrator exported the data of the user",
+    );
+    Json(export).into_response()
+}
+
+/// Reads everything the user store keeps about a user
+async fn collect_user(state: &AppState, user_id: Uuid) -> eyre::Result<Option<UserExport>> {
+    let Some(mut user) = state.users.user(user_id).await? else {
+        return Ok(None);
+    };
+    user.email = user
+        .email
+        .map(|email| state.secret_cipher.decrypt(&email))
+        .transpose()?;
+    let identities = state.users.identities(user_id).await?;
+    let sessions = state
+        .users
+        .sessions(Some(user_id), state.clock.now(), 0, MAX_EXPORTED_SESSIONS)
+        .await?;
+    let tokens = state
+        .users
+        .tokens(user_id)
+        .await?
+        .into_iter()
+        .map(|tokens| ExportedTokens {
+            provider: tokens.provider,
+            has_refresh_token: tokens.refresh_token.is_some(),
+            expires_at: tokens.expires_at,
+            updated_at: tokens.updated_at,
+            needs_reauth_at: tokens.needs_reauth_at,
+        })
+        .collect();
+
+    Ok(Some(UserExport {
+        user,
+        identities,
+        sessions,
+        tokens,
+    }))
+}
+
+/// User deletion endpoint handler
+///
+/// Stored provider tokens are revoked at their provider first, a failed
+/// revocation is logged and does not stop the deletion. The sessions of
+/// the user are then revoked, and the user is removed with their linked
+/// identities, indexed sessions and stored tokens. The login audit log only
+/// holds salted hashes of provider user ids, which no longer lead anywhere
+/// once the identities are gone. Every deletion is recorded as a
+/// `user_deleted` security event.
+///
+/// # Arguments
+///
+/// * `state` - Shared application state holding the user store and the providers
+/// * `user_id` - Internal id of the user
+/// * `connect_info` - Peer address recorded in the security event
+/// * `headers` - Request headers recorded in the security event
+///
+/// # Returns
+///
+/// Returns 204 No Content once deleted, also when the user did not exist,
+/// or a 500 error when the store failed
+pub async fn delete_user(
+    State(state): State<Arc<AppState>>,
+    Path(user_id): Path<Uuid>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let tokens = match state.users.tokens(user_id).await {
+        Ok(tokens) => tokens,
+        Err(e) => {
+            tracing::warn!("Failed to read the tokens of a deleted user: {}", e);
+            return internal_error("user_deletion_failed", "Failed to delete the user");
+        }
+    };
+    for stored in &tokens {
+        revoke_stored_tokens(&state, stored).await;
+    }
+
+    let now = state.clock.now();
+    let deleted = async {
+        state
+            .users
+            .revoke(
+                Revocation::User(user_id),
+                now,
+                now.saturating_add(state.session_ttl_seconds),
+            )
+            .await?;
+        state.users.delete_user(user_id).await
+    }
+    .await;
+    let detail = match deleted {
+        Ok(true) => format!(
+            "An administrator deleted the user, {} provider token set(s) revoked",
+            tokens.len()
+        ),
+        Ok(false) => "An administrator deleted a user that did not exist".to_string(),
+        Err(e) => {
+            tracing::warn!("Failed to delete a user: {}", e);
+            return internal_error("user_deletion_failed", "Failed to delete the user");
+        }
+    };
+
+    record_user_event(
+        &state,
+        "user_deleted",
+        user_id,
+        connect_info,
+        &headers,
+        &detail,
+    );
+    StatusCode::NO_CONTENT.into_response()
+}
+
+/// Revokes the stored tokens of a user at their provider
+///
+/// The refresh token is revoked first, which also ends the access tokens
+/// issued with it at most providers. Failures are only logged.
+async fn revoke_stored_tokens(state: &AppState, stored: &StoredTokens) {
+    let Some(oauth_provider) = state.oauth_providers.get(&stored.provider) else {
+        tracing::warn!(
+            "Cannot revoke the tokens of provider {}, it is no longer configured",
+            stored.provider
+        );
+        return;
+    };
+    for sealed in [stored.refresh_token.as_ref(), Some(&stored.access_token)]
+        .into_iter()
+        .flatten()
+    {
+        let token = match state.secret_cipher.decrypt(sealed) {
+            Ok(token) => token,
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to decrypt a stored {} token: {}",
+                    stored.provider,
+                    e
+                );
+                continue;
+            }
+        };
+        if let Err(e) = oauth_provider.revoke_token(&token).await {
+            // Provider error bodies may echo the token back
+            tracing::warn!(
+                "Token revocation failed for provider {}: {}",
+                stored.provider,
+                redact_secrets(&e.to_string(), &[&token])
+            );
+        }
+    }
+}
+
+/// Records an administrator action on a user
+///
+/// The action is emitted as a security event and published to the audit
+/// webhook.
+fn record_user_event(
+    state: &AppState,
+    event: &'static str,
+    user_id: Uuid,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: &HeaderMap,
+    detail: &str,
+) {
+    let ip = client_ip(
+        connect_info.map(|ConnectInfo(addr)| addr.ip()),
+        headers,
+        &state.trusted_proxies,
+    );
+    security_event!(
+        event,
+        request_id: request_id(headers),
+        client_ip: ip,
+        subject: Some(&user_id.to_string()),
+        detail: detail,
+    );
+    state.audit.publish(AuditEvent::new(
+        event,
+        state.clock.now(),
+        None,
+        ip.map(|ip| format!("ip:{}", ip)).as_deref(),
+        format!("{} (user {})", detail, user_id),
+    ));
+}
+
+/// Revokes a session or every session of a user
+///
+/// The revocation is stored in the user store, where every instance sharing
+/// it signs the revoked sessions out on their next request. It is kept until
+/// the revoked sessions would have expired anyway. Every revocation is
+/// recorded as a `sessions_revoked` security event.
+///
+/// # Arguments
+///
+/// * `state` - Shared application state holding the user store
+/// * `revocation` - The revoked session or user
+/// * `connect_info` - Peer address recorded in the security event
+/// * `headers` - Request headers recorded in the security event
+///
+/// # Returns
+///
+/// Returns the number of indexed sessions revoked, or the 500 error
+/// response when the store failed
+async fn revoke(
+    state: &AppState,
+    revocation: Revocation,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: &HeaderMap,
+) -> Result<u64, Response<Body>> {
+    let now = state.clock.now();
+    let revoked = state
+        .users
+        .revoke(
+            revocation,
+            now,
+            now.saturating_add(state.session_ttl_seconds),
+        )
+        .await
+        .map_err(|e| {
+            tracing::warn!("Failed to revoke sessions: {}", e);
+            internal_error("revocation_failed", "Failed to revoke the sessions")
+        })?;
+
+    let (subject, detail) = match revocation {
+        Revocation::Session(session_id) => (
+            None,
+            format!("An administrator revoked session {}", session_id),
+        ),
+        Revocation::User(user_id) => (
+            Some(user_id.to_string()),
+            format!(
+                "An administrator revoked every session of the user, {} of them listed",
+                revoked
+            ),
+        ),
+    };
+    security_event!(
+        "sessions_revoked",
+        request_id: request_id(headers),
+        client_ip: client_ip(
+            connect_info.map(|ConnectInfo(addr)| addr.ip()),
+            headers,
+            &state.trusted_proxies,
+        ),
+        subject: subject.as_deref(),
+        detail: &detail,
+    );
+    Ok(revoked)
+}
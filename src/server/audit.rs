@@ -0,0 +1,442 @@
+use crate::settings::AuditWebhookSettings;
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use hmac::{Hmac, Mac};
+use reqwest::{Client, Url};
+use serde::Serialize;
+use sha2::Sha256;
+use std::{
+    fmt::Write,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use tokio::{
+    sync::mpsc::{self, error::TrySendError},
+    time::{sleep, timeout},
+};
+
+/// Header carrying the HMAC-SHA256 signature of the request body
+pub const SIGNATURE_HEADER: &str = "x-audit-signature";
+
+/// Header carrying the idempotency id of a batch
+pub const IDEMPOTENCY_HEADER: &str = "x-idempotency-key";
+
+/// Security-relevant event delivered to the audit webhook
+///
+/// # Fields
+///
+/// * `id` - Unique id, receivers deduplicate retried deliveries with it
+/// * `event` - Event type, e.g. `csrf_mismatch` or `state_replay`
+/// * `timestamp` - Unix timestamp at which the event occurred
+/// * `provider` - The OAuth provider involved, if known
+/// * `client` - The client key the event is attributed to, if known
+/// * `detail` - Human readable description
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditEvent {
+    /// Unique id of the event
+    pub id: String,
+    /// Event type
+    pub event: &'static str,
+    /// Unix timestamp at which the event occurred
+    pub timestamp: u64,
+    /// The OAuth provider involved, if known
+    pub provider: Option<String>,
+    /// The client key the event is attributed to, if known
+    pub client: Option<String>,
+    /// Human readable description
+    pub detail: String,
+}
+
+impl AuditEvent {
+    /// Creates a new event with a random id
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - Event type
+    /// * `timestamp` - Unix timestamp at which the event occurred
+    /// * `provider` - The OAuth provider involved, if known
+    /// * `client` - The client key the event is attributed to, if known
+    /// * `detail` - Human readable description
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `AuditEvent` instance
+    pub fn new(
+        event: &'static str,
+        timestamp: u64,
+        provider: Option<&str>,
+        client: Option<&str>,
+        detail: impl Into<String>,
+    ) -> Self {
+        Self {
+            id: random_id(),
+            event,
+            timestamp,
+            provider: provider.map(str::to_string),
+            client: client.map(str::to_string),
+            detail: detail.into(),
+        }
+    }
+}
+
+/// Body of a webhook delivery
+#[derive(Serialize)]
+struct AuditBatch<'a> {
+    /// Idempotency id of the batch
+    id: &'a str,
+    /// Events in the batch
+    events: &'a [AuditEvent],
+}
+
+/// Producer side of the audit webhook stream
+///
+/// Events are pushed onto a bounded queue drained by an [`AuditWorker`].
+/// Publishing never blocks the request path: when the queue is full the
+/// event is dropped and counted.
+///
+/// # Fields
+///
+/// * `sender` - Queue feeding the worker, `None` when no webhook is configured
+/// * `dropped` - Number of events dropped because the queue was full
+pub struct AuditSink {
+    /// Queue feeding the worker
+    sender: Option<mpsc::Sender<AuditEvent>>,
+    /// Number of events dropped because the queue was full
+    dropped: AtomicU64,
+}
+
+impl AuditSink {
+    /// Creates a sink that discards every event
+    pub fn disabled() -> Self {
+        Self {
+            sender: None,
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Creates a sink and the worker delivering its events
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Webhook endpoint, secret and delivery tuning
+    /// * `url` - The parsed webhook URL
+    ///
+    /// # Returns
+    ///
+    /// Returns the sink and the worker, which must be spawned
+    pub fn new(settings: AuditWebhookSettings, url: Url) -> (Self, AuditWorker) {
+        let (sender, receiver) = mpsc::channel(settings.queue_capacity.max(1));
+        let sink = Self {
+            sender: Some(sender),
+            dropped: AtomicU64::new(0),
+        };
+        let worker = AuditWorker {
+            client: Client::new(),
+            url,
+            settings,
+            receiver,
+        };
+        (sink, worker)
+    }
+
+    /// Queues an event for delivery
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event to deliver
+    pub fn publish(&self, event: AuditEvent) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+
+        match sender.try_send(event) {
+            Ok(()) => {}
+            Err(TrySendError::Full(event)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!(
+                    event = event.event,
+                    "Audit webhook queue is full, dropping event"
+                );
+            }
+            Err(TrySendError::Closed(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Returns the number of events dropped because the queue was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Renders the sink's counters in the Prometheus text exposition format
+    pub fn render_metrics(&self) -> String {
+        format!(
+            "# HELP audit_events_dropped_total Audit events dropped because the webhook queue was full\n\
+             # TYPE audit_events_dropped_total counter\n\
+             audit_events_dropped_total {}\n",
+            self.dropped()
+        )
+    }
+}
+
+/// Background task delivering queued audit events to the webhook
+///
+/// Events are sent in batches of up to `batch_size`, or whatever arrived
+/// within `flush_interval_ms` of the first event. Each batch is signed with
+/// HMAC-SHA256 over the exact body bytes and retried with exponential
+/// backoff. Retries resend the same bytes under the same idempotency id, so
+/// delivery is at-least-once and receivers can deduplicate.
+///
+/// # Fields
+///
+/// * `client` - HTTP client used for deliveries
+/// * `url` - Webhook endpoint
+/// * `settings` - Secret and delivery tuning
+/// * `receiver` - Queue of pending events
+pub struct AuditWorker {
+    /// HTTP client used for deliveries
+    client: Client,
+    /// Webhook endpoint
+    url: Url,
+    /// Secret and delivery tuning
+    settings: AuditWebhookSettings,
+    /// Queue of pending events
+    receiver: mpsc::Receiver<AuditEvent>,
+}
+
+impl AuditWorker {
+    /// Delivers events until every sink has been dropped
+    pub async fn run(mut self) {
+        let flush_interval = Duration::from_millis(self.settings.flush_interval_ms);
+        let batch_size = self.settings.batch_size.max(1);
+
+        while let Some(first) = self.receiver.recv().await {
+            let mut events = vec![first];
+
+            // Collect whatever else arrives before the flush deadline
+            let _ = timeout(flush_interval, async {
+                while events.len() < batch_size {
+                    match self.receiver.recv().await {
+                        Some(event) => events.push(event),
+                        None => break,
+                    }
+                }
+            })
+            .await;
+
+            self.deliver(&events).await;
+        }
+    }
+
+    /// Delivers one batch, retrying with exponential backoff
+    async fn deliver(&self, events: &[AuditEvent]) {
+        let id = random_id();
+        let body = match serde_json::to_vec(&AuditBatch { id: &id, events }) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::warn!("Failed to serialize audit batch: {}", e);
+                return;
+            }
+        };
+        let signature = sign(self.settings.secret.expose().as_bytes(), &body);
+
+        let mut backoff = Duration::from_millis(self.settings.initial_backoff_ms);
+        for attempt in 1..=self.settings.max_attempts.max(1) {
+            let result = self
+                .client
+                .post(self.url.clone())
+                .header("content-type", "application/json")
+                .header(SIGNATURE_HEADER, &signature)
+                .header(IDEMPOTENCY_HEADER, &id)
+                .body(body.clone())
+                .send()
+                .await;
+
+            match result {
+                Ok(response) if response.status().is_success() => return,
+                Ok(response) => tracing::warn!(
+                    attempt,
+                    status = %response.status(),
+                    "Audit webhook rejected batch"
+                ),
+                Err(e) => tracing::warn!(
+                    attempt,
+                    "Failed to deliver audit batch: {}",
+                    e.without_url()
+                ),
+            }
+
+            if attempt < self.settings.max_attempts {
+                sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+
+        tracing::warn!(
+            events = events.len(),
+            "Giving up on audit batch after {} attempts",
+            self.settings.max_attempts
+        );
+    }
+}
+
+/// Signs a webhook body with HMAC-SHA256
+///
+/// # Returns
+///
+/// Returns the signature as `sha256=<hex>`
+pub fn sign(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(body);
+    format!("sha256={}", hex(&mac.finalize().into_bytes()))
+}
+
+/// Returns a random 128-bit id in hex
+fn random_id() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    hex(&bytes)
+}
+
+/// Encodes bytes as lowercase hex
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut output, b| {
+        let _ = write!(output, "{:02x}", b);
+        output
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redact::SecretString;
+    use axum::{
+        body::Bytes,
+        http::{HeaderMap, StatusCode},
+        routing::post,
+        Router,
+    };
+    use std::sync::{Arc, Mutex};
+
+    fn settings(batch_size: usize, queue_capacity: usize) -> AuditWebhookSettings {
+        AuditWebhookSettings {
+            url: SecretString::default(),
+            secret: SecretString::from("webhook-secret"),
+            batch_size,
+            flush_interval_ms: 200,
+            queue_capacity,
+            max_attempts: 3,
+            initial_backoff_ms: 10,
+        }
+    }
+
+    fn event(name: &'static str) -> AuditEvent {
+        AuditEvent::new(name, 1_000, Some("google"), Some("ip:10.0.0.1"), "detail")
+    }
+
+    /// Starts a receiver that fails the first request with 500 and records every delivery
+    async fn receiver() -> (Url, Arc<Mutex<Vec<(HeaderMap, Bytes)>>>) {
+        let deliveries = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&deliveries);
+
+        let router = Router::new().route(
+            "/audit",
+            post(move |headers: HeaderMap, body: Bytes| async move {
+                let mut deliveries = recorded.lock().unwrap();
+                deliveries.push((headers, body));
+                if deliveries.len() == 1 {
+                    StatusCode::INTERNAL_SERVER_ERROR
+                } else {
+                    StatusCode::OK
+                }
+            }),
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        (
+            Url::parse(&format!("http://{}/audit", address)).unwrap(),
+            deliveries,
+        )
+    }
+
+    /// Tests batching, signatures and retry after a 500
+    #[tokio::test]
+    async fn test_delivers_signed_batches_with_retry() {
+        let (url, deliveries) = receiver().await;
+        let (sink, worker) = AuditSink::new(settings(3, 10), url);
+        tokio::spawn(worker.run());
+
+        sink.publish(event("csrf_mismatch"));
+        sink.publish(event("state_replay"));
+        sink.publish(event("csrf_failure_threshold"));
+
+        for _ in 0..100 {
+            if deliveries.lock().unwrap().len() >= 2 {
+                break;
+            }
+            sleep(Duration::from_millis(20)).await;
+        }
+
+        let deliveries = deliveries.lock().unwrap();
+        assert_eq!(
+            deliveries.len(),
+            2,
+            "expected one failed and one retried delivery"
+        );
+
+        let (first_headers, first_body) = &deliveries[0];
+        let (second_headers, second_body) = &deliveries[1];
+
+        // The retry resends the exact same batch
+        assert_eq!(first_body, second_body);
+        assert_eq!(
+            first_headers[IDEMPOTENCY_HEADER],
+            second_headers[IDEMPOTENCY_HEADER]
+        );
+
+        // The signature covers the exact bytes sent
+        assert_eq!(
+            second_headers[SIGNATURE_HEADER].to_str().unwrap(),
+            sign(b"webhook-secret", second_body)
+        );
+        assert_ne!(
+            second_headers[SIGNATURE_HEADER].to_str().unwrap(),
+            sign(b"other-secret", second_body)
+        );
+
+        let batch: serde_json::Value = serde_json::from_slice(second_body).unwrap();
+        let events: Vec<&str> = batch["events"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|event| event["event"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            events,
+            ["csrf_mismatch", "state_replay", "csrf_failure_threshold"]
+        );
+        assert_eq!(
+            batch["id"].as_str().unwrap(),
+            second_headers[IDEMPOTENCY_HEADER].to_str().unwrap()
+        );
+    }
+
+    /// Tests that events are dropped and counted rather than blocking when the queue is full
+    #[tokio::test]
+    async fn test_drops_when_queue_is_full() {
+        let url = Url::parse("http://127.0.0.1:9/audit").unwrap();
+        let (sink, _worker) = AuditSink::new(settings(10, 2), url);
+
+        for _ in 0..5 {
+            sink.publish(event("csrf_mismatch"));
+        }
+
+        assert_eq!(sink.dropped(), 3);
+        assert!(sink
+            .render_metrics()
+            .contains("audit_events_dropped_total 3"));
+    }
+}
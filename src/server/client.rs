@@ -0,0 +1,296 @@
+use crate::settings::FlowBinding;
+use axum::http::{
+    header::{HOST, USER_AGENT},
+    HeaderMap,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+
+/// Header carrying the client address chain set by reverse proxies
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// Standard header carrying the client address chain (RFC 7239)
+const FORWARDED: &str = "forwarded";
+
+/// Header carrying the host requested by the client, set by reverse proxies
+const X_FORWARDED_HOST: &str = "x-forwarded-host";
+
+/// Number of hash bytes kept in a flow fingerprint
+const FINGERPRINT_LEN: usize = 16;
+
+/// Resolves the client IP address of a request
+///
+/// The peer address is used unless it belongs to a trusted proxy, in which
+/// case the `X-Forwarded-For` chain, or the `for` parameters of the
+/// `Forwarded` header when there is none, is walked from the right,
+/// skipping trusted proxies, and the first untrusted address is returned.
+/// Headers from untrusted peers are ignored since any client can set them.
+///
+/// # Arguments
+///
+/// * `peer` - The address of the TCP peer, if known
+/// * `headers` - The request headers
+/// * `trusted_proxies` - Addresses of proxies whose forwarding headers are honored
+///
+/// # Returns
+///
+/// Returns the resolved client IP, or `None` when the peer is unknown
+pub fn client_ip(
+    peer: Option<IpAddr>,
+    headers: &HeaderMap,
+    trusted_proxies: &[IpAddr],
+) -> Option<IpAddr> {
+    let peer = peer?;
+    if !trusted_proxies.contains(&peer) {
+        return Some(peer);
+    }
+
+    let mut forwarded: Vec<IpAddr> = headers
+        .get_all(X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|entry| entry.trim().parse().ok())
+        .collect();
+    if forwarded.is_empty() {
+        forwarded = headers
+            .get_all(FORWARDED)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(forwarded_for)
+            .collect();
+    }
+
+    Some(
+        forwarded
+            .into_iter()
+            .rev()
+            .find(|ip| !trusted_proxies.contains(ip))
+            .unwrap_or(peer),
+    )
+}
+
+/// Resolves the host a request was sent to
+///
+/// The `Host` header is used unless the peer is a trusted proxy, in which
+/// case the first `X-Forwarded-Host` entry, the host the client requested
+/// from the proxy, takes precedence.
+///
+/// # Arguments
+///
+/// * `peer` - The address of the TCP peer, if known
+/// * `headers` - The request headers
+/// * `trusted_proxies` - Addresses of proxies whose forwarding headers are honored
+///
+/// # Returns
+///
+/// Returns the lowercased host with its port, if any, or `None` when the
+/// request names no host
+pub fn request_host(
+    peer: Option<IpAddr>,
+    headers: &HeaderMap,
+    trusted_proxies: &[IpAddr],
+) -> Option<String> {
+    let forwarded = peer
+        .filter(|peer| trusted_proxies.contains(peer))
+        .and_then(|_| headers.get(X_FORWARDED_HOST))
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(',').next());
+    let host = match forwarded {
+        Some(host) => host,
+        None => headers.get(HOST)?.to_str().ok()?,
+    };
+
+    let host = host.trim();
+    (!host.is_empty()).then(|| host.to_ascii_lowercase())
+}
+
+/// Extracts the address of a `Forwarded` header element
+///
+/// # Arguments
+///
+/// * `element` - One comma-separated element, e.g. `for="[2001:db8::1]:443";proto=https`
+///
+/// # Returns
+///
+/// Returns the address of the `for` parameter, or `None` when it is missing
+/// or obfuscated
+fn forwarded_for(element: &str) -> Option<IpAddr> {
+    let node = element.split(';').find_map(|pair| {
+        let (name, value) = pair.trim().split_once('=')?;
+        name.eq_ignore_ascii_case("for")
+            .then(|| value.trim_matches('"'))
+    })?;
+
+    // IPv6 addresses are bracketed, ports follow the address
+    match node.strip_prefix('[') {
+        Some(node) => node.split(']').next()?.parse().ok(),
+        None => node.split(':').next()?.parse().ok(),
+    }
+}
+
+/// Computes the fingerprint binding a pending flow to its client
+///
+/// The fingerprint is a SHA-256 hash truncated to 128 bits, so neither the
+/// IP address nor the user agent is stored in the flow state, and the
+/// stateless flow state stays compact.
+///
+/// # Arguments
+///
+/// * `binding` - Which client attributes are bound
+/// * `ip` - The resolved client IP
+/// * `headers` - The request headers, used for the user agent
+///
+/// # Returns
+///
+/// Returns the fingerprint, or `None` when binding is off
+pub fn flow_fingerprint(
+    binding: FlowBinding,
+    ip: Option<IpAddr>,
+    headers: &HeaderMap,
+) -> Option<String> {
+    let ip = ip.map(|ip| ip.to_string()).unwrap_or_default();
+    let material = match binding {
+        FlowBinding::Off => return None,
+        FlowBinding::Ip => ip,
+        FlowBinding::IpAndUa => {
+            let user_agent = headers
+                .get(USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default();
+            format!("{}\n{}", ip, user_agent)
+        }
+    };
+
+    Some(URL_SAFE_NO_PAD.encode(&Sha256::digest(material.as_bytes())[..FINGERPRINT_LEN]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn ip(value: &str) -> IpAddr {
+        value.parse().unwrap()
+    }
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(*name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    /// Tests that forwarding headers from untrusted peers are ignored
+    #[test]
+    fn test_untrusted_peer_ignores_forwarded_for() {
+        let headers = headers(&[(X_FORWARDED_FOR, "1.1.1.1")]);
+        assert_eq!(
+            client_ip(Some(ip("9.9.9.9")), &headers, &[ip("10.0.0.1")]),
+            Some(ip("9.9.9.9"))
+        );
+    }
+
+    /// Tests that the first untrusted address from the right is used
+    #[test]
+    fn test_trusted_proxy_chain() {
+        let proxies = [ip("10.0.0.1"), ip("10.0.0.2")];
+        let headers = headers(&[(X_FORWARDED_FOR, "6.6.6.6, 1.1.1.1, 10.0.0.2")]);
+
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.1")), &headers, &proxies),
+            Some(ip("1.1.1.1"))
+        );
+    }
+
+    /// Tests that the Forwarded header is used when X-Forwarded-For is absent
+    #[test]
+    fn test_forwarded_header() {
+        let proxies = [ip("10.0.0.1")];
+        let forwarded = headers(&[(
+            FORWARDED,
+            "for=6.6.6.6, for=\"[2001:db8::1]:4711\";proto=https, for=10.0.0.1:80",
+        )]);
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.1")), &forwarded, &proxies),
+            Some(ip("2001:db8::1"))
+        );
+
+        let both = headers(&[(X_FORWARDED_FOR, "1.1.1.1"), (FORWARDED, "for=6.6.6.6")]);
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.1")), &both, &proxies),
+            Some(ip("1.1.1.1"))
+        );
+        assert_eq!(forwarded_for("for=_hidden;proto=https"), None);
+    }
+
+    /// Tests that a trusted peer without forwarding headers is used as-is
+    #[test]
+    fn test_trusted_proxy_without_header() {
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.1")), &HeaderMap::new(), &[ip("10.0.0.1")]),
+            Some(ip("10.0.0.1"))
+        );
+    }
+
+    /// Tests that X-Forwarded-Host is only honored from trusted proxies
+    #[test]
+    fn test_request_host() {
+        let proxies = [ip("10.0.0.1")];
+        let headers = headers(&[
+            ("host", "internal:8080"),
+            (X_FORWARDED_HOST, "App.example.com, internal"),
+        ]);
+
+        assert_eq!(
+            request_host(Some(ip("10.0.0.1")), &headers, &proxies).as_deref(),
+            Some("app.example.com")
+        );
+        assert_eq!(
+            request_host(Some(ip("9.9.9.9")), &headers, &proxies).as_deref(),
+            Some("internal:8080")
+        );
+        assert_eq!(request_host(None, &HeaderMap::new(), &proxies), None);
+    }
+
+    /// Tests that matching clients produce matching fingerprints
+    #[test]
+    fn test_fingerprint_match() {
+        let headers = headers(&[("user-agent", "Mozilla/5.0")]);
+        let first = flow_fingerprint(FlowBinding::IpAndUa, Some(ip("1.1.1.1")), &headers);
+        let second = flow_fingerprint(FlowBinding::IpAndUa, Some(ip("1.1.1.1")), &headers);
+
+        assert!(first.is_some());
+        assert_eq!(first, second);
+        assert_eq!(
+            flow_fingerprint(FlowBinding::Off, Some(ip("1.1.1.1")), &headers),
+            None
+        );
+    }
+
+    /// Tests that a changed IP or user agent changes the fingerprint
+    #[test]
+    fn test_fingerprint_mismatch() {
+        let firefox = headers(&[("user-agent", "Firefox")]);
+        let chrome = headers(&[("user-agent", "Chrome")]);
+
+        let original = flow_fingerprint(FlowBinding::IpAndUa, Some(ip("1.1.1.1")), &firefox);
+        assert_ne!(
+            original,
+            flow_fingerprint(FlowBinding::IpAndUa, Some(ip("2.2.2.2")), &firefox)
+        );
+        assert_ne!(
+            original,
+            flow_fingerprint(FlowBinding::IpAndUa, Some(ip("1.1.1.1")), &chrome)
+        );
+
+        // The user agent is ignored when only the IP is bound
+        assert_eq!(
+            flow_fingerprint(FlowBinding::Ip, Some(ip("1.1.1.1")), &firefox),
+            flow_fingerprint(FlowBinding::Ip, Some(ip("1.1.1.1")), &chrome)
+        );
+    }
+}
@@ -0,0 +1,183 @@
+use crate::settings::CorsSettings;
+use axum::http::{HeaderValue, Method};
+use eyre::{bail, eyre, Result};
+use reqwest::Url;
+use std::time::Duration;
+use tower_http::cors::{AllowHeaders, AllowOrigin, Any, CorsLayer};
+
+/// Origin entry allowing every origin
+const ANY_ORIGIN: &str = "*";
+
+/// Returns the CORS layer allowing any origin
+///
+/// Cross-origin requests may use GET and POST with any header, but never
+/// carry credentials.
+///
+/// # Returns
+///
+/// Returns the permissive `CorsLayer`
+pub fn permissive_cors() -> CorsLayer {
+    CorsLayer::new()
+        .allow_methods(vec![Method::GET, Method::POST])
+        .allow_origin(Any)
+        .allow_headers(AllowHeaders::any())
+}
+
+/// Builds the CORS layer from the settings
+///
+/// Without a `[cors]` section the permissive layer is used and a warning
+/// is logged. Allowed origins are compared to the browser's `Origin`
+/// header, so they are normalized to `scheme://host[:port]`.
+///
+/// # Arguments
+///
+/// * `settings` - The `[cors]` section, if configured
+///
+/// # Returns
+///
+/// Returns the `CorsLayer`, or an error if an origin or method is invalid,
+/// or if credentials are allowed for any origin
+pub fn cors_layer(settings: Option<&CorsSettings>) -> Result<CorsLayer> {
+    let Some(settings) = settings else {
+        tracing::warn!(
+            "No [cors] section configured, allowing cross-origin requests from any origin"
+        );
+        return Ok(permissive_cors());
+    };
+
+    let any_origin = settings
+        .allowed_origins
+        .iter()
+        .any(|origin| origin == ANY_ORIGIN);
+    if any_origin && settings.allow_credentials {
+        bail!("cors.allow_credentials cannot be combined with the \"*\" origin");
+    }
+    if any_origin && settings.allowed_origins.len() > 1 {
+        bail!("cors.allowed_origins cannot list origins next to \"*\"");
+    }
+
+    let allow_origin = if any_origin {
+        AllowOrigin::any()
+    } else {
+        let origins = settings
+            .allowed_origins
+            .iter()
+            .map(|origin| {
+                let url = Url::parse(origin)
+                    .map_err(|e| eyre!("Invalid cors.allowed_origins entry {}: {}", origin, e))?;
+                if !url.origin().is_tuple() {
+                    bail!("cors.allowed_origins entry {} has no origin", origin);
+                }
+                Ok(HeaderValue::try_from(url.origin().ascii_serialization())?)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        AllowOrigin::list(origins)
+    };
+
+    let methods = settings
+        .allowed_methods
+        .iter()
+        .map(|method| {
+            Method::from_bytes(method.to_ascii_uppercase().as_bytes())
+                .map_err(|_| eyre!("Invalid cors.allowed_methods entry {}", method))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Browsers refuse a wildcard header list on credentialed requests
+    let allow_headers = if settings.allow_credentials {
+        AllowHeaders::mirror_request()
+    } else {
+        AllowHeaders::any()
+    };
+
+    let mut layer = CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods(methods)
+        .allow_headers(allow_headers)
+        .allow_credentials(settings.allow_credentials);
+    if let Some(max_age_seconds) = settings.max_age_seconds {
+        layer = layer.max_age(Duration::from_secs(max_age_seconds));
+    }
+
+    Ok(layer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode},
+        routing::get,
+        Router,
+    };
+    use tower::ServiceExt;
+
+    fn settings(origins: &[&str], allow_credentials: bool) -> CorsSettings {
+        CorsSettings {
+            allowed_origins: origins.iter().map(|origin| origin.to_string()).collect(),
+            allow_credentials,
+            max_age_seconds: Some(600),
+            ..CorsSettings::default()
+        }
+    }
+
+    /// Sends a preflight request from the origin and returns the response headers
+    async fn preflight(layer: CorsLayer, origin: &str) -> axum::http::HeaderMap {
+        let router = Router::new().route("/me", get(|| async {})).layer(layer);
+        let response = router
+            .oneshot(
+                Request::options("/me")
+                    .header("origin", origin)
+                    .header("access-control-request-method", "GET")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        response.headers().clone()
+    }
+
+    /// Tests that preflights from configured origins are allowed and others are not
+    #[tokio::test]
+    async fn test_allowed_origins() {
+        let layer = || cors_layer(Some(&settings(&["https://app.example.com/"], true))).unwrap();
+
+        let headers = preflight(layer(), "https://app.example.com").await;
+        assert_eq!(
+            headers["access-control-allow-origin"],
+            "https://app.example.com"
+        );
+        assert_eq!(headers["access-control-allow-credentials"], "true");
+        assert_eq!(headers["access-control-max-age"], "600");
+
+        let headers = preflight(layer(), "https://evil.example.com").await;
+        assert!(headers.get("access-control-allow-origin").is_none());
+    }
+
+    /// Tests that the wildcard origin and the permissive default allow any origin
+    #[tokio::test]
+    async fn test_any_origin() {
+        let layer = cors_layer(Some(&settings(&["*"], false))).unwrap();
+        let headers = preflight(layer, "https://anywhere.example.com").await;
+        assert_eq!(headers["access-control-allow-origin"], "*");
+
+        let headers = preflight(cors_layer(None).unwrap(), "https://anywhere.example.com").await;
+        assert_eq!(headers["access-control-allow-origin"], "*");
+        assert!(headers.get("access-control-allow-credentials").is_none());
+    }
+
+    /// Tests that invalid combinations are rejected at startup
+    #[test]
+    fn test_invalid_settings() {
+        assert!(cors_layer(Some(&settings(&["*"], true))).is_err());
+        assert!(cors_layer(Some(&settings(&["*", "https://app.example.com"], false))).is_err());
+        assert!(cors_layer(Some(&settings(&["app.example.com"], false))).is_err());
+        assert!(cors_layer(Some(&CorsSettings {
+            allowed_methods: vec!["NOT A METHOD".to_string()],
+            ..settings(&["https://app.example.com"], false)
+        }))
+        .is_err());
+    }
+}
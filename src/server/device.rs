@@ -0,0 +1,400 @@
+//! Device authorization grant (RFC 8628)
+//!
+//! Clients without a browser, such as CLIs, start a device flow and show
+//! the user a code to enter at the provider's verification page, then poll
+//! until the user approved the device. The completed flow returns the same
+//! response as the callback, without signing a session in.
+
+use crate::{
+    primitives::{DeviceAuthorization, DevicePoll, UserInfo},
+    redact::{redact_secrets, Redact},
+    server::{
+        errors::{ApiError, AppError},
+        handlers::{
+            audit_login, check_granted_scopes, fetch_user_info, issue_token, record_call_status,
+            record_login_outcome, reject_unverified_email, request_ip, store_provider_tokens,
+            store_user, CallbackClient, CallbackResponse,
+        },
+        login_audit::LoginAction,
+        metrics::{FlowOutcome, FlowStage},
+        security::request_id,
+        server::AppState,
+    },
+    traits::{OAuthProvider, ProviderTimeout, TokenExchangeError},
+};
+use axum::{
+    extract::{ConnectInfo, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Instant,
+};
+use tracing::Instrument;
+
+/// Seconds between two polls when the client does not send its interval
+const DEFAULT_POLL_INTERVAL: u64 = 5;
+
+/// Query parameters of a device flow start
+///
+/// # Fields
+///
+/// * `provider` - The name of the OAuth provider
+#[derive(Debug, Deserialize)]
+pub struct DeviceStartParams {
+    /// OAuth provider name
+    provider: String,
+}
+
+/// Device flow poll request body
+///
+/// # Fields
+///
+/// * `provider` - The name of the OAuth provider the flow was started with
+/// * `device_code` - The device code returned by `/device/start`
+/// * `interval` - Seconds the client currently waits between polls, 5 when omitted
+#[derive(Deserialize)]
+pub struct DevicePollRequest {
+    /// OAuth provider name
+    provider: String,
+    /// Device code returned by `/device/start`
+    device_code: String,
+    /// Seconds the client currently waits between polls
+    #[serde(default)]
+    interval: Option<u64>,
+}
+
+impl std::fmt::Debug for DevicePollRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DevicePollRequest")
+            .field("provider", &self.provider)
+            .field("device_code", &Redact(&self.device_code))
+            .field("interval", &self.interval)
+            .finish()
+    }
+}
+
+/// Details of a pending device flow poll
+///
+/// # Fields
+///
+/// * `interval` - Seconds to wait before the next poll
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PollInterval {
+    /// Seconds to wait before the next poll
+    pub interval: u64,
+}
+
+/// Returns a provider whose device flow is enabled
+///
+/// # Returns
+///
+/// Returns the provider, or an `UnknownProvider` or
+/// `device_flow_unsupported` error
+fn device_provider<'a>(
+    state: &'a AppState,
+    provider: &str,
+) -> Result<&'a Arc<dyn OAuthProvider>, AppError> {
+    let oauth_provider = state
+        .oauth_providers
+        .get(provider)
+        .ok_or_else(|| AppError::UnknownProvider(provider.to_string()))?;
+    if !oauth_provider.supports_device_flow()
+        || oauth_provider
+            .get_oauth_client()
+            .device_authorization_url()
+            .is_none()
+    {
+        return Err(AppError::rejected(
+            Some(provider),
+            StatusCode::BAD_REQUEST,
+            "device_flow_unsupported",
+            "The provider has no device flow configured",
+        ));
+    }
+    Ok(oauth_provider)
+}
+
+/// Device flow start handler
+///
+/// Requests a device and user code from the provider. The client shows the
+/// user code and verification URI to the user, then polls `/device/poll`
+/// with the device code.
+///
+/// # Arguments
+///
+/// * `state` - Shared application state containing OAuth providers
+/// * `params` - Query parameters naming the provider
+///
+/// # Returns
+///
+/// Returns the provider's `DeviceAuthorization`, a 400 error when the
+/// provider is unknown or has no device flow, or a 504 error when the
+/// provider did not answer in time
+pub async fn device_start(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<DeviceStartParams>,
+) -> Result<Json<DeviceAuthorization>, AppError> {
+    let oauth_provider = device_provider(&state, &params.provider)?;
+
+    let span = tracing::info_span!(
+        "device_authorization",
+        provider = %params.provider,
+        http.response.status_code = tracing::field::Empty,
+        otel.status_code = tracing::field::Empty,
+    );
+    let authorization = oauth_provider
+        .start_device_authorization()
+        .instrument(span.clone())
+        .await;
+    record_call_status(&span, authorization.is_ok());
+
+    match authorization {
+        Ok(authorization) => {
+            state
+                .metrics
+                .record(Some(&params.provider), FlowOutcome::Initiated);
+            Ok(Json(authorization))
+        }
+        Err(e) => match e.downcast_ref::<ProviderTimeout>() {
+            Some(timeout) => Err(AppError::ProviderTimeout {
+                provider: params.provider,
+                reason: timeout.to_string(),
+            }),
+            None => Err(AppError::unexpected(
+                "device_authorization_failed",
+                "Failed to start the device authorization",
+                e.as_ref(),
+                Some(&params.provider),
+                &[],
+            )),
+        },
+    }
+}
+
+/// Device flow poll handler
+///
+/// Polls the provider's token endpoint once. Until the user approved the
+/// device, the client gets an `authorization_pending` or `slow_down` error
+/// carrying the interval to wait before polling again. Neither is logged,
+/// they are the expected answer to most polls.
+///
+/// Once approved, the user is signed in like by the callback: the granted
+/// scopes and email are checked, the user is stored and a JWT is issued
+/// when configured. No session is signed in, the client keeps the
+/// returned tokens.
+///
+/// # Arguments
+///
+/// * `state` - Shared application state containing OAuth providers
+/// * `connect_info` - Peer address recorded in the audit log
+/// * `headers` - Request headers recorded in the audit log
+/// * `request` - The provider, device code and current interval
+///
+/// # Returns
+///
+/// Returns the `CallbackResponse` once the user approved the device, a
+/// 400 error while the authorization is pending or when the user denied
+/// it or the device code expired, or a 504 error when the provider did
+/// not answer in time
+pub async fn device_poll(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Json(request): Json<DevicePollRequest>,
+) -> Result<Response, AppError> {
+    let ip = request_ip(&state, connect_info.as_ref(), &headers);
+    match complete_device_flow(&state, &request, ip, &headers).await {
+        Ok(DeviceOutcome::Pending(error)) => Ok(error.into_response()),
+        Ok(DeviceOutcome::Completed(response)) => {
+            audit_login(
+                &state,
+                LoginAction::Login,
+                Some(&request.provider),
+                Some(&response.user_id),
+                ip,
+                &headers,
+                None,
+            );
+            Ok(response.into_response())
+        }
+        Err(e) => {
+            audit_login(
+                &state,
+                LoginAction::Login,
+                e.provider(),
+                None,
+                ip,
+                &headers,
+                Some(e.code()),
+            );
+            Err(e)
+        }
+    }
+}
+
+/// Result of a device flow poll
+///
+/// * `Pending` - The user has not approved the device yet, with the error sent back
+/// * `Completed` - The user approved the device and was signed in
+enum DeviceOutcome {
+    /// The user has not approved the device yet
+    Pending(ApiError),
+    /// The user approved the device
+    Completed(Box<CallbackResponse>),
+}
+
+/// Polls the token endpoint and signs the user in once the device is approved
+async fn complete_device_flow(
+    state: &AppState,
+    request: &DevicePollRequest,
+    ip: Option<IpAddr>,
+    headers: &HeaderMap,
+) -> Result<DeviceOutcome, AppError> {
+    let provider = request.provider.as_str();
+    let oauth_provider = device_provider(state, provider)?;
+
+    let interval = request.interval.unwrap_or(DEFAULT_POLL_INTERVAL);
+    let poll_started = Instant::now();
+    let poll_span = tracing::info_span!(
+        "token_exchange",
+        provider = %provider,
+        http.response.status_code = tracing::field::Empty,
+        otel.status_code = tracing::field::Empty,
+    );
+    let poll = oauth_provider
+        .poll_device_token(&request.device_code, interval, state.clock.now())
+        .instrument(poll_span.clone())
+        .await;
+    record_call_status(&poll_span, poll.is_ok());
+
+    let tokens = match poll {
+        Ok(DevicePoll::Pending { interval }) => {
+            return Ok(DeviceOutcome::Pending(
+                ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    "authorization_pending",
+                    "The user has not approved the device yet",
+                )
+                .with_details(PollInterval { interval }),
+            ));
+        }
+        Ok(DevicePoll::SlowDown { interval }) => {
+            return Ok(DeviceOutcome::Pending(
+                ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    "slow_down",
+                    "Polling too often, wait longer between polls",
+                )
+                .with_details(PollInterval { interval }),
+            ));
+        }
+        Ok(DevicePoll::Complete(tokens)) => tokens,
+        Err(e) => return Err(poll_error(state, provider, &request.device_code, e)),
+    };
+    state
+        .metrics
+        .observe(provider, FlowStage::TokenExchange, poll_started.elapsed());
+    state
+        .provider_health
+        .record(provider, FlowStage::TokenExchange, true, state.clock.now());
+
+    let missing_scopes = check_granted_scopes(state, provider, oauth_provider.as_ref(), &tokens)?;
+    // Without a nonce, an ID token cannot be bound to this flow
+    let user_info =
+        fetch_user_info(state, provider, oauth_provider.as_ref(), &tokens, None).await?;
+    let client = CallbackClient {
+        ip,
+        request_id: request_id(headers),
+        keys: Vec::new(),
+    };
+    reject_unverified_email(state, &client, provider, &user_info)?;
+    let stored_user = store_user(state, provider, &user_info).await?;
+    store_provider_tokens(state, provider, stored_user.id, &tokens).await?;
+    let token = issue_token(state, provider, &user_info.id)?;
+
+    let UserInfo {
+        id: user_id,
+        email,
+        name,
+        username,
+        avatar_url,
+        tenant,
+        raw,
+        ..
+    } = user_info;
+    record_login_outcome(state, provider, FlowOutcome::Completed, Some(&user_id));
+
+    Ok(DeviceOutcome::Completed(Box::new(CallbackResponse {
+        user_id,
+        internal_user_id: stored_user.id,
+        email,
+        name,
+        username,
+        avatar_url,
+        tenant,
+        raw,
+        tokens,
+        missing_scopes,
+        token,
+    })))
+}
+
+/// Converts a failed device token poll to the error sent back
+///
+/// # Returns
+///
+/// Returns an `authorization_denied` or `expired_token` error when the
+/// flow ended, a `ProviderTimeout`, or a `TokenExchange` error otherwise
+fn poll_error(state: &AppState, provider: &str, device_code: &str, e: eyre::Report) -> AppError {
+    state
+        .provider_health
+        .record(provider, FlowStage::TokenExchange, false, state.clock.now());
+
+    let error = e.downcast_ref::<TokenExchangeError>();
+    match error.map(|error| error.error.as_str()) {
+        Some("access_denied") => {
+            record_login_outcome(state, provider, FlowOutcome::Denied, None);
+            return AppError::rejected(
+                Some(provider),
+                StatusCode::BAD_REQUEST,
+                "authorization_denied",
+                "The user denied the device authorization",
+            );
+        }
+        Some("expired_token") => {
+            return AppError::rejected(
+                Some(provider),
+                StatusCode::BAD_REQUEST,
+                "expired_token",
+                "The device code has expired, please start again",
+            );
+        }
+        _ => {}
+    }
+
+    record_login_outcome(state, provider, FlowOutcome::ExchangeFailed, None);
+    if let Some(timeout) = e.downcast_ref::<ProviderTimeout>() {
+        return AppError::ProviderTimeout {
+            provider: provider.to_string(),
+            reason: timeout.to_string(),
+        };
+    }
+    // Provider error bodies may echo the device code back
+    let secrets = [device_code];
+    AppError::TokenExchange {
+        provider: provider.to_string(),
+        reason: redact_secrets(&e.to_string(), &secrets),
+        error: error.map(|error| TokenExchangeError {
+            error: redact_secrets(&error.error, &secrets),
+            error_description: error
+                .error_description
+                .as_deref()
+                .map(|description| redact_secrets(description, &secrets)),
+        }),
+    }
+}
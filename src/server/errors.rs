@@ -1,5 +1,423 @@
-use axum::{body::Body, http::Response, response::IntoResponse};
+use crate::{
+    server::{redirect::Redirects, reporting::Failure},
+    traits::TokenExchangeError,
+};
+use axum::{
+    body::Body,
+    http::{header::WWW_AUTHENTICATE, Response},
+    response::{IntoResponse, Redirect},
+    Json,
+};
 use reqwest::StatusCode;
+use serde::Serialize;
+
+/// JSON error returned by every handler
+///
+/// Clients branch on `code`, which is a stable identifier such as
+/// `invalid_provider` or `csrf_mismatch`. `message` is meant for humans
+/// and may change.
+///
+/// # Fields
+///
+/// * `status` - HTTP status of the response, not part of the body
+/// * `code` - Stable error code
+/// * `message` - Human-readable description of the error
+/// * `details` - Additional data about the error, if any
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    /// HTTP status of the response
+    #[serde(skip)]
+    pub status: StatusCode,
+    /// Stable error code
+    pub code: &'static str,
+    /// Human-readable description of the error
+    pub message: String,
+    /// Additional data about the error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+impl ApiError {
+    /// Creates an error without details
+    ///
+    /// # Arguments
+    ///
+    /// * `status` - HTTP status of the response
+    /// * `code` - Stable error code
+    /// * `message` - Human-readable description of the error
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `ApiError`
+    pub fn new(status: StatusCode, code: &'static str, message: &str) -> Self {
+        Self {
+            status,
+            code,
+            message: message.to_string(),
+            details: None,
+        }
+    }
+
+    /// Attaches additional data to the error
+    ///
+    /// # Arguments
+    ///
+    /// * `details` - Data serialized into the `details` field
+    ///
+    /// # Returns
+    ///
+    /// Returns the error with its details set, unchanged if they cannot be serialized
+    pub fn with_details(mut self, details: impl Serialize) -> Self {
+        self.details = serde_json::to_value(details).ok();
+        self
+    }
+}
+
+impl IntoResponse for ApiError {
+    /// Converts the error to an HTTP response
+    ///
+    /// # Returns
+    ///
+    /// Returns a response with the error's status and a JSON body
+    fn into_response(self) -> axum::response::Response {
+        (self.status, Json(self)).into_response()
+    }
+}
+
+/// Error returned by the OAuth flow handlers
+///
+/// Each variant maps to a status and an `ApiError` body, and every error
+/// is logged once with its provider when it is turned into a response.
+/// Unexpected failures carry a sanitized `Failure`, attached to the
+/// response for error reporting. Texts are sanitized by the constructors,
+/// so variants never hold secrets.
+///
+/// * `Session` - The flow state could not be read from or written to the session
+/// * `UnknownProvider` - The requested provider is not configured
+/// * `CsrfMismatch` - The callback's `state` does not match the pending flow
+/// * `TokenExchange` - The provider's token endpoint rejected the code exchange
+/// * `UserInfo` - The provider's user info request failed
+/// * `ProviderTimeout` - The provider did not answer the token or user info request in time
+/// * `Rejected` - Any other rejected request, with the error sent back
+/// * `Unexpected` - Any other unexpected failure
+#[derive(Debug)]
+pub enum AppError {
+    /// The flow state could not be read from or written to the session
+    Session(Failure),
+    /// The requested provider is not configured
+    UnknownProvider(String),
+    /// The callback's `state` does not match the pending flow
+    CsrfMismatch {
+        /// Provider of the pending flow, if known
+        provider: Option<String>,
+    },
+    /// The provider's token endpoint rejected the code exchange
+    TokenExchange {
+        /// Provider of the flow
+        provider: String,
+        /// Why the exchange failed
+        reason: String,
+        /// Error response of the token endpoint, if it sent one
+        error: Option<TokenExchangeError>,
+    },
+    /// The provider's user info request failed
+    UserInfo(Failure),
+    /// The provider did not answer the token or user info request in time
+    ProviderTimeout {
+        /// Provider of the flow
+        provider: String,
+        /// The request that timed out
+        reason: String,
+    },
+    /// Any other rejected request
+    Rejected {
+        /// Provider of the flow, if known
+        provider: Option<String>,
+        /// Error sent back
+        error: ApiError,
+    },
+    /// Any other unexpected failure
+    Unexpected {
+        /// Stable error code
+        code: &'static str,
+        /// The sanitized failure
+        failure: Failure,
+    },
+}
+
+impl AppError {
+    /// Creates a session failure
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - What failed
+    /// * `error` - The error that caused the failure
+    /// * `provider` - Provider of the flow, if known
+    /// * `secrets` - Secret values known to the caller, redacted from the error
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `AppError::Session`
+    pub fn session(
+        message: &str,
+        error: &(dyn std::error::Error + 'static),
+        provider: Option<&str>,
+        secrets: &[&str],
+    ) -> Self {
+        Self::Session(Failure::new(message, error, provider, secrets))
+    }
+
+    /// Creates a user info failure
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - Provider of the flow
+    /// * `error` - The error that caused the failure
+    /// * `secrets` - Secret values known to the caller, redacted from the error
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `AppError::UserInfo`
+    pub fn user_info(
+        provider: &str,
+        error: &(dyn std::error::Error + 'static),
+        secrets: &[&str],
+    ) -> Self {
+        Self::UserInfo(Failure::new(
+            "Failed to get user info",
+            error,
+            Some(provider),
+            secrets,
+        ))
+    }
+
+    /// Creates an unexpected failure
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The stable error code
+    /// * `message` - What failed
+    /// * `error` - The error that caused the failure
+    /// * `provider` - Provider of the flow, if known
+    /// * `secrets` - Secret values known to the caller, redacted from the error
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `AppError::Unexpected`
+    pub fn unexpected(
+        code: &'static str,
+        message: &str,
+        error: &(dyn std::error::Error + 'static),
+        provider: Option<&str>,
+        secrets: &[&str],
+    ) -> Self {
+        Self::Unexpected {
+            code,
+            failure: Failure::new(message, error, provider, secrets),
+        }
+    }
+
+    /// Creates a rejected request
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - Provider of the flow, if known
+    /// * `status` - HTTP status of the response
+    /// * `code` - The stable error code
+    /// * `message` - Human-readable description of the error
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `AppError::Rejected`
+    pub fn rejected(
+        provider: Option<&str>,
+        status: StatusCode,
+        code: &'static str,
+        message: &str,
+    ) -> Self {
+        Self::Rejected {
+            provider: provider.map(str::to_string),
+            error: ApiError::new(status, code, message),
+        }
+    }
+}
+
+impl AppError {
+    /// Returns the provider of the flow, if known
+    ///
+    /// # Returns
+    ///
+    /// Returns the provider name, `None` when the error occurred before the
+    /// provider was known
+    pub fn provider(&self) -> Option<&str> {
+        match self {
+            AppError::Session(failure)
+            | AppError::UserInfo(failure)
+            | AppError::Unexpected { failure, .. } => failure.provider.as_deref(),
+            AppError::UnknownProvider(_) => None,
+            AppError::CsrfMismatch { provider } | AppError::Rejected { provider, .. } => {
+                provider.as_deref()
+            }
+            AppError::TokenExchange { provider, .. }
+            | AppError::ProviderTimeout { provider, .. } => Some(provider),
+        }
+    }
+
+    /// Returns the stable error code sent back
+    ///
+    /// # Returns
+    ///
+    /// Returns the `code` of the `ApiError` body
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Session(_) => "session_error",
+            AppError::UnknownProvider(_) => "invalid_provider",
+            AppError::CsrfMismatch { .. } => "csrf_mismatch",
+            AppError::TokenExchange { .. } => "token_exchange_failed",
+            AppError::UserInfo(_) => "user_info_failed",
+            AppError::ProviderTimeout { .. } => "provider_timeout",
+            AppError::Rejected { error, .. } => error.code,
+            AppError::Unexpected { code, .. } => code,
+        }
+    }
+
+    /// Logs the error and splits it into its response body and failure
+    ///
+    /// # Returns
+    ///
+    /// Returns the `ApiError` sent back and the `Failure` of unexpected errors
+    fn report(self) -> (ApiError, Option<Failure>) {
+        let (provider, reason, error, failure) = match self {
+            AppError::Session(failure) => (
+                failure.provider.clone(),
+                Some(failure.chain.join(": ")),
+                ApiError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "session_error",
+                    &failure.message,
+                ),
+                Some(failure),
+            ),
+            AppError::UnknownProvider(provider) => (
+                Some(provider),
+                None,
+                ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    "invalid_provider",
+                    "Invalid provider",
+                ),
+                None,
+            ),
+            AppError::CsrfMismatch { provider } => (
+                provider,
+                None,
+                ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    "csrf_mismatch",
+                    "CSRF token mismatch",
+                ),
+                None,
+            ),
+            AppError::TokenExchange {
+                provider,
+                reason,
+                error,
+            } => {
+                let mut api_error = ApiError::new(
+                    StatusCode::BAD_REQUEST,
+                    "token_exchange_failed",
+                    "OAuth token exchange failed",
+                );
+                if let Some(error) = error {
+                    api_error = api_error.with_details(serde_json::json!({
+                        "error": error.error,
+                        "error_description": error.error_description,
+                    }));
+                }
+                (Some(provider), Some(reason), api_error, None)
+            }
+            AppError::UserInfo(failure) => (
+                failure.provider.clone(),
+                Some(failure.chain.join(": ")),
+                ApiError::new(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "user_info_failed",
+                    &failure.message,
+                ),
+                Some(failure),
+            ),
+            AppError::ProviderTimeout { provider, reason } => (
+                Some(provider),
+                Some(reason),
+                ApiError::new(
+                    StatusCode::GATEWAY_TIMEOUT,
+                    "provider_timeout",
+                    "The provider did not respond in time, please try again",
+                ),
+                None,
+            ),
+            AppError::Rejected { provider, error } => (provider, None, error, None),
+            AppError::Unexpected { code, failure } => (
+                failure.provider.clone(),
+                Some(failure.chain.join(": ")),
+                ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, code, &failure.message),
+                Some(failure),
+            ),
+        };
+
+        let provider = provider.as_deref().unwrap_or("unknown");
+        let summary = match reason {
+            Some(reason) => format!("{}: {}", error.message, reason),
+            None => error.message.clone(),
+        };
+        if error.status.is_server_error() {
+            tracing::error!(provider, code = error.code, "{}", summary);
+        } else {
+            tracing::warn!(provider, code = error.code, "{}", summary);
+        }
+
+        (error, failure)
+    }
+
+    /// Logs the error and redirects to the frontend's error page
+    ///
+    /// # Arguments
+    ///
+    /// * `redirects` - The configured frontend redirects
+    ///
+    /// # Returns
+    ///
+    /// Returns a redirect carrying the error code and message, or the JSON
+    /// error response when no error URL is configured
+    pub fn into_redirect(self, redirects: &Redirects) -> axum::response::Response {
+        let (error, failure) = self.report();
+        let mut response = match redirects.error(error.code, &error.message) {
+            Some(url) => Redirect::to(url.as_str()).into_response(),
+            None => error.into_response(),
+        };
+        if let Some(failure) = failure {
+            response.extensions_mut().insert(failure);
+        }
+        response
+    }
+}
+
+impl IntoResponse for AppError {
+    /// Logs the error and converts it to an HTTP response
+    ///
+    /// # Returns
+    ///
+    /// Returns a response with the error's status and `ApiError` body,
+    /// carrying the `Failure` of unexpected errors
+    fn into_response(self) -> axum::response::Response {
+        let (error, failure) = self.report();
+        let mut response = error.into_response();
+        if let Some(failure) = failure {
+            response.extensions_mut().insert(failure);
+        }
+        response
+    }
+}
 
 /// Creates an HTTP 500 Internal Server Error response with a custom error message
 ///
@@ -8,13 +426,14 @@ use reqwest::StatusCode;
 ///
 /// # Arguments
 ///
+/// * `code` - The stable error code
 /// * `message` - The error message to include in the response body
 ///
 /// Returns an HTTP response with:
 /// - Status code: 500 Internal Server Error
-/// - Body: The provided error message as a string
-pub fn internal_error(message: &str) -> Response<Body> {
-    (StatusCode::INTERNAL_SERVER_ERROR, message.to_string()).into_response()
+/// - Body: `{"code": code, "message": message}`
+pub fn internal_error(code: &'static str, message: &str) -> Response<Body> {
+    ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, code, message).into_response()
 }
 
 /// Creates an HTTP 400 Bad Request response with a custom error message
@@ -25,11 +444,49 @@ pub fn internal_error(message: &str) -> Response<Body> {
 ///
 /// # Arguments
 ///
+/// * `code` - The stable error code
 /// * `message` - The error message to include in the response body
 ///
 /// Returns an HTTP response with:
 /// - Status code: 400 Bad Request
-/// - Body: The provided error message as a string
-pub fn bad_request(message: &str) -> Response<Body> {
-    (StatusCode::BAD_REQUEST, message.to_string()).into_response()
+/// - Body: `{"code": code, "message": message}`
+pub fn bad_request(code: &'static str, message: &str) -> Response<Body> {
+    ApiError::new(StatusCode::BAD_REQUEST, code, message).into_response()
+}
+
+/// Creates an HTTP 401 Unauthorized response with a custom error message
+///
+/// This function creates a standardized unauthorized response for requests
+/// that lack valid credentials.
+///
+/// # Arguments
+///
+/// * `code` - The stable error code
+/// * `message` - The error message to include in the response body
+///
+/// Returns an HTTP response with:
+/// - Status code: 401 Unauthorized
+/// - Body: `{"code": code, "message": message}`
+pub fn unauthorized(code: &'static str, message: &str) -> Response<Body> {
+    ApiError::new(StatusCode::UNAUTHORIZED, code, message).into_response()
+}
+
+/// Creates an HTTP 401 Unauthorized response with a bearer challenge
+///
+/// Used by token-authenticated routes. The response carries a
+/// `WWW-Authenticate: Bearer` challenge.
+///
+/// # Arguments
+///
+/// * `message` - The error message to include in the response body
+///
+/// Returns an HTTP response with:
+/// - Status code: 401 Unauthorized
+/// - Body: `{"code": "invalid_token", "message": message}`
+pub fn unauthorized_bearer(message: &str) -> Response<Body> {
+    (
+        [(WWW_AUTHENTICATE, "Bearer")],
+        ApiError::new(StatusCode::UNAUTHORIZED, "invalid_token", message),
+    )
+        .into_response()
 }
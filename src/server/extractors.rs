@@ -0,0 +1,144 @@
+use crate::{
+    primitives::{CurrentUser, UserInfo},
+    server::{
+        errors::{internal_error, unauthorized, unauthorized_bearer},
+        server::AppState,
+    },
+};
+use axum::{
+    async_trait,
+    body::Body,
+    extract::{FromRequest, FromRequestParts, Query, Request},
+    http::{
+        header::{AUTHORIZATION, CONTENT_TYPE},
+        request::Parts,
+        Method, Response,
+    },
+    response::IntoResponse,
+    Form,
+};
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
+use tower_sessions::Session;
+
+/// Session key storing the signed-in user
+pub const CURRENT_USER_KEY: &str = "current_user";
+
+/// User authenticated by the JWT issued after login
+///
+/// Extracting it requires an `Authorization: Bearer <jwt>` header carrying
+/// a token minted by the configured issuer. Missing, malformed and expired
+/// tokens are rejected with 401 and an `invalid_token` JSON error, so handlers taking
+/// this extractor only run for authenticated users.
+#[derive(Debug)]
+pub struct AuthenticatedUser(pub UserInfo);
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for AuthenticatedUser {
+    type Rejection = Response<Body>;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(token_issuer) = &state.token_issuer else {
+            return Err(unauthorized_bearer("Token authentication is not enabled"));
+        };
+
+        let Some(token) = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+        else {
+            return Err(unauthorized_bearer("Missing bearer token"));
+        };
+
+        match token_issuer.verify_token(token, &state.clock) {
+            Ok(claims) => Ok(Self(UserInfo {
+                id: claims.sub,
+                provider: claims.provider,
+                ..UserInfo::default()
+            })),
+            Err(e) => {
+                tracing::debug!("Rejected bearer token: {}", e);
+                Err(unauthorized_bearer("Invalid or expired token"))
+            }
+        }
+    }
+}
+
+/// User signed in through the session cookie
+///
+/// The callback stores the user in the session once the sign-in completes.
+/// Requests whose session holds no user are rejected with 401 and an
+/// `unauthenticated` JSON error.
+#[derive(Debug)]
+pub struct SessionUser(pub CurrentUser);
+
+#[async_trait]
+impl FromRequestParts<Arc<AppState>> for SessionUser {
+    type Rejection = Response<Body>;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let session = Session::from_request_parts(parts, state)
+            .await
+            .map_err(|_| internal_error("session_error", "Failed to load the session"))?;
+
+        match session.get::<CurrentUser>(CURRENT_USER_KEY).await {
+            Ok(Some(current_user)) => Ok(Self(current_user)),
+            Ok(None) => Err(unauthorized("unauthenticated", "Not signed in")),
+            Err(e) => {
+                tracing::warn!("Failed to load the signed-in user: {}", e);
+                Err(internal_error(
+                    "session_error",
+                    "Failed to load the session",
+                ))
+            }
+        }
+    }
+}
+
+/// Parameters sent in the query string or in a posted form
+///
+/// Providers using `response_mode=form_post` post the callback parameters
+/// as an `application/x-www-form-urlencoded` body instead of redirecting
+/// with a query string. POST requests with a form body are read from the
+/// body, every other request from the query string. Invalid parameters are
+/// rejected with 400 like `Query` and `Form` do.
+#[derive(Debug)]
+pub struct QueryOrForm<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for QueryOrForm<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = Response<Body>;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let is_form = request.method() == Method::POST
+            && request
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|value| value.starts_with("application/x-www-form-urlencoded"));
+
+        if is_form {
+            let Form(params) = Form::<T>::from_request(request, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            Ok(Self(params))
+        } else {
+            let (mut parts, _) = request.into_parts();
+            let Query(params) = Query::<T>::from_request_parts(&mut parts, state)
+                .await
+                .map_err(IntoResponse::into_response)?;
+            Ok(Self(params))
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Home page listing a sign-in button per configured provider
+//!
+//! The page is rendered from `home.html` at request time, with the same
+//! providers as `/providers`. Providers with branding get their label, color
+//! and logo from [`display_metadata`](crate::traits::OAuthProvider::display_metadata), others a neutral button
+//! labeled with the name of their section.
+
+use crate::{
+    primitives::ProviderDisplay,
+    server::{
+        handlers::{provider_summaries, ProviderSummary},
+        server::AppState,
+    },
+};
+use axum::{extract::State, response::Html};
+use std::sync::Arc;
+
+/// Home page markup, with a `{{buttons}}` placeholder for the provider buttons
+const HOME_PAGE: &str = include_str!("home.html");
+
+/// Escapes text for use in HTML content and attribute values
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Renders the home page button of a provider
+///
+/// Branded buttons show the provider's label, followed by the section name
+/// when it differs, so several sections sharing a provider type can be told
+/// apart.
+///
+/// # Arguments
+///
+/// * `summary` - The provider to render
+/// * `display` - Branding of the provider, if any
+///
+/// # Returns
+///
+/// Returns the button markup
+fn provider_button(summary: &ProviderSummary, display: Option<ProviderDisplay>) -> String {
+    let (style, icon, label) = match display {
+        Some(display) => {
+            let label = if display.label.eq_ignore_ascii_case(&summary.name) {
+                escape_html(display.label)
+            } else {
+                format!(
+                    "{} ({})",
+                    escape_html(display.label),
+                    escape_html(&summary.name)
+                )
+            };
+            (
+                format!(" style=\"--brand-color: {}\"", escape_html(display.color)),
+                format!(
+                    "<svg width=\"18\" height=\"18\" viewBox=\"0 0 24 24\">{}</svg>",
+                    display.icon
+                ),
+                label,
+            )
+        }
+        None => (String::new(), String::new(), escape_html(&summary.name)),
+    };
+
+    format!(
+        "            <a href=\"{}\" class=\"oauth-button\"{}>\n                {}\n                Sign in with {}\n            </a>\n",
+        escape_html(&summary.authorize_url),
+        style,
+        icon,
+        label
+    )
+}
+
+/// Renders the home page
+///
+/// # Arguments
+///
+/// * `state` - Shared application state holding the providers
+///
+/// # Returns
+///
+/// Returns the page markup with a button per configured provider, sorted
+/// by name
+pub(crate) fn render_home_page(state: &AppState) -> String {
+    let buttons: String = provider_summaries(state)
+        .iter()
+        .map(|summary| {
+            let display = state
+                .oauth_providers
+                .get(&summary.name)
+                .and_then(|provider| provider.display_metadata());
+            provider_button(summary, display)
+        })
+        .collect();
+
+    HOME_PAGE.replace("{{buttons}}", &buttons)
+}
+
+/// Home page handler for OAuth testing
+///
+/// Serves a simple HTML page with a sign-in button for each configured
+/// provider. The route is disabled with `serve_home_page = false`.
+///
+/// # Arguments
+///
+/// * `state` - Shared application state holding the providers
+///
+/// # Returns
+///
+/// Returns an HTML page with OAuth provider buttons for testing
+/// the OAuth flow with different providers
+pub async fn home_page(State(state): State<Arc<AppState>>) -> Html<String> {
+    Html(render_home_page(&state))
+}
@@ -0,0 +1,127 @@
+use crate::{
+    server::{
+        errors::AppError, extractors::SessionUser, handlers::signed_in_user_id, server::AppState,
+    },
+    users::{Identity, IdentityError},
+};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Provider accounts linked to the signed-in user
+///
+/// # Fields
+///
+/// * `user_id` - Internal id of the user
+/// * `identities` - The linked accounts, oldest first
+#[derive(Debug, Serialize, Deserialize)]
+pub struct IdentitiesResponse {
+    /// Internal id of the user
+    pub user_id: Uuid,
+    /// The linked accounts, oldest first
+    pub identities: Vec<Identity>,
+}
+
+/// Loads the provider accounts linked to a user
+///
+/// # Arguments
+///
+/// * `state` - Shared application state containing the user store
+/// * `user_id` - Internal id of the user
+/// * `provider` - Provider of the flow the accounts are loaded for, if any
+///
+/// # Returns
+///
+/// Returns the linked accounts, or an error if the user store failed
+pub(crate) async fn linked_identities(
+    state: &AppState,
+    user_id: Uuid,
+    provider: Option<&str>,
+) -> Result<IdentitiesResponse, AppError> {
+    let identities = state.users.identities(user_id).await.map_err(|e| {
+        AppError::unexpected(
+            "user_store_failed",
+            "Failed to load the linked accounts",
+            e.as_ref(),
+            provider,
+            &[],
+        )
+    })?;
+    Ok(IdentitiesResponse {
+        user_id,
+        identities,
+    })
+}
+
+/// Linked accounts handler
+///
+/// # Arguments
+///
+/// * `state` - Shared application state containing the user store
+/// * `session_user` - The user signed in through the session
+///
+/// # Returns
+///
+/// Returns the accounts linked to the signed-in user, or a 401 error when
+/// no user is signed in
+pub async fn list_identities(
+    State(state): State<Arc<AppState>>,
+    SessionUser(current_user): SessionUser,
+) -> Result<Json<IdentitiesResponse>, AppError> {
+    let user_id = signed_in_user_id(&current_user)?;
+    Ok(Json(linked_identities(&state, user_id, None).await?))
+}
+
+/// Account unlinking handler
+///
+/// Unlinks every account of the provider from the signed-in user. The
+/// session stays signed in, even when it was signed in through the
+/// unlinked provider.
+///
+/// # Arguments
+///
+/// * `state` - Shared application state containing the user store
+/// * `session_user` - The user signed in through the session
+/// * `provider` - The provider to unlink
+///
+/// # Returns
+///
+/// Returns 204 No Content, a 401 error when no user is signed in, a 404
+/// error when no account of the provider is linked, or a 409 error when it
+/// is the user's last linked provider
+pub async fn unlink_identity(
+    State(state): State<Arc<AppState>>,
+    SessionUser(current_user): SessionUser,
+    Path(provider): Path<String>,
+) -> Result<StatusCode, AppError> {
+    let user_id = signed_in_user_id(&current_user)?;
+    match state.users.unlink(user_id, &provider).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(AppError::rejected(
+            Some(&provider),
+            StatusCode::NOT_FOUND,
+            "identity_not_found",
+            "No account of the provider is linked",
+        )),
+        Err(e) if e.downcast_ref::<IdentityError>() == Some(&IdentityError::LastIdentity) => {
+            Err(AppError::rejected(
+                Some(&provider),
+                StatusCode::CONFLICT,
+                "last_identity",
+                "The last linked account cannot be unlinked",
+            ))
+        }
+        Err(e) => Err(AppError::unexpected(
+            "user_store_failed",
+            "Failed to unlink the account",
+            e.as_ref(),
+            Some(&provider),
+            &[],
+        )),
+    }
+}
@@ -0,0 +1,56 @@
+use crate::server::{errors::ApiError, server::AppState};
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header::CACHE_CONTROL, Response, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use std::sync::Arc;
+
+/// How long clients may cache the key set, in seconds
+///
+/// A new signing key is published as a previous key stays in the set, so
+/// clients refetching within this delay verify every token issued.
+const JWKS_MAX_AGE_SECONDS: u64 = 300;
+
+/// JSON Web Key Set endpoint handler
+///
+/// Publishes the public keys verifying the JWTs issued after login, the
+/// current signing key first. Each key carries its `kid`, `alg` and `use`.
+///
+/// # Arguments
+///
+/// * `state` - Shared application state holding the token issuer
+///
+/// # Returns
+///
+/// Returns the key set with cache headers, or a 404 error when no JWTs are
+/// issued or they are signed with a shared `HS256` secret
+pub async fn jwks(State(state): State<Arc<AppState>>) -> Response<Body> {
+    let Some(token_issuer) = &state.token_issuer else {
+        return ApiError::new(
+            StatusCode::NOT_FOUND,
+            "jwks_unavailable",
+            "No JWTs are issued, configure a [jwt] section with RS256 or ES256",
+        )
+        .into_response();
+    };
+    let Some(jwks) = token_issuer.jwks() else {
+        return ApiError::new(
+            StatusCode::NOT_FOUND,
+            "jwks_unavailable",
+            "JWTs are signed with HS256, whose shared secret is not published; use RS256 or ES256 to verify them with a public key",
+        )
+        .into_response();
+    };
+
+    (
+        [(
+            CACHE_CONTROL,
+            format!("public, max-age={}", JWKS_MAX_AGE_SECONDS),
+        )],
+        Json(jwks),
+    )
+        .into_response()
+}
@@ -0,0 +1,537 @@
+use crate::{
+    server::audit::hex,
+    settings::{AuditSettings, AuditSinkKind},
+};
+use eyre::{bail, eyre, Result};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{ErrorKind, Write},
+    path::PathBuf,
+    sync::{mpsc, Mutex},
+    thread,
+};
+
+/// Hash the first record of a chain refers to
+const CHAIN_START: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Action recorded in the login audit log
+///
+/// * `Login` - An OAuth callback, whether it signed the user in or not
+/// * `Logout` - A sign-out
+/// * `Link` - A provider account linked to the signed-in user
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginAction {
+    /// An OAuth callback
+    Login,
+    /// A sign-out
+    Logout,
+    /// A linked provider account
+    Link,
+}
+
+/// Outcome of an audited action
+///
+/// * `Success` - The action completed
+/// * `Failure` - The action was rejected or failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LoginOutcome {
+    /// The action completed
+    Success,
+    /// The action was rejected or failed
+    Failure,
+}
+
+/// Record of a sign-in or sign-out
+///
+/// # Fields
+///
+/// * `timestamp` - Unix timestamp of the action
+/// * `action` - Sign-in or sign-out
+/// * `provider` - The OAuth provider involved, if known
+/// * `user_id_hash` - Salted hash of the user id, if known
+/// * `client_ip` - IP address of the client
+/// * `user_agent` - User-Agent of the client, if sent
+/// * `outcome` - Whether the action completed
+/// * `failure_reason` - Error code of a failed action
+/// * `prev_hash` - SHA-256 of the previous record, chaining the records
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoginEvent {
+    /// Unix timestamp of the action
+    pub timestamp: u64,
+    /// Sign-in or sign-out
+    pub action: LoginAction,
+    /// The OAuth provider involved, if known
+    pub provider: Option<String>,
+    /// Salted hash of the user id, if known
+    pub user_id_hash: Option<String>,
+    /// IP address of the client
+    pub client_ip: Option<String>,
+    /// User-Agent of the client, if sent
+    pub user_agent: Option<String>,
+    /// Whether the action completed
+    pub outcome: LoginOutcome,
+    /// Error code of a failed action
+    pub failure_reason: Option<String>,
+    /// SHA-256 of the previous record, set when the event is recorded
+    #[serde(default)]
+    pub prev_hash: String,
+}
+
+/// Destination of the login audit records
+///
+/// Records arrive serialized as single-line JSON, in the order of the chain.
+pub trait LoginAuditSink: Send + Sync {
+    /// Writes one record
+    ///
+    /// # Arguments
+    ///
+    /// * `record` - The record as a line of JSON, without the line break
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` once written or handed to a writer, or an error if
+    /// the record was lost
+    fn write(&self, record: &str) -> Result<()>;
+
+    /// Returns the last record the sink held when it was opened
+    ///
+    /// # Returns
+    ///
+    /// Returns the record the chain continues from, `None` by default to
+    /// start a new chain
+    fn last_record(&self) -> Option<&str> {
+        None
+    }
+}
+
+/// Sink logging the records under the `audit` target
+pub struct LogSink;
+
+impl LoginAuditSink for LogSink {
+    fn write(&self, record: &str) -> Result<()> {
+        tracing::info!(target: "audit", "{}", record);
+        Ok(())
+    }
+}
+
+/// Sink appending the records as JSON lines to a file
+///
+/// Records are written by a dedicated thread, so requests never wait on
+/// the disk. Once a record would grow the file past `max_file_bytes`, the
+/// file is renamed to `<path>.1`, older files shift to `<path>.2` and so
+/// on, and the oldest beyond `max_files` is removed. Dropping the sink
+/// waits for the pending records to be written.
+///
+/// # Fields
+///
+/// * `last_record` - Last record of the files when the sink was opened
+/// * `sender` - Channel to the writer thread
+/// * `writer` - The writer thread
+pub struct FileSink {
+    /// Last record of the files when the sink was opened
+    last_record: Option<String>,
+    /// Channel to the writer thread, `None` once the sink is dropped
+    sender: Option<mpsc::Sender<String>>,
+    /// The writer thread, `None` once the sink is dropped
+    writer: Option<thread::JoinHandle<()>>,
+}
+
+impl FileSink {
+    /// Opens the file, appending to its existing records
+    ///
+    /// The last record is read from the file, or from `<path>.1` when the
+    /// file is empty, so the chain continues across restarts.
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - File the records are appended to
+    /// * `max_file_bytes` - Size past which the file is rotated
+    /// * `max_files` - Rotated files kept next to the current one
+    ///
+    /// # Returns
+    ///
+    /// Returns the sink, or an error if the file cannot be opened or read
+    pub fn open(path: impl Into<PathBuf>, max_file_bytes: u64, max_files: usize) -> Result<Self> {
+        let path = path.into();
+        let mut file = open_append(&path)?;
+        let content = read_records(&path)?;
+        // A record cut short by a crash is closed, so the next one starts
+        // on its own line
+        if !content.is_empty() && !content.ends_with('\n') {
+            file.write_all(b"\n")?;
+        }
+        let size = file.metadata()?.len();
+
+        let mut writer = FileWriter {
+            path,
+            max_file_bytes,
+            max_files,
+            file,
+            size,
+        };
+        let last_record = match last_line(&content) {
+            Some(record) => Some(record.to_string()),
+            None => last_line(&read_records(&writer.rotated_path(1))?).map(str::to_string),
+        };
+
+        let (sender, receiver) = mpsc::channel::<String>();
+        let writer = thread::Builder::new()
+            .name("login-audit".to_string())
+            .spawn(move || {
+                for record in receiver {
+                    if let Err(e) = writer.write(&record) {
+                        tracing::warn!("Failed to write audit record: {}", e);
+                    }
+                }
+            })?;
+
+        Ok(Self {
+            last_record,
+            sender: Some(sender),
+            writer: Some(writer),
+        })
+    }
+}
+
+impl LoginAuditSink for FileSink {
+    fn write(&self, record: &str) -> Result<()> {
+        self.sender
+            .as_ref()
+            .and_then(|sender| sender.send(record.to_string()).ok())
+            .ok_or_else(|| eyre!("the audit log writer has stopped"))
+    }
+
+    fn last_record(&self) -> Option<&str> {
+        self.last_record.as_deref()
+    }
+}
+
+impl Drop for FileSink {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// Writer of the file sink, owned by its thread
+///
+/// # Fields
+///
+/// * `path` - File the records are appended to
+/// * `max_file_bytes` - Size past which the file is rotated
+/// * `max_files` - Rotated files kept next to the current one
+/// * `file` - The open file
+/// * `size` - Size of the open file
+struct FileWriter {
+    /// File the records are appended to
+    path: PathBuf,
+    /// Size past which the file is rotated in bytes
+    max_file_bytes: u64,
+    /// Rotated files kept next to the current one
+    max_files: usize,
+    /// The open file
+    file: File,
+    /// Size of the open file in bytes
+    size: u64,
+}
+
+impl FileWriter {
+    /// Appends a record, rotating the file first if it would grow too large
+    fn write(&mut self, record: &str) -> Result<()> {
+        let line = format!("{}\n", record);
+        let len = line.len() as u64;
+
+        if self.size > 0 && self.size + len > self.max_file_bytes {
+            self.rotate()?;
+            self.file = open_append(&self.path)?;
+            self.size = 0;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.size += len;
+        Ok(())
+    }
+
+    /// Returns the path of a rotated file
+    fn rotated_path(&self, index: usize) -> PathBuf {
+        let mut path = self.path.clone().into_os_string();
+        path.push(format!(".{}", index));
+        path.into()
+    }
+
+    /// Shifts the rotated files and moves the current file to `<path>.1`
+    fn rotate(&self) -> Result<()> {
+        if self.max_files == 0 {
+            fs::remove_file(&self.path)?;
+            return Ok(());
+        }
+        let _ = fs::remove_file(self.rotated_path(self.max_files));
+        for index in (1..self.max_files).rev() {
+            let from = self.rotated_path(index);
+            if from.exists() {
+                fs::rename(from, self.rotated_path(index + 1))?;
+            }
+        }
+        fs::rename(&self.path, self.rotated_path(1))?;
+        Ok(())
+    }
+}
+
+/// Opens a file for appending, creating it if needed
+fn open_append(path: &PathBuf) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| eyre!("Failed to open audit log {}: {}", path.display(), e))
+}
+
+/// Reads the records of an audit file, empty if it does not exist
+fn read_records(path: &PathBuf) -> Result<String> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(content),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(eyre!("Failed to read audit log {}: {}", path.display(), e)),
+    }
+}
+
+/// Returns the last non-empty line of a file's content
+fn last_line(content: &str) -> Option<&str> {
+    content.lines().rev().find(|line| !line.is_empty())
+}
+
+/// Tamper-evident audit log of sign-ins and sign-outs
+///
+/// Each record carries the SHA-256 of the previous one, so removing or
+/// editing a record breaks the chain. The chain continues from the last
+/// record of the sink, so a file keeps a single chain across restarts.
+/// Write failures are logged and never fail the request.
+///
+/// # Fields
+///
+/// * `sink` - Destination of the records, `None` when auditing is disabled
+/// * `salt` - Salt of the user id hashes
+/// * `prev_hash` - Hash of the last record written
+pub struct LoginAudit {
+    /// Destination of the records
+    sink: Option<Box<dyn LoginAuditSink>>,
+    /// Salt of the user id hashes
+    salt: String,
+    /// Hash of the last record written
+    prev_hash: Mutex<String>,
+}
+
+impl LoginAudit {
+    /// Creates an audit log that discards every event
+    pub fn disabled() -> Self {
+        Self {
+            sink: None,
+            salt: String::new(),
+            prev_hash: Mutex::new(CHAIN_START.to_string()),
+        }
+    }
+
+    /// Creates an audit log writing to a sink
+    ///
+    /// The chain continues from the sink's last record, if any.
+    ///
+    /// # Arguments
+    ///
+    /// * `sink` - Destination of the records
+    /// * `salt` - Salt of the user id hashes
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `LoginAudit` instance
+    pub fn new(sink: Box<dyn LoginAuditSink>, salt: &str) -> Self {
+        let prev_hash = match sink.last_record() {
+            Some(record) => hex(&Sha256::digest(record.as_bytes())),
+            None => CHAIN_START.to_string(),
+        };
+        Self {
+            sink: Some(sink),
+            salt: salt.to_string(),
+            prev_hash: Mutex::new(prev_hash),
+        }
+    }
+
+    /// Creates the audit log configured in the `[audit]` section
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The `[audit]` section
+    ///
+    /// # Returns
+    ///
+    /// Returns the audit log, or an error if the salt or the file sink's
+    /// path is missing, or the file cannot be opened
+    pub fn from_settings(settings: &AuditSettings) -> Result<Self> {
+        if settings.user_id_salt.expose().is_empty() {
+            bail!("audit.user_id_salt is required");
+        }
+        let sink: Box<dyn LoginAuditSink> = match settings.sink {
+            AuditSinkKind::Log => Box::new(LogSink),
+            AuditSinkKind::File => {
+                let Some(path) = &settings.path else {
+                    bail!("audit.path is required by the file sink");
+                };
+                Box::new(FileSink::open(
+                    path,
+                    settings.max_file_bytes,
+                    settings.max_files,
+                )?)
+            }
+        };
+        Ok(Self::new(sink, settings.user_id_salt.expose()))
+    }
+
+    /// Returns whether events are recorded
+    pub fn is_enabled(&self) -> bool {
+        self.sink.is_some()
+    }
+
+    /// Hashes a user id with the salt
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - The user id, often an email address
+    ///
+    /// # Returns
+    ///
+    /// Returns the HMAC-SHA256 of the user id in hex
+    pub fn hash_user_id(&self, user_id: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.salt.as_bytes())
+            .expect("HMAC accepts any key length");
+        mac.update(user_id.as_bytes());
+        hex(&mac.finalize().into_bytes())
+    }
+
+    /// Chains and writes an event
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The event, its `prev_hash` is overwritten
+    pub fn record(&self, mut event: LoginEvent) {
+        let Some(sink) = &self.sink else {
+            return;
+        };
+
+        let mut prev_hash = self.prev_hash.lock().unwrap_or_else(|e| e.into_inner());
+        event.prev_hash = prev_hash.clone();
+        let record = match serde_json::to_string(&event) {
+            Ok(record) => record,
+            Err(e) => {
+                tracing::warn!("Failed to serialize audit record: {}", e);
+                return;
+            }
+        };
+        match sink.write(&record) {
+            Ok(()) => *prev_hash = hex(&Sha256::digest(record.as_bytes())),
+            Err(e) => tracing::warn!("Failed to write audit record: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(outcome: LoginOutcome) -> LoginEvent {
+        LoginEvent {
+            timestamp: 1_000,
+            action: LoginAction::Login,
+            provider: Some("google".to_string()),
+            user_id_hash: None,
+            client_ip: Some("10.0.0.1".to_string()),
+            user_agent: None,
+            outcome,
+            failure_reason: None,
+            prev_hash: String::new(),
+        }
+    }
+
+    /// Tests that records are chained and the file is rotated past its size
+    #[test]
+    fn test_file_sink_chains_and_rotates() {
+        let dir = std::env::temp_dir().join(format!("audit-{}", hex(&rand_bytes())));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audit.log");
+
+        let audit = LoginAudit::new(Box::new(FileSink::open(&path, 600, 2).unwrap()), "salt");
+        for _ in 0..7 {
+            audit.record(event(LoginOutcome::Success));
+        }
+        drop(audit);
+
+        // Every file stays within the limit and the oldest beyond two are removed
+        let mut records = Vec::new();
+        for file in [dir.join("audit.log.2"), dir.join("audit.log.1"), path] {
+            let content = fs::read_to_string(file).unwrap();
+            assert!(content.len() <= 600);
+            records.extend(content.lines().map(str::to_string));
+        }
+        assert!(!dir.join("audit.log.3").exists());
+        assert!(records.len() < 7);
+
+        for pair in records.windows(2) {
+            let next: LoginEvent = serde_json::from_str(&pair[1]).unwrap();
+            assert_eq!(next.prev_hash, hex(&Sha256::digest(pair[0].as_bytes())));
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Tests that reopening the file continues its chain instead of starting a new one
+    #[test]
+    fn test_file_sink_continues_chain() {
+        let dir = std::env::temp_dir().join(format!("audit-{}", hex(&rand_bytes())));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("audit.log");
+
+        for records in [2, 3] {
+            let audit =
+                LoginAudit::new(Box::new(FileSink::open(&path, 1 << 20, 2).unwrap()), "salt");
+            for _ in 0..records {
+                audit.record(event(LoginOutcome::Success));
+            }
+        }
+
+        let content = fs::read_to_string(&path).unwrap();
+        let records: Vec<&str> = content.lines().collect();
+        assert_eq!(records.len(), 5);
+        let first: LoginEvent = serde_json::from_str(records[0]).unwrap();
+        assert_eq!(first.prev_hash, CHAIN_START);
+        for pair in records.windows(2) {
+            let next: LoginEvent = serde_json::from_str(pair[1]).unwrap();
+            assert_eq!(next.prev_hash, hex(&Sha256::digest(pair[0].as_bytes())));
+        }
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Tests that user ids are hashed with the salt
+    #[test]
+    fn test_hash_user_id() {
+        let audit = LoginAudit::new(Box::new(LogSink), "salt");
+        let hash = audit.hash_user_id("user@example.com");
+        assert_eq!(hash.len(), 64);
+        assert!(!hash.contains("user"));
+        assert_eq!(hash, audit.hash_user_id("user@example.com"));
+        assert_ne!(
+            hash,
+            LoginAudit::new(Box::new(LogSink), "other").hash_user_id("user@example.com")
+        );
+    }
+
+    /// Returns random bytes naming a temporary directory
+    fn rand_bytes() -> [u8; 8] {
+        use aes_gcm::aead::{rand_core::RngCore, OsRng};
+        let mut bytes = [0u8; 8];
+        OsRng.fill_bytes(&mut bytes);
+        bytes
+    }
+}
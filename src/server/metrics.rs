@@ -0,0 +1,374 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write,
+    sync::Mutex,
+    time::Duration,
+};
+
+/// Label used for flows whose provider is not configured or not known
+const UNKNOWN_PROVIDER: &str = "unknown";
+
+/// Upper bounds of the stage duration histogram buckets in seconds
+const DURATION_BUCKETS: [f64; 8] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Outcome of an OAuth flow step
+///
+/// * `Initiated` - The client was redirected to the provider
+/// * `Completed` - The callback returned the user's identity
+/// * `CsrfMismatch` - The callback failed CSRF/state validation
+/// * `ExchangeFailed` - The provider rejected the token exchange
+/// * `UserInfoFailed` - The user info request failed
+/// * `Denied` - The user or provider denied the authorization
+/// * `UnverifiedEmail` - The user's email is not verified by the provider
+/// * `InvalidIdToken` - The provider's ID token failed verification
+/// * `InsufficientScope` - The provider did not grant every required scope
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FlowOutcome {
+    /// The client was redirected to the provider
+    Initiated,
+    /// The callback returned the user's identity
+    Completed,
+    /// The callback failed CSRF/state validation
+    CsrfMismatch,
+    /// The provider rejected the token exchange
+    ExchangeFailed,
+    /// The user info request failed
+    UserInfoFailed,
+    /// The user or provider denied the authorization
+    Denied,
+    /// The user's email is not verified
+    UnverifiedEmail,
+    /// The provider's ID token failed verification
+    InvalidIdToken,
+    /// The provider did not grant every required scope
+    InsufficientScope,
+}
+
+impl FlowOutcome {
+    /// Returns the label value of the outcome
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FlowOutcome::Initiated => "initiated",
+            FlowOutcome::Completed => "completed",
+            FlowOutcome::CsrfMismatch => "csrf_mismatch",
+            FlowOutcome::ExchangeFailed => "exchange_failed",
+            FlowOutcome::UserInfoFailed => "user_info_failed",
+            FlowOutcome::Denied => "denied",
+            FlowOutcome::UnverifiedEmail => "unverified_email",
+            FlowOutcome::InvalidIdToken => "invalid_id_token",
+            FlowOutcome::InsufficientScope => "insufficient_scope",
+        }
+    }
+}
+
+/// Timed stage of the OAuth callback
+///
+/// * `TokenExchange` - Exchanging the authorization code for a token
+/// * `UserInfo` - Fetching the user's identity from the provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FlowStage {
+    /// Exchanging the authorization code for a token
+    TokenExchange,
+    /// Fetching the user's identity from the provider
+    UserInfo,
+}
+
+impl FlowStage {
+    /// Returns the label value of the stage
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FlowStage::TokenExchange => "token_exchange",
+            FlowStage::UserInfo => "user_info",
+        }
+    }
+}
+
+/// Outcome of a background refresh of stored provider tokens
+///
+/// * `Refreshed` - The provider issued new tokens
+/// * `Failed` - The refresh failed, the user must sign in again
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RefreshOutcome {
+    /// The provider issued new tokens
+    Refreshed,
+    /// The refresh failed
+    Failed,
+}
+
+impl RefreshOutcome {
+    /// Returns the label value of the outcome
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RefreshOutcome::Refreshed => "refreshed",
+            RefreshOutcome::Failed => "failed",
+        }
+    }
+}
+
+/// Histogram of stage durations
+#[derive(Default)]
+struct Histogram {
+    /// Observations per bucket, not cumulative
+    buckets: [u64; DURATION_BUCKETS.len()],
+    /// Number of observations
+    count: u64,
+    /// Sum of all observations in seconds
+    sum: f64,
+}
+
+/// Per-provider OAuth flow metrics
+///
+/// Counts flow outcomes and records stage latencies, exported in the
+/// Prometheus text format. Provider labels are restricted to the configured
+/// providers, anything else is recorded as `unknown`, so user input can
+/// never grow the number of series.
+///
+/// # Fields
+///
+/// * `providers` - Configured provider names allowed as label values
+/// * `outcomes` - Outcome counters keyed by provider and outcome
+/// * `durations` - Stage duration histograms keyed by provider and stage
+/// * `refreshes` - Background token refresh counters keyed by provider and outcome
+pub struct FlowMetrics {
+    /// Configured provider names allowed as label values
+    providers: HashSet<String>,
+    /// Outcome counters keyed by provider and outcome
+    outcomes: Mutex<BTreeMap<(String, FlowOutcome), u64>>,
+    /// Stage duration histograms keyed by provider and stage
+    durations: Mutex<BTreeMap<(String, FlowStage), Histogram>>,
+    /// Background token refresh counters keyed by provider and outcome
+    refreshes: Mutex<BTreeMap<(String, RefreshOutcome), u64>>,
+}
+
+impl FlowMetrics {
+    /// Creates metrics for the configured providers
+    ///
+    /// # Arguments
+    ///
+    /// * `providers` - Names of the configured providers
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `FlowMetrics` instance
+    pub fn new(providers: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            providers: providers.into_iter().collect(),
+            outcomes: Mutex::new(BTreeMap::new()),
+            durations: Mutex::new(BTreeMap::new()),
+            refreshes: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Counts a flow outcome
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - The provider of the flow, if known
+    /// * `outcome` - The outcome to count
+    pub fn record(&self, provider: Option<&str>, outcome: FlowOutcome) {
+        let key = (self.label(provider), outcome);
+        let mut outcomes = self.outcomes.lock().unwrap_or_else(|e| e.into_inner());
+        *outcomes.entry(key).or_default() += 1;
+    }
+
+    /// Records the duration of a callback stage
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - The provider of the flow
+    /// * `stage` - The timed stage
+    /// * `duration` - How long the stage took
+    pub fn observe(&self, provider: &str, stage: FlowStage, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        let key = (self.label(Some(provider)), stage);
+        let mut durations = self.durations.lock().unwrap_or_else(|e| e.into_inner());
+        let histogram = durations.entry(key).or_default();
+
+        if let Some(bucket) = DURATION_BUCKETS.iter().position(|le| seconds <= *le) {
+            histogram.buckets[bucket] += 1;
+        }
+        histogram.count += 1;
+        histogram.sum += seconds;
+    }
+
+    /// Counts a background refresh of stored provider tokens
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - The provider that issued the tokens
+    /// * `outcome` - The outcome to count
+    pub fn record_refresh(&self, provider: &str, outcome: RefreshOutcome) {
+        let key = (self.label(Some(provider)), outcome);
+        let mut refreshes = self.refreshes.lock().unwrap_or_else(|e| e.into_inner());
+        *refreshes.entry(key).or_default() += 1;
+    }
+
+    /// Renders all metrics in the Prometheus text exposition format
+    ///
+    /// Besides the per-outcome `oauth_flow_total`, the outcomes are exported
+    /// as started flows, successful callbacks, and failed callbacks labelled
+    /// with the failure reason.
+    pub fn render(&self) -> String {
+        let mut output = String::new();
+        let outcomes = self.outcomes.lock().unwrap_or_else(|e| e.into_inner());
+        let by_outcome = |wanted: fn(&FlowOutcome) -> bool| {
+            outcomes
+                .iter()
+                .filter(move |((_, outcome), _)| wanted(outcome))
+        };
+
+        output.push_str("# HELP oauth_flow_total OAuth flow steps by provider and outcome\n");
+        output.push_str("# TYPE oauth_flow_total counter\n");
+        for ((provider, outcome), count) in outcomes.iter() {
+            let _ = writeln!(
+                output,
+                "oauth_flow_total{{provider=\"{}\",outcome=\"{}\"}} {}",
+                provider,
+                outcome.as_str(),
+                count
+            );
+        }
+
+        output.push_str("# HELP oauth_authorize_total OAuth flows started by provider\n");
+        output.push_str("# TYPE oauth_authorize_total counter\n");
+        for ((provider, _), count) in by_outcome(|outcome| *outcome == FlowOutcome::Initiated) {
+            let _ = writeln!(
+                output,
+                "oauth_authorize_total{{provider=\"{}\"}} {}",
+                provider, count
+            );
+        }
+
+        output.push_str(
+            "# HELP oauth_callback_success_total OAuth callbacks signing a user in by provider\n",
+        );
+        output.push_str("# TYPE oauth_callback_success_total counter\n");
+        for ((provider, _), count) in by_outcome(|outcome| *outcome == FlowOutcome::Completed) {
+            let _ = writeln!(
+                output,
+                "oauth_callback_success_total{{provider=\"{}\"}} {}",
+                provider, count
+            );
+        }
+
+        output.push_str(
+            "# HELP oauth_callback_failure_total Failed OAuth callbacks by provider and reason\n",
+        );
+        output.push_str("# TYPE oauth_callback_failure_total counter\n");
+        for ((provider, outcome), count) in by_outcome(|outcome| {
+            !matches!(outcome, FlowOutcome::Initiated | FlowOutcome::Completed)
+        }) {
+            let _ = writeln!(
+                output,
+                "oauth_callback_failure_total{{provider=\"{}\",reason=\"{}\"}} {}",
+                provider,
+                outcome.as_str(),
+                count
+            );
+        }
+        drop(outcomes);
+
+        output.push_str(
+            "# HELP oauth_token_refresh_total Background refreshes of stored provider tokens by provider and outcome\n",
+        );
+        output.push_str("# TYPE oauth_token_refresh_total counter\n");
+        for ((provider, outcome), count) in self
+            .refreshes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+        {
+            let _ = writeln!(
+                output,
+                "oauth_token_refresh_total{{provider=\"{}\",outcome=\"{}\"}} {}",
+                provider,
+                outcome.as_str(),
+                count
+            );
+        }
+
+        output.push_str(
+            "# HELP oauth_stage_duration_seconds Duration of OAuth callback stages by provider\n",
+        );
+        output.push_str("# TYPE oauth_stage_duration_seconds histogram\n");
+        for ((provider, stage), histogram) in self
+            .durations
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+        {
+            let labels = format!("provider=\"{}\",stage=\"{}\"", provider, stage.as_str());
+            let mut cumulative = 0;
+            for (le, observations) in DURATION_BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += observations;
+                let _ = writeln!(
+                    output,
+                    "oauth_stage_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, le, cumulative
+                );
+            }
+            let _ = writeln!(
+                output,
+                "oauth_stage_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, histogram.count
+            );
+            let _ = writeln!(
+                output,
+                "oauth_stage_duration_seconds_sum{{{}}} {}",
+                labels, histogram.sum
+            );
+            let _ = writeln!(
+                output,
+                "oauth_stage_duration_seconds_count{{{}}} {}",
+                labels, histogram.count
+            );
+        }
+
+        output
+    }
+
+    /// Returns the label value for a provider, bounded to the configured ones
+    fn label(&self, provider: Option<&str>) -> String {
+        match provider {
+            Some(provider) if self.providers.contains(provider) => provider.to_string(),
+            _ => UNKNOWN_PROVIDER.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that unconfigured providers never become label values
+    #[test]
+    fn test_provider_labels_are_bounded() {
+        let metrics = FlowMetrics::new(["google".to_string()]);
+        metrics.record(Some("google"), FlowOutcome::Initiated);
+        metrics.record(Some("<script>"), FlowOutcome::CsrfMismatch);
+        metrics.record(None, FlowOutcome::CsrfMismatch);
+
+        let output = metrics.render();
+        assert!(output.contains("oauth_flow_total{provider=\"google\",outcome=\"initiated\"} 1"));
+        assert!(
+            output.contains("oauth_flow_total{provider=\"unknown\",outcome=\"csrf_mismatch\"} 2")
+        );
+        assert!(!output.contains("script"));
+    }
+
+    /// Tests that histogram buckets are cumulative
+    #[test]
+    fn test_histogram_buckets() {
+        let metrics = FlowMetrics::new(["google".to_string()]);
+        metrics.observe("google", FlowStage::UserInfo, Duration::from_millis(80));
+        metrics.observe("google", FlowStage::UserInfo, Duration::from_secs(30));
+
+        let output = metrics.render();
+        let labels = "provider=\"google\",stage=\"user_info\"";
+        assert!(output.contains(&format!("_bucket{{{},le=\"0.05\"}} 0", labels)));
+        assert!(output.contains(&format!("_bucket{{{},le=\"0.1\"}} 1", labels)));
+        assert!(output.contains(&format!("_bucket{{{},le=\"10\"}} 1", labels)));
+        assert!(output.contains(&format!("_bucket{{{},le=\"+Inf\"}} 2", labels)));
+        assert!(output.contains(&format!("_count{{{}}} 2", labels)));
+    }
+}
@@ -1,3 +1,28 @@
+pub mod admin;
+pub mod audit;
+pub mod client;
+pub mod cors;
+pub mod device;
 pub mod errors;
+pub mod extractors;
 pub mod handlers;
+pub mod home;
+pub mod identities;
+pub mod jwks;
+pub mod login_audit;
+pub mod metrics;
+pub mod provider_health;
+pub mod rate_limit;
+pub mod redirect;
+#[cfg(feature = "redis")]
+pub mod redis_store;
+pub mod reporting;
+pub mod revocation;
+pub mod security;
+#[allow(clippy::module_inception)]
 pub mod server;
+pub mod session;
+pub mod stateless;
+pub mod stats;
+pub mod throttle;
+pub mod token_refresh;
@@ -0,0 +1,508 @@
+use crate::{
+    server::metrics::FlowStage, settings::ProviderHealthSettings, traits::OAuthProvider,
+    types::HttpClient,
+};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::task::JoinSet;
+
+/// Upper bound on the errors remembered per provider
+const MAX_RECENT_ERRORS: usize = 1_000;
+
+/// Result of a reachability probe of a provider's authorization endpoint
+///
+/// # Fields
+///
+/// * `reachable` - Whether the endpoint answered with a non-5xx status
+/// * `status` - HTTP status of the answer, if any
+/// * `error` - Why the probe failed, if it did
+/// * `checked_at` - Unix timestamp at which the probe was started
+#[derive(Debug, Clone, Serialize)]
+pub struct ProbeResult {
+    /// Whether the endpoint answered with a non-5xx status
+    pub reachable: bool,
+    /// HTTP status of the answer, if any
+    pub status: Option<u16>,
+    /// Why the probe failed, if it did
+    pub error: Option<String>,
+    /// Unix timestamp at which the probe was started
+    pub checked_at: u64,
+}
+
+/// Errors of a provider within the error window, per callback stage
+///
+/// # Fields
+///
+/// * `token_exchange` - Failed token exchanges
+/// * `user_info` - Failed user info requests
+#[derive(Debug, Default, Serialize)]
+pub struct RecentErrors {
+    /// Failed token exchanges
+    pub token_exchange: usize,
+    /// Failed user info requests
+    pub user_info: usize,
+}
+
+/// Health report of a single provider
+///
+/// # Fields
+///
+/// * `last_token_exchange_at` - Unix timestamp of the last successful token exchange
+/// * `last_user_info_at` - Unix timestamp of the last successful user info request
+/// * `recent_errors` - Errors within the error window
+/// * `probe` - Result of the latest reachability probe, if one was run
+#[derive(Debug, Serialize)]
+pub struct ProviderReport {
+    /// Unix timestamp of the last successful token exchange
+    pub last_token_exchange_at: Option<u64>,
+    /// Unix timestamp of the last successful user info request
+    pub last_user_info_at: Option<u64>,
+    /// Errors within the error window
+    pub recent_errors: RecentErrors,
+    /// Result of the latest reachability probe, if one was run
+    pub probe: Option<ProbeResult>,
+}
+
+/// Health report of every configured provider
+///
+/// # Fields
+///
+/// * `error_window_seconds` - Window over which errors are counted
+/// * `providers` - Reports keyed by provider name
+#[derive(Debug, Serialize)]
+pub struct ProviderHealthReport {
+    /// Window over which errors are counted in seconds
+    pub error_window_seconds: u64,
+    /// Reports keyed by provider name
+    pub providers: BTreeMap<String, ProviderReport>,
+}
+
+/// Readiness of a single provider
+///
+/// # Variants
+///
+/// * `Ok` - The latest probe reached the provider
+/// * `Unreachable` - The latest probe failed or the provider answered with a 5xx
+/// * `Unchecked` - The provider has not been probed yet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProviderReadiness {
+    /// The latest probe reached the provider
+    Ok,
+    /// The latest probe failed or the provider answered with a 5xx
+    Unreachable,
+    /// The provider has not been probed yet
+    Unchecked,
+}
+
+/// Overall readiness of the server
+///
+/// # Variants
+///
+/// * `Ok` - Every required provider is usable
+/// * `Unavailable` - A required provider is unreachable
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadinessStatus {
+    /// Every required provider is usable
+    Ok,
+    /// A required provider is unreachable
+    Unavailable,
+}
+
+/// Readiness report of the server
+///
+/// # Fields
+///
+/// * `status` - Overall readiness
+/// * `providers` - Readiness keyed by provider name
+#[derive(Debug, Serialize)]
+pub struct ReadinessReport {
+    /// Overall readiness
+    pub status: ReadinessStatus,
+    /// Readiness keyed by provider name
+    pub providers: BTreeMap<String, ProviderReadiness>,
+}
+
+/// Tracked health of a single provider
+#[derive(Default)]
+struct ProviderRecord {
+    /// Unix timestamp of the last successful token exchange
+    last_token_exchange_at: Option<u64>,
+    /// Unix timestamp of the last successful user info request
+    last_user_info_at: Option<u64>,
+    /// Stage and unix timestamp of recent errors, oldest first
+    errors: VecDeque<(FlowStage, u64)>,
+    /// Unix timestamp at which the last probe was started
+    probe_started_at: Option<u64>,
+    /// Result of the latest completed probe
+    probe: Option<ProbeResult>,
+}
+
+/// Per-provider health tracking
+///
+/// Records the outcome of every token exchange and user info request, and
+/// probes the providers' authorization endpoints on demand. Probes run
+/// concurrently with a per-provider timeout, and each provider is probed
+/// at most once per probe interval, so the admin endpoint cannot be used
+/// to hammer identity providers.
+///
+/// # Fields
+///
+/// * `settings` - Error window and probe limits from the configuration
+/// * `records` - Tracked health keyed by configured provider name
+pub struct ProviderHealth {
+    /// Error window and probe limits from the configuration
+    settings: ProviderHealthSettings,
+    /// Tracked health keyed by configured provider name
+    records: Mutex<HashMap<String, ProviderRecord>>,
+}
+
+impl ProviderHealth {
+    /// Creates health tracking for the configured providers
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Error window and probe limits from the configuration
+    /// * `providers` - Names of the configured providers
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `ProviderHealth` instance
+    pub fn new(
+        settings: ProviderHealthSettings,
+        providers: impl IntoIterator<Item = String>,
+    ) -> Self {
+        Self {
+            settings,
+            records: Mutex::new(
+                providers
+                    .into_iter()
+                    .map(|provider| (provider, ProviderRecord::default()))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Records the outcome of a callback stage
+    ///
+    /// Outcomes for providers that are not configured are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - The provider of the flow
+    /// * `stage` - The callback stage
+    /// * `success` - Whether the stage succeeded
+    /// * `now` - The current unix timestamp
+    pub fn record(&self, provider: &str, stage: FlowStage, success: bool, now: u64) {
+        let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(record) = records.get_mut(provider) else {
+            return;
+        };
+
+        if !success {
+            if record.errors.len() >= MAX_RECENT_ERRORS {
+                record.errors.pop_front();
+            }
+            record.errors.push_back((stage, now));
+            return;
+        }
+
+        match stage {
+            FlowStage::TokenExchange => record.last_token_exchange_at = Some(now),
+            FlowStage::UserInfo => record.last_user_info_at = Some(now),
+        }
+    }
+
+    /// Probes the authorization endpoint of every provider
+    ///
+    /// Providers probed within the last probe interval are skipped and keep
+    /// their previous result.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The shared HTTP client, which does not follow redirects
+    /// * `providers` - The configured providers
+    /// * `now` - The current unix timestamp
+    pub async fn probe(
+        &self,
+        client: &HttpClient,
+        providers: &HashMap<String, Arc<dyn OAuthProvider>>,
+        now: u64,
+    ) {
+        let timeout = Duration::from_millis(self.settings.probe_timeout_ms);
+        let mut probes = JoinSet::new();
+
+        {
+            let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+            for (name, record) in records.iter_mut() {
+                let Some(provider) = providers.get(name) else {
+                    continue;
+                };
+                if record.probe_started_at.is_some_and(|started_at| {
+                    now < started_at.saturating_add(self.settings.probe_interval_seconds)
+                }) {
+                    continue;
+                }
+                record.probe_started_at = Some(now);
+
+                let request = client
+                    .head(provider.get_oauth_client().auth_uri().url().clone())
+                    .timeout(timeout);
+                let name = name.clone();
+                probes.spawn(async move {
+                    let result = match request.send().await {
+                        Ok(response) => ProbeResult {
+                            reachable: !response.status().is_server_error(),
+                            status: Some(response.status().as_u16()),
+                            error: None,
+                            checked_at: now,
+                        },
+                        Err(e) => ProbeResult {
+                            reachable: false,
+                            status: None,
+                            error: Some(if e.is_timeout() {
+                                "timed out".to_string()
+                            } else {
+                                "connection failed".to_string()
+                            }),
+                            checked_at: now,
+                        },
+                    };
+                    (name, result)
+                });
+            }
+        }
+
+        while let Some(probe) = probes.join_next().await {
+            let Ok((name, result)) = probe else {
+                continue;
+            };
+            let mut records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(record) = records.get_mut(&name) {
+                record.probe = Some(result);
+            }
+        }
+    }
+
+    /// Returns whether the readiness check probes the providers
+    ///
+    /// # Returns
+    ///
+    /// Returns the `readiness_probe` setting
+    pub fn readiness_probe(&self) -> bool {
+        self.settings.readiness_probe
+    }
+
+    /// Builds the readiness report from the latest probe results
+    ///
+    /// The server is unavailable once a required provider is unreachable.
+    /// Providers that have not been probed yet do not make it unavailable.
+    ///
+    /// # Returns
+    ///
+    /// Returns the report, every provider `unchecked` when none was probed
+    pub fn readiness(&self) -> ReadinessReport {
+        let records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+        let providers: BTreeMap<String, ProviderReadiness> = records
+            .iter()
+            .map(|(name, record)| {
+                let readiness = match &record.probe {
+                    Some(probe) if probe.reachable => ProviderReadiness::Ok,
+                    Some(_) => ProviderReadiness::Unreachable,
+                    None => ProviderReadiness::Unchecked,
+                };
+                (name.clone(), readiness)
+            })
+            .collect();
+
+        let required = |name: &str| {
+            self.settings
+                .required_providers
+                .as_ref()
+                .is_none_or(|required| required.iter().any(|r| r == name))
+        };
+        let status = if providers
+            .iter()
+            .any(|(name, readiness)| *readiness == ProviderReadiness::Unreachable && required(name))
+        {
+            ReadinessStatus::Unavailable
+        } else {
+            ReadinessStatus::Ok
+        };
+
+        ReadinessReport { status, providers }
+    }
+
+    /// Builds the health report of every provider
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current unix timestamp
+    ///
+    /// # Returns
+    ///
+    /// Returns the report, with errors counted over the error window
+    pub fn report(&self, now: u64) -> ProviderHealthReport {
+        let window_start = now.saturating_sub(self.settings.error_window_seconds);
+        let records = self.records.lock().unwrap_or_else(|e| e.into_inner());
+
+        ProviderHealthReport {
+            error_window_seconds: self.settings.error_window_seconds,
+            providers: records
+                .iter()
+                .map(|(name, record)| {
+                    let mut recent_errors = RecentErrors::default();
+                    for (stage, _) in record.errors.iter().filter(|(_, at)| *at >= window_start) {
+                        match stage {
+                            FlowStage::TokenExchange => recent_errors.token_exchange += 1,
+                            FlowStage::UserInfo => recent_errors.user_info += 1,
+                        }
+                    }
+
+                    let report = ProviderReport {
+                        last_token_exchange_at: record.last_token_exchange_at,
+                        last_user_info_at: record.last_user_info_at,
+                        recent_errors,
+                        probe: record.probe.clone(),
+                    };
+                    (name.clone(), report)
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::BareOAuthClient;
+    use crate::{
+        primitives::ProviderContext, providers::ProviderRegistry, settings::OAuthSettings,
+    };
+    use axum::{routing::get, Router};
+    use oauth2::{AuthUrl, ClientId, RedirectUrl, TokenUrl};
+    use reqwest::Url;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Tests that repeated probes within the interval do not reach the provider
+    #[tokio::test]
+    async fn test_probes_are_rate_limited() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&hits);
+        let router = Router::new().route(
+            "/authorize",
+            get(move || async move {
+                counter.fetch_add(1, Ordering::SeqCst);
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let idp = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let http_client = HttpClient::default();
+        let client = BareOAuthClient::new(ClientId::new("client".to_string()))
+            .set_auth_uri(AuthUrl::new(format!("{}/authorize", idp)).unwrap())
+            .set_token_uri(TokenUrl::new(format!("{}/token", idp)).unwrap())
+            .set_redirect_uri(RedirectUrl::new(format!("{}/callback", idp)).unwrap())
+            .set_revocation_url_option(None)
+            .set_device_authorization_url_option(None);
+        let provider = ProviderRegistry::with_builtins()
+            .get("google")
+            .unwrap()
+            .create(ProviderContext {
+                name: "google",
+                http_client: &http_client,
+                oauth_client: client.into(),
+                user_info_url: Url::parse(&format!("{}/userinfo", idp)).unwrap(),
+                settings: &OAuthSettings::default(),
+            })
+            .unwrap();
+        let providers = HashMap::from([("google".to_string(), provider)]);
+
+        let health = ProviderHealth::new(
+            ProviderHealthSettings {
+                probe_interval_seconds: 30,
+                ..ProviderHealthSettings::default()
+            },
+            ["google".to_string()],
+        );
+
+        health.probe(&http_client, &providers, 1_000).await;
+        health.probe(&http_client, &providers, 1_029).await;
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+        let probe = health.report(1_029).providers["google"]
+            .probe
+            .clone()
+            .unwrap();
+        assert!(probe.reachable);
+        assert_eq!(probe.checked_at, 1_000);
+
+        health.probe(&http_client, &providers, 1_030).await;
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    /// Tests that only unreachable required providers make the server unavailable
+    #[tokio::test]
+    async fn test_readiness_of_required_providers() {
+        let router = Router::new().route("/authorize", get(|| async {}));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let idp = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        let http_client = HttpClient::default();
+        let provider = |idp: &str| {
+            let client = BareOAuthClient::new(ClientId::new("client".to_string()))
+                .set_auth_uri(AuthUrl::new(format!("{}/authorize", idp)).unwrap())
+                .set_token_uri(TokenUrl::new(format!("{}/token", idp)).unwrap())
+                .set_redirect_uri(RedirectUrl::new(format!("{}/callback", idp)).unwrap())
+                .set_revocation_url_option(None)
+                .set_device_authorization_url_option(None);
+            ProviderRegistry::with_builtins()
+                .get("google")
+                .unwrap()
+                .create(ProviderContext {
+                    name: "google",
+                    http_client: &http_client,
+                    oauth_client: client.into(),
+                    user_info_url: Url::parse(&format!("{}/userinfo", idp)).unwrap(),
+                    settings: &OAuthSettings::default(),
+                })
+                .unwrap()
+        };
+        let providers = HashMap::from([
+            ("google".to_string(), provider(&idp)),
+            ("github".to_string(), provider("http://127.0.0.1:9")),
+        ]);
+
+        for (required_providers, status) in [
+            (None, ReadinessStatus::Unavailable),
+            (Some(vec!["google".to_string()]), ReadinessStatus::Ok),
+        ] {
+            let health = ProviderHealth::new(
+                ProviderHealthSettings {
+                    readiness_probe: true,
+                    required_providers,
+                    ..ProviderHealthSettings::default()
+                },
+                ["google".to_string(), "github".to_string()],
+            );
+            let readiness = health.readiness();
+            assert_eq!(readiness.status, ReadinessStatus::Ok);
+            assert_eq!(readiness.providers["google"], ProviderReadiness::Unchecked);
+
+            health.probe(&http_client, &providers, 1_000).await;
+            let readiness = health.readiness();
+            assert_eq!(readiness.status, status);
+            assert_eq!(readiness.providers["google"], ProviderReadiness::Ok);
+            assert_eq!(
+                readiness.providers["github"],
+                ProviderReadiness::Unreachable
+            );
+        }
+    }
+}
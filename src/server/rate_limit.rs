@@ -0,0 +1,255 @@
+use crate::{
+    server::{client::client_ip, errors::ApiError, server::AppState},
+    settings::RateLimitSettings,
+};
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request, State},
+    http::{header::RETRY_AFTER, Response, StatusCode},
+    middleware::Next,
+    response::IntoResponse,
+};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// Token bucket of a single client
+///
+/// # Fields
+///
+/// * `tokens` - Requests the client may still send right away
+/// * `updated_at` - Unix timestamp the bucket was last refilled at
+struct Bucket {
+    /// Requests the client may still send right away
+    tokens: f64,
+    /// Unix timestamp the bucket was last refilled at
+    updated_at: u64,
+}
+
+/// Per-IP rate limiter of the OAuth flow routes
+///
+/// Each client IP has a token bucket holding up to `burst` requests,
+/// refilled at `requests_per_minute`. Requests finding the bucket empty
+/// are refused. Requests whose client IP is unknown share a single bucket,
+/// so they are limited like one client. The number of tracked clients is
+/// bounded; when the table is full, refilled buckets are dropped first and
+/// then the oldest.
+///
+/// # Fields
+///
+/// * `settings` - Limits from the configuration
+/// * `buckets` - Token buckets keyed by client IP, `None` for unknown clients
+/// * `warned_unknown_client` - Whether the missing client IP was reported
+pub struct RateLimiter {
+    /// Limits from the configuration
+    settings: RateLimitSettings,
+    /// Token buckets keyed by client IP, `None` for unknown clients
+    buckets: Mutex<HashMap<Option<IpAddr>, Bucket>>,
+    /// Whether the missing client IP was reported
+    warned_unknown_client: AtomicBool,
+}
+
+impl RateLimiter {
+    /// Creates a new rate limiter
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Limits from the configuration
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `RateLimiter` instance
+    pub fn new(settings: RateLimitSettings) -> Self {
+        Self {
+            settings,
+            buckets: Mutex::new(HashMap::new()),
+            warned_unknown_client: AtomicBool::new(false),
+        }
+    }
+
+    /// Takes a token from the client's bucket
+    ///
+    /// # Arguments
+    ///
+    /// * `ip` - The client IP, `None` when it is unknown
+    /// * `now` - The current unix timestamp
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` if the request is allowed, or the number of seconds
+    /// until the next token is available
+    pub fn check(&self, ip: Option<IpAddr>, now: u64) -> Result<(), u64> {
+        if ip.is_none() && !self.warned_unknown_client.swap(true, Ordering::Relaxed) {
+            tracing::warn!(
+                "The client IP of a rate limited request is unknown, every such request \
+                 shares one bucket; serve the router with \
+                 `into_make_service_with_connect_info::<SocketAddr>()`"
+            );
+        }
+
+        let burst = f64::from(self.settings.burst.max(1));
+        let per_second = f64::from(self.settings.requests_per_minute.max(1)) / 60.0;
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if !buckets.contains_key(&ip) {
+            self.make_room(&mut buckets, now, burst, per_second);
+        }
+        let bucket = buckets.entry(ip).or_insert(Bucket {
+            tokens: burst,
+            updated_at: now,
+        });
+
+        let elapsed = now.saturating_sub(bucket.updated_at) as f64;
+        bucket.tokens = (bucket.tokens + elapsed * per_second).min(burst);
+        bucket.updated_at = now;
+
+        if bucket.tokens < 1.0 {
+            return Err(((1.0 - bucket.tokens) / per_second).ceil().max(1.0) as u64);
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+
+    /// Frees a slot in the table when it is full
+    fn make_room(
+        &self,
+        buckets: &mut HashMap<Option<IpAddr>, Bucket>,
+        now: u64,
+        burst: f64,
+        per_second: f64,
+    ) {
+        if buckets.len() < self.settings.max_tracked_clients {
+            return;
+        }
+
+        // A bucket that has refilled behaves exactly like a new one
+        buckets.retain(|_, bucket| {
+            bucket.tokens + now.saturating_sub(bucket.updated_at) as f64 * per_second < burst
+        });
+
+        if buckets.len() >= self.settings.max_tracked_clients {
+            if let Some(oldest) = buckets
+                .iter()
+                .min_by_key(|(_, bucket)| bucket.updated_at)
+                .map(|(ip, _)| *ip)
+            {
+                buckets.remove(&oldest);
+            }
+        }
+    }
+}
+
+/// Middleware refusing OAuth flow requests from clients over the rate limit
+///
+/// The client is identified by its IP, resolved through the trusted
+/// proxies. The IP is only known when the router is served with
+/// `into_make_service_with_connect_info::<SocketAddr>()`, requests without
+/// one share a single bucket.
+///
+/// # Arguments
+///
+/// * `state` - Shared application state holding the rate limiter
+/// * `request` - The incoming request
+/// * `next` - The rest of the middleware stack
+///
+/// # Returns
+///
+/// Returns the response of the route, or 429 with a `Retry-After` header
+/// when the client is over the limit
+pub async fn rate_limit(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    let ip = client_ip(
+        request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip()),
+        request.headers(),
+        &state.trusted_proxies,
+    );
+
+    if let Err(retry_after) = state.rate_limiter.check(ip, state.clock.now()) {
+        match ip {
+            Some(ip) => tracing::warn!("Rate limited OAuth flow request from {}", ip),
+            None => tracing::warn!("Rate limited OAuth flow request from an unknown client"),
+        }
+        return (
+            [(RETRY_AFTER, retry_after.to_string())],
+            ApiError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limited",
+                "Too many requests, please try again later",
+            ),
+        )
+            .into_response();
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(max_tracked_clients: usize) -> RateLimiter {
+        RateLimiter::new(RateLimitSettings {
+            requests_per_minute: 6,
+            burst: 2,
+            max_tracked_clients,
+        })
+    }
+
+    fn ip(value: &str) -> Option<IpAddr> {
+        Some(value.parse().unwrap())
+    }
+
+    /// Tests that a client may burst, is then refused, and recovers as the bucket refills
+    #[test]
+    fn test_token_bucket() {
+        let limiter = limiter(10);
+
+        assert_eq!(limiter.check(ip("1.1.1.1"), 1_000), Ok(()));
+        assert_eq!(limiter.check(ip("1.1.1.1"), 1_000), Ok(()));
+        assert_eq!(limiter.check(ip("1.1.1.1"), 1_000), Err(10));
+        assert_eq!(limiter.check(ip("2.2.2.2"), 1_000), Ok(()));
+
+        assert_eq!(limiter.check(ip("1.1.1.1"), 1_004), Err(6));
+        assert_eq!(limiter.check(ip("1.1.1.1"), 1_010), Ok(()));
+        assert_eq!(limiter.check(ip("1.1.1.1"), 1_010), Err(10));
+    }
+
+    /// Tests that the table is bounded and evicts the oldest client when full
+    #[test]
+    fn test_tracked_clients_are_bounded() {
+        let limiter = limiter(2);
+
+        limiter.check(ip("1.1.1.1"), 1_000).unwrap();
+        limiter.check(ip("1.1.1.1"), 1_000).unwrap();
+        limiter.check(ip("2.2.2.2"), 1_001).unwrap();
+        limiter.check(ip("3.3.3.3"), 1_002).unwrap();
+
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), 2);
+        assert!(!buckets.contains_key(&ip("1.1.1.1")));
+    }
+
+    /// Tests that requests without a client IP share one bucket
+    #[test]
+    fn test_unknown_clients_share_a_bucket() {
+        let limiter = limiter(10);
+
+        assert_eq!(limiter.check(None, 1_000), Ok(()));
+        assert_eq!(limiter.check(None, 1_000), Ok(()));
+        assert_eq!(limiter.check(None, 1_000), Err(10));
+        assert!(limiter.warned_unknown_client.load(Ordering::Relaxed));
+
+        assert_eq!(limiter.check(ip("1.1.1.1"), 1_000), Ok(()));
+    }
+}
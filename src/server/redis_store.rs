@@ -0,0 +1,209 @@
+use eyre::{bail, eyre, Result};
+use fred::{
+    interfaces::ClientLike,
+    prelude::RedisPool,
+    types::{Builder, ReconnectPolicy, RedisConfig},
+};
+use std::time::Duration;
+use tower_sessions_redis_store::RedisStore;
+use url::Url;
+
+/// Number of connections opened to Redis
+const POOL_SIZE: usize = 8;
+
+/// Maximum time spent connecting to Redis, including the TLS handshake
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum time a session read or write waits for Redis to answer
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Session store keeping sessions in Redis
+///
+/// Sessions expire with their record, so every instance pointed at the same
+/// server shares them.
+pub type RedisSessionStore = RedisStore<RedisPool>;
+
+/// Connects a pool of clients to the Redis server
+///
+/// `rediss://` URLs connect over TLS. Commands time out after
+/// `COMMAND_TIMEOUT`, so a stalled server fails the request instead of
+/// holding it, and dropped connections are re-established in the
+/// background.
+///
+/// # Arguments
+///
+/// * `url` - The `redis://` or `rediss://` URL of the server
+///
+/// # Returns
+///
+/// Returns the store, or an error if the URL is invalid or the server
+/// cannot be reached or rejects the credentials. Errors never contain the
+/// password.
+pub async fn connect(url: &str) -> Result<RedisSessionStore> {
+    let scheme = Url::parse(url)
+        .map_err(|e| eyre!("session.redis_url is invalid: {}", e))?
+        .scheme()
+        .to_string();
+    if scheme != "redis" && scheme != "rediss" {
+        bail!(
+            "session.redis_url must use the redis:// or rediss:// scheme, got {}://",
+            scheme
+        );
+    }
+    let config =
+        RedisConfig::from_url(url).map_err(|e| eyre!("session.redis_url is invalid: {}", e))?;
+    let server = config
+        .server
+        .hosts()
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    // The first connection fails fast, so an unreachable server is
+    // reported at startup, later ones are retried with a backoff
+    let pool = Builder::from_config(config)
+        .with_connection_config(|connection| {
+            connection.connection_timeout = CONNECT_TIMEOUT;
+            connection.internal_command_timeout = CONNECT_TIMEOUT;
+        })
+        .with_performance_config(|performance| {
+            performance.default_command_timeout = COMMAND_TIMEOUT;
+        })
+        .set_policy(ReconnectPolicy::new_exponential(0, 100, 5_000, 2))
+        .build_pool(POOL_SIZE)
+        .map_err(|e| eyre!("Invalid Redis session store settings: {}", e))?;
+    pool.init()
+        .await
+        .map_err(|e| eyre!("Redis session store at {} is unreachable: {}", server, e))?;
+    tracing::info!("Sessions are stored in Redis at {}", server);
+
+    Ok(RedisStore::new(pool))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{collections::HashMap, sync::Arc};
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufStream},
+        net::TcpListener,
+        sync::Mutex,
+    };
+    use tower_sessions::{
+        cookie::time::{Duration as CookieDuration, OffsetDateTime},
+        session::{Id, Record},
+        SessionStore,
+    };
+
+    /// Serves a minimal in-memory Redis answering GET, SET and DEL
+    async fn serve_redis() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let data = Arc::new(Mutex::new(HashMap::<Vec<u8>, Vec<u8>>::new()));
+
+        tokio::spawn(async move {
+            loop {
+                let (socket, _) = listener.accept().await.unwrap();
+                let data = Arc::clone(&data);
+                tokio::spawn(async move {
+                    let mut stream = BufStream::new(socket);
+                    loop {
+                        let mut line = String::new();
+                        if stream.read_line(&mut line).await.unwrap_or(0) == 0 {
+                            return;
+                        }
+                        let count: usize = line.trim()[1..].parse().unwrap();
+                        let mut args = Vec::new();
+                        for _ in 0..count {
+                            let mut len = String::new();
+                            stream.read_line(&mut len).await.unwrap();
+                            let mut arg = vec![0; len.trim()[1..].parse::<usize>().unwrap() + 2];
+                            stream.read_exact(&mut arg).await.unwrap();
+                            arg.truncate(arg.len() - 2);
+                            args.push(arg);
+                        }
+
+                        let mut data = data.lock().await;
+                        let reply = match args[0].to_ascii_uppercase().as_slice() {
+                            b"GET" => match data.get(&args[1]) {
+                                Some(value) => {
+                                    let mut reply = format!("${}\r\n", value.len()).into_bytes();
+                                    reply.extend_from_slice(value);
+                                    reply.extend_from_slice(b"\r\n");
+                                    reply
+                                }
+                                None => b"$-1\r\n".to_vec(),
+                            },
+                            b"SET"
+                                if args.iter().any(|arg| arg == b"NX")
+                                    && data.contains_key(&args[1]) =>
+                            {
+                                b"$-1\r\n".to_vec()
+                            }
+                            b"SET" => {
+                                data.insert(args[1].clone(), args[2].clone());
+                                b"+OK\r\n".to_vec()
+                            }
+                            b"DEL" => format!(":{}\r\n", u8::from(data.remove(&args[1]).is_some()))
+                                .into_bytes(),
+                            b"PING" => b"+PONG\r\n".to_vec(),
+                            _ => b"+OK\r\n".to_vec(),
+                        };
+                        stream.write_all(&reply).await.unwrap();
+                        stream.flush().await.unwrap();
+                    }
+                });
+            }
+        });
+
+        format!("redis://{}", address)
+    }
+
+    /// Tests that sessions round-trip through the store and can be deleted
+    #[tokio::test]
+    async fn test_round_trip() {
+        let store = connect(&serve_redis().await).await.unwrap();
+
+        let mut record = Record {
+            id: Id::default(),
+            data: HashMap::from([("value".to_string(), serde_json::json!("secret"))]),
+            expiry_date: OffsetDateTime::now_utc() + CookieDuration::hours(1),
+        };
+        store.create(&mut record).await.unwrap();
+        assert_eq!(store.load(&record.id).await.unwrap(), Some(record.clone()));
+
+        // Taken ids are replaced on creation
+        let mut duplicate = record.clone();
+        store.create(&mut duplicate).await.unwrap();
+        assert_ne!(duplicate.id, record.id);
+
+        store.delete(&record.id).await.unwrap();
+        assert_eq!(store.load(&record.id).await.unwrap(), None);
+    }
+
+    /// Tests that startup fails when the server cannot be reached
+    #[tokio::test]
+    async fn test_unreachable_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("redis://:hunter2@{}", listener.local_addr().unwrap());
+        drop(listener);
+
+        let error = connect(&url).await.unwrap_err().to_string();
+        assert!(error.contains("unreachable"), "{}", error);
+        assert!(!error.contains("hunter2"), "{}", error);
+    }
+
+    /// Tests that invalid connection URLs are rejected
+    #[tokio::test]
+    async fn test_invalid_url() {
+        for url in [
+            "localhost:6379",
+            "http://localhost",
+            "redis://localhost/cache",
+        ] {
+            let error = connect(url).await.unwrap_err().to_string();
+            assert!(error.contains("session.redis_url"), "{}: {}", url, error);
+        }
+    }
+}
@@ -0,0 +1,362 @@
+use crate::{
+    redact::redact_secrets,
+    server::{errors::internal_error, security::request_id},
+};
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::Response,
+    middleware::{self, Next},
+    Router,
+};
+use std::{any::Any, error::Error, sync::Arc};
+use tower_http::catch_panic::CatchPanicLayer;
+
+/// Kind of unexpected failure
+///
+/// * `InternalError` - A handler answered with a 5xx response
+/// * `Panic` - A handler panicked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    /// A handler answered with a 5xx response
+    InternalError,
+    /// A handler panicked
+    Panic,
+}
+
+impl FailureKind {
+    /// Returns the name of the kind
+    #[cfg_attr(not(feature = "sentry"), allow(dead_code))]
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureKind::InternalError => "internal_error",
+            FailureKind::Panic => "panic",
+        }
+    }
+}
+
+/// Unexpected failure attached to a 5xx response
+///
+/// Handlers attach it through `errors::AppError`, the panic
+/// handler attaches it to the response it builds. Its text is sanitized
+/// when it is created, so it never holds secrets.
+///
+/// # Fields
+///
+/// * `kind` - Kind of the failure
+/// * `message` - What failed
+/// * `provider` - OAuth provider involved, if any
+/// * `chain` - Sanitized error chain, outermost error first
+#[derive(Debug, Clone)]
+pub struct Failure {
+    /// Kind of the failure
+    pub kind: FailureKind,
+    /// What failed
+    pub message: String,
+    /// OAuth provider involved, if any
+    pub provider: Option<String>,
+    /// Sanitized error chain, outermost error first
+    pub chain: Vec<String>,
+}
+
+impl Failure {
+    /// Captures an unexpected handler error
+    ///
+    /// # Arguments
+    ///
+    /// * `message` - What failed
+    /// * `error` - The error that caused the failure
+    /// * `provider` - OAuth provider involved, if any
+    /// * `secrets` - Secret values known to the caller, redacted from the chain
+    ///
+    /// # Returns
+    ///
+    /// Returns the sanitized failure
+    pub fn new(
+        message: &str,
+        error: &(dyn Error + 'static),
+        provider: Option<&str>,
+        secrets: &[&str],
+    ) -> Self {
+        let chain = std::iter::successors(Some(error), |&error| error.source())
+            .map(|error| redact_secrets(&error.to_string(), secrets))
+            .collect();
+
+        Self {
+            kind: FailureKind::InternalError,
+            message: redact_secrets(message, secrets),
+            provider: provider.map(str::to_string),
+            chain,
+        }
+    }
+
+    /// Captures a handler panic
+    ///
+    /// # Arguments
+    ///
+    /// * `payload` - The panic payload
+    ///
+    /// # Returns
+    ///
+    /// Returns the sanitized failure
+    pub fn panic(payload: &(dyn Any + Send)) -> Self {
+        let message = payload
+            .downcast_ref::<String>()
+            .map(String::as_str)
+            .or_else(|| payload.downcast_ref::<&str>().copied())
+            .unwrap_or("Handler panicked");
+
+        Self {
+            kind: FailureKind::Panic,
+            message: redact_secrets(message, &[]),
+            provider: None,
+            chain: Vec::new(),
+        }
+    }
+}
+
+/// Unexpected failure as handed to an `ErrorReporter`
+///
+/// # Fields
+///
+/// * `kind` - Kind of the failure
+/// * `message` - What failed
+/// * `status` - HTTP status sent to the client
+/// * `request_id` - Request id forwarded by the proxy, if any
+/// * `provider` - OAuth provider involved, if any
+/// * `chain` - Sanitized error chain, outermost error first
+// Only read by reporters, none of which is built without the `sentry` feature
+#[cfg_attr(not(feature = "sentry"), allow(dead_code))]
+#[derive(Debug, Clone)]
+pub struct ErrorEvent {
+    /// Kind of the failure
+    pub kind: FailureKind,
+    /// What failed
+    pub message: String,
+    /// HTTP status sent to the client
+    pub status: u16,
+    /// Request id forwarded by the proxy, if any
+    pub request_id: Option<String>,
+    /// OAuth provider involved, if any
+    pub provider: Option<String>,
+    /// Sanitized error chain, outermost error first
+    pub chain: Vec<String>,
+}
+
+/// Hook receiving every unexpected failure
+///
+/// Implement it to forward failures to an error tracker. It is called on
+/// the request path, so implementations must not block.
+pub trait ErrorReporter: Send + Sync {
+    /// Reports an unexpected failure
+    ///
+    /// # Arguments
+    ///
+    /// * `event` - The sanitized failure
+    fn report(&self, event: ErrorEvent);
+}
+
+/// Reporter discarding every failure
+pub struct NoopReporter;
+
+impl ErrorReporter for NoopReporter {
+    fn report(&self, _event: ErrorEvent) {}
+}
+
+/// Reporter forwarding failures to Sentry
+///
+/// Sentry must be initialized with `sentry::init` before failures occur.
+#[cfg(feature = "sentry")]
+pub struct SentryReporter;
+
+#[cfg(feature = "sentry")]
+impl ErrorReporter for SentryReporter {
+    fn report(&self, event: ErrorEvent) {
+        use sentry::protocol::{Event, Exception, Level, Map};
+
+        let mut tags = Map::new();
+        tags.insert("kind".to_string(), event.kind.as_str().to_string());
+        tags.insert("status".to_string(), event.status.to_string());
+        if let Some(request_id) = event.request_id {
+            tags.insert("request_id".to_string(), request_id);
+        }
+        if let Some(provider) = event.provider {
+            tags.insert("provider".to_string(), provider);
+        }
+
+        // Sentry expects the root cause first
+        let exceptions: Vec<Exception> = event
+            .chain
+            .into_iter()
+            .rev()
+            .map(|cause| Exception {
+                ty: "Error".to_string(),
+                value: Some(cause),
+                ..Default::default()
+            })
+            .collect();
+
+        sentry::capture_event(Event {
+            level: Level::Error,
+            message: Some(event.message),
+            tags,
+            exception: exceptions.into(),
+            ..Default::default()
+        });
+    }
+}
+
+/// Wraps a router so every unexpected failure reaches the reporter
+///
+/// Panics are turned into 500 responses, and every 5xx response is
+/// reported exactly once, with the failure attached by the handler when
+/// there is one.
+///
+/// # Arguments
+///
+/// * `router` - The routes to watch
+/// * `reporter` - Reporter receiving the failures
+///
+/// # Returns
+///
+/// Returns the wrapped router
+pub fn with_error_reporting<S>(router: Router<S>, reporter: Arc<dyn ErrorReporter>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    router
+        .layer(CatchPanicLayer::custom(panic_response))
+        .layer(middleware::from_fn_with_state(reporter, report_failures))
+}
+
+/// Builds the response sent when a handler panics
+fn panic_response(payload: Box<dyn Any + Send>) -> Response<Body> {
+    let failure = Failure::panic(payload.as_ref());
+    tracing::error!("Handler panicked: {}", failure.message);
+
+    let mut response = internal_error("internal_error", "Internal server error");
+    response.extensions_mut().insert(failure);
+    response
+}
+
+/// Middleware reporting every 5xx response
+async fn report_failures(
+    State(reporter): State<Arc<dyn ErrorReporter>>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    let request_id = request_id(request.headers()).map(str::to_string);
+    let mut response = next.run(request).await;
+
+    let status = response.status();
+    if status.is_server_error() {
+        let failure = response
+            .extensions_mut()
+            .remove::<Failure>()
+            .unwrap_or_else(|| Failure {
+                kind: FailureKind::InternalError,
+                message: format!("Responded with {}", status),
+                provider: None,
+                chain: Vec::new(),
+            });
+
+        reporter.report(ErrorEvent {
+            kind: failure.kind,
+            message: failure.message,
+            status: status.as_u16(),
+            request_id,
+            provider: failure.provider,
+            chain: failure.chain,
+        });
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server::errors::AppError;
+    use axum::{http::StatusCode, response::IntoResponse, routing::get};
+    use std::sync::Mutex;
+    use tower::ServiceExt;
+
+    /// Reporter keeping every event it receives
+    #[derive(Default)]
+    struct RecordingReporter(Mutex<Vec<ErrorEvent>>);
+
+    impl ErrorReporter for RecordingReporter {
+        fn report(&self, event: ErrorEvent) {
+            self.0.lock().unwrap().push(event);
+        }
+    }
+
+    /// Handler panicking with a secret in its message
+    async fn panicking_handler() -> StatusCode {
+        panic!("callback failed for https://app.example.com/callback?code=abc");
+    }
+
+    /// Tests that each induced failure is reported exactly once and sanitized
+    #[tokio::test]
+    async fn test_failures_are_reported_once() {
+        let reporter = Arc::new(RecordingReporter::default());
+        let router = with_error_reporting(
+            Router::new()
+                .route("/ok", get(|| async { "OK" }))
+                .route(
+                    "/error",
+                    get(|| async {
+                        let error = eyre::eyre!("token tok-123 was revoked")
+                            .wrap_err("user info request failed");
+                        AppError::unexpected(
+                            "user_info_failed",
+                            "Failed to get user info",
+                            error.as_ref(),
+                            Some("google"),
+                            &["tok-123"],
+                        )
+                        .into_response()
+                    }),
+                )
+                .route("/panic", get(panicking_handler)),
+            reporter.clone(),
+        );
+
+        for (path, status) in [
+            ("/ok", StatusCode::OK),
+            ("/error", StatusCode::INTERNAL_SERVER_ERROR),
+            ("/panic", StatusCode::INTERNAL_SERVER_ERROR),
+        ] {
+            let response = router
+                .clone()
+                .oneshot(
+                    Request::get(path)
+                        .header("x-request-id", path)
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), status);
+        }
+
+        let events = reporter.0.lock().unwrap();
+        assert_eq!(events.len(), 2);
+
+        let error = &events[0];
+        assert_eq!(error.kind, FailureKind::InternalError);
+        assert_eq!(error.status, 500);
+        assert_eq!(error.request_id.as_deref(), Some("/error"));
+        assert_eq!(error.provider.as_deref(), Some("google"));
+        assert_eq!(error.chain.len(), 2);
+        assert!(error.chain.iter().all(|cause| !cause.contains("tok-123")));
+
+        let panic = &events[1];
+        assert_eq!(panic.kind, FailureKind::Panic);
+        assert_eq!(panic.request_id.as_deref(), Some("/panic"));
+        assert_eq!(
+            panic.message,
+            "callback failed for https://app.example.com/callback?code=[REDACTED]"
+        );
+    }
+}
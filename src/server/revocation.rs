@@ -0,0 +1,97 @@
+use crate::{
+    primitives::CurrentUser,
+    server::{
+        errors::internal_error,
+        extractors::CURRENT_USER_KEY,
+        security::{request_id, security_event},
+        server::AppState,
+    },
+};
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::Response,
+    middleware::Next,
+};
+use std::sync::Arc;
+use tower_sessions::Session;
+
+/// Middleware signing out the sessions revoked by an administrator
+///
+/// Revocations are kept in the user store, so a session revoked through
+/// one instance is signed out by every instance sharing the store. The
+/// store is only queried for sessions holding a signed-in user. A revoked
+/// session is destroyed before the route runs, which then sees a signed-out
+/// session. Other requests are recorded in the session index, extending the
+/// expiry it lists. Requests without a session layer pass through.
+///
+/// # Arguments
+///
+/// * `state` - Shared application state holding the user store
+/// * `request` - The incoming request
+/// * `next` - The rest of the middleware stack
+///
+/// # Returns
+///
+/// Returns the response of the route, or a 500 error when the revocations
+/// could not be checked
+pub async fn reject_revoked_sessions(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    let Some(session) = request.extensions().get::<Session>().cloned() else {
+        return next.run(request).await;
+    };
+    let current_user: Option<CurrentUser> = session.get(CURRENT_USER_KEY).await.ok().flatten();
+    let Some(current_user) = current_user else {
+        return next.run(request).await;
+    };
+    let Some(user_id) = current_user.internal_user_id else {
+        return next.run(request).await;
+    };
+
+    // Sessions signed in before ids were assigned can only be revoked with their user
+    let now = state.clock.now();
+    let revoked = state
+        .users
+        .is_revoked(
+            current_user.session_id.unwrap_or_default(),
+            user_id,
+            current_user.logged_in_at,
+            now,
+        )
+        .await;
+    match revoked {
+        Ok(false) => {
+            if let Some(session_id) = current_user.session_id {
+                let expires_at = now.saturating_add(state.session_ttl_seconds);
+                if let Err(e) = state.users.touch_session(session_id, now, expires_at).await {
+                    tracing::warn!("Failed to record the request of a session: {}", e);
+                }
+            }
+        }
+        Ok(true) => {
+            if let Err(e) = session.flush().await {
+                tracing::warn!("Failed to destroy a revoked session: {}", e);
+                return internal_error("session_error", "Failed to load the session");
+            }
+            security_event!(
+                "revoked_session",
+                provider: Some(&current_user.provider),
+                request_id: request_id(request.headers()),
+                subject: Some(&user_id.to_string()),
+                detail: "Signed out a session revoked by an administrator",
+            );
+        }
+        Err(e) => {
+            tracing::warn!("Failed to check the session revocations: {}", e);
+            return internal_error(
+                "revocation_check_failed",
+                "Failed to check whether the session was revoked",
+            );
+        }
+    }
+
+    next.run(request).await
+}
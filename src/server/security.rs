@@ -0,0 +1,212 @@
+use axum::http::HeaderMap;
+use serde::Serialize;
+use std::net::IpAddr;
+
+/// Tracing target of security events
+///
+/// Route this target to its own sink to separate security events from
+/// request tracing, e.g. `RUST_LOG=info,security=warn`.
+pub const SECURITY_TARGET: &str = "security";
+
+/// Request header carrying the request id set by an upstream proxy
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Schema of a security event
+///
+/// Every security-relevant decision (rejected flows, PKCE violations,
+/// throttling, failed admin authentication) is emitted with these fields
+/// on the `security` target, so downstream tooling can rely on a fixed
+/// shape. Fields that are not known at the decision point are omitted.
+///
+/// # Fields
+///
+/// * `event_type` - Stable identifier of the decision, e.g. `csrf_mismatch`
+/// * `provider` - OAuth provider of the flow, if known
+/// * `request_id` - Request id forwarded by the proxy, if any
+/// * `client_ip` - Resolved client IP address, if known
+/// * `subject` - User the event relates to, if known
+/// * `detail` - Human-readable description of the event
+#[derive(Debug, Default, Serialize)]
+pub struct SecurityEvent<'a> {
+    /// Stable identifier of the decision
+    pub event_type: &'static str,
+    /// OAuth provider of the flow, if known
+    pub provider: Option<&'a str>,
+    /// Request id forwarded by the proxy, if any
+    pub request_id: Option<&'a str>,
+    /// Resolved client IP address, if known
+    pub client_ip: Option<IpAddr>,
+    /// User the event relates to, if known
+    pub subject: Option<&'a str>,
+    /// Human-readable description of the event
+    pub detail: &'a str,
+}
+
+impl SecurityEvent<'_> {
+    /// Emits the event on the `security` target
+    pub fn emit(&self) {
+        tracing::warn!(
+            target: SECURITY_TARGET,
+            event_type = self.event_type,
+            provider = self.provider,
+            request_id = self.request_id,
+            client_ip = self.client_ip.map(tracing::field::display),
+            subject = self.subject,
+            detail = self.detail,
+            "{}",
+            self.detail
+        );
+    }
+}
+
+/// Emits a security event
+///
+/// Takes the event type followed by any of the optional `SecurityEvent`
+/// fields, which must at least include the `detail`.
+///
+/// ```ignore
+/// security_event!(
+///     "csrf_mismatch",
+///     provider: Some("google"),
+///     client_ip: ip,
+///     detail: "CSRF token mismatch",
+/// );
+/// ```
+macro_rules! security_event {
+    ($event_type:expr, $($field:ident: $value:expr),+ $(,)?) => {{
+        // The update is needless when every field is given
+        #[allow(clippy::needless_update)]
+        let event = $crate::server::security::SecurityEvent {
+            event_type: $event_type,
+            $($field: $value,)+
+            ..Default::default()
+        };
+        event.emit()
+    }};
+}
+
+pub(crate) use security_event;
+
+/// Returns the request id forwarded by the proxy, if any
+///
+/// # Arguments
+///
+/// * `headers` - Request headers
+pub fn request_id(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| !value.is_empty())
+}
+
+/// Subscriber layer recording the fields of every security event
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct CapturedEvents(
+    std::sync::Arc<std::sync::Mutex<Vec<std::collections::BTreeMap<String, String>>>>,
+);
+
+#[cfg(test)]
+impl CapturedEvents {
+    /// Installs the layer as the default subscriber of the current thread
+    pub fn install(&self) -> tracing::subscriber::DefaultGuard {
+        use tracing_subscriber::layer::SubscriberExt;
+        tracing::subscriber::set_default(tracing_subscriber::registry().with(self.clone()))
+    }
+
+    /// Returns the fields of the captured events of a type
+    pub fn of_type(&self, event_type: &str) -> Vec<std::collections::BTreeMap<String, String>> {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|fields| fields.get("event_type").map(String::as_str) == Some(event_type))
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for CapturedEvents {
+    fn on_event(
+        &self,
+        event: &tracing::Event<'_>,
+        _ctx: tracing_subscriber::layer::Context<'_, S>,
+    ) {
+        struct Fields(std::collections::BTreeMap<String, String>);
+
+        impl tracing::field::Visit for Fields {
+            fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+                self.0.insert(field.name().to_string(), value.to_string());
+            }
+
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                self.0
+                    .insert(field.name().to_string(), format!("{:?}", value));
+            }
+        }
+
+        if event.metadata().target() == SECURITY_TARGET {
+            let mut fields = Fields(Default::default());
+            event.record(&mut fields);
+            self.0.lock().unwrap().push(fields.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Tests that emitted events carry the schema fields that are known
+    #[test]
+    fn test_emitted_fields() {
+        let captured = CapturedEvents::default();
+        let _guard = captured.install();
+
+        security_event!(
+            "pkce_verifier_missing",
+            provider: Some("google"),
+            request_id: Some("req-1"),
+            client_ip: Some("203.0.113.7".parse().unwrap()),
+            detail: "OAuth flow expects an S256 PKCE verifier but none is present",
+        );
+
+        let events = captured.of_type("pkce_verifier_missing");
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event["provider"], "google");
+        assert_eq!(event["request_id"], "req-1");
+        assert_eq!(event["client_ip"], "203.0.113.7");
+        assert_eq!(
+            event["detail"],
+            "OAuth flow expects an S256 PKCE verifier but none is present"
+        );
+        assert!(!event.contains_key("subject"));
+    }
+
+    /// Tests that the serialized schema keeps every field name
+    #[test]
+    fn test_serialized_schema() {
+        let event = SecurityEvent {
+            event_type: "admin_auth_failed",
+            detail: "Rejected admin request",
+            ..Default::default()
+        };
+
+        let value = serde_json::to_value(&event).unwrap();
+        let mut keys: Vec<_> = value.as_object().unwrap().keys().cloned().collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            [
+                "client_ip",
+                "detail",
+                "event_type",
+                "provider",
+                "request_id",
+                "subject"
+            ]
+        );
+    }
+}
@@ -1,24 +1,69 @@
 use crate::{
-    server::handlers::{health_check, home_page, oauth_authorize, oauth_callback},
+    auth::TokenIssuer,
+    clock::Clock,
+    crypto::SecretCipher,
+    redact::scrub_url,
+    server::{
+        admin::{
+            delete_session, delete_sessions, delete_user, effective_config, export_user,
+            list_sessions, login_stats, provider_health, require_admin_key, revoke_sessions,
+            rotate_jwt_key, ConfigResponse,
+        },
+        audit::AuditSink,
+        cors::permissive_cors,
+        device::{device_poll, device_start},
+        handlers::{
+            health_check, link, list_providers, logout, me, metrics, oauth_authorize,
+            oauth_callback, readiness_check, refresh, require_metrics_token,
+        },
+        home::home_page,
+        identities::{list_identities, unlink_identity},
+        jwks::jwks,
+        login_audit::LoginAudit,
+        metrics::FlowMetrics,
+        provider_health::ProviderHealth,
+        rate_limit::{rate_limit, RateLimiter},
+        redirect::Redirects,
+        reporting::{with_error_reporting, ErrorReporter, NoopReporter},
+        revocation::reject_revoked_sessions,
+        session::{flow_session_layer, session_layer, SessionBackend},
+        stateless::ReplayGuard,
+        stats::LoginStats,
+        throttle::FailureThrottle,
+        token_refresh::TokenRefresher,
+    },
+    settings::{CookieSettings, FlowBinding, FlowStateMode, SessionSettings, TokenRefreshSettings},
     traits::OAuthProvider,
+    types::HttpClient,
+    users::UserRepository,
 };
 use axum::{
     extract::{MatchedPath, Request},
-    http::Method,
-    routing::get,
+    middleware,
+    routing::{delete, get, post},
     Router,
 };
-use std::{collections::HashMap, sync::Arc};
-use tower_http::{
-    cors::{AllowHeaders, Any, CorsLayer},
-    trace::TraceLayer,
+use eyre::{eyre, Result};
+use reqwest::Url;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::ErrorKind,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration as StdDuration,
 };
-use tower_sessions::{cookie::time::Duration, Expiry, SessionManagerLayer};
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use tower_http::{cors::CorsLayer, trace::TraceLayer};
+use tower_sessions::cookie::{time::Duration, Key};
 use tower_sessions_moka_store::MokaStore;
-use tracing::info_span;
+use tracing::{info, info_span, warn};
 
-/// Cache TTL for session data (1 hour)
-const CACHE_TTL: Duration = Duration::seconds(3600);
+/// Path of the OAuth callback route, redirect URIs must point at it
+pub const CALLBACK_PATH: &str = "/callback";
+
+/// Default time given to in-flight requests on shutdown
+const DEFAULT_SHUTDOWN_TIMEOUT_SECONDS: u64 = 30;
 
 /// Application state shared across request handlers
 ///
@@ -29,9 +74,96 @@ const CACHE_TTL: Duration = Duration::seconds(3600);
 /// # Fields
 ///
 /// * `oauth_providers` - HashMap of OAuth providers keyed by provider name
+/// * `secret_cipher` - Cipher applied to secrets before they are stored in the session
+/// * `flow_state` - Where the in-flight OAuth flow state is kept
+/// * `flow_ttl_seconds` - Maximum lifetime of an in-flight OAuth flow
+/// * `replay_guard` - Consumed stateless flows
+/// * `csrf_throttle` - Throttle for repeated CSRF/state validation failures
+/// * `rate_limiter` - Per-IP rate limiter of the OAuth flow routes
+/// * `flow_binding` - Client attributes a pending flow is bound to
+/// * `trusted_proxies` - Proxies whose forwarding headers are honored
+/// * `pkce_exempt_providers` - Providers configured with `pkce = false`
+/// * `unverified_email_providers` - Providers configured with `require_verified_email = false`
+/// * `strict_scope_providers` - Providers configured with `require_all_scopes = true`
+/// * `extra_auth_params` - Authorization URL parameters configured per provider
+/// * `http_client` - HTTP client shared by the providers and health probes
+/// * `clock` - Clock used for every time-based validation
+/// * `metrics` - Per-provider OAuth flow metrics
+/// * `stats` - In-memory login statistics
+/// * `admin_api_key` - Key required by the admin routes, which are disabled without it
+/// * `metrics_token` - Bearer token required by `/metrics`, which is public without it
+/// * `audit` - Signed webhook stream of security events
+/// * `login_audit` - Tamper-evident audit log of sign-ins and sign-outs
+/// * `users` - Store assigning the signed-in users their internal ids
+/// * `session_ttl_seconds` - Lifetime of an inactive session, for which revocations are kept
+/// * `token_refresh` - Background refresh of the provider tokens, which are only stored when set
+/// * `effective_config` - Sanitized configuration served by `/admin/config`
+/// * `provider_health` - Per-provider health tracking
+/// * `token_issuer` - Issuer of the JWTs minted after login, if configured
+/// * `token_redirect_url` - Frontend URL receiving the issued JWT, if configured
+/// * `redirects` - Frontend redirects of the browser flow
+/// * `route_prefix` - Path the routes are served under, empty at the root
 pub struct AppState {
     /// OAuth providers configured for the application
     pub oauth_providers: HashMap<String, Arc<dyn OAuthProvider>>,
+    /// Cipher for secrets stored in the session
+    pub secret_cipher: SecretCipher,
+    /// Where the in-flight OAuth flow state is kept
+    pub flow_state: FlowStateMode,
+    /// Maximum lifetime of an in-flight OAuth flow in seconds
+    pub flow_ttl_seconds: u64,
+    /// Consumed stateless flows
+    pub replay_guard: ReplayGuard,
+    /// Throttle for repeated CSRF/state validation failures
+    pub csrf_throttle: FailureThrottle,
+    /// Per-IP rate limiter of the OAuth flow routes
+    pub rate_limiter: RateLimiter,
+    /// Client attributes a pending flow is bound to
+    pub flow_binding: FlowBinding,
+    /// Proxies whose forwarding headers are honored
+    pub trusted_proxies: Vec<IpAddr>,
+    /// Providers configured with `pkce = false`
+    pub pkce_exempt_providers: HashSet<String>,
+    /// Providers configured with `require_verified_email = false`
+    pub unverified_email_providers: HashSet<String>,
+    /// Providers configured with `require_all_scopes = true`
+    pub strict_scope_providers: HashSet<String>,
+    /// Authorization URL parameters configured per provider
+    pub extra_auth_params: HashMap<String, BTreeMap<String, String>>,
+    /// HTTP client shared by the providers and health probes
+    pub http_client: HttpClient,
+    /// Clock used for every time-based validation
+    pub clock: Clock,
+    /// Per-provider OAuth flow metrics
+    pub metrics: FlowMetrics,
+    /// In-memory login statistics
+    pub stats: LoginStats,
+    /// Key required by the admin routes
+    pub admin_api_key: Option<String>,
+    /// Bearer token required by `/metrics`
+    pub metrics_token: Option<String>,
+    /// Signed webhook stream of security events
+    pub audit: AuditSink,
+    /// Tamper-evident audit log of sign-ins and sign-outs
+    pub login_audit: LoginAudit,
+    /// Store assigning the signed-in users their internal ids
+    pub users: Arc<dyn UserRepository>,
+    /// Lifetime of an inactive session in seconds, for which revocations are kept
+    pub session_ttl_seconds: u64,
+    /// Background refresh of the provider tokens, which are only stored when set
+    pub token_refresh: Option<TokenRefreshSettings>,
+    /// Sanitized configuration served by `/admin/config`
+    pub effective_config: ConfigResponse,
+    /// Per-provider health tracking
+    pub provider_health: ProviderHealth,
+    /// Issuer of the JWTs minted after login
+    pub token_issuer: Option<TokenIssuer>,
+    /// Frontend URL receiving the issued JWT
+    pub token_redirect_url: Option<Url>,
+    /// Frontend redirects of the browser flow
+    pub redirects: Redirects,
+    /// Path the routes are served under, empty at the root
+    pub route_prefix: String,
 }
 
 /// HTTP server struct that holds port and shared state
@@ -43,11 +175,41 @@ pub struct AppState {
 ///
 /// * `port` - Port number to listen on
 /// * `app_state` - Shared application state for request handlers
+/// * `cookie_key` - Key encrypting and authenticating session cookies
+/// * `cookie_settings` - Attributes of the session cookies
+/// * `session_store` - Store keeping the session data
+/// * `session_ttl` - Lifetime of an inactive session
+/// * `error_reporter` - Hook receiving unexpected failures and panics
+/// * `cors` - CORS policy applied to every route
+/// * `shutdown` - Token cancelled when the server starts shutting down
+/// * `shutdown_timeout` - Time given to in-flight requests on shutdown
+/// * `serve_home_page` - Whether `GET /` serves the home page
+/// * `unprefixed_health` - Whether the health routes stay at the root despite the route prefix
 pub struct Server {
     /// Port number to listen on
     pub port: u16,
     /// Shared application state for request handlers
     pub app_state: Arc<AppState>,
+    /// Key encrypting and authenticating session cookies
+    pub cookie_key: Key,
+    /// Attributes of the session cookies
+    pub cookie_settings: CookieSettings,
+    /// Store keeping the session data
+    pub session_store: SessionBackend,
+    /// Lifetime of an inactive session
+    pub session_ttl: Duration,
+    /// Hook receiving unexpected failures and panics
+    pub error_reporter: Arc<dyn ErrorReporter>,
+    /// CORS policy applied to every route
+    pub cors: CorsLayer,
+    /// Token cancelled when the server starts shutting down
+    shutdown: CancellationToken,
+    /// Time given to in-flight requests on shutdown
+    pub shutdown_timeout: StdDuration,
+    /// Whether `GET /` serves the home page
+    pub serve_home_page: bool,
+    /// Whether the health routes stay at the root despite the route prefix
+    pub unprefixed_health: bool,
 }
 
 impl Server {
@@ -60,54 +222,262 @@ impl Server {
     ///
     /// * `port` - Port number to listen on
     /// * `app_state` - Shared state containing the OAuth providers
+    /// * `cookie_key` - Key encrypting and authenticating session cookies
+    /// * `cookie_settings` - Attributes of the session cookies
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `Server` instance that does not report failures, allows
+    /// any origin and keeps sessions in memory with the default capacity
+    /// and lifetime
+    pub fn new(
+        port: u16,
+        app_state: Arc<AppState>,
+        cookie_key: Key,
+        cookie_settings: CookieSettings,
+    ) -> Self {
+        let defaults = SessionSettings::default();
+        Server {
+            port,
+            app_state,
+            cookie_key,
+            cookie_settings,
+            session_store: SessionBackend::Memory(MokaStore::new(Some(defaults.capacity))),
+            session_ttl: Duration::seconds(defaults.ttl_seconds.try_into().unwrap_or(i64::MAX)),
+            error_reporter: Arc::new(NoopReporter),
+            cors: permissive_cors(),
+            shutdown: CancellationToken::new(),
+            shutdown_timeout: StdDuration::from_secs(DEFAULT_SHUTDOWN_TIMEOUT_SECONDS),
+            serve_home_page: true,
+            unprefixed_health: false,
+        }
+    }
+
+    /// Sets the time given to in-flight requests on shutdown
+    ///
+    /// # Arguments
+    ///
+    /// * `seconds` - Time after which remaining connections are dropped
     ///
     /// # Returns
     ///
-    /// Returns a new `Server` instance
-    pub fn new(port: u16, app_state: Arc<AppState>) -> Self {
-        Server { port, app_state }
+    /// Returns the server with the timeout set
+    pub fn with_shutdown_timeout(mut self, seconds: u64) -> Self {
+        self.shutdown_timeout = StdDuration::from_secs(seconds);
+        self
     }
 
-    /// Runs the HTTP server with configured routes and middleware
+    /// Enables or disables the home page
+    ///
+    /// # Arguments
+    ///
+    /// * `serve_home_page` - Whether `GET /` serves the home page, 404 otherwise
+    ///
+    /// # Returns
+    ///
+    /// Returns the server with the home page enabled or disabled
+    pub fn with_home_page(mut self, serve_home_page: bool) -> Self {
+        self.serve_home_page = serve_home_page;
+        self
+    }
+
+    /// Keeps the health routes at the root when the routes are prefixed
+    ///
+    /// Load balancers probing `/health` then do not need to know the
+    /// prefix. Without a route prefix this has no effect.
+    ///
+    /// # Arguments
+    ///
+    /// * `unprefixed_health` - Whether `/health` and `/health/ready` stay at the root
+    ///
+    /// # Returns
+    ///
+    /// Returns the server with the health routes placed
+    pub fn with_unprefixed_health(mut self, unprefixed_health: bool) -> Self {
+        self.unprefixed_health = unprefixed_health;
+        self
+    }
+
+    /// Returns a token shutting the server down when cancelled
+    ///
+    /// Cancelling the token has the same effect as SIGTERM: the server
+    /// stops accepting connections and drains in-flight requests.
+    ///
+    /// # Returns
+    ///
+    /// Returns a clone of the server's shutdown token
+    pub fn shutdown_handle(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// Sets the store keeping the session data
+    ///
+    /// # Arguments
+    ///
+    /// * `session_store` - The configured session store
+    /// * `ttl_seconds` - Lifetime of an inactive session in seconds
+    ///
+    /// # Returns
+    ///
+    /// Returns the server with the store set
+    pub fn with_session_store(mut self, session_store: SessionBackend, ttl_seconds: u64) -> Self {
+        self.session_store = session_store;
+        self.session_ttl = Duration::seconds(ttl_seconds.try_into().unwrap_or(i64::MAX));
+        self
+    }
+
+    /// Registers the hook receiving unexpected failures and panics
+    ///
+    /// # Arguments
+    ///
+    /// * `error_reporter` - Reporter receiving every 5xx failure and panic
+    ///
+    /// # Returns
+    ///
+    /// Returns the server with the reporter registered
+    pub fn with_error_reporter(mut self, error_reporter: Arc<dyn ErrorReporter>) -> Self {
+        self.error_reporter = error_reporter;
+        self
+    }
+
+    /// Sets the CORS policy applied to every route
+    ///
+    /// # Arguments
+    ///
+    /// * `cors` - Layer built from the `[cors]` settings
+    ///
+    /// # Returns
     ///
-    /// This method starts the HTTP server with the following configuration:
+    /// Returns the server with the CORS policy set
+    pub fn with_cors(mut self, cors: CorsLayer) -> Self {
+        self.cors = cors;
+        self
+    }
+
+    /// Builds the router with configured routes and middleware
     ///
     /// ## Routes
     ///
     /// - `GET /authorize` - Initiates OAuth flow
+    /// - `GET /link` - Initiates a flow linking a provider account to the signed-in user
     /// - `GET /callback` - Handles OAuth callback
+    /// - `POST /callback` - Handles OAuth callback posted with `response_mode=form_post`
+    /// - `POST /device/start` - Starts a device flow, returning the code to show the user
+    /// - `POST /device/poll` - Polls a device flow, returning the user once approved
+    /// - `POST /refresh` - Exchanges a refresh token for a new access token
+    /// - `POST /logout` - Destroys the session, signing the user out, and revokes the given tokens
+    /// - `GET /me` - Information of the user signed in through the session or the bearer JWT
+    /// - `GET /identities` - Provider accounts linked to the signed-in user
+    /// - `DELETE /identities/:provider` - Unlinks the signed-in user's account at a provider
+    /// - `GET /providers` - Configured providers as JSON, sorted by name
+    /// - `GET /.well-known/jwks.json` - Public keys verifying the issued JWTs, for RS256 and ES256
     /// - `GET /health` - Health check endpoint
-    /// - `GET /` - Home page with provider buttons
+    /// - `GET /health/ready` - Readiness of the providers, probing them when enabled
+    /// - `GET /metrics` - OAuth flow metrics in the Prometheus text format
+    /// - `GET /admin/stats` - Login statistics, only when an admin API key is configured
+    /// - `GET /admin/config` - Effective configuration with secrets redacted, same condition
+    /// - `GET /admin/health/providers` - Provider health report, same condition
+    /// - `GET /admin/sessions` - Signed-in sessions, paginated and filtered by `subject`, same condition
+    /// - `DELETE /admin/sessions/:id` - Revokes a session, same condition
+    /// - `DELETE /admin/sessions?subject=` - Revokes every session of a user, same condition
+    /// - `POST /admin/sessions/revoke` - Revokes a session or every session of a user, same condition
+    /// - `GET /admin/users/:id/export` - Everything stored about a user, same condition
+    /// - `DELETE /admin/users/:id` - Deletes a user and revokes their provider tokens, same condition
+    /// - `POST /admin/jwt/rotate` - Promotes a staged JWT signing key, same condition
+    /// - `GET /` - Home page with a button per provider, unless disabled
+    ///
+    /// Every route is served under the application state's route prefix,
+    /// except the health routes when [`Server::with_unprefixed_health`] is set.
     ///
     /// ## Middleware
     ///
-    /// - **Session Management**: Uses MokaStore with 1-hour TTL
-    /// - **CORS**: Allows any origin, GET and POST methods, all headers
+    /// - **Session Management**: Uses the configured session store and encrypted cookies,
+    ///   the OAuth flow routes, `/me` and `/logout` get their own `SameSite=Lax` cookie in
+    ///   strict mode, as the sign-in is stored in the session of the callback
+    /// - **Session Revocation**: Signs out the sessions of those routes revoked by an administrator
+    /// - **Error Reporting**: Turns panics into 500 responses and reports every 5xx failure
+    /// - **CORS**: The configured origins and methods, any origin when not configured
     /// - **Tracing**: Request logging with method and path information
-    pub async fn run(&self) {
-        let moka_store = MokaStore::new(Some(20));
-
-        // Configure session middleware
-        let session_layer = SessionManagerLayer::new(moka_store)
-            .with_same_site(tower_sessions::cookie::SameSite::Lax)
-            .with_secure(true)
-            .with_expiry(Expiry::OnInactivity(CACHE_TTL));
-
-        let cors = CorsLayer::new()
-            // allow `GET` and `POST` when accessing the resource
-            .allow_methods(vec![Method::GET, Method::POST])
-            // allow requests from any origin
-            .allow_origin(Any)
-            .allow_headers(AllowHeaders::any());
+    pub fn router(&self) -> Router {
+        // Configure session middleware, both layers share the store
+        let session_layer = session_layer(
+            self.session_store.clone(),
+            self.cookie_key.clone(),
+            &self.cookie_settings,
+            self.session_ttl,
+        );
+        let flow_session_layer = flow_session_layer(
+            self.session_store.clone(),
+            self.cookie_key.clone(),
+            &self.cookie_settings,
+            self.session_ttl,
+            self.app_state.flow_state,
+        );
 
-        // Set up API routes and attach middleware
-        let app = Router::new()
-            .route("/authorize", get(oauth_authorize))
-            .route("/callback", get(oauth_callback))
+        let flow_routes = flow_routes(&self.app_state).layer(flow_session_layer);
+
+        // Admin routes, guarded by the admin API key
+        let mut admin_routes = Router::new();
+        if self.app_state.admin_api_key.is_some() {
+            admin_routes = admin_routes
+                .route("/admin/stats", get(login_stats))
+                .route("/admin/config", get(effective_config))
+                .route("/admin/health/providers", get(provider_health))
+                .route(
+                    "/admin/sessions",
+                    get(list_sessions).delete(delete_sessions),
+                )
+                .route("/admin/sessions/:id", delete(delete_session))
+                .route("/admin/sessions/revoke", post(revoke_sessions))
+                .route("/admin/users/:id/export", get(export_user))
+                .route("/admin/users/:id", delete(delete_user))
+                .route("/admin/jwt/rotate", post(rotate_jwt_key))
+                .route_layer(middleware::from_fn_with_state(
+                    Arc::clone(&self.app_state),
+                    require_admin_key,
+                ));
+        }
+
+        // Metrics route, guarded by the metrics token when one is configured
+        let mut metrics_routes = Router::new().route("/metrics", get(metrics));
+        if self.app_state.metrics_token.is_some() {
+            metrics_routes = metrics_routes.route_layer(middleware::from_fn_with_state(
+                Arc::clone(&self.app_state),
+                require_metrics_token,
+            ));
+        }
+
+        let health_routes = Router::new()
             .route("/health", get(health_check))
-            .route("/", get(home_page))
+            .route("/health/ready", get(readiness_check));
+        let unprefixed_health = self.unprefixed_health && !self.app_state.route_prefix.is_empty();
+
+        // Set up API routes and attach middleware
+        let mut routes = Router::new();
+        if !unprefixed_health {
+            routes = routes.merge(health_routes.clone());
+        }
+        if self.serve_home_page {
+            routes = routes.route("/", get(home_page));
+        }
+        let mut routes = routes
+            .merge(api_routes())
             .layer(session_layer)
-            .layer(cors)
+            .merge(flow_routes)
+            .merge(metrics_routes)
+            .merge(admin_routes);
+
+        // Serve the routes under the prefix, the health routes stay at the
+        // root for load balancers when configured
+        if !self.app_state.route_prefix.is_empty() {
+            routes = Router::new().nest(&self.app_state.route_prefix, routes);
+        }
+        if unprefixed_health {
+            routes = routes.merge(health_routes);
+        }
+
+        with_error_reporting(routes, Arc::clone(&self.error_reporter))
+            .layer(self.cors.clone())
             .layer(
                 TraceLayer::new_for_http().make_span_with(|request: &Request<_>| {
                     let matched_path = request
@@ -115,19 +485,194 @@ impl Server {
                         .get::<MatchedPath>()
                         .map(MatchedPath::as_str);
 
+                    // The callback URI carries the authorization code
                     info_span!(
                         "http_request",
                         method = ?request.method(),
                         matched_path,
-                        some_other_field = tracing::field::Empty,
+                        uri = %scrub_url(&request.uri().to_string()),
+                        flow_id = tracing::field::Empty,
                     )
                 }),
             )
-            .with_state(Arc::clone(&self.app_state));
+            .with_state(Arc::clone(&self.app_state))
+    }
+
+    /// Runs the HTTP server
+    ///
+    /// Binds to the configured port on all interfaces and serves the
+    /// router built by [`Server::router`] until shut down.
+    ///
+    /// # Returns
+    ///
+    /// Returns once the server has shut down, or an error if the port
+    /// cannot be bound
+    pub async fn run(&self) -> Result<()> {
+        let address = SocketAddr::from(([0, 0, 0, 0], self.port));
+        let listener = TcpListener::bind(address).await.map_err(|e| {
+            if e.kind() == ErrorKind::AddrInUse {
+                eyre!("Port {} is already in use", self.port)
+            } else {
+                eyre!("Failed to bind port {}: {}", self.port, e)
+            }
+        })?;
+
+        self.serve(listener).await
+    }
+
+    /// Serves the router on a bound listener until shut down
+    ///
+    /// The user store is prepared first, and the stored provider tokens
+    /// are refreshed in the background when configured. SIGINT, SIGTERM or cancelling the
+    /// [`Server::shutdown_handle`] token stops accepting connections.
+    /// In-flight requests are given the shutdown timeout to complete,
+    /// remaining connections are then dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `listener` - The listener to accept connections on
+    ///
+    /// # Returns
+    ///
+    /// Returns once the server has shut down, or an error if the user store
+    /// could not be prepared or serving failed
+    pub async fn serve(&self, listener: TcpListener) -> Result<()> {
+        self.app_state.users.prepare().await?;
+
+        let app = self.router();
+        let shutdown = self.shutdown.clone();
+        if let Some(token_refresh) = &self.app_state.token_refresh {
+            let refresher = TokenRefresher::new(Arc::clone(&self.app_state), token_refresh.clone());
+            tokio::spawn(refresher.run(shutdown.clone()));
+        }
+
+        let server = axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(shutdown_signal(shutdown.clone()));
+
+        let drain_timeout = async {
+            shutdown.cancelled().await;
+            tokio::time::sleep(self.shutdown_timeout).await;
+        };
+
+        tokio::select! {
+            result = server => result?,
+            _ = drain_timeout => warn!(
+                "In-flight requests did not complete within {:?}, dropping their connections",
+                self.shutdown_timeout
+            ),
+        }
+        info!("Server stopped");
+
+        Ok(())
+    }
+}
+
+/// Builds the OAuth routes for embedding in another application
+///
+/// Unlike [`Server::router`], only the OAuth routes are served, without any
+/// middleware besides the per-client rate limit of the flow routes, so the
+/// application can merge them into its own router:
+///
+/// - `GET /authorize` - Initiates OAuth flow
+/// - `GET /link` - Initiates a flow linking a provider account
+/// - `GET /callback` and `POST /callback` - Handles OAuth callback
+/// - `POST /device/start` and `POST /device/poll` - Runs a device flow
+/// - `POST /refresh` - Exchanges a refresh token for a new access token
+/// - `POST /logout` - Destroys the session and revokes the given tokens
+/// - `GET /me` - Information of the signed-in user
+/// - `GET /identities` and `DELETE /identities/:provider` - Lists and unlinks linked accounts
+/// - `GET /providers` - Configured providers as JSON, sorted by name
+///
+/// The routes keep the flow state and the sign-in in the session, so the
+/// application must add a `tower_sessions::SessionManagerLayer`. Client
+/// IPs are only known when it is served with
+/// `into_make_service_with_connect_info::<SocketAddr>()`, without it every
+/// client shares the same rate limit bucket.
+///
+/// # Arguments
+///
+/// * `state` - Shared application state, see [`crate::app::build_app_state`]
+///
+/// # Returns
+///
+/// Returns the router of the OAuth routes, with its state applied
+pub fn router(state: Arc<AppState>) -> Router {
+    api_routes().merge(flow_routes(&state)).with_state(state)
+}
+
+/// Returns the OAuth flow routes and the routes reading the signed-in user
+///
+/// The flow routes are reached through cross-site navigations and rate
+/// limited per client. `/me`, `/logout`, `/link` and `/identities` read
+/// the sign-in the callback stores, so they share the callback's session,
+/// whose revocation is checked before any of the routes runs.
+fn flow_routes(state: &Arc<AppState>) -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/authorize", get(oauth_authorize))
+        .route("/link", get(link))
+        .route(CALLBACK_PATH, get(oauth_callback).post(oauth_callback))
+        .route("/device/start", post(device_start))
+        .route_layer(middleware::from_fn_with_state(
+            Arc::clone(state),
+            rate_limit,
+        ))
+        .route("/me", get(me))
+        .route("/logout", post(logout))
+        .route("/identities", get(list_identities))
+        .route("/identities/:provider", delete(unlink_identity))
+        .layer(middleware::from_fn_with_state(
+            Arc::clone(state),
+            reject_revoked_sessions,
+        ))
+}
+
+/// Returns the token refresh, device flow polling, provider listing and key set routes
+///
+/// Device flow clients poll every few seconds, so `/device/poll` is not
+/// rate limited like the flow routes.
+fn api_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/providers", get(list_providers))
+        .route("/.well-known/jwks.json", get(jwks))
+        .route("/refresh", post(refresh))
+        .route("/device/poll", post(device_poll))
+}
+
+/// Waits for a shutdown request and cancels the shutdown token
+///
+/// Shutdown is requested by SIGINT, SIGTERM on unix, or by cancelling the
+/// token itself.
+///
+/// # Arguments
+///
+/// * `shutdown` - The server's shutdown token
+async fn shutdown_signal(shutdown: CancellationToken) {
+    let ctrl_c = async {
+        if tokio::signal::ctrl_c().await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    };
 
-        let address = format!("0.0.0.0:{}", self.port);
-        let listener = tokio::net::TcpListener::bind(address).await.unwrap();
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending::<()>().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
 
-        axum::serve(listener, app).await.unwrap();
+    tokio::select! {
+        _ = ctrl_c => info!("Received SIGINT"),
+        _ = terminate => info!("Received SIGTERM"),
+        _ = shutdown.cancelled() => {}
     }
+    info!("Shutting down, draining in-flight requests");
+    shutdown.cancel();
 }
@@ -0,0 +1,502 @@
+#[cfg(feature = "redis")]
+use crate::server::redis_store::{self, RedisSessionStore};
+use crate::settings::{
+    CookieSettings, FlowStateMode, SameSitePolicy, SessionSettings, SessionStoreKind,
+};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use eyre::{bail, eyre, Result};
+use sha2::{Digest, Sha256};
+use tower_sessions::{
+    cookie::{time::Duration, Key, SameSite},
+    service::PrivateCookie,
+    session::{Id, Record},
+    session_store, Expiry, SessionManagerLayer, SessionStore,
+};
+use tower_sessions_moka_store::MokaStore;
+
+/// Name of the session cookie outside strict mode
+const DEFAULT_SESSION_COOKIE: &str = "id";
+
+/// Minimum length in bytes of the session cookie key
+const MIN_COOKIE_KEY_LEN: usize = 64;
+
+/// Resolves the key protecting session cookies
+///
+/// The key is read from `session.cookie_key` as base64 and must be at least
+/// 64 bytes long. A fingerprint of the key is logged at startup: cookies
+/// issued under a different key are rejected, so a changed fingerprint
+/// means every existing session was invalidated. Debug builds without a
+/// configured key fall back to an ephemeral random key.
+///
+/// # Arguments
+///
+/// * `settings` - The session settings
+///
+/// # Returns
+///
+/// Returns the cookie `Key`, or an error if the key is missing (in release
+/// builds), not valid base64, or too short
+pub fn resolve_cookie_key(settings: &SessionSettings) -> Result<Key> {
+    let Some(encoded) = &settings.cookie_key else {
+        if cfg!(debug_assertions) {
+            tracing::warn!(
+                "!!! session.cookie_key is not configured, using an EPHEMERAL key: \
+                 every session is lost on restart and this is only allowed in debug builds !!!"
+            );
+            return Ok(Key::generate());
+        }
+        bail!("session.cookie_key is required");
+    };
+
+    let bytes = STANDARD
+        .decode(encoded.expose().trim())
+        .map_err(|e| eyre!("session.cookie_key is not valid base64: {}", e))?;
+
+    if bytes.len() < MIN_COOKIE_KEY_LEN {
+        bail!(
+            "session.cookie_key must be at least {} bytes, got {}",
+            MIN_COOKIE_KEY_LEN,
+            bytes.len()
+        );
+    }
+
+    let fingerprint: String = Sha256::digest(&bytes)[..4]
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    tracing::info!(
+        "Session cookies are protected with key {}; cookies issued under any other key are rejected",
+        fingerprint
+    );
+
+    Key::try_from(bytes.as_slice()).map_err(|e| eyre!("Invalid session.cookie_key: {}", e))
+}
+
+/// Session store selected in the settings
+///
+/// * `Memory` - Sessions are kept in a bounded in-process cache
+/// * `Redis` - Sessions are kept in Redis and shared between instances
+#[derive(Debug, Clone)]
+pub enum SessionBackend {
+    /// Bounded in-process cache
+    Memory(MokaStore),
+    /// Redis server shared between instances
+    #[cfg(feature = "redis")]
+    Redis(RedisSessionStore),
+}
+
+impl SessionBackend {
+    /// Creates the session store configured in the settings
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The session settings
+    ///
+    /// # Returns
+    ///
+    /// Returns the store, or an error if Redis is selected without a URL,
+    /// cannot be reached or the `redis` feature is disabled
+    pub async fn from_settings(settings: &SessionSettings) -> Result<Self> {
+        match settings.store {
+            SessionStoreKind::Memory => Ok(Self::Memory(MokaStore::new(Some(settings.capacity)))),
+            #[cfg(feature = "redis")]
+            SessionStoreKind::Redis => {
+                let Some(url) = &settings.redis_url else {
+                    bail!("session.store = \"redis\" requires session.redis_url");
+                };
+                Ok(Self::Redis(redis_store::connect(url.expose()).await?))
+            }
+            #[cfg(not(feature = "redis"))]
+            SessionStoreKind::Redis => {
+                bail!("session.store = \"redis\" requires building with the `redis` feature")
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl SessionStore for SessionBackend {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        match self {
+            Self::Memory(store) => store.create(record).await,
+            #[cfg(feature = "redis")]
+            Self::Redis(store) => store.create(record).await,
+        }
+    }
+
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        match self {
+            Self::Memory(store) => store.save(record).await,
+            #[cfg(feature = "redis")]
+            Self::Redis(store) => store.save(record).await,
+        }
+    }
+
+    async fn load(&self, id: &Id) -> session_store::Result<Option<Record>> {
+        match self {
+            Self::Memory(store) => store.load(id).await,
+            #[cfg(feature = "redis")]
+            Self::Redis(store) => store.load(id).await,
+        }
+    }
+
+    async fn delete(&self, id: &Id) -> session_store::Result<()> {
+        match self {
+            Self::Memory(store) => store.delete(id).await,
+            #[cfg(feature = "redis")]
+            Self::Redis(store) => store.delete(id).await,
+        }
+    }
+}
+
+/// Name of the session cookie in strict mode
+pub const HOST_SESSION_COOKIE: &str = "__Host-session";
+
+/// Name of the `SameSite=Lax` cookie carrying the OAuth flow in strict mode
+pub const HOST_FLOW_COOKIE: &str = "__Host-oauth_flow";
+
+/// Builds the session middleware for application routes
+///
+/// Session cookies are encrypted and authenticated with the given key, so
+/// they cannot be read or forged without it. In strict mode the cookie is
+/// `__Host-`-prefixed and `SameSite=Strict`.
+///
+/// # Arguments
+///
+/// * `store` - The session store backing the layer
+/// * `key` - The key protecting the session cookie
+/// * `cookie` - The session cookie attributes
+/// * `ttl` - Lifetime of an inactive session
+///
+/// # Returns
+///
+/// Returns the configured `SessionManagerLayer`
+pub fn session_layer<S: SessionStore + Clone>(
+    store: S,
+    key: Key,
+    cookie: &CookieSettings,
+    ttl: Duration,
+) -> SessionManagerLayer<S, PrivateCookie> {
+    if cookie.strict {
+        cookie_layer(
+            store,
+            key,
+            cookie,
+            ttl,
+            HOST_SESSION_COOKIE,
+            SameSite::Strict,
+        )
+    } else {
+        let name = cookie.name.as_deref().unwrap_or(DEFAULT_SESSION_COOKIE);
+        let same_site = match cookie.same_site {
+            Some(SameSitePolicy::Strict) => SameSite::Strict,
+            Some(SameSitePolicy::Lax) | None => SameSite::Lax,
+            Some(SameSitePolicy::None) => SameSite::None,
+        };
+        cookie_layer(store, key, cookie, ttl, name, same_site)
+    }
+}
+
+/// Builds the session middleware for the authorize, callback, `/me` and `/logout` routes
+///
+/// The provider redirects back to the callback with a cross-site top-level
+/// navigation, on which browsers do not send `SameSite=Strict` cookies. In
+/// strict mode with session flow state, the flow therefore lives in a
+/// separate `__Host-` cookie that stays `SameSite=Lax`, while every other
+/// route keeps the strict session cookie. The sign-in is stored in the same
+/// session, which is why the routes reading it share the layer. Stateless
+/// flows carry their state in the `state` parameter and keep the strict
+/// cookie everywhere.
+///
+/// # Arguments
+///
+/// * `store` - The session store backing the layer
+/// * `key` - The key protecting the session cookie
+/// * `cookie` - The session cookie attributes
+/// * `ttl` - Lifetime of an inactive session
+/// * `flow_state` - Where the in-flight OAuth flow state is kept
+///
+/// # Returns
+///
+/// Returns the configured `SessionManagerLayer`
+pub fn flow_session_layer<S: SessionStore + Clone>(
+    store: S,
+    key: Key,
+    cookie: &CookieSettings,
+    ttl: Duration,
+    flow_state: FlowStateMode,
+) -> SessionManagerLayer<S, PrivateCookie> {
+    if cookie.strict && flow_state == FlowStateMode::Session {
+        cookie_layer(store, key, cookie, ttl, HOST_FLOW_COOKIE, SameSite::Lax)
+    } else {
+        session_layer(store, key, cookie, ttl)
+    }
+}
+
+/// Builds a session layer with the given cookie name and `SameSite` policy
+///
+/// The remaining attributes come from the cookie settings.
+fn cookie_layer<S: SessionStore + Clone>(
+    store: S,
+    key: Key,
+    cookie: &CookieSettings,
+    ttl: Duration,
+    name: &str,
+    same_site: SameSite,
+) -> SessionManagerLayer<S, PrivateCookie> {
+    let mut layer = SessionManagerLayer::new(store)
+        .with_name(name.to_string())
+        .with_same_site(same_site)
+        .with_secure(cookie.secure)
+        .with_http_only(cookie.http_only)
+        .with_path(cookie.path.clone().unwrap_or_else(|| "/".to_string()))
+        .with_expiry(Expiry::OnInactivity(ttl));
+
+    if let Some(domain) = &cookie.domain {
+        layer = layer.with_domain(domain.clone());
+    }
+
+    layer.with_private(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::redact::SecretString;
+    use axum::{body::Body, http::Request, routing::get, Router};
+    use tower::ServiceExt;
+    use tower_sessions::Session;
+
+    /// Session lifetime used by the tests
+    const TTL: Duration = Duration::seconds(3600);
+
+    fn router(store: MokaStore, key: Key) -> Router {
+        Router::new()
+            .route(
+                "/set",
+                get(|session: Session| async move {
+                    session.insert("value", "secret").await.unwrap();
+                }),
+            )
+            .route(
+                "/get",
+                get(|session: Session| async move {
+                    session
+                        .get::<String>("value")
+                        .await
+                        .unwrap()
+                        .unwrap_or_else(|| "none".to_string())
+                }),
+            )
+            .layer(session_layer(store, key, &CookieSettings::default(), TTL))
+    }
+
+    async fn get_value(router: Router, cookie: &str) -> String {
+        let response = router
+            .oneshot(
+                Request::get("/get")
+                    .header("cookie", cookie)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    /// Tests that a cookie issued under one key is rejected under another
+    #[tokio::test]
+    async fn test_cookie_rejected_under_other_key() {
+        let store = MokaStore::new(Some(10));
+        let key = Key::generate();
+
+        let response = router(store.clone(), key.clone())
+            .oneshot(Request::get("/set").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let set_cookie = response.headers()["set-cookie"].to_str().unwrap();
+        let cookie = set_cookie.split(';').next().unwrap().to_string();
+
+        assert_eq!(
+            get_value(router(store.clone(), key), &cookie).await,
+            "secret"
+        );
+        assert_eq!(
+            get_value(router(store, Key::generate()), &cookie).await,
+            "none"
+        );
+    }
+
+    /// Tests that short and malformed keys fail validation
+    #[test]
+    fn test_rejects_invalid_keys() {
+        let short = SessionSettings {
+            cookie_key: Some(SecretString::from(STANDARD.encode([0u8; 32]))),
+            ..SessionSettings::default()
+        };
+        assert!(resolve_cookie_key(&short).is_err());
+
+        let malformed = SessionSettings {
+            cookie_key: Some(SecretString::from("not base64!")),
+            ..SessionSettings::default()
+        };
+        assert!(resolve_cookie_key(&malformed).is_err());
+
+        let valid = SessionSettings {
+            cookie_key: Some(SecretString::from(STANDARD.encode([7u8; 64]))),
+            ..SessionSettings::default()
+        };
+        assert!(resolve_cookie_key(&valid).is_ok());
+    }
+
+    /// Tests that the Redis store requires a URL
+    #[tokio::test]
+    async fn test_redis_store_requires_url() {
+        let settings = SessionSettings {
+            store: SessionStoreKind::Redis,
+            ..SessionSettings::default()
+        };
+        let error = SessionBackend::from_settings(&settings).await.unwrap_err();
+        assert!(error.to_string().contains("session.redis_url"));
+
+        let settings = SessionSettings::default();
+        assert!(matches!(
+            SessionBackend::from_settings(&settings).await.unwrap(),
+            SessionBackend::Memory(_)
+        ));
+    }
+
+    /// Returns the name and sorted attributes of the cookie set by a route, dropping its value
+    async fn set_cookie_attributes(router: Router, path: &str) -> (String, Vec<String>) {
+        let response = router
+            .oneshot(Request::get(path).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let set_cookie = response.headers()["set-cookie"].to_str().unwrap();
+
+        let mut parts = set_cookie.split("; ");
+        let name = parts.next().unwrap().split('=').next().unwrap().to_string();
+        let mut attributes: Vec<String> = parts
+            .filter(|part| !part.starts_with("Expires="))
+            .map(str::to_string)
+            .collect();
+        attributes.sort();
+        (name, attributes)
+    }
+
+    fn dual_cookie_router(cookie: &CookieSettings, flow_state: FlowStateMode) -> Router {
+        let store = MokaStore::new(Some(10));
+        let key = Key::generate();
+        let handler = get(|session: Session| async move {
+            session.insert("value", "secret").await.unwrap();
+        });
+
+        let flow_routes = Router::new()
+            .route("/flow", handler.clone())
+            .layer(flow_session_layer(
+                store.clone(),
+                key.clone(),
+                cookie,
+                TTL,
+                flow_state,
+            ));
+        Router::new()
+            .route("/app", handler)
+            .layer(session_layer(store, key, cookie, TTL))
+            .merge(flow_routes)
+    }
+
+    fn attributes(same_site: &str) -> Vec<String> {
+        let mut attributes = vec![
+            "HttpOnly".to_string(),
+            "Max-Age=3600".to_string(),
+            "Path=/".to_string(),
+            format!("SameSite={}", same_site),
+            "Secure".to_string(),
+        ];
+        attributes.sort();
+        attributes
+    }
+
+    /// Tests the exact cookie attributes emitted in default mode
+    #[tokio::test]
+    async fn test_default_cookie_attributes() {
+        let router = dual_cookie_router(&CookieSettings::default(), FlowStateMode::Session);
+
+        for path in ["/app", "/flow"] {
+            assert_eq!(
+                set_cookie_attributes(router.clone(), path).await,
+                ("id".to_string(), attributes("Lax"))
+            );
+        }
+    }
+
+    /// Tests that the cookie reflects the configured attributes
+    #[tokio::test]
+    async fn test_configured_cookie_attributes() {
+        let cookie = CookieSettings {
+            name: Some("oauth_session".to_string()),
+            secure: false,
+            same_site: Some(SameSitePolicy::Strict),
+            http_only: false,
+            domain: Some("example.com".to_string()),
+            path: Some("/auth".to_string()),
+            ..CookieSettings::default()
+        };
+        let router = dual_cookie_router(&cookie, FlowStateMode::Session);
+
+        let mut expected = vec![
+            "Domain=example.com".to_string(),
+            "Max-Age=3600".to_string(),
+            "Path=/auth".to_string(),
+            "SameSite=Strict".to_string(),
+        ];
+        expected.sort();
+        for path in ["/app", "/flow"] {
+            assert_eq!(
+                set_cookie_attributes(router.clone(), path).await,
+                ("oauth_session".to_string(), expected.clone())
+            );
+        }
+
+        let cross_site = CookieSettings {
+            same_site: Some(SameSitePolicy::None),
+            ..CookieSettings::default()
+        };
+        let router = dual_cookie_router(&cross_site, FlowStateMode::Session);
+        assert_eq!(
+            set_cookie_attributes(router, "/app").await,
+            ("id".to_string(), attributes("None"))
+        );
+    }
+
+    /// Tests the exact cookie attributes emitted in strict mode
+    #[tokio::test]
+    async fn test_strict_cookie_attributes() {
+        let cookie = CookieSettings {
+            strict: true,
+            ..CookieSettings::default()
+        };
+
+        let router = dual_cookie_router(&cookie, FlowStateMode::Session);
+        assert_eq!(
+            set_cookie_attributes(router.clone(), "/app").await,
+            (HOST_SESSION_COOKIE.to_string(), attributes("Strict"))
+        );
+        assert_eq!(
+            set_cookie_attributes(router, "/flow").await,
+            (HOST_FLOW_COOKIE.to_string(), attributes("Lax"))
+        );
+
+        // Stateless flows need no cookie on the callback, so everything stays strict
+        let router = dual_cookie_router(&cookie, FlowStateMode::Stateless);
+        assert_eq!(
+            set_cookie_attributes(router, "/flow").await,
+            (HOST_SESSION_COOKIE.to_string(), attributes("Strict"))
+        );
+    }
+}
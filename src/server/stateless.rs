@@ -0,0 +1,417 @@
+use crate::{clock::Clock, crypto::SecretCipher, primitives::FlowMode, redact::Redact};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use eyre::{bail, eyre, Result};
+use serde::{de::Error as _, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    sync::Mutex,
+};
+use uuid::Uuid;
+
+/// Maximum length of a minted state parameter
+///
+/// Keeps the callback URL well under the query-string limits enforced by
+/// common proxies and providers. The limit allows 452 bytes of serialized
+/// state once sealed and base64url-encoded, which every flow fits within:
+///
+/// | Field | Largest value | Bytes |
+/// |-------|---------------|-------|
+/// | `p` | provider name of [`MAX_PROVIDER_NAME_LEN`] characters | 30 |
+/// | `v` | 43 character PKCE verifier | 50 |
+/// | `i`, `e` | 10 digit timestamps | 30 |
+/// | `f` | 22 character client fingerprint | 29 |
+/// | `r` | `return_to` of [`MAX_RETURN_TO_LEN`](crate::server::redirect::MAX_RETURN_TO_LEN) characters | 135 |
+/// | `o` | 22 character ID token nonce | 29 |
+/// | `k` | user id of a link flow | 29 |
+/// | `id` | flow id | 30 |
+/// | `a` | Shopify `shop` of 63 characters | 79 |
+///
+/// With the enclosing braces, the largest state is 443 bytes.
+pub const MAX_STATE_LEN: usize = 640;
+
+/// Maximum length of a provider name in stateless mode
+///
+/// Longer names would eat into the budget of [`MAX_STATE_LEN`].
+pub const MAX_PROVIDER_NAME_LEN: usize = 24;
+
+/// OAuth flow state carried in the `state` parameter in stateless mode
+///
+/// In stateless mode nothing is stored server-side between the authorize
+/// and callback requests. Instead this structure is serialized, encrypted
+/// and authenticated with the configured key ring, and round-trips through
+/// the provider as the `state` parameter. Field names are shortened and ids
+/// are written as base64url bytes to keep the encoded state compact, and the
+/// redirect URI is not carried since the callback reads it from the provider.
+///
+/// # Fields
+///
+/// * `provider` - The name of the OAuth provider
+/// * `pkce_verifier` - The PKCE code verifier, `None` without PKCE
+/// * `issued_at` - Unix timestamp at which the state was minted
+/// * `expires_at` - Unix timestamp after which the state is rejected
+/// * `client_fingerprint` - Hash of the client attributes the flow is bound to
+/// * `return_to` - Validated frontend URL receiving the user after sign-in
+/// * `id_token_nonce` - OpenID Connect nonce the ID token must carry, if one was sent
+/// * `mode` - Whether the flow signs the user in or links a provider
+/// * `flow_id` - Random id of the flow, prefixing the `state` parameter and used for replay detection
+/// * `provider_params` - Parameters the provider was configured with for the flow
+#[derive(Serialize, Deserialize)]
+pub struct StatelessFlowState {
+    /// OAuth provider name
+    #[serde(rename = "p")]
+    pub provider: String,
+    /// PKCE code verifier, `None` when PKCE is disabled
+    #[serde(rename = "v", default, skip_serializing_if = "Option::is_none")]
+    pub pkce_verifier: Option<String>,
+    /// Unix timestamp at which the state was minted
+    #[serde(rename = "i")]
+    pub issued_at: u64,
+    /// Unix timestamp after which the state is rejected
+    #[serde(rename = "e")]
+    pub expires_at: u64,
+    /// Hash of the client attributes the flow is bound to
+    #[serde(rename = "f", default, skip_serializing_if = "Option::is_none")]
+    pub client_fingerprint: Option<String>,
+    /// Validated frontend URL receiving the user after sign-in
+    #[serde(rename = "r", default, skip_serializing_if = "Option::is_none")]
+    pub return_to: Option<String>,
+    /// OpenID Connect nonce sent on the authorization URL
+    #[serde(rename = "o", default, skip_serializing_if = "Option::is_none")]
+    pub id_token_nonce: Option<String>,
+    /// Whether the flow signs the user in or links a provider
+    #[serde(
+        rename = "k",
+        default,
+        skip_serializing_if = "FlowMode::is_login",
+        serialize_with = "serialize_mode",
+        deserialize_with = "deserialize_mode"
+    )]
+    pub mode: FlowMode,
+    /// Random id of the flow
+    #[serde(
+        rename = "id",
+        serialize_with = "serialize_uuid",
+        deserialize_with = "deserialize_uuid"
+    )]
+    pub flow_id: Uuid,
+    /// Parameters the provider was configured with
+    #[serde(rename = "a", default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub provider_params: BTreeMap<String, String>,
+}
+
+impl fmt::Debug for StatelessFlowState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StatelessFlowState")
+            .field("provider", &self.provider)
+            .field("pkce_verifier", &Redact(&self.pkce_verifier))
+            .field("issued_at", &self.issued_at)
+            .field("expires_at", &self.expires_at)
+            .field("client_fingerprint", &self.client_fingerprint)
+            .field("return_to", &self.return_to)
+            .field("id_token_nonce", &Redact(&self.id_token_nonce))
+            .field("mode", &self.mode)
+            .field("flow_id", &self.flow_id)
+            .field("provider_params", &self.provider_params)
+            .finish()
+    }
+}
+
+impl StatelessFlowState {
+    /// Encrypts the flow state into a URL-safe `state` parameter
+    ///
+    /// # Arguments
+    ///
+    /// * `cipher` - The cipher used to seal the state
+    ///
+    /// # Returns
+    ///
+    /// Returns the base64url-encoded sealed state, or an error if
+    /// encryption is not configured or the result is too long
+    pub fn mint(&self, cipher: &SecretCipher) -> Result<String> {
+        let payload = serde_json::to_vec(self)?;
+        let state = URL_SAFE_NO_PAD.encode(cipher.seal(&payload)?);
+
+        if state.len() > MAX_STATE_LEN {
+            bail!(
+                "Stateless flow state is {} bytes, exceeding the {} byte limit",
+                state.len(),
+                MAX_STATE_LEN
+            );
+        }
+
+        Ok(state)
+    }
+
+    /// Decrypts and validates a `state` parameter minted by [`StatelessFlowState::mint`]
+    ///
+    /// # Arguments
+    ///
+    /// * `cipher` - The cipher used to open the state
+    /// * `state` - The `state` parameter returned by the provider
+    /// * `clock` - The clock the issue and expiry times are checked against
+    ///
+    /// # Returns
+    ///
+    /// Returns the decrypted flow state, or an error if the state was
+    /// tampered with, minted under an unknown key, has expired, or was
+    /// issued in the future
+    pub fn open(cipher: &SecretCipher, state: &str, clock: &Clock) -> Result<Self> {
+        if state.len() > MAX_STATE_LEN {
+            bail!("State parameter is too long");
+        }
+
+        let payload = URL_SAFE_NO_PAD
+            .decode(state)
+            .map_err(|e| eyre!("State parameter is not valid base64: {}", e))?;
+        let flow_state: Self = serde_json::from_slice(&cipher.open(&payload)?)?;
+
+        if clock.has_expired(flow_state.expires_at) {
+            bail!("State parameter has expired");
+        }
+
+        if clock.is_in_future(flow_state.issued_at) {
+            bail!("State parameter was issued in the future");
+        }
+
+        Ok(flow_state)
+    }
+}
+
+/// Serializes a UUID as the base64url encoding of its 16 bytes
+fn serialize_uuid<S: Serializer>(uuid: &Uuid, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&URL_SAFE_NO_PAD.encode(uuid.as_bytes()))
+}
+
+/// Deserializes a UUID written by [`serialize_uuid`]
+fn deserialize_uuid<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Uuid, D::Error> {
+    let encoded = String::deserialize(deserializer)?;
+    URL_SAFE_NO_PAD
+        .decode(encoded)
+        .ok()
+        .and_then(|bytes| Uuid::from_slice(&bytes).ok())
+        .ok_or_else(|| D::Error::custom("invalid flow state id"))
+}
+
+/// Serializes a link flow as the id of the user linking the provider
+///
+/// Login flows are skipped when serializing, so only link flows get here.
+fn serialize_mode<S: Serializer>(mode: &FlowMode, serializer: S) -> Result<S::Ok, S::Error> {
+    match mode {
+        FlowMode::Login => serializer.serialize_none(),
+        FlowMode::Link { user_id } => serialize_uuid(user_id, serializer),
+    }
+}
+
+/// Deserializes a link flow written by [`serialize_mode`]
+fn deserialize_mode<'de, D: Deserializer<'de>>(deserializer: D) -> Result<FlowMode, D::Error> {
+    Ok(FlowMode::Link {
+        user_id: deserialize_uuid(deserializer)?,
+    })
+}
+
+/// In-memory record of consumed stateless flows
+///
+/// Prevents the same `state` parameter from being redeemed twice on this
+/// instance. Entries are kept only until the state they belong to expires,
+/// leeway included, after which the expiry check rejects the state anyway.
+#[derive(Default)]
+pub struct ReplayGuard {
+    /// Ids of the consumed flows mapped to their expiry timestamp
+    consumed: Mutex<HashMap<Uuid, u64>>,
+}
+
+impl ReplayGuard {
+    /// Records a flow as consumed
+    ///
+    /// # Arguments
+    ///
+    /// * `flow_id` - The id of the flow being redeemed
+    /// * `expires_at` - Unix timestamp at which the flow state expires
+    /// * `clock` - The clock used to prune expired flows
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` the first time a flow is seen and `false` on replay
+    pub fn consume(&self, flow_id: Uuid, expires_at: u64, clock: &Clock) -> bool {
+        let mut consumed = self.consumed.lock().unwrap_or_else(|e| e.into_inner());
+        consumed.retain(|_, expiry| !clock.has_expired(*expiry));
+        consumed.insert(flow_id, expires_at).is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::FixedTimeSource, server::redirect::MAX_RETURN_TO_LEN};
+    use std::sync::Arc;
+
+    fn clock_at(now: u64, leeway_seconds: u64) -> Clock {
+        let time = Arc::new(FixedTimeSource::default());
+        time.set(now);
+        Clock::new(time, leeway_seconds)
+    }
+
+    fn cipher(key: u8) -> SecretCipher {
+        SecretCipher::new(&[[key; 32]], false).unwrap()
+    }
+
+    fn flow_state() -> StatelessFlowState {
+        StatelessFlowState {
+            provider: "google".to_string(),
+            pkce_verifier: Some("a".repeat(43)),
+            issued_at: 1_000,
+            expires_at: 1_600,
+            client_fingerprint: Some("c".repeat(43)),
+            return_to: None,
+            id_token_nonce: Some("d".repeat(22)),
+            mode: FlowMode::Link {
+                user_id: Uuid::from_u128(1),
+            },
+            flow_id: Uuid::from_u128(2),
+            provider_params: BTreeMap::new(),
+        }
+    }
+
+    /// Tests that a minted state opens back to the same flow state
+    #[test]
+    fn test_round_trip() {
+        let cipher = cipher(1);
+        let state = flow_state().mint(&cipher).unwrap();
+
+        assert!(state.len() <= MAX_STATE_LEN);
+        assert!(!state.contains(&"a".repeat(43)));
+
+        let opened = StatelessFlowState::open(&cipher, &state, &clock_at(1_100, 0)).unwrap();
+        assert_eq!(opened.provider, "google");
+        assert_eq!(opened.pkce_verifier, Some("a".repeat(43)));
+        assert_eq!(opened.id_token_nonce, Some("d".repeat(22)));
+        assert_eq!(opened.flow_id, Uuid::from_u128(2));
+        assert_eq!(
+            opened.mode,
+            FlowMode::Link {
+                user_id: Uuid::from_u128(1)
+            }
+        );
+    }
+
+    /// Tests that a link flow bound to its client, with an ID token nonce and
+    /// current timestamps, fits the state length limit
+    #[test]
+    fn test_link_state_fits() {
+        let cipher = cipher(1);
+        let user_id = Uuid::new_v4();
+        let flow_state = StatelessFlowState {
+            issued_at: 1_760_000_000,
+            expires_at: 1_760_000_600,
+            mode: FlowMode::Link { user_id },
+            flow_id: Uuid::new_v4(),
+            ..flow_state()
+        };
+
+        let state = flow_state.mint(&cipher).unwrap();
+        let opened =
+            StatelessFlowState::open(&cipher, &state, &clock_at(1_760_000_100, 0)).unwrap();
+        assert_eq!(opened.mode, FlowMode::Link { user_id });
+        assert_eq!(opened.flow_id, flow_state.flow_id);
+    }
+
+    /// Tests that a state filling every field with its largest value fits
+    /// the state length limit
+    #[test]
+    fn test_largest_state_fits() {
+        let cipher = cipher(1);
+        let flow_state = StatelessFlowState {
+            provider: "p".repeat(MAX_PROVIDER_NAME_LEN),
+            pkce_verifier: Some("a".repeat(43)),
+            issued_at: 9_999_999_000,
+            expires_at: 9_999_999_600,
+            client_fingerprint: Some("c".repeat(22)),
+            return_to: Some(format!(
+                "https://app.example.com/{}",
+                "r".repeat(MAX_RETURN_TO_LEN - 24)
+            )),
+            id_token_nonce: Some("d".repeat(22)),
+            mode: FlowMode::Link {
+                user_id: Uuid::new_v4(),
+            },
+            flow_id: Uuid::new_v4(),
+            provider_params: BTreeMap::from([("shop".to_string(), "s".repeat(63))]),
+        };
+        assert_eq!(serde_json::to_vec(&flow_state).unwrap().len(), 443);
+
+        let state = flow_state.mint(&cipher).unwrap();
+        assert!(state.len() <= MAX_STATE_LEN);
+        let opened =
+            StatelessFlowState::open(&cipher, &state, &clock_at(9_999_999_100, 0)).unwrap();
+        assert_eq!(opened.provider_params, flow_state.provider_params);
+        assert_eq!(opened.return_to, flow_state.return_to);
+    }
+
+    /// Tests that expired states are rejected
+    #[test]
+    fn test_expired_state_is_rejected() {
+        let cipher = cipher(1);
+        let state = flow_state().mint(&cipher).unwrap();
+
+        assert!(StatelessFlowState::open(&cipher, &state, &clock_at(1_601, 0)).is_err());
+    }
+
+    /// Tests that tampered or foreign states are rejected
+    #[test]
+    fn test_tampered_state_is_rejected() {
+        let state = flow_state().mint(&cipher(1)).unwrap();
+        assert!(StatelessFlowState::open(&cipher(2), &state, &clock_at(1_100, 0)).is_err());
+
+        let mut tampered = state.into_bytes();
+        let last = tampered.len() - 1;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        let tampered = String::from_utf8(tampered).unwrap();
+        assert!(StatelessFlowState::open(&cipher(1), &tampered, &clock_at(1_100, 0)).is_err());
+    }
+
+    /// Tests that minting fails without a configured key
+    #[test]
+    fn test_mint_requires_key() {
+        assert!(flow_state().mint(&SecretCipher::disabled()).is_err());
+    }
+
+    /// Tests that a flow can only be consumed once until it expires
+    #[test]
+    fn test_replay_guard() {
+        let guard = ReplayGuard::default();
+        let (flow, other) = (Uuid::from_u128(1), Uuid::from_u128(2));
+
+        assert!(guard.consume(flow, 1_600, &clock_at(1_100, 0)));
+        assert!(!guard.consume(flow, 1_600, &clock_at(1_200, 0)));
+        assert!(guard.consume(other, 1_600, &clock_at(1_200, 0)));
+        assert!(guard.consume(flow, 2_600, &clock_at(1_700, 0)));
+    }
+
+    /// Tests that skewed clocks are tolerated within the leeway on both sides
+    #[test]
+    fn test_clock_skew_leeway() {
+        let cipher = cipher(1);
+        let state = flow_state().mint(&cipher).unwrap();
+
+        // Late side: expired at 1_600, accepted until 1_630
+        assert!(StatelessFlowState::open(&cipher, &state, &clock_at(1_630, 30)).is_ok());
+        assert!(StatelessFlowState::open(&cipher, &state, &clock_at(1_631, 30)).is_err());
+
+        // Early side: issued at 1_000 by a host running ahead of this one
+        assert!(StatelessFlowState::open(&cipher, &state, &clock_at(970, 30)).is_ok());
+        assert!(StatelessFlowState::open(&cipher, &state, &clock_at(969, 30)).is_err());
+        assert!(StatelessFlowState::open(&cipher, &state, &clock_at(999, 0)).is_err());
+    }
+
+    /// Tests that consumed flows outlive the leeway so late replays are still caught
+    #[test]
+    fn test_replay_guard_keeps_flows_through_leeway() {
+        let guard = ReplayGuard::default();
+        let (flow, other) = (Uuid::from_u128(1), Uuid::from_u128(2));
+
+        assert!(guard.consume(flow, 1_600, &clock_at(1_590, 30)));
+        assert!(guard.consume(other, 1_600, &clock_at(1_620, 30)));
+        assert!(!guard.consume(flow, 1_600, &clock_at(1_625, 30)));
+    }
+}
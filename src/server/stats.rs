@@ -0,0 +1,315 @@
+use crate::settings::StatsSettings;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Mutex,
+};
+
+/// Login counts of a single provider
+///
+/// # Fields
+///
+/// * `success` - Completed logins
+/// * `failure` - Failed or denied logins
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ProviderCounts {
+    /// Completed logins
+    pub success: u64,
+    /// Failed or denied logins
+    pub failure: u64,
+}
+
+/// Login statistics over one trailing window
+///
+/// # Fields
+///
+/// * `window_seconds` - Length of the trailing window
+/// * `total_logins` - Completed logins across all providers
+/// * `unique_subjects` - Distinct users among the completed logins
+/// * `unique_subjects_capped` - Whether the subject count hit the per-bucket limit
+/// * `providers` - Per-provider success and failure counts
+#[derive(Debug, Serialize)]
+pub struct WindowStats {
+    /// Length of the trailing window in seconds
+    pub window_seconds: u64,
+    /// Completed logins across all providers
+    pub total_logins: u64,
+    /// Distinct users among the completed logins
+    pub unique_subjects: usize,
+    /// Whether the subject count is a lower bound
+    pub unique_subjects_capped: bool,
+    /// Per-provider success and failure counts
+    pub providers: BTreeMap<String, ProviderCounts>,
+}
+
+/// Login outcomes recorded within one bucket of time
+#[derive(Default)]
+struct Bucket {
+    /// Unix timestamp at which the bucket starts
+    start: u64,
+    /// Per-provider counts
+    providers: HashMap<String, ProviderCounts>,
+    /// Hashes of the subjects that logged in
+    subjects: HashSet<[u8; 16]>,
+    /// Whether subjects were dropped because the bucket was full
+    subjects_capped: bool,
+}
+
+/// In-memory login statistics over trailing windows
+///
+/// Outcomes are aggregated into fixed-size time buckets kept in a ring
+/// buffer covering the longest configured window. Memory is bounded by the
+/// number of buckets, the configured providers and the per-bucket subject
+/// limit; subjects are only kept as truncated hashes.
+///
+/// # Fields
+///
+/// * `settings` - Windows and bucket sizes from the configuration
+/// * `providers` - Configured provider names
+/// * `buckets` - Ring buffer of time buckets
+pub struct LoginStats {
+    /// Windows and bucket sizes from the configuration
+    settings: StatsSettings,
+    /// Configured provider names
+    providers: HashSet<String>,
+    /// Ring buffer of time buckets
+    buckets: Mutex<Vec<Bucket>>,
+}
+
+impl LoginStats {
+    /// Creates empty login statistics
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Windows and bucket sizes from the configuration
+    /// * `providers` - Names of the configured providers
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `LoginStats` instance
+    pub fn new(settings: StatsSettings, providers: impl IntoIterator<Item = String>) -> Self {
+        let bucket_seconds = settings.bucket_seconds.max(1);
+        let longest_window = settings.windows_seconds.iter().copied().max().unwrap_or(0);
+        let bucket_count = longest_window.div_ceil(bucket_seconds) as usize + 1;
+
+        Self {
+            settings: StatsSettings {
+                bucket_seconds,
+                ..settings
+            },
+            providers: providers.into_iter().collect(),
+            buckets: Mutex::new((0..bucket_count).map(|_| Bucket::default()).collect()),
+        }
+    }
+
+    /// Records the outcome of a login
+    ///
+    /// Outcomes for providers that are not configured are ignored.
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - The provider of the login
+    /// * `success` - Whether the login completed
+    /// * `subject` - The user that logged in, for completed logins
+    /// * `now` - The current unix timestamp
+    pub fn record(&self, provider: &str, success: bool, subject: Option<&str>, now: u64) {
+        if !self.providers.contains(provider) {
+            return;
+        }
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let start = now - now % self.settings.bucket_seconds;
+        let slot = (now / self.settings.bucket_seconds) as usize % buckets.len();
+        let bucket = &mut buckets[slot];
+
+        // The slot still holds an older bucket, recycle it
+        if bucket.start != start {
+            *bucket = Bucket {
+                start,
+                ..Bucket::default()
+            };
+        }
+
+        let counts = bucket.providers.entry(provider.to_string()).or_default();
+        if success {
+            counts.success += 1;
+        } else {
+            counts.failure += 1;
+        }
+
+        if let Some(subject) = subject {
+            let mut hash = [0u8; 16];
+            hash.copy_from_slice(&Sha256::digest(subject.as_bytes())[..16]);
+            if bucket.subjects.len() < self.settings.max_subjects_per_bucket {
+                bucket.subjects.insert(hash);
+            } else if !bucket.subjects.contains(&hash) {
+                bucket.subjects_capped = true;
+            }
+        }
+    }
+
+    /// Aggregates the recorded outcomes over every configured window
+    ///
+    /// Windows are aligned to bucket boundaries, so a window may include
+    /// up to one extra bucket of older outcomes.
+    ///
+    /// # Arguments
+    ///
+    /// * `now` - The current unix timestamp
+    ///
+    /// # Returns
+    ///
+    /// Returns the statistics of every window, in configuration order
+    pub fn snapshot(&self, now: u64) -> Vec<WindowStats> {
+        let buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+
+        self.settings
+            .windows_seconds
+            .iter()
+            .map(|&window_seconds| {
+                let mut stats = WindowStats {
+                    window_seconds,
+                    total_logins: 0,
+                    unique_subjects: 0,
+                    unique_subjects_capped: false,
+                    providers: self
+                        .providers
+                        .iter()
+                        .map(|provider| (provider.clone(), ProviderCounts::default()))
+                        .collect(),
+                };
+                let mut subjects: HashSet<&[u8; 16]> = HashSet::new();
+
+                let window_start = now.saturating_sub(window_seconds);
+                for bucket in buckets.iter().filter(|bucket| {
+                    bucket.start <= now
+                        && bucket.start + self.settings.bucket_seconds > window_start
+                }) {
+                    for (provider, counts) in &bucket.providers {
+                        let total = stats.providers.entry(provider.clone()).or_default();
+                        total.success += counts.success;
+                        total.failure += counts.failure;
+                        stats.total_logins += counts.success;
+                    }
+                    subjects.extend(bucket.subjects.iter());
+                    stats.unique_subjects_capped |= bucket.subjects_capped;
+                }
+
+                stats.unique_subjects = subjects.len();
+                stats
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Arc, thread};
+
+    fn stats() -> LoginStats {
+        LoginStats::new(
+            StatsSettings {
+                windows_seconds: vec![3_600, 86_400],
+                bucket_seconds: 60,
+                max_subjects_per_bucket: 2,
+            },
+            ["google".to_string(), "github".to_string()],
+        )
+    }
+
+    /// Tests that outcomes fall in and out of the trailing windows
+    #[test]
+    fn test_windowed_aggregation() {
+        let stats = stats();
+        let now = 1_000_000;
+
+        // Two days ago, outside every window
+        stats.record("google", true, Some("old"), now - 172_800);
+        // Five hours ago, only in the daily window
+        stats.record("google", true, Some("alice"), now - 18_000);
+        stats.record("github", false, None, now - 18_000);
+        // Within the last hour
+        stats.record("google", true, Some("alice"), now - 600);
+        stats.record("google", true, Some("bob"), now - 60);
+        stats.record("google", false, None, now);
+        // Not configured
+        stats.record("unknown", true, Some("eve"), now);
+
+        let snapshot = stats.snapshot(now);
+
+        let hour = &snapshot[0];
+        assert_eq!(hour.window_seconds, 3_600);
+        assert_eq!(hour.total_logins, 2);
+        assert_eq!(hour.unique_subjects, 2);
+        assert_eq!(
+            hour.providers["google"],
+            ProviderCounts {
+                success: 2,
+                failure: 1
+            }
+        );
+        assert_eq!(hour.providers["github"], ProviderCounts::default());
+        assert!(!hour.providers.contains_key("unknown"));
+
+        let day = &snapshot[1];
+        assert_eq!(day.total_logins, 3);
+        assert_eq!(day.unique_subjects, 2);
+        assert_eq!(day.providers["github"].failure, 1);
+    }
+
+    /// Tests that recycled ring slots drop their previous contents
+    #[test]
+    fn test_ring_buffer_wraps() {
+        let stats = stats();
+
+        stats.record("google", true, Some("alice"), 0);
+        // Same slot one full ring later
+        let ring = 60 * (86_400 / 60 + 1);
+        stats.record("google", false, None, ring);
+
+        let day = &stats.snapshot(ring)[1];
+        assert_eq!(day.total_logins, 0);
+        assert_eq!(day.providers["google"].failure, 1);
+    }
+
+    /// Tests that subjects beyond the per-bucket limit are flagged
+    #[test]
+    fn test_subjects_are_capped() {
+        let stats = stats();
+
+        for subject in ["alice", "bob", "carol", "alice"] {
+            stats.record("google", true, Some(subject), 100);
+        }
+
+        let hour = &stats.snapshot(100)[0];
+        assert_eq!(hour.total_logins, 4);
+        assert_eq!(hour.unique_subjects, 2);
+        assert!(hour.unique_subjects_capped);
+    }
+
+    /// Tests that concurrent updates are all counted
+    #[test]
+    fn test_concurrent_updates() {
+        let stats = Arc::new(stats());
+
+        let handles: Vec<_> = (0..8)
+            .map(|i| {
+                let stats = Arc::clone(&stats);
+                thread::spawn(move || {
+                    for j in 0..500 {
+                        stats.record("github", (i + j) % 2 == 0, None, 1_000 + j);
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let counts = stats.snapshot(1_500)[0].providers["github"];
+        assert_eq!(counts.success + counts.failure, 4_000);
+        assert_eq!(counts.success, 2_000);
+    }
+}
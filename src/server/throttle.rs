@@ -0,0 +1,349 @@
+use crate::{
+    redact::redact_secrets, server::security::security_event, settings::CsrfThrottleSettings,
+};
+use reqwest::{Client, Url};
+use serde::Serialize;
+use std::{collections::HashMap, sync::Mutex};
+
+/// Failure count of a single client within the current window
+///
+/// # Fields
+///
+/// * `window_start` - Unix timestamp of the first failure in the window
+/// * `failures` - Number of failures recorded in the window
+/// * `blocked_until` - Unix timestamp until which the client is throttled
+struct FailureEntry {
+    /// Unix timestamp of the first failure in the window
+    window_start: u64,
+    /// Number of failures recorded in the window
+    failures: u32,
+    /// Unix timestamp until which the client is throttled
+    blocked_until: Option<u64>,
+}
+
+/// Security event emitted when a client crosses the failure threshold
+///
+/// # Fields
+///
+/// * `event` - Event type, always `csrf_failure_threshold`
+/// * `client` - The throttled client key (`ip:...` or `session:...`)
+/// * `failures` - Number of failures that triggered the throttle
+/// * `blocked_until` - Unix timestamp until which the client is throttled
+/// * `suppressed` - Number of alerts suppressed since the previous one
+#[derive(Debug, Serialize)]
+pub struct ThrottleAlert {
+    /// Event type
+    pub event: &'static str,
+    /// The throttled client key
+    pub client: String,
+    /// Number of failures that triggered the throttle
+    pub failures: u32,
+    /// Unix timestamp until which the client is throttled
+    pub blocked_until: u64,
+    /// Number of alerts suppressed since the previous one
+    pub suppressed: u64,
+}
+
+/// Alert rate-limiting state
+#[derive(Default)]
+struct AlertState {
+    /// Unix timestamp of the last emitted alert
+    last_sent: Option<u64>,
+    /// Number of alerts suppressed since the last emitted one
+    suppressed: u64,
+}
+
+/// Throttle for repeated CSRF/state validation failures
+///
+/// Counts validation failures per client key within a sliding window.
+/// Once a client reaches the configured number of failures it is blocked
+/// for a cool-down period, during which further callback attempts are
+/// answered with 429. The number of tracked clients is bounded; when the
+/// table is full, expired entries are dropped first and then the oldest.
+///
+/// # Fields
+///
+/// * `settings` - Thresholds and windows from the configuration
+/// * `entries` - Failure counts keyed by client
+/// * `alerts` - Alert rate-limiting state
+/// * `webhook` - Optional webhook receiving security events
+pub struct FailureThrottle {
+    /// Thresholds and windows from the configuration
+    settings: CsrfThrottleSettings,
+    /// Failure counts keyed by client
+    entries: Mutex<HashMap<String, FailureEntry>>,
+    /// Alert rate-limiting state
+    alerts: Mutex<AlertState>,
+    /// Optional webhook receiving security events
+    webhook: Option<(Client, Url)>,
+}
+
+impl FailureThrottle {
+    /// Creates a new failure throttle
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - Thresholds and windows from the configuration
+    /// * `webhook` - Optional webhook receiving security events
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `FailureThrottle` instance
+    pub fn new(settings: CsrfThrottleSettings, webhook: Option<Url>) -> Self {
+        Self {
+            settings,
+            entries: Mutex::new(HashMap::new()),
+            alerts: Mutex::new(AlertState::default()),
+            webhook: webhook.map(|url| (Client::new(), url)),
+        }
+    }
+
+    /// Returns whether any of the client keys is currently throttled
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - Client keys identifying the caller
+    /// * `now` - The current unix timestamp
+    pub fn is_blocked(&self, keys: &[String], now: u64) -> bool {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        keys.iter().any(|key| {
+            entries
+                .get(key)
+                .and_then(|entry| entry.blocked_until)
+                .is_some_and(|blocked_until| now < blocked_until)
+        })
+    }
+
+    /// Records a validation failure for every client key
+    ///
+    /// # Arguments
+    ///
+    /// * `keys` - Client keys identifying the caller
+    /// * `now` - The current unix timestamp
+    ///
+    /// # Returns
+    ///
+    /// Returns the alerts to emit for keys that just crossed the threshold,
+    /// after applying the alert rate limit
+    pub fn record_failure(&self, keys: &[String], now: u64) -> Vec<ThrottleAlert> {
+        let mut tripped = Vec::new();
+
+        {
+            let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+            for key in keys {
+                if !entries.contains_key(key) {
+                    self.make_room(&mut entries, now);
+                }
+
+                let entry = entries.entry(key.clone()).or_insert(FailureEntry {
+                    window_start: now,
+                    failures: 0,
+                    blocked_until: None,
+                });
+
+                // Start a fresh window once the previous one and any block have lapsed
+                let window_expired = now >= entry.window_start + self.settings.window_seconds;
+                let block_expired = entry.blocked_until.is_none_or(|until| now >= until);
+                if window_expired && block_expired {
+                    *entry = FailureEntry {
+                        window_start: now,
+                        failures: 0,
+                        blocked_until: None,
+                    };
+                }
+
+                entry.failures += 1;
+                if entry.failures >= self.settings.max_failures && entry.blocked_until.is_none() {
+                    let blocked_until = now + self.settings.cooldown_seconds;
+                    entry.blocked_until = Some(blocked_until);
+                    tripped.push((key.clone(), entry.failures, blocked_until));
+                }
+            }
+        }
+
+        let mut alerts = self.alerts.lock().unwrap_or_else(|e| e.into_inner());
+        tripped
+            .into_iter()
+            .filter_map(|(client, failures, blocked_until)| {
+                let rate_limited = alerts
+                    .last_sent
+                    .is_some_and(|last| now < last + self.settings.alert_interval_seconds);
+                if rate_limited {
+                    alerts.suppressed += 1;
+                    return None;
+                }
+
+                alerts.last_sent = Some(now);
+                Some(ThrottleAlert {
+                    event: "csrf_failure_threshold",
+                    client,
+                    failures,
+                    blocked_until,
+                    suppressed: std::mem::take(&mut alerts.suppressed),
+                })
+            })
+            .collect()
+    }
+
+    /// Emits a security event to the log and the optional webhook
+    ///
+    /// The webhook is called in the background so the request path is
+    /// never delayed by it.
+    ///
+    /// # Arguments
+    ///
+    /// * `alert` - The security event to emit
+    pub fn emit(&self, alert: ThrottleAlert) {
+        security_event!(
+            alert.event,
+            detail: &format!(
+                "Client {} throttled after {} CSRF/state validation failures until {} ({} alerts suppressed)",
+                alert.client, alert.failures, alert.blocked_until, alert.suppressed
+            ),
+        );
+
+        if let Some((client, url)) = &self.webhook {
+            let request = client.post(url.clone()).json(&alert);
+            tokio::spawn(async move {
+                if let Err(e) = request.send().await {
+                    tracing::warn!(
+                        "Failed to deliver security event webhook: {}",
+                        redact_secrets(&e.to_string(), &[])
+                    );
+                }
+            });
+        }
+    }
+
+    /// Frees a slot in the table when it is full
+    fn make_room(&self, entries: &mut HashMap<String, FailureEntry>, now: u64) {
+        if entries.len() < self.settings.max_tracked_clients {
+            return;
+        }
+
+        entries.retain(|_, entry| {
+            let window_active = now < entry.window_start + self.settings.window_seconds;
+            let blocked = entry.blocked_until.is_some_and(|until| now < until);
+            window_active || blocked
+        });
+
+        if entries.len() >= self.settings.max_tracked_clients {
+            if let Some(oldest) = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.window_start)
+                .map(|(key, _)| key.clone())
+            {
+                entries.remove(&oldest);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn throttle() -> FailureThrottle {
+        FailureThrottle::new(
+            CsrfThrottleSettings {
+                max_failures: 3,
+                window_seconds: 60,
+                cooldown_seconds: 300,
+                max_tracked_clients: 2,
+                alert_interval_seconds: 30,
+                webhook_url: None,
+            },
+            None,
+        )
+    }
+
+    fn keys(key: &str) -> Vec<String> {
+        vec![key.to_string()]
+    }
+
+    /// Tests that a client is blocked once it crosses the threshold
+    #[test]
+    fn test_blocks_after_threshold() {
+        let throttle = throttle();
+        let client = keys("ip:10.0.0.1");
+
+        assert!(throttle.record_failure(&client, 100).is_empty());
+        assert!(throttle.record_failure(&client, 101).is_empty());
+        assert!(!throttle.is_blocked(&client, 101));
+
+        let alerts = throttle.record_failure(&client, 102);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].client, "ip:10.0.0.1");
+        assert_eq!(alerts[0].blocked_until, 402);
+        assert!(throttle.is_blocked(&client, 102));
+        assert!(!throttle.is_blocked(&keys("ip:10.0.0.2"), 102));
+    }
+
+    /// Tests that a blocked client recovers after the cool-down
+    #[test]
+    fn test_recovers_after_cooldown() {
+        let throttle = throttle();
+        let client = keys("ip:10.0.0.1");
+
+        for now in 100..103 {
+            throttle.record_failure(&client, now);
+        }
+        assert!(throttle.is_blocked(&client, 401));
+        assert!(!throttle.is_blocked(&client, 402));
+
+        // A single failure after recovery starts a new window
+        throttle.record_failure(&client, 500);
+        assert!(!throttle.is_blocked(&client, 500));
+    }
+
+    /// Tests that failures spread over more than a window never trip the throttle
+    #[test]
+    fn test_failures_outside_window_reset() {
+        let throttle = throttle();
+        let client = keys("ip:10.0.0.1");
+
+        throttle.record_failure(&client, 100);
+        throttle.record_failure(&client, 120);
+        throttle.record_failure(&client, 170);
+        assert!(!throttle.is_blocked(&client, 170));
+    }
+
+    /// Tests that alerts are rate-limited across clients
+    #[test]
+    fn test_alerts_are_rate_limited() {
+        let throttle = throttle();
+
+        for now in 100..103 {
+            throttle.record_failure(&keys("ip:10.0.0.1"), now);
+        }
+
+        let mut suppressed = Vec::new();
+        for now in 103..106 {
+            suppressed.extend(throttle.record_failure(&keys("ip:10.0.0.2"), now));
+        }
+        assert!(suppressed.is_empty());
+        assert!(throttle.is_blocked(&keys("ip:10.0.0.2"), 106));
+
+        for now in 140..143 {
+            let alerts = throttle.record_failure(&keys("ip:10.0.0.3"), now);
+            if now == 142 {
+                assert_eq!(alerts.len(), 1);
+                assert_eq!(alerts[0].suppressed, 1);
+            }
+        }
+    }
+
+    /// Tests that the number of tracked clients stays bounded
+    #[test]
+    fn test_table_is_bounded() {
+        let throttle = throttle();
+
+        throttle.record_failure(&keys("ip:10.0.0.1"), 100);
+        throttle.record_failure(&keys("ip:10.0.0.2"), 101);
+        throttle.record_failure(&keys("ip:10.0.0.3"), 102);
+
+        let entries = throttle.entries.lock().unwrap();
+        assert_eq!(entries.len(), 2);
+        assert!(!entries.contains_key("ip:10.0.0.1"));
+    }
+}
@@ -0,0 +1,500 @@
+use crate::{
+    crypto::SecretCipher,
+    primitives::TokenDetails,
+    redact::redact_secrets,
+    server::{metrics::RefreshOutcome, server::AppState},
+    settings::TokenRefreshSettings,
+    users::StoredTokens,
+};
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use eyre::{eyre, Result};
+use std::{collections::HashMap, sync::Arc, time::Duration};
+use tokio::{sync::Semaphore, task::JoinSet, time::sleep};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+/// Encrypts the tokens issued by a provider for the token store
+///
+/// # Arguments
+///
+/// * `cipher` - Cipher encrypting the tokens
+/// * `user_id` - Internal id of the user
+/// * `provider` - Provider that issued the tokens
+/// * `tokens` - The issued tokens
+/// * `previous_refresh_token` - Encrypted refresh token kept when the provider issued none
+/// * `now` - Unix timestamp at which the tokens were issued
+///
+/// # Returns
+///
+/// Returns the tokens to store, or an error if they could not be encrypted
+pub fn seal_tokens(
+    cipher: &SecretCipher,
+    user_id: Uuid,
+    provider: &str,
+    tokens: &TokenDetails,
+    previous_refresh_token: Option<String>,
+    now: u64,
+) -> Result<StoredTokens> {
+    Ok(StoredTokens {
+        user_id,
+        provider: provider.to_string(),
+        access_token: cipher.encrypt(&tokens.access_token)?,
+        refresh_token: match &tokens.refresh_token {
+            Some(refresh_token) => Some(cipher.encrypt(refresh_token)?),
+            None => previous_refresh_token,
+        },
+        expires_at: tokens.expires_at,
+        updated_at: now,
+        needs_reauth_at: None,
+    })
+}
+
+/// Outcome of a scan of the expiring tokens
+///
+/// # Fields
+///
+/// * `refreshed` - Tokens the providers refreshed
+/// * `failed` - Tokens that could not be refreshed, now waiting for the user to sign in again
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RefreshPass {
+    /// Tokens the providers refreshed
+    pub refreshed: usize,
+    /// Tokens that could not be refreshed
+    pub failed: usize,
+}
+
+/// Background refresh of the provider tokens kept in the user store
+///
+/// Every interval, plus a random jitter so that restarted instances do not
+/// scan in step, the tokens expiring within the window are refreshed with
+/// their refresh token and written back. Refreshes run concurrently, with
+/// at most `max_concurrency_per_provider` in flight against a provider. A
+/// failed refresh is not retried: the tokens are marked as needing the user
+/// to sign in again, which stores new ones.
+///
+/// [`Server`] runs the refresh until it shuts down. Applications merging
+/// [`router`] into their own spawn [`TokenRefresher::run`] themselves.
+///
+/// [`Server`]: crate::server::server::Server
+/// [`router`]: crate::server::server::router
+///
+/// # Fields
+///
+/// * `state` - Shared application state holding the providers and the user store
+/// * `settings` - Window, interval and limits of the refresh
+/// * `limits` - Refreshes allowed in flight, keyed by provider
+pub struct TokenRefresher {
+    /// Shared application state holding the providers and the user store
+    state: Arc<AppState>,
+    /// Window, interval and limits of the refresh
+    settings: TokenRefreshSettings,
+    /// Refreshes allowed in flight, keyed by provider
+    limits: HashMap<String, Arc<Semaphore>>,
+}
+
+impl TokenRefresher {
+    /// Creates the refresh of the configured providers' tokens
+    ///
+    /// # Arguments
+    ///
+    /// * `state` - Shared application state holding the providers and the user store
+    /// * `settings` - Window, interval and limits of the refresh
+    ///
+    /// # Returns
+    ///
+    /// Returns a new `TokenRefresher` instance
+    pub fn new(state: Arc<AppState>, settings: TokenRefreshSettings) -> Self {
+        let permits = settings.max_concurrency_per_provider.max(1);
+        let limits = state
+            .oauth_providers
+            .keys()
+            .map(|provider| (provider.clone(), Arc::new(Semaphore::new(permits))))
+            .collect();
+        Self {
+            state,
+            settings,
+            limits,
+        }
+    }
+
+    /// Refreshes the expiring tokens every interval until shut down
+    ///
+    /// # Arguments
+    ///
+    /// * `shutdown` - Token stopping the refresh when cancelled
+    pub async fn run(self, shutdown: CancellationToken) {
+        loop {
+            let delay = self.settings.interval_seconds + jitter(self.settings.jitter_seconds);
+            tokio::select! {
+                _ = shutdown.cancelled() => return,
+                _ = sleep(Duration::from_secs(delay)) => {}
+            }
+
+            match self.refresh_expiring().await {
+                Ok(pass) if pass.refreshed + pass.failed > 0 => tracing::info!(
+                    refreshed = pass.refreshed,
+                    failed = pass.failed,
+                    "Refreshed the expiring provider tokens"
+                ),
+                Ok(_) => {}
+                Err(e) => tracing::warn!("Failed to list the expiring provider tokens: {}", e),
+            }
+        }
+    }
+
+    /// Refreshes the tokens expiring within the window once
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of refreshed and failed tokens, or an error if
+    /// the expiring tokens could not be listed
+    pub async fn refresh_expiring(&self) -> Result<RefreshPass> {
+        let expires_before = self
+            .state
+            .clock
+            .now()
+            .saturating_add(self.settings.window_seconds);
+        let expiring = self
+            .state
+            .users
+            .expiring_tokens(expires_before, self.settings.batch_size)
+            .await?;
+
+        let mut refreshes = JoinSet::new();
+        for stored in expiring {
+            let state = Arc::clone(&self.state);
+            let limit = self.limits.get(&stored.provider).cloned();
+            refreshes.spawn(async move {
+                let _permit = match limit {
+                    Some(limit) => Some(limit.acquire_owned().await),
+                    None => None,
+                };
+                refresh(&state, stored).await
+            });
+        }
+
+        let mut pass = RefreshPass::default();
+        while let Some(refreshed) = refreshes.join_next().await {
+            match refreshed {
+                Ok(RefreshOutcome::Refreshed) => pass.refreshed += 1,
+                Ok(RefreshOutcome::Failed) | Err(_) => pass.failed += 1,
+            }
+        }
+        Ok(pass)
+    }
+}
+
+/// Refreshes stored tokens, marking them as needing a sign-in on failure
+///
+/// # Arguments
+///
+/// * `state` - Shared application state holding the providers and the user store
+/// * `stored` - The expiring tokens
+///
+/// # Returns
+///
+/// Returns the outcome, also counted in the metrics
+async fn refresh(state: &AppState, stored: StoredTokens) -> RefreshOutcome {
+    let now = state.clock.now();
+    let refreshed = match refresh_tokens(state, &stored, now).await {
+        Ok(refreshed) => state.users.store_tokens(&refreshed).await,
+        Err(e) => Err(e),
+    };
+
+    let outcome = match refreshed {
+        Ok(()) => RefreshOutcome::Refreshed,
+        Err(e) => {
+            tracing::warn!(
+                provider = %stored.provider,
+                "Failed to refresh stored provider tokens, the user must sign in again: {}",
+                e
+            );
+            if let Err(e) = state
+                .users
+                .mark_needs_reauth(stored.user_id, &stored.provider, now)
+                .await
+            {
+                tracing::warn!("Failed to mark provider tokens as needing a sign-in: {}", e);
+            }
+            RefreshOutcome::Failed
+        }
+    };
+    state.metrics.record_refresh(&stored.provider, outcome);
+    outcome
+}
+
+/// Exchanges the refresh token of stored tokens for new tokens
+///
+/// # Arguments
+///
+/// * `state` - Shared application state holding the providers and the secret cipher
+/// * `stored` - The expiring tokens
+/// * `now` - The current unix timestamp
+///
+/// # Returns
+///
+/// Returns the new tokens to store, or an error if the provider is no
+/// longer configured, the refresh token cannot be decrypted, or the
+/// provider refused the refresh
+async fn refresh_tokens(state: &AppState, stored: &StoredTokens, now: u64) -> Result<StoredTokens> {
+    let oauth_provider = state
+        .oauth_providers
+        .get(&stored.provider)
+        .ok_or_else(|| eyre!("Provider {} is no longer configured", stored.provider))?;
+    let refresh_token = state
+        .secret_cipher
+        .decrypt(stored.refresh_token.as_deref().unwrap_or_default())?;
+
+    let tokens = oauth_provider
+        .refresh_token(&refresh_token, now)
+        .await
+        .map_err(|e| eyre!(redact_secrets(&e.to_string(), &[&refresh_token])))?;
+    seal_tokens(
+        &state.secret_cipher,
+        stored.user_id,
+        &stored.provider,
+        &tokens,
+        stored.refresh_token.clone(),
+        now,
+    )
+}
+
+/// Returns a random delay of up to the given number of seconds
+fn jitter(max_seconds: u64) -> u64 {
+    match max_seconds {
+        0 => 0,
+        max_seconds => OsRng.next_u64() % (max_seconds + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        app::build_app_state,
+        clock::{Clock, FixedTimeSource},
+        primitives::UserInfo,
+        settings::Settings,
+        traits::OAuthProvider,
+        types::{BareOAuthClient, HttpClient, OAuthClient},
+    };
+    use oauth2::{AuthUrl, ClientId, RedirectUrl, TokenUrl};
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    /// Refresh token the test provider refuses
+    const REVOKED_REFRESH_TOKEN: &str = "revoked-refresh-token";
+
+    /// Provider refreshing tokens after a short delay, refusing `REVOKED_REFRESH_TOKEN`
+    struct RefreshingProvider {
+        /// Configured OAuth client
+        oauth_client: OAuthClient,
+        /// Shared HTTP client
+        http_client: HttpClient,
+        /// Refreshes currently in flight
+        in_flight: AtomicUsize,
+        /// Most refreshes that were in flight at once
+        max_in_flight: AtomicUsize,
+        /// Refresh tokens received so far
+        received: Mutex<Vec<String>>,
+    }
+
+    impl RefreshingProvider {
+        fn new() -> Self {
+            Self {
+                oauth_client: BareOAuthClient::new(ClientId::new("client".to_string()))
+                    .set_auth_uri(AuthUrl::new("http://127.0.0.1:9/authorize".to_string()).unwrap())
+                    .set_token_uri(TokenUrl::new("http://127.0.0.1:9/token".to_string()).unwrap())
+                    .set_redirect_uri(
+                        RedirectUrl::new("http://127.0.0.1:9/callback".to_string()).unwrap(),
+                    )
+                    .set_revocation_url_option(None)
+                    .set_device_authorization_url_option(None)
+                    .into(),
+                http_client: HttpClient::default(),
+                in_flight: AtomicUsize::new(0),
+                max_in_flight: AtomicUsize::new(0),
+                received: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    #[axum::async_trait]
+    impl OAuthProvider for RefreshingProvider {
+        fn get_oauth_client(&self) -> &OAuthClient {
+            &self.oauth_client
+        }
+
+        fn http_client(&self) -> &HttpClient {
+            &self.http_client
+        }
+
+        fn get_scopes(&self) -> Vec<String> {
+            Vec::new()
+        }
+
+        async fn get_user_info(&self, _access_token: &str) -> eyre::Result<UserInfo> {
+            eyre::bail!("No user info")
+        }
+
+        async fn refresh_token(&self, refresh_token: &str, now: u64) -> eyre::Result<TokenDetails> {
+            self.received
+                .lock()
+                .unwrap()
+                .push(refresh_token.to_string());
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            sleep(Duration::from_millis(20)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            if refresh_token == REVOKED_REFRESH_TOKEN {
+                eyre::bail!("invalid_grant: {} was revoked", refresh_token);
+            }
+            Ok(TokenDetails {
+                access_token: format!("access-{}", now),
+                refresh_token: None,
+                expires_at: Some(now + 3_600),
+                scopes: Vec::new(),
+                id_token: None,
+                user_data: None,
+            })
+        }
+    }
+
+    /// Builds a state refreshing the tokens of `provider`, reading the given time
+    fn refreshing_state(
+        provider: Arc<RefreshingProvider>,
+        time: Arc<FixedTimeSource>,
+    ) -> Arc<AppState> {
+        let settings: Settings = serde_json::from_value(serde_json::json!({
+            "port": 0,
+            "oauth": {},
+            "encryption": { "keys": ["AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE="] },
+            "token_refresh": {},
+        }))
+        .unwrap();
+        let providers: HashMap<String, Arc<dyn OAuthProvider>> =
+            HashMap::from([("fitbit".to_string(), provider as Arc<dyn OAuthProvider>)]);
+        let mut state =
+            Arc::into_inner(build_app_state(&settings, HttpClient::default(), providers).unwrap())
+                .unwrap();
+        state.clock = Clock::new(time, 0);
+        Arc::new(state)
+    }
+
+    /// Stores tokens of a new user expiring at the given time
+    async fn store(state: &AppState, refresh_token: &str, expires_at: u64) -> Uuid {
+        let user = state
+            .users
+            .upsert("fitbit", &Uuid::new_v4().to_string(), 0)
+            .await
+            .unwrap();
+        let tokens = TokenDetails {
+            access_token: "access-0".to_string(),
+            refresh_token: Some(refresh_token.to_string()),
+            expires_at: Some(expires_at),
+            scopes: Vec::new(),
+            id_token: None,
+            user_data: None,
+        };
+        let stored =
+            seal_tokens(&state.secret_cipher, user.id, "fitbit", &tokens, None, 0).unwrap();
+        state.users.store_tokens(&stored).await.unwrap();
+        user.id
+    }
+
+    /// Tests that tokens are refreshed once they expire within the window,
+    /// and that refused refreshes wait for a sign-in instead of being retried
+    #[tokio::test]
+    async fn test_refreshes_expiring_tokens() {
+        let provider = Arc::new(RefreshingProvider::new());
+        let time = Arc::new(FixedTimeSource::default());
+        let state = refreshing_state(Arc::clone(&provider), Arc::clone(&time));
+        let refresher = TokenRefresher::new(Arc::clone(&state), TokenRefreshSettings::default());
+
+        let refreshed = store(&state, "refresh-token", 2_000).await;
+        let revoked = store(&state, REVOKED_REFRESH_TOKEN, 2_000).await;
+        store(&state, "later-refresh-token", 5_000).await;
+
+        // Nothing expires within the 600 seconds window yet
+        time.set(1_399);
+        assert_eq!(
+            refresher.refresh_expiring().await.unwrap(),
+            RefreshPass::default()
+        );
+
+        time.set(1_400);
+        assert_eq!(
+            refresher.refresh_expiring().await.unwrap(),
+            RefreshPass {
+                refreshed: 1,
+                failed: 1
+            }
+        );
+        let tokens = state.users.tokens(refreshed).await.unwrap().remove(0);
+        assert_eq!(tokens.expires_at, Some(5_000));
+        assert_eq!(tokens.updated_at, 1_400);
+        assert!(tokens.access_token.starts_with("enc:v1:"));
+        assert_eq!(
+            state.secret_cipher.decrypt(&tokens.access_token).unwrap(),
+            "access-1400"
+        );
+        assert_eq!(
+            state
+                .secret_cipher
+                .decrypt(tokens.refresh_token.as_deref().unwrap())
+                .unwrap(),
+            "refresh-token"
+        );
+        let tokens = state.users.tokens(revoked).await.unwrap().remove(0);
+        assert_eq!(tokens.needs_reauth_at, Some(1_400));
+
+        // The refused token is not retried
+        time.set(1_500);
+        assert_eq!(
+            refresher.refresh_expiring().await.unwrap(),
+            RefreshPass::default()
+        );
+        assert_eq!(provider.received.lock().unwrap().len(), 2);
+
+        let metrics = state.metrics.render();
+        assert!(metrics
+            .contains("oauth_token_refresh_total{provider=\"fitbit\",outcome=\"refreshed\"} 1"));
+        assert!(
+            metrics.contains("oauth_token_refresh_total{provider=\"fitbit\",outcome=\"failed\"} 1")
+        );
+        assert!(!metrics.contains(REVOKED_REFRESH_TOKEN));
+    }
+
+    /// Tests that no more refreshes than allowed run at once against a provider
+    #[tokio::test]
+    async fn test_refreshes_are_limited_per_provider() {
+        let provider = Arc::new(RefreshingProvider::new());
+        let time = Arc::new(FixedTimeSource::default());
+        let state = refreshing_state(Arc::clone(&provider), Arc::clone(&time));
+        let refresher = TokenRefresher::new(
+            Arc::clone(&state),
+            TokenRefreshSettings {
+                max_concurrency_per_provider: 2,
+                ..TokenRefreshSettings::default()
+            },
+        );
+        for _ in 0..6 {
+            store(&state, "refresh-token", 1_000).await;
+        }
+
+        time.set(1_000);
+        let pass = refresher.refresh_expiring().await.unwrap();
+        assert_eq!(pass.refreshed, 6);
+        assert_eq!(provider.max_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    /// Tests that the jitter never exceeds its bound
+    #[test]
+    fn test_jitter_is_bounded() {
+        assert_eq!(jitter(0), 0);
+        assert!((0..100).all(|_| jitter(5) <= 5));
+    }
+}
@@ -1,6 +1,11 @@
-use config::{Config, File};
+use crate::redact::{SecretString, DEFAULT_MIN_TOKEN_LENGTH};
+use config::{Config, Environment, File, Source};
+use eyre::{bail, eyre, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    net::IpAddr,
+};
 
 /// Main application settings structure
 ///
@@ -10,13 +15,182 @@ use std::collections::HashMap;
 /// # Fields
 ///
 /// * `port` - The port number the server will listen on
+/// * `base_url` - Public URL the server is reached at, redirect URIs default to its callback
 /// * `oauth` - HashMap of OAuth provider configurations keyed by provider name
+/// * `encryption` - Optional encryption settings for secrets at rest
+/// * `flow_state` - Where the in-flight OAuth flow state is kept
+/// * `flow_ttl_seconds` - Maximum lifetime of an in-flight OAuth flow
+/// * `csrf_throttle` - Throttling of repeated CSRF/state validation failures
+/// * `rate_limit` - Per-IP rate limit of the OAuth flow routes
+/// * `session` - Session cookie settings
+/// * `flow_binding` - Client attributes a pending flow is bound to
+/// * `trusted_proxies` - Proxies whose `X-Forwarded-For` headers are honored
+/// * `leeway_seconds` - Clock skew tolerated by time-based validations
+/// * `admin` - Admin API settings
+/// * `stats` - In-memory login statistics settings
+/// * `metrics` - Metrics endpoint settings
+/// * `audit_webhook` - Optional webhook receiving signed security events
+/// * `audit` - Optional audit log of sign-ins and sign-outs
+/// * `database` - Optional database the signed-in users are persisted to
+/// * `provider_health` - Provider health tracking and probe limits
+/// * `http_client` - Timeouts and User-Agent of the outgoing HTTP client
+/// * `jwt` - Optional signing settings of the JWTs issued after login
+/// * `strict_providers` - Refuse to start when a configured provider is not supported
+/// * `shutdown_timeout_seconds` - Time given to in-flight requests on shutdown
+/// * `serve_home_page` - Whether `GET /` serves the home page with the provider buttons
+/// * `route_prefix` - Path the routes are served under, e.g. `/auth`, at the root when empty
+/// * `unprefixed_health` - Serve the health routes at the root despite the route prefix
+/// * `success_redirect_url` - Frontend URL receiving the user after a sign-in
+/// * `error_redirect_url` - Frontend URL receiving the user after a failed sign-in
+/// * `allowed_redirect_origins` - Origins `return_to` targets may point at
+/// * `cors` - Cross-origin requests allowed by the server, any origin when unset
+/// * `redaction` - Redaction of secrets from the logs
+/// * `telemetry` - Optional OpenTelemetry export of the traces
+/// * `file_sections` - Top-level sections present in the configuration file
+/// * `env_sections` - Top-level sections set by environment variables
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Settings {
     /// Server port number
     pub port: u16,
+    /// Public URL the server is reached at
+    #[serde(default)]
+    pub base_url: Option<String>,
     /// OAuth provider configurations
     pub oauth: HashMap<String, OAuthSettings>,
+    /// Encryption settings for secrets stored in the session store
+    #[serde(default)]
+    pub encryption: Option<EncryptionSettings>,
+    /// Where the in-flight OAuth flow state is kept
+    #[serde(default)]
+    pub flow_state: FlowStateMode,
+    /// Maximum lifetime of an in-flight OAuth flow in seconds
+    #[serde(default = "default_flow_ttl_seconds")]
+    pub flow_ttl_seconds: u64,
+    /// Throttling of repeated CSRF/state validation failures
+    #[serde(default)]
+    pub csrf_throttle: CsrfThrottleSettings,
+    /// Per-IP rate limit of the OAuth flow routes
+    #[serde(default)]
+    pub rate_limit: RateLimitSettings,
+    /// Session cookie settings
+    #[serde(default)]
+    pub session: SessionSettings,
+    /// Client attributes a pending flow is bound to
+    #[serde(default)]
+    pub flow_binding: FlowBinding,
+    /// Proxies whose `X-Forwarded-For` headers are honored
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
+    /// Clock skew tolerated by time-based validations in seconds
+    #[serde(default = "default_leeway_seconds")]
+    pub leeway_seconds: u64,
+    /// Admin API settings
+    #[serde(default)]
+    pub admin: AdminSettings,
+    /// In-memory login statistics settings
+    #[serde(default)]
+    pub stats: StatsSettings,
+    /// Metrics endpoint settings
+    #[serde(default)]
+    pub metrics: MetricsSettings,
+    /// Optional webhook receiving signed security events
+    #[serde(default)]
+    pub audit_webhook: Option<AuditWebhookSettings>,
+    /// Audit log of sign-ins and sign-outs
+    #[serde(default)]
+    pub audit: Option<AuditSettings>,
+    /// Database the signed-in users are persisted to, in memory when unset
+    #[serde(default)]
+    pub database: Option<DatabaseSettings>,
+    /// Background refresh of the provider tokens, which are only stored when set
+    #[serde(default)]
+    pub token_refresh: Option<TokenRefreshSettings>,
+    /// Provider health tracking and probe limits
+    #[serde(default)]
+    pub provider_health: ProviderHealthSettings,
+    /// Timeouts and User-Agent of the outgoing HTTP client
+    #[serde(default)]
+    pub http_client: HttpClientSettings,
+    /// Signing settings of the JWTs issued after login
+    #[serde(default)]
+    pub jwt: Option<JwtSettings>,
+    /// Refuse to start when a configured provider is not supported
+    #[serde(default)]
+    pub strict_providers: bool,
+    /// Time given to in-flight requests on shutdown in seconds
+    #[serde(default = "default_shutdown_timeout_seconds")]
+    pub shutdown_timeout_seconds: u64,
+    /// Whether `GET /` serves the home page with the provider buttons
+    #[serde(default = "default_serve_home_page")]
+    pub serve_home_page: bool,
+    /// Path the routes are served under, at the root when empty
+    #[serde(default)]
+    pub route_prefix: String,
+    /// Serve the health routes at the root despite the route prefix
+    #[serde(default)]
+    pub unprefixed_health: bool,
+    /// Frontend URL receiving the user after a sign-in, JSON response when unset
+    #[serde(default)]
+    pub success_redirect_url: Option<String>,
+    /// Frontend URL receiving the user after a failed sign-in, JSON response when unset
+    #[serde(default)]
+    pub error_redirect_url: Option<String>,
+    /// Origins `return_to` targets may point at, none when empty
+    #[serde(default)]
+    pub allowed_redirect_origins: Vec<String>,
+    /// Cross-origin requests allowed by the server, any origin when unset
+    #[serde(default)]
+    pub cors: Option<CorsSettings>,
+    /// Redaction of secrets from the logs
+    #[serde(default)]
+    pub redaction: RedactionSettings,
+    /// OpenTelemetry export of the traces, spans stay in the process when unset
+    #[serde(default)]
+    pub telemetry: Option<TelemetrySettings>,
+    /// Top-level sections present in the configuration file
+    #[serde(skip)]
+    pub file_sections: BTreeSet<String>,
+    /// Top-level sections set by environment variables
+    #[serde(skip)]
+    pub env_sections: BTreeSet<String>,
+}
+
+/// Separator of nested keys in environment variable names
+const ENV_SEPARATOR: &str = "__";
+
+/// Default maximum lifetime of an in-flight OAuth flow (10 minutes)
+fn default_flow_ttl_seconds() -> u64 {
+    600
+}
+
+/// Default time given to in-flight requests on shutdown (30 seconds)
+fn default_shutdown_timeout_seconds() -> u64 {
+    30
+}
+
+/// The home page is served unless disabled
+fn default_serve_home_page() -> bool {
+    true
+}
+
+/// Default clock skew tolerance (30 seconds)
+fn default_leeway_seconds() -> u64 {
+    30
+}
+
+/// Storage mode for the in-flight OAuth flow state
+///
+/// * `Session` - The flow state is stored server-side in the session store
+/// * `Stateless` - The flow state is encrypted into the `state` parameter,
+///   so no session store has to be shared between instances
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlowStateMode {
+    /// Store the flow state in the session
+    #[default]
+    Session,
+    /// Carry the flow state in an encrypted `state` parameter
+    Stateless,
 }
 
 impl Settings {
@@ -24,7 +198,9 @@ impl Settings {
     ///
     /// This function reads the specified TOML file and deserializes it into
     /// a Settings structure. The file should contain server configuration
-    /// and OAuth provider settings.
+    /// and OAuth provider settings. Environment variables override the file,
+    /// with `__` separating nested keys (`OAUTH__GOOGLE__CLIENT_SECRET`), and
+    /// `${ENV_VAR}` references in string values are replaced by the variable.
     ///
     /// # Arguments
     ///
@@ -32,18 +208,401 @@ impl Settings {
     ///
     /// # Returns
     ///
+    /// Returns a `Settings` instance with the loaded configuration, or an
+    /// error if the file is invalid or references an unset variable
+    pub fn from_toml(path: &str) -> Result<Self> {
+        Self::load(
+            File::with_name(path),
+            Environment::default().separator(ENV_SEPARATOR),
+        )
+    }
+
+    /// Returns the path the routes are served under
+    ///
+    /// A trailing slash is ignored, so `/auth/` and `/auth` are the same
+    /// prefix and `/` is no prefix.
+    ///
+    /// # Returns
+    ///
+    /// Returns the prefix without a trailing slash, empty when the routes are
+    /// served at the root, or an error if it does not start with `/` or
+    /// contains characters the router would treat as a pattern
+    pub fn route_prefix(&self) -> Result<&str> {
+        let prefix = self.route_prefix.trim_end_matches('/');
+        if prefix.is_empty() {
+            return Ok(prefix);
+        }
+        if !prefix.starts_with('/')
+            || prefix.contains("//")
+            || prefix.contains([':', '*', '?', '#', '{', '}'])
+        {
+            bail!(
+                "route_prefix {:?} must be a path starting with /, such as \"/auth\"",
+                self.route_prefix
+            );
+        }
+        Ok(prefix)
+    }
+
+    /// Returns every secret value of the configuration
+    ///
+    /// Used to redact the values from the logs, should a provider or a
+    /// library error ever echo one of them.
+    ///
+    /// # Returns
+    ///
+    /// Returns the configured secrets, empty ones included
+    pub fn secret_values(&self) -> Vec<&str> {
+        let mut secrets: Vec<&SecretString> = self
+            .oauth
+            .values()
+            .map(|provider| &provider.client_secret)
+            .collect();
+        if let Some(encryption) = &self.encryption {
+            secrets.extend(&encryption.keys);
+        }
+        secrets.extend(&self.session.cookie_key);
+        secrets.extend(&self.session.redis_url);
+        secrets.extend(&self.admin.api_key);
+        secrets.extend(&self.metrics.bearer_token);
+        secrets.extend(&self.csrf_throttle.webhook_url);
+        if let Some(audit_webhook) = &self.audit_webhook {
+            secrets.extend([&audit_webhook.url, &audit_webhook.secret]);
+        }
+        if let Some(audit) = &self.audit {
+            secrets.push(&audit.user_id_salt);
+        }
+        if let Some(database) = &self.database {
+            secrets.push(&database.url);
+        }
+        if let Some(jwt) = &self.jwt {
+            secrets.extend(&jwt.secret);
+            secrets.extend(jwt.keys.iter().filter_map(|key| key.secret.as_ref()));
+        }
+        secrets.into_iter().map(SecretString::expose).collect()
+    }
+
+    /// Loads settings from a configuration source overridden by the environment
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The configuration file source
+    /// * `environment` - The environment source layered on top of the file
+    ///
+    /// # Returns
+    ///
     /// Returns a `Settings` instance with the loaded configuration
-    pub fn from_toml(path: &str) -> Self {
-        let config = Config::builder()
-            .add_source(File::with_name(path))
-            .build()
-            .unwrap();
-        config
+    fn load<S>(file: S, environment: Environment) -> Result<Self>
+    where
+        S: Source + Send + Sync + 'static,
+    {
+        let file_values = file.collect()?;
+        let env_keys: Vec<String> = environment.collect()?.into_keys().collect();
+        let file_sections = file_values.keys().cloned().collect();
+        let env_sections = env_keys
+            .iter()
+            .filter_map(|key| key.split('.').next().map(str::to_string))
+            .collect();
+
+        // Only file values are interpolated, the environment overrides them
+        let mut strings = Vec::new();
+        for (key, value) in file_values {
+            collect_strings(key, value.try_deserialize()?, &mut strings);
+        }
+        strings.retain(|(path, _)| {
+            let path = path.to_lowercase();
+            !env_keys.iter().any(|key| {
+                path.strip_prefix(key.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with(['.', '[']))
+            })
+        });
+
+        let mut builder = Config::builder().add_source(file).add_source(environment);
+        for (path, value) in interpolate_env_vars(strings)? {
+            builder = builder.set_override(path, value)?;
+        }
+        let settings: Self = builder
+            .build()?
             .try_deserialize()
-            .expect("Failed to deserialize settings")
+            .map_err(|e| eyre!("Failed to deserialize settings: {}", e))?;
+        Ok(Self {
+            file_sections,
+            env_sections,
+            ..settings
+        })
+    }
+
+    /// Returns which source supplied each top-level section
+    ///
+    /// # Returns
+    ///
+    /// Returns the source of every serialized section, keyed by section name
+    pub fn sources(&self) -> BTreeMap<String, ConfigSource> {
+        let sections = match serde_json::to_value(self) {
+            Ok(serde_json::Value::Object(sections)) => sections,
+            _ => return BTreeMap::new(),
+        };
+
+        sections
+            .into_iter()
+            .map(|(section, _)| {
+                let source = if self.file_sections.contains(&section) {
+                    ConfigSource::File
+                } else if self.env_sections.contains(&section) {
+                    ConfigSource::Environment
+                } else {
+                    ConfigSource::Default
+                };
+                (section, source)
+            })
+            .collect()
+    }
+}
+
+/// Replaces `${ENV_VAR}` references in string settings
+///
+/// # Arguments
+///
+/// * `strings` - Paths and values of the string settings
+///
+/// # Returns
+///
+/// Returns the paths and interpolated values of the settings referencing
+/// variables, or an error naming the first unset variable and the setting
+/// referencing it
+fn interpolate_env_vars(strings: Vec<(String, String)>) -> Result<Vec<(String, String)>> {
+    let mut interpolated = Vec::new();
+    for (path, value) in strings {
+        let replaced = interpolate(&value, |name| std::env::var(name).ok()).map_err(|name| {
+            match path
+                .strip_prefix("oauth.")
+                .and_then(|key| key.split_once('.'))
+            {
+                Some((provider, _)) => eyre!(
+                    "Environment variable {} referenced by provider {} ({}) is not set",
+                    name,
+                    provider,
+                    path
+                ),
+                None => eyre!(
+                    "Environment variable {} referenced by {} is not set",
+                    name,
+                    path
+                ),
+            }
+        })?;
+        if replaced != value {
+            interpolated.push((path, replaced));
+        }
+    }
+
+    Ok(interpolated)
+}
+
+/// Collects the string values of a configuration tree with their paths
+///
+/// # Arguments
+///
+/// * `path` - Path of `value` in the configuration
+/// * `value` - The configuration (sub)tree
+/// * `strings` - Collected paths and string values
+fn collect_strings(path: String, value: serde_json::Value, strings: &mut Vec<(String, String)>) {
+    match value {
+        serde_json::Value::String(value) => strings.push((path, value)),
+        serde_json::Value::Object(values) => {
+            for (key, value) in values {
+                let path = match path.is_empty() {
+                    true => key,
+                    false => format!("{}.{}", path, key),
+                };
+                collect_strings(path, value, strings);
+            }
+        }
+        serde_json::Value::Array(values) => {
+            for (index, value) in values.into_iter().enumerate() {
+                collect_strings(format!("{}[{}]", path, index), value, strings);
+            }
+        }
+        _ => {}
     }
 }
 
+/// Replaces the `${NAME}` references in a string
+///
+/// An unterminated `${` is kept as is.
+///
+/// # Arguments
+///
+/// * `value` - The string to interpolate
+/// * `lookup` - Returns the value of a variable, if set
+///
+/// # Returns
+///
+/// Returns the interpolated string, or the name of the first unset variable
+fn interpolate(
+    value: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> std::result::Result<String, String> {
+    let mut interpolated = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        let Some(length) = rest[start + 2..].find('}') else {
+            break;
+        };
+        let name = &rest[start + 2..start + 2 + length];
+        interpolated.push_str(&rest[..start]);
+        interpolated.push_str(&lookup(name).ok_or_else(|| name.to_string())?);
+        rest = &rest[start + 3 + length..];
+    }
+    interpolated.push_str(rest);
+
+    Ok(interpolated)
+}
+
+/// Source that supplied a top-level settings section
+///
+/// * `File` - The section was read from the configuration file
+/// * `Environment` - The section was absent from the file and set by environment variables
+/// * `Default` - The section was absent and holds its default value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConfigSource {
+    /// Read from the configuration file
+    File,
+    /// Absent from the file, set by environment variables
+    Environment,
+    /// Absent from the configuration, defaulted
+    Default,
+}
+
+/// Error in the OAuth provider configuration
+///
+/// Every variant names the provider whose section is invalid, so a typo
+/// in `Settings.toml` can be located from the startup log.
+///
+/// * `InvalidAuthUrl` - `auth_url` is not a valid URL
+/// * `InvalidTokenUrl` - `token_url` is not a valid URL
+/// * `InvalidRedirectUri` - `redirect_uri` is not a valid URL
+/// * `MissingRedirectUri` - `redirect_uri` is omitted and no `base_url` is set
+/// * `InvalidUserInfoUrl` - `user_info_url` is not a valid URL
+/// * `InvalidRevocationUrl` - `revocation_url` is not a valid URL
+/// * `InvalidDeviceAuthUrl` - `device_auth_url` is not a valid URL
+/// * `UnknownProvider` - No provider implementation is registered under the name or `type`
+/// * `InvalidProviderSettings` - A provider-specific setting is missing or invalid
+/// * `DiscoveryFailed` - The provider's endpoints could not be discovered
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigError {
+    /// `auth_url` is not a valid URL
+    InvalidAuthUrl {
+        /// Provider name
+        provider: String,
+        /// Why the URL was rejected
+        error: url::ParseError,
+    },
+    /// `token_url` is not a valid URL
+    InvalidTokenUrl {
+        /// Provider name
+        provider: String,
+        /// Why the URL was rejected
+        error: url::ParseError,
+    },
+    /// `redirect_uri` is not a valid URL
+    InvalidRedirectUri {
+        /// Provider name
+        provider: String,
+        /// Why the URL was rejected
+        error: url::ParseError,
+    },
+    /// `redirect_uri` is omitted and no `base_url` is set
+    MissingRedirectUri {
+        /// Provider name
+        provider: String,
+    },
+    /// `user_info_url` is not a valid URL
+    InvalidUserInfoUrl {
+        /// Provider name
+        provider: String,
+        /// Why the URL was rejected
+        error: url::ParseError,
+    },
+    /// `revocation_url` is not a valid URL
+    InvalidRevocationUrl {
+        /// Provider name
+        provider: String,
+        /// Why the URL was rejected
+        error: url::ParseError,
+    },
+    /// `device_auth_url` is not a valid URL
+    InvalidDeviceAuthUrl {
+        /// Provider name
+        provider: String,
+        /// Why the URL was rejected
+        error: url::ParseError,
+    },
+    /// No provider implementation is registered under the name
+    UnknownProvider {
+        /// Provider name
+        provider: String,
+    },
+    /// A provider-specific setting is missing or invalid
+    InvalidProviderSettings {
+        /// Provider name
+        provider: String,
+        /// Why the settings were rejected
+        reason: String,
+    },
+    /// The provider's endpoints could not be discovered
+    DiscoveryFailed {
+        /// Provider name
+        provider: String,
+        /// Why the discovery failed
+        reason: String,
+    },
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::InvalidAuthUrl { provider, error } => {
+                write!(f, "Invalid oauth.{}.auth_url: {}", provider, error)
+            }
+            ConfigError::InvalidTokenUrl { provider, error } => {
+                write!(f, "Invalid oauth.{}.token_url: {}", provider, error)
+            }
+            ConfigError::InvalidRedirectUri { provider, error } => {
+                write!(f, "Invalid oauth.{}.redirect_uri: {}", provider, error)
+            }
+            ConfigError::MissingRedirectUri { provider } => {
+                write!(
+                    f,
+                    "Missing oauth.{}.redirect_uri, set it or a top-level base_url",
+                    provider
+                )
+            }
+            ConfigError::InvalidUserInfoUrl { provider, error } => {
+                write!(f, "Invalid oauth.{}.user_info_url: {}", provider, error)
+            }
+            ConfigError::InvalidRevocationUrl { provider, error } => {
+                write!(f, "Invalid oauth.{}.revocation_url: {}", provider, error)
+            }
+            ConfigError::InvalidDeviceAuthUrl { provider, error } => {
+                write!(f, "Invalid oauth.{}.device_auth_url: {}", provider, error)
+            }
+            ConfigError::UnknownProvider { provider } => {
+                write!(f, "Unknown OAuth provider oauth.{}", provider)
+            }
+            ConfigError::InvalidProviderSettings { provider, reason } => {
+                write!(f, "Invalid oauth.{}: {}", provider, reason)
+            }
+            ConfigError::DiscoveryFailed { provider, reason } => {
+                write!(f, "Discovery failed for oauth.{}: {}", provider, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
 /// OAuth provider configuration structure
 ///
 /// Contains all the necessary configuration for a single OAuth provider
@@ -51,26 +610,1019 @@ impl Settings {
 ///
 /// # Fields
 ///
+/// * `provider_type` - Registered provider implementation to use, the section name by default
 /// * `client_id` - OAuth client ID from the provider
-/// * `client_secret` - OAuth client secret from the provider
-/// * `auth_url` - OAuth authorization endpoint URL
-/// * `token_url` - OAuth token exchange endpoint URL
-/// * `redirect_uri` - OAuth redirect URI (must match provider configuration)
-/// * `user_info_url` - Provider's user info endpoint URL
-#[derive(Debug, Serialize, Deserialize)]
+/// * `client_secret` - OAuth client secret from the provider, unused by Apple
+/// * `auth_url` - OAuth authorization endpoint URL, derived or discovered by some providers when omitted
+/// * `token_url` - OAuth token exchange endpoint URL, derived or discovered by some providers when omitted
+/// * `redirect_uri` - OAuth redirect URI (must match provider configuration), `{base_url}/callback` when omitted
+/// * `user_info_url` - Provider's user info endpoint URL, derived or discovered by some providers when omitted
+/// * `revocation_url` - RFC 7009 token revocation endpoint URL, tokens are not revoked when omitted
+/// * `device_auth_url` - RFC 8628 device authorization endpoint URL, the device flow is disabled when omitted
+/// * `pkce` - Whether PKCE (S256) is used, only disable for providers that reject it
+/// * `scopes` - Scopes requested in addition to the ones the provider requires
+/// * `extra_auth_params` - Extra query parameters of the authorization URL, replacing the provider's defaults of the same name
+/// * `team_id` - Apple developer team ID, Apple only
+/// * `key_id` - ID of the key signing the client secret, Apple only
+/// * `private_key_path` - Path of the PEM key signing the client secret, Apple only
+/// * `base_url` - Base URL of a self-hosted instance the endpoints are derived from, GitLab and Keycloak only
+/// * `issuer` - Authorization server the endpoints are derived from, Okta and OpenID Connect only, and the expected ID token issuer
+/// * `jwks_url` - Key set ID tokens are verified against, OpenID Connect, Google and Apple only, discovered for OpenID Connect when omitted
+/// * `realm` - Realm the endpoints are derived from with `base_url`, Keycloak only
+/// * `user_id_path` - Dotted path or JSON pointer of the user id in the user info response, generic only
+/// * `user_info_headers` - Extra headers sent with the user info request, generic only
+/// * `emails_url` - Endpoint listing the user's email addresses, `{user_info_url}/emails` when omitted, GitHub only
+/// * `shops` - Shops allowed to sign in, any shop when omitted, Shopify only
+/// * `require_verified_email` - Whether sign-ins with an email the provider flags as unverified are rejected
+/// * `require_all_scopes` - Whether sign-ins are rejected when the provider grants fewer scopes than requested
+/// * `id_field` - Whether users are identified by their stable id or their username, Discord and Twitter only
+/// * `auth_type` - How the client credentials are sent to the token endpoint, the provider's default when omitted
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OAuthSettings {
+    /// Registered provider implementation, `type` in the configuration
+    #[serde(default, rename = "type")]
+    pub provider_type: Option<String>,
     /// OAuth client ID
     pub client_id: String,
     /// OAuth client secret
-    pub client_secret: String,
+    #[serde(default)]
+    pub client_secret: SecretString,
     /// OAuth authorization URL
+    #[serde(default)]
     pub auth_url: String,
     /// OAuth token URL
+    #[serde(default)]
     pub token_url: String,
     /// OAuth redirect URI
+    #[serde(default)]
     pub redirect_uri: String,
     /// User info endpoint URL
+    #[serde(default)]
     pub user_info_url: String,
+    /// Token revocation endpoint URL
+    #[serde(default)]
+    pub revocation_url: Option<String>,
+    /// Device authorization endpoint URL
+    #[serde(default)]
+    pub device_auth_url: Option<String>,
+    /// Whether PKCE (S256) is used for this provider
+    #[serde(default = "default_pkce")]
+    pub pkce: bool,
+    /// Scopes requested in addition to the ones the provider requires
+    #[serde(default)]
+    pub scopes: Option<Vec<String>>,
+    /// Extra query parameters of the authorization URL
+    #[serde(default)]
+    pub extra_auth_params: Option<BTreeMap<String, String>>,
+    /// Apple developer team ID
+    #[serde(default)]
+    pub team_id: Option<String>,
+    /// ID of the key signing the Apple client secret
+    #[serde(default)]
+    pub key_id: Option<String>,
+    /// Path of the PEM key signing the Apple client secret
+    #[serde(default)]
+    pub private_key_path: Option<String>,
+    /// Base URL the endpoints are derived from when they are omitted
+    #[serde(default)]
+    pub base_url: Option<String>,
+    /// Authorization server the endpoints are derived from when they are omitted
+    #[serde(default)]
+    pub issuer: Option<String>,
+    /// Key set the ID tokens are verified against
+    #[serde(default)]
+    pub jwks_url: Option<String>,
+    /// Realm the endpoints are derived from together with `base_url`
+    #[serde(default)]
+    pub realm: Option<String>,
+    /// Location of the user id in the user info response
+    #[serde(default)]
+    pub user_id_path: Option<String>,
+    /// Extra headers sent with the user info request
+    #[serde(default)]
+    pub user_info_headers: Option<BTreeMap<String, String>>,
+    /// Endpoint listing the user's email addresses
+    #[serde(default)]
+    pub emails_url: Option<String>,
+    /// Shops allowed to sign in, every shop when omitted
+    #[serde(default)]
+    pub shops: Option<Vec<String>>,
+    /// Whether sign-ins with an unverified email are rejected
+    #[serde(default = "default_require_verified_email")]
+    pub require_verified_email: bool,
+    /// Whether sign-ins are rejected when a requested scope was not granted
+    #[serde(default)]
+    pub require_all_scopes: bool,
+    /// Field of the user info response identifying the user
+    #[serde(default)]
+    pub id_field: UserIdField,
+    /// How the client credentials are sent to the token endpoint
+    #[serde(default)]
+    pub auth_type: Option<TokenAuthType>,
+}
+
+impl Default for OAuthSettings {
+    fn default() -> Self {
+        Self {
+            provider_type: None,
+            client_id: String::new(),
+            client_secret: SecretString::default(),
+            auth_url: String::new(),
+            token_url: String::new(),
+            redirect_uri: String::new(),
+            user_info_url: String::new(),
+            revocation_url: None,
+            device_auth_url: None,
+            pkce: default_pkce(),
+            scopes: None,
+            extra_auth_params: None,
+            team_id: None,
+            key_id: None,
+            private_key_path: None,
+            base_url: None,
+            issuer: None,
+            jwks_url: None,
+            realm: None,
+            user_id_path: None,
+            user_info_headers: None,
+            emails_url: None,
+            shops: None,
+            require_verified_email: default_require_verified_email(),
+            require_all_scopes: false,
+            id_field: UserIdField::default(),
+            auth_type: None,
+        }
+    }
+}
+
+/// PKCE is enabled for every provider unless explicitly disabled
+fn default_pkce() -> bool {
+    true
+}
+
+/// Unverified emails are rejected unless explicitly allowed
+fn default_require_verified_email() -> bool {
+    true
+}
+
+/// Field identifying users of providers that also expose a username
+///
+/// Usernames can be changed by their owner at any time, so users are
+/// identified by their immutable id unless a deployment still relies on
+/// the username.
+///
+/// * `Id` - The provider's immutable user id
+/// * `Username` - The user's current username
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UserIdField {
+    /// Immutable user id
+    #[default]
+    Id,
+    /// Current username
+    Username,
+}
+
+/// How the client credentials are sent to the token endpoint
+///
+/// * `Basic` - In an HTTP Basic `Authorization` header (`client_secret_basic`)
+/// * `RequestBody` - As `client_id` and `client_secret` form fields (`client_secret_post`)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenAuthType {
+    /// HTTP Basic authentication
+    #[default]
+    Basic,
+    /// Credentials in the request body
+    RequestBody,
+}
+
+impl From<TokenAuthType> for oauth2::AuthType {
+    fn from(auth_type: TokenAuthType) -> Self {
+        match auth_type {
+            TokenAuthType::Basic => oauth2::AuthType::BasicAuth,
+            TokenAuthType::RequestBody => oauth2::AuthType::RequestBody,
+        }
+    }
+}
+
+/// Encryption settings for secrets persisted in the session store
+///
+/// Contains the AES-256-GCM key ring used to encrypt PKCE verifiers and
+/// tokens before they leave the process.
+///
+/// # Fields
+///
+/// * `keys` - Base64-encoded 32-byte keys, the first one is used for encryption
+/// * `allow_plaintext` - Accept unencrypted records written before encryption was enabled
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EncryptionSettings {
+    /// Base64-encoded 32-byte keys, the first one is used for encryption
+    pub keys: Vec<SecretString>,
+    /// Accept unencrypted records during a migration window
+    #[serde(default)]
+    pub allow_plaintext: bool,
+}
+
+/// Client attributes a pending OAuth flow is bound to
+///
+/// When enabled, the callback is rejected if it comes from a client whose
+/// fingerprint differs from the one that started the flow. Mobile clients
+/// frequently change IP mid-flow, so binding is off by default.
+///
+/// * `Off` - No binding
+/// * `Ip` - Bind to the client IP
+/// * `IpAndUa` - Bind to the client IP and user agent
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlowBinding {
+    /// No binding
+    #[default]
+    Off,
+    /// Bind to the client IP
+    Ip,
+    /// Bind to the client IP and user agent
+    IpAndUa,
+}
+
+/// Session settings
+///
+/// # Fields
+///
+/// * `cookie_key` - Base64-encoded key (64+ bytes) encrypting and
+///   authenticating the session cookie
+/// * `cookie` - Attributes of the session cookie
+/// * `store` - Backend keeping the session data
+/// * `capacity` - Maximum number of sessions kept by the in-memory store
+/// * `ttl_seconds` - Lifetime of an inactive session
+/// * `redis_url` - `redis://` or `rediss://` URL of the Redis server, required
+///   by the Redis store
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionSettings {
+    /// Base64-encoded key encrypting and authenticating the session cookie
+    pub cookie_key: Option<SecretString>,
+    /// Attributes of the session cookie
+    #[serde(default)]
+    pub cookie: CookieSettings,
+    /// Backend keeping the session data
+    #[serde(default)]
+    pub store: SessionStoreKind,
+    /// Maximum number of sessions kept by the in-memory store
+    #[serde(default = "default_session_capacity")]
+    pub capacity: u64,
+    /// Lifetime of an inactive session in seconds
+    #[serde(default = "default_session_ttl_seconds")]
+    pub ttl_seconds: u64,
+    /// URL of the Redis server, may carry its password
+    #[serde(default)]
+    pub redis_url: Option<SecretString>,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        Self {
+            cookie_key: None,
+            cookie: CookieSettings::default(),
+            store: SessionStoreKind::default(),
+            capacity: default_session_capacity(),
+            ttl_seconds: default_session_ttl_seconds(),
+            redis_url: None,
+        }
+    }
+}
+
+/// Default maximum number of sessions kept in memory
+fn default_session_capacity() -> u64 {
+    10_000
+}
+
+/// Default lifetime of an inactive session (1 hour)
+fn default_session_ttl_seconds() -> u64 {
+    3600
+}
+
+/// Backend keeping the session data
+///
+/// * `Memory` - Sessions are kept in process memory and lost on restart
+/// * `Redis` - Sessions are kept in Redis and shared between instances,
+///   requires the `redis` feature
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SessionStoreKind {
+    /// Keep sessions in process memory
+    #[default]
+    Memory,
+    /// Keep sessions in Redis
+    Redis,
+}
+
+/// Session cookie attributes
+///
+/// In strict mode the session cookie is `__Host-`-prefixed and sent with
+/// `SameSite=Strict`. The `__Host-` prefix requires `Secure`, `Path=/` and
+/// no `Domain`, so strict mode cannot be combined with a domain, a path,
+/// a custom name or `secure = false`.
+///
+/// # Fields
+///
+/// * `strict` - Use `__Host-` prefixed, `SameSite=Strict` cookies
+/// * `name` - Name of the cookie outside strict mode, `id` when unset
+/// * `secure` - Only send the cookie over HTTPS
+/// * `same_site` - `SameSite` policy of the cookie, `Lax` when unset
+/// * `http_only` - Hide the cookie from scripts
+/// * `domain` - Domain the cookie is scoped to, host-only when unset
+/// * `path` - Path the cookie is scoped to, `/` when unset
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CookieSettings {
+    /// Use `__Host-` prefixed, `SameSite=Strict` cookies
+    pub strict: bool,
+    /// Name of the cookie outside strict mode
+    pub name: Option<String>,
+    /// Only send the cookie over HTTPS
+    pub secure: bool,
+    /// `SameSite` policy of the cookie
+    pub same_site: Option<SameSitePolicy>,
+    /// Hide the cookie from scripts
+    pub http_only: bool,
+    /// Domain the cookie is scoped to, host-only when unset
+    pub domain: Option<String>,
+    /// Path the cookie is scoped to
+    pub path: Option<String>,
+}
+
+impl Default for CookieSettings {
+    fn default() -> Self {
+        Self {
+            strict: false,
+            name: None,
+            secure: true,
+            same_site: None,
+            http_only: true,
+            domain: None,
+            path: None,
+        }
+    }
+}
+
+/// `SameSite` policy of the session cookie
+///
+/// * `Strict` - Only sent with same-site requests
+/// * `Lax` - Also sent with top-level cross-site navigations
+/// * `None` - Sent with every request, requires `Secure`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SameSitePolicy {
+    /// Only sent with same-site requests
+    Strict,
+    /// Also sent with top-level cross-site navigations
+    Lax,
+    /// Sent with every request
+    None,
+}
+
+impl CookieSettings {
+    /// Rejects attribute combinations the browser would refuse
+    ///
+    /// # Returns
+    ///
+    /// Returns an error describing the first incompatible combination
+    pub fn validate(&self) -> Result<()> {
+        if self.strict && !self.secure {
+            bail!("session.cookie.strict requires session.cookie.secure, __Host- cookies must be Secure");
+        }
+        if self.strict && self.domain.is_some() {
+            bail!("session.cookie.strict cannot be combined with session.cookie.domain, __Host- cookies must not set a Domain");
+        }
+        if self.strict && self.path.as_deref().is_some_and(|path| path != "/") {
+            bail!("session.cookie.strict cannot be combined with session.cookie.path, __Host- cookies must use Path=/");
+        }
+        if self.strict && self.name.is_some() {
+            bail!("session.cookie.strict cannot be combined with session.cookie.name, strict cookies are always __Host- prefixed");
+        }
+        if self.strict
+            && self
+                .same_site
+                .is_some_and(|same_site| same_site != SameSitePolicy::Strict)
+        {
+            bail!("session.cookie.strict cannot be combined with a session.cookie.same_site other than \"strict\"");
+        }
+        if self.same_site == Some(SameSitePolicy::None) && !self.secure {
+            bail!("session.cookie.same_site = \"none\" requires session.cookie.secure, browsers reject SameSite=None cookies without Secure");
+        }
+        if let Some(name) = &self.name {
+            if name.is_empty()
+                || !name
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b))
+            {
+                bail!("session.cookie.name {:?} is not a valid cookie name", name);
+            }
+        }
+        if self
+            .path
+            .as_deref()
+            .is_some_and(|path| !path.starts_with('/'))
+        {
+            bail!("session.cookie.path must start with /");
+        }
+        Ok(())
+    }
+}
+
+/// Admin API settings
+///
+/// The admin routes are only mounted when an API key is configured, and
+/// every request must present it as `Authorization: Bearer <api_key>`.
+///
+/// # Fields
+///
+/// * `api_key` - Key required by the admin routes
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AdminSettings {
+    /// Key required by the admin routes
+    pub api_key: Option<SecretString>,
+}
+
+/// Metrics endpoint settings
+///
+/// `/metrics` is public unless a bearer token is configured, in which case
+/// every scrape must present it as `Authorization: Bearer <bearer_token>`.
+///
+/// # Fields
+///
+/// * `bearer_token` - Token required by the metrics endpoint
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MetricsSettings {
+    /// Token required by the metrics endpoint
+    pub bearer_token: Option<SecretString>,
+}
+
+/// In-memory login statistics settings
+///
+/// # Fields
+///
+/// * `windows_seconds` - Trailing windows reported by `/admin/stats`
+/// * `bucket_seconds` - Granularity of the aggregation buckets
+/// * `max_subjects_per_bucket` - Upper bound on distinct subjects kept per bucket
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StatsSettings {
+    /// Trailing windows reported by `/admin/stats` in seconds
+    pub windows_seconds: Vec<u64>,
+    /// Granularity of the aggregation buckets in seconds
+    pub bucket_seconds: u64,
+    /// Upper bound on distinct subjects kept per bucket
+    pub max_subjects_per_bucket: usize,
+}
+
+impl Default for StatsSettings {
+    fn default() -> Self {
+        Self {
+            windows_seconds: vec![3_600, 86_400, 604_800],
+            bucket_seconds: 300,
+            max_subjects_per_bucket: 10_000,
+        }
+    }
+}
+
+/// Audit webhook settings
+///
+/// Security events are delivered in batches signed with HMAC-SHA256 and
+/// retried with exponential backoff. Events are dropped when more than
+/// `queue_capacity` are waiting for delivery.
+///
+/// # Fields
+///
+/// * `url` - Endpoint receiving the events
+/// * `secret` - Key signing every delivery
+/// * `batch_size` - Maximum number of events per delivery
+/// * `flush_interval_ms` - How long to wait for a batch to fill
+/// * `queue_capacity` - Maximum number of events waiting for delivery
+/// * `max_attempts` - Delivery attempts per batch before giving up
+/// * `initial_backoff_ms` - Delay before the first retry, doubled on each retry
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditWebhookSettings {
+    /// Endpoint receiving the events
+    pub url: SecretString,
+    /// Key signing every delivery
+    pub secret: SecretString,
+    /// Maximum number of events per delivery
+    pub batch_size: usize,
+    /// How long to wait for a batch to fill in milliseconds
+    pub flush_interval_ms: u64,
+    /// Maximum number of events waiting for delivery
+    pub queue_capacity: usize,
+    /// Delivery attempts per batch before giving up
+    pub max_attempts: u32,
+    /// Delay before the first retry in milliseconds
+    pub initial_backoff_ms: u64,
+}
+
+impl Default for AuditWebhookSettings {
+    fn default() -> Self {
+        Self {
+            url: SecretString::default(),
+            secret: SecretString::default(),
+            batch_size: 50,
+            flush_interval_ms: 1_000,
+            queue_capacity: 1_000,
+            max_attempts: 5,
+            initial_backoff_ms: 500,
+        }
+    }
+}
+
+/// Destination of the login audit records
+///
+/// * `Log` - Each record is logged through `tracing` under the `audit` target
+/// * `File` - Each record is appended as a JSON line to a rotated file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditSinkKind {
+    /// Log the records
+    #[default]
+    Log,
+    /// Append the records to a file
+    File,
+}
+
+/// Login audit log settings
+///
+/// Every completed or failed sign-in and every sign-out is recorded. User
+/// ids are hashed with the salt, so the records do not contain emails.
+///
+/// # Fields
+///
+/// * `sink` - Where the records are written
+/// * `path` - File the records are appended to, required by the `file` sink
+/// * `max_file_bytes` - Size past which the file is rotated
+/// * `max_files` - Rotated files kept next to the current one
+/// * `user_id_salt` - Salt of the user id hashes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditSettings {
+    /// Where the records are written
+    pub sink: AuditSinkKind,
+    /// File the records are appended to
+    pub path: Option<String>,
+    /// Size past which the file is rotated in bytes
+    pub max_file_bytes: u64,
+    /// Rotated files kept next to the current one
+    pub max_files: usize,
+    /// Salt of the user id hashes
+    pub user_id_salt: SecretString,
+}
+
+impl Default for AuditSettings {
+    fn default() -> Self {
+        Self {
+            sink: AuditSinkKind::default(),
+            path: None,
+            max_file_bytes: 10 * 1024 * 1024,
+            max_files: 5,
+            user_id_salt: SecretString::default(),
+        }
+    }
+}
+
+/// User database settings
+///
+/// Requires the `database` feature. The tables are created by the bundled
+/// migrations, at startup when `auto_migrate` is on, or by `--migrate`.
+///
+/// # Fields
+///
+/// * `url` - Connection string, `sqlite://` or `postgres://`
+/// * `max_connections` - Maximum number of pooled connections
+/// * `auto_migrate` - Whether the server applies pending migrations at startup
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DatabaseSettings {
+    /// Connection string, `sqlite://` or `postgres://`
+    pub url: SecretString,
+    /// Maximum number of pooled connections
+    pub max_connections: u32,
+    /// Whether the server applies pending migrations at startup, see
+    /// [`DatabaseSettings::migrates_at_startup`] when omitted
+    pub auto_migrate: Option<bool>,
+}
+
+impl DatabaseSettings {
+    /// Returns whether the server applies pending migrations at startup
+    ///
+    /// # Returns
+    ///
+    /// Returns `auto_migrate` when set, otherwise `false` for Postgres,
+    /// whose schema is usually migrated by its operators, and `true` for
+    /// SQLite
+    pub fn migrates_at_startup(&self) -> bool {
+        self.auto_migrate.unwrap_or_else(|| {
+            let url = self.url.expose();
+            !(url.starts_with("postgres:") || url.starts_with("postgresql:"))
+        })
+    }
+}
+
+impl Default for DatabaseSettings {
+    fn default() -> Self {
+        Self {
+            url: SecretString::default(),
+            max_connections: 5,
+            auto_migrate: None,
+        }
+    }
+}
+
+/// OpenTelemetry trace export settings
+///
+/// Requires the `opentelemetry` feature. Spans are exported over OTLP/HTTP
+/// in protobuf.
+///
+/// # Fields
+///
+/// * `endpoint` - URL of the collector's OTLP traces endpoint
+/// * `service_name` - Service name the spans are reported under
+/// * `sampling_ratio` - Share of the traces sampled, between 0 and 1
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TelemetrySettings {
+    /// URL of the collector's OTLP traces endpoint
+    pub endpoint: String,
+    /// Service name the spans are reported under
+    pub service_name: String,
+    /// Share of the traces sampled, between 0 and 1
+    pub sampling_ratio: f64,
+}
+
+impl Default for TelemetrySettings {
+    fn default() -> Self {
+        Self {
+            endpoint: "http://localhost:4318/v1/traces".to_string(),
+            service_name: "oauth-server".to_string(),
+            sampling_ratio: 1.0,
+        }
+    }
+}
+
+/// Provider health settings
+///
+/// # Fields
+///
+/// * `error_window_seconds` - Window over which provider errors are counted
+/// * `probe_timeout_ms` - Timeout of a single reachability probe
+/// * `probe_interval_seconds` - Minimum interval between probes of a provider
+/// * `readiness_probe` - Whether `GET /health/ready` probes the providers
+/// * `required_providers` - Providers that must be reachable to be ready
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProviderHealthSettings {
+    /// Window over which provider errors are counted in seconds
+    pub error_window_seconds: u64,
+    /// Timeout of a single reachability probe in milliseconds
+    pub probe_timeout_ms: u64,
+    /// Minimum interval between probes of a provider in seconds
+    pub probe_interval_seconds: u64,
+    /// Whether `GET /health/ready` probes the providers' authorization endpoints
+    pub readiness_probe: bool,
+    /// Providers that must be reachable to be ready, every provider when unset
+    pub required_providers: Option<Vec<String>>,
+}
+
+impl Default for ProviderHealthSettings {
+    fn default() -> Self {
+        Self {
+            error_window_seconds: 900,
+            probe_timeout_ms: 2_000,
+            probe_interval_seconds: 30,
+            readiness_probe: false,
+            required_providers: None,
+        }
+    }
+}
+
+/// Background refresh of the provider tokens
+///
+/// When set, the tokens issued at sign-in are kept in the user store,
+/// encrypted with the `[encryption]` keys, and refreshed before they expire.
+/// Tokens whose refresh fails are left until the user signs in again.
+///
+/// # Fields
+///
+/// * `window_seconds` - Tokens expiring within this window are refreshed
+/// * `interval_seconds` - Interval between two scans of the stored tokens
+/// * `jitter_seconds` - Random delay added to each interval
+/// * `max_concurrency_per_provider` - Refreshes running at once against a provider
+/// * `batch_size` - Maximum number of tokens refreshed per scan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TokenRefreshSettings {
+    /// Tokens expiring within this window are refreshed, in seconds
+    pub window_seconds: u64,
+    /// Interval between two scans of the stored tokens in seconds
+    pub interval_seconds: u64,
+    /// Random delay added to each interval in seconds
+    pub jitter_seconds: u64,
+    /// Refreshes running at once against a provider
+    pub max_concurrency_per_provider: usize,
+    /// Maximum number of tokens refreshed per scan
+    pub batch_size: u64,
+}
+
+impl Default for TokenRefreshSettings {
+    fn default() -> Self {
+        Self {
+            window_seconds: 600,
+            interval_seconds: 60,
+            jitter_seconds: 15,
+            max_concurrency_per_provider: 4,
+            batch_size: 100,
+        }
+    }
+}
+
+/// Outgoing HTTP client settings
+///
+/// One client sends every request to the identity providers: token
+/// exchanges, refreshes, revocations, user info calls and health probes.
+/// It never follows redirects. Token and user info requests that fail to
+/// connect or get a 5xx response are retried with exponential backoff.
+///
+/// # Fields
+///
+/// * `connect_timeout_ms` - Timeout of establishing a connection
+/// * `timeout_ms` - Timeout of a whole request, including reading the response
+/// * `user_agent` - User-Agent header sent with every request
+/// * `max_retries` - Retries of a failed token or user info request, none when zero
+/// * `retry_backoff_ms` - Delay before the first retry, doubled for each next one
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HttpClientSettings {
+    /// Timeout of establishing a connection in milliseconds
+    pub connect_timeout_ms: u64,
+    /// Timeout of a whole request in milliseconds
+    pub timeout_ms: u64,
+    /// User-Agent header sent with every request
+    pub user_agent: String,
+    /// Retries of a failed token or user info request
+    pub max_retries: u32,
+    /// Delay before the first retry in milliseconds
+    pub retry_backoff_ms: u64,
+}
+
+impl Default for HttpClientSettings {
+    fn default() -> Self {
+        Self {
+            connect_timeout_ms: 5_000,
+            timeout_ms: 10_000,
+            user_agent: format!("oauth_server/{}", env!("CARGO_PKG_VERSION")),
+            max_retries: 2,
+            retry_backoff_ms: 200,
+        }
+    }
+}
+
+/// Signing algorithm of the issued JWTs
+///
+/// * `HS256` - HMAC-SHA256 with a shared secret
+/// * `RS256` - RSA-SHA256 with a PEM key pair
+/// * `ES256` - ECDSA P-256 SHA-256 with a PEM key pair
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum JwtAlgorithm {
+    /// HMAC-SHA256 with a shared secret
+    #[default]
+    Hs256,
+    /// RSA-SHA256 with a PEM key pair
+    Rs256,
+    /// ECDSA P-256 SHA-256 with a PEM key pair
+    Es256,
+}
+
+/// JWT issuance settings
+///
+/// When configured, the callback mints a JWT for the signed-in user and
+/// either returns it in the JSON body or redirects to `redirect_url` with
+/// the token in the `token` query parameter.
+///
+/// # Fields
+///
+/// * `algorithm` - Signing algorithm
+/// * `secret` - Shared secret, required for `HS256`
+/// * `private_key_path` - Path of the PEM private key, required for `RS256` and `ES256`
+/// * `public_key_path` - Path of the PEM public key, required for `RS256` and `ES256`
+/// * `previous_public_key_paths` - Paths of the PEM public keys of retired signing keys
+/// * `keys` - Key ring replacing the single key above, one key signing and all verifying
+/// * `active_key` - Id of the ring key signing tokens, the first signing key when unset
+/// * `ttl_seconds` - Lifetime of an issued token
+/// * `issuer` - Value of the `iss` claim
+/// * `redirect_url` - Frontend URL receiving the token, JSON response when unset
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JwtSettings {
+    /// Signing algorithm
+    pub algorithm: JwtAlgorithm,
+    /// Shared secret, required for `HS256`
+    pub secret: Option<SecretString>,
+    /// Path of the PEM private key, required for `RS256` and `ES256`
+    pub private_key_path: Option<String>,
+    /// Path of the PEM public key, required for `RS256` and `ES256`
+    pub public_key_path: Option<String>,
+    /// Paths of the PEM public keys of retired signing keys, still published in the JWKS
+    pub previous_public_key_paths: Vec<String>,
+    /// Key ring replacing the single key above, one key signing and all verifying
+    pub keys: Vec<JwtKeySettings>,
+    /// Id of the ring key signing tokens, the first signing key when unset
+    pub active_key: Option<String>,
+    /// Lifetime of an issued token in seconds
+    pub ttl_seconds: u64,
+    /// Value of the `iss` claim
+    pub issuer: String,
+    /// Frontend URL receiving the token, JSON response when unset
+    pub redirect_url: Option<String>,
+}
+
+impl Default for JwtSettings {
+    fn default() -> Self {
+        Self {
+            algorithm: JwtAlgorithm::default(),
+            secret: None,
+            private_key_path: None,
+            public_key_path: None,
+            previous_public_key_paths: Vec::new(),
+            keys: Vec::new(),
+            active_key: None,
+            ttl_seconds: 3_600,
+            issuer: "oauth_server".to_string(),
+            redirect_url: None,
+        }
+    }
+}
+
+/// Key of the JWT key ring
+///
+/// Keys with a secret or a private key can sign and must match
+/// `jwt.algorithm`. Keys with only a public key verify the tokens they
+/// signed before being retired and may be of another asymmetric algorithm.
+///
+/// # Fields
+///
+/// * `id` - Id of the key, set in the `kid` header of the tokens it signs
+/// * `secret` - Shared secret of an `HS256` key
+/// * `private_key_path` - Path of the PEM private key of a signing `RS256` or `ES256` key
+/// * `public_key_path` - Path of the PEM public key of an `RS256` or `ES256` key
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JwtKeySettings {
+    /// Id of the key, set in the `kid` header of the tokens it signs
+    pub id: String,
+    /// Shared secret of an `HS256` key
+    pub secret: Option<SecretString>,
+    /// Path of the PEM private key of a signing `RS256` or `ES256` key
+    pub private_key_path: Option<String>,
+    /// Path of the PEM public key of an `RS256` or `ES256` key
+    pub public_key_path: Option<String>,
+}
+
+/// Throttling settings for repeated CSRF/state validation failures
+///
+/// A client that fails CSRF/state validation `max_failures` times within
+/// `window_seconds` receives 429 on further callback attempts for
+/// `cooldown_seconds`.
+///
+/// # Fields
+///
+/// * `max_failures` - Failures within the window that trigger the throttle
+/// * `window_seconds` - Length of the failure counting window
+/// * `cooldown_seconds` - How long a throttled client is blocked
+/// * `max_tracked_clients` - Upper bound on the number of tracked clients
+/// * `alert_interval_seconds` - Minimum interval between security alerts
+/// * `webhook_url` - Optional URL receiving security events as JSON
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CsrfThrottleSettings {
+    /// Failures within the window that trigger the throttle
+    pub max_failures: u32,
+    /// Length of the failure counting window in seconds
+    pub window_seconds: u64,
+    /// How long a throttled client is blocked in seconds
+    pub cooldown_seconds: u64,
+    /// Upper bound on the number of tracked clients
+    pub max_tracked_clients: usize,
+    /// Minimum interval between security alerts in seconds
+    pub alert_interval_seconds: u64,
+    /// Optional URL receiving security events as JSON
+    pub webhook_url: Option<SecretString>,
+}
+
+impl Default for CsrfThrottleSettings {
+    fn default() -> Self {
+        Self {
+            max_failures: 5,
+            window_seconds: 300,
+            cooldown_seconds: 900,
+            max_tracked_clients: 10_000,
+            alert_interval_seconds: 60,
+            webhook_url: None,
+        }
+    }
+}
+
+/// CORS settings
+///
+/// # Fields
+///
+/// * `allowed_origins` - Origins allowed to call the server, `*` for any
+/// * `allowed_methods` - Methods allowed in cross-origin requests
+/// * `allow_credentials` - Whether cross-origin requests may carry cookies
+/// * `max_age_seconds` - How long browsers may cache a preflight response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CorsSettings {
+    /// Origins allowed to call the server, `*` for any
+    pub allowed_origins: Vec<String>,
+    /// Methods allowed in cross-origin requests
+    pub allowed_methods: Vec<String>,
+    /// Whether cross-origin requests may carry cookies
+    pub allow_credentials: bool,
+    /// How long browsers may cache a preflight response in seconds
+    pub max_age_seconds: Option<u64>,
+}
+
+impl Default for CorsSettings {
+    fn default() -> Self {
+        Self {
+            allowed_origins: Vec::new(),
+            allowed_methods: vec!["GET".to_string(), "POST".to_string()],
+            allow_credentials: false,
+            max_age_seconds: None,
+        }
+    }
+}
+
+/// Rate limit of the OAuth flow routes
+///
+/// `/authorize` and the callback are limited per client IP with a token
+/// bucket: a client may send `burst` requests at once, then
+/// `requests_per_minute` on average.
+///
+/// # Fields
+///
+/// * `requests_per_minute` - Sustained request rate allowed per client
+/// * `burst` - Requests a client may send at once
+/// * `max_tracked_clients` - Upper bound on the number of tracked clients
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitSettings {
+    /// Sustained request rate allowed per client
+    pub requests_per_minute: u32,
+    /// Requests a client may send at once
+    pub burst: u32,
+    /// Upper bound on the number of tracked clients
+    pub max_tracked_clients: usize,
+}
+
+impl Default for RateLimitSettings {
+    fn default() -> Self {
+        Self {
+            requests_per_minute: 60,
+            burst: 20,
+            max_tracked_clients: 10_000,
+        }
+    }
+}
+
+/// Redaction of secrets from the logs
+///
+/// Every configured secret, such as the client secrets, is always redacted
+/// from logged errors. Words looking like tokens are masked as well, unless
+/// disabled.
+///
+/// # Fields
+///
+/// * `mask_tokens` - Whether token-looking words are masked
+/// * `min_token_length` - Length from which a word mixing letters and digits looks like a token
+/// * `secrets` - Further values redacted from the logs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RedactionSettings {
+    /// Whether token-looking words are masked
+    pub mask_tokens: bool,
+    /// Length from which a word mixing letters and digits looks like a token
+    pub min_token_length: usize,
+    /// Further values redacted from the logs
+    pub secrets: Vec<SecretString>,
+}
+
+impl Default for RedactionSettings {
+    fn default() -> Self {
+        Self {
+            mask_tokens: true,
+            min_token_length: DEFAULT_MIN_TOKEN_LENGTH,
+            secrets: Vec::new(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -84,8 +1636,203 @@ mod tests {
     /// OAuth provider is configured.
     #[test]
     fn test_deserialize_settings() {
-        let path = "Settings.toml";
-        let settings = Settings::from_toml(path);
-        assert!(settings.oauth.len() > 0);
+        let path = "local.settings.toml";
+        let settings = Settings::from_toml(path).unwrap();
+        assert!(!settings.oauth.is_empty());
+    }
+
+    /// Tests that sections absent from the file are reported as defaulted
+    #[test]
+    fn test_settings_sources() {
+        let settings = Settings::from_toml("local.settings.toml").unwrap();
+        let sources = settings.sources();
+        assert_eq!(sources["port"], ConfigSource::File);
+        assert_eq!(sources["oauth"], ConfigSource::File);
+        assert_eq!(sources["session"], ConfigSource::Default);
+        assert!(!sources.contains_key("file_sections"));
+    }
+
+    /// Tests that the route prefix is normalized and rejected when it is not a path
+    #[test]
+    fn test_route_prefix() {
+        let mut settings = Settings::from_toml("local.settings.toml").unwrap();
+        assert_eq!(settings.route_prefix().unwrap(), "");
+
+        for (prefix, expected) in [("/", ""), ("/auth", "/auth"), ("/api/auth/", "/api/auth")] {
+            settings.route_prefix = prefix.to_string();
+            assert_eq!(settings.route_prefix().unwrap(), expected);
+        }
+        for prefix in ["auth", "/auth//v1", "/:tenant", "/auth/*rest", "/auth?x=1"] {
+            settings.route_prefix = prefix.to_string();
+            assert!(settings.route_prefix().is_err(), "{}", prefix);
+        }
+    }
+
+    /// Tests that environment variables override and are interpolated into the file
+    #[test]
+    fn test_environment_overrides() {
+        // Only the google section exists in local.settings.toml, which other
+        // tests load concurrently with the same environment
+        std::env::set_var("OAUTH__GOOGLE__CLIENT_SECRET", "env-secret");
+        std::env::set_var("SETTINGS_TEST_CLIENT_ID", "env-client-id");
+        std::env::set_var("SHUTDOWN_TIMEOUT_SECONDS", "10");
+        let load = |toml: &str| {
+            Settings::load(
+                File::from_str(toml, config::FileFormat::Toml),
+                Environment::default().separator(ENV_SEPARATOR),
+            )
+        };
+
+        let settings = load(
+            r#"
+            port = 4427
+            [oauth.google]
+            client_id = "${SETTINGS_TEST_CLIENT_ID}"
+            client_secret = "${SETTINGS_TEST_UNSET_SECRET}"
+            redirect_uri = "https://app.example.com/callback"
+            "#,
+        )
+        .unwrap();
+        let google = &settings.oauth["google"];
+        assert_eq!(google.client_id, "env-client-id");
+        assert_eq!(google.client_secret.expose(), "env-secret");
+        assert_eq!(google.redirect_uri, "https://app.example.com/callback");
+        assert_eq!(settings.shutdown_timeout_seconds, 10);
+        assert!(settings.serve_home_page);
+        assert_eq!(
+            settings.sources()["shutdown_timeout_seconds"],
+            ConfigSource::Environment
+        );
+        assert_eq!(settings.sources()["oauth"], ConfigSource::File);
+
+        let error = load(
+            r#"
+            port = 4427
+            [oauth.google]
+            client_id = "${SETTINGS_TEST_MISSING_CLIENT_ID}"
+            "#,
+        )
+        .unwrap_err()
+        .to_string();
+        assert!(
+            error.contains("SETTINGS_TEST_MISSING_CLIENT_ID"),
+            "{}",
+            error
+        );
+        assert!(error.contains("provider google"), "{}", error);
+    }
+
+    /// Tests that migrations run at startup by default for SQLite only
+    #[test]
+    fn test_database_auto_migrate() {
+        let database = |url: &str, auto_migrate| DatabaseSettings {
+            url: url.into(),
+            auto_migrate,
+            ..DatabaseSettings::default()
+        };
+        assert!(database("sqlite://users.db?mode=rwc", None).migrates_at_startup());
+        assert!(!database("postgres://db/oauth", None).migrates_at_startup());
+        assert!(!database("postgresql://db/oauth", None).migrates_at_startup());
+        assert!(database("postgres://db/oauth", Some(true)).migrates_at_startup());
+        assert!(!database("sqlite::memory:", Some(false)).migrates_at_startup());
+    }
+
+    /// Tests that `${NAME}` references are replaced and unterminated ones kept
+    #[test]
+    fn test_interpolate() {
+        let lookup = |name: &str| (name == "HOST").then(|| "example.com".to_string());
+
+        assert_eq!(
+            interpolate("https://${HOST}/callback", lookup),
+            Ok("https://example.com/callback".to_string())
+        );
+        assert_eq!(
+            interpolate("${HOST}${HOST}", lookup),
+            Ok("example.comexample.com".to_string())
+        );
+        assert_eq!(
+            interpolate("$HOST ${HOST", lookup),
+            Ok("$HOST ${HOST".to_string())
+        );
+        assert_eq!(
+            interpolate("${HOST}/${PORT}", lookup),
+            Err("PORT".to_string())
+        );
+    }
+
+    /// Tests that strict cookies reject attributes incompatible with `__Host-`
+    #[test]
+    fn test_cookie_settings_validation() {
+        assert!(CookieSettings::default().validate().is_ok());
+
+        let strict = CookieSettings {
+            strict: true,
+            ..CookieSettings::default()
+        };
+        assert!(strict.validate().is_ok());
+
+        let insecure = CookieSettings {
+            secure: false,
+            ..strict.clone()
+        };
+        assert!(insecure.validate().is_err());
+
+        let scoped = CookieSettings {
+            domain: Some("example.com".to_string()),
+            ..strict.clone()
+        };
+        assert!(scoped.validate().is_err());
+
+        for invalid in [
+            CookieSettings {
+                path: Some("/app".to_string()),
+                ..strict.clone()
+            },
+            CookieSettings {
+                name: Some("session".to_string()),
+                ..strict.clone()
+            },
+            CookieSettings {
+                same_site: Some(SameSitePolicy::Lax),
+                ..strict
+            },
+        ] {
+            assert!(invalid.validate().is_err(), "{:?}", invalid);
+        }
+    }
+
+    /// Tests that cross-site cookies must be Secure and names must be valid
+    #[test]
+    fn test_cookie_attribute_validation() {
+        let cross_site = CookieSettings {
+            same_site: Some(SameSitePolicy::None),
+            ..CookieSettings::default()
+        };
+        assert!(cross_site.validate().is_ok());
+
+        let insecure = CookieSettings {
+            secure: false,
+            ..cross_site
+        };
+        assert!(insecure.validate().is_err());
+
+        let development = CookieSettings {
+            secure: false,
+            ..CookieSettings::default()
+        };
+        assert!(development.validate().is_ok());
+
+        for name in ["", "my session", "a;b"] {
+            let invalid = CookieSettings {
+                name: Some(name.to_string()),
+                ..CookieSettings::default()
+            };
+            assert!(invalid.validate().is_err(), "{:?}", name);
+        }
+        let relative = CookieSettings {
+            path: Some("app".to_string()),
+            ..CookieSettings::default()
+        };
+        assert!(relative.validate().is_err());
     }
 }
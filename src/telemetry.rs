@@ -0,0 +1,231 @@
+//! Tracing setup and trace context propagation
+//!
+//! Logs are written by the `fmt` subscriber. Built with the `opentelemetry`
+//! feature and configured with a `[telemetry]` section, spans are also
+//! exported over OTLP, and the requests sent to the providers carry the W3C
+//! `traceparent` header so their side of the request joins the trace.
+
+use crate::settings::TelemetrySettings;
+use eyre::Result;
+use reqwest::{header::HeaderMap, StatusCode};
+use uuid::Uuid;
+
+/// Field of the provider call spans recording the provider's response status
+pub const STATUS_CODE_FIELD: &str = "http.response.status_code";
+
+/// Field of the request spans recording the OAuth flow being started or completed
+pub const FLOW_ID_FIELD: &str = "flow_id";
+
+/// Keeps the trace export running
+///
+/// Dropping the guard flushes the spans that were not exported yet and shuts
+/// the exporter down, so it must live until the server has shut down.
+///
+/// # Fields
+///
+/// * `provider` - The tracer provider exporting the spans, if any
+#[derive(Default)]
+pub struct TelemetryGuard {
+    /// The tracer provider exporting the spans
+    #[cfg(feature = "opentelemetry")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "opentelemetry")]
+        if let Some(provider) = self.provider.take() {
+            if let Err(e) = provider.shutdown() {
+                tracing::warn!("Failed to flush the exported spans: {}", e);
+            }
+        }
+    }
+}
+
+/// Sets up the global tracing subscriber
+///
+/// # Arguments
+///
+/// * `settings` - The `[telemetry]` section, logs only when unset
+///
+/// # Returns
+///
+/// Returns the guard of the trace export, or an error if the exporter cannot
+/// be built, the sampling ratio is not between 0 and 1, or the section is
+/// set without the `opentelemetry` feature
+pub fn init(settings: Option<&TelemetrySettings>) -> Result<TelemetryGuard> {
+    let Some(settings) = settings else {
+        tracing_subscriber::fmt::init();
+        return Ok(TelemetryGuard::default());
+    };
+
+    #[cfg(feature = "opentelemetry")]
+    {
+        export::init(settings)
+    }
+    #[cfg(not(feature = "opentelemetry"))]
+    {
+        let _ = settings;
+        eyre::bail!("[telemetry] requires building with the `opentelemetry` feature")
+    }
+}
+
+/// Returns the headers propagating the current span's trace context
+///
+/// # Returns
+///
+/// Returns the `traceparent` header, and `tracestate` when set, or no
+/// header when the current span is not exported
+pub fn trace_context_headers() -> HeaderMap {
+    #[allow(unused_mut)]
+    let mut headers = HeaderMap::new();
+    #[cfg(feature = "opentelemetry")]
+    export::inject_context(&mut headers);
+    headers
+}
+
+/// Records the status of a provider's response on the current span
+///
+/// Only spans declaring the `http.response.status_code` field record it.
+///
+/// # Arguments
+///
+/// * `status` - Status of the provider's response
+pub fn record_status(status: StatusCode) {
+    tracing::Span::current().record(STATUS_CODE_FIELD, status.as_u16());
+}
+
+/// Records the id of an OAuth flow on the current span
+///
+/// The authorize and callback requests of a flow record the same id, so
+/// their logs can be correlated. Only spans declaring the `flow_id` field
+/// record it.
+///
+/// # Arguments
+///
+/// * `flow_id` - Id of the flow prefixing the `state` parameter
+pub fn record_flow_id(flow_id: Uuid) {
+    tracing::Span::current().record(FLOW_ID_FIELD, tracing::field::display(flow_id));
+}
+
+/// OTLP export of the spans
+#[cfg(feature = "opentelemetry")]
+mod export {
+    use super::TelemetryGuard;
+    use crate::settings::TelemetrySettings;
+    use eyre::{bail, Result};
+    use opentelemetry::{
+        propagation::{Injector, TextMapPropagator},
+        trace::TracerProvider,
+    };
+    use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+    use opentelemetry_sdk::{
+        propagation::TraceContextPropagator,
+        trace::{Sampler, SdkTracerProvider},
+        Resource,
+    };
+    use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::{filter::LevelFilter, layer::SubscriberExt, util::SubscriberInitExt};
+
+    /// Sets up the subscriber logging and exporting the spans
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The `[telemetry]` section
+    ///
+    /// # Returns
+    ///
+    /// Returns the guard of the trace export, or an error if the exporter
+    /// cannot be built or the sampling ratio is not between 0 and 1
+    pub(super) fn init(settings: &TelemetrySettings) -> Result<TelemetryGuard> {
+        if !(0.0..=1.0).contains(&settings.sampling_ratio) {
+            bail!("telemetry.sampling_ratio must be between 0 and 1");
+        }
+
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(&settings.endpoint)
+            .build()?;
+        // Requests continuing a sampled trace are always sampled
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+                settings.sampling_ratio,
+            ))))
+            .with_resource(
+                Resource::builder()
+                    .with_service_name(settings.service_name.clone())
+                    .build(),
+            )
+            .build();
+
+        tracing_subscriber::registry()
+            .with(LevelFilter::INFO)
+            .with(tracing_subscriber::fmt::layer())
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("oauth_server")))
+            .try_init()?;
+
+        Ok(TelemetryGuard {
+            provider: Some(provider),
+        })
+    }
+
+    /// Writes the propagated trace context into request headers
+    struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+    impl Injector for HeaderInjector<'_> {
+        fn set(&mut self, key: &str, value: String) {
+            if let (Ok(name), Ok(value)) = (
+                HeaderName::from_bytes(key.as_bytes()),
+                HeaderValue::from_str(&value),
+            ) {
+                self.0.insert(name, value);
+            }
+        }
+    }
+
+    /// Injects the current span's trace context into request headers
+    ///
+    /// # Arguments
+    ///
+    /// * `headers` - Headers of the outgoing request
+    pub(super) fn inject_context(headers: &mut HeaderMap) {
+        TraceContextPropagator::new().inject_context(
+            &tracing::Span::current().context(),
+            &mut HeaderInjector(headers),
+        );
+    }
+}
+
+#[cfg(all(test, feature = "opentelemetry"))]
+mod tests {
+    use super::*;
+    use opentelemetry::trace::{TraceContextExt, TracerProvider};
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    /// Tests that the trace context of exported spans is propagated, and
+    /// that nothing is sent outside of them
+    #[test]
+    fn test_trace_context_headers() {
+        assert!(trace_context_headers().is_empty());
+
+        let provider = SdkTracerProvider::builder().build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let span = tracing::info_span!("token_exchange");
+        let _entered = span.enter();
+        let headers = trace_context_headers();
+        let trace_id = span.context().span().span_context().trace_id();
+        let traceparent = headers["traceparent"].to_str().unwrap();
+        assert!(
+            traceparent.starts_with(&format!("00-{}-", trace_id)),
+            "{}",
+            traceparent
+        );
+    }
+}
@@ -0,0 +1,725 @@
+//! Mock identity provider for integration tests
+//!
+//! Enabled by the `test-utils` feature. `MockIdp` serves the authorization,
+//! token and user info endpoints of a provider on a local port, and
+//! `MockProviderFactory` builds the `MockProvider` signing in through them,
+//! so the whole authorize → callback flow runs without a real provider.
+//! `MockApi` stubs the endpoints of a built-in provider instead, answering
+//! each route from a handler and recording the requests it receives.
+
+use crate::{
+    app::build_oauth_providers,
+    primitives::{ProviderContext, UserInfo},
+    providers::ProviderRegistry,
+    settings::OAuthSettings,
+    traits::{request_error, OAuthProvider, OAuthProviderFactory},
+    types::{HttpClient, OAuthClient},
+};
+use axum::{
+    async_trait,
+    body::Bytes,
+    extract::{Query, State},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode, Uri},
+    response::{IntoResponse, Redirect, Response},
+    routing::{get, on, post, MethodFilter},
+    Form, Json, Router,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use eyre::{eyre, Result};
+use reqwest::Url;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Authorization code issued by the mock provider
+pub const MOCK_CODE: &str = "mock-code";
+
+/// Access token issued by the mock provider
+pub const MOCK_ACCESS_TOKEN: &str = "mock-access-token";
+
+/// Refresh token issued by the mock provider
+pub const MOCK_REFRESH_TOKEN: &str = "mock-refresh-token";
+
+/// Identifier of the user signed in by the mock provider
+pub const MOCK_USER_ID: &str = "mock-user";
+
+/// Email address of the user signed in by the mock provider
+pub const MOCK_EMAIL: &str = "mock-user@example.com";
+
+/// Requests received by the mock provider's token endpoint
+///
+/// # Fields
+///
+/// * `code` - Authorization code of the last exchange
+/// * `code_verifier` - PKCE verifier presented with the last exchange, if any
+/// * `client_id` - Client the code was exchanged by, from the form or the Basic credentials
+/// * `traceparent` - W3C trace context the request was sent with, if any
+#[derive(Debug, Clone, Default)]
+pub struct TokenRequest {
+    /// Authorization code of the last exchange
+    pub code: String,
+    /// PKCE verifier presented with the last exchange
+    pub code_verifier: Option<String>,
+    /// Client the code was exchanged by
+    pub client_id: Option<String>,
+    /// W3C trace context the request was sent with
+    pub traceparent: Option<String>,
+}
+
+/// Identity provider served on a local port
+///
+/// The authorization endpoint redirects straight back to the requested
+/// `redirect_uri` with `MOCK_CODE`, the token endpoint only exchanges that
+/// code, and the user info endpoint only accepts `MOCK_ACCESS_TOKEN`.
+///
+/// # Fields
+///
+/// * `url` - Base URL of the mock provider
+/// * `token_requests` - Requests received by the token endpoint
+pub struct MockIdp {
+    /// Base URL of the mock provider
+    url: String,
+    /// Requests received by the token endpoint
+    token_requests: Arc<Mutex<Vec<TokenRequest>>>,
+}
+
+impl MockIdp {
+    /// Starts the mock provider on a random local port
+    ///
+    /// The provider runs on a background task until the runtime shuts down.
+    ///
+    /// # Returns
+    ///
+    /// Returns the running `MockIdp`, or an error if no port could be bound
+    pub async fn start() -> Result<Self> {
+        let token_requests = Arc::new(Mutex::new(Vec::new()));
+        let router = Router::new()
+            .route("/authorize", get(authorize))
+            .route("/token", post(token))
+            .route("/userinfo", get(userinfo))
+            .with_state(Arc::clone(&token_requests));
+
+        Ok(Self {
+            url: serve(router).await?,
+            token_requests,
+        })
+    }
+
+    /// Returns the base URL of the mock provider
+    ///
+    /// # Returns
+    ///
+    /// Returns the URL, without a trailing slash
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Returns the settings of a provider section using the mock provider
+    ///
+    /// # Arguments
+    ///
+    /// * `redirect_uri` - The callback URL of the server under test
+    ///
+    /// # Returns
+    ///
+    /// Returns the `OAuthSettings` pointing at the mock provider's endpoints
+    pub fn settings(&self, redirect_uri: &str) -> OAuthSettings {
+        OAuthSettings {
+            client_id: "mock-client".to_string(),
+            client_secret: "mock-secret".into(),
+            auth_url: format!("{}/authorize", self.url),
+            token_url: format!("{}/token", self.url),
+            redirect_uri: redirect_uri.to_string(),
+            user_info_url: format!("{}/userinfo", self.url),
+            ..OAuthSettings::default()
+        }
+    }
+
+    /// Returns the requests received by the token endpoint
+    ///
+    /// # Returns
+    ///
+    /// Returns the requests, oldest first
+    pub fn token_requests(&self) -> Vec<TokenRequest> {
+        self.token_requests.lock().unwrap().clone()
+    }
+}
+
+/// Request received by a `MockApi`
+///
+/// # Fields
+///
+/// * `path` - Path the request was sent to
+/// * `query` - Query parameters of the request
+/// * `headers` - Headers of the request
+/// * `body` - Raw body of the request
+#[derive(Debug, Clone)]
+pub struct ApiRequest {
+    /// Path the request was sent to
+    pub path: String,
+    /// Query parameters of the request
+    pub query: HashMap<String, String>,
+    /// Headers of the request
+    pub headers: HeaderMap,
+    /// Raw body of the request
+    pub body: Bytes,
+}
+
+impl ApiRequest {
+    /// Returns a header of the request
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - Name of the header
+    ///
+    /// # Returns
+    ///
+    /// Returns the header's value, or `None` if it is missing or not text
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).and_then(|value| value.to_str().ok())
+    }
+
+    /// Returns the parameters of a form encoded body
+    ///
+    /// # Returns
+    ///
+    /// Returns the parameters by name
+    pub fn form(&self) -> HashMap<String, String> {
+        url::form_urlencoded::parse(&self.body)
+            .into_owned()
+            .collect()
+    }
+
+    /// Returns the JSON body of the request
+    ///
+    /// # Returns
+    ///
+    /// Returns the body, or `Value::Null` if it is not JSON
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.body).unwrap_or_default()
+    }
+}
+
+/// Provider API served on a local port
+///
+/// Each route answers with the status and JSON body its handler builds from
+/// the request, so tests can stub the token and user info endpoints of a
+/// built-in provider and check what the provider sent to them.
+///
+/// # Fields
+///
+/// * `url` - Base URL of the mock API
+/// * `requests` - Requests received by the routes
+pub struct MockApi {
+    /// Base URL of the mock API
+    url: String,
+    /// Requests received by the routes
+    requests: Arc<Mutex<Vec<ApiRequest>>>,
+}
+
+/// Routes of a `MockApi` that has not started yet
+///
+/// # Fields
+///
+/// * `router` - Routes answering from their handlers
+/// * `requests` - Requests received by the routes, shared with the `MockApi`
+#[derive(Default)]
+pub struct MockApiBuilder {
+    /// Routes answering from their handlers
+    router: Router,
+    /// Requests received by the routes
+    requests: Arc<Mutex<Vec<ApiRequest>>>,
+}
+
+impl MockApiBuilder {
+    /// Answers `GET` requests to a path
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Route path, which may capture segments like `/users/:id`
+    /// * `respond` - Builds the status and JSON body from the request
+    ///
+    /// # Returns
+    ///
+    /// Returns the builder with the route added
+    pub fn get<F>(self, path: &str, respond: F) -> Self
+    where
+        F: Fn(&ApiRequest) -> (StatusCode, serde_json::Value) + Send + Sync + 'static,
+    {
+        self.route(MethodFilter::GET, path, respond)
+    }
+
+    /// Answers `POST` requests to a path
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Route path, which may capture segments like `/users/:id`
+    /// * `respond` - Builds the status and JSON body from the request
+    ///
+    /// # Returns
+    ///
+    /// Returns the builder with the route added
+    pub fn post<F>(self, path: &str, respond: F) -> Self
+    where
+        F: Fn(&ApiRequest) -> (StatusCode, serde_json::Value) + Send + Sync + 'static,
+    {
+        self.route(MethodFilter::POST, path, respond)
+    }
+
+    /// Adds a route recording the requests it answers
+    ///
+    /// # Arguments
+    ///
+    /// * `method` - Methods the route answers
+    /// * `path` - Route path
+    /// * `respond` - Builds the status and JSON body from the request
+    ///
+    /// # Returns
+    ///
+    /// Returns the builder with the route added
+    fn route<F>(mut self, method: MethodFilter, path: &str, respond: F) -> Self
+    where
+        F: Fn(&ApiRequest) -> (StatusCode, serde_json::Value) + Send + Sync + 'static,
+    {
+        let requests = Arc::clone(&self.requests);
+        let respond = Arc::new(respond);
+        let handler = move |uri: Uri, headers: HeaderMap, body: Bytes| {
+            let requests = Arc::clone(&requests);
+            let respond = Arc::clone(&respond);
+            async move {
+                let request = ApiRequest {
+                    path: uri.path().to_string(),
+                    query: url::form_urlencoded::parse(uri.query().unwrap_or_default().as_bytes())
+                        .into_owned()
+                        .collect(),
+                    headers,
+                    body,
+                };
+                let (status, body) = respond(&request);
+                requests.lock().unwrap().push(request);
+                (status, Json(body))
+            }
+        };
+        self.router = self.router.route(path, on(method, handler));
+        self
+    }
+
+    /// Starts the mock API on a random local port
+    ///
+    /// The API runs on a background task until the runtime shuts down.
+    ///
+    /// # Returns
+    ///
+    /// Returns the running `MockApi`, or an error if no port could be bound
+    pub async fn start(self) -> Result<MockApi> {
+        Ok(MockApi {
+            url: serve(self.router).await?,
+            requests: self.requests,
+        })
+    }
+}
+
+impl MockApi {
+    /// Returns a builder for the routes of a mock API
+    ///
+    /// # Returns
+    ///
+    /// Returns an empty `MockApiBuilder`
+    pub fn builder() -> MockApiBuilder {
+        MockApiBuilder::default()
+    }
+
+    /// Returns the base URL of the mock API
+    ///
+    /// # Returns
+    ///
+    /// Returns the URL, without a trailing slash
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Returns the URL of an endpoint of the mock API
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path of the endpoint, starting with a slash
+    ///
+    /// # Returns
+    ///
+    /// Returns the endpoint's URL
+    pub fn endpoint(&self, path: &str) -> String {
+        format!("{}{}", self.url, path)
+    }
+
+    /// Returns the settings of a provider section using the mock API
+    ///
+    /// # Arguments
+    ///
+    /// * `auth_path` - Path of the authorization endpoint
+    /// * `token_path` - Path of the token endpoint
+    /// * `user_info_path` - Path of the user info endpoint
+    ///
+    /// # Returns
+    ///
+    /// Returns the `OAuthSettings` of the `mock-client` client pointing at
+    /// the mock API's endpoints
+    pub fn settings(
+        &self,
+        auth_path: &str,
+        token_path: &str,
+        user_info_path: &str,
+    ) -> OAuthSettings {
+        OAuthSettings {
+            client_id: "mock-client".to_string(),
+            client_secret: "mock-secret".into(),
+            auth_url: self.endpoint(auth_path),
+            token_url: self.endpoint(token_path),
+            user_info_url: self.endpoint(user_info_path),
+            ..OAuthSettings::default()
+        }
+    }
+
+    /// Returns the requests received by the mock API
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - Path the requests were sent to
+    ///
+    /// # Returns
+    ///
+    /// Returns the requests sent to the path, oldest first
+    pub fn requests(&self, path: &str) -> Vec<ApiRequest> {
+        self.requests
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| request.path == path)
+            .cloned()
+            .collect()
+    }
+
+    /// Builds a built-in provider the way the server does
+    ///
+    /// The redirect URI defaults to the mock API's `/callback`, and the
+    /// endpoints are taken from `settings`, usually built by `settings`.
+    ///
+    /// # Arguments
+    ///
+    /// * `provider_type` - Name of the built-in provider
+    /// * `settings` - Settings of the provider section
+    ///
+    /// # Returns
+    ///
+    /// Returns the provider, or the configuration error building it
+    pub async fn provider(
+        &self,
+        provider_type: &str,
+        settings: OAuthSettings,
+    ) -> Result<Arc<dyn OAuthProvider>> {
+        self.provider_with_client(&HttpClient::default(), provider_type, settings)
+            .await
+    }
+
+    /// Builds a built-in provider sending its requests through a client
+    ///
+    /// # Arguments
+    ///
+    /// * `http_client` - The client the provider sends its requests with
+    /// * `provider_type` - Name of the built-in provider
+    /// * `settings` - Settings of the provider section
+    ///
+    /// # Returns
+    ///
+    /// Returns the provider, or the configuration error building it
+    pub async fn provider_with_client(
+        &self,
+        http_client: &HttpClient,
+        provider_type: &str,
+        settings: OAuthSettings,
+    ) -> Result<Arc<dyn OAuthProvider>> {
+        let base_url = Url::parse(&self.url)?;
+        let mut providers = build_oauth_providers(
+            http_client,
+            &ProviderRegistry::with_builtins(),
+            &HashMap::from([(provider_type.to_string(), settings)]),
+            Some(&base_url),
+            "",
+            true,
+        )
+        .await?;
+        providers
+            .remove(provider_type)
+            .ok_or_else(|| eyre!("Provider {} was not built", provider_type))
+    }
+
+    /// Starts a mock API whose responses never finish
+    ///
+    /// Every request is answered with the headers of a `200 OK` JSON
+    /// response and the first byte of its body, then the connection stalls,
+    /// so tests can check how a timeout while reading a body is reported.
+    ///
+    /// # Returns
+    ///
+    /// Returns the running `MockApi`, which records no requests, or an
+    /// error if no port could be bound
+    pub async fn stalled() -> Result<Self> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let url = format!("http://{}", listener.local_addr()?);
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut request = [0; 4096];
+                    let _ = stream.read(&mut request).await;
+                    let _ = stream
+                        .write_all(
+                            b"HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: 1024\r\n\r\n{",
+                        )
+                        .await;
+                    std::future::pending::<()>().await;
+                });
+            }
+        });
+
+        Ok(Self {
+            url,
+            requests: Arc::default(),
+        })
+    }
+}
+
+/// Serves a router on a random local port
+///
+/// # Arguments
+///
+/// * `router` - Routes to serve on a background task
+///
+/// # Returns
+///
+/// Returns the base URL the router is served at, or an error if no port
+/// could be bound
+async fn serve(router: Router) -> Result<String> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let url = format!("http://{}", listener.local_addr()?);
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    Ok(url)
+}
+
+/// Redirects back to the client with the authorization code
+///
+/// # Arguments
+///
+/// * `params` - Query parameters of the authorization request
+///
+/// # Returns
+///
+/// Returns the redirect to the `redirect_uri`, or `400 Bad Request` if it
+/// is missing or invalid
+async fn authorize(Query(params): Query<HashMap<String, String>>) -> Response {
+    let Some(mut redirect_uri) = params
+        .get("redirect_uri")
+        .and_then(|redirect_uri| Url::parse(redirect_uri).ok())
+    else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    redirect_uri
+        .query_pairs_mut()
+        .append_pair("code", MOCK_CODE)
+        .append_pair("state", params.get("state").map_or("", String::as_str));
+    Redirect::to(redirect_uri.as_str()).into_response()
+}
+
+/// Exchanges `MOCK_CODE` for the mock tokens
+///
+/// # Arguments
+///
+/// * `token_requests` - Requests received so far, the request is recorded
+/// * `headers` - Headers of the token request
+/// * `form` - Form parameters of the token request
+///
+/// # Returns
+///
+/// Returns the token response, or an `invalid_grant` error for any other code
+async fn token(
+    State(token_requests): State<Arc<Mutex<Vec<TokenRequest>>>>,
+    headers: HeaderMap,
+    Form(form): Form<HashMap<String, String>>,
+) -> Response {
+    let basic_client_id = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|credentials| STANDARD.decode(credentials).ok())
+        .and_then(|credentials| {
+            let credentials = String::from_utf8(credentials).ok()?;
+            Some(credentials.split_once(':')?.0.to_string())
+        });
+    let code = form.get("code").cloned().unwrap_or_default();
+    token_requests.lock().unwrap().push(TokenRequest {
+        code: code.clone(),
+        code_verifier: form.get("code_verifier").cloned(),
+        client_id: form.get("client_id").cloned().or(basic_client_id),
+        traceparent: headers
+            .get("traceparent")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string),
+    });
+
+    if code != MOCK_CODE {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": "invalid_grant" })),
+        )
+            .into_response();
+    }
+
+    Json(serde_json::json!({
+        "access_token": MOCK_ACCESS_TOKEN,
+        "token_type": "bearer",
+        "refresh_token": MOCK_REFRESH_TOKEN,
+        "expires_in": 3600,
+    }))
+    .into_response()
+}
+
+/// Returns the mock user to requests bearing `MOCK_ACCESS_TOKEN`
+///
+/// # Arguments
+///
+/// * `headers` - Headers of the user info request
+///
+/// # Returns
+///
+/// Returns the user, or `401 Unauthorized` for any other token
+async fn userinfo(headers: HeaderMap) -> Response {
+    let expected = format!("Bearer {}", MOCK_ACCESS_TOKEN);
+    if headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        != Some(&expected)
+    {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    Json(serde_json::json!({
+        "sub": MOCK_USER_ID,
+        "email": MOCK_EMAIL,
+        "name": "Mock User",
+    }))
+    .into_response()
+}
+
+/// Provider signing in through a `MockIdp`
+///
+/// # Fields
+///
+/// * `client` - HTTP client for making API requests
+/// * `oauth_client` - Configured OAuth 2.0 client
+/// * `user_info_url` - The mock provider's user info endpoint URL
+pub struct MockProvider {
+    /// HTTP client for API requests
+    client: HttpClient,
+    /// Configured OAuth client
+    oauth_client: OAuthClient,
+    /// Mock user info endpoint URL
+    user_info_url: Url,
+}
+
+#[async_trait]
+impl OAuthProvider for MockProvider {
+    /// Returns a reference to the OAuth client for the mock provider
+    ///
+    /// # Returns
+    ///
+    /// Returns a reference to the configured `OAuthClient` instance
+    fn get_oauth_client(&self) -> &OAuthClient {
+        &self.oauth_client
+    }
+
+    /// Returns the shared HTTP client
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient {
+        &self.client
+    }
+
+    /// Returns the OAuth scopes requested from the mock provider
+    ///
+    /// # Returns
+    ///
+    /// Returns the `profile` scope
+    fn get_scopes(&self) -> Vec<String> {
+        vec!["profile".to_string()]
+    }
+
+    /// Fetches the mock user from the mock provider's user info endpoint
+    ///
+    /// # Arguments
+    ///
+    /// * `access_token` - The access token issued by the mock provider
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the mock user, or an error if
+    /// the token was rejected
+    async fn get_user_info(&self, access_token: &str) -> Result<UserInfo> {
+        let request = self
+            .client
+            .get(self.user_info_url.as_str())
+            .bearer_auth(access_token);
+        let response = self
+            .client
+            .send(request)
+            .await
+            .map_err(|e| request_error("Failed to get user info from the mock provider", e))?;
+        if !response.status().is_success() {
+            return Err(eyre!(
+                "Failed to get user info from the mock provider: {}",
+                response.status()
+            ));
+        }
+
+        let user_data: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| request_error("Failed to get user info from the mock provider", e))?;
+        let field = |name: &str| user_data[name].as_str().map(str::to_string);
+        Ok(UserInfo {
+            id: field("sub").ok_or_else(|| eyre!("No sub field in mock user info response"))?,
+            provider: "mock".to_string(),
+            email: field("email"),
+            name: field("name"),
+            email_verified: Some(true),
+            raw: Some(user_data),
+            ..UserInfo::default()
+        })
+    }
+}
+
+/// Factory for creating `MockProvider` instances
+///
+/// Register it under the name of the section configured with
+/// `MockIdp::settings`.
+pub struct MockProviderFactory;
+
+impl OAuthProviderFactory for MockProviderFactory {
+    /// Creates a new mock provider instance
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The provider's OAuth client and endpoints
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created mock provider
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>> {
+        Ok(Arc::new(MockProvider {
+            client: Arc::clone(ctx.http_client),
+            oauth_client: ctx.oauth_client,
+            user_info_url: ctx.user_info_url,
+        }))
+    }
+}
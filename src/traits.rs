@@ -1,9 +1,144 @@
 use async_trait::async_trait;
-use eyre::Result;
-use reqwest::Url;
-use std::sync::Arc;
+use eyre::{eyre, Result};
+use oauth2::{
+    AccessToken, AuthorizationCode, DeviceAuthorizationResponse, DeviceCodeErrorResponse,
+    DeviceCodeErrorResponseType, EmptyExtraDeviceAuthorizationFields, ErrorResponseType,
+    HttpClientError, PkceCodeVerifier, RefreshToken, RequestTokenError, Scope,
+    StandardErrorResponse, StandardRevocableToken,
+};
+use reqwest::{header::HeaderMap, Url};
+use std::{collections::BTreeMap, fmt, sync::Arc};
 
-use crate::{primitives::UserInfo, types::OAuthClient};
+use crate::{
+    oidc::IdTokenVerifier,
+    primitives::{
+        DeviceAuthorization, DevicePoll, ProviderContext, ProviderDisplay, ProviderEndpoints,
+        TokenDetails, UserInfo,
+    },
+    providers::{send_token_request, TokenRequestBody},
+    settings::{OAuthSettings, TokenAuthType},
+    types::{HttpClient, OAuthClient, OAuthTokenResponse},
+};
+
+/// Grant type of the RFC 8628 device access token request
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// Error returned by providers that cannot refresh access tokens
+///
+/// Handlers downcast to this error to tell an unsupported refresh apart
+/// from a refresh the provider rejected.
+#[derive(Debug)]
+pub struct RefreshUnsupported;
+
+impl fmt::Display for RefreshUnsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Provider does not support refresh tokens")
+    }
+}
+
+impl std::error::Error for RefreshUnsupported {}
+
+/// Error response returned by a provider's token endpoint
+///
+/// The default code exchange returns it when the provider answers with an
+/// RFC 6749 error response, so handlers can pass the provider's reason on
+/// instead of a generic failure.
+///
+/// # Fields
+///
+/// * `error` - The error code, e.g. `invalid_grant`
+/// * `error_description` - Human-readable description of the error, if provided
+#[derive(Debug)]
+pub struct TokenExchangeError {
+    /// The error code
+    pub error: String,
+    /// Human-readable description of the error
+    pub error_description: Option<String>,
+}
+
+impl fmt::Display for TokenExchangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.error_description {
+            Some(error_description) => write!(f, "{}: {}", self.error, error_description),
+            None => f.write_str(&self.error),
+        }
+    }
+}
+
+impl std::error::Error for TokenExchangeError {}
+
+/// Error returned when a provider endpoint does not answer in time
+///
+/// Handlers downcast to this error to answer with a gateway timeout
+/// instead of a generic failure.
+///
+/// # Fields
+///
+/// * `context` - The request that timed out
+#[derive(Debug)]
+pub struct ProviderTimeout {
+    /// The request that timed out, e.g. `Failed to get user info from Google`
+    pub context: String,
+}
+
+impl fmt::Display for ProviderTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: the provider did not answer in time", self.context)
+    }
+}
+
+impl std::error::Error for ProviderTimeout {}
+
+/// Converts a failed provider request to an error
+///
+/// # Arguments
+///
+/// * `context` - What the request was for
+/// * `error` - The error of the request
+///
+/// # Returns
+///
+/// Returns a `ProviderTimeout` if the request timed out, or an error
+/// carrying the context and the request's error otherwise
+pub fn request_error(context: &str, error: reqwest::Error) -> eyre::Report {
+    if error.is_timeout() {
+        return ProviderTimeout {
+            context: context.to_string(),
+        }
+        .into();
+    }
+    eyre!("{}: {}", context, error)
+}
+
+/// Converts a failed token endpoint request to an error
+///
+/// # Arguments
+///
+/// * `context` - What the request was for
+/// * `error` - The error of the request
+///
+/// # Returns
+///
+/// Returns a `TokenExchangeError` if the provider answered with an RFC 6749
+/// error response, a `ProviderTimeout` if the request timed out, or an
+/// error carrying the context and the request's error otherwise
+pub fn token_request_error<T>(
+    context: &str,
+    error: RequestTokenError<HttpClientError<reqwest::Error>, StandardErrorResponse<T>>,
+) -> eyre::Report
+where
+    T: ErrorResponseType + fmt::Display + 'static,
+{
+    match error {
+        RequestTokenError::ServerResponse(response) => TokenExchangeError {
+            error: response.error().to_string(),
+            error_description: response.error_description().cloned(),
+        }
+        .into(),
+        RequestTokenError::Request(HttpClientError::Reqwest(e)) => request_error(context, *e),
+        e => eyre!("{}: {}", context, e),
+    }
+}
 
 /// Core trait for OAuth provider implementations
 ///
@@ -26,6 +161,13 @@ pub trait OAuthProvider: Send + Sync {
     /// Returns a reference to the configured `OAuthClient` instance
     fn get_oauth_client(&self) -> &OAuthClient;
 
+    /// Returns the HTTP client shared by every provider
+    ///
+    /// # Returns
+    ///
+    /// Returns the client sending the user info and token requests
+    fn http_client(&self) -> &HttpClient;
+
     /// Returns the OAuth scopes required for this provider
     ///
     /// OAuth scopes define the permissions that the application
@@ -37,6 +179,127 @@ pub trait OAuthProvider: Send + Sync {
     /// during the OAuth authorization flow
     fn get_scopes(&self) -> Vec<String>;
 
+    /// Returns extra query parameters to add to the authorization URL
+    ///
+    /// Some providers only issue a refresh token when asked for one through
+    /// a provider-specific parameter. These are defaults, the provider's
+    /// `extra_auth_params` setting replaces parameters of the same name.
+    ///
+    /// # Returns
+    ///
+    /// Returns the name and value of every extra parameter, none by default
+    fn get_extra_auth_params(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    /// Returns the separator of the scopes on the authorization URL
+    ///
+    /// RFC 6749 separates scopes with spaces, providers expecting another
+    /// separator get the scopes joined with it instead.
+    ///
+    /// # Returns
+    ///
+    /// Returns `' '` unless the provider expects another separator
+    fn scope_separator(&self) -> char {
+        ' '
+    }
+
+    /// Returns the names of the `/authorize` query parameters the provider takes
+    ///
+    /// Parameters named here are passed to `with_flow_params` when the flow
+    /// starts, and stored with the flow so the callback passes them again.
+    ///
+    /// # Returns
+    ///
+    /// Returns the parameter names, none by default
+    fn flow_params(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Returns the provider configured for the parameters of a flow
+    ///
+    /// Providers whose endpoints depend on the flow, such as Shopify's
+    /// per-shop endpoints, return a copy of themselves configured for it.
+    /// The parameters are the ones named by `flow_params` that were sent to
+    /// `/authorize`, and have to be validated here.
+    ///
+    /// # Arguments
+    ///
+    /// * `_params` - The flow's parameters by name
+    ///
+    /// # Returns
+    ///
+    /// Returns the configured provider, `None` by default to use the
+    /// provider as-is, or an error if a parameter is missing or refused
+    fn with_flow_params(
+        &self,
+        _params: &BTreeMap<String, String>,
+    ) -> Result<Option<Arc<dyn OAuthProvider>>> {
+        Ok(None)
+    }
+
+    /// Returns whether the provider posts the callback parameters
+    ///
+    /// Providers returning `true` get `response_mode=form_post` added to the
+    /// authorization URL, and post the code and state to the callback as a
+    /// form instead of redirecting with a query string.
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` unless the provider requires a `form_post` callback
+    fn uses_form_post(&self) -> bool {
+        false
+    }
+
+    /// Returns whether the provider accepts PKCE
+    ///
+    /// Providers whose token endpoint rejects a `code_verifier` opt out, and
+    /// their flows run without a challenge like providers configured with
+    /// `pkce = false`.
+    ///
+    /// # Returns
+    ///
+    /// Returns `true` unless the provider rejects PKCE
+    fn supports_pkce(&self) -> bool {
+        true
+    }
+
+    /// Returns whether the provider supports the device authorization grant
+    ///
+    /// Providers implementing RFC 8628 opt in, and their device flow is
+    /// enabled once a `device_auth_url` is configured.
+    ///
+    /// # Returns
+    ///
+    /// Returns `false` unless the provider supports the device flow
+    fn supports_device_flow(&self) -> bool {
+        false
+    }
+
+    /// Returns the branding of the provider's home page button
+    ///
+    /// Providers without branding get a neutral button labeled with the
+    /// name of their section.
+    ///
+    /// # Returns
+    ///
+    /// Returns the label, color and icon of the button, `None` by default
+    fn display_metadata(&self) -> Option<ProviderDisplay> {
+        None
+    }
+
+    /// Returns the provider's end-session (logout) endpoint
+    ///
+    /// `/logout` names the endpoint to users signed in through the
+    /// provider, so the frontend can end the provider's own session.
+    ///
+    /// # Returns
+    ///
+    /// Returns the endpoint URL, `None` by default
+    fn get_end_session_url(&self) -> Option<&Url> {
+        None
+    }
+
     /// Fetches user information from the OAuth provider
     ///
     /// This method makes an authenticated request to the provider's
@@ -51,6 +314,269 @@ pub trait OAuthProvider: Send + Sync {
     /// Returns `Result<UserInfo>` containing the user's information
     /// or an error if the request fails
     async fn get_user_info(&self, access_token: &str) -> Result<UserInfo>;
+
+    /// Fetches user information using the full token response
+    ///
+    /// The default implementation calls `get_user_info` with the access
+    /// token. Providers that convey the identity in the ID token override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `tokens` - The tokens returned by the code exchange
+    ///
+    /// # Returns
+    ///
+    /// Returns `Result<UserInfo>` containing the user's information
+    async fn get_user_info_from_tokens(&self, tokens: &TokenDetails) -> Result<UserInfo> {
+        self.get_user_info(&tokens.access_token).await
+    }
+
+    /// Returns the verifier of the provider's ID tokens
+    ///
+    /// Flows of providers with a verifier send a nonce on the authorization
+    /// URL, and are rejected unless the token exchange returns a valid ID
+    /// token carrying it.
+    ///
+    /// # Returns
+    ///
+    /// Returns the verifier, `None` by default so ID tokens are not checked
+    fn id_token_verifier(&self) -> Option<&IdTokenVerifier> {
+        None
+    }
+
+    /// Extracts the user information from verified ID token claims
+    ///
+    /// Providers whose ID token carries the whole identity override it, so
+    /// the user info request is skipped.
+    ///
+    /// # Arguments
+    ///
+    /// * `_claims` - The claims of the verified ID token
+    ///
+    /// # Returns
+    ///
+    /// Returns the user information, `None` by default to fall back to
+    /// `get_user_info_from_tokens`
+    fn user_info_from_id_token(&self, _claims: &serde_json::Value) -> Option<Result<UserInfo>> {
+        None
+    }
+
+    /// Returns the extra headers sent to the token endpoint
+    ///
+    /// Providers whose token endpoint expects extra headers, such as a
+    /// descriptive `User-Agent`, override it.
+    ///
+    /// # Returns
+    ///
+    /// Returns the headers added to every token and revocation request,
+    /// none by default
+    fn token_request_headers(&self) -> HeaderMap {
+        HeaderMap::new()
+    }
+
+    /// Exchanges an authorization code for tokens
+    ///
+    /// The default implementation performs the standard `authorization_code`
+    /// grant with the configured OAuth client. Providers whose token endpoint
+    /// needs a different request override it.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The authorization code returned by the provider
+    /// * `pkce_verifier` - The PKCE verifier of the flow, `None` without PKCE
+    /// * `now` - The current unix timestamp, used to compute the expiry
+    ///
+    /// # Returns
+    ///
+    /// Returns the issued token details, or a `ProviderTimeout` if the
+    /// token endpoint did not answer in time
+    async fn exchange_code(
+        &self,
+        code: &str,
+        pkce_verifier: Option<PkceCodeVerifier>,
+        now: u64,
+    ) -> Result<TokenDetails> {
+        let http_client = self
+            .http_client()
+            .token_client(self.token_request_headers());
+
+        let mut token_request = self
+            .get_oauth_client()
+            .exchange_code(AuthorizationCode::new(code.to_string()));
+        if let Some(pkce_verifier) = pkce_verifier {
+            token_request = token_request.set_pkce_verifier(pkce_verifier);
+        }
+        let token = token_request
+            .request_async(&http_client)
+            .await
+            .map_err(|e| token_request_error("Failed to exchange authorization code", e))?;
+
+        Ok(TokenDetails::from_response(&token, self.get_scopes(), now))
+    }
+
+    /// Exchanges a refresh token for a new access token
+    ///
+    /// The default implementation performs the standard `refresh_token`
+    /// grant against the provider's token endpoint. Providers that do not
+    /// issue refresh tokens return `RefreshUnsupported`.
+    ///
+    /// # Arguments
+    ///
+    /// * `refresh_token` - The refresh token issued by the provider
+    /// * `now` - The current unix timestamp, used to compute the expiry
+    ///
+    /// # Returns
+    ///
+    /// Returns the new token details, whose `refresh_token` is only set
+    /// when the provider rotated it
+    async fn refresh_token(&self, refresh_token: &str, now: u64) -> Result<TokenDetails> {
+        let http_client = self
+            .http_client()
+            .token_client(self.token_request_headers());
+
+        let token = self
+            .get_oauth_client()
+            .exchange_refresh_token(&RefreshToken::new(refresh_token.to_string()))
+            .request_async(&http_client)
+            .await
+            .map_err(|e| token_request_error("Failed to refresh access token", e))?;
+
+        Ok(TokenDetails::from_response(&token, self.get_scopes(), now))
+    }
+
+    /// Requests a device and user code from the device authorization endpoint
+    ///
+    /// # Returns
+    ///
+    /// Returns the codes the user and the client need, an error if no
+    /// `device_auth_url` is configured, or a `TokenExchangeError` if the
+    /// provider rejected the request
+    async fn start_device_authorization(&self) -> Result<DeviceAuthorization> {
+        let http_client = self
+            .http_client()
+            .token_client(self.token_request_headers());
+
+        let response: DeviceAuthorizationResponse<EmptyExtraDeviceAuthorizationFields> = self
+            .get_oauth_client()
+            .exchange_device_code()?
+            .add_scopes(self.get_scopes().into_iter().map(Scope::new))
+            .request_async(&http_client)
+            .await
+            .map_err(|e| token_request_error("Failed to start the device authorization", e))?;
+
+        Ok(DeviceAuthorization::from_response(&response))
+    }
+
+    /// Polls the token endpoint once with a device code
+    ///
+    /// Sends the RFC 8628 `device_code` grant, authenticated like the other
+    /// token requests. The provider answers a poll made before the user
+    /// approved the device with an `authorization_pending` or `slow_down`
+    /// error.
+    ///
+    /// # Arguments
+    ///
+    /// * `device_code` - The device code returned by `start_device_authorization`
+    /// * `interval` - Seconds the client currently waits between polls
+    /// * `now` - The current unix timestamp, used to compute the expiry
+    ///
+    /// # Returns
+    ///
+    /// Returns whether the user approved the device, or a
+    /// `TokenExchangeError` if the provider answered with another error,
+    /// such as `access_denied` or `expired_token`
+    async fn poll_device_token(
+        &self,
+        device_code: &str,
+        interval: u64,
+        now: u64,
+    ) -> Result<DevicePoll> {
+        const CONTEXT: &str = "Failed to poll the device token";
+
+        let oauth_client = self.get_oauth_client();
+        if oauth_client.device_authorization_url().is_none() {
+            return Err(eyre!("No device authorization endpoint is configured"));
+        }
+        let response = send_token_request(
+            self.http_client(),
+            oauth_client,
+            self.token_request_headers(),
+            &[
+                ("grant_type", DEVICE_CODE_GRANT),
+                ("device_code", device_code),
+            ],
+            TokenRequestBody::Form,
+            CONTEXT,
+        )
+        .await?;
+        let status = response.status();
+        let body = response
+            .bytes()
+            .await
+            .map_err(|e| request_error(CONTEXT, e))?;
+
+        if status.is_success() {
+            let token: OAuthTokenResponse = serde_json::from_slice(&body)
+                .map_err(|e| eyre!("{}: invalid token response: {}", CONTEXT, e))?;
+            return Ok(DevicePoll::Complete(TokenDetails::from_response(
+                &token,
+                self.get_scopes(),
+                now,
+            )));
+        }
+
+        // The error body is not logged, it may echo the device code back
+        let error: DeviceCodeErrorResponse =
+            serde_json::from_slice(&body).map_err(|_| eyre!("{}: {}", CONTEXT, status))?;
+        let interval = interval.max(1);
+        match error.error() {
+            DeviceCodeErrorResponseType::AuthorizationPending => {
+                Ok(DevicePoll::Pending { interval })
+            }
+            // RFC 8628 section 3.5 adds 5 seconds to the interval
+            DeviceCodeErrorResponseType::SlowDown => Ok(DevicePoll::SlowDown {
+                interval: interval + 5,
+            }),
+            _ => Err(TokenExchangeError {
+                error: error.error().to_string(),
+                error_description: error.error_description().cloned(),
+            }
+            .into()),
+        }
+    }
+
+    /// Revokes an access or refresh token
+    ///
+    /// The default implementation sends an RFC 7009 revocation request to
+    /// the configured `revocation_url`, and does nothing for providers
+    /// without one. Revocation servers look the token up by its value, the
+    /// `access_token` type hint only speeds up the lookup.
+    ///
+    /// # Arguments
+    ///
+    /// * `token` - The access or refresh token issued by the provider
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` once the token is revoked or when the provider has
+    /// no revocation endpoint, or an error if the provider rejected the request
+    async fn revoke_token(&self, token: &str) -> Result<()> {
+        let oauth_client = self.get_oauth_client();
+        if oauth_client.revocation_url().is_none() {
+            return Ok(());
+        }
+        let http_client = self
+            .http_client()
+            .token_client(self.token_request_headers());
+
+        oauth_client
+            .revoke_token(StandardRevocableToken::AccessToken(AccessToken::new(
+                token.to_string(),
+            )))?
+            .request_async(&http_client)
+            .await
+            .map_err(|e| eyre!("Failed to revoke token: {}", e))
+    }
 }
 
 /// Factory trait for creating OAuth provider instances
@@ -63,19 +589,70 @@ pub trait OAuthProvider: Send + Sync {
 ///
 /// Each OAuth provider has a corresponding factory implementation
 /// (GoogleProviderFactory, GithubProviderFactory, etc.)
+#[async_trait]
 pub trait OAuthProviderFactory: Send + Sync {
     /// Creates a new OAuth provider instance
     ///
-    /// This method creates a new instance of the OAuth provider
-    /// with the given OAuth client and user info URL.
+    /// This method creates a new instance of the OAuth provider from its
+    /// context. Provider-specific settings are validated here, so an
+    /// invalid provider is reported at startup.
+    ///
+    /// # Arguments
+    ///
+    /// * `ctx` - The provider's name, OAuth client, endpoints and settings
+    ///
+    /// # Returns
+    ///
+    /// Returns an `Arc<dyn OAuthProvider>` containing the created provider instance,
+    /// or an error if the provider-specific settings are invalid
+    fn create(&self, ctx: ProviderContext<'_>) -> Result<Arc<dyn OAuthProvider>>;
+
+    /// Returns the endpoints used when they are not configured explicitly
+    ///
+    /// Providers that can be self-hosted or are tenant-specific derive them
+    /// from a setting such as `base_url` or `issuer`.
+    ///
+    /// # Arguments
+    ///
+    /// * `_settings` - The provider's settings
+    ///
+    /// # Returns
+    ///
+    /// Returns the default endpoints, `None` by default so every endpoint
+    /// has to be configured
+    fn default_endpoints(&self, _settings: &OAuthSettings) -> Option<ProviderEndpoints> {
+        None
+    }
+
+    /// Returns how the provider expects the client credentials
+    ///
+    /// The provider's `auth_type` setting takes precedence.
+    ///
+    /// # Returns
+    ///
+    /// Returns `TokenAuthType::Basic` by default
+    fn default_auth_type(&self) -> TokenAuthType {
+        TokenAuthType::Basic
+    }
+
+    /// Resolves the default endpoints at startup
+    ///
+    /// The default implementation returns `default_endpoints`. Providers
+    /// whose endpoints are published in a discovery document override it
+    /// to fetch them.
     ///
     /// # Arguments
     ///
-    /// * `oauth_client` - The configured OAuth client for the provider
-    /// * `user_info_url` - The URL for the provider's user info endpoint
+    /// * `settings` - The provider's settings
     ///
     /// # Returns
     ///
-    /// Returns an `Arc<dyn OAuthProvider>` containing the created provider instance
-    fn create(&self, oauth_client: OAuthClient, user_info_url: Url) -> Arc<dyn OAuthProvider>;
+    /// Returns the default endpoints, or an error if they could not be
+    /// discovered
+    async fn discover_endpoints(
+        &self,
+        settings: &OAuthSettings,
+    ) -> Result<Option<ProviderEndpoints>> {
+        Ok(self.default_endpoints(settings))
+    }
 }
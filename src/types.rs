@@ -1,11 +1,59 @@
+use crate::{http_client::SharedClient, redact::SecretString};
 use oauth2::{
     basic::{BasicErrorResponseType, BasicTokenType},
-    Client, EmptyExtraTokenFields, EndpointNotSet, EndpointSet, RevocationErrorResponseType,
-    StandardErrorResponse, StandardRevocableToken, StandardTokenIntrospectionResponse,
-    StandardTokenResponse,
+    Client, ClientSecret, EmptyExtraTokenFields, EndpointMaybeSet, EndpointNotSet, EndpointSet,
+    ExtraTokenFields, RevocationErrorResponseType, StandardErrorResponse, StandardRevocableToken,
+    StandardTokenIntrospectionResponse, StandardTokenResponse,
 };
+use serde::{Deserialize, Serialize};
+use std::{ops::Deref, sync::Arc};
 
-/// OAuth client type alias for the configured OAuth 2.0 client
+/// HTTP client shared by the providers and the token exchange
+///
+/// A single client is built at startup so every provider reuses its
+/// connection pool. It is reference counted, providers holding the same
+/// client point at the same instance.
+pub type HttpClient = Arc<SharedClient>;
+
+/// Extra fields of a token response read by the server
+///
+/// OpenID Connect providers return the ID token next to the access token.
+///
+/// # Fields
+///
+/// * `id_token` - The OpenID Connect ID token, if the provider issued one
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct IdTokenFields {
+    /// The OpenID Connect ID token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+}
+
+impl ExtraTokenFields for IdTokenFields {}
+
+/// Token response of the OAuth client, with the ID token when issued
+pub type OAuthTokenResponse = StandardTokenResponse<IdTokenFields, BasicTokenType>;
+
+/// OAuth client before its endpoints are set
+///
+/// Created with `BareOAuthClient::new(client_id)`, it becomes a
+/// `ConfiguredClient` once the authorization and token endpoints are set
+/// and the revocation and device authorization endpoints are set or
+/// explicitly left out.
+pub type BareOAuthClient = Client<
+    StandardErrorResponse<BasicErrorResponseType>,
+    OAuthTokenResponse,
+    StandardTokenIntrospectionResponse<EmptyExtraTokenFields, BasicTokenType>,
+    StandardRevocableToken,
+    StandardErrorResponse<RevocationErrorResponseType>,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointNotSet,
+    EndpointNotSet,
+>;
+
+/// OAuth 2.0 client with its endpoints set
 ///
 /// This type alias defines the specific OAuth client configuration used
 /// throughout the application. It uses the standard OAuth 2.0 error and
@@ -15,24 +63,108 @@ use oauth2::{
 ///
 /// The client is configured with:
 /// - `StandardErrorResponse<BasicErrorResponseType>` - Standard OAuth error responses
-/// - `StandardTokenResponse<EmptyExtraTokenFields, BasicTokenType>` - Standard token responses
+/// - `OAuthTokenResponse` - Standard token responses carrying the ID token
 /// - `StandardTokenIntrospectionResponse<EmptyExtraTokenFields, BasicTokenType>` - Token introspection
 /// - `StandardRevocableToken` - Token revocation support
 /// - `StandardErrorResponse<RevocationErrorResponseType>` - Revocation error responses
 /// - `EndpointSet` - Authorization endpoint is configured
-/// - `EndpointNotSet` - Device authorization not configured
+/// - `EndpointMaybeSet` - Device authorization configured when the provider has an endpoint
 /// - `EndpointNotSet` - Token introspection not configured
-/// - `EndpointNotSet` - Token revocation not configured
+/// - `EndpointMaybeSet` - Token revocation configured when the provider has an endpoint
 /// - `EndpointSet` - Token endpoint is configured
-pub type OAuthClient = Client<
+pub type ConfiguredClient = Client<
     StandardErrorResponse<BasicErrorResponseType>,
-    StandardTokenResponse<EmptyExtraTokenFields, BasicTokenType>,
+    OAuthTokenResponse,
     StandardTokenIntrospectionResponse<EmptyExtraTokenFields, BasicTokenType>,
     StandardRevocableToken,
     StandardErrorResponse<RevocationErrorResponseType>,
-    EndpointSet,    // set_auth_uri called
-    EndpointNotSet, // device auth not set
-    EndpointNotSet, // introspection not set
-    EndpointNotSet, // revocation not set
-    EndpointSet,    // set_token_uri called
+    EndpointSet,      // set_auth_uri called
+    EndpointMaybeSet, // set_device_authorization_url_option called
+    EndpointNotSet,   // introspection not set
+    EndpointMaybeSet, // set_revocation_url_option called
+    EndpointSet,      // set_token_uri called
 >;
+
+/// The configured OAuth client of a provider
+///
+/// Wraps the OAuth client and keeps the client secret it authenticates
+/// with, which the OAuth client does not expose, so token requests the
+/// client cannot build, such as the device code poll, authenticate the
+/// same way. The OAuth client's methods are reachable through `Deref`.
+///
+/// # Fields
+///
+/// * `client` - The OAuth client
+/// * `client_secret` - The client secret, `None` for public clients
+#[derive(Clone, Debug)]
+pub struct OAuthClient {
+    /// The OAuth client
+    client: ConfiguredClient,
+    /// The client secret, `None` for public clients
+    client_secret: Option<SecretString>,
+}
+
+impl OAuthClient {
+    /// Creates a provider's OAuth client
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - The OAuth client with its endpoints set
+    /// * `client_secret` - The client secret, `None` for public clients
+    ///
+    /// # Returns
+    ///
+    /// Returns the OAuth client, authenticating with the secret if any
+    pub fn new(client: ConfiguredClient, client_secret: Option<SecretString>) -> Self {
+        let client = match &client_secret {
+            Some(secret) => {
+                client.set_client_secret(ClientSecret::new(secret.expose().to_string()))
+            }
+            None => client,
+        };
+        Self {
+            client,
+            client_secret,
+        }
+    }
+
+    /// Returns the client secret
+    ///
+    /// # Returns
+    ///
+    /// Returns the secret, or `None` for public clients
+    pub fn client_secret(&self) -> Option<&SecretString> {
+        self.client_secret.as_ref()
+    }
+
+    /// Changes the OAuth client, keeping the client secret
+    ///
+    /// # Arguments
+    ///
+    /// * `change` - Builds the new OAuth client from the current one
+    ///
+    /// # Returns
+    ///
+    /// Returns the changed client
+    pub fn map(self, change: impl FnOnce(ConfiguredClient) -> ConfiguredClient) -> Self {
+        Self {
+            client: change(self.client),
+            client_secret: self.client_secret,
+        }
+    }
+}
+
+impl From<ConfiguredClient> for OAuthClient {
+    /// Wraps the OAuth client of a public client, which has no secret
+    fn from(client: ConfiguredClient) -> Self {
+        Self::new(client, None)
+    }
+}
+
+impl Deref for OAuthClient {
+    type Target = ConfiguredClient;
+
+    fn deref(&self) -> &Self::Target {
+        &self.client
+    }
+}
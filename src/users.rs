@@ -0,0 +1,2182 @@
+//! Persistence of signed-in users
+//!
+//! Every user is assigned a stable internal id the first time they sign in
+//! through a provider, so applications do not map provider ids themselves.
+//! Further providers can be linked to the same user, each `(provider,
+//! provider_user_id)` identity belonging to a single user. The signed-in
+//! sessions are indexed by user, and the sessions revoked by an
+//! administrator are kept alongside the users, as are the provider tokens
+//! kept for refreshing. Emails are stored encrypted, next to a blind index
+//! that finds users by email without storing it readable. Users are kept
+//! in memory by default; with the `database` feature they are persisted to
+//! SQLite or Postgres.
+
+use crate::crypto::SecretCipher;
+use async_trait::async_trait;
+use eyre::Result;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fmt, sync::Mutex};
+use uuid::Uuid;
+
+#[cfg(feature = "database")]
+use crate::settings::DatabaseSettings;
+#[cfg(feature = "database")]
+use eyre::{bail, eyre};
+#[cfg(feature = "database")]
+use sqlx::{
+    any::{install_default_drivers, AnyPoolOptions},
+    migrate::{Migrate, Migrator},
+    AnyPool, Row,
+};
+#[cfg(feature = "database")]
+use tokio::sync::OnceCell;
+
+/// A user known to the server, as seen through the identity they signed in with
+///
+/// # Fields
+///
+/// * `id` - Stable internal id of the user
+/// * `provider` - Provider the user signed in through
+/// * `provider_user_id` - The user's id at the provider
+/// * `created_at` - Unix timestamp of the first sign-in
+/// * `last_seen` - Unix timestamp of the latest sign-in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredUser {
+    /// Stable internal id
+    pub id: Uuid,
+    /// Provider the user signed in through
+    pub provider: String,
+    /// The user's id at the provider
+    pub provider_user_id: String,
+    /// Unix timestamp of the first sign-in
+    pub created_at: u64,
+    /// Unix timestamp of the latest sign-in
+    pub last_seen: u64,
+}
+
+/// A user as kept by the store, whatever identity they signed in with
+///
+/// # Fields
+///
+/// * `id` - Stable internal id of the user
+/// * `created_at` - Unix timestamp of the first sign-in
+/// * `last_seen` - Unix timestamp of the latest sign-in
+/// * `email` - Email of the user, encrypted as stored, if known
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserRecord {
+    /// Stable internal id
+    pub id: Uuid,
+    /// Unix timestamp of the first sign-in
+    pub created_at: u64,
+    /// Unix timestamp of the latest sign-in
+    pub last_seen: u64,
+    /// Email of the user, encrypted as stored
+    pub email: Option<String>,
+}
+
+/// A provider account linked to a user
+///
+/// # Fields
+///
+/// * `provider` - Provider of the account
+/// * `provider_user_id` - The user's id at the provider
+/// * `linked_at` - Unix timestamp at which the account was linked
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Identity {
+    /// Provider of the account
+    pub provider: String,
+    /// The user's id at the provider
+    pub provider_user_id: String,
+    /// Unix timestamp at which the account was linked
+    pub linked_at: u64,
+}
+
+/// A signed-in session, as kept by the session index
+///
+/// # Fields
+///
+/// * `id` - Id assigned to the session at sign-in, not the cookie's session id
+/// * `subject` - Internal id of the signed-in user
+/// * `provider` - Provider the user signed in through
+/// * `provider_user_id` - The user's id at the provider
+/// * `created_at` - Unix timestamp of the sign-in
+/// * `last_seen` - Unix timestamp of the latest request, to the minute
+/// * `expires_at` - Unix timestamp at which the session expires unless used again
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserSession {
+    /// Id assigned to the session at sign-in
+    pub id: Uuid,
+    /// Internal id of the signed-in user
+    pub subject: Uuid,
+    /// Provider the user signed in through
+    pub provider: String,
+    /// The user's id at the provider
+    pub provider_user_id: String,
+    /// Unix timestamp of the sign-in
+    pub created_at: u64,
+    /// Unix timestamp of the latest request
+    pub last_seen: u64,
+    /// Unix timestamp at which the session expires unless used again
+    pub expires_at: u64,
+}
+
+/// Tokens a user holds at a provider, as kept by the token store
+///
+/// The tokens are encrypted by the server before they are stored.
+///
+/// # Fields
+///
+/// * `user_id` - Internal id of the user
+/// * `provider` - Provider that issued the tokens
+/// * `access_token` - The encrypted access token
+/// * `refresh_token` - The encrypted refresh token, if the provider issued one
+/// * `expires_at` - Unix timestamp at which the access token expires, if known
+/// * `updated_at` - Unix timestamp at which the tokens were issued or refreshed
+/// * `needs_reauth_at` - Unix timestamp at which refreshing failed, the user must sign in again
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredTokens {
+    /// Internal id of the user
+    pub user_id: Uuid,
+    /// Provider that issued the tokens
+    pub provider: String,
+    /// The encrypted access token
+    pub access_token: String,
+    /// The encrypted refresh token, if the provider issued one
+    pub refresh_token: Option<String>,
+    /// Unix timestamp at which the access token expires, if known
+    pub expires_at: Option<u64>,
+    /// Unix timestamp at which the tokens were issued or refreshed
+    pub updated_at: u64,
+    /// Unix timestamp at which refreshing failed
+    pub needs_reauth_at: Option<u64>,
+}
+
+/// Interval at which the last request of a session is recorded
+///
+/// Sessions are only written to the index once per interval, not on every
+/// request.
+const LAST_SEEN_INTERVAL_SECONDS: u64 = 60;
+
+/// Rejected change to the identities of a user
+///
+/// Handlers downcast to this error to answer with a conflict instead of a
+/// generic failure.
+///
+/// * `AlreadyLinked` - The identity belongs to another user
+/// * `LastIdentity` - The user would be left without a way to sign in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdentityError {
+    /// The identity belongs to another user
+    AlreadyLinked,
+    /// The user would be left without a way to sign in
+    LastIdentity,
+}
+
+impl fmt::Display for IdentityError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdentityError::AlreadyLinked => {
+                write!(f, "The account is already linked to another user")
+            }
+            IdentityError::LastIdentity => {
+                write!(f, "The last linked account cannot be unlinked")
+            }
+        }
+    }
+}
+
+impl std::error::Error for IdentityError {}
+
+/// Sessions revoked by an administrator
+///
+/// * `Session` - A single session, by the id assigned to it at sign-in
+/// * `User` - Every session a user signed in to before the revocation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Revocation {
+    /// A single session
+    Session(Uuid),
+    /// Every session of a user
+    User(Uuid),
+}
+
+impl Revocation {
+    /// Returns the kind of the revocation, as stored in the database
+    #[cfg(feature = "database")]
+    fn kind(&self) -> &'static str {
+        match self {
+            Revocation::Session(_) => "session",
+            Revocation::User(_) => "user",
+        }
+    }
+
+    /// Returns the id of the revoked session or user
+    #[cfg(feature = "database")]
+    fn id(&self) -> Uuid {
+        match self {
+            Revocation::Session(id) | Revocation::User(id) => *id,
+        }
+    }
+}
+
+/// Store of the users signed in through the providers
+/// Number of users re-indexed per page
+const REINDEX_PAGE: u64 = 500;
+
+/// Normalizes an email before it is indexed, so that lookups ignore case
+///
+/// # Arguments
+///
+/// * `email` - The email to normalize
+///
+/// # Returns
+///
+/// Returns the email trimmed and lowercased
+pub fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+#[async_trait]
+pub trait UserRepository: Send + Sync {
+    /// Prepares the store, such as checking or creating its tables
+    ///
+    /// The server calls it at startup, so a store that cannot be used stops
+    /// the server instead of failing the first sign-in.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` once the store is ready, or an error if it cannot be
+    /// prepared
+    async fn prepare(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Applies the pending migrations of the store's schema
+    ///
+    /// `--migrate` calls it whatever `database.auto_migrate` says.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` once the schema is up to date, or an error if it is
+    /// newer than the bundled migrations or a migration failed
+    async fn migrate(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Records a sign-in, creating the user on their first sign-in
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - Provider the user signed in through
+    /// * `provider_user_id` - The user's id at the provider
+    /// * `now` - Unix timestamp of the sign-in
+    ///
+    /// # Returns
+    ///
+    /// Returns the user with its `last_seen` updated, or an error if the
+    /// store failed
+    async fn upsert(&self, provider: &str, provider_user_id: &str, now: u64) -> Result<StoredUser>;
+
+    /// Looks a user up by their internal id
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - Internal id of the user
+    ///
+    /// # Returns
+    ///
+    /// Returns the user, `None` if there is no such user, or an error if
+    /// the store failed
+    async fn user(&self, user_id: Uuid) -> Result<Option<UserRecord>>;
+
+    /// Deletes a user with their identities, indexed sessions and tokens
+    ///
+    /// Deleting a user that does not exist does nothing. Revocations are
+    /// kept, they name no more than the ids of the user and sessions.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - Internal id of the user
+    ///
+    /// # Returns
+    ///
+    /// Returns whether the user existed, or an error if the store failed
+    async fn delete_user(&self, user_id: Uuid) -> Result<bool>;
+
+    /// Stores the encrypted email of a user and its blind index
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - Internal id of the user
+    /// * `email` - The email, encrypted
+    /// * `email_index` - Blind index of the normalized email
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` once stored, or an error if the store failed
+    async fn store_email(&self, user_id: Uuid, email: &str, email_index: &str) -> Result<()>;
+
+    /// Looks users up by the blind index of their email
+    ///
+    /// # Arguments
+    ///
+    /// * `email_indexes` - Blind indexes of the email, one per key in the ring
+    ///
+    /// # Returns
+    ///
+    /// Returns the users with any of the indexes, first signed in first, or
+    /// an error if the store failed
+    async fn find_by_email_index(&self, email_indexes: &[String]) -> Result<Vec<UserRecord>>;
+
+    /// Lists the users with an email, in the order of their ids
+    ///
+    /// # Arguments
+    ///
+    /// * `after` - Id of the last user of the previous page, `None` for the first page
+    /// * `limit` - Maximum number of users listed
+    ///
+    /// # Returns
+    ///
+    /// Returns the page of users, or an error if the store failed
+    async fn users_with_email(&self, after: Option<Uuid>, limit: u64) -> Result<Vec<UserRecord>>;
+
+    /// Stores the email of a user, encrypted and indexed
+    ///
+    /// # Arguments
+    ///
+    /// * `cipher` - Cipher encrypting the email and keying its blind index
+    /// * `user_id` - Internal id of the user
+    /// * `email` - The email, as given by the provider
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` once stored, or an error if encryption is disabled
+    /// or the store failed
+    async fn set_email(&self, cipher: &SecretCipher, user_id: Uuid, email: &str) -> Result<()> {
+        let email = email.trim();
+        let email_index = cipher.blind_index(&normalize_email(email))?;
+        self.store_email(user_id, &cipher.encrypt(email)?, &email_index)
+            .await
+    }
+
+    /// Finds the users with an email
+    ///
+    /// The email is matched through its blind index, ignoring case. Users
+    /// indexed under a key that was rotated out of the active position are
+    /// found until they are re-indexed.
+    ///
+    /// # Arguments
+    ///
+    /// * `cipher` - Cipher keying the blind index
+    /// * `email` - The email to look up
+    ///
+    /// # Returns
+    ///
+    /// Returns the users with the email, first signed in first, or an error
+    /// if the store failed
+    async fn find_by_email(&self, cipher: &SecretCipher, email: &str) -> Result<Vec<UserRecord>> {
+        let email_indexes = cipher.blind_indexes(&normalize_email(email));
+        if email_indexes.is_empty() {
+            return Ok(Vec::new());
+        }
+        self.find_by_email_index(&email_indexes).await
+    }
+
+    /// Re-encrypts and re-indexes every email with the active key
+    ///
+    /// Run after a new key is put first in `encryption.keys`, before the
+    /// previous key is removed. Users are processed a page at a time.
+    ///
+    /// # Arguments
+    ///
+    /// * `cipher` - Cipher holding the new active key and the previous keys
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of emails re-indexed, or an error if an email
+    /// cannot be decrypted with any key or the store failed
+    async fn reindex_emails(&self, cipher: &SecretCipher) -> Result<u64> {
+        let mut reindexed = 0;
+        let mut after = None;
+        loop {
+            let page = self.users_with_email(after, REINDEX_PAGE).await?;
+            for user in &page {
+                let email = cipher.decrypt(user.email.as_deref().unwrap_or_default())?;
+                self.set_email(cipher, user.id, &email).await?;
+                reindexed += 1;
+            }
+            match page.last() {
+                Some(last) if page.len() as u64 == REINDEX_PAGE => after = Some(last.id),
+                _ => return Ok(reindexed),
+            }
+        }
+    }
+
+    /// Links a provider account to a user
+    ///
+    /// Linking an account already linked to the same user does nothing.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - Internal id of the user
+    /// * `provider` - Provider of the account
+    /// * `provider_user_id` - The user's id at the provider
+    /// * `now` - Unix timestamp of the link
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` once the account is linked,
+    /// `IdentityError::AlreadyLinked` if it belongs to another user, or an
+    /// error if the store failed
+    async fn link(
+        &self,
+        user_id: Uuid,
+        provider: &str,
+        provider_user_id: &str,
+        now: u64,
+    ) -> Result<()>;
+
+    /// Lists the provider accounts linked to a user
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - Internal id of the user
+    ///
+    /// # Returns
+    ///
+    /// Returns the identities, oldest first, or an error if the store failed
+    async fn identities(&self, user_id: Uuid) -> Result<Vec<Identity>>;
+
+    /// Unlinks the accounts of a provider from a user
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - Internal id of the user
+    /// * `provider` - Provider whose accounts are unlinked
+    ///
+    /// # Returns
+    ///
+    /// Returns whether an account was unlinked,
+    /// `IdentityError::LastIdentity` if the user has no account of another
+    /// provider, or an error if the store failed
+    async fn unlink(&self, user_id: Uuid, provider: &str) -> Result<bool>;
+
+    /// Adds a signed-in session to the session index
+    ///
+    /// Sessions that expired are dropped along the way.
+    ///
+    /// # Arguments
+    ///
+    /// * `session` - The session, created at the sign-in
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` once the session is indexed, or an error if the store
+    /// failed
+    async fn add_session(&self, session: &UserSession) -> Result<()>;
+
+    /// Records a request of an indexed session, extending its expiry
+    ///
+    /// Requests are recorded at most once a minute per session.
+    ///
+    /// # Arguments
+    ///
+    /// * `session_id` - Id assigned to the session at sign-in
+    /// * `now` - Unix timestamp of the request
+    /// * `expires_at` - Unix timestamp at which the session now expires
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` once recorded, or an error if the store failed
+    async fn touch_session(&self, session_id: Uuid, now: u64, expires_at: u64) -> Result<()>;
+
+    /// Lists the sessions that have not expired, latest sign-in first
+    ///
+    /// # Arguments
+    ///
+    /// * `subject` - Internal id of the user whose sessions are listed, all users when `None`
+    /// * `now` - Current Unix timestamp
+    /// * `offset` - Number of sessions skipped
+    /// * `limit` - Maximum number of sessions listed
+    ///
+    /// # Returns
+    ///
+    /// Returns the page of sessions, or an error if the store failed
+    async fn sessions(
+        &self,
+        subject: Option<Uuid>,
+        now: u64,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<UserSession>>;
+
+    /// Revokes a session, or every session of a user
+    ///
+    /// The revoked sessions are dropped from the session index. Revocations
+    /// that expired are dropped along the way. Revoking a user again moves
+    /// the revocation to the later sign-ins.
+    ///
+    /// # Arguments
+    ///
+    /// * `revocation` - The revoked session or user
+    /// * `now` - Unix timestamp of the revocation
+    /// * `expires_at` - Unix timestamp by which the revoked sessions have expired anyway
+    ///
+    /// # Returns
+    ///
+    /// Returns the number of indexed sessions the revocation dropped, or an
+    /// error if the store failed
+    async fn revoke(&self, revocation: Revocation, now: u64, expires_at: u64) -> Result<u64>;
+
+    /// Checks whether a session was revoked
+    ///
+    /// # Arguments
+    ///
+    /// * `session_id` - Id assigned to the session at sign-in
+    /// * `user_id` - Internal id of the signed-in user
+    /// * `logged_in_at` - Unix timestamp of the sign-in
+    /// * `now` - Current Unix timestamp
+    ///
+    /// # Returns
+    ///
+    /// Returns whether the session, or the sessions its user signed in to
+    /// until then, were revoked, or an error if the store failed
+    async fn is_revoked(
+        &self,
+        session_id: Uuid,
+        user_id: Uuid,
+        logged_in_at: u64,
+        now: u64,
+    ) -> Result<bool>;
+
+    /// Stores the tokens a user holds at a provider, replacing previous ones
+    ///
+    /// # Arguments
+    ///
+    /// * `tokens` - The encrypted tokens
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` once stored, or an error if the store failed
+    async fn store_tokens(&self, tokens: &StoredTokens) -> Result<()>;
+
+    /// Lists the tokens a user holds at the providers
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - Internal id of the user
+    ///
+    /// # Returns
+    ///
+    /// Returns the tokens sorted by provider, or an error if the store failed
+    async fn tokens(&self, user_id: Uuid) -> Result<Vec<StoredTokens>>;
+
+    /// Lists the tokens to refresh before they expire
+    ///
+    /// Only tokens with a refresh token, a known expiry and no failed
+    /// refresh are listed.
+    ///
+    /// # Arguments
+    ///
+    /// * `expires_before` - Unix timestamp until which expiring tokens are listed
+    /// * `limit` - Maximum number of tokens listed
+    ///
+    /// # Returns
+    ///
+    /// Returns the tokens, the earliest expiry first, or an error if the
+    /// store failed
+    async fn expiring_tokens(&self, expires_before: u64, limit: u64) -> Result<Vec<StoredTokens>>;
+
+    /// Records that the tokens of a user could not be refreshed
+    ///
+    /// The tokens are no longer listed for refreshing, until the user signs
+    /// in again.
+    ///
+    /// # Arguments
+    ///
+    /// * `user_id` - Internal id of the user
+    /// * `provider` - Provider that issued the tokens
+    /// * `now` - Unix timestamp of the failed refresh
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(())` once recorded, or an error if the store failed
+    async fn mark_needs_reauth(&self, user_id: Uuid, provider: &str, now: u64) -> Result<()>;
+}
+
+/// Identity linked to a user, as kept by the in-memory store
+///
+/// # Fields
+///
+/// * `user_id` - Internal id of the user
+/// * `linked_at` - Unix timestamp at which the identity was linked
+struct LinkedIdentity {
+    /// Internal id of the user
+    user_id: Uuid,
+    /// Unix timestamp at which the identity was linked
+    linked_at: u64,
+}
+
+/// Users and identities of the in-memory store
+///
+/// # Fields
+///
+/// * `users` - First and latest sign-in of each user, keyed by internal id
+/// * `identities` - Identities keyed by provider and provider user id
+/// * `sessions` - Signed-in sessions keyed by their id
+/// * `revocations` - Time of each revocation and of its expiry
+/// * `tokens` - Provider tokens keyed by user and provider
+#[derive(Default)]
+struct MemoryUsers {
+    /// First and latest sign-in of each user
+    users: HashMap<Uuid, (u64, u64)>,
+    /// Identities keyed by provider and provider user id
+    identities: HashMap<(String, String), LinkedIdentity>,
+    /// Signed-in sessions keyed by their id
+    sessions: HashMap<Uuid, UserSession>,
+    /// Time of each revocation and of its expiry
+    revocations: HashMap<Revocation, (u64, u64)>,
+    /// Provider tokens keyed by user and provider
+    tokens: HashMap<(Uuid, String), StoredTokens>,
+    /// Encrypted email and blind index of each user
+    emails: HashMap<Uuid, (String, String)>,
+}
+
+impl MemoryUsers {
+    /// Returns a user as the store keeps them
+    fn record(&self, user_id: Uuid) -> Option<UserRecord> {
+        self.users
+            .get(&user_id)
+            .map(|(created_at, last_seen)| UserRecord {
+                id: user_id,
+                created_at: *created_at,
+                last_seen: *last_seen,
+                email: self.emails.get(&user_id).map(|(email, _)| email.clone()),
+            })
+    }
+}
+
+/// Users kept in memory, lost on restart
+///
+/// # Fields
+///
+/// * `users` - Users and their identities
+#[derive(Default)]
+pub struct InMemoryUserRepository {
+    /// Users and their identities
+    users: Mutex<MemoryUsers>,
+}
+
+#[async_trait]
+impl UserRepository for InMemoryUserRepository {
+    async fn upsert(&self, provider: &str, provider_user_id: &str, now: u64) -> Result<StoredUser> {
+        let mut guard = self.users.lock().unwrap_or_else(|e| e.into_inner());
+        let MemoryUsers {
+            users, identities, ..
+        } = &mut *guard;
+        let identity = identities
+            .entry((provider.to_string(), provider_user_id.to_string()))
+            .or_insert_with(|| LinkedIdentity {
+                user_id: Uuid::new_v4(),
+                linked_at: now,
+            });
+        let (created_at, last_seen) = users.entry(identity.user_id).or_insert((now, now));
+        *last_seen = now;
+
+        Ok(StoredUser {
+            id: identity.user_id,
+            provider: provider.to_string(),
+            provider_user_id: provider_user_id.to_string(),
+            created_at: *created_at,
+            last_seen: now,
+        })
+    }
+
+    async fn user(&self, user_id: Uuid) -> Result<Option<UserRecord>> {
+        let users = self.users.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(users.record(user_id))
+    }
+
+    async fn delete_user(&self, user_id: Uuid) -> Result<bool> {
+        let mut users = self.users.lock().unwrap_or_else(|e| e.into_inner());
+        users
+            .identities
+            .retain(|_, identity| identity.user_id != user_id);
+        users
+            .sessions
+            .retain(|_, session| session.subject != user_id);
+        users
+            .tokens
+            .retain(|(token_user_id, _), _| *token_user_id != user_id);
+        users.emails.remove(&user_id);
+        Ok(users.users.remove(&user_id).is_some())
+    }
+
+    async fn store_email(&self, user_id: Uuid, email: &str, email_index: &str) -> Result<()> {
+        let mut users = self.users.lock().unwrap_or_else(|e| e.into_inner());
+        if users.users.contains_key(&user_id) {
+            users
+                .emails
+                .insert(user_id, (email.to_string(), email_index.to_string()));
+        }
+        Ok(())
+    }
+
+    async fn find_by_email_index(&self, email_indexes: &[String]) -> Result<Vec<UserRecord>> {
+        let users = self.users.lock().unwrap_or_else(|e| e.into_inner());
+        let mut found: Vec<UserRecord> = users
+            .emails
+            .iter()
+            .filter(|(_, (_, email_index))| email_indexes.contains(email_index))
+            .filter_map(|(user_id, _)| users.record(*user_id))
+            .collect();
+        found.sort_by_key(|user| (user.created_at, user.id.to_string()));
+        Ok(found)
+    }
+
+    async fn users_with_email(&self, after: Option<Uuid>, limit: u64) -> Result<Vec<UserRecord>> {
+        let users = self.users.lock().unwrap_or_else(|e| e.into_inner());
+        let after = after.map(|after| after.to_string()).unwrap_or_default();
+        let mut ids: Vec<String> = users
+            .emails
+            .keys()
+            .map(Uuid::to_string)
+            .filter(|user_id| *user_id > after)
+            .collect();
+        ids.sort();
+        ids.truncate(limit as usize);
+        Ok(ids
+            .iter()
+            .filter_map(|user_id| users.record(Uuid::parse_str(user_id).ok()?))
+            .collect())
+    }
+
+    async fn link(
+        &self,
+        user_id: Uuid,
+        provider: &str,
+        provider_user_id: &str,
+        now: u64,
+    ) -> Result<()> {
+        let mut users = self.users.lock().unwrap_or_else(|e| e.into_inner());
+        let identity = users
+            .identities
+            .entry((provider.to_string(), provider_user_id.to_string()))
+            .or_insert(LinkedIdentity {
+                user_id,
+                linked_at: now,
+            });
+        if identity.user_id != user_id {
+            return Err(IdentityError::AlreadyLinked.into());
+        }
+        Ok(())
+    }
+
+    async fn identities(&self, user_id: Uuid) -> Result<Vec<Identity>> {
+        let users = self.users.lock().unwrap_or_else(|e| e.into_inner());
+        let mut identities: Vec<Identity> = users
+            .identities
+            .iter()
+            .filter(|(_, identity)| identity.user_id == user_id)
+            .map(|((provider, provider_user_id), identity)| Identity {
+                provider: provider.clone(),
+                provider_user_id: provider_user_id.clone(),
+                linked_at: identity.linked_at,
+            })
+            .collect();
+        identities.sort_by(|a, b| {
+            (a.linked_at, &a.provider, &a.provider_user_id).cmp(&(
+                b.linked_at,
+                &b.provider,
+                &b.provider_user_id,
+            ))
+        });
+        Ok(identities)
+    }
+
+    async fn unlink(&self, user_id: Uuid, provider: &str) -> Result<bool> {
+        let mut users = self.users.lock().unwrap_or_else(|e| e.into_inner());
+        let linked: Vec<&String> = users
+            .identities
+            .iter()
+            .filter(|(_, identity)| identity.user_id == user_id)
+            .map(|((linked_provider, _), _)| linked_provider)
+            .collect();
+        if !linked.iter().any(|linked| *linked == provider) {
+            return Ok(false);
+        }
+        if linked.iter().all(|linked| *linked == provider) {
+            return Err(IdentityError::LastIdentity.into());
+        }
+
+        users.identities.retain(|(linked_provider, _), identity| {
+            identity.user_id != user_id || linked_provider != provider
+        });
+        Ok(true)
+    }
+
+    async fn add_session(&self, session: &UserSession) -> Result<()> {
+        let mut users = self.users.lock().unwrap_or_else(|e| e.into_inner());
+        users
+            .sessions
+            .retain(|_, indexed| indexed.expires_at > session.created_at);
+        users.sessions.insert(session.id, session.clone());
+        Ok(())
+    }
+
+    async fn touch_session(&self, session_id: Uuid, now: u64, expires_at: u64) -> Result<()> {
+        let mut users = self.users.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(session) = users.sessions.get_mut(&session_id) {
+            if session.last_seen + LAST_SEEN_INTERVAL_SECONDS <= now {
+                session.last_seen = now;
+                session.expires_at = expires_at;
+            }
+        }
+        Ok(())
+    }
+
+    async fn sessions(
+        &self,
+        subject: Option<Uuid>,
+        now: u64,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<UserSession>> {
+        let users = self.users.lock().unwrap_or_else(|e| e.into_inner());
+        let mut sessions: Vec<UserSession> = users
+            .sessions
+            .values()
+            .filter(|session| session.expires_at > now)
+            .filter(|session| subject.is_none_or(|subject| session.subject == subject))
+            .cloned()
+            .collect();
+        sessions.sort_by(|a, b| (b.created_at, a.id).cmp(&(a.created_at, b.id)));
+        Ok(sessions
+            .into_iter()
+            .skip(usize::try_from(offset)?)
+            .take(usize::try_from(limit)?)
+            .collect())
+    }
+
+    async fn revoke(&self, revocation: Revocation, now: u64, expires_at: u64) -> Result<u64> {
+        let mut users = self.users.lock().unwrap_or_else(|e| e.into_inner());
+        users
+            .revocations
+            .retain(|_, (_, expires_at)| *expires_at > now);
+        users.revocations.insert(revocation, (now, expires_at));
+
+        let indexed = users.sessions.len();
+        users.sessions.retain(|id, session| match revocation {
+            Revocation::Session(session_id) => *id != session_id,
+            Revocation::User(user_id) => session.subject != user_id,
+        });
+        Ok(u64::try_from(indexed - users.sessions.len())?)
+    }
+
+    async fn is_revoked(
+        &self,
+        session_id: Uuid,
+        user_id: Uuid,
+        logged_in_at: u64,
+        now: u64,
+    ) -> Result<bool> {
+        let users = self.users.lock().unwrap_or_else(|e| e.into_inner());
+        let active = |revocation| {
+            users
+                .revocations
+                .get(&revocation)
+                .filter(|(_, expires_at)| *expires_at > now)
+                .map(|(revoked_at, _)| *revoked_at)
+        };
+        Ok(active(Revocation::Session(session_id)).is_some()
+            || active(Revocation::User(user_id))
+                .is_some_and(|revoked_at| logged_in_at <= revoked_at))
+    }
+
+    async fn store_tokens(&self, tokens: &StoredTokens) -> Result<()> {
+        let mut users = self.users.lock().unwrap_or_else(|e| e.into_inner());
+        users
+            .tokens
+            .insert((tokens.user_id, tokens.provider.clone()), tokens.clone());
+        Ok(())
+    }
+
+    async fn tokens(&self, user_id: Uuid) -> Result<Vec<StoredTokens>> {
+        let users = self.users.lock().unwrap_or_else(|e| e.into_inner());
+        let mut tokens: Vec<StoredTokens> = users
+            .tokens
+            .values()
+            .filter(|tokens| tokens.user_id == user_id)
+            .cloned()
+            .collect();
+        tokens.sort_by(|a, b| a.provider.cmp(&b.provider));
+        Ok(tokens)
+    }
+
+    async fn expiring_tokens(&self, expires_before: u64, limit: u64) -> Result<Vec<StoredTokens>> {
+        let users = self.users.lock().unwrap_or_else(|e| e.into_inner());
+        let mut tokens: Vec<StoredTokens> = users
+            .tokens
+            .values()
+            .filter(|tokens| tokens.refresh_token.is_some() && tokens.needs_reauth_at.is_none())
+            .filter(|tokens| {
+                tokens
+                    .expires_at
+                    .is_some_and(|expires_at| expires_at <= expires_before)
+            })
+            .cloned()
+            .collect();
+        tokens.sort_by(|a, b| {
+            (a.expires_at, a.user_id, &a.provider).cmp(&(b.expires_at, b.user_id, &b.provider))
+        });
+        tokens.truncate(usize::try_from(limit)?);
+        Ok(tokens)
+    }
+
+    async fn mark_needs_reauth(&self, user_id: Uuid, provider: &str, now: u64) -> Result<()> {
+        let mut users = self.users.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(tokens) = users.tokens.get_mut(&(user_id, provider.to_string())) {
+            tokens.needs_reauth_at = Some(now);
+        }
+        Ok(())
+    }
+}
+
+/// Migrations creating the `users`, `identities`, `sessions`, `session_revocations` and
+/// `provider_tokens` tables, and adding the encrypted emails to `users`
+#[cfg(feature = "database")]
+static MIGRATOR: Migrator = sqlx::migrate!("./migrations");
+
+/// Users persisted to SQLite or Postgres
+///
+/// The pool connects lazily. The schema is prepared when the server
+/// starts, or before the first query when the store is used without the
+/// server: the pending migrations are applied when `database.auto_migrate`
+/// is on, and refused otherwise.
+///
+/// # Fields
+///
+/// * `pool` - Connection pool of the configured database
+/// * `auto_migrate` - Whether preparing the store applies the pending migrations
+/// * `prepared` - Set once the schema is up to date
+#[cfg(feature = "database")]
+pub struct SqlUserRepository {
+    /// Connection pool of the configured database
+    pool: AnyPool,
+    /// Whether preparing the store applies the pending migrations
+    auto_migrate: bool,
+    /// Set once the schema is up to date
+    prepared: OnceCell<()>,
+}
+
+#[cfg(feature = "database")]
+impl SqlUserRepository {
+    /// Creates the repository of the configured database
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - The database settings
+    ///
+    /// # Returns
+    ///
+    /// Returns the repository, or an error if the connection string is
+    /// empty or invalid
+    pub fn from_settings(settings: &DatabaseSettings) -> Result<Self> {
+        if settings.url.expose().is_empty() {
+            bail!("database.url must be set");
+        }
+
+        install_default_drivers();
+        let pool = AnyPoolOptions::new()
+            .max_connections(settings.max_connections)
+            .connect_lazy(settings.url.expose())
+            .map_err(|e| eyre!("Invalid database.url: {}", e))?;
+        Ok(Self {
+            pool,
+            auto_migrate: settings.migrates_at_startup(),
+            prepared: OnceCell::new(),
+        })
+    }
+
+    /// Lists the bundled migrations the database has not applied
+    ///
+    /// # Returns
+    ///
+    /// Returns the versions of the pending migrations in order, or an error
+    /// if the schema is newer than the bundled migrations or the database
+    /// failed
+    async fn pending_migrations(&self) -> Result<Vec<i64>> {
+        let mut conn = self.pool.acquire().await?;
+        conn.ensure_migrations_table().await?;
+        let applied: Vec<i64> = conn
+            .list_applied_migrations()
+            .await?
+            .iter()
+            .map(|migration| migration.version)
+            .collect();
+
+        // A newer server migrated the database, this one may not read it correctly
+        let latest = MIGRATOR
+            .iter()
+            .map(|migration| migration.version)
+            .max()
+            .unwrap_or_default();
+        if let Some(newer) = applied.iter().copied().filter(|v| *v > latest).max() {
+            bail!(
+                "the schema is at version {}, newer than version {} of this server, upgrade the server",
+                newer,
+                latest
+            );
+        }
+
+        Ok(MIGRATOR
+            .iter()
+            .map(|migration| migration.version)
+            .filter(|version| !applied.contains(version))
+            .collect())
+    }
+
+    /// Records a sign-in in a single transaction
+    ///
+    /// # Arguments
+    ///
+    /// * `provider` - Provider the user signed in through
+    /// * `provider_user_id` - The user's id at the provider
+    /// * `now` - Unix timestamp of the sign-in
+    ///
+    /// # Returns
+    ///
+    /// Returns the user, `None` if a concurrent first sign-in of the same
+    /// identity created its user first, or an error if the store failed
+    async fn try_upsert(
+        &self,
+        provider: &str,
+        provider_user_id: &str,
+        now: i64,
+    ) -> Result<Option<StoredUser>> {
+        let mut tx = self.pool.begin().await?;
+        let linked: Option<String> = sqlx::query_scalar(
+            "SELECT user_id FROM identities WHERE provider = $1 AND provider_user_id = $2",
+        )
+        .bind(provider)
+        .bind(provider_user_id)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let row = match linked {
+            Some(user_id) => {
+                sqlx::query(
+                    "UPDATE users SET last_seen = $1 WHERE id = $2 \
+                     RETURNING id, created_at, last_seen",
+                )
+                .bind(now)
+                .bind(user_id)
+                .fetch_one(&mut *tx)
+                .await?
+            }
+            None => {
+                let user_id = Uuid::new_v4().to_string();
+                let row = sqlx::query(
+                    "INSERT INTO users (id, created_at, last_seen) VALUES ($1, $2, $2) \
+                     RETURNING id, created_at, last_seen",
+                )
+                .bind(&user_id)
+                .bind(now)
+                .fetch_one(&mut *tx)
+                .await?;
+                let inserted = sqlx::query(
+                    "INSERT INTO identities (provider, provider_user_id, user_id, linked_at) \
+                     VALUES ($1, $2, $3, $4) ON CONFLICT (provider, provider_user_id) DO NOTHING",
+                )
+                .bind(provider)
+                .bind(provider_user_id)
+                .bind(&user_id)
+                .bind(now)
+                .execute(&mut *tx)
+                .await?;
+                if inserted.rows_affected() == 0 {
+                    tx.rollback().await?;
+                    return Ok(None);
+                }
+                row
+            }
+        };
+        tx.commit().await?;
+
+        Ok(Some(StoredUser {
+            id: Uuid::parse_str(row.try_get("id")?)?,
+            provider: provider.to_string(),
+            provider_user_id: provider_user_id.to_string(),
+            created_at: u64::try_from(row.try_get::<i64, _>("created_at")?)?,
+            last_seen: u64::try_from(row.try_get::<i64, _>("last_seen")?)?,
+        }))
+    }
+}
+
+#[cfg(feature = "database")]
+#[async_trait]
+impl UserRepository for SqlUserRepository {
+    async fn prepare(&self) -> Result<()> {
+        self.prepared
+            .get_or_try_init(|| async {
+                if self.auto_migrate {
+                    return self.migrate().await;
+                }
+                let pending = self
+                    .pending_migrations()
+                    .await
+                    .map_err(|e| eyre!("Failed to check the user database: {}", e))?;
+                if let Some(version) = pending.first() {
+                    bail!(
+                        "The user database lacks migration {} and database.auto_migrate is off, run the server with --migrate first",
+                        version
+                    );
+                }
+                Ok(())
+            })
+            .await?;
+        Ok(())
+    }
+
+    async fn migrate(&self) -> Result<()> {
+        self.pending_migrations()
+            .await
+            .map_err(|e| eyre!("Failed to migrate the user database: {}", e))?;
+        MIGRATOR
+            .run(&self.pool)
+            .await
+            .map_err(|e| eyre!("Failed to migrate the user database: {}", e))?;
+        Ok(())
+    }
+
+    async fn upsert(&self, provider: &str, provider_user_id: &str, now: u64) -> Result<StoredUser> {
+        self.prepare().await?;
+
+        // Two callbacks of a new identity may both miss it, the one that
+        // loses the race then signs in as the user the other created
+        let now = i64::try_from(now)?;
+        match self.try_upsert(provider, provider_user_id, now).await? {
+            Some(user) => Ok(user),
+            None => self
+                .try_upsert(provider, provider_user_id, now)
+                .await?
+                .ok_or_else(|| eyre!("The identity was created and removed concurrently")),
+        }
+    }
+
+    async fn user(&self, user_id: Uuid) -> Result<Option<UserRecord>> {
+        self.prepare().await?;
+
+        let row = sqlx::query("SELECT id, created_at, last_seen, email FROM users WHERE id = $1")
+            .bind(user_id.to_string())
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(user_record).transpose()
+    }
+
+    async fn delete_user(&self, user_id: Uuid) -> Result<bool> {
+        self.prepare().await?;
+
+        let user_id = user_id.to_string();
+        let mut tx = self.pool.begin().await?;
+        for table in ["provider_tokens", "sessions", "identities"] {
+            sqlx::query(&format!("DELETE FROM {} WHERE user_id = $1", table))
+                .bind(&user_id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        let deleted = sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(&user_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    async fn store_email(&self, user_id: Uuid, email: &str, email_index: &str) -> Result<()> {
+        self.prepare().await?;
+
+        sqlx::query("UPDATE users SET email = $1, email_index = $2 WHERE id = $3")
+            .bind(email)
+            .bind(email_index)
+            .bind(user_id.to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn find_by_email_index(&self, email_indexes: &[String]) -> Result<Vec<UserRecord>> {
+        self.prepare().await?;
+
+        let placeholders: Vec<String> = (1..=email_indexes.len())
+            .map(|position| format!("${}", position))
+            .collect();
+        let sql = format!(
+            "SELECT id, created_at, last_seen, email FROM users \
+             WHERE email_index IN ({}) ORDER BY created_at, id",
+            placeholders.join(", ")
+        );
+        let mut query = sqlx::query(&sql);
+        for email_index in email_indexes {
+            query = query.bind(email_index);
+        }
+        query
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .map(user_record)
+            .collect()
+    }
+
+    async fn users_with_email(&self, after: Option<Uuid>, limit: u64) -> Result<Vec<UserRecord>> {
+        self.prepare().await?;
+
+        sqlx::query(
+            "SELECT id, created_at, last_seen, email FROM users \
+             WHERE email IS NOT NULL AND id > $1 ORDER BY id LIMIT $2",
+        )
+        .bind(after.map(|after| after.to_string()).unwrap_or_default())
+        .bind(i64::try_from(limit)?)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(user_record)
+        .collect()
+    }
+
+    async fn link(
+        &self,
+        user_id: Uuid,
+        provider: &str,
+        provider_user_id: &str,
+        now: u64,
+    ) -> Result<()> {
+        self.prepare().await?;
+
+        let user_id = user_id.to_string();
+        let inserted = sqlx::query(
+            "INSERT INTO identities (provider, provider_user_id, user_id, linked_at) \
+             VALUES ($1, $2, $3, $4) ON CONFLICT (provider, provider_user_id) DO NOTHING",
+        )
+        .bind(provider)
+        .bind(provider_user_id)
+        .bind(&user_id)
+        .bind(i64::try_from(now)?)
+        .execute(&self.pool)
+        .await?;
+        if inserted.rows_affected() > 0 {
+            return Ok(());
+        }
+
+        let owner: String = sqlx::query_scalar(
+            "SELECT user_id FROM identities WHERE provider = $1 AND provider_user_id = $2",
+        )
+        .bind(provider)
+        .bind(provider_user_id)
+        .fetch_one(&self.pool)
+        .await?;
+        if owner != user_id {
+            return Err(IdentityError::AlreadyLinked.into());
+        }
+        Ok(())
+    }
+
+    async fn identities(&self, user_id: Uuid) -> Result<Vec<Identity>> {
+        self.prepare().await?;
+
+        let rows = sqlx::query(
+            "SELECT provider, provider_user_id, linked_at FROM identities WHERE user_id = $1 \
+             ORDER BY linked_at, provider, provider_user_id",
+        )
+        .bind(user_id.to_string())
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter()
+            .map(|row| {
+                Ok(Identity {
+                    provider: row.try_get("provider")?,
+                    provider_user_id: row.try_get("provider_user_id")?,
+                    linked_at: u64::try_from(row.try_get::<i64, _>("linked_at")?)?,
+                })
+            })
+            .collect()
+    }
+
+    async fn unlink(&self, user_id: Uuid, provider: &str) -> Result<bool> {
+        self.prepare().await?;
+
+        let user_id = user_id.to_string();
+        let mut tx = self.pool.begin().await?;
+        let row = sqlx::query(
+            "SELECT \
+             COUNT(CASE WHEN provider = $2 THEN 1 END) AS unlinked, \
+             COUNT(CASE WHEN provider <> $2 THEN 1 END) AS remaining \
+             FROM identities WHERE user_id = $1",
+        )
+        .bind(&user_id)
+        .bind(provider)
+        .fetch_one(&mut *tx)
+        .await?;
+        if row.try_get::<i64, _>("unlinked")? == 0 {
+            return Ok(false);
+        }
+        if row.try_get::<i64, _>("remaining")? == 0 {
+            return Err(IdentityError::LastIdentity.into());
+        }
+
+        sqlx::query("DELETE FROM identities WHERE user_id = $1 AND provider = $2")
+            .bind(&user_id)
+            .bind(provider)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    async fn add_session(&self, session: &UserSession) -> Result<()> {
+        self.prepare().await?;
+
+        let created_at = i64::try_from(session.created_at)?;
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM sessions WHERE expires_at <= $1")
+            .bind(created_at)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "INSERT INTO sessions \
+             (id, user_id, provider, provider_user_id, created_at, last_seen, expires_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(session.id.to_string())
+        .bind(session.subject.to_string())
+        .bind(&session.provider)
+        .bind(&session.provider_user_id)
+        .bind(created_at)
+        .bind(i64::try_from(session.last_seen)?)
+        .bind(i64::try_from(session.expires_at)?)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn touch_session(&self, session_id: Uuid, now: u64, expires_at: u64) -> Result<()> {
+        self.prepare().await?;
+
+        sqlx::query(
+            "UPDATE sessions SET last_seen = $2, expires_at = $3 WHERE id = $1 AND last_seen <= $4",
+        )
+        .bind(session_id.to_string())
+        .bind(i64::try_from(now)?)
+        .bind(i64::try_from(expires_at)?)
+        .bind(i64::try_from(
+            now.saturating_sub(LAST_SEEN_INTERVAL_SECONDS),
+        )?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn sessions(
+        &self,
+        subject: Option<Uuid>,
+        now: u64,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<UserSession>> {
+        self.prepare().await?;
+
+        let columns = "SELECT id, user_id, provider, provider_user_id, created_at, last_seen, \
+                       expires_at FROM sessions WHERE expires_at > $1";
+        let order = "ORDER BY created_at DESC, id LIMIT $2 OFFSET $3";
+        let query = match subject {
+            Some(_) => format!("{} AND user_id = $4 {}", columns, order),
+            None => format!("{} {}", columns, order),
+        };
+        let mut query = sqlx::query(&query)
+            .bind(i64::try_from(now)?)
+            .bind(i64::try_from(limit)?)
+            .bind(i64::try_from(offset)?);
+        if let Some(subject) = subject {
+            query = query.bind(subject.to_string());
+        }
+
+        let rows = query.fetch_all(&self.pool).await?;
+        rows.iter()
+            .map(|row| {
+                Ok(UserSession {
+                    id: Uuid::parse_str(row.try_get("id")?)?,
+                    subject: Uuid::parse_str(row.try_get("user_id")?)?,
+                    provider: row.try_get("provider")?,
+                    provider_user_id: row.try_get("provider_user_id")?,
+                    created_at: u64::try_from(row.try_get::<i64, _>("created_at")?)?,
+                    last_seen: u64::try_from(row.try_get::<i64, _>("last_seen")?)?,
+                    expires_at: u64::try_from(row.try_get::<i64, _>("expires_at")?)?,
+                })
+            })
+            .collect()
+    }
+
+    async fn revoke(&self, revocation: Revocation, now: u64, expires_at: u64) -> Result<u64> {
+        self.prepare().await?;
+
+        let now = i64::try_from(now)?;
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM session_revocations WHERE expires_at <= $1")
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "INSERT INTO session_revocations (kind, id, revoked_at, expires_at) \
+             VALUES ($1, $2, $3, $4) ON CONFLICT (kind, id) DO UPDATE \
+             SET revoked_at = excluded.revoked_at, expires_at = excluded.expires_at",
+        )
+        .bind(revocation.kind())
+        .bind(revocation.id().to_string())
+        .bind(now)
+        .bind(i64::try_from(expires_at)?)
+        .execute(&mut *tx)
+        .await?;
+        let dropped = match revocation {
+            Revocation::Session(_) => "DELETE FROM sessions WHERE id = $1",
+            Revocation::User(_) => "DELETE FROM sessions WHERE user_id = $1",
+        };
+        let dropped = sqlx::query(dropped)
+            .bind(revocation.id().to_string())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(dropped.rows_affected())
+    }
+
+    async fn is_revoked(
+        &self,
+        session_id: Uuid,
+        user_id: Uuid,
+        logged_in_at: u64,
+        now: u64,
+    ) -> Result<bool> {
+        self.prepare().await?;
+
+        let revoked: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM session_revocations WHERE expires_at > $1 AND (\
+             (kind = 'session' AND id = $2) OR \
+             (kind = 'user' AND id = $3 AND revoked_at >= $4))",
+        )
+        .bind(i64::try_from(now)?)
+        .bind(session_id.to_string())
+        .bind(user_id.to_string())
+        .bind(i64::try_from(logged_in_at)?)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(revoked > 0)
+    }
+
+    async fn store_tokens(&self, tokens: &StoredTokens) -> Result<()> {
+        self.prepare().await?;
+
+        sqlx::query(
+            "INSERT INTO provider_tokens \
+             (user_id, provider, access_token, refresh_token, expires_at, updated_at, needs_reauth_at) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7) ON CONFLICT (user_id, provider) DO UPDATE \
+             SET access_token = excluded.access_token, refresh_token = excluded.refresh_token, \
+             expires_at = excluded.expires_at, updated_at = excluded.updated_at, \
+             needs_reauth_at = excluded.needs_reauth_at",
+        )
+        .bind(tokens.user_id.to_string())
+        .bind(&tokens.provider)
+        .bind(&tokens.access_token)
+        .bind(tokens.refresh_token.as_deref())
+        .bind(tokens.expires_at.map(i64::try_from).transpose()?)
+        .bind(i64::try_from(tokens.updated_at)?)
+        .bind(tokens.needs_reauth_at.map(i64::try_from).transpose()?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn tokens(&self, user_id: Uuid) -> Result<Vec<StoredTokens>> {
+        self.prepare().await?;
+
+        let rows = sqlx::query(
+            "SELECT user_id, provider, access_token, refresh_token, expires_at, updated_at, \
+             needs_reauth_at FROM provider_tokens WHERE user_id = $1 ORDER BY provider",
+        )
+        .bind(user_id.to_string())
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(stored_tokens).collect()
+    }
+
+    async fn expiring_tokens(&self, expires_before: u64, limit: u64) -> Result<Vec<StoredTokens>> {
+        self.prepare().await?;
+
+        let rows = sqlx::query(
+            "SELECT user_id, provider, access_token, refresh_token, expires_at, updated_at, \
+             needs_reauth_at FROM provider_tokens \
+             WHERE expires_at <= $1 AND refresh_token IS NOT NULL AND needs_reauth_at IS NULL \
+             ORDER BY expires_at, user_id, provider LIMIT $2",
+        )
+        .bind(i64::try_from(expires_before)?)
+        .bind(i64::try_from(limit)?)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(stored_tokens).collect()
+    }
+
+    async fn mark_needs_reauth(&self, user_id: Uuid, provider: &str, now: u64) -> Result<()> {
+        self.prepare().await?;
+
+        sqlx::query(
+            "UPDATE provider_tokens SET needs_reauth_at = $3 WHERE user_id = $1 AND provider = $2",
+        )
+        .bind(user_id.to_string())
+        .bind(provider)
+        .bind(i64::try_from(now)?)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Reads a user from a row of the users table
+///
+/// # Arguments
+///
+/// * `row` - Row holding the `id`, `created_at`, `last_seen` and `email` columns
+///
+/// # Returns
+///
+/// Returns the user, or an error if a column is missing or invalid
+#[cfg(feature = "database")]
+fn user_record(row: &sqlx::any::AnyRow) -> Result<UserRecord> {
+    Ok(UserRecord {
+        id: Uuid::parse_str(row.try_get("id")?)?,
+        created_at: u64::try_from(row.try_get::<i64, _>("created_at")?)?,
+        last_seen: u64::try_from(row.try_get::<i64, _>("last_seen")?)?,
+        email: row.try_get("email")?,
+    })
+}
+
+/// Reads the provider tokens of a `provider_tokens` row
+///
+/// # Arguments
+///
+/// * `row` - The row, with every column of the table
+///
+/// # Returns
+///
+/// Returns the tokens, or an error if a column is missing or invalid
+#[cfg(feature = "database")]
+fn stored_tokens(row: &sqlx::any::AnyRow) -> Result<StoredTokens> {
+    let timestamp = |column: &str| -> Result<Option<u64>> {
+        Ok(row
+            .try_get::<Option<i64>, _>(column)?
+            .map(u64::try_from)
+            .transpose()?)
+    };
+    Ok(StoredTokens {
+        user_id: Uuid::parse_str(row.try_get("user_id")?)?,
+        provider: row.try_get("provider")?,
+        access_token: row.try_get("access_token")?,
+        refresh_token: row.try_get("refresh_token")?,
+        expires_at: timestamp("expires_at")?,
+        updated_at: u64::try_from(row.try_get::<i64, _>("updated_at")?)?,
+        needs_reauth_at: timestamp("needs_reauth_at")?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "database")]
+    use std::path::PathBuf;
+
+    /// Checks sign-ins, links and unlinks against a store
+    async fn check_repository(users: &dyn UserRepository) {
+        let first = users.upsert("google", "user-1", 100).await.unwrap();
+        assert_eq!(first.provider, "google");
+        assert_eq!(first.provider_user_id, "user-1");
+        assert_eq!(first.created_at, 100);
+        assert_eq!(first.last_seen, 100);
+
+        let again = users.upsert("google", "user-1", 200).await.unwrap();
+        assert_eq!(again.id, first.id);
+        assert_eq!(again.created_at, 100);
+        assert_eq!(again.last_seen, 200);
+
+        let other = users.upsert("github", "user-1", 300).await.unwrap();
+        assert_ne!(other.id, first.id);
+        assert_eq!(other.created_at, 300);
+
+        // A linked account signs in as the user it is linked to
+        users.link(first.id, "gitlab", "gl-1", 400).await.unwrap();
+        users.link(first.id, "gitlab", "gl-1", 500).await.unwrap();
+        let linked = users.upsert("gitlab", "gl-1", 600).await.unwrap();
+        assert_eq!(linked.id, first.id);
+        assert_eq!(linked.created_at, 100);
+        assert_eq!(linked.last_seen, 600);
+
+        let error = users
+            .link(first.id, "github", "user-1", 700)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<IdentityError>(),
+            Some(&IdentityError::AlreadyLinked)
+        );
+
+        let identities = users.identities(first.id).await.unwrap();
+        assert_eq!(
+            identities,
+            [
+                Identity {
+                    provider: "google".to_string(),
+                    provider_user_id: "user-1".to_string(),
+                    linked_at: 100,
+                },
+                Identity {
+                    provider: "gitlab".to_string(),
+                    provider_user_id: "gl-1".to_string(),
+                    linked_at: 400,
+                },
+            ]
+        );
+
+        assert!(users.unlink(first.id, "google").await.unwrap());
+        assert!(!users.unlink(first.id, "google").await.unwrap());
+        let error = users.unlink(first.id, "gitlab").await.unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<IdentityError>(),
+            Some(&IdentityError::LastIdentity)
+        );
+
+        // An unlinked account signs in as a new user
+        let unlinked = users.upsert("google", "user-1", 800).await.unwrap();
+        assert_ne!(unlinked.id, first.id);
+
+        check_revocations(users).await;
+        check_sessions(users).await;
+        check_tokens(users).await;
+        check_deletion(users).await;
+        check_emails(users).await;
+    }
+
+    /// Checks session and user revocations against a store
+    async fn check_revocations(users: &dyn UserRepository) {
+        let (session, other_session) = (Uuid::new_v4(), Uuid::new_v4());
+        let (user, other_user) = (Uuid::new_v4(), Uuid::new_v4());
+        assert!(!users.is_revoked(session, user, 100, 150).await.unwrap());
+
+        users
+            .revoke(Revocation::Session(session), 200, 1_000)
+            .await
+            .unwrap();
+        assert!(users.is_revoked(session, user, 100, 300).await.unwrap());
+        assert!(!users
+            .is_revoked(other_session, user, 100, 300)
+            .await
+            .unwrap());
+
+        // Only the sessions signed in to until the revocation are revoked
+        users
+            .revoke(Revocation::User(user), 400, 1_200)
+            .await
+            .unwrap();
+        assert!(users
+            .is_revoked(other_session, user, 400, 500)
+            .await
+            .unwrap());
+        assert!(!users
+            .is_revoked(other_session, user, 401, 500)
+            .await
+            .unwrap());
+        assert!(!users
+            .is_revoked(other_session, other_user, 100, 500)
+            .await
+            .unwrap());
+        users
+            .revoke(Revocation::User(user), 600, 1_400)
+            .await
+            .unwrap();
+        assert!(users
+            .is_revoked(other_session, user, 500, 700)
+            .await
+            .unwrap());
+
+        // Revocations end once the revoked sessions would have expired anyway
+        assert!(!users
+            .is_revoked(session, other_user, 100, 1_000)
+            .await
+            .unwrap());
+        assert!(users
+            .is_revoked(other_session, user, 500, 1_000)
+            .await
+            .unwrap());
+        assert!(!users
+            .is_revoked(other_session, user, 500, 1_400)
+            .await
+            .unwrap());
+    }
+
+    /// Checks the session index against a store
+    async fn check_sessions(users: &dyn UserRepository) {
+        let alice = users.upsert("google", "alice", 1_000).await.unwrap();
+        let bob = users.upsert("github", "bob", 1_000).await.unwrap();
+        let session = |user: &StoredUser, created_at: u64| UserSession {
+            id: Uuid::new_v4(),
+            subject: user.id,
+            provider: user.provider.clone(),
+            provider_user_id: user.provider_user_id.clone(),
+            created_at,
+            last_seen: created_at,
+            expires_at: created_at + 3_600,
+        };
+        let first = session(&alice, 1_000);
+        let second = session(&bob, 1_100);
+        let third = session(&alice, 1_200);
+        for session in [&first, &second, &third] {
+            users.add_session(session).await.unwrap();
+        }
+
+        let listed = users.sessions(None, 1_300, 0, 10).await.unwrap();
+        assert_eq!(listed, [third.clone(), second.clone(), first.clone()]);
+        let page = users.sessions(None, 1_300, 1, 1).await.unwrap();
+        assert_eq!(page, std::slice::from_ref(&second));
+        let listed = users.sessions(Some(alice.id), 1_300, 0, 10).await.unwrap();
+        assert_eq!(listed, [third.clone(), first.clone()]);
+
+        // Requests extend the expiry, recorded at most once a minute
+        for now in [1_030, 1_060, 1_090] {
+            users
+                .touch_session(first.id, now, now + 3_600)
+                .await
+                .unwrap();
+        }
+        let listed = users.sessions(Some(alice.id), 4_650, 0, 10).await.unwrap();
+        assert_eq!(
+            listed,
+            [
+                third.clone(),
+                UserSession {
+                    last_seen: 1_060,
+                    expires_at: 4_660,
+                    ..first.clone()
+                },
+            ]
+        );
+        let listed = users.sessions(None, 4_690, 0, 10).await.unwrap();
+        assert_eq!(listed, [third.clone(), second.clone()]);
+
+        // Revocations drop the revoked sessions from the index
+        for (revocation, dropped) in [
+            (Revocation::Session(second.id), 1),
+            (Revocation::Session(second.id), 0),
+            (Revocation::User(alice.id), 2),
+        ] {
+            assert_eq!(
+                users.revoke(revocation, 1_400, 5_000).await.unwrap(),
+                dropped
+            );
+        }
+        assert!(users.sessions(None, 1_400, 0, 10).await.unwrap().is_empty());
+    }
+
+    /// Checks the token store against a store
+    async fn check_tokens(users: &dyn UserRepository) {
+        let alice = users.upsert("google", "alice-tokens", 1_000).await.unwrap();
+        let bob = users.upsert("github", "bob-tokens", 1_000).await.unwrap();
+        let tokens = |user: &StoredUser, provider: &str, expires_at: Option<u64>| StoredTokens {
+            user_id: user.id,
+            provider: provider.to_string(),
+            access_token: format!("enc:v1:access-{}", provider),
+            refresh_token: Some(format!("enc:v1:refresh-{}", provider)),
+            expires_at,
+            updated_at: 1_000,
+            needs_reauth_at: None,
+        };
+        let google = tokens(&alice, "google", Some(2_000));
+        let gitlab = tokens(&alice, "gitlab", Some(1_500));
+        let github = tokens(&bob, "github", Some(1_800));
+        let unrefreshable = StoredTokens {
+            refresh_token: None,
+            ..tokens(&bob, "gitlab", Some(1_000))
+        };
+        let no_expiry = tokens(&bob, "google", None);
+        for tokens in [&google, &gitlab, &github, &unrefreshable, &no_expiry] {
+            users.store_tokens(tokens).await.unwrap();
+        }
+
+        assert_eq!(
+            users.tokens(alice.id).await.unwrap(),
+            [gitlab.clone(), google.clone()]
+        );
+        let expiring = users.expiring_tokens(1_900, 10).await.unwrap();
+        assert_eq!(expiring, [gitlab.clone(), github.clone()]);
+        let expiring = users.expiring_tokens(2_000, 1).await.unwrap();
+        assert_eq!(expiring, std::slice::from_ref(&gitlab));
+
+        // Tokens that failed to refresh wait for the user to sign in again
+        users
+            .mark_needs_reauth(alice.id, "gitlab", 1_400)
+            .await
+            .unwrap();
+        let expiring = users.expiring_tokens(2_000, 10).await.unwrap();
+        assert_eq!(expiring, [github.clone(), google.clone()]);
+        let marked = users.tokens(alice.id).await.unwrap();
+        assert_eq!(marked[0].needs_reauth_at, Some(1_400));
+
+        let refreshed = StoredTokens {
+            access_token: "enc:v1:refreshed".to_string(),
+            expires_at: Some(5_000),
+            updated_at: 1_450,
+            ..gitlab.clone()
+        };
+        users.store_tokens(&refreshed).await.unwrap();
+        assert_eq!(
+            users.tokens(alice.id).await.unwrap(),
+            [refreshed.clone(), google.clone()]
+        );
+    }
+
+    /// Checks that emails are stored encrypted and found through their
+    /// blind index, also after a key rotation
+    async fn check_emails(users: &dyn UserRepository) {
+        let cipher = SecretCipher::new(&[[1; 32]], false).unwrap();
+        let user = users.upsert("google", "email-1", 1_000).await.unwrap();
+        let other = users.upsert("github", "email-2", 1_100).await.unwrap();
+        let unrelated = users.upsert("gitlab", "email-3", 1_200).await.unwrap();
+        users
+            .set_email(&cipher, user.id, " Jane.Doe@Example.com")
+            .await
+            .unwrap();
+        users
+            .set_email(&cipher, other.id, "jane.doe@example.com")
+            .await
+            .unwrap();
+        users
+            .set_email(&cipher, unrelated.id, "john@example.com")
+            .await
+            .unwrap();
+
+        let stored = users.user(user.id).await.unwrap().unwrap().email.unwrap();
+        assert!(!stored.to_lowercase().contains("jane"));
+        assert_eq!(cipher.decrypt(&stored).unwrap(), "Jane.Doe@Example.com");
+        let found = users
+            .find_by_email(&cipher, "JANE.DOE@example.COM ")
+            .await
+            .unwrap();
+        let ids: Vec<Uuid> = found.iter().map(|user| user.id).collect();
+        assert_eq!(ids, [user.id, other.id]);
+        assert!(users
+            .find_by_email(&cipher, "nobody@example.com")
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(users
+            .find_by_email(&SecretCipher::disabled(), "john@example.com")
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(users
+            .set_email(&SecretCipher::disabled(), user.id, "jane@example.com")
+            .await
+            .is_err());
+
+        // A rotated ring still finds the emails, which re-indexing moves to the new key
+        let rotated = SecretCipher::new(&[[2; 32], [1; 32]], false).unwrap();
+        let new_key = SecretCipher::new(&[[2; 32]], false).unwrap();
+        assert_eq!(
+            users
+                .find_by_email(&rotated, "john@example.com")
+                .await
+                .unwrap()[0]
+                .id,
+            unrelated.id
+        );
+        assert!(users
+            .find_by_email(&new_key, "john@example.com")
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(users.reindex_emails(&rotated).await.unwrap(), 3);
+        assert_eq!(
+            users
+                .find_by_email(&new_key, "john@example.com")
+                .await
+                .unwrap()[0]
+                .id,
+            unrelated.id
+        );
+        let stored = users.user(user.id).await.unwrap().unwrap().email.unwrap();
+        assert_eq!(new_key.decrypt(&stored).unwrap(), "Jane.Doe@Example.com");
+
+        for user_id in [user.id, other.id, unrelated.id] {
+            users.delete_user(user_id).await.unwrap();
+        }
+        assert!(users
+            .find_by_email(&new_key, "john@example.com")
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    /// Checks that deleting a user leaves nothing of them in a store
+    async fn check_deletion(users: &dyn UserRepository) {
+        let user = users.upsert("google", "deleted", 1_000).await.unwrap();
+        let other = users.upsert("google", "kept", 1_000).await.unwrap();
+        users
+            .link(user.id, "github", "deleted-gh", 1_100)
+            .await
+            .unwrap();
+        for (subject, provider) in [
+            (user.id, "google"),
+            (user.id, "github"),
+            (other.id, "google"),
+        ] {
+            users
+                .add_session(&UserSession {
+                    id: Uuid::new_v4(),
+                    subject,
+                    provider: provider.to_string(),
+                    provider_user_id: "deleted".to_string(),
+                    created_at: 1_200,
+                    last_seen: 1_200,
+                    expires_at: 5_000,
+                })
+                .await
+                .unwrap();
+            users
+                .store_tokens(&StoredTokens {
+                    user_id: subject,
+                    provider: provider.to_string(),
+                    access_token: "enc:v1:access".to_string(),
+                    refresh_token: None,
+                    expires_at: Some(5_000),
+                    updated_at: 1_200,
+                    needs_reauth_at: None,
+                })
+                .await
+                .unwrap();
+        }
+        assert_eq!(
+            users.user(user.id).await.unwrap(),
+            Some(UserRecord {
+                id: user.id,
+                created_at: 1_000,
+                last_seen: 1_000,
+                email: None,
+            })
+        );
+
+        assert!(users.delete_user(user.id).await.unwrap());
+        assert!(!users.delete_user(user.id).await.unwrap());
+        assert_eq!(users.user(user.id).await.unwrap(), None);
+        assert!(users.identities(user.id).await.unwrap().is_empty());
+        assert!(users.tokens(user.id).await.unwrap().is_empty());
+        assert!(users
+            .sessions(Some(user.id), 1_300, 0, 10)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // The identities sign in as new users, other users are left alone
+        let again = users.upsert("github", "deleted-gh", 1_400).await.unwrap();
+        assert_ne!(again.id, user.id);
+        assert!(users.user(other.id).await.unwrap().is_some());
+        assert_eq!(users.tokens(other.id).await.unwrap().len(), 1);
+        assert_eq!(
+            users
+                .sessions(Some(other.id), 1_300, 0, 10)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    /// Tests that the in-memory store keeps the id of a returning user and links accounts
+    #[tokio::test]
+    async fn test_in_memory_repository() {
+        check_repository(&InMemoryUserRepository::default()).await;
+    }
+
+    /// Tests that users are inserted on their first sign-in, updated on the next, and linked
+    #[cfg(feature = "database")]
+    #[tokio::test]
+    async fn test_sql_repository() {
+        let users = SqlUserRepository::from_settings(&DatabaseSettings {
+            url: "sqlite::memory:".into(),
+            max_connections: 1,
+            ..DatabaseSettings::default()
+        })
+        .unwrap();
+        check_repository(&users).await;
+
+        let error = SqlUserRepository::from_settings(&DatabaseSettings::default())
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("database.url"));
+    }
+
+    /// Tests that a database that cannot be migrated is reported by `migrate`
+    #[cfg(feature = "database")]
+    #[tokio::test]
+    async fn test_sql_repository_migration_failure() {
+        let users = SqlUserRepository::from_settings(&DatabaseSettings {
+            url: "sqlite:///nonexistent/users.db".into(),
+            max_connections: 1,
+            ..DatabaseSettings::default()
+        })
+        .unwrap();
+
+        let error = users.migrate().await.unwrap_err();
+        assert!(
+            error.to_string().starts_with("Failed to migrate"),
+            "{}",
+            error
+        );
+    }
+
+    /// Creates a store of a new SQLite database file
+    ///
+    /// # Arguments
+    ///
+    /// * `auto_migrate` - The `database.auto_migrate` setting
+    ///
+    /// # Returns
+    ///
+    /// Returns the store, the database's connection string and its path
+    #[cfg(feature = "database")]
+    fn sqlite_file(auto_migrate: Option<bool>) -> (SqlUserRepository, String, PathBuf) {
+        let path = std::env::temp_dir().join(format!("users-{}.db", Uuid::new_v4()));
+        let url = format!("sqlite://{}?mode=rwc", path.display());
+        let settings = DatabaseSettings {
+            url: url.clone().into(),
+            max_connections: 1,
+            auto_migrate,
+        };
+        (
+            SqlUserRepository::from_settings(&settings).unwrap(),
+            url,
+            path,
+        )
+    }
+
+    /// Tests that the migrations apply in order, and that applying them
+    /// again changes nothing
+    #[cfg(feature = "database")]
+    #[tokio::test]
+    async fn test_migrations_apply_twice() {
+        let users = SqlUserRepository::from_settings(&DatabaseSettings {
+            url: "sqlite::memory:".into(),
+            max_connections: 1,
+            ..DatabaseSettings::default()
+        })
+        .unwrap();
+        users.migrate().await.unwrap();
+        let user = users.upsert("google", "user-1", 100).await.unwrap();
+        users.migrate().await.unwrap();
+
+        let applied: Vec<i64> = users
+            .pool
+            .acquire()
+            .await
+            .unwrap()
+            .list_applied_migrations()
+            .await
+            .unwrap()
+            .iter()
+            .map(|migration| migration.version)
+            .collect();
+        let bundled: Vec<i64> = MIGRATOR.iter().map(|migration| migration.version).collect();
+        assert_eq!(applied, bundled);
+        assert!(bundled.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(users.pending_migrations().await.unwrap().is_empty());
+
+        let again = users.upsert("google", "user-1", 200).await.unwrap();
+        assert_eq!(again.id, user.id);
+    }
+
+    /// Tests that a store without `auto_migrate` refuses a database lacking
+    /// migrations until they are applied
+    #[cfg(feature = "database")]
+    #[tokio::test]
+    async fn test_pending_migrations_without_auto_migrate() {
+        let (users, url, path) = sqlite_file(Some(false));
+        let error = users.prepare().await.unwrap_err().to_string();
+        assert!(error.contains("--migrate"), "{}", error);
+
+        users.migrate().await.unwrap();
+        let users = SqlUserRepository::from_settings(&DatabaseSettings {
+            url: url.into(),
+            max_connections: 1,
+            auto_migrate: Some(false),
+        })
+        .unwrap();
+        users.prepare().await.unwrap();
+        users.upsert("google", "user-1", 100).await.unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// Tests that the plaintext email never reaches the database file while
+    /// lookups by email still succeed
+    #[cfg(feature = "database")]
+    #[tokio::test]
+    async fn test_emails_are_not_stored_readable() {
+        let (users, _, path) = sqlite_file(None);
+        let cipher = SecretCipher::new(&[[1; 32]], false).unwrap();
+        let user = users.upsert("google", "1234567890", 100).await.unwrap();
+        users
+            .set_email(&cipher, user.id, "Jane.Doe@Example.com")
+            .await
+            .unwrap();
+        let found = users
+            .find_by_email(&cipher, "jane.doe@example.com")
+            .await
+            .unwrap();
+        assert_eq!(found[0].id, user.id);
+        users.pool.close().await;
+
+        let mut contents = std::fs::read(&path).unwrap();
+        let journal = PathBuf::from(format!("{}-wal", path.display()));
+        contents.extend(std::fs::read(&journal).unwrap_or_default());
+        let contents = String::from_utf8_lossy(&contents).to_lowercase();
+        assert!(contents.contains("1234567890"), "the user must be written");
+        assert!(!contents.contains("jane"));
+        assert!(!contents.contains("example.com"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// Tests that a schema migrated by a newer server is refused
+    #[cfg(feature = "database")]
+    #[tokio::test]
+    async fn test_schema_newer_than_server() {
+        let (users, url, path) = sqlite_file(None);
+        users.migrate().await.unwrap();
+        sqlx::query(
+            "INSERT INTO _sqlx_migrations \
+             (version, description, success, checksum, execution_time) \
+             VALUES (99990101000000, 'future', TRUE, X'00', 0)",
+        )
+        .execute(&users.pool)
+        .await
+        .unwrap();
+
+        for auto_migrate in [Some(true), Some(false)] {
+            let users = SqlUserRepository::from_settings(&DatabaseSettings {
+                url: url.clone().into(),
+                max_connections: 1,
+                auto_migrate,
+            })
+            .unwrap();
+            let error = users.prepare().await.unwrap_err().to_string();
+            assert!(
+                error.contains("version 99990101000000, newer than"),
+                "{}",
+                error
+            );
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,283 @@
+//! End to end tests of the authorize → callback flow against a mock provider
+
+use axum::{
+    body::Body,
+    http::{Request, StatusCode},
+    Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use oauth_server::{
+    app::{build_app_state, build_http_client, build_oauth_providers},
+    settings::{OAuthSettings, Settings},
+    testing::{
+        MockIdp, MockProviderFactory, MOCK_ACCESS_TOKEN, MOCK_CODE, MOCK_EMAIL, MOCK_USER_ID,
+    },
+    ProviderRegistry, Server,
+};
+use opentelemetry::trace::{Status, TracerProvider};
+use opentelemetry_sdk::trace::{InMemorySpanExporter, SdkTracerProvider, SpanData};
+use reqwest::Url;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, sync::Arc};
+use tower::ServiceExt;
+use tower_sessions::cookie::Key;
+use tracing_subscriber::layer::SubscriberExt;
+
+/// Callback URL the mock provider redirects back to
+const REDIRECT_URI: &str = "http://localhost:3000/callback";
+
+/// Builds the server's router with a `mock` provider signing in through the mock provider
+async fn server(idp: &MockIdp) -> Router {
+    server_with(HashMap::from([(
+        "mock".to_string(),
+        idp.settings(REDIRECT_URI),
+    )]))
+    .await
+}
+
+/// Builds the server's router with the given provider sections
+async fn server_with(oauth: HashMap<String, OAuthSettings>) -> Router {
+    let mut settings: Settings =
+        serde_json::from_value(serde_json::json!({ "port": 0, "oauth": {} })).unwrap();
+    settings.oauth = oauth;
+
+    let mut registry = ProviderRegistry::with_builtins();
+    registry.register("mock", Arc::new(MockProviderFactory));
+    let http_client = build_http_client(&settings.http_client).unwrap();
+    let providers = build_oauth_providers(&http_client, &registry, &settings.oauth, None, "", true)
+        .await
+        .unwrap();
+    let state = build_app_state(&settings, http_client, providers).unwrap();
+
+    Server::new(0, state, Key::generate(), settings.session.cookie.clone()).router()
+}
+
+/// Starts a flow, returning the session cookie and the authorization URL
+async fn authorize(router: &Router) -> (String, Url) {
+    authorize_with(router, "mock").await
+}
+
+/// Starts a flow with the given provider, returning the session cookie and the authorization URL
+async fn authorize_with(router: &Router, provider: &str) -> (String, Url) {
+    let response = router
+        .clone()
+        .oneshot(
+            Request::get(format!("/authorize?provider={}", provider))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+    let cookie = response.headers()["set-cookie"].to_str().unwrap();
+    let cookie = cookie.split(';').next().unwrap().to_string();
+    let location = Url::parse(response.headers()["location"].to_str().unwrap()).unwrap();
+    (cookie, location)
+}
+
+/// Returns the value of a query parameter
+fn query_param(url: &Url, name: &str) -> String {
+    url.query_pairs()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.to_string())
+        .unwrap()
+}
+
+/// Follows the authorization URL like a browser, returning the callback's path and query
+async fn follow_authorization(location: &Url) -> String {
+    let browser = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let response = browser.get(location.clone()).send().await.unwrap();
+    assert!(response.status().is_redirection());
+
+    let callback = Url::parse(response.headers()["location"].to_str().unwrap()).unwrap();
+    assert!(callback.as_str().starts_with(REDIRECT_URI));
+    format!("{}?{}", callback.path(), callback.query().unwrap())
+}
+
+/// Sends the callback request, returning its status and JSON body
+async fn callback(
+    router: &Router,
+    uri: &str,
+    cookie: Option<&str>,
+) -> (StatusCode, serde_json::Value) {
+    let mut request = Request::get(uri);
+    if let Some(cookie) = cookie {
+        request = request.header("cookie", cookie);
+    }
+    let response = router
+        .clone()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+/// Tests that a flow redirected through the mock provider signs the user in
+/// and presents the PKCE verifier matching the challenge
+#[tokio::test]
+async fn test_authorize_callback_flow() {
+    let idp = MockIdp::start().await.unwrap();
+    let router = server(&idp).await;
+
+    let (cookie, location) = authorize(&router).await;
+    assert!(location
+        .as_str()
+        .starts_with(&format!("{}/authorize", idp.url())));
+    assert_eq!(query_param(&location, "code_challenge_method"), "S256");
+    let code_challenge = query_param(&location, "code_challenge");
+
+    let callback_uri = follow_authorization(&location).await;
+    let (status, body) = callback(&router, &callback_uri, Some(&cookie)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+    assert_eq!(body["user_id"], MOCK_USER_ID);
+    assert_eq!(body["email"], MOCK_EMAIL);
+    assert_eq!(body["tokens"]["access_token"], MOCK_ACCESS_TOKEN);
+
+    let token_requests = idp.token_requests();
+    assert_eq!(token_requests.len(), 1);
+    assert_eq!(token_requests[0].code, MOCK_CODE);
+    let code_verifier = token_requests[0].code_verifier.as_ref().unwrap();
+    assert_eq!(
+        URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes())),
+        code_challenge
+    );
+
+    // The session is renewed on sign-in, replaying the callback with the
+    // cookie of the flow finds no flow to complete
+    let (status, body) = callback(&router, &callback_uri, Some(&cookie)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "session_not_found");
+    assert_eq!(idp.token_requests().len(), 1);
+}
+
+/// Tests that a callback whose state does not match the pending flow is rejected
+#[tokio::test]
+async fn test_csrf_mismatch() {
+    let idp = MockIdp::start().await.unwrap();
+    let router = server(&idp).await;
+
+    let (cookie, _) = authorize(&router).await;
+    let (status, body) = callback(
+        &router,
+        &format!("/callback?code={}&state=forged", MOCK_CODE),
+        Some(&cookie),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "csrf_mismatch");
+    assert!(idp.token_requests().is_empty());
+}
+
+/// Tests that a callback without the session of its flow is rejected
+#[tokio::test]
+async fn test_missing_session() {
+    let idp = MockIdp::start().await.unwrap();
+    let router = server(&idp).await;
+
+    let (_, location) = authorize(&router).await;
+    let callback_uri = follow_authorization(&location).await;
+    let (status, body) = callback(&router, &callback_uri, None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["code"], "session_not_found");
+    assert!(idp.token_requests().is_empty());
+}
+
+/// Tests that two sections of the same provider type each sign in through
+/// their own client, under their own name
+#[tokio::test]
+async fn test_provider_instances() {
+    let idp = MockIdp::start().await.unwrap();
+    let staging = OAuthSettings {
+        provider_type: Some("mock".to_string()),
+        client_id: "staging-client".to_string(),
+        ..idp.settings(REDIRECT_URI)
+    };
+    let router = server_with(HashMap::from([
+        ("mock".to_string(), idp.settings(REDIRECT_URI)),
+        ("mock_staging".to_string(), staging),
+    ]))
+    .await;
+
+    let mut user_ids = Vec::new();
+    for (provider, client_id) in [("mock_staging", "staging-client"), ("mock", "mock-client")] {
+        let (cookie, location) = authorize_with(&router, provider).await;
+        assert_eq!(query_param(&location, "client_id"), client_id);
+
+        let callback_uri = follow_authorization(&location).await;
+        let (status, body) = callback(&router, &callback_uri, Some(&cookie)).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+        assert_eq!(body["user_id"], MOCK_USER_ID);
+        assert_eq!(
+            idp.token_requests().last().unwrap().client_id.as_deref(),
+            Some(client_id)
+        );
+        user_ids.push(body["internal_user_id"].clone());
+    }
+
+    // Users are keyed by the section name, the same account of both
+    // instances is two users until linked
+    assert_ne!(user_ids[0], user_ids[1]);
+}
+
+/// Tests that the token exchange and user info calls are exported as spans
+/// of the callback's trace, which the provider's requests carry on
+#[tokio::test]
+async fn test_provider_calls_are_traced() {
+    let exporter = InMemorySpanExporter::default();
+    let provider = SdkTracerProvider::builder()
+        .with_simple_exporter(exporter.clone())
+        .build();
+    let subscriber = tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let idp = MockIdp::start().await.unwrap();
+    let router = server(&idp).await;
+    let (cookie, location) = authorize(&router).await;
+    let callback_uri = follow_authorization(&location).await;
+    let (status, body) = callback(&router, &callback_uri, Some(&cookie)).await;
+    assert_eq!(status, StatusCode::OK, "{}", body);
+
+    let spans = exporter.get_finished_spans().unwrap();
+    let span = |name: &str| {
+        spans
+            .iter()
+            .find(|span| span.name == name)
+            .unwrap_or_else(|| panic!("no {} span in {:?}", name, spans))
+    };
+    let attribute = |span: &SpanData, key: &str| {
+        span.attributes
+            .iter()
+            .find(|attribute| attribute.key.as_str() == key)
+            .map(|attribute| attribute.value.to_string())
+    };
+    for name in ["token_exchange", "user_info"] {
+        let span = span(name);
+        assert_eq!(attribute(span, "provider").as_deref(), Some("mock"));
+        assert_eq!(
+            attribute(span, "http.response.status_code").as_deref(),
+            Some("200")
+        );
+        assert_eq!(span.status, Status::Ok);
+    }
+
+    let exchange = span("token_exchange");
+    let traceparent = idp.token_requests()[0].traceparent.clone().unwrap();
+    assert_eq!(
+        traceparent,
+        format!(
+            "00-{}-{}-01",
+            exchange.span_context.trace_id(),
+            exchange.span_context.span_id()
+        )
+    );
+}